@@ -0,0 +1,98 @@
+//! ProxyConnection::relay 的转发吞吐: 经本地回环 TCP，客户端 → relay → 目标
+//!
+//! 单向 (仅上行) 与双向 (目标回显) 各一组。
+//! 传输量默认 64 MiB，可通过 RELAY_BENCH_BYTES 调整 (如 1073741824 即 1 GiB)。
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use xray_lite::network::connection::ProxyConnection;
+
+const WRITE_SIZE: usize = 64 * 1024;
+
+/// 目标端: 读完上行；`echo` 时原样回写
+async fn spawn_target(echo: bool) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                if echo {
+                    let (mut r, mut w) = stream.split();
+                    let _ = tokio::io::copy(&mut r, &mut w).await;
+                    let _ = w.shutdown().await;
+                } else {
+                    let _ = tokio::io::copy(&mut stream, &mut tokio::io::sink()).await;
+                    let _ = stream.shutdown().await;
+                }
+            });
+        }
+    });
+    addr
+}
+
+/// 中转端: 每个连接拨号到目标并用 ProxyConnection 转发
+async fn spawn_relay(target: std::net::SocketAddr) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (client, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let remote = TcpStream::connect(target).await.unwrap();
+                let _ = ProxyConnection::new(client, remote).relay().await;
+            });
+        }
+    });
+    addr
+}
+
+async fn transfer(relay: std::net::SocketAddr, total: usize, echo: bool) {
+    let stream = TcpStream::connect(relay).await.unwrap();
+    let (mut r, mut w) = stream.into_split();
+    let upload = tokio::spawn(async move {
+        let chunk = vec![0x5au8; WRITE_SIZE];
+        let mut sent = 0;
+        while sent < total {
+            let n = WRITE_SIZE.min(total - sent);
+            w.write_all(&chunk[..n]).await.unwrap();
+            sent += n;
+        }
+        w.shutdown().await.unwrap();
+    });
+
+    let mut buf = vec![0u8; WRITE_SIZE];
+    let mut received = 0;
+    loop {
+        let n = r.read(&mut buf).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        received += n;
+    }
+    upload.await.unwrap();
+    assert_eq!(received, if echo { total } else { 0 });
+}
+
+fn bench_relay(c: &mut Criterion) {
+    let total = std::env::var("RELAY_BENCH_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(64 << 20);
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("relay");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(total as u64));
+    for echo in [false, true] {
+        let relay = rt.block_on(async { spawn_relay(spawn_target(echo).await).await });
+        let name = if echo { "echo" } else { "upload" };
+        group.bench_with_input(BenchmarkId::from_parameter(name), &echo, |b, &echo| {
+            b.iter(|| rt.block_on(transfer(relay, total, echo)));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_relay);
+criterion_main!(benches);
@@ -0,0 +1,116 @@
+//! 单条记录的加密写入开销 (1 KiB / 16 KiB)
+//!
+//! TlsStream 写入一个不会阻塞的空设备，逐条 write + flush，
+//! 计时之外先打印每条记录的平均分配次数与分配字节数。
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use xray_lite::transport::reality::crypto::{CipherSuite, TlsKeys, TranscriptHash};
+use xray_lite::transport::reality::stream::TlsStream;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// 丢弃所有写入的底层流
+struct Discard;
+
+impl AsyncRead for Discard {
+    fn poll_read(self: Pin<&mut Self>, _: &mut Context<'_>, _: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for Discard {
+    fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn stream() -> TlsStream<Discard> {
+    let suite = CipherSuite::Aes128GcmSha256;
+    let mut transcript = TranscriptHash::new(suite);
+    transcript.add(b"ch");
+    transcript.add(b"sh");
+    let hash = transcript.current_hash();
+    let (keys, _) = TlsKeys::derive_handshake_keys(suite, &[3u8; 32], &hash).unwrap();
+    TlsStream::new(Discard, keys)
+}
+
+fn write_record(tls: &mut TlsStream<Discard>, record: &[u8]) {
+    futures::executor::block_on(async {
+        tls.write_all(record).await.unwrap();
+        tls.flush().await.unwrap();
+    });
+}
+
+fn bench_tls_record(c: &mut Criterion) {
+    const RECORDS: usize = 10_000;
+
+    for size in [1024, 16 * 1024] {
+        let record = vec![0x5au8; size];
+        let mut tls = stream();
+        write_record(&mut tls, &record);
+        let (count, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), ALLOCATED_BYTES.load(Ordering::Relaxed));
+        for _ in 0..RECORDS {
+            write_record(&mut tls, &record);
+        }
+        let count = ALLOCATIONS.load(Ordering::Relaxed) - count;
+        let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes;
+        println!(
+            "tls_record/{}: {:.2} allocations, {} bytes allocated per record",
+            size,
+            count as f64 / RECORDS as f64,
+            bytes / RECORDS
+        );
+    }
+
+    let mut group = c.benchmark_group("tls_record");
+    for size in [1024, 16 * 1024] {
+        let record = vec![0x5au8; size];
+        let mut tls = stream();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &record, |b, record| {
+            b.iter(|| write_record(&mut tls, record));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_tls_record);
+criterion_main!(benches);
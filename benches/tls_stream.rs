@@ -0,0 +1,87 @@
+//! TlsStream 写路径吞吐: 经本地回环 TCP 传输，客户端逐条解密记录
+//!
+//! 传输量默认 64 MiB，可通过 TLS_STREAM_BENCH_BYTES 调整 (如 1073741824 即 1 GiB)。
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use xray_lite::transport::reality::crypto::{CipherSuite, TlsKeys, TranscriptHash};
+use xray_lite::transport::reality::stream::TlsStream;
+
+/// 服务端密钥和交换了读写方向的客户端密钥
+fn keys() -> (TlsKeys, TlsKeys) {
+    let suite = CipherSuite::Aes128GcmSha256;
+    let mut transcript = TranscriptHash::new(suite);
+    transcript.add(b"ch");
+    transcript.add(b"sh");
+    let hash = transcript.current_hash();
+    let (server, _) = TlsKeys::derive_handshake_keys(suite, &[3u8; 32], &hash).unwrap();
+    let (peer, _) = TlsKeys::derive_handshake_keys(suite, &[3u8; 32], &hash).unwrap();
+    let client = TlsKeys {
+        suite,
+        client_write_key: peer.server_write_key,
+        server_write_key: peer.client_write_key,
+        client_iv: peer.server_iv,
+        server_iv: peer.client_iv,
+        client_traffic_secret: peer.server_traffic_secret,
+        server_traffic_secret: peer.client_traffic_secret,
+    };
+    (server, client)
+}
+
+async fn transfer(total: usize, write_size: usize) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (server_keys, client_keys) = keys();
+
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut tls = TlsStream::new(stream, server_keys);
+        let chunk = vec![0x5au8; write_size];
+        let mut sent = 0;
+        while sent < total {
+            let n = write_size.min(total - sent);
+            tls.write_all(&chunk[..n]).await.unwrap();
+            sent += n;
+        }
+        tls.shutdown().await.unwrap();
+    });
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut received = 0;
+    let mut seq = 0;
+    let mut body = vec![0u8; 1 << 16];
+    while received < total {
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).await.unwrap();
+        let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+        stream.read_exact(&mut body[..len]).await.unwrap();
+        let (content_type, plaintext_len) = client_keys.decrypt_client_record(seq, &header, &mut body[..len]).unwrap();
+        assert_eq!(content_type, 23);
+        received += plaintext_len;
+        seq += 1;
+    }
+    assert_eq!(received, total);
+    server.await.unwrap();
+}
+
+fn bench_tls_stream_write(c: &mut Criterion) {
+    let total = std::env::var("TLS_STREAM_BENCH_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(64 << 20);
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("tls_stream_write");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(total as u64));
+    // 64 KiB: 大块写入 (需拆分为多条记录)；512 B: 突发小写入 (需合并)
+    for write_size in [64 * 1024, 512] {
+        group.bench_with_input(BenchmarkId::from_parameter(write_size), &write_size, |b, &write_size| {
+            b.iter(|| rt.block_on(transfer(total, write_size)));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_tls_stream_write);
+criterion_main!(benches);
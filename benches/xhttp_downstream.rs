@@ -0,0 +1,167 @@
+//! XHTTP stream-one 下行路径的分配次数与吞吐
+//!
+//! 并发打开多个 stream-one 流 (普通流与 gRPC 分帧各一组)，服务端回显上行数据。
+//! 除 criterion 计时外，先打印一轮传输中每个流的平均分配次数与分配字节数。
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bytes::{Buf, Bytes, BytesMut};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::net::{TcpListener, TcpStream};
+use xray_lite::server::AsyncStream;
+use xray_lite::transport::xhttp::{XhttpConfig, XhttpMode};
+use xray_lite::transport::XhttpServer;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const STREAMS: usize = 64;
+const BYTES_PER_STREAM: usize = 1 << 20;
+const WRITE_SIZE: usize = 16 * 1024;
+
+async fn start_server() -> std::net::SocketAddr {
+    let server = XhttpServer::new(XhttpConfig { mode: XhttpMode::Auto, path: "/xhttp".to_string(), ..Default::default() })
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let server = server.clone();
+            tokio::spawn(async move {
+                let echo = |stream: Box<dyn AsyncStream>, _| async move {
+                    let (mut r, mut w) = tokio::io::split(stream);
+                    tokio::io::copy(&mut r, &mut w).await?;
+                    Ok(())
+                };
+                let _ = server.accept(stream, echo).await;
+            });
+        }
+    });
+    addr
+}
+
+/// 一个 stream-one 流: 上传 BYTES_PER_STREAM 字节并读回相同数量的回显
+async fn echo_stream(mut client: h2::client::SendRequest<Bytes>, grpc: bool) {
+    let mut request = hyper::http::Request::post("http://bench.example.com/xhttp");
+    if grpc {
+        request = request.header("content-type", "application/grpc");
+    }
+    let (response, mut send) = client.send_request(request.body(()).unwrap(), false).unwrap();
+
+    let upload = tokio::spawn(async move {
+        let chunk = vec![0x5au8; WRITE_SIZE];
+        for _ in 0..BYTES_PER_STREAM / WRITE_SIZE {
+            let mut data = BytesMut::with_capacity(WRITE_SIZE + 5);
+            if grpc {
+                data.extend_from_slice(&[0]);
+                data.extend_from_slice(&(WRITE_SIZE as u32).to_be_bytes());
+            }
+            data.extend_from_slice(&chunk);
+            send.reserve_capacity(data.len());
+            while send.capacity() < data.len() {
+                std::future::poll_fn(|cx| send.poll_capacity(cx)).await.unwrap().unwrap();
+            }
+            send.send_data(data.freeze(), false).unwrap();
+        }
+        send
+    });
+
+    let mut body = response.await.unwrap().into_body();
+    let mut received = 0;
+    let mut pending = BytesMut::new();
+    while received < BYTES_PER_STREAM {
+        let chunk = body.data().await.unwrap().unwrap();
+        let _ = body.flow_control().release_capacity(chunk.len());
+        if grpc {
+            pending.extend_from_slice(&chunk);
+            while pending.len() >= 5 {
+                let len = u32::from_be_bytes([pending[1], pending[2], pending[3], pending[4]]) as usize;
+                if pending.len() < 5 + len {
+                    break;
+                }
+                pending.advance(5 + len);
+                received += len;
+            }
+        } else {
+            received += chunk.len();
+        }
+    }
+    let mut send = upload.await.unwrap();
+    send.send_data(Bytes::new(), true).unwrap();
+}
+
+async fn transfer(addr: std::net::SocketAddr, grpc: bool) {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (client, connection) = h2::client::Builder::new()
+        .initial_window_size(4 << 20)
+        .initial_connection_window_size(8 << 20)
+        .handshake(stream)
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+
+    let streams: Vec<_> = (0..STREAMS)
+        .map(|_| tokio::spawn(echo_stream(client.clone(), grpc)))
+        .collect();
+    for stream in streams {
+        stream.await.unwrap();
+    }
+}
+
+fn bench_xhttp_downstream(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let addr = rt.block_on(start_server());
+
+    for grpc in [false, true] {
+        // 预热一轮，使缓冲池与 h2 内部状态稳定
+        rt.block_on(transfer(addr, grpc));
+        let (count, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), ALLOCATED_BYTES.load(Ordering::Relaxed));
+        rt.block_on(transfer(addr, grpc));
+        let count = ALLOCATIONS.load(Ordering::Relaxed) - count;
+        let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes;
+        println!(
+            "xhttp_downstream/{}: {} allocations, {} KiB allocated per stream",
+            if grpc { "grpc" } else { "raw" },
+            count / STREAMS,
+            bytes / STREAMS / 1024
+        );
+    }
+
+    let mut group = c.benchmark_group("xhttp_downstream");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes((STREAMS * BYTES_PER_STREAM) as u64));
+    for grpc in [false, true] {
+        let name = if grpc { "grpc" } else { "raw" };
+        group.bench_with_input(BenchmarkId::from_parameter(name), &grpc, |b, &grpc| {
+            b.iter(|| rt.block_on(transfer(addr, grpc)));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_xhttp_downstream);
+criterion_main!(benches);
@@ -0,0 +1,95 @@
+//! 下行整形与 Padding 的开销
+//!
+//! `send_split_data` 把数据切成 8~16 KiB 的随机块发送，与整块直接 send_data 对比；
+//! H2 连接建立在内存管道上，不受网络栈影响。另单独测量生成 X-Padding 的耗时。
+use bytes::{Bytes, BytesMut};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use h2::SendStream;
+use xray_lite::transport::xhttp::{H2Handler, Shaping, XhttpMetrics};
+
+const TOTAL: usize = 16 << 20;
+const READ_SIZE: usize = 64 * 1024;
+
+/// 等到发送窗口足够后整块发送
+async fn send_whole(data: Bytes, send_stream: &mut SendStream<Bytes>) {
+    send_stream.reserve_capacity(data.len());
+    while send_stream.capacity() < data.len() {
+        std::future::poll_fn(|cx| send_stream.poll_capacity(cx)).await.unwrap().unwrap();
+    }
+    send_stream.send_data(data, false).unwrap();
+}
+
+/// 服务端以 READ_SIZE 为单位发送 TOTAL 字节，客户端读完
+async fn transfer(split: bool) {
+    let (client_io, server_io) = tokio::io::duplex(1 << 20);
+    let server = tokio::spawn(async move {
+        let mut connection = h2::server::Builder::new()
+            .initial_connection_window_size(8 << 20)
+            .handshake::<_, Bytes>(server_io)
+            .await
+            .unwrap();
+        let (_, mut respond) = connection.accept().await.unwrap().unwrap();
+        tokio::spawn(async move { while connection.accept().await.is_some() {} });
+
+        let mut send_stream = respond.send_response(hyper::http::Response::new(()), false).unwrap();
+        let metrics = XhttpMetrics::default();
+        let shaping = Shaping::default();
+        let chunk = vec![0x5au8; READ_SIZE];
+        let mut buf = BytesMut::with_capacity(READ_SIZE);
+        for _ in 0..TOTAL / READ_SIZE {
+            buf.extend_from_slice(&chunk);
+            if split {
+                H2Handler::send_split_data(&mut buf, &mut send_stream, &shaping, &metrics).await.unwrap();
+            } else {
+                send_whole(buf.split().freeze(), &mut send_stream).await;
+            }
+        }
+        send_stream.send_data(Bytes::new(), true).unwrap();
+    });
+
+    let (mut client, connection) = h2::client::Builder::new()
+        .initial_window_size(4 << 20)
+        .initial_connection_window_size(8 << 20)
+        .handshake::<_, Bytes>(client_io)
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+    let (response, _) = client.send_request(hyper::http::Request::get("http://bench.example.com/").body(()).unwrap(), true).unwrap();
+    let mut body = response.await.unwrap().into_body();
+    let mut received = 0;
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.unwrap();
+        let _ = body.flow_control().release_capacity(chunk.len());
+        received += chunk.len();
+    }
+    assert_eq!(received, TOTAL);
+    server.await.unwrap();
+}
+
+fn bench_split_data(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("xhttp_split_data");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(TOTAL as u64));
+    for split in [false, true] {
+        let name = if split { "split" } else { "whole" };
+        group.bench_with_input(BenchmarkId::from_parameter(name), &split, |b, &split| {
+            b.iter(|| rt.block_on(transfer(split)));
+        });
+    }
+    group.finish();
+}
+
+fn bench_padding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("xhttp_padding");
+    // 流量权重 1 MiB 以下生成 64~512 字节，以上生成 16~32 字节
+    for traffic in [0u64, 2 << 20] {
+        group.bench_with_input(BenchmarkId::from_parameter(traffic), &traffic, |b, &traffic| {
+            b.iter(|| H2Handler::gen_adaptive_padding(black_box(traffic)));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_split_data, bench_padding);
+criterion_main!(benches);
@@ -1,91 +1,109 @@
 /// Reality Authentication Demo
 ///
-/// This program demonstrates how Reality authentication is injected
-/// into ServerHello.random field.
-use vless_reality_xhttp::transport::reality::server_rustls::RealityServerRustls;
+/// This program demonstrates how a Reality client hides its authentication
+/// in the ClientHello session_id, and how the server answers with an
+/// HMAC-signed certificate (the same scheme as xray-core).
+use base64::{engine::general_purpose, Engine as _};
+use ring::{aead, hkdf};
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::transport::reality::hello_parser::parse_client_hello_message;
+use xray_lite::transport::reality::{certificate_signature, RealityAuth};
 
 fn main() {
     println!("=== Reality Authentication Demo ===\n");
 
-    // 1. Create Reality server with a test private key
-    let private_key = vec![0x42; 32]; // Test key: all bytes are 0x42
-    println!("1. Creating Reality server...");
+    // 1. Create Reality authenticator with a test private key
+    let private_key = [0x42; 32]; // Test key: all bytes are 0x42
+    let server_public = PublicKey::from(&StaticSecret::from(private_key));
+    println!("1. Creating Reality authenticator...");
     println!("   Private key: {:02x?}...", &private_key[0..8]);
+    println!("   Public key:  {}", general_purpose::URL_SAFE_NO_PAD.encode(server_public.as_bytes()));
 
-    let server = RealityServerRustls::new(
-        private_key,
-        Some("www.microsoft.com:443".to_string()),
-        vec![],
-    )
-    .expect("Failed to create Reality server");
-    println!("   ✓ Server created successfully\n");
+    let auth = RealityAuth::new(private_key, &["0123abcd".to_string()])
+        .expect("Failed to create Reality authenticator");
+    println!("   ✓ Authenticator created successfully\n");
 
-    // 2. Simulate ServerHello.random generation
-    let mut server_random = [0u8; 32];
-    for (i, byte) in server_random.iter_mut().enumerate() {
-        *byte = i as u8; // Fill with sequential values
-    }
-    println!("2. Original ServerHello.random:");
-    println!("   {:02x?}", &server_random);
-    println!("   First 20 bytes: {:02x?}", &server_random[0..20]);
-    println!("   Last 12 bytes:  {:02x?}\n", &server_random[20..32]);
-
-    // 3. Simulate ClientHello.random
+    // 2. Client: ephemeral X25519 key share and random
+    let client_secret = StaticSecret::random_from_rng(rand::rngs::OsRng);
     let client_random = [0x99; 32]; // Test client random: all bytes are 0x99
-    println!("3. ClientHello.random:");
-    println!("   {:02x?}\n", &client_random);
+    println!("2. Client key share: {:02x?}...", &PublicKey::from(&client_secret).as_bytes()[0..8]);
+    println!("   ClientHello.random: {:02x?}...\n", &client_random[0..8]);
 
-    // 4. Inject Reality authentication
-    println!("4. Injecting Reality authentication...");
-    server
-        .test_inject_auth(&mut server_random, &client_random)
-        .expect("Failed to inject Reality auth");
-    println!("   ✓ Authentication injected\n");
+    // 3. Client: build the ClientHello with a zeroed session_id
+    let mut hello = build_client_hello(&client_random, PublicKey::from(&client_secret).as_bytes());
 
-    // 5. Show modified ServerHello.random
-    println!("5. Modified ServerHello.random:");
-    println!("   {:02x?}", &server_random);
-    println!(
-        "   First 20 bytes: {:02x?} (unchanged)",
-        &server_random[0..20]
-    );
-    println!(
-        "   Last 12 bytes:  {:02x?} (HMAC-SHA256)\n",
-        &server_random[20..32]
-    );
+    // 4. Client: auth_key = HKDF-SHA256(ECDH, salt = random[..20], info = "REALITY")
+    let shared = client_secret.diffie_hellman(&server_public);
+    let mut auth_key = [0u8; 32];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, &client_random[..20])
+        .extract(shared.as_bytes())
+        .expand(&[b"REALITY"], hkdf::HKDF_SHA256)
+        .and_then(|okm| okm.fill(&mut auth_key))
+        .expect("HKDF failed");
+    println!("4. Client auth_key: {:02x?}...", &auth_key[0..8]);
 
-    // 6. Verify the modification
-    println!("6. Verification:");
-    let original_last_12 = [20u8, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31];
-    if server_random[20..32] != original_last_12 {
-        println!("   ✓ Last 12 bytes were modified (Reality auth injected)");
-    } else {
-        println!("   ✗ Last 12 bytes were NOT modified (ERROR)");
-    }
+    // 5. Client: seal version | time | shortId into session_id (AAD = ClientHello)
+    let mut session_id = vec![1, 8, 0, 0];
+    session_id.extend_from_slice(&1_700_000_000u32.to_be_bytes());
+    session_id.extend_from_slice(&[0x01, 0x23, 0xab, 0xcd, 0, 0, 0, 0]);
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &auth_key).unwrap());
+    let nonce = aead::Nonce::try_assume_unique_for_key(&client_random[20..]).unwrap();
+    key.seal_in_place_append_tag(nonce, aead::Aad::from(hello.as_slice()), &mut session_id)
+        .expect("AES-GCM seal failed");
+    hello[39..71].copy_from_slice(&session_id);
+    println!("5. Sealed session_id: {:02x?}...\n", &session_id[0..8]);
+
+    // 6. Server: authenticate the ClientHello
+    println!("6. Server authentication:");
+    let client_hello = parse_client_hello_message(hello.clone()).ok().flatten().expect("Invalid ClientHello");
+    match auth.authenticate(&client_hello) {
+        Some(client) => {
+            println!("   ✓ Client {}.{}.{} authenticated", client.version[0], client.version[1], client.version[2]);
+            println!("   shortId: {}, time: {}", hex::encode(client.short_id), client.timestamp);
+            println!("   auth_key matches: {}", client.auth_key == auth_key);
 
-    // 7. Demonstrate determinism
-    println!("\n7. Testing determinism...");
-    let mut server_random2 = [0u8; 32];
-    for (i, byte) in server_random2.iter_mut().enumerate() {
-        *byte = i as u8;
+            // 7. Server: sign the certificate public key
+            let cert_public_key = [0x55; 32];
+            let signature = certificate_signature(&client.auth_key, &cert_public_key);
+            println!("\n7. Certificate signature (HMAC-SHA512): {:02x?}...", &signature[0..8]);
+        }
+        None => println!("   ✗ Authentication failed (ERROR)"),
     }
-    server
-        .test_inject_auth(&mut server_random2, &client_random)
-        .expect("Failed to inject Reality auth");
 
-    if server_random == server_random2 {
-        println!("   ✓ HMAC is deterministic (same input → same output)");
+    // 8. Any change to the ClientHello breaks authentication
+    println!("\n8. Tampering with the ClientHello...");
+    let last = hello.len() - 1;
+    hello[last] ^= 1;
+    let tampered = parse_client_hello_message(hello.clone()).ok().flatten().expect("Invalid ClientHello");
+    if auth.authenticate(&tampered).is_none() {
+        println!("   ✓ Tampered ClientHello rejected");
     } else {
-        println!("   ✗ HMAC is NOT deterministic (ERROR)");
+        println!("   ✗ Tampered ClientHello accepted (ERROR)");
     }
 
     println!("\n=== Demo Complete ===");
     println!("\nSummary:");
-    println!("- Reality authentication uses HMAC-SHA256");
-    println!("- Authentication is injected into ServerHello.random[20..32]");
-    println!("- First 20 bytes of random remain unchanged");
-    println!("- HMAC input: server_random[0..20] + client_random[0..32]");
-    println!("- HMAC key: Reality private key (32 bytes)");
-    println!("\nThis allows Reality clients to verify the server's identity");
-    println!("without relying on traditional TLS certificates!");
+    println!("- auth_key = HKDF-SHA256(X25519(server key, client share), random[..20], \"REALITY\")");
+    println!("- session_id = AES-256-GCM(auth_key, random[20..], version | time | shortId)");
+    println!("- The whole ClientHello (session_id zeroed) is the AEAD associated data");
+    println!("- The server proves itself with HMAC-SHA512(auth_key, certificate public key)");
+}
+
+/// Minimal TLS 1.3 ClientHello handshake message with an X25519 key share.
+fn build_client_hello(random: &[u8; 32], key_share: &[u8; 32]) -> Vec<u8> {
+    let mut extensions = vec![0x00, 0x33, 0x00, 0x26, 0x00, 0x24, 0x00, 0x1d, 0x00, 0x20];
+    extensions.extend_from_slice(key_share);
+
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(random);
+    body.push(32);
+    body.extend_from_slice(&[0u8; 32]);
+    body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extensions);
+
+    let mut hello = vec![0x01];
+    hello.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    hello.extend_from_slice(&body);
+    hello
 }
@@ -1,8 +1,5 @@
 use anyhow::Result;
-use base64::{engine::general_purpose, Engine as _};
-use rand::rngs::OsRng;
-use rand::RngCore;
-use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::utils::X25519KeyPair;
 
 fn main() -> Result<()> {
     println!("========================================");
@@ -10,17 +7,10 @@ fn main() -> Result<()> {
     println!("========================================");
     println!();
 
-    // Generate random static secret (private key)
-    let secret = StaticSecret::random_from_rng(OsRng);
-    let private_bytes = secret.to_bytes();
-
-    // Calculate public key
-    let public_key = PublicKey::from(&secret);
-    let public_bytes = public_key.as_bytes();
-
-    // Encode to Base64 (URL-safe, no padding - Xray format)
-    let private_b64 = general_purpose::URL_SAFE_NO_PAD.encode(private_bytes);
-    let public_b64 = general_purpose::URL_SAFE_NO_PAD.encode(public_bytes);
+    // Generate key pair, encoded as URL-safe Base64 without padding (Xray format)
+    let pair = X25519KeyPair::generate();
+    let private_b64 = pair.private_key_base64();
+    let public_b64 = pair.public_key_base64();
 
     // Output
     println!("Private key: {}", private_b64);
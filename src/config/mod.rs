@@ -12,18 +12,286 @@ pub struct Config {
     pub outbounds: Vec<Outbound>,
     #[serde(default)]
     pub routing: RoutingConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub dns: DnsConfig,
+    #[serde(default)]
+    pub socket: SocketConfig,
+    #[serde(default)]
+    pub connection: ConnectionConfig,
+    #[serde(default)]
+    pub xdp: XdpConfig,
+}
+
+/// 代理连接的转发策略
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConnectionConfig {
+    /// 最长存活时间 (秒)，到期后不论是否活跃都关闭连接；0 为不限制
+    pub max_lifetime: u64,
+    /// 闲置超时 (秒)，两个方向都没有数据达到该时间后关闭连接；0 为不限制
+    pub idle_timeout: u64,
+    /// 所有入站同时接纳的客户端连接数上限，0 为不限制
+    pub max_connections: usize,
+    /// 每个来源 IP 同时接纳的客户端连接数上限，0 为不限制
+    pub max_connections_per_ip: usize,
+    /// 连接数限制的计数时机
+    pub limit_stage: ConnectionLimitStage,
+}
+
+/// 客户端连接在哪个阶段计入连接数限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionLimitStage {
+    /// 接受 TCP 连接时，按对端地址计数，超出时立即关闭
+    #[default]
+    Accept,
+    /// 解析 Proxy Protocol 并完成 Reality 握手之后，按真实客户端地址计数
+    Handshake,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            max_lifetime: 0,
+            idle_timeout: crate::network::connection::DEFAULT_IDLE_TIMEOUT.as_secs(),
+            max_connections: 0,
+            max_connections_per_ip: 0,
+            limit_stage: ConnectionLimitStage::Accept,
+        }
+    }
+}
+
+impl ConnectionConfig {
+    pub fn max_lifetime(&self) -> Option<std::time::Duration> {
+        (self.max_lifetime > 0).then(|| std::time::Duration::from_secs(self.max_lifetime))
+    }
+
+    pub fn idle_timeout(&self) -> Option<std::time::Duration> {
+        (self.idle_timeout > 0).then(|| std::time::Duration::from_secs(self.idle_timeout))
+    }
+}
+
+/// XDP 内核防火墙 (需以 `xdp` feature 构建)
+///
+/// 挂载到 `interface` 后保护所有入站端口: 丢弃非法 TCP 标志，按源 IP 限制 SYN 与 UDP 速率
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct XdpConfig {
+    /// 挂载的网卡，留空时不启用
+    pub interface: String,
+    /// 每个源 IP 每秒允许的 SYN 数，0 为不限制
+    pub syn_rate_limit: u32,
+    /// 每个源 IP 每秒允许的 UDP 包数 (QUIC)，0 为不限制
+    pub udp_rate_limit: u32,
+    /// 直接丢弃的源 IPv4 地址 (所有端口)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub blocklist: Vec<std::net::Ipv4Addr>,
+}
+
+impl Default for XdpConfig {
+    fn default() -> Self {
+        Self {
+            interface: String::new(),
+            syn_rate_limit: 100,
+            udp_rate_limit: 10000,
+            blocklist: Vec::new(),
+        }
+    }
+}
+
+impl XdpConfig {
+    /// 挂载的网卡，未启用时为 `None`
+    pub fn interface(&self) -> Option<&str> {
+        let interface = self.interface.trim();
+        (!interface.is_empty()).then_some(interface)
+    }
+}
+
+/// 入站接受与出站拨出的 TCP 连接的 socket 选项
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SocketConfig {
+    /// 禁用 Nagle 算法
+    #[serde(rename = "tcpNoDelay", default = "default_true")]
+    pub tcp_no_delay: bool,
+    #[serde(rename = "keepAlive", default)]
+    pub keep_alive: KeepAliveConfig,
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        Self {
+            tcp_no_delay: true,
+            keep_alive: KeepAliveConfig::default(),
+        }
+    }
+}
+
+impl SocketConfig {
+    pub fn options(&self) -> crate::network::SocketOptions {
+        crate::network::SocketOptions {
+            no_delay: self.tcp_no_delay,
+            keepalive: self.keep_alive.keepalive(),
+        }
+    }
+}
+
+/// TCP KeepAlive 参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeepAliveConfig {
+    /// 空闲多久后开始探测 (秒)，0 为不启用
+    pub idle: u64,
+    /// 探测间隔 (秒)
+    pub interval: u64,
+    /// 连续无响应的探测次数
+    pub count: u32,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        let keepalive = crate::network::Keepalive::default();
+        Self {
+            idle: keepalive.idle.as_secs(),
+            interval: keepalive.interval.as_secs(),
+            count: keepalive.count,
+        }
+    }
+}
+
+impl KeepAliveConfig {
+    /// 未启用时为 `None`
+    pub fn keepalive(&self) -> Option<crate::network::Keepalive> {
+        use std::time::Duration;
+        (self.idle > 0).then(|| crate::network::Keepalive {
+            idle: Duration::from_secs(self.idle),
+            interval: Duration::from_secs(self.interval),
+            count: self.count,
+        })
+    }
+}
+
+/// 直连出站的域名解析
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnsConfig {
+    /// 上游 DNS 服务器 (`IP[:端口]`、`tls://主机[:端口]` 或 `https://主机[:端口]/路径`)，为空时使用系统解析
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub servers: Vec<String>,
+    /// 缓存时间下限 (秒)，系统解析的结果没有 TTL，按此缓存
+    #[serde(rename = "minTtl", default = "default_dns_min_ttl")]
+    pub min_ttl: u64,
+    /// 缓存时间上限 (秒)
+    #[serde(rename = "maxTtl", default = "default_dns_max_ttl")]
+    pub max_ttl: u64,
+    /// 域名不存在或没有地址记录时的缓存时间 (秒)
+    #[serde(rename = "negativeTtl", default = "default_dns_negative_ttl")]
+    pub negative_ttl: u64,
+}
+
+fn default_dns_min_ttl() -> u64 {
+    10
+}
+
+fn default_dns_max_ttl() -> u64 {
+    3600
+}
+
+fn default_dns_negative_ttl() -> u64 {
+    30
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            servers: Vec::new(),
+            min_ttl: default_dns_min_ttl(),
+            max_ttl: default_dns_max_ttl(),
+            negative_ttl: default_dns_negative_ttl(),
+        }
+    }
+}
+
+impl DnsConfig {
+    pub fn cache_ttl(&self) -> crate::network::dns::CacheTtl {
+        use std::time::Duration;
+        crate::network::dns::CacheTtl {
+            min: Duration::from_secs(self.min_ttl),
+            max: Duration::from_secs(self.max_ttl),
+            negative: Duration::from_secs(self.negative_ttl),
+        }
+    }
+}
+
+/// 日志配置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LogConfig {
+    /// 访问日志: 文件路径或 `stdout`，留空或 `none` 时不记录
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub access: String,
+    /// 访问日志格式
+    #[serde(rename = "accessFormat", default)]
+    pub access_format: AccessLogFormat,
+}
+
+impl LogConfig {
+    /// 访问日志的输出目标，未启用时为 `None`
+    pub fn access_target(&self) -> Option<&str> {
+        let access = self.access.trim();
+        (!access.is_empty() && !access.eq_ignore_ascii_case("none")).then_some(access)
+    }
+}
+
+/// 访问日志格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// 每行一条空格分隔的记录
+    #[default]
+    Text,
+    /// 每行一个 JSON 对象
+    Json,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Inbound {
     pub protocol: Protocol,
-    pub listen: String,
+    pub listen: Listen,
     pub port: u16,
     pub settings: InboundSettings,
     #[serde(rename = "streamSettings")]
     pub stream_settings: StreamSettings,
 }
 
+/// 入站监听地址: 单个 IP 或 IP 列表，例如 `"0.0.0.0"` 或 `["0.0.0.0", "::"]`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Listen {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Listen {
+    pub fn addrs(&self) -> &[String] {
+        match self {
+            Listen::One(addr) => std::slice::from_ref(addr),
+            Listen::Many(addrs) => addrs,
+        }
+    }
+}
+
+impl From<&str> for Listen {
+    fn from(addr: &str) -> Self {
+        Listen::One(addr.to_string())
+    }
+}
+
+impl std::fmt::Display for Listen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.addrs().join(","))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Protocol {
@@ -40,6 +308,9 @@ pub struct InboundSettings {
     pub decryption: String,
     #[serde(default)]
     pub sniffing: SniffingConfig,
+    /// 默认限速 (客户端未单独配置时使用)
+    #[serde(rename = "rateLimit", default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitConfig>,
 }
 
 fn default_true() -> bool {
@@ -52,9 +323,21 @@ pub struct SniffingConfig {
     /// 是否启用嗅探
     #[serde(default)]
     pub enabled: bool,
-    /// 嗅探目标类型
-    #[serde(rename = "destOverride", default = "default_dest_override")]
+    /// 嗅探目标类型 (tls / http / quic)
+    #[serde(rename = "destOverride", alias = "dest_override", default = "default_dest_override")]
     pub dest_override: Vec<String>,
+    /// 仅将嗅探结果用于路由，不替换连接目标
+    #[serde(rename = "routeOnly", alias = "route_only", default)]
+    pub route_only: bool,
+}
+
+impl SniffingConfig {
+    /// 该协议的嗅探结果是否参与目标覆盖
+    pub fn overrides(&self, protocol: &str) -> bool {
+        self.dest_override
+            .iter()
+            .any(|p| p.eq_ignore_ascii_case(protocol))
+    }
 }
 
 impl Default for SniffingConfig {
@@ -62,6 +345,7 @@ impl Default for SniffingConfig {
         Self {
             enabled: false, // 默认关闭
             dest_override: vec!["tls".to_string(), "http".to_string()],
+            route_only: false,
         }
     }
 }
@@ -81,6 +365,40 @@ pub struct Client {
     pub flow: String,
     #[serde(default)]
     pub email: String,
+    /// 用户限速
+    #[serde(rename = "rateLimit", default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+/// 带宽限速配置 (令牌桶)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// 每个方向的速率 (字节/秒)
+    #[serde(rename = "bytesPerSec")]
+    pub bytes_per_sec: u64,
+    /// 突发容量 (字节)，默认等于一秒的速率
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u64>,
+    /// 作用范围
+    #[serde(default)]
+    pub scope: RateLimitScope,
+}
+
+impl RateLimitConfig {
+    pub fn burst(&self) -> u64 {
+        self.burst.unwrap_or(self.bytes_per_sec)
+    }
+}
+
+/// 限速作用范围
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitScope {
+    /// 同一用户的所有连接共享
+    #[default]
+    User,
+    /// 每个连接单独限速
+    Connection,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,6 +409,8 @@ pub struct StreamSettings {
     pub reality_settings: Option<RealitySettings>,
     #[serde(rename = "xhttpSettings", skip_serializing_if = "Option::is_none")]
     pub xhttp_settings: Option<XhttpSettings>,
+    #[serde(rename = "wsSettings", default, skip_serializing_if = "Option::is_none")]
+    pub ws_settings: Option<WsSettings>,
     #[serde(default)]
     pub sockopt: SockOpt,
 }
@@ -101,12 +421,20 @@ pub struct SockOpt {
     /// TCP Fast Open - 减少握手延迟
     #[serde(rename = "tcpFastOpen", default = "default_true")]
     pub tcp_fast_open: bool,
-    /// TCP No Delay (禁用 Nagle 算法) - 减少小包延迟
+    /// TCP No Delay (禁用 Nagle 算法) - 减少小包延迟，关闭时覆盖全局 `socket.tcpNoDelay`
     #[serde(rename = "tcpNoDelay", default = "default_true")]
     pub tcp_no_delay: bool,
     /// 接受 Proxy Protocol (用于获取真实客户端 IP)
     #[serde(rename = "acceptProxyProtocol", default)]
     pub accept_proxy_protocol: bool,
+    /// 监听器绑定到指定网卡 (SO_BINDTODEVICE，仅 Linux)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    /// IPv6 监听器是否只接受 IPv6 (IPV6_V6ONLY)
+    ///
+    /// 未设置时: 同时监听 IPv4 地址的入站只接受 IPv6，否则使用系统默认 (Linux 默认双栈)
+    #[serde(rename = "v6Only", default, skip_serializing_if = "Option::is_none")]
+    pub v6_only: Option<bool>,
 }
 
 impl Default for SockOpt {
@@ -115,6 +443,8 @@ impl Default for SockOpt {
             tcp_fast_open: true,          // 默认开启
             tcp_no_delay: true,           // 默认开启
             accept_proxy_protocol: false, // 默认关闭
+            interface: None,
+            v6_only: None,
         }
     }
 }
@@ -138,7 +468,7 @@ pub enum Security {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RealitySettings {
-    pub dest: String,
+    pub dest: RealityDest,
     #[serde(rename = "serverNames")]
     pub server_names: Vec<String>,
     #[serde(rename = "privateKey")]
@@ -149,6 +479,77 @@ pub struct RealitySettings {
     pub short_ids: Vec<String>,
     #[serde(default = "default_fingerprint")]
     pub fingerprint: String,
+    /// 握手后发送 NewSessionTicket (与真实 TLS 1.3 服务器一致)
+    #[serde(rename = "sessionTickets", default = "default_true")]
+    pub session_tickets: bool,
+    /// 握手超时 (秒)，超时未完成握手的连接直接关闭
+    #[serde(rename = "handshakeTimeout", default = "default_handshake_timeout")]
+    pub handshake_timeout: u64,
+    /// 每个来源 IP 同时进行的未认证握手上限
+    #[serde(rename = "maxHandshakesPerIp", default = "default_max_handshakes_per_ip")]
+    pub max_handshakes_per_ip: usize,
+    /// PEM 证书链: 设置后未通过认证的连接在本地以该证书完成 TLS 握手，再以明文转发到 dest
+    #[serde(rename = "certificateFile", default, skip_serializing_if = "Option::is_none")]
+    pub certificate_file: Option<String>,
+    /// 与 `certificateFile` 配对的 PEM 私钥
+    #[serde(rename = "keyFile", default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<String>,
+    /// 回落连接按 SNI 选择的证书 (serverName → 证书与私钥)，未命中时使用 `certificateFile`
+    #[serde(rename = "serverNameCertificates", default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub server_name_certificates: std::collections::HashMap<String, crate::transport::reality::CertificateFiles>,
+    /// Reality 证书 (以及只配置了 `serverNameCertificates` 时的默认回落证书) 的 SAN、CN、组织与有效期
+    #[serde(rename = "selfSigned", default)]
+    pub self_signed: crate::transport::reality::SelfSignedParams,
+    /// 借用 dest 中间证书的刷新间隔 (秒)，0 表示不借用
+    #[serde(rename = "destCertificateRefresh", default = "default_dest_certificate_refresh")]
+    pub dest_certificate_refresh: u64,
+}
+
+/// Reality 回落目标: 单个地址，或 serverName → 地址 的映射
+///
+/// 映射形式必须包含 `"default"`，未命中的 SNI 回落到该目标:
+/// `{ "default": "www.microsoft.com:443", "www.apple.com": "www.apple.com:443" }`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RealityDest {
+    Single(String),
+    PerServerName(std::collections::BTreeMap<String, String>),
+}
+
+impl RealityDest {
+    pub const DEFAULT_KEY: &'static str = "default";
+
+    /// 未命中任何 serverName 时使用的目标
+    pub fn default_dest(&self) -> Option<&str> {
+        match self {
+            RealityDest::Single(dest) => Some(dest.as_str()),
+            RealityDest::PerServerName(map) => map.get(Self::DEFAULT_KEY).map(String::as_str),
+        }
+    }
+
+    /// 按 serverName 指定的目标 (不含 default)
+    pub fn server_name_dests(&self) -> impl Iterator<Item = (&str, &str)> {
+        let map = match self {
+            RealityDest::Single(_) => None,
+            RealityDest::PerServerName(map) => Some(map),
+        };
+        map.into_iter()
+            .flatten()
+            .filter(|(name, _)| name.as_str() != Self::DEFAULT_KEY)
+            .map(|(name, dest)| (name.as_str(), dest.as_str()))
+    }
+}
+
+fn default_handshake_timeout() -> u64 {
+    10
+}
+
+fn default_max_handshakes_per_ip() -> usize {
+    16
+}
+
+fn default_dest_certificate_refresh() -> u64 {
+    3600
 }
 
 fn default_fingerprint() -> String {
@@ -163,6 +564,172 @@ pub struct XhttpSettings {
     pub path: String,
     #[serde(default = "default_host")]
     pub host: String,
+    /// 允许的 Host (精确域名或 `*.example.com`)，为空时不检查
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// H2 服务端参数，未设置的字段使用默认值
+    #[serde(default)]
+    pub h2: H2Settings,
+    /// packet-up 上行重组的限制
+    #[serde(rename = "packetUp", default)]
+    pub packet_up: PacketUpSettings,
+    /// 分离会话的建立期限
+    #[serde(default)]
+    pub sessions: XhttpSessionSettings,
+    /// 响应头拟态模板
+    #[serde(default)]
+    pub masquerade: crate::transport::xhttp::Masquerade,
+    /// 非 XHTTP 路径请求的伪装站点 (静态目录或反向代理)
+    #[serde(default)]
+    pub decoy: crate::transport::xhttp::Decoy,
+    /// stream-one gRPC 分帧上行的消息大小上限
+    #[serde(default)]
+    pub grpc: crate::transport::xhttp::GrpcLimits,
+    /// 下行随机分块整形
+    #[serde(default)]
+    pub shaping: crate::transport::xhttp::Shaping,
+    /// XHTTP 与 VLESS 处理之间的管道容量
+    #[serde(default)]
+    pub pipe: crate::transport::xhttp::PipeSizing,
+    /// 直接终结 TLS (源站证书)，前面有反向代理时不设置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<crate::transport::TlsSettings>,
+}
+
+/// XHTTP 的 H2 服务端参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct H2Settings {
+    /// 流窗口 (字节)
+    pub initial_window_size: u32,
+    /// 连接窗口 (字节)
+    pub initial_connection_window_size: u32,
+    pub max_concurrent_streams: u32,
+    pub max_frame_size: u32,
+    /// H2 握手超时 (秒)
+    pub handshake_timeout: u64,
+    /// 无活跃流的连接在此时间后关闭 (秒)
+    pub idle_timeout: u64,
+    /// 随机 PING 间隔 (秒)，pingIntervalMax 为 0 时不发送
+    pub ping_interval_min: u64,
+    pub ping_interval_max: u64,
+    /// PING 未被 ACK 时关闭连接的期限 (秒)，0 为不启用
+    pub keepalive_timeout: u64,
+}
+
+impl Default for H2Settings {
+    fn default() -> Self {
+        let tuning = crate::transport::xhttp::H2Tuning::default();
+        Self {
+            initial_window_size: tuning.initial_window_size,
+            initial_connection_window_size: tuning.initial_connection_window_size,
+            max_concurrent_streams: tuning.max_concurrent_streams,
+            max_frame_size: tuning.max_frame_size,
+            handshake_timeout: tuning.handshake_timeout.as_secs(),
+            idle_timeout: tuning.idle_timeout.as_secs(),
+            ping_interval_min: tuning.ping_interval_min.as_secs(),
+            ping_interval_max: tuning.ping_interval_max.as_secs(),
+            keepalive_timeout: tuning.keepalive_timeout.as_secs(),
+        }
+    }
+}
+
+impl H2Settings {
+    pub fn tuning(&self) -> crate::transport::xhttp::H2Tuning {
+        crate::transport::xhttp::H2Tuning {
+            initial_window_size: self.initial_window_size,
+            initial_connection_window_size: self.initial_connection_window_size,
+            max_concurrent_streams: self.max_concurrent_streams,
+            max_frame_size: self.max_frame_size,
+            handshake_timeout: std::time::Duration::from_secs(self.handshake_timeout),
+            idle_timeout: std::time::Duration::from_secs(self.idle_timeout),
+            ping_interval_min: std::time::Duration::from_secs(self.ping_interval_min),
+            ping_interval_max: std::time::Duration::from_secs(self.ping_interval_max),
+            keepalive_timeout: std::time::Duration::from_secs(self.keepalive_timeout),
+        }
+    }
+}
+
+/// XHTTP packet-up 上行重组的限制 (每个会话)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PacketUpSettings {
+    /// 最多领先已交付位置的 POST 数
+    pub max_buffered_posts: usize,
+    /// 单个 POST 的最大字节数
+    pub max_each_post_bytes: usize,
+    /// 乱序缓存的最大字节数
+    pub max_buffered_bytes: usize,
+    /// 缺失的 POST 等待时间 (秒)
+    pub gap_timeout: u64,
+}
+
+impl Default for PacketUpSettings {
+    fn default() -> Self {
+        let limits = crate::transport::xhttp::PacketUpLimits::default();
+        Self {
+            max_buffered_posts: limits.max_buffered_posts,
+            max_each_post_bytes: limits.max_each_post_bytes,
+            max_buffered_bytes: limits.max_buffered_bytes,
+            gap_timeout: limits.gap_timeout.as_secs(),
+        }
+    }
+}
+
+impl PacketUpSettings {
+    pub fn limits(&self) -> crate::transport::xhttp::PacketUpLimits {
+        crate::transport::xhttp::PacketUpLimits {
+            max_buffered_posts: self.max_buffered_posts,
+            max_each_post_bytes: self.max_each_post_bytes,
+            max_buffered_bytes: self.max_buffered_bytes,
+            gap_timeout: std::time::Duration::from_secs(self.gap_timeout),
+        }
+    }
+}
+
+/// XHTTP 分离会话的建立限制
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct XhttpSessionSettings {
+    /// 待建立会话数上限
+    pub max_pending: usize,
+    /// GET 之后等待上行数据的时间 (秒)
+    pub pairing_timeout: u64,
+    /// 等待 VLESS 握手完成的时间 (秒)
+    pub handshake_timeout: u64,
+    /// 每个会话同时进行的上行 POST 数上限
+    pub max_concurrent_posts: usize,
+    /// stream-up 单个上行 POST 的最大字节数，0 为不限制
+    pub max_stream_post_bytes: u64,
+    /// stream-one 单个流或分离会话的上行总字节数上限，0 为不限制
+    pub max_upload_bytes: u64,
+}
+
+impl Default for XhttpSessionSettings {
+    fn default() -> Self {
+        let limits = crate::transport::xhttp::SessionLimits::default();
+        Self {
+            max_pending: limits.max_pending,
+            pairing_timeout: limits.pairing_timeout.as_secs(),
+            handshake_timeout: limits.handshake_timeout.as_secs(),
+            max_concurrent_posts: limits.max_concurrent_posts,
+            max_stream_post_bytes: limits.max_stream_post_bytes,
+            max_upload_bytes: limits.max_upload_bytes,
+        }
+    }
+}
+
+impl XhttpSessionSettings {
+    pub fn limits(&self) -> crate::transport::xhttp::SessionLimits {
+        crate::transport::xhttp::SessionLimits {
+            max_pending: self.max_pending,
+            pairing_timeout: std::time::Duration::from_secs(self.pairing_timeout),
+            handshake_timeout: std::time::Duration::from_secs(self.handshake_timeout),
+            max_concurrent_posts: self.max_concurrent_posts,
+            max_stream_post_bytes: self.max_stream_post_bytes,
+            max_upload_bytes: self.max_upload_bytes,
+        }
+    }
 }
 
 fn default_xhttp_mode() -> XhttpMode {
@@ -177,6 +744,29 @@ fn default_host() -> String {
     "".to_string()
 }
 
+/// WebSocket 传输配置 (network 为 "ws" 时生效)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsSettings {
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// 校验 Host 头，为空时不校验
+    #[serde(default = "default_host")]
+    pub host: String,
+    /// 客户端必须携带的请求头 (如 User-Agent)
+    #[serde(default)]
+    pub headers: std::collections::BTreeMap<String, String>,
+}
+
+impl Default for WsSettings {
+    fn default() -> Self {
+        Self {
+            path: default_path(),
+            host: default_host(),
+            headers: Default::default(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum XhttpMode {
@@ -184,7 +774,10 @@ pub enum XhttpMode {
     Auto,
     StreamUp,
     StreamDown,
+    /// 只接受单个 POST 双向传输
     StreamOne,
+    /// 上行拆分为带序号的 POST
+    PacketUp,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -193,12 +786,158 @@ pub struct Outbound {
     pub tag: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub settings: Option<serde_json::Value>,
+    #[serde(rename = "streamSettings", default, skip_serializing_if = "Option::is_none")]
+    pub stream_settings: Option<OutboundStreamSettings>,
+}
+
+impl Outbound {
+    /// 解析 socks 出站的 settings
+    pub fn socks_settings(&self) -> Result<SocksSettings> {
+        let settings = self
+            .settings
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("缺少 settings"))?;
+        Ok(serde_json::from_value(settings)?)
+    }
+
+    /// 解析 freedom 出站的 settings，未配置时使用默认值
+    pub fn freedom_settings(&self) -> Result<FreedomSettings> {
+        match self.settings.clone() {
+            Some(settings) => Ok(serde_json::from_value(settings)?),
+            None => Ok(FreedomSettings::default()),
+        }
+    }
+
+    /// 解析 vless 出站的 settings
+    pub fn vless_settings(&self) -> Result<VlessOutboundSettings> {
+        let settings = self
+            .settings
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("缺少 settings"))?;
+        Ok(serde_json::from_value(settings)?)
+    }
+}
+
+/// freedom 出站设置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FreedomSettings {
+    #[serde(rename = "happyEyeballs", default)]
+    pub happy_eyeballs: HappyEyeballsSettings,
+}
+
+/// 双栈域名目标的并发拨号 (RFC 8305)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HappyEyeballsSettings {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 向下一个地址发起连接前等待的毫秒数
+    #[serde(rename = "tryDelayMs", default = "default_try_delay_ms")]
+    pub try_delay_ms: u64,
+}
+
+fn default_try_delay_ms() -> u64 {
+    crate::network::happy_eyeballs::DEFAULT_TRY_DELAY.as_millis() as u64
+}
+
+impl Default for HappyEyeballsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            try_delay_ms: default_try_delay_ms(),
+        }
+    }
+}
+
+impl HappyEyeballsSettings {
+    /// 启用时的尝试间隔
+    pub fn try_delay(&self) -> Option<std::time::Duration> {
+        self.enabled.then(|| std::time::Duration::from_millis(self.try_delay_ms))
+    }
+}
+
+/// vless 出站设置 (与 Xray 的格式一致)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VlessOutboundSettings {
+    pub vnext: Vec<VlessServer>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VlessServer {
+    pub address: String,
+    pub port: u16,
+    /// 只使用第一个用户
+    pub users: Vec<VlessUser>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VlessUser {
+    pub id: String,
+}
+
+/// 出站传输设置，目前只支持 Reality
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboundStreamSettings {
+    pub security: Security,
+    #[serde(rename = "realitySettings", skip_serializing_if = "Option::is_none")]
+    pub reality_settings: Option<RealityClientSettings>,
+}
+
+/// Reality 客户端设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RealityClientSettings {
+    #[serde(rename = "serverName")]
+    pub server_name: String,
+    /// 服务端 X25519 公钥 (Base64)
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    #[serde(rename = "shortId", default)]
+    pub short_id: String,
+    #[serde(default = "default_fingerprint")]
+    pub fingerprint: String,
+}
+
+/// socks 出站设置 (与 Xray 的格式一致)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocksSettings {
+    pub servers: Vec<SocksServer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocksServer {
+    pub address: String,
+    pub port: u16,
+    /// 用户名/密码认证 (RFC 1929)，只使用第一个
+    #[serde(default)]
+    pub users: Vec<SocksUser>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocksUser {
+    pub user: String,
+    pub pass: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoutingConfig {
+    /// 目标地址规则，按顺序匹配，指向 blackhole 出站的规则阻止连接
     #[serde(default)]
     pub rules: Vec<RoutingRule>,
+    /// 丢弃识别为 BitTorrent 的连接
+    #[serde(rename = "blockBittorrent", alias = "block_bittorrent", default)]
+    pub block_bittorrent: bool,
+    /// 未命中规则时阻止回环、内网等私有地址
+    #[serde(rename = "blockPrivate", default = "default_true")]
+    pub block_private: bool,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            block_bittorrent: false,
+            block_private: true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -211,6 +950,9 @@ pub struct RoutingRule {
     pub ip: Option<Vec<String>>,
     #[serde(rename = "outboundTag")]
     pub outbound_tag: String,
+    /// 命中该规则的 TCP 连接使用的闲置超时 (秒)，覆盖 `connection.idleTimeout`；0 为不限制
+    #[serde(rename = "idleTimeout", default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout: Option<u64>,
 }
 
 impl Config {
@@ -219,12 +961,17 @@ impl Config {
         let content = fs::read_to_string(path)?;
         let config: Config = serde_json::from_str(&content)?;
 
-        // 验证配置
-        Validator::validate(&config)?;
+        // 验证配置，启动时尽早失败
+        config.validate()?;
 
         Ok(config)
     }
 
+    /// 检查配置有效性，错误信息中包含出错字段的路径
+    pub fn validate(&self) -> Result<()> {
+        Validator::validate(self)
+    }
+
     /// 保存配置到文件
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let content = serde_json::to_string_pretty(self)?;
@@ -274,4 +1021,44 @@ mod tests {
         assert_eq!(config.inbounds.len(), 1);
         assert_eq!(config.outbounds.len(), 1);
     }
+
+    #[test]
+    fn test_listen_forms() {
+        let one: Listen = serde_json::from_str(r#""0.0.0.0""#).unwrap();
+        assert_eq!(one.addrs(), ["0.0.0.0"]);
+        let many: Listen = serde_json::from_str(r#"["0.0.0.0", "::"]"#).unwrap();
+        assert_eq!(many.addrs(), ["0.0.0.0", "::"]);
+        assert_eq!(many.to_string(), "0.0.0.0,::");
+    }
+
+    #[test]
+    fn test_reality_dest_forms() {
+        let single: RealityDest = serde_json::from_str(r#""www.apple.com:443""#).unwrap();
+        assert_eq!(single.default_dest(), Some("www.apple.com:443"));
+        assert_eq!(single.server_name_dests().count(), 0);
+
+        let map: RealityDest = serde_json::from_str(
+            r#"{ "default": "www.microsoft.com:443", "www.apple.com": "17.253.144.10:443" }"#,
+        )
+        .unwrap();
+        assert_eq!(map.default_dest(), Some("www.microsoft.com:443"));
+        assert_eq!(
+            map.server_name_dests().collect::<Vec<_>>(),
+            vec![("www.apple.com", "17.253.144.10:443")]
+        );
+    }
+
+    #[test]
+    fn test_sniffing_deserialization() {
+        let json = r#"{ "enabled": true, "dest_override": ["TLS", "quic"], "route_only": true }"#;
+        let sniffing: SniffingConfig = serde_json::from_str(json).unwrap();
+        assert!(sniffing.route_only);
+        assert!(sniffing.overrides("tls"));
+        assert!(sniffing.overrides("quic"));
+        assert!(!sniffing.overrides("http"));
+
+        let sniffing: SniffingConfig = serde_json::from_str(r#"{ "enabled": true }"#).unwrap();
+        assert!(!sniffing.route_only);
+        assert!(sniffing.overrides("http"));
+    }
 }
@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine as _};
 use uuid::Uuid;
 
 use super::Config;
@@ -17,30 +18,228 @@ impl Validator {
             Self::validate_inbound(inbound, idx)?;
         }
 
+        // 各入站独立监听，地址和端口不能重复
+        for (idx, inbound) in config.inbounds.iter().enumerate() {
+            for addr in inbound.listen.addrs() {
+                if let Some(prev) = config.inbounds[..idx]
+                    .iter()
+                    .position(|other| other.port == inbound.port && other.listen.addrs().contains(addr))
+                {
+                    return Err(anyhow!(
+                        "inbounds[{}].port: {}:{} 已被 inbounds[{}] 使用",
+                        idx,
+                        addr,
+                        inbound.port,
+                        prev
+                    ));
+                }
+            }
+        }
+
         // 验证出站配置
         if config.outbounds.is_empty() {
             return Err(anyhow!("至少需要一个出站配置"));
         }
 
+        // 第一个出站是默认出站，所有连接都经由它发出
+        match config.outbounds[0].protocol.as_str() {
+            "freedom" | "socks" | "vless" => {}
+            other => return Err(anyhow!("outbounds[0].protocol: 不支持的出站协议 {:?}", other)),
+        }
+
+        for (idx, outbound) in config.outbounds.iter().enumerate() {
+            match outbound.protocol.as_str() {
+                "freedom" => Self::validate_freedom_settings(outbound, idx)?,
+                "socks" => Self::validate_socks_settings(outbound, idx)?,
+                "vless" => Self::validate_vless_settings(outbound, idx)?,
+                _ => {}
+            }
+        }
+
+        crate::network::filter::DestinationFilter::from_config(config)?;
+
+        for (idx, server) in config.dns.servers.iter().enumerate() {
+            crate::network::dns::parse_server(server).map_err(|e| anyhow!("dns.servers[{}]: {}", idx, e))?;
+        }
+        let keep_alive = &config.socket.keep_alive;
+        if keep_alive.idle > 0 {
+            if keep_alive.interval == 0 {
+                return Err(anyhow!("socket.keepAlive.interval: 必须大于 0"));
+            }
+            if keep_alive.count == 0 {
+                return Err(anyhow!("socket.keepAlive.count: 必须大于 0"));
+            }
+        }
+
+        if config.dns.min_ttl > config.dns.max_ttl {
+            return Err(anyhow!(
+                "dns.minTtl: 不能大于 maxTtl ({} > {})",
+                config.dns.min_ttl,
+                config.dns.max_ttl
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn validate_freedom_settings(outbound: &super::Outbound, idx: usize) -> Result<()> {
+        let settings = outbound
+            .freedom_settings()
+            .map_err(|e| anyhow!("outbounds[{}].settings: {}", idx, e))?;
+        // RFC 8305: 间隔不应低于 10ms，超过 2s 已失去并发拨号的意义
+        let delay = settings.happy_eyeballs.try_delay_ms;
+        if !(10..=2000).contains(&delay) {
+            return Err(anyhow!(
+                "outbounds[{}].settings.happyEyeballs.tryDelayMs: 必须在 10-2000 之间 (当前 {})",
+                idx,
+                delay
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_socks_settings(outbound: &super::Outbound, idx: usize) -> Result<()> {
+        let settings = outbound
+            .socks_settings()
+            .map_err(|e| anyhow!("outbounds[{}].settings: {}", idx, e))?;
+        let server = settings
+            .servers
+            .first()
+            .ok_or_else(|| anyhow!("outbounds[{}].settings.servers: 至少需要一个 SOCKS 服务器", idx))?;
+        if server.address.is_empty() || server.port == 0 {
+            return Err(anyhow!("outbounds[{}].settings.servers[0]: 地址和端口不能为空", idx));
+        }
+        // RFC 1929: 用户名和密码长度均为 1-255 字节
+        if let Some(user) = server.users.first() {
+            if !(1..=255).contains(&user.user.len()) || !(1..=255).contains(&user.pass.len()) {
+                return Err(anyhow!(
+                    "outbounds[{}].settings.servers[0].users[0]: 用户名和密码长度必须为 1-255 字节",
+                    idx
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_vless_settings(outbound: &super::Outbound, idx: usize) -> Result<()> {
+        let settings = outbound
+            .vless_settings()
+            .map_err(|e| anyhow!("outbounds[{}].settings: {}", idx, e))?;
+        let server = settings
+            .vnext
+            .first()
+            .ok_or_else(|| anyhow!("outbounds[{}].settings.vnext: 至少需要一个服务器", idx))?;
+        if server.address.is_empty() || server.port == 0 {
+            return Err(anyhow!("outbounds[{}].settings.vnext[0]: 地址和端口不能为空", idx));
+        }
+        let user = server
+            .users
+            .first()
+            .ok_or_else(|| anyhow!("outbounds[{}].settings.vnext[0].users: 至少需要一个用户", idx))?;
+        if Uuid::parse_str(&user.id).is_err() {
+            return Err(anyhow!(
+                "outbounds[{}].settings.vnext[0].users[0].id: UUID 格式无效: {}",
+                idx,
+                user.id
+            ));
+        }
+
+        // 出站只支持 Reality 传输
+        let field = format!("outbounds[{}].streamSettings", idx);
+        let reality = match &outbound.stream_settings {
+            Some(super::OutboundStreamSettings {
+                security: super::Security::Reality,
+                reality_settings: Some(reality),
+            }) => reality,
+            _ => return Err(anyhow!("{}: vless 出站需要 security 为 reality 且配置 realitySettings", field)),
+        };
+        if reality.server_name.is_empty() {
+            return Err(anyhow!("{}.realitySettings.serverName: 不能为空", field));
+        }
+        let key = URL_SAFE_NO_PAD
+            .decode(&reality.public_key)
+            .or_else(|_| STANDARD.decode(&reality.public_key))
+            .map_err(|e| anyhow!("{}.realitySettings.publicKey: Base64 解码失败: {}", field, e))?;
+        if key.len() != 32 {
+            return Err(anyhow!(
+                "{}.realitySettings.publicKey: 解码后应为 32 字节，实际为 {} 字节",
+                field,
+                key.len()
+            ));
+        }
+        if reality.short_id.len() > 16 || hex::decode(&reality.short_id).is_err() {
+            return Err(anyhow!("{}.realitySettings.shortId: 必须是最长 16 位的十六进制", field));
+        }
         Ok(())
     }
 
     fn validate_inbound(inbound: &super::Inbound, idx: usize) -> Result<()> {
         // 验证端口
         if inbound.port == 0 {
-            return Err(anyhow!("入站 {} 的端口不能为 0", idx));
+            return Err(anyhow!("inbounds[{}].port: 端口不能为 0", idx));
+        }
+
+        // 监听地址必须是 IP，列表形式不能为空或重复
+        let addrs = inbound.listen.addrs();
+        if addrs.is_empty() {
+            return Err(anyhow!("inbounds[{}].listen: 至少需要一个监听地址", idx));
+        }
+        for (addr_idx, addr) in addrs.iter().enumerate() {
+            let field = match inbound.listen {
+                super::Listen::One(_) => format!("inbounds[{}].listen", idx),
+                super::Listen::Many(_) => format!("inbounds[{}].listen[{}]", idx, addr_idx),
+            };
+            if addr.parse::<std::net::IpAddr>().is_err() {
+                return Err(anyhow!("{}: 不是有效的 IP 地址: {:?}", field, addr));
+            }
+            if addrs[..addr_idx].contains(addr) {
+                return Err(anyhow!("{}: 地址重复: {}", field, addr));
+            }
+        }
+
+        if let Some(interface) = &inbound.stream_settings.sockopt.interface {
+            if interface.is_empty() {
+                return Err(anyhow!("inbounds[{}].streamSettings.sockopt.interface: 不能为空", idx));
+            }
+            if !cfg!(target_os = "linux") {
+                return Err(anyhow!("inbounds[{}].streamSettings.sockopt.interface: 仅 Linux 支持绑定网卡", idx));
+            }
+        }
+
+        // 至少一个用户
+        if inbound.settings.clients.is_empty() {
+            return Err(anyhow!("inbounds[{}].settings.clients: 至少需要配置一个用户", idx));
         }
 
         // 验证客户端 UUID
         for (client_idx, client) in inbound.settings.clients.iter().enumerate() {
             if Uuid::parse_str(&client.id).is_err() {
                 return Err(anyhow!(
-                    "入站 {} 的客户端 {} UUID 格式无效: {}",
+                    "inbounds[{}].settings.clients[{}].id: UUID 格式无效: {}",
                     idx,
                     client_idx,
                     client.id
                 ));
             }
+            if !client.flow.is_empty() {
+                return Err(anyhow!(
+                    "inbounds[{}].settings.clients[{}].flow: 不支持 {:?}，只支持空 flow (不使用 XTLS Vision)",
+                    idx,
+                    client_idx,
+                    client.flow
+                ));
+            }
+        }
+
+        // 验证限速设置
+        let client_limits = inbound.settings.clients.iter().map(|c| c.rate_limit.as_ref());
+        for limit in std::iter::once(inbound.settings.rate_limit.as_ref())
+            .chain(client_limits)
+            .flatten()
+        {
+            if limit.bytes_per_sec == 0 || limit.burst() == 0 {
+                return Err(anyhow!("inbounds[{}] rateLimit: 速率和突发容量必须大于 0", idx));
+            }
         }
 
         // 验证 Reality 设置
@@ -51,6 +250,20 @@ impl Validator {
         // 验证 XHTTP 设置
         if let Some(xhttp) = &inbound.stream_settings.xhttp_settings {
             Self::validate_xhttp_settings(xhttp, idx)?;
+            if xhttp.tls.is_some() && matches!(inbound.stream_settings.security, super::Security::Reality) {
+                return Err(anyhow!("inbounds[{}].streamSettings.xhttpSettings.tls: 不能与 Reality 同时使用", idx));
+            }
+        }
+
+        // 验证 WebSocket 设置
+        if let Some(ws) = &inbound.stream_settings.ws_settings {
+            if !ws.path.starts_with('/') {
+                return Err(anyhow!(
+                    "inbounds[{}].streamSettings.wsSettings.path: 必须以 / 开头 (当前为 {:?})",
+                    idx,
+                    ws.path
+                ));
+            }
         }
 
         Ok(())
@@ -60,27 +273,67 @@ impl Validator {
         reality: &super::RealitySettings,
         inbound_idx: usize,
     ) -> Result<()> {
+        let field = format!("inbounds[{}].streamSettings.realitySettings", inbound_idx);
+
         // 验证目标地址
-        if reality.dest.is_empty() {
-            return Err(anyhow!("入站 {} 的 Reality dest 不能为空", inbound_idx));
+        match reality.dest.default_dest() {
+            Some(dest) if !dest.is_empty() => {}
+            Some(_) => return Err(anyhow!("{}.dest: 不能为空", field)),
+            None => return Err(anyhow!("{}.dest: 按 serverName 配置时必须包含 \"default\"", field)),
+        }
+        if let Some((name, _)) = reality.dest.server_name_dests().find(|(_, dest)| dest.is_empty()) {
+            return Err(anyhow!("{}.dest.{}: 不能为空", field, name));
         }
 
-        // 验证服务器名称
-        if reality.server_names.is_empty() {
-            return Err(anyhow!(
-                "入站 {} 的 Reality serverNames 不能为空",
-                inbound_idx
-            ));
+        // 验证服务器名称 (dest 映射中的 serverName 同样被接受)
+        if reality.server_names.is_empty() && reality.dest.server_name_dests().next().is_none() {
+            return Err(anyhow!("{}.serverNames: 不能为空", field));
         }
 
-        // 验证私钥
+        // 验证私钥 (与 RealityServer 相同: URL-Safe No Padding 或 Standard Base64)
         if reality.private_key.is_empty() {
+            return Err(anyhow!("{}.privateKey: 不能为空", field));
+        }
+        let key = URL_SAFE_NO_PAD
+            .decode(&reality.private_key)
+            .or_else(|_| STANDARD.decode(&reality.private_key))
+            .map_err(|e| anyhow!("{}.privateKey: Base64 解码失败: {}", field, e))?;
+        if key.len() != 32 {
             return Err(anyhow!(
-                "入站 {} 的 Reality privateKey 不能为空",
-                inbound_idx
+                "{}.privateKey: 解码后应为 32 字节，实际为 {} 字节",
+                field,
+                key.len()
             ));
         }
 
+        if reality.handshake_timeout == 0 {
+            return Err(anyhow!("{}.handshakeTimeout: 必须大于 0", field));
+        }
+        if reality.max_handshakes_per_ip == 0 {
+            return Err(anyhow!("{}.maxHandshakesPerIp: 必须大于 0", field));
+        }
+
+        // 证书在加载配置时读取并校验，避免运行中才发现证书与私钥不配对
+        match (&reality.certificate_file, &reality.key_file) {
+            (Some(certificate_file), Some(key_file)) => {
+                crate::transport::load_key_pair(certificate_file, key_file)
+                    .map_err(|e| anyhow!("{}.certificateFile: {}", field, e))?;
+            }
+            (None, None) => {}
+            (Some(_), None) => return Err(anyhow!("{}.keyFile: 设置 certificateFile 时不能为空", field)),
+            (None, Some(_)) => return Err(anyhow!("{}.certificateFile: 设置 keyFile 时不能为空", field)),
+        }
+        if reality.self_signed.validity_days == 0 {
+            return Err(anyhow!("{}.selfSigned.validityDays: 必须大于 0", field));
+        }
+        if reality.self_signed.domains.iter().any(String::is_empty) {
+            return Err(anyhow!("{}.selfSigned.domains: 不能包含空域名", field));
+        }
+        for (name, files) in &reality.server_name_certificates {
+            crate::transport::load_key_pair(&files.certificate_file, &files.key_file)
+                .map_err(|e| anyhow!("{}.serverNameCertificates.{}: {}", field, name, e))?;
+        }
+
         Ok(())
     }
 
@@ -88,8 +341,48 @@ impl Validator {
         // host validation removed to allow empty host
 
         // 验证 path
-        if xhttp.path.is_empty() {
-            return Err(anyhow!("入站 {} 的 XHTTP path 不能为空", inbound_idx));
+        if !xhttp.path.starts_with('/') {
+            return Err(anyhow!(
+                "inbounds[{}].streamSettings.xhttpSettings.path: 必须以 / 开头 (当前为 {:?})",
+                inbound_idx,
+                xhttp.path
+            ));
+        }
+
+        // 验证 H2 参数
+        xhttp.h2.tuning().validate().map_err(|e| {
+            anyhow!("inbounds[{}].streamSettings.xhttpSettings.h2.{}", inbound_idx, e)
+        })?;
+        xhttp.packet_up.limits().validate().map_err(|e| {
+            anyhow!("inbounds[{}].streamSettings.xhttpSettings.packetUp.{}", inbound_idx, e)
+        })?;
+        xhttp.sessions.limits().validate().map_err(|e| {
+            anyhow!("inbounds[{}].streamSettings.xhttpSettings.sessions.{}", inbound_idx, e)
+        })?;
+        xhttp.masquerade.validate().map_err(|e| {
+            anyhow!("inbounds[{}].streamSettings.xhttpSettings.masquerade.{}", inbound_idx, e)
+        })?;
+        xhttp.decoy.validate().map_err(|e| {
+            anyhow!("inbounds[{}].streamSettings.xhttpSettings.decoy.{}", inbound_idx, e)
+        })?;
+        for (j, pattern) in xhttp.hosts.iter().enumerate() {
+            crate::transport::xhttp::validate_host_pattern(pattern).map_err(|e| {
+                anyhow!("inbounds[{}].streamSettings.xhttpSettings.hosts[{}]: {}", inbound_idx, j, e)
+            })?;
+        }
+        xhttp.grpc.validate().map_err(|e| {
+            anyhow!("inbounds[{}].streamSettings.xhttpSettings.grpc.{}", inbound_idx, e)
+        })?;
+        xhttp.shaping.validate().map_err(|e| {
+            anyhow!("inbounds[{}].streamSettings.xhttpSettings.shaping.{}", inbound_idx, e)
+        })?;
+        xhttp.pipe.validate().map_err(|e| {
+            anyhow!("inbounds[{}].streamSettings.xhttpSettings.pipe.{}", inbound_idx, e)
+        })?;
+        if let Some(tls) = &xhttp.tls {
+            tls.validate().map_err(|e| {
+                anyhow!("inbounds[{}].streamSettings.xhttpSettings.tls.{}", inbound_idx, e)
+            })?;
         }
 
         Ok(())
@@ -106,36 +399,55 @@ mod tests {
         let config = Config {
             inbounds: vec![Inbound {
                 protocol: Protocol::Vless,
-                listen: "0.0.0.0".to_string(),
+                listen: "0.0.0.0".into(),
                 port: 443,
                 settings: InboundSettings {
                     clients: vec![Client {
                         id: "b831381d-6324-4d53-ad4f-8cda48b30811".to_string(),
                         flow: "".to_string(),
                         email: "".to_string(),
+                        rate_limit: None,
                     }],
                     decryption: "none".to_string(),
+                    sniffing: SniffingConfig::default(),
+                    rate_limit: None,
                 },
                 stream_settings: StreamSettings {
                     network: Network::Tcp,
                     security: Security::Reality,
                     reality_settings: Some(RealitySettings {
-                        dest: "www.apple.com:443".to_string(),
+                        dest: RealityDest::Single("www.apple.com:443".to_string()),
                         server_names: vec!["www.apple.com".to_string()],
-                        private_key: "test_key".to_string(),
+                        private_key: "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=".to_string(),
                         public_key: None,
                         short_ids: vec!["0123456789abcdef".to_string()],
                         fingerprint: "chrome".to_string(),
+                        session_tickets: true,
+                        handshake_timeout: 10,
+                        max_handshakes_per_ip: 16,
+                        certificate_file: None,
+                        key_file: None,
+                        server_name_certificates: Default::default(),
+                        self_signed: Default::default(),
+                        dest_certificate_refresh: 3600,
                     }),
                     xhttp_settings: None,
+                    ws_settings: None,
+                    sockopt: SockOpt::default(),
                 },
             }],
             outbounds: vec![Outbound {
                 protocol: "freedom".to_string(),
                 tag: "direct".to_string(),
                 settings: None,
+                stream_settings: None,
             }],
             routing: RoutingConfig::default(),
+            log: LogConfig::default(),
+            dns: DnsConfig::default(),
+            socket: SocketConfig::default(),
+            connection: Default::default(),
+            xdp: Default::default(),
         };
 
         assert!(Validator::validate(&config).is_ok());
@@ -146,31 +458,273 @@ mod tests {
         let config = Config {
             inbounds: vec![Inbound {
                 protocol: Protocol::Vless,
-                listen: "0.0.0.0".to_string(),
+                listen: "0.0.0.0".into(),
                 port: 443,
                 settings: InboundSettings {
                     clients: vec![Client {
                         id: "invalid-uuid".to_string(),
                         flow: "".to_string(),
                         email: "".to_string(),
+                        rate_limit: None,
                     }],
                     decryption: "none".to_string(),
+                    sniffing: SniffingConfig::default(),
+                    rate_limit: None,
                 },
                 stream_settings: StreamSettings {
                     network: Network::Tcp,
                     security: Security::None,
                     reality_settings: None,
                     xhttp_settings: None,
+                    ws_settings: None,
+                    sockopt: SockOpt::default(),
                 },
             }],
             outbounds: vec![Outbound {
                 protocol: "freedom".to_string(),
                 tag: "direct".to_string(),
                 settings: None,
+                stream_settings: None,
             }],
             routing: RoutingConfig::default(),
+            log: LogConfig::default(),
+            dns: DnsConfig::default(),
+            socket: SocketConfig::default(),
+            connection: Default::default(),
+            xdp: Default::default(),
         };
 
         assert!(Validator::validate(&config).is_err());
     }
+
+    fn minimal_config() -> Config {
+        serde_json::from_str(
+            r#"{
+                "inbounds": [{
+                    "protocol": "vless",
+                    "listen": "0.0.0.0",
+                    "port": 443,
+                    "settings": { "clients": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }] },
+                    "streamSettings": {
+                        "network": "tcp",
+                        "security": "reality",
+                        "realitySettings": {
+                            "dest": "www.apple.com:443",
+                            "serverNames": ["www.apple.com"],
+                            "privateKey": "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE",
+                            "shortIds": [""]
+                        },
+                        "xhttpSettings": { "path": "/xhttp" }
+                    }
+                }],
+                "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn error_of(config: &Config) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_field_errors() {
+        assert!(minimal_config().validate().is_ok());
+
+        let mut config = minimal_config();
+        config.inbounds[0].port = 0;
+        assert!(error_of(&config).starts_with("inbounds[0].port"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].settings.clients.clear();
+        assert!(error_of(&config).starts_with("inbounds[0].settings.clients"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].settings.clients[0].flow = "xtls-rprx-vision".to_string();
+        assert!(error_of(&config).starts_with("inbounds[0].settings.clients[0].flow"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.reality_settings.as_mut().unwrap().private_key =
+            "QUFBQQ".to_string();
+        let err = error_of(&config);
+        assert!(err.starts_with("inbounds[0].streamSettings.realitySettings.privateKey"));
+        assert!(err.contains("4 字节"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.reality_settings.as_mut().unwrap().dest =
+            serde_json::from_str(r#"{ "www.apple.com": "www.apple.com:443" }"#).unwrap();
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.realitySettings.dest"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.reality_settings.as_mut().unwrap().handshake_timeout = 0;
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.realitySettings.handshakeTimeout"));
+
+        let mut config = minimal_config();
+        let reality = config.inbounds[0].stream_settings.reality_settings.as_mut().unwrap();
+        reality.certificate_file = Some("/nonexistent/reality.pem".to_string());
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.realitySettings.keyFile"));
+        let reality = config.inbounds[0].stream_settings.reality_settings.as_mut().unwrap();
+        reality.key_file = Some("/nonexistent/reality.key".to_string());
+        let err = error_of(&config);
+        assert!(err.starts_with("inbounds[0].streamSettings.realitySettings.certificateFile"));
+        assert!(err.contains("无法读取证书"), "{}", err);
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.reality_settings.as_mut().unwrap().server_name_certificates =
+            serde_json::from_str(r#"{ "www.apple.com": { "certificateFile": "/nonexistent/a.pem", "keyFile": "/nonexistent/a.key" } }"#)
+                .unwrap();
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.realitySettings.serverNameCertificates.www.apple.com"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.reality_settings.as_mut().unwrap().self_signed =
+            serde_json::from_str(r#"{ "commonName": "www.apple.com", "validityDays": 0 }"#).unwrap();
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.realitySettings.selfSigned.validityDays"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().path = "xhttp".to_string();
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.path"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().h2.max_frame_size = 1024;
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.h2.maxFrameSize"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().packet_up.gap_timeout = 0;
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.packetUp.gapTimeout"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().sessions.max_pending = 0;
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.sessions.maxPending"));
+        let sessions = &mut config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().sessions;
+        sessions.max_pending = 1;
+        sessions.max_concurrent_posts = 0;
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.sessions.maxConcurrentPosts"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().grpc.max_message_bytes = 0;
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.grpc.maxMessageBytes"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().shaping.max_chunk_size = 4096;
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.shaping.maxChunkSize"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().pipe.initial_size = 0;
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.pipe.initialSize"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().tls = Some(crate::transport::TlsSettings {
+            certificate_file: "/etc/ssl/origin.pem".into(),
+            key_file: "/etc/ssl/origin.key".into(),
+            alpn: vec!["h2".into(), "spdy/3".into()],
+        });
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.tls.alpn"));
+        // minimal_config 使用 Reality，证书设置正确时报告冲突
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().tls.as_mut().unwrap().alpn = vec!["h2".into()];
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.tls: 不能与 Reality"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().hosts =
+            vec!["*.example.com".into(), "cdn.example.com:443".into()];
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.hosts[1]"));
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().hosts = vec!["cdn.*.com".into()];
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.hosts[0]"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().masquerade.headers = vec!["Server: Apache".into()];
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.masquerade.headers"));
+
+        let mut config = minimal_config();
+        config.inbounds[0].stream_settings.xhttp_settings.as_mut().unwrap().decoy.proxy = Some("127.0.0.1".into());
+        assert!(error_of(&config).starts_with("inbounds[0].streamSettings.xhttpSettings.decoy.proxy"));
+
+        let mut config = minimal_config();
+        config.inbounds.push(config.inbounds[0].clone());
+        assert!(error_of(&config).starts_with("inbounds[1].port"));
+        config.inbounds[1].port = 8443;
+        assert!(config.validate().is_ok());
+
+        // 列表形式: 与其他入站共用任一地址即冲突
+        config.inbounds[1].listen = Listen::Many(vec!["::".into(), "0.0.0.0".into()]);
+        config.inbounds[1].port = config.inbounds[0].port;
+        assert!(error_of(&config).starts_with("inbounds[1].port"));
+        config.inbounds[1].listen = Listen::Many(vec!["::".into(), "localhost".into()]);
+        assert!(error_of(&config).starts_with("inbounds[1].listen[1]"));
+        config.inbounds[1].listen = Listen::Many(vec!["::".into()]);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_outbound_errors() {
+        let mut config = minimal_config();
+        config.outbounds[0].protocol = "blackhole".to_string();
+        assert!(error_of(&config).starts_with("outbounds[0].protocol"));
+
+        let mut config = minimal_config();
+        config.dns.servers = vec!["1.1.1.1".to_string(), "quic://1.1.1.1".to_string()];
+        assert!(error_of(&config).starts_with("dns.servers[1]"));
+        config.dns.servers.pop();
+        config.dns.min_ttl = config.dns.max_ttl + 1;
+        assert!(error_of(&config).starts_with("dns.minTtl"));
+
+        // idle 为 0 时不启用 KeepAlive，其余参数不检查
+        let mut config = minimal_config();
+        config.socket.keep_alive.count = 0;
+        assert!(error_of(&config).starts_with("socket.keepAlive.count"));
+        config.socket.keep_alive.idle = 0;
+        assert!(config.validate().is_ok());
+
+        let mut config = minimal_config();
+        config.outbounds[0].settings = Some(serde_json::json!({ "happyEyeballs": { "tryDelayMs": 5 } }));
+        assert!(error_of(&config).starts_with("outbounds[0].settings.happyEyeballs.tryDelayMs"));
+        config.outbounds[0].settings = Some(serde_json::json!({ "happyEyeballs": { "enabled": false } }));
+        assert!(config.validate().is_ok());
+
+        let mut config = minimal_config();
+        config.outbounds[0].protocol = "socks".to_string();
+        assert!(error_of(&config).starts_with("outbounds[0].settings"));
+
+        config.outbounds[0].settings = Some(serde_json::json!({ "servers": [] }));
+        assert!(error_of(&config).starts_with("outbounds[0].settings.servers"));
+
+        config.outbounds[0].settings = Some(serde_json::json!({
+            "servers": [{ "address": "127.0.0.1", "port": 1080, "users": [{ "user": "", "pass": "x" }] }]
+        }));
+        assert!(error_of(&config).starts_with("outbounds[0].settings.servers[0].users[0]"));
+
+        config.outbounds[0].settings = Some(serde_json::json!({
+            "servers": [{ "address": "127.0.0.1", "port": 1080 }]
+        }));
+        assert!(config.validate().is_ok());
+
+        let mut config = minimal_config();
+        config.outbounds[0] = serde_json::from_value(serde_json::json!({
+            "protocol": "vless",
+            "tag": "upstream",
+            "settings": {
+                "vnext": [{
+                    "address": "203.0.113.1",
+                    "port": 443,
+                    "users": [{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811" }]
+                }]
+            },
+            "streamSettings": {
+                "security": "reality",
+                "realitySettings": {
+                    "serverName": "www.apple.com",
+                    "publicKey": "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE",
+                    "shortId": "0123"
+                }
+            }
+        }))
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        config.outbounds[0].stream_settings.as_mut().unwrap().reality_settings.as_mut().unwrap().short_id =
+            "xyz".to_string();
+        assert!(error_of(&config).starts_with("outbounds[0].streamSettings.realitySettings.shortId"));
+
+        config.outbounds[0].stream_settings = None;
+        assert!(error_of(&config).starts_with("outbounds[0].streamSettings"));
+    }
 }
@@ -1,16 +1,27 @@
 use anyhow::Result;
-use tracing::{info, error, debug};
-use crate::server::AsyncStream;
-use crate::protocol::vless::{VlessCodec, Command, VlessResponse};
-use crate::network::ConnectionManager;
+use tracing::{info, error, debug, warn};
+use crate::server::{AsyncStream, AuthReport};
+use crate::config::SniffingConfig;
+use crate::protocol::sniffer::is_bittorrent;
+use crate::protocol::vless::{Address, VlessCodec, Command, VlessResponse};
+use crate::network::{AccessEntry, CloseReason, ConnectionManager, CountingStream, Outbound, Verdict};
+
+/// 嗅探等待首包的超时时间
+const SNIFF_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(300);
 
 /// 处理 VLESS 会话核心逻辑
+///
+/// 请求头通过 UUID 校验后经 `auth` 报告用户标签
+#[allow(clippy::too_many_arguments)]
 pub async fn serve_vless(
     mut stream: Box<dyn AsyncStream>,
+    auth: AuthReport,
+    source: Option<std::net::SocketAddr>,
     codec: VlessCodec,
     connection_manager: ConnectionManager,
-    sniffing_enabled: bool,
-    tcp_no_delay: bool,
+    sniffing: SniffingConfig,
+    outbound: std::sync::Arc<dyn Outbound>,
+    block_bittorrent: bool,
 ) -> Result<()> {
     // 读取 VLESS 请求（带超时，支持多次读取）
     // Optimize: 增大缓冲区至 16KB 以减少系统调用，提升高吞吐场景性能
@@ -60,7 +71,17 @@ pub async fn serve_vless(
             return Err(e);
         }
     };
-    info!("📨 VLESS 请求: {:?} -> {}", request.command, request.address.to_string());
+    // 只实现了普通 VLESS: Vision 客户端会期待填充帧并在内层 TLS 握手后改用直连拷贝，继续转发只会得到损坏的流。
+    // 在报告认证结果之前拒绝，XHTTP 会话的上行闸门不会因此放行
+    if !request.flow.is_empty() {
+        warn!("❌ 不支持的 flow {:?} (用户 {})，客户端的 flow 须留空", request.flow, codec.label_for(&request.uuid));
+        return Err(anyhow::anyhow!("不支持的 flow: {}", request.flow));
+    }
+    auth.authenticated(codec.label_for(&request.uuid));
+    match request.mux_session_id {
+        Some(session_id) => info!("📨 VLESS 请求: {:?} -> {} (Mux Session: {})", request.command, request.address, session_id),
+        None => info!("📨 VLESS 请求: {:?} -> {}", request.command, request.address),
+    }
 
     // 发送 VLESS 响应
     let response = VlessResponse::new();
@@ -70,10 +91,44 @@ pub async fn serve_vless(
     stream.write_all(&response_bytes).await?;
     stream.flush().await?; // 确保响应已发送
 
-    // 根据命令类型处理
+    let destination = match request.command {
+        Command::Tcp => format!("tcp:{}", request.address),
+        Command::Udp => format!("udp:{}", request.address),
+        Command::Mux => "mux".to_string(),
+    };
+    let mut connection = connection_manager.open(AccessEntry {
+        source,
+        user: codec.label_for(&request.uuid),
+        destination,
+    });
+    // 请求头之后已读入的数据同样计为上行
+    let stats = connection.stats();
+    stats.add_up(buf.len() as u64);
+    let stream: Box<dyn AsyncStream> = Box::new(CountingStream::new(stream, stats));
+
+    let result = serve_command(stream, buf, request, codec, connection_manager, sniffing, outbound, block_bittorrent).await;
+    connection.finish(&result);
+    result.map(|_| ())
+}
+
+/// 按命令类型处理已认证的 VLESS 会话
+#[allow(clippy::too_many_arguments)]
+async fn serve_command(
+    mut stream: Box<dyn AsyncStream>,
+    mut buf: bytes::BytesMut,
+    request: crate::protocol::vless::VlessRequest,
+    codec: VlessCodec,
+    connection_manager: ConnectionManager,
+    sniffing: SniffingConfig,
+    outbound: std::sync::Arc<dyn Outbound>,
+    block_bittorrent: bool,
+) -> Result<CloseReason> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::time::{timeout, Duration};
+
     match request.command {
         Command::Tcp => {
-            let mut target_address = request.address.to_string();
+            let mut target_address = request.address.clone();
             let mut initial_data = Vec::new();
 
             // --- 🌟 SNIFFING START ---
@@ -82,120 +137,132 @@ pub async fn serve_vless(
                 buf.clear(); 
             }
 
-            if sniffing_enabled {
-                // 如果没有初始数据，尝试再次通过超时读取
-                if initial_data.is_empty() {
-                    let mut temp_buf = vec![0u8; 16384];
-                    if let Ok(Ok(n)) = timeout(Duration::from_millis(500), stream.read(&mut temp_buf)).await {
-                         if n > 0 {
-                             initial_data.extend_from_slice(&temp_buf[..n]);
-                             debug!("Sniffing: 读取了额外的 {} 字节", n);
-                         }
-                    }
+            // 如果没有初始数据，尝试再次通过超时读取 (超时则沿用原始目标)
+            if (sniffing.enabled || block_bittorrent) && initial_data.is_empty() {
+                let mut temp_buf = vec![0u8; 16384];
+                if let Ok(Ok(n)) = timeout(SNIFF_TIMEOUT, stream.read(&mut temp_buf)).await {
+                     if n > 0 {
+                         initial_data.extend_from_slice(&temp_buf[..n]);
+                         debug!("Sniffing: 读取了额外的 {} 字节", n);
+                     }
                 }
+            }
+
+            if block_bittorrent && is_bittorrent(&initial_data) {
+                log_bittorrent_blocked(&codec.label_for(&request.uuid), &target_address.to_string());
+                return Ok(CloseReason::Closed);
+            }
 
-                if !initial_data.is_empty() {
-                    if let Some(sni) = crate::protocol::sniffer::sniff_tls_sni(&initial_data) {
-                        info!("👃 Sniffed SNI: {} (Override: {})", sni, target_address);
-                        // 判断是否需要覆盖目标地址
-                        // 这里不再做 dest_override 过滤，简单起见总是覆盖
-                        // 实际应根据配置判断
-                         target_address = format!("{}:443", sni);
+            // 路由使用的目标 (routeOnly 时与连接目标不同)
+            let mut route_address = target_address.clone();
+            if sniffing.enabled && !initial_data.is_empty() {
+                let sniffed = crate::protocol::sniffer::sniff(&initial_data);
+                debug!(
+                    "👃 Sniff: {:?} ALPN: {:?} Version: {:?} ECH: {}",
+                    sniffed.protocol, sniffed.alpn, sniffed.version, sniffed.ech
+                );
+                if let Some(host) = sniffed.host.filter(|_| sniffing.overrides(sniffed.protocol.as_str())) {
+                    // 保留原始端口，缓冲的首包原样转发
+                    let sniffed_address = Address::Domain(host, target_address.port());
+                    info!(
+                        "👃 Sniffed {}: {} (原目标: {}, routeOnly: {})",
+                        sniffed.protocol.as_str(), sniffed_address, target_address, sniffing.route_only
+                    );
+                    if !sniffing.route_only {
+                        target_address = sniffed_address.clone();
                     }
+                    route_address = sniffed_address;
                 }
             }
             // --- SNIFFING END ---
 
+            let filter = outbound.filter();
+            if route_address != target_address {
+                debug!("🧭 路由目标: {}", route_address);
+                // 嗅探出的域名参与路由: 域名规则可以阻止连接，未命中时仍按连接目标判断
+                if let Some(Verdict::Block(reason)) = filter.map(|filter| filter.check(&route_address)) {
+                    warn!("🚫 阻止连接: {} (连接目标 {})", reason, target_address);
+                    return Err(anyhow::anyhow!("目标被阻止: {}", reason));
+                }
+            }
             info!("🔗 连接目标: {}", target_address);
             
-            // 连接远程服务器
+            // 经由出站连接远程服务器
             let mut remote_stream = match tokio::time::timeout(
                 std::time::Duration::from_secs(10),
-                tokio::net::TcpStream::connect(&target_address)
+                outbound.connect(&target_address)
             ).await {
                 Ok(Ok(s)) => s,
                 Ok(Err(e)) => {
                     error!("无法连接到目标 {}: {}", target_address, e);
-                    return Err(e.into());
+                    return Err(e);
                 }
                 Err(_) => {
                     error!("连接目标超时: {}", target_address);
                     return Err(anyhow::anyhow!("Connection timeout"));
                 }
             };
-            
-            // TCP No Delay
-            if tcp_no_delay {
-                if let Err(e) = remote_stream.set_nodelay(true) {
-                    error!("Remote: 设置 TCP_NODELAY 失败: {}", e);
-                }
-            }
 
             // 发送初始数据
             if !initial_data.is_empty() {
                 remote_stream.write_all(&initial_data).await?;
+                remote_stream.flush().await?;
             }
 
             // 开始双向转发
-            connection_manager
-                .handle_connection(stream, remote_stream)
-                .await?;
+            let rate_limiter = connection_manager.rate_limiter_for(&request.uuid);
+            let idle_timeout = filter
+                .and_then(|filter| filter.idle_timeout(&route_address).or_else(|| filter.idle_timeout(&target_address)));
+            return connection_manager
+                .handle_connection(stream, remote_stream, rate_limiter, idle_timeout, |stats| {
+                    debug!(
+                        "🔚 {} 转发结束: ↑{} ↓{} 字节, {:?}, {}",
+                        target_address, stats.up_bytes, stats.down_bytes, stats.duration, stats.reason
+                    );
+                })
+                .await;
         }
         Command::Udp => {
-            info!("📡 UDP 请求: {}", request.address.to_string());
+            info!("📡 UDP 请求: {}", request.address);
             
-            // Optimize: Create UDP socket with large buffers for QUIC/Video
-            let std_socket = match std::net::UdpSocket::bind("0.0.0.0:0") {
-                Ok(s) => s,
+            // UDP 经出站转发，不支持 UDP 的出站 (socks / vless) 直接拒绝
+            let udp_socket = match outbound.bind_udp().await {
+                Ok(socket) => socket,
                 Err(e) => {
-                    error!("无法绑定 UDP socket: {}", e);
-                    return Err(e.into());
+                    warn!("🚫 拒绝 UDP {}: {}", request.address, e);
+                    return Err(e);
                 }
             };
-            
-            // Set socket buffers to 4MB to handle video bursts
-            let socket = socket2::Socket::from(std_socket);
-            let _ = socket.set_recv_buffer_size(4 * 1024 * 1024);
-            let _ = socket.set_send_buffer_size(4 * 1024 * 1024);
-            
-            // Convert back to Tokio socket
-            let udp_socket = tokio::net::UdpSocket::from_std(socket.into())?;
-            
-            // 解析目标地址
             let target_addr = request.address.to_string();
-            let initial_target: std::net::SocketAddr = match tokio::net::lookup_host(&target_addr).await {
-                Ok(mut addrs) => {
-                    if let Some(addr) = addrs.next() {
-                        info!("🔗 UDP 初始目标: {}", addr);
-                        addr
-                    } else {
-                        error!("无法解析 UDP 目标地址: {}", target_addr);
-                        return Err(anyhow::anyhow!("DNS resolution failed"));
-                    }
-                }
-                Err(e) => {
-                    error!("DNS 解析失败: {}", e);
-                    return Err(e.into());
-                }
-            };
+            let initial_target = udp_socket.resolve(&request.address).await?;
+            info!("🔗 UDP 初始目标: {}", initial_target);
             
-            // UDP 会话超时 (5分钟)
-            let session_timeout = Duration::from_secs(300);
+            // 与 TCP 转发共用全局闲置超时、最长存活时间和用户限速
+            let session_timeout = connection_manager.idle_timeout().unwrap_or(Duration::MAX);
+            let max_lifetime = connection_manager.max_lifetime();
+            let rate_limiter = connection_manager.rate_limiter_for(&request.uuid);
             
             let udp_socket = std::sync::Arc::new(udp_socket);
             let udp_socket_recv = udp_socket.clone();
             
             // 发送初始 UDP 数据
-            if !buf.is_empty() {
-               if buf.len() >= 2 {
-                    let len = ((buf[0] as usize) << 8) | (buf[1] as usize);
-                    if buf.len() >= 2 + len {
-                        let payload = &buf[2..2+len];
-                        if let Err(e) = udp_socket.send_to(payload, initial_target).await {
-                            error!("UDP 发送失败: {}", e);
-                        } else {
-                            debug!("UDP 发送了 {} 字节 (初始数据)", len);
-                        }
+            let mut first_packet_checked = false;
+            if buf.len() >= 2 {
+                let len = ((buf[0] as usize) << 8) | (buf[1] as usize);
+                if buf.len() >= 2 + len {
+                    let payload = &buf[2..2+len];
+                    first_packet_checked = true;
+                    if block_bittorrent && is_bittorrent(payload) {
+                        log_bittorrent_blocked(&codec.label_for(&request.uuid), &target_addr);
+                        return Ok(CloseReason::Closed);
+                    }
+                    if let Some(limiter) = &rate_limiter {
+                        limiter.upload.acquire(len).await;
+                    }
+                    if let Err(e) = udp_socket.send_to(payload, initial_target).await {
+                        error!("UDP 发送失败: {}", e);
+                    } else {
+                        debug!("UDP 发送了 {} 字节 (初始数据)", len);
                     }
                 }
             }
@@ -220,7 +287,17 @@ pub async fn serve_vless(
                             }
                             match stream_read.read_exact(&mut read_buf[..len]).await {
                                 Ok(_) => {
-                                    if let Err(_) = udp_socket.send_to(&read_buf[..len], initial_target_clone).await {
+                                    if !first_packet_checked {
+                                        first_packet_checked = true;
+                                        if block_bittorrent && is_bittorrent(&read_buf[..len]) {
+                                            log_bittorrent_blocked(&codec.label_for(&request.uuid), &target_addr);
+                                            break;
+                                        }
+                                    }
+                                    if let Some(limiter) = &rate_limiter {
+                                        limiter.upload.acquire(len).await;
+                                    }
+                                    if udp_socket.send_to(&read_buf[..len], initial_target_clone).await.is_err() {
                                         break;
                                     }
                                 }
@@ -242,6 +319,9 @@ pub async fn serve_vless(
                         Ok(Ok((n, _))) => {
                             if n == 0 { break; }
                             last_activity = tokio::time::Instant::now();
+                            if let Some(limiter) = &rate_limiter {
+                                limiter.download.acquire(n).await;
+                            }
                             let len_bytes = [(n >> 8) as u8, (n & 0xff) as u8];
                             let mut frame = Vec::with_capacity(2 + n);
                             frame.extend_from_slice(&len_bytes);
@@ -254,17 +334,165 @@ pub async fn serve_vless(
                 }
             };
             
+            let lifetime = async {
+                match max_lifetime {
+                    Some(max_lifetime) => tokio::time::sleep(max_lifetime).await,
+                    None => std::future::pending().await,
+                }
+            };
             tokio::select! {
                 _ = send_task => {}
                 _ = recv_task => {}
+                _ = lifetime => {
+                    info!("📡 UDP 会话达到最长存活时间");
+                    return Ok(CloseReason::MaxLifetime);
+                }
             }
             info!("📡 UDP 会话结束");
         }
         Command::Mux => {
-            use tracing::warn;
-            warn!("Mux 暂不支持");
+            info!("🔀 Mux.Cool 隧道建立");
+            let policy = crate::network::mux::MuxPolicy {
+                rate_limiter: connection_manager.rate_limiter_for(&request.uuid),
+                idle_timeout: connection_manager.idle_timeout(),
+                max_lifetime: connection_manager.max_lifetime(),
+                block_bittorrent,
+                user: codec.label_for(&request.uuid),
+            };
+            crate::network::mux::serve_mux(stream, buf, outbound, policy).await?;
         }
     }
 
-    Ok(())
+    Ok(CloseReason::Closed)
+}
+
+/// BitTorrent 屏蔽日志的最小间隔
+const BT_LOG_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// 记录被屏蔽的 BitTorrent 连接 (限频，期间被抑制的次数会在下一条日志中汇总)
+pub(crate) fn log_bittorrent_blocked(client: &str, target: &str) {
+    use std::sync::Mutex;
+    use once_cell::sync::Lazy;
+
+    static STATE: Lazy<Mutex<(Option<std::time::Instant>, u64)>> = Lazy::new(|| Mutex::new((None, 0)));
+
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let (last, suppressed) = &mut *state;
+    if last.is_some_and(|t| t.elapsed() < BT_LOG_INTERVAL) {
+        *suppressed += 1;
+        return;
+    }
+    info!("🚫 已屏蔽 BitTorrent 流量: 用户 {} -> {} (此前抑制 {} 条)", client, target, suppressed);
+    *last = Some(std::time::Instant::now());
+    *suppressed = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::DirectOutbound;
+    use crate::protocol::vless::{VlessRequest, FLOW_VISION};
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_rejected_flow_is_not_reported_as_authenticated() {
+        let uuid = uuid::Uuid::new_v4();
+        let (mut client, server) = tokio::io::duplex(4096);
+        let (auth, user) = AuthReport::channel();
+        let sniffing: SniffingConfig = serde_json::from_str("{}").unwrap();
+        let serve = serve_vless(
+            Box::new(server),
+            auth,
+            None,
+            VlessCodec::new(vec![uuid]),
+            ConnectionManager::new(),
+            sniffing,
+            std::sync::Arc::new(DirectOutbound::new(Default::default())),
+            false,
+        );
+
+        let request = VlessRequest {
+            version: 0,
+            uuid,
+            command: Command::Tcp,
+            address: Address::Domain("example.com".into(), 443),
+            addon_length: 0,
+            flow: FLOW_VISION.to_string(),
+            mux_session_id: None,
+        };
+        client.write_all(&request.encode().unwrap()).await.unwrap();
+        assert!(serve.await.is_err());
+        assert!(user.borrow().is_none());
+    }
+
+    fn udp_request(uuid: uuid::Uuid, address: Address, payload: &[u8]) -> bytes::BytesMut {
+        let request = VlessRequest {
+            version: 0,
+            uuid,
+            command: Command::Udp,
+            address,
+            addon_length: 0,
+            flow: String::new(),
+            mux_session_id: None,
+        };
+        let mut data = request.encode().unwrap();
+        data.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[tokio::test]
+    async fn test_udp_relayed_through_direct_outbound() {
+        use tokio::io::AsyncReadExt;
+
+        let echo = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = echo.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            let (n, from) = echo.recv_from(&mut buf).await.unwrap();
+            echo.send_to(&buf[..n], from).await.unwrap();
+        });
+
+        let uuid = uuid::Uuid::new_v4();
+        let (mut client, server) = tokio::io::duplex(4096);
+        let (auth, _user) = AuthReport::channel();
+        tokio::spawn(serve_vless(
+            Box::new(server),
+            auth,
+            None,
+            VlessCodec::new(vec![uuid]),
+            ConnectionManager::new(),
+            serde_json::from_str("{}").unwrap(),
+            std::sync::Arc::new(DirectOutbound::new(Default::default())),
+            false,
+        ));
+
+        let address = Address::Domain("localhost".into(), target.port());
+        client.write_all(&udp_request(uuid, address, b"ping")).await.unwrap();
+        // VLESS 响应头 + 长度前缀的回显数据
+        let mut reply = [0u8; 8];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"\x00\x00\x00\x04ping");
+    }
+
+    #[tokio::test]
+    async fn test_udp_rejected_by_outbound_without_udp() {
+        let uuid = uuid::Uuid::new_v4();
+        let (mut client, server) = tokio::io::duplex(4096);
+        let (auth, _user) = AuthReport::channel();
+        let serve = serve_vless(
+            Box::new(server),
+            auth,
+            None,
+            VlessCodec::new(vec![uuid]),
+            ConnectionManager::new(),
+            serde_json::from_str("{}").unwrap(),
+            std::sync::Arc::new(crate::network::Socks5Outbound::new("127.0.0.1:9", Default::default())),
+            false,
+        );
+
+        client.write_all(&udp_request(uuid, Address::Ipv4(std::net::Ipv4Addr::LOCALHOST, 53), b"ping")).await.unwrap();
+        let err = serve.await.unwrap_err();
+        assert!(err.to_string().contains("不支持 UDP"), "{}", err);
+    }
 }
@@ -5,6 +5,8 @@ pub mod protocol;
 pub mod server;
 pub mod transport;
 pub mod utils;
+#[cfg(feature = "xdp")]
+pub mod xdp;
 
 pub use config::Config;
-pub use server::Server;
+pub use server::{ReloadHandle, Server};
@@ -1,18 +1,9 @@
 use anyhow::Result;
-use clap::Parser;
-use tracing::{info, Level};
-use tracing_subscriber;
+use clap::{Parser, Subcommand};
+use tracing::{error, info, warn, Level};
 
-mod config;
-mod network;
-mod protocol;
-mod server;
-mod transport;
-mod utils;
-mod handler;
-
-use crate::config::Config;
-use crate::server::Server;
+use xray_lite::utils::{KeyFormat, X25519KeyPair};
+use xray_lite::{Config, ReloadHandle, Server};
 
 #[cfg(not(target_os = "windows"))]
 #[global_allocator]
@@ -28,8 +19,83 @@ struct Args {
     /// 日志级别
     #[arg(short, long, default_value = "info")]
     log_level: String,
+
+    /// 停机宽限期 (秒)，等待活跃连接结束
+    #[arg(long, default_value_t = 30)]
+    grace_period: u64,
+
+    /// 健康检查端点地址 (如 127.0.0.1:8081)，监听器就绪后返回 200，启动与停机期间返回 503
+    #[arg(long)]
+    health_addr: Option<std::net::SocketAddr>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 生成 Reality X25519 密钥对 (privateKey 用于服务端，publicKey 用于客户端)
+    Keygen {
+        /// 输出格式
+        #[arg(long, value_enum, default_value_t = KeyFormat::Base64Url)]
+        format: KeyFormat,
+    },
+}
+
+/// 等待 SIGTERM / SIGINT
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+            }
+            Err(e) => {
+                tracing::warn!("无法注册 SIGTERM 处理: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
 
+/// 收到 SIGHUP 时重新加载配置，失败时保留旧配置
+#[cfg(unix)]
+async fn reload_on_sighup(path: String, handle: ReloadHandle) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("无法注册 SIGHUP 处理: {}", e);
+            return;
+        }
+    };
+
+    while sighup.recv().await.is_some() {
+        info!("🔄 收到 SIGHUP，重新加载配置: {}", path);
+        match Config::load(&path).and_then(|config| handle.apply(config)) {
+            Ok(pending) => {
+                for change in &pending {
+                    warn!("⚠️ {} 需要重启才能生效，继续使用旧值", change);
+                }
+                info!("✅ 配置已重新加载");
+            }
+            Err(e) => error!("❌ 配置重新加载失败，继续使用旧配置: {}", e),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn reload_on_sighup(_path: String, _handle: ReloadHandle) {}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // 提高文件句柄限制 (Linux)
@@ -50,6 +116,13 @@ async fn main() -> Result<()> {
 
     let args = Args::parse();
 
+    if let Some(Command::Keygen { format }) = args.command {
+        let pair = X25519KeyPair::generate();
+        println!("Private key: {}", pair.private_key_string(format));
+        println!("Public key:  {}", pair.public_key_string(format));
+        return Ok(());
+    }
+
     // 初始化日志
     let log_level_str = std::env::var("RUST_LOG")
         .unwrap_or_else(|_| args.log_level.clone());
@@ -80,8 +153,21 @@ async fn main() -> Result<()> {
     let server = Server::new(config)?;
     info!("🌐 Server initialized");
 
-    // 运行服务器
-    server.run().await?;
+    tokio::spawn(reload_on_sighup(args.config.clone(), server.reload_handle()));
+    if let Some(addr) = args.health_addr {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let health = server.health();
+        tokio::spawn(async move {
+            if let Err(e) = xray_lite::network::health::serve(listener, health).await {
+                error!("❌ 健康检查端点退出: {}", e);
+            }
+        });
+    }
+
+    // 运行服务器，收到信号后优雅停机
+    let grace_period = std::time::Duration::from_secs(args.grace_period);
+    server.run_until(shutdown_signal(), grace_period).await?;
+    info!("👋 Server stopped");
 
     Ok(())
 }
@@ -0,0 +1,234 @@
+//! 访问日志
+//!
+//! 每个 VLESS 连接结束时输出一行摘要: 时间、来源、用户、目标、上下行字节数、时长与关闭原因。
+//! 记录直接写入文件或标准输出，不经过 tracing，开启后不受日志级别影响。
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use tracing::warn;
+
+use super::connection::TrafficStats;
+use crate::config::AccessLogFormat;
+
+/// 访问日志写入器
+pub struct AccessLog {
+    format: AccessLogFormat,
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    /// 打开访问日志，`target` 为 `stdout` 或文件路径 (追加写入)
+    pub fn open(target: &str, format: AccessLogFormat) -> Result<Self> {
+        let sink: Box<dyn Write + Send> = if target.eq_ignore_ascii_case("stdout") {
+            Box::new(std::io::stdout())
+        } else {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(target)
+                .map_err(|e| anyhow!("无法打开访问日志 {}: {}", target, e))?;
+            Box::new(file)
+        };
+        Ok(Self::with_sink(sink, format))
+    }
+
+    fn with_sink(sink: Box<dyn Write + Send>, format: AccessLogFormat) -> Self {
+        Self { format, sink: Mutex::new(sink) }
+    }
+
+    /// 写入一条记录，整行一次写出，并发连接的记录不会交错
+    pub fn write(&self, record: &AccessRecord) {
+        let mut line = match self.format {
+            AccessLogFormat::Text => record.to_text(),
+            AccessLogFormat::Json => record.to_json(),
+        };
+        line.push('\n');
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = sink.write_all(line.as_bytes()).and_then(|_| sink.flush()) {
+            warn!("写入访问日志失败: {}", e);
+        }
+    }
+}
+
+/// 连接建立时已知的信息
+#[derive(Debug, Clone)]
+pub struct AccessEntry {
+    /// 客户端地址 (启用 Proxy Protocol 时为真实客户端地址)
+    pub source: Option<SocketAddr>,
+    /// 用户标签 (email，未配置时为 UUID)
+    pub user: String,
+    /// 目标，如 `tcp:example.com:443`
+    pub destination: String,
+}
+
+/// 一条访问日志记录
+#[derive(Debug, Clone)]
+pub struct AccessRecord {
+    pub time: SystemTime,
+    pub entry: AccessEntry,
+    pub up: u64,
+    pub down: u64,
+    pub duration: Duration,
+    pub reason: String,
+}
+
+impl AccessRecord {
+    fn source(&self) -> String {
+        self.entry.source.map_or_else(|| "-".to_string(), |addr| addr.to_string())
+    }
+
+    fn to_text(&self) -> String {
+        format!(
+            "{} {} {} {} up={} down={} duration={:.3}s reason={:?}",
+            format_time(self.time),
+            self.source(),
+            self.entry.user,
+            self.entry.destination,
+            self.up,
+            self.down,
+            self.duration.as_secs_f64(),
+            self.reason,
+        )
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::json!({
+            "time": format_time(self.time),
+            "source": self.source(),
+            "user": self.entry.user,
+            "destination": self.entry.destination,
+            "up": self.up,
+            "down": self.down,
+            "duration": (self.duration.as_secs_f64() * 1000.0).round() / 1000.0,
+            "reason": self.reason,
+        })
+        .to_string()
+    }
+}
+
+/// 连接结束 (被 drop) 时写出访问日志
+pub(super) struct AccessRecorder {
+    log: Arc<AccessLog>,
+    entry: AccessEntry,
+    stats: Arc<TrafficStats>,
+    started: Instant,
+    pub(super) reason: Option<String>,
+}
+
+impl AccessRecorder {
+    pub(super) fn new(log: Arc<AccessLog>, entry: AccessEntry, stats: Arc<TrafficStats>) -> Self {
+        Self { log, entry, stats, started: Instant::now(), reason: None }
+    }
+}
+
+impl Drop for AccessRecorder {
+    fn drop(&mut self) {
+        self.log.write(&AccessRecord {
+            time: SystemTime::now(),
+            entry: self.entry.clone(),
+            up: self.stats.up(),
+            down: self.stats.down(),
+            duration: self.started.elapsed(),
+            // 未记录结果就被 drop: 处理任务被取消 (如所在的 XHTTP 会话被关闭)
+            reason: self.reason.take().unwrap_or_else(|| "aborted".to_string()),
+        });
+    }
+}
+
+/// RFC 3339 UTC 时间，精确到毫秒
+fn format_time(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+
+    // 由 1970-01-01 起的天数换算公历日期 (以 3 月为年首，闰日落在年末)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 共享的内存缓冲区，用于检查写出的内容
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn record() -> AccessRecord {
+        AccessRecord {
+            time: UNIX_EPOCH + Duration::from_millis(1_792_145_045_250),
+            entry: AccessEntry {
+                source: Some("203.0.113.7:51234".parse().unwrap()),
+                user: "alice@example.com".to_string(),
+                destination: "tcp:example.com:443".to_string(),
+            },
+            up: 1200,
+            down: 34000,
+            duration: Duration::from_millis(2500),
+            reason: "closed".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_time(UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29T00:00:00.000Z");
+        assert_eq!(format_time(record().time), "2026-10-16T10:04:05.250Z");
+    }
+
+    #[test]
+    fn test_record_formats() {
+        let buffer = Buffer::default();
+        let text = AccessLog::with_sink(Box::new(buffer.clone()), AccessLogFormat::Text);
+        let json = AccessLog::with_sink(Box::new(buffer.clone()), AccessLogFormat::Json);
+        text.write(&record());
+        json.write(&record());
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "2026-10-16T10:04:05.250Z 203.0.113.7:51234 alice@example.com tcp:example.com:443 \
+             up=1200 down=34000 duration=2.500s reason=\"closed\""
+        );
+        let value: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(value["source"], "203.0.113.7:51234");
+        assert_eq!(value["destination"], "tcp:example.com:443");
+        assert_eq!((value["up"].as_u64(), value["down"].as_u64()), (Some(1200), Some(34000)));
+        assert_eq!(value["duration"], 2.5);
+        assert_eq!(value["reason"], "closed");
+    }
+}
@@ -1,28 +1,61 @@
-use std::sync::Mutex;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use once_cell::sync::Lazy;
-use anyhow::Result;
-use tokio::net::TcpStream;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt};
-use tracing::{debug, error};
+use anyhow::{anyhow, Result};
+use bytes::BytesMut;
+use dashmap::DashMap;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt, ReadBuf};
+use tracing::{debug, info, warn};
+
+use super::access_log::{AccessEntry, AccessLog, AccessRecorder};
+use super::rate_limit::{RateLimitRegistry, RateLimiter};
 
 const BUFFER_SIZE: usize = 16 * 1024;
-static BUFFER_POOL: Lazy<Mutex<Vec<Vec<u8>>>> = Lazy::new(|| Mutex::new(Vec::with_capacity(256)));
+/// 默认闲置超时: 两个方向都没有数据的最长时间
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// 达到最长存活时间或闲置超时后关闭写端的期限
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// 池化缓冲区容量
+pub(crate) const POOLED_BUFFER_CAPACITY: usize = 64 * 1024;
+/// 剩余容量低于该值时换一块缓冲区
+const MIN_READ_CAPACITY: usize = 2048;
+/// 池中最多保留的缓冲区数
+const MAX_POOLED: usize = 512;
+/// 取用时最多检查的缓冲区数 (从最早归还的开始)
+const MAX_PROBES: usize = 8;
+
+static BUFFER_POOL: Lazy<Mutex<Vec<BytesMut>>> = Lazy::new(|| Mutex::new(Vec::with_capacity(MAX_POOLED)));
 
-struct PooledBuffer(Option<Vec<u8>>);
+/// 从缓冲池取出的读缓冲区，drop 时归还
+///
+/// 读到的数据可以 `split_to().freeze()` 零拷贝交出，分片在发送完成前仍引用原缓冲区。
+/// 归还的缓冲区在所有分片释放后才能通过 `try_reclaim` 取回全部容量，因此取用时跳过仍被引用的缓冲区。
+pub(crate) struct PooledBuffer(Option<BytesMut>);
 
 impl PooledBuffer {
-    fn get() -> Self {
-        if let Ok(mut pool) = BUFFER_POOL.lock() {
-            if let Some(buf) = pool.pop() {
-                return PooledBuffer(Some(buf));
+    pub(crate) fn get() -> Self {
+        Self(Some(take_buffer()))
+    }
+
+    /// 剩余容量不足时把当前缓冲区归还并换一块，不在原地扩容
+    pub(crate) fn ensure_capacity(&mut self) {
+        if self.capacity() < MIN_READ_CAPACITY {
+            let mut fresh = take_buffer();
+            fresh.extend_from_slice(self);
+            if let Some(old) = self.0.replace(fresh) {
+                give_buffer(old);
             }
         }
-        PooledBuffer(Some(vec![0u8; BUFFER_SIZE]))
     }
 }
 
 impl std::ops::Deref for PooledBuffer {
-    type Target = [u8];
+    type Target = BytesMut;
     fn deref(&self) -> &Self::Target {
         self.0.as_ref().unwrap()
     }
@@ -37,19 +70,107 @@ impl std::ops::DerefMut for PooledBuffer {
 impl Drop for PooledBuffer {
     fn drop(&mut self) {
         if let Some(buf) = self.0.take() {
-            if let Ok(mut pool) = BUFFER_POOL.lock() {
-                if pool.len() < 512 {
-                    pool.push(buf);
-                }
+            give_buffer(buf);
+        }
+    }
+}
+
+fn take_buffer() -> BytesMut {
+    if let Ok(mut pool) = BUFFER_POOL.lock() {
+        let probes = pool.len().min(MAX_PROBES);
+        for i in 0..probes {
+            if pool[i].try_reclaim(POOLED_BUFFER_CAPACITY) {
+                return pool.swap_remove(i);
             }
         }
     }
+    BytesMut::with_capacity(POOLED_BUFFER_CAPACITY)
+}
+
+fn give_buffer(mut buf: BytesMut) {
+    buf.clear();
+    if let Ok(mut pool) = BUFFER_POOL.lock() {
+        if pool.len() < MAX_POOLED {
+            pool.push(buf);
+        }
+    }
+}
+
+/// 连接的上下行字节数
+#[derive(Debug, Default)]
+pub struct TrafficStats {
+    up: AtomicU64,
+    down: AtomicU64,
+}
+
+impl TrafficStats {
+    pub fn add_up(&self, n: u64) {
+        self.up.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn add_down(&self, n: u64) {
+        self.down.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// 客户端发往目标的字节数
+    pub fn up(&self) -> u64 {
+        self.up.load(Ordering::Relaxed)
+    }
+
+    /// 目标发往客户端的字节数
+    pub fn down(&self) -> u64 {
+        self.down.load(Ordering::Relaxed)
+    }
+}
+
+/// 统计客户端流量的流包装: 读取计为上行，写入计为下行
+pub struct CountingStream<S> {
+    inner: S,
+    stats: Arc<TrafficStats>,
+}
+
+impl<S> CountingStream<S> {
+    pub fn new(inner: S, stats: Arc<TrafficStats>) -> Self {
+        Self { inner, stats }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            self.stats.add_up((buf.filled().len() - before) as u64);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CountingStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            self.stats.add_down(n as u64);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
 }
 
 /// 代理连接
 pub struct ProxyConnection<C, R> {
     client_stream: C,
     remote_stream: R,
+    rate_limiter: Option<RateLimiter>,
+    max_lifetime: Option<Duration>,
+    idle_timeout: Option<Duration>,
 }
 
 impl<C, R> ProxyConnection<C, R> 
@@ -62,77 +183,260 @@ where
         Self {
             client_stream,
             remote_stream,
+            rate_limiter: None,
+            max_lifetime: None,
+            idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
         }
     }
 
-    /// 双向数据转发
-    pub async fn relay(self) -> Result<()> {
-        debug!("开始双向数据转发 (High-Performance Relay with 300s idle timeout)");
+    /// 设置限速器
+    pub fn with_rate_limiter(mut self, rate_limiter: Option<RateLimiter>) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// 设置最长存活时间，从转发开始计时，到期后不论是否活跃都关闭连接
+    pub fn with_max_lifetime(mut self, max_lifetime: Option<Duration>) -> Self {
+        self.max_lifetime = max_lifetime;
+        self
+    }
+
+    /// 设置闲置超时，两个方向都没有数据达到该时间后关闭连接；`None` 为不限制
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// 双向数据转发，结束后返回两个方向的字节数与首个错误
+    pub async fn relay(self) -> RelayStats {
+        debug!("开始双向数据转发 (闲置超时 {:?})", self.idle_timeout);
+        let started = Instant::now();
+        // 最近一次读到数据的时间 (相对 started 的毫秒数)，两个方向共用
+        let last_activity = AtomicU64::new(0);
+        let touch = || last_activity.store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
 
         let (mut c_r, mut c_w) = tokio::io::split(self.client_stream);
         let (mut r_r, mut r_w) = tokio::io::split(self.remote_stream);
 
-        let idle_timeout = std::time::Duration::from_secs(300);
+        let idle_timeout = self.idle_timeout;
+        let upload_limit = self.rate_limiter.as_ref().map(|l| l.upload.clone());
+        let download_limit = self.rate_limiter.as_ref().map(|l| l.download.clone());
+        let (mut up_bytes, mut down_bytes) = (0u64, 0u64);
 
         let client_to_remote = async {
-            let mut buf = bytes::BytesMut::with_capacity(BUFFER_SIZE);
+            let mut buf = BytesMut::with_capacity(BUFFER_SIZE);
             loop {
-                match tokio::time::timeout(idle_timeout, c_r.read_buf(&mut buf)).await {
-                    Ok(Ok(0)) => {
-                        r_w.shutdown().await?;
+                match c_r.read_buf(&mut buf).await {
+                    Ok(0) => {
+                        // 客户端正常结束上行: 半关闭目标，对方可能已先关闭，失败不算错误
+                        if let Err(e) = r_w.shutdown().await {
+                            debug!("关闭目标写端失败: {}", e);
+                        }
                         break;
                     }
-                    Ok(Ok(_)) => {
-                        r_w.write_all(&buf).await?;
+                    Ok(n) => {
+                        touch();
+                        if let Some(bucket) = &upload_limit {
+                            bucket.acquire(n).await;
+                        }
+                        r_w.write_all(&buf).await.map_err(|e| (RelayDirection::Up, e))?;
+                        r_w.flush().await.map_err(|e| (RelayDirection::Up, e))?;
+                        up_bytes += n as u64;
                         buf.clear();
                     }
-                    Ok(Err(e)) => return Err(e),
-                    Err(_) => {
-                        debug!("连接闲置超时 (Client -> Remote)");
-                        break;
-                    }
+                    Err(e) => return Err((RelayDirection::Up, e)),
                 }
             }
-            Ok::<u64, std::io::Error>(0)
+            Ok(())
         };
 
         let remote_to_client = async {
-            let mut buf = bytes::BytesMut::with_capacity(BUFFER_SIZE);
+            let mut buf = BytesMut::with_capacity(BUFFER_SIZE);
             loop {
-                match tokio::time::timeout(idle_timeout, r_r.read_buf(&mut buf)).await {
-                    Ok(Ok(0)) => {
-                        c_w.shutdown().await?;
+                match r_r.read_buf(&mut buf).await {
+                    Ok(0) => {
+                        if let Err(e) = c_w.shutdown().await {
+                            debug!("关闭客户端写端失败: {}", e);
+                        }
                         break;
                     }
-                    Ok(Ok(_)) => {
-                        c_w.write_all(&buf).await?;
+                    Ok(n) => {
+                        touch();
+                        if let Some(bucket) = &download_limit {
+                            bucket.acquire(n).await;
+                        }
+                        c_w.write_all(&buf).await.map_err(|e| (RelayDirection::Down, e))?;
+                        c_w.flush().await.map_err(|e| (RelayDirection::Down, e))?;
+                        down_bytes += n as u64;
                         buf.clear();
                     }
-                    Ok(Err(e)) => return Err(e),
-                    Err(_) => {
-                        debug!("连接闲置超时 (Remote -> Client)");
-                        break;
-                    }
+                    Err(e) => return Err((RelayDirection::Down, e)),
                 }
             }
-            Ok::<u64, std::io::Error>(0)
+            Ok(())
         };
 
 
-        // 使用 try_join! 并发执行两个拷贝任务
-        // 任何一方出错或完成，都会结束
-        match tokio::try_join!(client_to_remote, remote_to_client) {
-            Ok(_) => {
-                debug!("连接正常关闭");
-                Ok(())
+        // 闲置检查: 到期时若期间有过数据则顺延，否则结束
+        let idle_watch = async {
+            let Some(idle_timeout) = idle_timeout else {
+                return std::future::pending().await;
+            };
+            loop {
+                let deadline = started + Duration::from_millis(last_activity.load(Ordering::Relaxed)) + idle_timeout;
+                if Instant::now() >= deadline {
+                    break;
+                }
+                tokio::time::sleep_until(deadline).await;
+            }
+        };
+
+        // 两个方向各自结束: 一方读到 EOF 只半关闭对端写端，另一方继续转发到自己的 EOF；
+        // 任一方向出错则整个连接结束，闲置超时返回 `true`
+        let transfer = async {
+            tokio::pin!(client_to_remote, remote_to_client, idle_watch);
+            let (mut up_open, mut down_open) = (true, true);
+            while up_open || down_open {
+                tokio::select! {
+                    result = &mut client_to_remote, if up_open => {
+                        result?;
+                        up_open = false;
+                        debug!("上行已结束，继续转发下行");
+                    }
+                    result = &mut remote_to_client, if down_open => {
+                        result?;
+                        down_open = false;
+                        debug!("下行已结束，继续转发上行");
+                    }
+                    _ = &mut idle_watch => return Ok(true),
+                }
+            }
+            Ok::<bool, (RelayDirection, std::io::Error)>(false)
+        };
+        let outcome = match self.max_lifetime {
+            Some(lifetime) => tokio::time::timeout(lifetime, transfer).await,
+            None => Ok(transfer.await),
+        };
+        let mut stats = RelayStats {
+            up_bytes,
+            down_bytes,
+            first_error_direction: None,
+            duration: Duration::ZERO,
+            reason: CloseReason::Closed,
+            error: None,
+        };
+        match outcome {
+            Ok(Ok(false)) => debug!("连接正常关闭"),
+            Ok(Ok(true)) => {
+                debug!("连接闲置超过 {:?}，关闭", idle_timeout.unwrap_or_default());
+                let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, c_w.shutdown()).await;
+                let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, r_w.shutdown()).await;
+                stats.reason = CloseReason::IdleTimeout;
             }
-            Err(e) => {
-                // 如果是正常的连接重置或关闭，不记录为错误
-                debug!("连接断开: {}", e);
-                Err(e.into())
+            Ok(Err((direction, e))) => {
+                debug!("连接断开 ({}): {}", direction, e);
+                stats.reason = CloseReason::from_error(direction, &e);
+                stats.first_error_direction = Some(direction);
+                stats.error = Some(e);
+            }
+            Err(_) => {
+                // 与闲置超时无关: 到期后向两端发出 FIN
+                info!("连接达到最长存活时间 {:?}，关闭", self.max_lifetime.unwrap_or_default());
+                let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, c_w.shutdown()).await;
+                let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, r_w.shutdown()).await;
+                stats.reason = CloseReason::MaxLifetime;
             }
         }
+        stats.duration = started.elapsed();
+        stats
+    }
+}
 
+/// 转发方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelayDirection {
+    /// 客户端 → 目标
+    Up,
+    /// 目标 → 客户端
+    Down,
+}
+
+impl std::fmt::Display for RelayDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RelayDirection::Up => "客户端 -> 目标",
+            RelayDirection::Down => "目标 -> 客户端",
+        })
+    }
+}
+
+/// 连接结束的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// 两个方向都正常结束
+    Closed,
+    /// 两个方向都没有数据达到闲置超时
+    IdleTimeout,
+    /// 达到最长存活时间
+    MaxLifetime,
+    /// 对端重置或提前断开，属于正常现象
+    Reset(RelayDirection),
+    /// 其他 IO 错误
+    Error(RelayDirection),
+}
+
+impl CloseReason {
+    /// 按 IO 错误类型区分正常断开与意外错误
+    pub fn from_error(direction: RelayDirection, e: &std::io::Error) -> Self {
+        use std::io::ErrorKind::*;
+        match e.kind() {
+            ConnectionReset | ConnectionAborted | BrokenPipe | UnexpectedEof | NotConnected | TimedOut => {
+                CloseReason::Reset(direction)
+            }
+            _ => CloseReason::Error(direction),
+        }
+    }
+
+    /// 是否为需要关注的意外错误
+    pub fn is_error(&self) -> bool {
+        matches!(self, CloseReason::Error(_))
+    }
+}
+
+impl std::fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseReason::Closed => f.write_str("closed"),
+            CloseReason::IdleTimeout => f.write_str("idle timeout"),
+            CloseReason::MaxLifetime => f.write_str("max lifetime"),
+            CloseReason::Reset(direction) => write!(f, "reset ({})", direction),
+            CloseReason::Error(direction) => write!(f, "error ({})", direction),
+        }
+    }
+}
+
+/// 一次转发的结果: 出错时统计照常保留，错误附在 `error` 中
+#[derive(Debug)]
+pub struct RelayStats {
+    /// 已写入目标的字节数
+    pub up_bytes: u64,
+    /// 已写入客户端的字节数
+    pub down_bytes: u64,
+    /// 首个出错的方向，双方正常关闭时为 `None`
+    pub first_error_direction: Option<RelayDirection>,
+    pub duration: Duration,
+    pub reason: CloseReason,
+    pub error: Option<std::io::Error>,
+}
+
+impl RelayStats {
+    /// 转换为 `Result`，错误信息带上出错方向
+    pub fn into_result(self) -> Result<()> {
+        match (self.error, self.first_error_direction) {
+            (Some(e), Some(direction)) => Err(anyhow!("{}: {}", direction, e)),
+            (Some(e), None) => Err(e.into()),
+            (None, _) => Ok(()),
+        }
     }
 }
 
@@ -141,6 +445,25 @@ where
 pub struct ConnectionManager {
     /// 活跃连接数
     active_connections: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    /// 用户限速 (热重载时整体替换)
+    rate_limits: std::sync::Arc<std::sync::RwLock<std::sync::Arc<RateLimitRegistry>>>,
+    /// 访问日志 (未启用时为 `None`)
+    access_log: Option<Arc<AccessLog>>,
+    /// 连接最长存活时间 (秒)，0 为不限制；热重载时更新
+    max_lifetime: Arc<AtomicU64>,
+    /// 全局闲置超时 (秒)，0 为不限制；热重载时更新
+    idle_timeout: Arc<AtomicU64>,
+    /// 已接纳的客户端连接 (总数与每个来源 IP 的数量)
+    clients: Arc<ClientCounts>,
+}
+
+/// 客户端连接计数与上限 (0 为不限制)
+#[derive(Default)]
+struct ClientCounts {
+    total: AtomicUsize,
+    per_ip: DashMap<IpAddr, usize>,
+    max_total: AtomicUsize,
+    max_per_ip: AtomicUsize,
 }
 
 impl ConnectionManager {
@@ -148,41 +471,216 @@ impl ConnectionManager {
     pub fn new() -> Self {
         Self {
             active_connections: std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+            rate_limits: Default::default(),
+            access_log: None,
+            max_lifetime: Default::default(),
+            idle_timeout: Arc::new(AtomicU64::new(DEFAULT_IDLE_TIMEOUT.as_secs())),
+            clients: Arc::default(),
+        }
+    }
+
+    /// 每个连接结束时写入访问日志
+    pub fn with_access_log(mut self, access_log: AccessLog) -> Self {
+        self.access_log = Some(Arc::new(access_log));
+        self
+    }
+
+    /// 使用用户限速表创建连接管理器
+    pub fn with_rate_limits(rate_limits: RateLimitRegistry) -> Self {
+        let manager = Self::new();
+        manager.set_rate_limits(rate_limits);
+        manager
+    }
+
+    /// 替换用户限速表，已建立的连接继续使用原限速器
+    pub fn set_rate_limits(&self, rate_limits: RateLimitRegistry) {
+        let mut guard = self.rate_limits.write().unwrap_or_else(|e| e.into_inner());
+        *guard = std::sync::Arc::new(rate_limits);
+    }
+
+    /// 设置之后建立的连接的最长存活时间，`None` 为不限制
+    pub fn set_max_lifetime(&self, max_lifetime: Option<Duration>) {
+        self.max_lifetime.store(max_lifetime.map_or(0, |d| d.as_secs()), Ordering::Relaxed);
+    }
+
+    /// 设置之后建立的连接的闲置超时，`None` 为不限制
+    pub fn set_idle_timeout(&self, idle_timeout: Option<Duration>) {
+        self.idle_timeout.store(idle_timeout.map_or(0, |d| d.as_secs()), Ordering::Relaxed);
+    }
+
+    /// 设置客户端连接数上限 (总数与每个来源 IP)，0 为不限制；已接纳的连接不受影响
+    pub fn set_connection_limits(&self, max_connections: usize, max_connections_per_ip: usize) {
+        self.clients.max_total.store(max_connections, Ordering::Relaxed);
+        self.clients.max_per_ip.store(max_connections_per_ip, Ordering::Relaxed);
+    }
+
+    /// 接纳一个来自 `ip` 的客户端连接，返回的许可被 drop 时归还；总数或该 IP 的连接数已达上限时返回 `None`
+    pub fn try_admit(&self, ip: IpAddr) -> Option<ClientPermit> {
+        let clients = &self.clients;
+        let max_total = clients.max_total.load(Ordering::Relaxed);
+        clients
+            .total
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (max_total == 0 || n < max_total).then_some(n + 1))
+            .ok()?;
+        let max_per_ip = clients.max_per_ip.load(Ordering::Relaxed);
+        let mut count = clients.per_ip.entry(ip).or_insert(0);
+        if max_per_ip > 0 && *count >= max_per_ip {
+            drop(count);
+            clients.per_ip.remove_if(&ip, |_, n| *n == 0);
+            clients.total.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        *count += 1;
+        Some(ClientPermit { ip, clients: clients.clone() })
+    }
+
+    /// 已接纳的客户端连接数
+    pub fn client_count(&self) -> usize {
+        self.clients.total.load(Ordering::Relaxed)
+    }
+
+    /// 每个来源 IP 的客户端连接数，按连接数从多到少排列
+    pub fn clients_per_ip(&self) -> Vec<(IpAddr, usize)> {
+        let mut counts: Vec<_> = self.clients.per_ip.iter().map(|entry| (*entry.key(), *entry.value())).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts
+    }
+
+    /// 当前的连接最长存活时间，`None` 为不限制
+    pub fn max_lifetime(&self) -> Option<Duration> {
+        match self.max_lifetime.load(Ordering::Relaxed) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// 当前的全局闲置超时，`None` 为不限制
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        match self.idle_timeout.load(Ordering::Relaxed) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
         }
     }
 
+    /// 获取用户的限速器
+    pub fn rate_limiter_for(&self, uuid: &uuid::Uuid) -> Option<RateLimiter> {
+        let registry = self.rate_limits.read().unwrap_or_else(|e| e.into_inner()).clone();
+        registry.limiter_for(uuid)
+    }
+
     /// 获取活跃连接数
     pub fn active_count(&self) -> usize {
         self.active_connections
             .load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    /// 处理新连接
-    pub async fn handle_connection<T>(
+    /// 登记一个活跃连接，返回的守卫被 drop 时自动注销
+    pub fn track(&self) -> ConnectionGuard {
+        self.active_connections
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        ConnectionGuard {
+            active_connections: self.active_connections.clone(),
+            stats: Arc::default(),
+            access: None,
+        }
+    }
+
+    /// 登记一个已认证的连接，启用访问日志时守卫被 drop 时写出一行摘要
+    pub fn open(&self, entry: AccessEntry) -> ConnectionGuard {
+        let mut guard = self.track();
+        guard.access = self
+            .access_log
+            .clone()
+            .map(|log| AccessRecorder::new(log, entry, guard.stats.clone()));
+        guard
+    }
+
+    /// 转发已建立的连接 (活跃计数由调用方通过 [`ConnectionManager::open`] 登记)
+    ///
+    /// `idle_timeout` 为路由规则指定的闲置超时 (`Some(0)` 为不限制)，`None` 时使用全局设置。
+    /// 转发结束后先以统计调用 `on_complete`。重置等正常断开只记 debug 日志并返回关闭原因，
+    /// 意外的 IO 错误才作为错误返回。
+    pub async fn handle_connection<T, R, F>(
         &self,
         client_stream: T,
-        remote_stream: TcpStream,
-    ) -> Result<()> 
+        remote_stream: R,
+        rate_limiter: Option<RateLimiter>,
+        idle_timeout: Option<Duration>,
+        on_complete: F,
+    ) -> Result<CloseReason> 
     where
-        T: AsyncRead + AsyncWrite + Unpin + Send + 'static
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        R: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        F: FnOnce(&RelayStats),
     {
-        // 增加活跃连接计数
-        self.active_connections
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let idle_timeout = match idle_timeout {
+            Some(timeout) => (!timeout.is_zero()).then_some(timeout),
+            None => self.idle_timeout(),
+        };
+        let connection = ProxyConnection::new(client_stream, remote_stream)
+            .with_rate_limiter(rate_limiter)
+            .with_max_lifetime(self.max_lifetime())
+            .with_idle_timeout(idle_timeout);
+        let stats = connection.relay().await;
+        on_complete(&stats);
 
-        let active_connections = self.active_connections.clone();
+        let reason = stats.reason;
+        if reason.is_error() {
+            let e = stats.into_result().unwrap_err();
+            warn!("连接转发失败: {}", e);
+            return Err(e);
+        }
+        if let Some(e) = &stats.error {
+            debug!("连接结束 ({}): {}", reason, e);
+        }
+        Ok(reason)
+    }
+}
 
-        let connection = ProxyConnection::new(client_stream, remote_stream);
-        let result = connection.relay().await;
+/// 客户端连接的接纳许可，连接结束 (drop) 时归还；来源 IP 的最后一个连接结束时移除其计数
+pub struct ClientPermit {
+    ip: IpAddr,
+    clients: Arc<ClientCounts>,
+}
 
-        if let Err(ref e) = result {
-            error!("连接处理失败: {}", e);
+impl Drop for ClientPermit {
+    fn drop(&mut self) {
+        if let Some(mut count) = self.clients.per_ip.get_mut(&self.ip) {
+            *count -= 1;
         }
+        self.clients.per_ip.remove_if(&self.ip, |_, n| *n == 0);
+        self.clients.total.fetch_sub(1, Ordering::SeqCst);
+    }
+}
 
-        // 减少活跃连接计数
-        active_connections.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+/// 活跃连接守卫
+pub struct ConnectionGuard {
+    active_connections: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    stats: Arc<TrafficStats>,
+    access: Option<AccessRecorder>,
+}
 
-        result
+impl ConnectionGuard {
+    /// 本连接的流量计数
+    pub fn stats(&self) -> Arc<TrafficStats> {
+        self.stats.clone()
+    }
+
+    /// 记录连接的处理结果，作为访问日志中的关闭原因
+    pub fn finish(&mut self, result: &Result<CloseReason>) {
+        if let Some(access) = &mut self.access {
+            access.reason = Some(match result {
+                Ok(reason) => reason.to_string(),
+                Err(e) => e.to_string(),
+            });
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.active_connections
+            .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
     }
 }
 
@@ -196,9 +694,279 @@ impl Default for ConnectionManager {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_pooled_buffer_reused_after_chunks_released() {
+        let mut buf = PooledBuffer::get();
+        buf.resize(POOLED_BUFFER_CAPACITY, 7);
+        let chunk = buf.split_to(POOLED_BUFFER_CAPACITY).freeze();
+        let ptr = chunk.as_ptr();
+
+        // 分片仍被引用: 换到的是另一块缓冲区
+        buf.ensure_capacity();
+        assert!(buf.capacity() >= POOLED_BUFFER_CAPACITY);
+        let other = PooledBuffer::get();
+        assert_ne!(other.as_ptr(), ptr);
+        drop(other);
+
+        // 分片释放后原缓冲区可以取回
+        drop(chunk);
+        let reused = (0..MAX_PROBES).map(|_| PooledBuffer::get()).any(|b| b.as_ptr() == ptr);
+        assert!(reused);
+    }
+
     #[test]
     fn test_connection_manager_creation() {
         let manager = ConnectionManager::new();
         assert_eq!(manager.active_count(), 0);
     }
+
+    #[test]
+    fn test_connection_guard() {
+        let manager = ConnectionManager::new();
+        let a = manager.track();
+        let b = manager.clone().track();
+        assert_eq!(manager.active_count(), 2);
+        drop(a);
+        assert_eq!(manager.active_count(), 1);
+        drop(b);
+        assert_eq!(manager.active_count(), 0);
+    }
+
+    #[test]
+    fn test_connection_limits() {
+        let manager = ConnectionManager::new();
+        manager.set_connection_limits(3, 2);
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let first = manager.try_admit(a).unwrap();
+        let _second = manager.try_admit(a).unwrap();
+        assert!(manager.try_admit(a).is_none());
+        let other = manager.try_admit(b).unwrap();
+        // 总数已满，其他 IP 同样被拒绝
+        assert!(manager.try_admit("10.0.0.3".parse().unwrap()).is_none());
+        assert_eq!(manager.client_count(), 3);
+        assert_eq!(manager.clients_per_ip(), vec![(a, 2), (b, 1)]);
+
+        drop(first);
+        drop(other);
+        // 最后一个连接结束后该 IP 的计数被移除
+        assert_eq!(manager.clients_per_ip(), vec![(a, 1)]);
+        assert!(manager.try_admit(a).is_some());
+
+        manager.set_connection_limits(0, 0);
+        let many: Vec<_> = (0..10).map(|_| manager.try_admit(b).unwrap()).collect();
+        assert_eq!(manager.client_count(), 11);
+        drop(many);
+        assert_eq!(manager.clients_per_ip(), vec![(a, 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_relay() {
+        const TOTAL: usize = 64 * 1024;
+        const RATE: u64 = 256 * 1024;
+        const BURST: u64 = 16 * 1024;
+
+        let (client, mut client_peer) = tokio::io::duplex(TOTAL * 2);
+        let (remote, mut remote_peer) = tokio::io::duplex(TOTAL * 2);
+        let relay = ProxyConnection::new(client, remote)
+            .with_rate_limiter(Some(RateLimiter::new(RATE, BURST)))
+            .relay();
+        let relay = tokio::spawn(relay);
+
+        let start = tokio::time::Instant::now();
+        client_peer.write_all(&vec![7u8; TOTAL]).await.unwrap();
+        let mut received = vec![0u8; TOTAL];
+        remote_peer.read_exact(&mut received).await.unwrap();
+        let elapsed = start.elapsed();
+
+        // 初始桶容量之外的数据按速率发送: (64K - 16K) / 256K/s = 187.5ms
+        let expected = std::time::Duration::from_secs_f64((TOTAL as u64 - BURST) as f64 / RATE as f64);
+        assert!(elapsed >= expected, "elapsed {:?} < expected {:?}", elapsed, expected);
+        assert!(received.iter().all(|&b| b == 7));
+
+        drop(client_peer);
+        drop(remote_peer);
+        let _ = relay.await;
+    }
+
+    #[tokio::test]
+    async fn test_max_lifetime_closes_active_relay() {
+        const LIFETIME: Duration = Duration::from_millis(300);
+
+        let (client, mut client_peer) = tokio::io::duplex(4096);
+        let (remote, remote_peer) = tokio::io::duplex(4096);
+        // 回显目标
+        tokio::spawn(async move {
+            let (mut r, mut w) = tokio::io::split(remote_peer);
+            let _ = tokio::io::copy(&mut r, &mut w).await;
+        });
+        let start = tokio::time::Instant::now();
+        let relay = tokio::spawn(ProxyConnection::new(client, remote).with_max_lifetime(Some(LIFETIME)).relay());
+
+        // 连接一直有数据往来，只会因存活时间到期而关闭
+        let mut buf = [0u8; 5];
+        let closed_at = loop {
+            if client_peer.write_all(b"ping!").await.is_err() || client_peer.read_exact(&mut buf).await.is_err() {
+                break start.elapsed();
+            }
+            assert_eq!(&buf, b"ping!");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        };
+        assert!(closed_at >= LIFETIME && closed_at < LIFETIME * 3, "closed after {:?}", closed_at);
+        let stats = relay.await.unwrap();
+        assert_eq!(stats.reason, CloseReason::MaxLifetime);
+        assert!(stats.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_relay_stats() {
+        let (client, mut client_peer) = tokio::io::duplex(4096);
+        let (remote, mut remote_peer) = tokio::io::duplex(4096);
+        let relay = tokio::spawn(ProxyConnection::new(client, remote).relay());
+
+        // 客户端先结束上行，目标随后回复并关闭: 两次半关闭都不是错误
+        client_peer.write_all(&[1u8; 100]).await.unwrap();
+        client_peer.shutdown().await.unwrap();
+        let mut request = Vec::new();
+        remote_peer.read_to_end(&mut request).await.unwrap();
+        assert_eq!(request.len(), 100);
+        remote_peer.write_all(&[2u8; 300]).await.unwrap();
+        drop(remote_peer);
+        let mut response = Vec::new();
+        client_peer.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response.len(), 300);
+
+        let stats = relay.await.unwrap();
+        assert_eq!((stats.up_bytes, stats.down_bytes), (100, 300));
+        assert_eq!(stats.first_error_direction, None);
+        assert_eq!(stats.reason, CloseReason::Closed);
+        assert!(stats.into_result().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_half_close_keeps_response_flowing() {
+        const RESPONSE: usize = 1 << 20;
+
+        let (client, client_peer) = tokio::io::duplex(16 * 1024);
+        let (remote, remote_peer) = tokio::io::duplex(16 * 1024);
+        let relay = tokio::spawn(ProxyConnection::new(client, remote).relay());
+
+        // 目标读完请求 (直到 FIN) 后才开始分批返回大响应
+        let server = tokio::spawn(async move {
+            let (mut r, mut w) = tokio::io::split(remote_peer);
+            let mut request = Vec::new();
+            r.read_to_end(&mut request).await.unwrap();
+            for _ in 0..RESPONSE / 4096 {
+                w.write_all(&[9u8; 4096]).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+            w.shutdown().await.unwrap();
+            request
+        });
+
+        // 客户端发完请求立即半关闭，仍然读完整个响应
+        let (mut r, mut w) = tokio::io::split(client_peer);
+        w.write_all(b"GET / HTTP/1.0\r\n\r\n").await.unwrap();
+        w.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        r.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response.len(), RESPONSE);
+        assert_eq!(server.await.unwrap(), b"GET / HTTP/1.0\r\n\r\n");
+
+        let stats = relay.await.unwrap();
+        assert_eq!(stats.down_bytes, RESPONSE as u64);
+        assert!(stats.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_relay_error_keeps_stats() {
+        let (client, mut client_peer) = tokio::io::duplex(64);
+        let (remote, mut remote_peer) = tokio::io::duplex(4096);
+        let relay = tokio::spawn(ProxyConnection::new(client, remote).relay());
+
+        client_peer.write_all(b"hello").await.unwrap();
+        let mut buf = [0u8; 5];
+        remote_peer.read_exact(&mut buf).await.unwrap();
+        // 客户端消失后目标仍在发送，写往客户端失败
+        drop(client_peer);
+        let writer = tokio::spawn(async move {
+            while remote_peer.write_all(&[0u8; 1024]).await.is_ok() {}
+        });
+        let stats = relay.await.unwrap();
+        writer.abort();
+        assert_eq!(stats.up_bytes, 5);
+        assert_eq!(stats.first_error_direction, Some(RelayDirection::Down));
+        assert_eq!(stats.reason, CloseReason::Reset(RelayDirection::Down));
+        assert!(stats.into_result().unwrap_err().to_string().starts_with("目标 -> 客户端"));
+    }
+
+    #[test]
+    fn test_close_reason_classification() {
+        use std::io::{Error, ErrorKind};
+
+        let reset = CloseReason::from_error(RelayDirection::Up, &Error::from(ErrorKind::ConnectionReset));
+        assert_eq!(reset, CloseReason::Reset(RelayDirection::Up));
+        assert!(!reset.is_error());
+        let eof = CloseReason::from_error(RelayDirection::Down, &Error::from(ErrorKind::UnexpectedEof));
+        assert!(!eof.is_error());
+        let invalid = CloseReason::from_error(RelayDirection::Down, &Error::new(ErrorKind::InvalidData, "bad record mac"));
+        assert_eq!(invalid, CloseReason::Error(RelayDirection::Down));
+        assert_eq!(invalid.to_string(), "error (目标 -> 客户端)");
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_treats_reset_as_close() {
+        let manager = ConnectionManager::new();
+        let (client, client_peer) = tokio::io::duplex(64);
+        let (remote, mut remote_peer) = tokio::io::duplex(4096);
+        drop(client_peer);
+        let writer = tokio::spawn(async move {
+            while remote_peer.write_all(&[0u8; 1024]).await.is_ok() {}
+        });
+
+        let mut completed = None;
+        let result = manager.handle_connection(client, remote, None, None, |stats| completed = Some(stats.reason)).await;
+        writer.abort();
+        assert_eq!(result.unwrap(), CloseReason::Reset(RelayDirection::Down));
+        assert_eq!(completed, Some(CloseReason::Reset(RelayDirection::Down)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout() {
+        const IDLE: Duration = Duration::from_secs(60);
+
+        let (client, mut client_peer) = tokio::io::duplex(4096);
+        let (remote, mut remote_peer) = tokio::io::duplex(4096);
+        let started = Instant::now();
+        let relay = tokio::spawn(ProxyConnection::new(client, remote).with_idle_timeout(Some(IDLE)).relay());
+
+        // 持续有数据时不超时
+        let mut buf = [0u8; 4];
+        for _ in 0..5 {
+            tokio::time::sleep(IDLE / 2).await;
+            client_peer.write_all(b"ping").await.unwrap();
+            remote_peer.read_exact(&mut buf).await.unwrap();
+        }
+        // 之后两端都沉默，最后一次数据后 IDLE 到期，两端都被关闭
+        let mut rest = Vec::new();
+        client_peer.read_to_end(&mut rest).await.unwrap();
+        remote_peer.read_to_end(&mut rest).await.unwrap();
+        let stats = relay.await.unwrap();
+        assert_eq!(stats.reason, CloseReason::IdleTimeout);
+        assert!(stats.error.is_none());
+        let elapsed = started.elapsed();
+        assert!(elapsed >= IDLE / 2 * 5 + IDLE && elapsed < IDLE / 2 * 5 + IDLE * 2, "closed after {:?}", elapsed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout_disabled() {
+        let manager = ConnectionManager::new();
+        manager.set_idle_timeout(Some(Duration::from_secs(1)));
+        let (client, _client_peer) = tokio::io::duplex(64);
+        let (remote, _remote_peer) = tokio::io::duplex(64);
+        // 路由规则的 0 覆盖全局设置
+        let relay = manager.handle_connection(client, remote, None, Some(Duration::ZERO), |_| {});
+        assert!(tokio::time::timeout(Duration::from_secs(3600), relay).await.is_err());
+    }
 }
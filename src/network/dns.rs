@@ -0,0 +1,764 @@
+//! 域名解析与缓存
+//!
+//! 直连出站的域名目标经由 [`DnsCache`] 解析。结果按记录 TTL 缓存 (限制在 `minTtl`-`maxTtl` 之间)，
+//! NXDOMAIN 与无 A/AAAA 记录的结果按 `negativeTtl` 缓存；超时等临时错误不缓存，
+//! A 与 AAAA 只有一个查询成功时结果可以使用，但同样不缓存。IP 字面量直接返回，不经过缓存与上游。
+//! 上游可以是系统解析器，或配置的 DNS 服务器 (UDP、DNS over TLS 或 DNS over HTTPS，同时查询 A 与 AAAA)。
+
+use std::convert::Infallible;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use hyper::body::{Body, Frame, Incoming, SizeHint};
+use hyper::http::{Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use lru::LruCache;
+use rustls_pki_types::ServerName;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::Instant;
+use tokio_rustls::client::TlsStream;
+use tracing::debug;
+
+use crate::config::DnsConfig;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+/// 缓存的域名数上限
+const CACHE_CAPACITY: usize = 4096;
+/// 单个上游服务器的查询超时
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+/// DoT / DoH 的查询超时，含 TCP 与 TLS 握手
+const STREAM_QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// DNS 报文长度上限
+const MAX_MESSAGE_LEN: usize = 65535;
+
+/// 一次解析的结果，`addrs` 为空表示域名不存在或没有地址记录
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Answer {
+    pub addrs: Vec<IpAddr>,
+    /// 记录中最小的 TTL，系统解析器不提供
+    pub ttl: Option<Duration>,
+    /// A 或 AAAA 之一查询失败，地址不完整，不缓存
+    pub partial: bool,
+}
+
+/// 上游解析器
+pub trait Resolve: Send + Sync {
+    fn resolve<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Answer>>;
+}
+
+/// 系统解析器 (getaddrinfo)
+pub struct SystemResolver;
+
+impl Resolve for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Answer>> {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host, 0)).await?.map(|addr| addr.ip()).collect();
+            Ok(Answer { addrs, ..Default::default() })
+        })
+    }
+}
+
+/// 配置中的一个上游 DNS 服务器
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Upstream {
+    /// 普通 DNS (UDP)
+    Udp(SocketAddr),
+    /// DNS over TLS (RFC 7858)，主机名同时用于连接与证书校验
+    Tls { host: String, port: u16 },
+    /// DNS over HTTPS (RFC 8484)，以 HTTP/1.1 POST 发送
+    Https { host: String, port: u16, path: String },
+}
+
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Upstream::Udp(addr) => write!(f, "{}", addr),
+            Upstream::Tls { host, port } => write!(f, "tls://{}", authority(host, *port)),
+            Upstream::Https { host, port, path } => write!(f, "https://{}{}", authority(host, *port), path),
+        }
+    }
+}
+
+/// `host:port`，IPv6 地址加方括号
+fn authority(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// 按顺序查询配置的 DNS 服务器，直到有一个应答
+pub struct UpstreamResolver {
+    servers: Vec<Upstream>,
+    /// DoT 使用的 TLS 配置
+    tls: Arc<rustls::ClientConfig>,
+    /// DoH 使用的 TLS 配置 (ALPN 为 http/1.1)
+    https: Arc<rustls::ClientConfig>,
+}
+
+impl UpstreamResolver {
+    /// DoT / DoH 服务器的证书按内置的 Web PKI 根证书校验
+    pub fn new(servers: Vec<Upstream>) -> Self {
+        let roots = rustls::RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+        Self::with_roots(servers, roots)
+    }
+
+    /// 以 `roots` 校验 DoT / DoH 服务器的证书
+    pub fn with_roots(servers: Vec<Upstream>, roots: rustls::RootCertStore) -> Self {
+        let tls = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("ring 支持默认的 TLS 版本")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let mut https = tls.clone();
+        https.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Self { servers, tls: Arc::new(tls), https: Arc::new(https) }
+    }
+
+    /// 向 `server` 查询一种记录
+    async fn query(&self, server: &Upstream, host: &str, qtype: u16) -> Result<Answer> {
+        let exchange = match server {
+            Upstream::Udp(addr) => return query_udp(*addr, host, qtype).await,
+            Upstream::Tls { host: name, port } => Box::pin(query_tls(&self.tls, name, *port, host, qtype)) as BoxFuture<'_, _>,
+            Upstream::Https { host: name, port, path } => Box::pin(query_https(&self.https, name, *port, path, host, qtype)),
+        };
+        tokio::time::timeout(STREAM_QUERY_TIMEOUT, exchange)
+            .await
+            .map_err(|_| anyhow!("DNS 服务器 {} 超时", server))?
+    }
+}
+
+impl Resolve for UpstreamResolver {
+    fn resolve<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Answer>> {
+        Box::pin(async move {
+            if let Ok(ip) = host.parse::<IpAddr>() {
+                return Ok(Answer { addrs: vec![ip], ..Default::default() });
+            }
+            let mut last_error = anyhow!("没有配置 DNS 服务器");
+            for server in &self.servers {
+                let (a, aaaa) = tokio::join!(self.query(server, host, TYPE_A), self.query(server, host, TYPE_AAAA));
+                let partial = match (&a, &aaaa) {
+                    (Ok(_), Ok(_)) => false,
+                    (Err(e), other) | (other, Err(e)) => {
+                        // 另一个查询也没有地址时无法区分 "不存在" 与 "暂时查不到"，换下一个服务器
+                        if !other.as_ref().is_ok_and(|answer| !answer.addrs.is_empty()) {
+                            debug!("DNS: {} 查询 {} 失败: {}", server, host, e);
+                            last_error = anyhow!("{}", e);
+                            continue;
+                        }
+                        debug!("DNS: {} 查询 {} 部分失败: {}", server, host, e);
+                        true
+                    }
+                };
+                let mut answer = Answer { partial, ..Default::default() };
+                for part in [aaaa, a].into_iter().flatten() {
+                    answer.addrs.extend(part.addrs);
+                    answer.ttl = match (answer.ttl, part.ttl) {
+                        (Some(x), Some(y)) => Some(x.min(y)),
+                        (x, y) => x.or(y),
+                    };
+                }
+                return Ok(answer);
+            }
+            Err(last_error)
+        })
+    }
+}
+
+/// 经 UDP 向 `server` 发送一个查询并等待对应的应答
+async fn query_udp(server: SocketAddr, host: &str, qtype: u16) -> Result<Answer> {
+    let id: u16 = rand::random();
+    let request = encode_query(id, host, qtype)?;
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    socket.send(&request).await?;
+
+    let mut buf = vec![0u8; 4096];
+    tokio::time::timeout(QUERY_TIMEOUT, async {
+        loop {
+            let n = socket.recv(&mut buf).await?;
+            // 忽略不属于本次查询的应答
+            if n >= 2 && buf[..2] == id.to_be_bytes() {
+                return parse_response(&buf[..n]);
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("DNS 服务器 {} 超时", server))?
+}
+
+/// 与 `name:port` 建立 TLS 连接，证书按 `name` 校验
+async fn connect_tls(config: &Arc<rustls::ClientConfig>, name: &str, port: u16) -> Result<TlsStream<TcpStream>> {
+    let server_name = ServerName::try_from(name.to_string()).map_err(|e| anyhow!("无效的服务器名 {}: {}", name, e))?;
+    let stream = TcpStream::connect((name, port)).await?;
+    Ok(tokio_rustls::TlsConnector::from(config.clone()).connect(server_name, stream).await?)
+}
+
+/// DNS over TLS: 报文前加两字节长度，每次查询使用一条新连接
+async fn query_tls(config: &Arc<rustls::ClientConfig>, name: &str, port: u16, host: &str, qtype: u16) -> Result<Answer> {
+    let id: u16 = rand::random();
+    let request = encode_query(id, host, qtype)?;
+    let mut stream = connect_tls(config, name, port).await?;
+    let mut framed = Vec::with_capacity(2 + request.len());
+    framed.extend_from_slice(&(request.len() as u16).to_be_bytes());
+    framed.extend_from_slice(&request);
+    stream.write_all(&framed).await?;
+
+    let len = stream.read_u16().await? as usize;
+    let mut response = vec![0u8; len];
+    stream.read_exact(&mut response).await?;
+    if response.get(..2) != Some(&id.to_be_bytes()[..]) {
+        bail!("DNS 应答 ID 不匹配");
+    }
+    parse_response(&response)
+}
+
+/// DNS over HTTPS: POST `application/dns-message`，ID 按 RFC 8484 置 0
+async fn query_https(
+    config: &Arc<rustls::ClientConfig>,
+    name: &str,
+    port: u16,
+    path: &str,
+    host: &str,
+    qtype: u16,
+) -> Result<Answer> {
+    let request = encode_query(0, host, qtype)?;
+    let stream = connect_tls(config, name, port).await?;
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("DoH 连接结束: {}", e);
+        }
+    });
+
+    // 默认端口不写入 Host
+    let mut host_header = authority(name, port);
+    if port == 443 {
+        host_header.truncate(host_header.len() - ":443".len());
+    }
+    let request = Request::post(path)
+        .header("host", host_header)
+        .header("content-type", "application/dns-message")
+        .header("accept", "application/dns-message")
+        .body(WireBody(Some(Bytes::from(request))))?;
+    let response = sender.send_request(request).await?;
+    if response.status() != StatusCode::OK {
+        bail!("DoH 服务器返回 {}", response.status());
+    }
+    parse_response(&read_body(response.into_body()).await?)
+}
+
+/// 只有一块数据的 HTTP 消息体
+struct WireBody(Option<Bytes>);
+
+impl Body for WireBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        Poll::Ready(self.0.take().map(|data| Ok(Frame::data(data))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.0.as_ref().map_or(0, |data| data.len() as u64))
+    }
+}
+
+/// 读取完整的消息体，超过 DNS 报文长度上限时报错
+async fn read_body(mut body: Incoming) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    while let Some(frame) = futures::future::poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await {
+        if let Ok(chunk) = frame?.into_data() {
+            data.extend_from_slice(&chunk);
+            if data.len() > MAX_MESSAGE_LEN {
+                bail!("DNS 报文过长");
+            }
+        }
+    }
+    Ok(data)
+}
+
+/// 构造递归查询报文 (RD=1，单个问题)
+fn encode_query(id: u16, host: &str, qtype: u16) -> Result<Vec<u8>> {
+    let mut msg = Vec::with_capacity(18 + host.len());
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("无效的域名: {}", host);
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend_from_slice(&qtype.to_be_bytes());
+    msg.extend_from_slice(&1u16.to_be_bytes());
+    Ok(msg)
+}
+
+/// 解析应答中的 A / AAAA 记录 (CNAME 等其它记录跳过)
+fn parse_response(msg: &[u8]) -> Result<Answer> {
+    let truncated = || anyhow!("DNS 应答被截断");
+    let header = msg.get(..12).ok_or_else(truncated)?;
+    let flags = u16::from_be_bytes([header[2], header[3]]);
+    if flags & 0x8000 == 0 {
+        bail!("DNS 报文不是应答");
+    }
+    match flags & 0x000F {
+        0 => {}
+        // NXDOMAIN
+        3 => return Ok(Answer::default()),
+        rcode => bail!("DNS 服务器返回错误 (RCODE={})", rcode),
+    }
+    let questions = u16::from_be_bytes([header[4], header[5]]);
+    let answers = u16::from_be_bytes([header[6], header[7]]);
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(msg, pos)? + 4;
+    }
+    let mut answer = Answer::default();
+    for _ in 0..answers {
+        pos = skip_name(msg, pos)?;
+        let record = msg.get(pos..pos + 10).ok_or_else(truncated)?;
+        let rtype = u16::from_be_bytes([record[0], record[1]]);
+        let ttl = u32::from_be_bytes([record[4], record[5], record[6], record[7]]);
+        let len = u16::from_be_bytes([record[8], record[9]]) as usize;
+        let data = msg.get(pos + 10..pos + 10 + len).ok_or_else(truncated)?;
+        pos += 10 + len;
+
+        let ip = match (rtype, data.len()) {
+            (TYPE_A, 4) => IpAddr::from(<[u8; 4]>::try_from(data)?),
+            (TYPE_AAAA, 16) => IpAddr::from(<[u8; 16]>::try_from(data)?),
+            _ => continue,
+        };
+        answer.addrs.push(ip);
+        let ttl = Duration::from_secs(ttl as u64);
+        answer.ttl = Some(answer.ttl.map_or(ttl, |t| t.min(ttl)));
+    }
+    Ok(answer)
+}
+
+/// 跳过一个 (可能压缩的) 域名，返回其后的位置
+fn skip_name(msg: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        let len = *msg.get(pos).ok_or_else(|| anyhow!("DNS 应答被截断"))?;
+        match len {
+            0 => return Ok(pos + 1),
+            len if len & 0xC0 == 0xC0 => return Ok(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
+
+/// 解析配置中的 DNS 服务器
+///
+/// - `IP` 或 `IP:端口`: UDP，端口默认 53
+/// - `tls://主机[:端口]`: DNS over TLS，端口默认 853
+/// - `https://主机[:端口][/路径]`: DNS over HTTPS，端口默认 443，路径默认 `/dns-query`
+///
+/// DoT / DoH 的主机名由系统解析器解析，证书按主机名 (或 IP) 校验。
+pub fn parse_server(server: &str) -> Result<Upstream> {
+    if let Some(rest) = server.strip_prefix("tls://") {
+        let (host, port) = split_host_port(rest, 853).ok_or_else(|| anyhow!("{:?}: 需要 tls://主机[:端口]", server))?;
+        return Ok(Upstream::Tls { host, port });
+    }
+    if let Some(rest) = server.strip_prefix("https://") {
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/dns-query"),
+        };
+        let (host, port) =
+            split_host_port(authority, 443).ok_or_else(|| anyhow!("{:?}: 需要 https://主机[:端口]/路径", server))?;
+        return Ok(Upstream::Https { host, port, path: path.to_string() });
+    }
+    if server.contains("://") {
+        bail!("{:?}: 只支持 UDP、tls:// 与 https:// DNS 服务器", server);
+    }
+    if let Ok(addr) = server.parse::<SocketAddr>() {
+        return Ok(Upstream::Udp(addr));
+    }
+    let ip: IpAddr = server.parse().map_err(|_| anyhow!("{:?}: 需要 IP 或 IP:端口", server))?;
+    Ok(Upstream::Udp(SocketAddr::new(ip, 53)))
+}
+
+/// 拆分 `主机[:端口]`，IPv6 地址须加方括号 (不带端口时也可以不加)
+fn split_host_port(authority: &str, default_port: u16) -> Option<(String, u16)> {
+    if let Ok(ip) = authority.parse::<Ipv6Addr>() {
+        return Some((ip.to_string(), default_port));
+    }
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(rest) => {
+            let (host, port) = rest.split_once(']')?;
+            host.parse::<Ipv6Addr>().ok()?;
+            (host, port)
+        }
+        None => match authority.find(':') {
+            Some(i) => authority.split_at(i),
+            None => (authority, ""),
+        },
+    };
+    let port = match port {
+        "" => default_port,
+        port => port.strip_prefix(':')?.parse().ok()?,
+    };
+    if host.is_empty() || ServerName::try_from(host).is_err() {
+        return None;
+    }
+    Some((host.to_string(), port))
+}
+
+/// 缓存时间
+#[derive(Debug, Clone, Copy)]
+pub struct CacheTtl {
+    pub min: Duration,
+    pub max: Duration,
+    pub negative: Duration,
+}
+
+struct Entry {
+    addrs: Arc<[IpAddr]>,
+    expires: Instant,
+}
+
+/// 带 TTL 的域名解析缓存
+pub struct DnsCache {
+    resolver: Box<dyn Resolve>,
+    ttl: CacheTtl,
+    entries: Mutex<LruCache<String, Entry>>,
+}
+
+impl DnsCache {
+    pub fn new(resolver: Box<dyn Resolve>, ttl: CacheTtl) -> Self {
+        Self {
+            resolver,
+            ttl,
+            entries: Mutex::new(LruCache::new(NonZeroUsize::new(CACHE_CAPACITY).unwrap())),
+        }
+    }
+
+    /// 按配置创建: 未配置服务器时使用系统解析器
+    pub fn from_config(config: &DnsConfig) -> Result<Self> {
+        let resolver: Box<dyn Resolve> = if config.servers.is_empty() {
+            Box::new(SystemResolver)
+        } else {
+            let servers = config.servers.iter().map(|s| parse_server(s)).collect::<Result<_>>()?;
+            Box::new(UpstreamResolver::new(servers))
+        };
+        Ok(Self::new(resolver, config.cache_ttl()))
+    }
+
+    /// 解析域名，缓存未过期时不访问上游
+    pub async fn resolve(&self, host: &str) -> Result<Arc<[IpAddr]>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(Arc::new([ip]));
+        }
+        let key = host.trim_end_matches('.').to_ascii_lowercase();
+        let cached = {
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            match entries.get(&key) {
+                Some(entry) if entry.expires > Instant::now() => Some(entry.addrs.clone()),
+                _ => None,
+            }
+        };
+        let addrs = match cached {
+            Some(addrs) => addrs,
+            None => {
+                let answer = self.resolver.resolve(&key).await?;
+                let ttl = if answer.addrs.is_empty() {
+                    self.ttl.negative
+                } else {
+                    answer.ttl.unwrap_or(self.ttl.min).max(self.ttl.min).min(self.ttl.max)
+                };
+                let partial = answer.partial;
+                let addrs: Arc<[IpAddr]> = answer.addrs.into();
+                if partial {
+                    debug!("DNS: {} -> {:?} (结果不完整，不缓存)", key, addrs);
+                } else {
+                    debug!("DNS: {} -> {:?} (缓存 {:?})", key, addrs, ttl);
+                    let entry = Entry { addrs: addrs.clone(), expires: Instant::now() + ttl };
+                    self.entries.lock().unwrap_or_else(|e| e.into_inner()).put(key, entry);
+                }
+                addrs
+            }
+        };
+        if addrs.is_empty() {
+            bail!("域名 {} 没有可用的地址", host);
+        }
+        Ok(addrs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 返回固定结果并记录调用次数
+    struct FakeResolver {
+        answer: Answer,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Resolve for FakeResolver {
+        fn resolve<'a>(&'a self, _host: &'a str) -> BoxFuture<'a, Result<Answer>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move { Ok(self.answer.clone()) })
+        }
+    }
+
+    fn fake_cache(answer: Answer) -> (DnsCache, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let ttl = CacheTtl {
+            min: Duration::from_secs(10),
+            max: Duration::from_secs(60),
+            negative: Duration::from_secs(5),
+        };
+        (DnsCache::new(Box::new(FakeResolver { answer, calls: calls.clone() }), ttl), calls)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cached_within_ttl() {
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let (cache, calls) = fake_cache(Answer { addrs: vec![ip], ttl: Some(Duration::from_secs(30)), ..Default::default() });
+
+        assert_eq!(&cache.resolve("Example.COM.").await.unwrap()[..], &[ip]);
+        tokio::time::advance(Duration::from_secs(29)).await;
+        assert_eq!(&cache.resolve("example.com").await.unwrap()[..], &[ip]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        cache.resolve("example.com").await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ttl_clamped_and_negative_cached() {
+        // TTL 1s 被提升到 minTtl 10s
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let (cache, calls) = fake_cache(Answer { addrs: vec![ip], ttl: Some(Duration::from_secs(1)), ..Default::default() });
+        cache.resolve("example.com").await.unwrap();
+        tokio::time::advance(Duration::from_secs(9)).await;
+        cache.resolve("example.com").await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let (cache, calls) = fake_cache(Answer::default());
+        assert!(cache.resolve("missing.example").await.is_err());
+        assert!(cache.resolve("missing.example").await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        tokio::time::advance(Duration::from_secs(6)).await;
+        assert!(cache.resolve("missing.example").await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_partial_answer_not_cached() {
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let answer = Answer { addrs: vec![ip], ttl: Some(Duration::from_secs(30)), partial: true };
+        let (cache, calls) = fake_cache(answer);
+        assert_eq!(&cache.resolve("example.com").await.unwrap()[..], &[ip]);
+        assert_eq!(&cache.resolve("example.com").await.unwrap()[..], &[ip]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_ip_literal_skips_resolver() {
+        let (cache, calls) = fake_cache(Answer::default());
+        assert_eq!(&cache.resolve("192.0.2.7").await.unwrap()[..], &["192.0.2.7".parse::<IpAddr>().unwrap()]);
+        assert_eq!(&cache.resolve("2001:db8::1").await.unwrap()[..], &["2001:db8::1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        // 没有可用的服务器也能直接返回
+        let answer = UpstreamResolver::new(Vec::new()).resolve("192.0.2.7").await.unwrap();
+        assert_eq!(answer.addrs, vec![IpAddr::from([192, 0, 2, 7])]);
+    }
+
+    /// 构造应答: A 查询返回一条经压缩指针引用问题域名的记录，AAAA 查询返回空 (`aaaa_fails` 时返回 SERVFAIL)
+    fn reply_to(query: &[u8], aaaa_fails: bool) -> Vec<u8> {
+        let n = query.len();
+        let qtype = u16::from_be_bytes([query[n - 4], query[n - 3]]);
+        let mut reply = query.to_vec();
+        reply[2..4].copy_from_slice(&0x8180u16.to_be_bytes());
+        if qtype == TYPE_A {
+            reply[7] = 1;
+            reply.extend_from_slice(&[0xC0, 0x0C, 0, 1, 0, 1, 0, 0, 0, 120, 0, 4, 127, 0, 0, 1]);
+        } else if aaaa_fails {
+            reply[3] = 0x82;
+        }
+        reply
+    }
+
+    async fn spawn_dns_server(aaaa_fails: bool) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (n, peer) = socket.recv_from(&mut buf).await.unwrap();
+                socket.send_to(&reply_to(&buf[..n], aaaa_fails), peer).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_udp_resolver() {
+        let server = spawn_dns_server(false).await;
+        let answer = UpstreamResolver::new(vec![Upstream::Udp(server)]).resolve("example.com").await.unwrap();
+        assert_eq!(answer.addrs, vec![IpAddr::from([127, 0, 0, 1])]);
+        assert_eq!(answer.ttl, Some(Duration::from_secs(120)));
+        assert!(!answer.partial);
+
+        let server = spawn_dns_server(true).await;
+        let answer = UpstreamResolver::new(vec![Upstream::Udp(server)]).resolve("example.com").await.unwrap();
+        assert_eq!(answer.addrs, vec![IpAddr::from([127, 0, 0, 1])]);
+        assert!(answer.partial);
+    }
+
+    /// 为 localhost 与 127.0.0.1 签发的自签名证书，以及信任它的根证书集
+    fn test_certificate() -> (rustls::ServerConfig, rustls::RootCertStore) {
+        let rcgen::CertifiedKey { cert, key_pair } =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string(), "127.0.0.1".to_string()]).unwrap();
+        let key = rustls_pki_types::PrivateKeyDer::Pkcs8(key_pair.serialize_der().into());
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(cert.der().clone()).unwrap();
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.der().clone()], key)
+            .unwrap();
+        (config, roots)
+    }
+
+    /// DNS over TLS 服务器: 每条连接回答一个查询
+    async fn spawn_dot_server(config: rustls::ServerConfig) -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let mut tls = acceptor.accept(stream).await.unwrap();
+                    let len = tls.read_u16().await.unwrap() as usize;
+                    let mut query = vec![0u8; len];
+                    tls.read_exact(&mut query).await.unwrap();
+                    let reply = reply_to(&query, false);
+                    tls.write_u16(reply.len() as u16).await.unwrap();
+                    tls.write_all(&reply).await.unwrap();
+                    tls.shutdown().await.unwrap();
+                });
+            }
+        });
+        port
+    }
+
+    /// DNS over HTTPS 服务器: 只接受 POST /dns-query
+    async fn spawn_doh_server(mut config: rustls::ServerConfig) -> u16 {
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let tls = acceptor.accept(stream).await.unwrap();
+                    let service = hyper::service::service_fn(|request: Request<Incoming>| async move {
+                        let valid = request.method() == "POST"
+                            && request.uri().path() == "/dns-query"
+                            && request.headers()["content-type"] == "application/dns-message";
+                        let query = read_body(request.into_body()).await?;
+                        let (status, body) = match valid {
+                            true => (StatusCode::OK, reply_to(&query, false)),
+                            false => (StatusCode::BAD_REQUEST, Vec::new()),
+                        };
+                        hyper::http::Response::builder().status(status).body(WireBody(Some(Bytes::from(body))))
+                            .map_err(anyhow::Error::from)
+                    });
+                    let _ = hyper::server::conn::http1::Builder::new().serve_connection(TokioIo::new(tls), service).await;
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_tls_and_https_resolvers() {
+        let (config, roots) = test_certificate();
+        let dot = spawn_dot_server(config.clone()).await;
+        let doh = spawn_doh_server(config).await;
+
+        for server in [format!("tls://127.0.0.1:{}", dot), format!("https://localhost:{}/dns-query", doh)] {
+            let resolver = UpstreamResolver::with_roots(vec![parse_server(&server).unwrap()], roots.clone());
+            let answer = resolver.resolve("example.com").await.unwrap();
+            assert_eq!(answer.addrs, vec![IpAddr::from([127, 0, 0, 1])], "{}", server);
+            assert_eq!(answer.ttl, Some(Duration::from_secs(120)), "{}", server);
+
+            // 不信任的证书: 查询失败
+            let resolver = UpstreamResolver::new(vec![parse_server(&server).unwrap()]);
+            assert!(resolver.resolve("example.com").await.is_err(), "{}", server);
+        }
+
+        let resolver = UpstreamResolver::with_roots(vec![parse_server(&format!("https://localhost:{}/other", doh)).unwrap()], roots);
+        assert!(resolver.resolve("example.com").await.unwrap_err().to_string().contains("400"));
+    }
+
+    #[test]
+    fn test_parse_response_codes() {
+        let mut msg = encode_query(7, "example.com", TYPE_A).unwrap();
+        assert!(parse_response(&msg).is_err());
+        msg[2..4].copy_from_slice(&0x8183u16.to_be_bytes());
+        assert_eq!(parse_response(&msg).unwrap(), Answer::default());
+        msg[2..4].copy_from_slice(&0x8182u16.to_be_bytes());
+        assert!(parse_response(&msg).unwrap_err().to_string().contains("RCODE=2"));
+        assert!(encode_query(7, "bad..name", TYPE_A).is_err());
+    }
+
+    #[test]
+    fn test_parse_server() {
+        let udp = |addr: &str| Upstream::Udp(addr.parse().unwrap());
+        assert_eq!(parse_server("1.1.1.1").unwrap(), udp("1.1.1.1:53"));
+        assert_eq!(parse_server("127.0.0.1:5353").unwrap(), udp("127.0.0.1:5353"));
+        assert_eq!(parse_server("2606:4700::1111").unwrap(), udp("[2606:4700::1111]:53"));
+        assert!(parse_server("dns.google").is_err());
+
+        let tls = |host: &str, port| Upstream::Tls { host: host.to_string(), port };
+        assert_eq!(parse_server("tls://1.1.1.1").unwrap(), tls("1.1.1.1", 853));
+        assert_eq!(parse_server("tls://dns.google:8853").unwrap(), tls("dns.google", 8853));
+        assert_eq!(parse_server("tls://[2606:4700::1111]:853").unwrap(), tls("2606:4700::1111", 853));
+        assert_eq!(parse_server("tls://2606:4700::1111").unwrap(), tls("2606:4700::1111", 853));
+
+        let https = |host: &str, port, path: &str| Upstream::Https { host: host.to_string(), port, path: path.to_string() };
+        assert_eq!(parse_server("https://dns.google/dns-query").unwrap(), https("dns.google", 443, "/dns-query"));
+        assert_eq!(parse_server("https://1.1.1.1").unwrap(), https("1.1.1.1", 443, "/dns-query"));
+        assert_eq!(parse_server("https://[::1]:8443/q?x=1").unwrap(), https("::1", 8443, "/q?x=1"));
+        assert_eq!(parse_server("https://dns.google/dns-query").unwrap().to_string(), "https://dns.google:443/dns-query");
+
+        for bad in ["quic://dns.google", "tls://", "tls://dns.google:", "tls://dns.google:x", "https://[dns.google]/", "tls://a b"] {
+            assert!(parse_server(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! 目标地址过滤
+//!
+//! 拨号前按 `routing.rules` 顺序匹配目标，第一条命中的规则生效: 指向 `blackhole` 出站的规则阻止连接，
+//! 其它规则放行。没有规则命中时，`blockPrivate` 阻止回环、内网、链路本地等地址，防止代理被用来访问
+//! 服务器所在的内部网络 (SSRF)。域名目标先按域名规则匹配，未命中时对解析出的每个 IP 再做判断。
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::config::Config;
+use crate::protocol::vless::Address;
+
+/// `blockPrivate` 阻止的地址段，也是 `geoip:private` 的内容
+const PRIVATE_RANGES: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+];
+
+/// 过滤结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// 阻止，附带原因
+    Block(String),
+}
+
+/// IP 地址段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// 解析 `地址/前缀长度`，单个地址视为主机路由
+    pub fn parse(s: &str) -> Result<Self> {
+        let (ip, prefix) = match s.split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (s, None),
+        };
+        let network: IpAddr = ip.parse().map_err(|_| anyhow!("无效的 IP: {:?}", s))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().ok().filter(|&p| p <= max).ok_or_else(|| anyhow!("无效的前缀长度: {:?}", s))?,
+            None => max,
+        };
+        Ok(Self { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// 域名匹配方式 (与 Xray 的写法一致)
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DomainMatcher {
+    /// `domain:` 该域名及其子域名
+    Suffix(String),
+    /// `full:` 完全相同
+    Full(String),
+    /// `keyword:` 或不带前缀: 包含该字符串
+    Keyword(String),
+}
+
+impl DomainMatcher {
+    fn parse(s: &str) -> Result<Self> {
+        let s = s.to_ascii_lowercase();
+        let matcher = match s.split_once(':') {
+            Some(("domain", d)) => Self::Suffix(d.trim_start_matches('.').to_string()),
+            Some(("full", d)) => Self::Full(d.to_string()),
+            Some(("keyword", d)) => Self::Keyword(d.to_string()),
+            Some((kind, _)) => bail!("不支持的域名规则类型 {:?}", kind),
+            None => Self::Keyword(s),
+        };
+        match &matcher {
+            Self::Suffix(d) | Self::Full(d) | Self::Keyword(d) if d.is_empty() => bail!("域名规则为空"),
+            _ => Ok(matcher),
+        }
+    }
+
+    fn matches(&self, domain: &str) -> bool {
+        match self {
+            Self::Suffix(suffix) => {
+                domain == suffix || domain.strip_suffix(suffix.as_str()).is_some_and(|rest| rest.ends_with('.'))
+            }
+            Self::Full(full) => domain == full,
+            Self::Keyword(keyword) => domain.contains(keyword.as_str()),
+        }
+    }
+}
+
+/// 解析 IP 规则: CIDR、单个 IP 或 `geoip:private`
+fn parse_ip_rule(s: &str) -> Result<Vec<Cidr>> {
+    if s.eq_ignore_ascii_case("geoip:private") {
+        return Ok(private_ranges());
+    }
+    if s.contains(':') && s.split_once(':').is_some_and(|(kind, _)| kind.eq_ignore_ascii_case("geoip")) {
+        bail!("只支持 geoip:private，不支持 {:?}", s);
+    }
+    Ok(vec![Cidr::parse(s)?])
+}
+
+fn private_ranges() -> Vec<Cidr> {
+    PRIVATE_RANGES.iter().map(|r| Cidr::parse(r).expect("built-in range is valid")).collect()
+}
+
+struct Rule {
+    domains: Vec<DomainMatcher>,
+    ips: Vec<Cidr>,
+    block: bool,
+    tag: String,
+    idle_timeout: Option<u64>,
+}
+
+impl Rule {
+    fn verdict(&self, target: &dyn std::fmt::Display) -> Verdict {
+        if self.block {
+            Verdict::Block(format!("{} 命中规则 (outboundTag: {})", target, self.tag))
+        } else {
+            Verdict::Allow
+        }
+    }
+}
+
+/// 目标地址过滤器
+pub struct DestinationFilter {
+    rules: Vec<Rule>,
+    block_private: bool,
+    private: Vec<Cidr>,
+}
+
+impl DestinationFilter {
+    /// 由路由规则构建，规则格式错误时返回带字段路径的错误
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (idx, rule) in config.routing.rules.iter().enumerate() {
+            let field = format!("routing.rules[{}]", idx);
+            let outbound = config
+                .outbounds
+                .iter()
+                .find(|o| o.tag == rule.outbound_tag)
+                .ok_or_else(|| anyhow!("{}.outboundTag: 没有标签为 {:?} 的出站", field, rule.outbound_tag))?;
+            let domains = rule
+                .domain
+                .iter()
+                .flatten()
+                .enumerate()
+                .map(|(i, d)| DomainMatcher::parse(d).map_err(|e| anyhow!("{}.domain[{}]: {}", field, i, e)))
+                .collect::<Result<Vec<_>>>()?;
+            let mut ips = Vec::new();
+            for (i, ip) in rule.ip.iter().flatten().enumerate() {
+                ips.extend(parse_ip_rule(ip).map_err(|e| anyhow!("{}.ip[{}]: {}", field, i, e))?);
+            }
+            rules.push(Rule {
+                domains,
+                ips,
+                block: outbound.protocol == "blackhole",
+                tag: rule.outbound_tag.clone(),
+                idle_timeout: rule.idle_timeout,
+            });
+        }
+        Ok(Self {
+            rules,
+            block_private: config.routing.block_private,
+            private: private_ranges(),
+        })
+    }
+
+    /// 域名目标命中的规则，未命中时由解析出的 IP 决定
+    pub fn check_domain(&self, domain: &str) -> Option<Verdict> {
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+        self.rule_for_domain(&domain).map(|rule| rule.verdict(&domain))
+    }
+
+    /// IP 目标 (字面量或域名解析结果)
+    pub fn check_ip(&self, ip: IpAddr) -> Verdict {
+        let ip = ip.to_canonical();
+        if let Some(rule) = self.rule_for_ip(ip) {
+            return rule.verdict(&ip);
+        }
+        if self.block_private && self.private.iter().any(|c| c.contains(ip)) {
+            return Verdict::Block(format!("{} 是私有地址 (blockPrivate)", ip));
+        }
+        Verdict::Allow
+    }
+
+    /// 拨号前的检查: IP 目标直接判断，域名目标只看域名规则
+    pub fn check(&self, address: &Address) -> Verdict {
+        match address {
+            Address::Ipv4(ip, _) => self.check_ip(IpAddr::V4(*ip)),
+            Address::Ipv6(ip, _) => self.check_ip(IpAddr::V6(*ip)),
+            Address::Domain(domain, _) => self.check_domain(domain).unwrap_or(Verdict::Allow),
+        }
+    }
+
+    /// 首条命中规则指定的闲置超时 (`Some(0)` 为不限制)；域名目标只看域名规则
+    pub fn idle_timeout(&self, address: &Address) -> Option<Duration> {
+        let rule = match address {
+            Address::Domain(domain, _) => self.rule_for_domain(&domain.trim_end_matches('.').to_ascii_lowercase()),
+            Address::Ipv4(ip, _) => self.rule_for_ip(IpAddr::V4(*ip)),
+            Address::Ipv6(ip, _) => self.rule_for_ip(IpAddr::V6(*ip)),
+        };
+        rule.and_then(|rule| rule.idle_timeout).map(Duration::from_secs)
+    }
+
+    /// `domain` 已转为小写并去掉末尾的点
+    fn rule_for_domain(&self, domain: &str) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.domains.iter().any(|m| m.matches(domain)))
+    }
+
+    fn rule_for_ip(&self, ip: IpAddr) -> Option<&Rule> {
+        let ip = ip.to_canonical();
+        self.rules.iter().find(|rule| rule.ips.iter().any(|c| c.contains(ip)))
+    }
+
+    /// 解析后的检查: 域名规则优先，未命中时判断解析出的 IP
+    pub fn check_resolved(&self, address: &Address, ip: IpAddr) -> Verdict {
+        match address {
+            Address::Domain(domain, _) => self.check_domain(domain).unwrap_or_else(|| self.check_ip(ip)),
+            _ => self.check_ip(ip),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(routing: serde_json::Value) -> Result<DestinationFilter> {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "inbounds": [],
+            "outbounds": [
+                { "protocol": "freedom", "tag": "direct" },
+                { "protocol": "blackhole", "tag": "block" }
+            ],
+            "routing": routing
+        }))
+        .unwrap();
+        DestinationFilter::from_config(&config)
+    }
+
+    fn blocked(verdict: Verdict) -> bool {
+        matches!(verdict, Verdict::Block(_))
+    }
+
+    #[test]
+    fn test_cidr() {
+        let cidr = Cidr::parse("10.1.0.0/16").unwrap();
+        assert!(cidr.contains("10.1.255.1".parse().unwrap()));
+        assert!(!cidr.contains("10.2.0.1".parse().unwrap()));
+        // IPv4 映射的 IPv6 地址按 IPv4 匹配
+        assert!(cidr.contains("::ffff:10.1.0.1".parse().unwrap()));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains("8.8.8.8".parse().unwrap()));
+        assert!(Cidr::parse("2001:db8::/32").unwrap().contains("2001:db8:1::1".parse().unwrap()));
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("example.com").is_err());
+    }
+
+    #[test]
+    fn test_block_private_by_default() {
+        let filter = build(serde_json::json!({})).unwrap();
+        for ip in ["127.0.0.1", "10.0.0.1", "169.254.169.254", "192.168.1.1", "::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(blocked(filter.check_ip(ip.parse().unwrap())), "{} not blocked", ip);
+        }
+        assert_eq!(filter.check_ip("8.8.8.8".parse().unwrap()), Verdict::Allow);
+        // 域名目标在解析后按 IP 判断
+        let localhost = Address::Domain("localhost".into(), 80);
+        assert_eq!(filter.check(&localhost), Verdict::Allow);
+        assert!(blocked(filter.check_resolved(&localhost, "127.0.0.1".parse().unwrap())));
+
+        let filter = build(serde_json::json!({ "blockPrivate": false })).unwrap();
+        assert_eq!(filter.check_ip("127.0.0.1".parse().unwrap()), Verdict::Allow);
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let filter = build(serde_json::json!({
+            "rules": [
+                { "type": "field", "ip": ["10.0.5.0/24"], "domain": ["full:intranet.example.com"], "outboundTag": "direct" },
+                { "type": "field", "ip": ["1.1.1.1"], "domain": ["domain:example.com", "keyword:tracker"], "outboundTag": "block" }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(filter.check_ip("10.0.5.9".parse().unwrap()), Verdict::Allow);
+        assert!(blocked(filter.check_ip("10.0.6.9".parse().unwrap())));
+        assert!(blocked(filter.check_ip("1.1.1.1".parse().unwrap())));
+
+        assert_eq!(filter.check_domain("intranet.example.com"), Some(Verdict::Allow));
+        assert!(blocked(filter.check_domain("WWW.Example.com.").unwrap()));
+        assert!(blocked(filter.check_domain("example.com").unwrap()));
+        assert!(blocked(filter.check_domain("ads-tracker.net").unwrap()));
+        assert_eq!(filter.check_domain("notexample.com"), None);
+    }
+
+    #[test]
+    fn test_rule_idle_timeout() {
+        let filter = build(serde_json::json!({
+            "rules": [
+                { "type": "field", "domain": ["domain:ssh.example.com"], "outboundTag": "direct", "idleTimeout": 0 },
+                { "type": "field", "ip": ["203.0.113.0/24"], "outboundTag": "direct", "idleTimeout": 30 },
+                { "type": "field", "domain": ["domain:example.com"], "outboundTag": "direct" }
+            ]
+        }))
+        .unwrap();
+        let domain = |d: &str| Address::Domain(d.to_string(), 22);
+        assert_eq!(filter.idle_timeout(&domain("Git.SSH.example.com")), Some(Duration::ZERO));
+        assert_eq!(filter.idle_timeout(&Address::Ipv4("203.0.113.8".parse().unwrap(), 443)), Some(Duration::from_secs(30)));
+        // 首条命中的规则没有指定时使用全局设置
+        assert_eq!(filter.idle_timeout(&domain("www.example.com")), None);
+        assert_eq!(filter.idle_timeout(&domain("other.net")), None);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let error = |routing| build(routing).err().unwrap().to_string();
+        assert!(error(serde_json::json!({ "rules": [{ "type": "field", "ip": ["geoip:cn"], "outboundTag": "block" }] }))
+            .starts_with("routing.rules[0].ip[0]"));
+        assert!(error(serde_json::json!({ "rules": [{ "type": "field", "domain": ["geosite:cn"], "outboundTag": "block" }] }))
+            .starts_with("routing.rules[0].domain[0]"));
+        assert!(error(serde_json::json!({ "rules": [{ "type": "field", "ip": ["1.1.1.1"], "outboundTag": "nope" }] }))
+            .starts_with("routing.rules[0].outboundTag"));
+    }
+}
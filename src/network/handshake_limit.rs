@@ -0,0 +1,127 @@
+//! 握手阶段的防护: 每个来源 IP 的并发握手上限与统计计数
+//!
+//! 未完成认证的连接只占用有限的槽位，慢速发送 ClientHello 的攻击者
+//! 无法无限占用任务与文件句柄。
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// 握手统计 (供统计层读取)
+#[derive(Debug, Default)]
+pub struct HandshakeStats {
+    /// 握手超时次数
+    pub handshake_timeouts: AtomicU64,
+    /// 因单 IP 并发握手超限而关闭的连接数
+    pub handshake_concurrency_rejects: AtomicU64,
+}
+
+/// 全局握手统计
+pub static HANDSHAKE_STATS: HandshakeStats = HandshakeStats {
+    handshake_timeouts: AtomicU64::new(0),
+    handshake_concurrency_rejects: AtomicU64::new(0),
+};
+
+impl HandshakeStats {
+    pub fn record_timeout(&self) {
+        self.handshake_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_concurrency_reject(&self) {
+        self.handshake_concurrency_rejects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// (handshake_timeouts, handshake_concurrency_rejects)
+    pub fn snapshot(&self) -> (u64, u64) {
+        (
+            self.handshake_timeouts.load(Ordering::Relaxed),
+            self.handshake_concurrency_rejects.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// 每个来源 IP 的并发握手计数 (克隆后共享)
+#[derive(Clone)]
+pub struct HandshakeLimiter {
+    max_per_ip: usize,
+    in_flight: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl HandshakeLimiter {
+    pub fn new(max_per_ip: usize) -> Self {
+        Self {
+            max_per_ip: max_per_ip.max(1),
+            in_flight: Default::default(),
+        }
+    }
+
+    /// 占用一个握手槽位，已达上限时返回 `None` 并计入统计
+    pub fn try_acquire(&self, ip: IpAddr) -> Option<HandshakePermit> {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        let count = in_flight.entry(ip).or_insert(0);
+        if *count >= self.max_per_ip {
+            HANDSHAKE_STATS.record_concurrency_reject();
+            return None;
+        }
+        *count += 1;
+        Some(HandshakePermit {
+            ip,
+            in_flight: self.in_flight.clone(),
+        })
+    }
+
+    /// 该 IP 正在进行的握手数
+    pub fn in_flight(&self, ip: IpAddr) -> usize {
+        let in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        in_flight.get(&ip).copied().unwrap_or(0)
+    }
+}
+
+/// 握手槽位，握手结束 (drop) 时归还
+pub struct HandshakePermit {
+    ip: IpAddr,
+    in_flight: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl Drop for HandshakePermit {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = in_flight.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_per_ip() {
+        let limiter = HandshakeLimiter::new(2);
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let first = limiter.try_acquire(a).unwrap();
+        let _second = limiter.try_acquire(a).unwrap();
+        assert!(limiter.try_acquire(a).is_none());
+        // 其他 IP 不受影响
+        assert!(limiter.try_acquire(b).is_some());
+
+        drop(first);
+        assert_eq!(limiter.in_flight(a), 1);
+        assert!(limiter.try_acquire(a).is_some());
+    }
+
+    #[test]
+    fn test_released_entries_are_removed() {
+        let limiter = HandshakeLimiter::new(1);
+        let ip: IpAddr = "::1".parse().unwrap();
+        drop(limiter.try_acquire(ip).unwrap());
+        assert!(limiter.in_flight.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+//! 双栈目标的并发拨号 (Happy Eyeballs, RFC 8305)
+//!
+//! 域名解析出的 A 与 AAAA 地址按 IPv6 优先、两族交替排列。先向第一个地址发起连接，
+//! 每隔 `try_delay` (或上一个尝试失败时立即) 再向下一个地址发起，使用最先建立的连接，其余尝试随之取消。
+//! 这样 IPv6 路由不通时最多多等一个 `try_delay`，而不是等到系统连接超时。
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// RFC 8305 推荐的尝试间隔
+pub const DEFAULT_TRY_DELAY: Duration = Duration::from_millis(250);
+
+/// 向域名解析出的地址并发拨号
+pub async fn connect(addrs: Vec<SocketAddr>, try_delay: Duration) -> io::Result<TcpStream> {
+    race(interleave(addrs), try_delay, TcpStream::connect).await
+}
+
+/// IPv6 优先，两族交替排列，族内保持解析顺序
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+    let mut ordered = Vec::with_capacity(v6.len() + v4.len());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// 按顺序错开发起连接，返回最先成功的一个；全部失败时返回最后一个错误
+async fn race<F, Fut, T>(addrs: Vec<SocketAddr>, try_delay: Duration, connect: F) -> io::Result<T>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut queue = addrs.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+    let next_attempt = tokio::time::sleep(Duration::ZERO);
+    tokio::pin!(next_attempt);
+
+    loop {
+        tokio::select! {
+            biased;
+            Some(result) = attempts.next(), if !attempts.is_empty() => match result {
+                Ok(stream) => return Ok(stream),
+                Err(e) => {
+                    debug!("Happy Eyeballs: 连接失败: {}", e);
+                    last_error = Some(e);
+                    // 失败后立即尝试下一个地址
+                    next_attempt.as_mut().reset(Instant::now());
+                }
+            },
+            _ = &mut next_attempt, if queue.len() > 0 => {
+                let addr = queue.next().expect("queue is not empty");
+                attempts.push(connect(addr));
+                next_attempt.as_mut().reset(Instant::now() + try_delay);
+            }
+            else => break,
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "没有可用的地址")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_interleave_prefers_ipv6() {
+        let ordered = interleave(vec![
+            addr("192.0.2.1:443"),
+            addr("192.0.2.2:443"),
+            addr("192.0.2.3:443"),
+            addr("[2001:db8::1]:443"),
+        ]);
+        assert_eq!(
+            ordered,
+            vec![addr("[2001:db8::1]:443"), addr("192.0.2.1:443"), addr("192.0.2.2:443"), addr("192.0.2.3:443")]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stalled_ipv6_falls_back_after_delay() {
+        let start = Instant::now();
+        let addrs = vec![addr("[2001:db8::1]:443"), addr("192.0.2.1:443")];
+        let winner = race(addrs, DEFAULT_TRY_DELAY, |addr| async move {
+            if addr.is_ipv6() {
+                std::future::pending::<()>().await;
+            }
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            Ok(addr)
+        })
+        .await
+        .unwrap();
+        assert_eq!(winner, addr("192.0.2.1:443"));
+        assert_eq!(start.elapsed(), DEFAULT_TRY_DELAY + Duration::from_millis(30));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_attempt_starts_next_immediately() {
+        let start = Instant::now();
+        let addrs = vec![addr("[2001:db8::1]:443"), addr("192.0.2.1:443")];
+        let winner = race(addrs, DEFAULT_TRY_DELAY, |addr| async move {
+            if addr.is_ipv6() {
+                return Err(io::Error::from(io::ErrorKind::ConnectionRefused));
+            }
+            Ok(addr)
+        })
+        .await
+        .unwrap();
+        assert_eq!(winner, addr("192.0.2.1:443"));
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        let error = race(vec![addr("192.0.2.1:443")], DEFAULT_TRY_DELAY, |_| async {
+            Err::<(), _>(io::Error::from(io::ErrorKind::ConnectionRefused))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+        assert_eq!(race(Vec::new(), DEFAULT_TRY_DELAY, |_| async { Ok(()) }).await.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,202 @@
+//! 健康检查 / 就绪探测端点
+//!
+//! 供负载均衡与编排系统探测: 所有入站监听器绑定完成 (启用 XDP 时还要挂载成功) 后返回 200，
+//! 启动中与停机排空期间返回 503。
+//! 只响应最简单的 HTTP/1.x 请求，每个连接一个请求。
+
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+/// 读取探测请求的期限
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// 探测请求头的上限
+const MAX_REQUEST_LEN: usize = 4096;
+
+/// 服务的就绪状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Readiness {
+    /// 监听器尚未全部绑定，或 XDP 尚未挂载
+    Starting,
+    Ready,
+    /// 已收到停机信号，正在排空连接
+    Draining,
+}
+
+impl Readiness {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Readiness::Ready,
+            2 => Readiness::Draining,
+            _ => Readiness::Starting,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Readiness::Starting => "starting",
+            Readiness::Ready => "ready",
+            Readiness::Draining => "draining",
+        }
+    }
+}
+
+/// 就绪状态 (克隆后共享)
+#[derive(Clone, Default)]
+pub struct Health {
+    state: Arc<AtomicU8>,
+    /// 尚未完成的启动项 (未绑定的监听器与未挂载的 XDP)
+    pending: Arc<AtomicUsize>,
+}
+
+impl Health {
+    pub fn readiness(&self) -> Readiness {
+        Readiness::from_u8(self.state.load(Ordering::SeqCst))
+    }
+
+    /// 开始启动，等待 `pending` 个启动项完成
+    pub(crate) fn starting(&self, pending: usize) {
+        self.pending.store(pending, Ordering::SeqCst);
+        let state = if pending == 0 { Readiness::Ready } else { Readiness::Starting };
+        self.state.store(state as u8, Ordering::SeqCst);
+    }
+
+    /// 一个监听器绑定完成
+    pub(crate) fn listener_bound(&self) {
+        self.completed();
+    }
+
+    /// XDP 程序已挂载到网卡
+    #[cfg_attr(not(feature = "xdp"), allow(dead_code))]
+    pub(crate) fn xdp_attached(&self) {
+        self.completed();
+    }
+
+    /// 一个启动项完成，全部完成时进入就绪
+    fn completed(&self) {
+        if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // 停机开始后不再回到就绪
+            let _ = self.state.compare_exchange(
+                Readiness::Starting as u8,
+                Readiness::Ready as u8,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            );
+            info!("✅ 服务已就绪");
+        }
+    }
+
+    pub(crate) fn draining(&self) {
+        self.state.store(Readiness::Draining as u8, Ordering::SeqCst);
+    }
+}
+
+/// 在已绑定的 `listener` 上提供健康检查端点，直到任务被取消
+pub async fn serve(listener: TcpListener, health: Health) -> Result<()> {
+    info!("🩺 健康检查端点: http://{}", listener.local_addr()?);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let health = health.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, health.readiness()).await {
+                debug!("健康检查请求处理失败: {}", e);
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, readiness: Readiness) -> Result<()> {
+    let mut buf = Vec::with_capacity(512);
+    let head_read = tokio::time::timeout(REQUEST_TIMEOUT, async {
+        while !buf.windows(4).any(|w| w == b"\r\n\r\n") && buf.len() < MAX_REQUEST_LEN {
+            if stream.read_buf(&mut buf).await? == 0 {
+                break;
+            }
+        }
+        Ok::<(), std::io::Error>(())
+    });
+    head_read.await??;
+
+    let request_line = buf.split(|&b| b == b'\r').next().unwrap_or_default();
+    let method = request_line.split(|&b| b == b' ').next().unwrap_or_default();
+    let (status, body) = match (method, readiness) {
+        (b"GET" | b"HEAD", Readiness::Ready) => ("200 OK", readiness.as_str()),
+        (b"GET" | b"HEAD", _) => ("503 Service Unavailable", readiness.as_str()),
+        _ => ("405 Method Not Allowed", ""),
+    };
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    if method != b"HEAD" {
+        response.push_str(body);
+    }
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness_transitions() {
+        let health = Health::default();
+        health.starting(2);
+        assert_eq!(health.readiness(), Readiness::Starting);
+        health.listener_bound();
+        assert_eq!(health.readiness(), Readiness::Starting);
+        health.listener_bound();
+        assert_eq!(health.readiness(), Readiness::Ready);
+        health.draining();
+        assert_eq!(health.readiness(), Readiness::Draining);
+
+        // 停机开始后绑定完成的监听器不会让状态回到就绪
+        let health = Health::default();
+        health.starting(1);
+        health.draining();
+        health.listener_bound();
+        assert_eq!(health.readiness(), Readiness::Draining);
+    }
+
+    async fn probe(addr: std::net::SocketAddr, method: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("{method} / HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_serve_waits_for_listeners_and_xdp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let health = Health::default();
+        tokio::spawn(serve(listener, health.clone()));
+
+        // 一个监听器 + XDP
+        health.starting(2);
+        assert!(probe(addr, "GET").await.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        health.listener_bound();
+        let response = probe(addr, "GET").await;
+        assert!(response.starts_with("HTTP/1.1 503") && response.ends_with("\r\n\r\nstarting"), "{}", response);
+
+        health.xdp_attached();
+        let response = probe(addr, "GET").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n") && response.ends_with("\r\n\r\nready"), "{}", response);
+        assert!(probe(addr, "POST").await.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+
+        health.draining();
+        assert!(probe(addr, "GET").await.ends_with("\r\n\r\ndraining"));
+    }
+}
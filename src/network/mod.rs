@@ -1,3 +1,20 @@
+pub mod access_log;
 pub mod connection;
+pub mod dns;
+pub mod filter;
+pub mod handshake_limit;
+pub mod happy_eyeballs;
+pub mod health;
+pub mod mux;
+pub mod outbound;
+pub mod rate_limit;
+pub mod sockopt;
 
-pub use connection::ConnectionManager;
+pub use access_log::{AccessEntry, AccessLog};
+pub use connection::{ClientPermit, CloseReason, ConnectionManager, CountingStream, RelayDirection, RelayStats};
+pub use filter::{DestinationFilter, Verdict};
+pub use health::{Health, Readiness};
+pub use handshake_limit::{HandshakeLimiter, HandshakePermit, HANDSHAKE_STATS};
+pub use outbound::{DirectOutbound, Outbound, Socks5Outbound, UdpOutbound};
+pub use rate_limit::{RateLimitRegistry, RateLimiter};
+pub use sockopt::{Keepalive, SocketOptions};
@@ -0,0 +1,639 @@
+//! Mux.Cool 子连接分发
+//!
+//! 一条 VLESS 隧道内承载多个逻辑子连接，每个子连接独立连接上游并转发。
+//! 所有回程帧通过同一个写任务串行写回客户端。
+
+use anyhow::Result;
+use bytes::{Bytes, BytesMut};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::time::{timeout, Duration};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+use super::{Outbound, RateLimiter};
+use crate::handler::log_bittorrent_blocked;
+use crate::protocol::mux::{MuxFrame, MuxNetwork, MuxStatus};
+use crate::protocol::sniffer::is_bittorrent;
+use crate::protocol::vless::Address;
+
+/// 上游连接超时
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// 隧道结束后等待回程帧 (子连接的 End) 写完的期限
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+/// 每个子连接排队等待写往上游的帧数
+const SESSION_QUEUE: usize = 64;
+/// 单帧读取上限，保持在 u16 长度字段以内
+const READ_CHUNK: usize = 16384;
+
+/// 发往子连接的数据 (UDP 可携带逐包目标地址)
+type Payload = (Option<Address>, Bytes);
+
+/// 隧道沿用所属 VLESS 连接的限速与超时设置
+#[derive(Clone, Default)]
+pub struct MuxPolicy {
+    /// 用户限速，隧道内所有子连接共用
+    pub rate_limiter: Option<RateLimiter>,
+    /// 客户端没有发来帧、或 UDP 子连接没有回包达到该时间后结束，`None` 为不限制
+    pub idle_timeout: Option<Duration>,
+    /// 隧道的最长存活时间，`None` 为不限制
+    pub max_lifetime: Option<Duration>,
+    /// 检测每个子连接的首个载荷，BitTorrent 流量直接结束
+    pub block_bittorrent: bool,
+    /// 日志中的用户标识
+    pub user: String,
+}
+
+impl MuxPolicy {
+    fn idle(&self) -> Duration {
+        self.idle_timeout.unwrap_or(Duration::MAX)
+    }
+
+    /// 首个载荷是否需要屏蔽 (限频记录日志)
+    fn blocks(&self, address: &Address, data: &[u8]) -> bool {
+        let blocked = self.block_bittorrent && is_bittorrent(data);
+        if blocked {
+            log_bittorrent_blocked(&self.user, &address.to_string());
+        }
+        blocked
+    }
+}
+
+/// 分发循环持有的子连接
+struct Session {
+    tx: mpsc::Sender<Payload>,
+    address: Address,
+    /// 首个载荷是否已检测过
+    sniffed: bool,
+}
+
+/// 处理 Mux.Cool 隧道，直到客户端关闭、空闲超时或达到最长存活时间
+pub async fn serve_mux<S>(stream: S, initial_data: BytesMut, outbound: Arc<dyn Outbound>, policy: MuxPolicy) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let (frame_tx, mut frame_rx) = mpsc::channel::<MuxFrame>(64);
+
+    // 回程帧写任务
+    let mut write_task = tokio::spawn(async move {
+        let mut out = BytesMut::with_capacity(READ_CHUNK + 64);
+        while let Some(frame) = frame_rx.recv().await {
+            out.clear();
+            if let Err(e) = frame.encode(&mut out) {
+                error!("Mux 帧编码失败: {}", e);
+                continue;
+            }
+            if writer.write_all(&out).await.is_err() || writer.flush().await.is_err() {
+                break;
+            }
+        }
+    }.in_current_span());
+
+    let mut sessions: HashMap<u16, Session> = HashMap::new();
+    let mut buf = initial_data;
+
+    let tunnel = async {
+        loop {
+            while let Some(frame) = MuxFrame::decode(&mut buf)? {
+                handle_frame(frame, &mut sessions, &frame_tx, &outbound, &policy).await;
+            }
+
+            match timeout(policy.idle(), reader.read_buf(&mut buf)).await {
+                Ok(Ok(0)) => break,
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => {
+                    debug!("Mux 隧道空闲超时");
+                    break;
+                }
+            }
+        }
+        Ok(())
+    };
+    let result = match policy.max_lifetime {
+        Some(max_lifetime) => timeout(max_lifetime, tunnel).await.unwrap_or_else(|_| {
+            debug!("Mux 隧道达到最长存活时间 {:?}", max_lifetime);
+            Ok(())
+        }),
+        None => tunnel.await,
+    };
+
+    // 丢弃所有发送端，子连接任务随之结束；写任务在最后一个子连接退出后写完剩余的帧
+    info!("🔀 Mux 隧道结束 (剩余子连接: {})", sessions.len());
+    sessions.clear();
+    drop(frame_tx);
+    if timeout(DRAIN_TIMEOUT, &mut write_task).await.is_err() {
+        debug!("Mux 回程帧未能在 {:?} 内写完，放弃", DRAIN_TIMEOUT);
+        write_task.abort();
+    }
+
+    result
+}
+
+async fn handle_frame(
+    frame: MuxFrame,
+    sessions: &mut HashMap<u16, Session>,
+    frame_tx: &mpsc::Sender<MuxFrame>,
+    outbound: &Arc<dyn Outbound>,
+    policy: &MuxPolicy,
+) {
+    let session_id = frame.session_id;
+    match frame.status {
+        MuxStatus::New => {
+            let Some((network, address)) = frame.target else {
+                return;
+            };
+            debug!("Mux 新建子连接 #{}: {:?} -> {}", session_id, network, address);
+            if frame.data.as_ref().is_some_and(|data| policy.blocks(&address, data)) {
+                let _ = frame_tx.send(MuxFrame::end(session_id, true)).await;
+                return;
+            }
+
+            let (tx, rx) = mpsc::channel::<Payload>(SESSION_QUEUE);
+            let sniffed = frame.data.is_some();
+            if let Some(data) = frame.data {
+                // 新建的通道必有空位
+                let _ = tx.try_send((None, data));
+            }
+            let frame_tx = frame_tx.clone();
+            let session = Session { tx, address: address.clone(), sniffed };
+            match network {
+                MuxNetwork::Tcp => {
+                    let span = info_span!("mux", session = session_id);
+                    tokio::spawn(run_tcp_session(session_id, address, rx, frame_tx, outbound.clone(), policy.clone()).instrument(span));
+                }
+                MuxNetwork::Udp => {
+                    let span = info_span!("mux", session = session_id);
+                    tokio::spawn(run_udp_session(session_id, address, rx, frame_tx, outbound.clone(), policy.clone()).instrument(span));
+                }
+            }
+            sessions.insert(session_id, session);
+        }
+        MuxStatus::Keep => {
+            let Some(data) = frame.data else {
+                return;
+            };
+            match sessions.get_mut(&session_id) {
+                Some(session) => {
+                    if !session.sniffed {
+                        session.sniffed = true;
+                        if policy.blocks(&session.address, &data) {
+                            // 丢弃发送端即结束子连接任务
+                            sessions.remove(&session_id);
+                            let _ = frame_tx.send(MuxFrame::end(session_id, true)).await;
+                            return;
+                        }
+                    }
+                    let target = frame.target.map(|(_, address)| address);
+                    // 不在分发循环里等待: 一个上游消费过慢的子连接不能阻塞隧道内的其他子连接
+                    match session.tx.try_send((target, data)) {
+                        Ok(()) => {}
+                        Err(mpsc::error::TrySendError::Full(_)) => {
+                            debug!("Mux 子连接 #{} 的上游消费过慢 (积压 {} 帧)，重置", session_id, SESSION_QUEUE);
+                            sessions.remove(&session_id);
+                            let _ = frame_tx.send(MuxFrame::end(session_id, true)).await;
+                        }
+                        Err(mpsc::error::TrySendError::Closed(_)) => {
+                            // 子连接已结束，End 帧已由子连接任务发出
+                            sessions.remove(&session_id);
+                        }
+                    }
+                }
+                None => {
+                    // 未知子连接，通知客户端关闭
+                    let _ = frame_tx.send(MuxFrame::end(session_id, true)).await;
+                }
+            }
+        }
+        MuxStatus::End => {
+            if sessions.remove(&session_id).is_some() {
+                debug!("Mux 子连接 #{} 由客户端关闭", session_id);
+            }
+        }
+        MuxStatus::KeepAlive => {}
+    }
+}
+
+async fn run_tcp_session(
+    session_id: u16,
+    address: Address,
+    mut rx: mpsc::Receiver<Payload>,
+    frame_tx: mpsc::Sender<MuxFrame>,
+    outbound: Arc<dyn Outbound>,
+    policy: MuxPolicy,
+) {
+    let remote = match timeout(CONNECT_TIMEOUT, outbound.connect(&address)).await {
+        Ok(Ok(s)) => s,
+        Ok(Err(e)) => {
+            warn!("Mux 子连接 #{} 无法连接到 {}: {}", session_id, address, e);
+            let _ = frame_tx.send(MuxFrame::end(session_id, true)).await;
+            return;
+        }
+        Err(_) => {
+            warn!("Mux 子连接 #{} 连接超时: {}", session_id, address);
+            let _ = frame_tx.send(MuxFrame::end(session_id, true)).await;
+            return;
+        }
+    };
+    let (mut remote_read, mut remote_write) = tokio::io::split(remote);
+
+    // 客户端 -> 上游，通道关闭表示客户端发送了 End
+    let upload = async {
+        while let Some((_, data)) = rx.recv().await {
+            if let Some(limiter) = &policy.rate_limiter {
+                limiter.upload.acquire(data.len()).await;
+            }
+            remote_write.write_all(&data).await?;
+            remote_write.flush().await?;
+        }
+        Ok::<_, std::io::Error>(())
+    };
+
+    // 上游 -> 客户端
+    let download = async {
+        let mut buf = BytesMut::with_capacity(READ_CHUNK);
+        loop {
+            buf.reserve(READ_CHUNK);
+            if remote_read.read_buf(&mut buf).await? == 0 {
+                break;
+            }
+            if let Some(limiter) = &policy.rate_limiter {
+                limiter.download.acquire(buf.len()).await;
+            }
+            let data = buf.split().freeze();
+            if frame_tx.send(MuxFrame::keep(session_id, None, data)).await.is_err() {
+                break;
+            }
+        }
+        Ok::<_, std::io::Error>(())
+    };
+
+    tokio::select! {
+        r = upload => {
+            if r.is_err() {
+                let _ = frame_tx.send(MuxFrame::end(session_id, true)).await;
+            }
+        }
+        r = download => {
+            let _ = frame_tx.send(MuxFrame::end(session_id, r.is_err())).await;
+        }
+    }
+    debug!("Mux 子连接 #{} 结束", session_id);
+}
+
+async fn run_udp_session(
+    session_id: u16,
+    address: Address,
+    mut rx: mpsc::Receiver<Payload>,
+    frame_tx: mpsc::Sender<MuxFrame>,
+    outbound: Arc<dyn Outbound>,
+    policy: MuxPolicy,
+) {
+    let socket = match outbound.bind_udp().await {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("🚫 Mux 子连接 #{} 拒绝 UDP {}: {}", session_id, address, e);
+            let _ = frame_tx.send(MuxFrame::end(session_id, true)).await;
+            return;
+        }
+    };
+    let default_target = match socket.resolve(&address).await {
+        Ok(target) => target,
+        Err(e) => {
+            warn!("Mux 子连接 #{} 无法解析 UDP 目标 {}: {}", session_id, address, e);
+            let _ = frame_tx.send(MuxFrame::end(session_id, true)).await;
+            return;
+        }
+    };
+
+    let upload = async {
+        while let Some((target, data)) = rx.recv().await {
+            let dest = match target {
+                Some(addr) => match socket.resolve(&addr).await {
+                    Ok(dest) => dest,
+                    Err(e) => {
+                        debug!("Mux 子连接 #{} 丢弃发往 {} 的 UDP 包: {}", session_id, addr, e);
+                        continue;
+                    }
+                },
+                None => default_target,
+            };
+            if let Some(limiter) = &policy.rate_limiter {
+                limiter.upload.acquire(data.len()).await;
+            }
+            if socket.send_to(&data, dest).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    let download = async {
+        let mut buf = vec![0u8; READ_CHUNK];
+        while let Ok(Ok((n, from))) = timeout(policy.idle(), socket.recv_from(&mut buf)).await {
+            if let Some(limiter) = &policy.rate_limiter {
+                limiter.download.acquire(n).await;
+            }
+            let data = Bytes::copy_from_slice(&buf[..n]);
+            let target = Some((MuxNetwork::Udp, Address::from(from)));
+            if frame_tx.send(MuxFrame::keep(session_id, target, data)).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = upload => {}
+        _ = download => {
+            let _ = frame_tx.send(MuxFrame::end(session_id, false)).await;
+        }
+    }
+    debug!("Mux UDP 子连接 #{} 结束", session_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{DirectOutbound, SocketOptions};
+    use std::net::Ipv4Addr;
+    use tokio::net::TcpListener;
+
+    async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut BytesMut) -> MuxFrame {
+        loop {
+            if let Some(frame) = MuxFrame::decode(buf).unwrap() {
+                return frame;
+            }
+            assert!(reader.read_buf(buf).await.unwrap() > 0, "连接意外关闭");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_two_sessions_relayed_independently() {
+        // 两个上游 echo 服务，各自加上不同前缀以区分
+        let mut targets = Vec::new();
+        for tag in [b'A', b'B'] {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(async move {
+                let (mut s, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 64];
+                let n = s.read(&mut buf).await.unwrap();
+                let mut reply = vec![tag];
+                reply.extend_from_slice(&buf[..n]);
+                s.write_all(&reply).await.unwrap();
+            });
+            targets.push(Address::Ipv4(Ipv4Addr::LOCALHOST, port));
+        }
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(serve_mux(server, BytesMut::new(), Arc::new(DirectOutbound::new(SocketOptions::default())), MuxPolicy::default()));
+        let (mut client_read, mut client_write) = tokio::io::split(client);
+
+        let mut out = BytesMut::new();
+        for (i, target) in targets.iter().enumerate() {
+            MuxFrame {
+                session_id: i as u16 + 1,
+                status: MuxStatus::New,
+                option: 0,
+                target: Some((MuxNetwork::Tcp, target.clone())),
+                data: Some(Bytes::from(format!("hi{}", i))),
+            }
+            .encode(&mut out)
+            .unwrap();
+        }
+        client_write.write_all(&out).await.unwrap();
+
+        let mut replies = HashMap::new();
+        let mut ended = 0;
+        let mut buf = BytesMut::new();
+        while ended < 2 {
+            let frame = read_frame(&mut client_read, &mut buf).await;
+            match frame.status {
+                MuxStatus::Keep => {
+                    replies.insert(frame.session_id, frame.data.unwrap());
+                }
+                MuxStatus::End => ended += 1,
+                _ => panic!("unexpected frame {:?}", frame),
+            }
+        }
+        assert_eq!(replies[&1], Bytes::from_static(b"Ahi0"));
+        assert_eq!(replies[&2], Bytes::from_static(b"Bhi1"));
+    }
+
+    #[tokio::test]
+    async fn test_keep_for_unknown_session_is_ended() {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(serve_mux(server, BytesMut::new(), Arc::new(DirectOutbound::new(SocketOptions::default())), MuxPolicy::default()));
+        let (mut client_read, mut client_write) = tokio::io::split(client);
+
+        let mut out = BytesMut::new();
+        MuxFrame::keep(7, None, Bytes::from_static(b"x")).encode(&mut out).unwrap();
+        client_write.write_all(&out).await.unwrap();
+
+        let mut buf = BytesMut::new();
+        let frame = read_frame(&mut client_read, &mut buf).await;
+        assert_eq!(frame, MuxFrame::end(7, true));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_session_does_not_block_others() {
+        // 接受连接但从不读取的上游
+        let stalled = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stalled_port = stalled.local_addr().unwrap().port();
+        let _hold = tokio::spawn(async move {
+            let (s, _) = stalled.accept().await.unwrap();
+            std::future::pending::<()>().await;
+            drop(s);
+        });
+        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_port = echo.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut s, _) = echo.accept().await.unwrap();
+            let mut buf = [0u8; 64];
+            let n = s.read(&mut buf).await.unwrap();
+            s.write_all(&buf[..n]).await.unwrap();
+        });
+
+        let (client, server) = tokio::io::duplex(1 << 20);
+        tokio::spawn(serve_mux(server, BytesMut::new(), Arc::new(DirectOutbound::new(SocketOptions::default())), MuxPolicy::default()));
+        let (mut client_read, mut client_write) = tokio::io::split(client);
+
+        let new = |session_id, port| MuxFrame {
+            session_id,
+            status: MuxStatus::New,
+            option: 0,
+            target: Some((MuxNetwork::Tcp, Address::Ipv4(Ipv4Addr::LOCALHOST, port))),
+            data: None,
+        };
+        // 隧道被阻塞时写入会停住，放在单独的任务里，由下面的读取超时发现
+        tokio::spawn(async move {
+            let mut out = BytesMut::new();
+            new(1, stalled_port).encode(&mut out).unwrap();
+            client_write.write_all(&out).await.unwrap();
+            // 远超内核 socket 缓冲与子连接队列的上传
+            let chunk = Bytes::from(vec![0x42; READ_CHUNK]);
+            for _ in 0..1024 {
+                out.clear();
+                MuxFrame::keep(1, None, chunk.clone()).encode(&mut out).unwrap();
+                client_write.write_all(&out).await.unwrap();
+            }
+            out.clear();
+            new(2, echo_port).encode(&mut out).unwrap();
+            MuxFrame::keep(2, None, Bytes::from_static(b"ping")).encode(&mut out).unwrap();
+            client_write.write_all(&out).await.unwrap();
+            // 保持上行打开，隧道不会因 EOF 提前结束
+            std::future::pending::<()>().await;
+        });
+
+        let mut buf = BytesMut::new();
+        let (mut reset, mut echoed) = (false, false);
+        while !(reset && echoed) {
+            let frame = tokio::time::timeout(Duration::from_secs(5), read_frame(&mut client_read, &mut buf))
+                .await
+                .expect("tunnel is blocked");
+            match (frame.session_id, frame.status) {
+                (1, MuxStatus::End) => reset = true,
+                (2, MuxStatus::Keep) => {
+                    assert_eq!(frame.data.unwrap(), Bytes::from_static(b"ping"));
+                    echoed = true;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pending_end_frames_written_after_tunnel_closes() {
+        // 没有监听的端口: 子连接连接失败后发出 End
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        let (client, server) = tokio::io::duplex(4096);
+        let tunnel = tokio::spawn(serve_mux(server, BytesMut::new(), Arc::new(DirectOutbound::new(SocketOptions::default())), MuxPolicy::default()));
+        let (mut client_read, mut client_write) = tokio::io::split(client);
+
+        let mut out = BytesMut::new();
+        MuxFrame {
+            session_id: 1,
+            status: MuxStatus::New,
+            option: 0,
+            target: Some((MuxNetwork::Tcp, Address::Ipv4(Ipv4Addr::LOCALHOST, port))),
+            data: None,
+        }
+        .encode(&mut out)
+        .unwrap();
+        client_write.write_all(&out).await.unwrap();
+        // 客户端随即关闭上行，隧道先于子连接结束
+        client_write.shutdown().await.unwrap();
+
+        let mut buf = BytesMut::new();
+        let frame = read_frame(&mut client_read, &mut buf).await;
+        assert_eq!(frame, MuxFrame::end(1, true));
+        tunnel.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sub_connection_download_is_rate_limited() {
+        const TOTAL: usize = 64 * 1024;
+        const RATE: u64 = 256 * 1024;
+        const BURST: u64 = 16 * 1024;
+
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut s, _) = upstream.accept().await.unwrap();
+            s.write_all(&vec![7u8; TOTAL]).await.unwrap();
+        });
+
+        let (client, server) = tokio::io::duplex(TOTAL * 2);
+        let policy = MuxPolicy { rate_limiter: Some(RateLimiter::new(RATE, BURST)), ..Default::default() };
+        tokio::spawn(serve_mux(server, BytesMut::new(), Arc::new(DirectOutbound::new(SocketOptions::default())), policy));
+        let (mut client_read, mut client_write) = tokio::io::split(client);
+
+        let start = tokio::time::Instant::now();
+        let mut out = BytesMut::new();
+        MuxFrame {
+            session_id: 1,
+            status: MuxStatus::New,
+            option: 0,
+            target: Some((MuxNetwork::Tcp, Address::Ipv4(Ipv4Addr::LOCALHOST, port))),
+            data: None,
+        }
+        .encode(&mut out)
+        .unwrap();
+        client_write.write_all(&out).await.unwrap();
+
+        let mut received = 0;
+        let mut buf = BytesMut::new();
+        while received < TOTAL {
+            let frame = read_frame(&mut client_read, &mut buf).await;
+            assert_eq!(frame.status, MuxStatus::Keep, "unexpected frame {:?}", frame);
+            received += frame.data.map_or(0, |data| data.len());
+        }
+        let elapsed = start.elapsed();
+
+        // 子连接与普通 TCP 转发一样按用户速率发送: (64K - 16K) / 256K/s = 187.5ms
+        let expected = Duration::from_secs_f64((TOTAL as u64 - BURST) as f64 / RATE as f64);
+        assert!(elapsed >= expected, "elapsed {:?} < expected {:?}", elapsed, expected);
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_closes_at_max_lifetime() {
+        const LIFETIME: Duration = Duration::from_millis(300);
+
+        let (client, server) = tokio::io::duplex(4096);
+        let policy = MuxPolicy { max_lifetime: Some(LIFETIME), ..Default::default() };
+        let start = tokio::time::Instant::now();
+        let tunnel = tokio::spawn(serve_mux(server, BytesMut::new(), Arc::new(DirectOutbound::new(SocketOptions::default())), policy));
+
+        timeout(Duration::from_secs(5), tunnel).await.expect("tunnel outlived max lifetime").unwrap().unwrap();
+        assert!(start.elapsed() >= LIFETIME);
+        drop(client);
+    }
+
+    #[tokio::test]
+    async fn test_bittorrent_sub_connections_are_ended() {
+        let mut handshake = b"\x13BitTorrent protocol".to_vec();
+        handshake.extend_from_slice(&[0u8; 48]);
+        let handshake = Bytes::from(handshake);
+
+        let untouched = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let untouched_port = untouched.local_addr().unwrap().port();
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        let received = tokio::spawn(async move {
+            let (mut s, _) = upstream.accept().await.unwrap();
+            let mut buf = Vec::new();
+            s.read_to_end(&mut buf).await.unwrap();
+            buf
+        });
+
+        let (client, server) = tokio::io::duplex(4096);
+        let policy = MuxPolicy { block_bittorrent: true, user: "test".into(), ..Default::default() };
+        tokio::spawn(serve_mux(server, BytesMut::new(), Arc::new(DirectOutbound::new(SocketOptions::default())), policy));
+        let (mut client_read, mut client_write) = tokio::io::split(client);
+
+        // 首包随 New 帧到达: 不连接上游
+        let new = |session_id, port, data| MuxFrame {
+            session_id,
+            status: MuxStatus::New,
+            option: 0,
+            target: Some((MuxNetwork::Tcp, Address::Ipv4(Ipv4Addr::LOCALHOST, port))),
+            data,
+        };
+        let mut out = BytesMut::new();
+        new(1, untouched_port, Some(handshake.clone())).encode(&mut out).unwrap();
+        client_write.write_all(&out).await.unwrap();
+        let mut buf = BytesMut::new();
+        assert_eq!(read_frame(&mut client_read, &mut buf).await, MuxFrame::end(1, true));
+        assert!(timeout(Duration::from_millis(200), untouched.accept()).await.is_err());
+
+        // 首包随 Keep 帧到达: 不转发给已建立的上游
+        out.clear();
+        new(2, upstream_port, None).encode(&mut out).unwrap();
+        MuxFrame::keep(2, None, handshake).encode(&mut out).unwrap();
+        client_write.write_all(&out).await.unwrap();
+        assert_eq!(read_frame(&mut client_read, &mut buf).await, MuxFrame::end(2, true));
+        let received = timeout(Duration::from_secs(5), received).await.unwrap().unwrap();
+        assert!(received.is_empty());
+    }
+}
@@ -0,0 +1,688 @@
+//! 出站拨号
+//!
+//! 所有到目标的 TCP 连接和 UDP 转发都经由 `Outbound` 建立。`freedom` 直连目标，
+//! `socks` 通过上游 SOCKS5 代理 (RFC 1928 / RFC 1929) 转发，
+//! `vless` 经 Reality 隧道连接上游的 xray-lite / xray-core。目前只有 `freedom` 支持 UDP。
+
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::{TcpStream, UdpSocket};
+use tracing::warn;
+use uuid::Uuid;
+
+use super::dns::DnsCache;
+use super::filter::{DestinationFilter, Verdict};
+use super::happy_eyeballs;
+use super::sockopt::SocketOptions;
+use crate::config::{Config, Security, SocksUser};
+use crate::protocol::vless::{Address, Command, VlessRequest};
+use crate::server::AsyncStream;
+use crate::transport::reality::{RealityClient, RealityClientConfig};
+
+/// 出站拨号器
+pub trait Outbound: Send + Sync {
+    /// 建立到目标地址的连接
+    fn connect<'a>(&'a self, address: &'a Address) -> BoxFuture<'a, Result<Box<dyn AsyncStream>>>;
+
+    /// 打开 UDP 转发，不支持 UDP 的出站返回错误
+    fn bind_udp(&self) -> BoxFuture<'_, Result<UdpOutbound>> {
+        Box::pin(async { Err(anyhow!("出站不支持 UDP")) })
+    }
+
+    /// 目标地址过滤器
+    fn filter(&self) -> Option<&DestinationFilter> {
+        None
+    }
+}
+
+/// 根据配置创建默认出站 (第一个出站)，拨号前按路由规则过滤目标
+pub fn from_config(config: &Config, socket: SocketOptions) -> Result<Arc<dyn Outbound>> {
+    let filter = Arc::new(DestinationFilter::from_config(config)?);
+    let inner = dialer_from_config(config, socket, &filter)?;
+    Ok(Arc::new(FilteredOutbound { inner, filter }))
+}
+
+fn dialer_from_config(config: &Config, socket: SocketOptions, filter: &Arc<DestinationFilter>) -> Result<Arc<dyn Outbound>> {
+    let outbound = config
+        .outbounds
+        .first()
+        .ok_or_else(|| anyhow!("至少需要一个出站配置"))?;
+
+    match outbound.protocol.as_str() {
+        "freedom" => {
+            let settings = outbound.freedom_settings()?;
+            Ok(Arc::new(
+                DirectOutbound::new(socket)
+                    .with_happy_eyeballs(settings.happy_eyeballs.try_delay())
+                    .with_dns(DnsCache::from_config(&config.dns)?)
+                    .with_filter(filter.clone()),
+            ))
+        }
+        "socks" => {
+            let settings = outbound.socks_settings()?;
+            let server = settings
+                .servers
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("socks 出站缺少服务器"))?;
+            let mut socks = Socks5Outbound::new(format!("{}:{}", server.address, server.port), socket);
+            if let Some(SocksUser { user, pass }) = server.users.into_iter().next() {
+                socks = socks.with_credentials(user, pass);
+            }
+            Ok(Arc::new(socks))
+        }
+        "vless" => {
+            let settings = outbound.vless_settings()?;
+            let server = settings
+                .vnext
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("vless 出站缺少服务器"))?;
+            let user = server.users.first().ok_or_else(|| anyhow!("vless 出站缺少用户"))?;
+            let uuid = Uuid::parse_str(&user.id)?;
+            let reality = match &outbound.stream_settings {
+                Some(stream) if matches!(stream.security, Security::Reality) => stream
+                    .reality_settings
+                    .clone()
+                    .ok_or_else(|| anyhow!("vless 出站缺少 realitySettings"))?,
+                _ => return Err(anyhow!("vless 出站只支持 Reality")),
+            };
+            let reality = RealityClient::new(RealityClientConfig {
+                server_name: reality.server_name,
+                public_key: reality.public_key,
+                short_id: reality.short_id,
+                fingerprint: reality.fingerprint,
+            })?;
+            Ok(Arc::new(VlessRealityOutbound::new(
+                format!("{}:{}", server.address, server.port),
+                uuid,
+                reality,
+                socket,
+            )))
+        }
+        other => Err(anyhow!("不支持的出站协议: {}", other)),
+    }
+}
+
+/// 拦截被路由规则阻止的目标，阻止原因写入日志并作为连接错误返回
+struct FilteredOutbound {
+    inner: Arc<dyn Outbound>,
+    filter: Arc<DestinationFilter>,
+}
+
+impl Outbound for FilteredOutbound {
+    fn connect<'a>(&'a self, address: &'a Address) -> BoxFuture<'a, Result<Box<dyn AsyncStream>>> {
+        if let Verdict::Block(reason) = self.filter.check(address) {
+            warn!("🚫 阻止连接: {}", reason);
+            return Box::pin(async move { Err(anyhow!("目标被阻止: {}", reason)) });
+        }
+        self.inner.connect(address)
+    }
+
+    fn bind_udp(&self) -> BoxFuture<'_, Result<UdpOutbound>> {
+        self.inner.bind_udp()
+    }
+
+    fn filter(&self) -> Option<&DestinationFilter> {
+        Some(&self.filter)
+    }
+}
+
+/// 直连目标 (freedom)
+pub struct DirectOutbound {
+    socket: SocketOptions,
+    /// 域名目标的 Happy Eyeballs 尝试间隔，`None` 时按解析顺序逐个连接
+    happy_eyeballs: Option<Duration>,
+    dns: Arc<DnsCache>,
+    /// 检查域名解析出的地址
+    filter: Option<Arc<DestinationFilter>>,
+}
+
+impl DirectOutbound {
+    pub fn new(socket: SocketOptions) -> Self {
+        Self {
+            socket,
+            happy_eyeballs: Some(happy_eyeballs::DEFAULT_TRY_DELAY),
+            dns: Arc::new(DnsCache::from_config(&Default::default()).expect("default DNS config is valid")),
+            filter: None,
+        }
+    }
+
+    /// 丢弃域名解析结果中被过滤器阻止的地址
+    pub fn with_filter(mut self, filter: Arc<DestinationFilter>) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// 使用指定的解析缓存
+    pub fn with_dns(mut self, dns: DnsCache) -> Self {
+        self.dns = Arc::new(dns);
+        self
+    }
+
+    /// 设置 Happy Eyeballs 尝试间隔，`None` 关闭
+    pub fn with_happy_eyeballs(mut self, try_delay: Option<Duration>) -> Self {
+        self.happy_eyeballs = try_delay;
+        self
+    }
+}
+
+impl Outbound for DirectOutbound {
+    fn connect<'a>(&'a self, address: &'a Address) -> BoxFuture<'a, Result<Box<dyn AsyncStream>>> {
+        Box::pin(async move {
+            let stream = match address {
+                Address::Domain(..) => {
+                    let addrs = resolve_allowed(&self.dns, self.filter.as_deref(), address).await?;
+                    match self.happy_eyeballs {
+                        Some(try_delay) => happy_eyeballs::connect(addrs, try_delay).await?,
+                        None => TcpStream::connect(&addrs[..]).await?,
+                    }
+                }
+                _ => TcpStream::connect(address.to_string()).await?,
+            };
+            self.socket.apply(&stream);
+            Ok(Box::new(stream) as Box<dyn AsyncStream>)
+        })
+    }
+
+    fn bind_udp(&self) -> BoxFuture<'_, Result<UdpOutbound>> {
+        Box::pin(async move {
+            let socket = socket2::Socket::from(std::net::UdpSocket::bind("0.0.0.0:0")?);
+            // 加大收发缓冲区，承受 QUIC / 视频流的突发
+            let _ = socket.set_recv_buffer_size(UDP_SOCKET_BUFFER);
+            let _ = socket.set_send_buffer_size(UDP_SOCKET_BUFFER);
+            socket.set_nonblocking(true)?;
+            Ok(UdpOutbound {
+                socket: UdpSocket::from_std(socket.into())?,
+                dns: self.dns.clone(),
+                filter: self.filter.clone(),
+            })
+        })
+    }
+}
+
+/// 直连 UDP socket 的收发缓冲区大小
+const UDP_SOCKET_BUFFER: usize = 4 * 1024 * 1024;
+
+/// 解析目标地址并丢弃被过滤器阻止的结果，全部被阻止时返回错误
+async fn resolve_allowed(dns: &DnsCache, filter: Option<&DestinationFilter>, address: &Address) -> Result<Vec<SocketAddr>> {
+    let mut addrs: Vec<SocketAddr> = match address {
+        Address::Domain(host, port) => dns.resolve(host).await?.iter().map(|&ip| SocketAddr::new(ip, *port)).collect(),
+        Address::Ipv4(ip, port) => vec![SocketAddr::new((*ip).into(), *port)],
+        Address::Ipv6(ip, port) => vec![SocketAddr::new((*ip).into(), *port)],
+    };
+    if let Some(filter) = filter {
+        let mut blocked = None;
+        addrs.retain(|addr| match filter.check_resolved(address, addr.ip()) {
+            Verdict::Allow => true,
+            Verdict::Block(reason) => {
+                blocked.get_or_insert(reason);
+                false
+            }
+        });
+        if let (true, Some(reason)) = (addrs.is_empty(), blocked) {
+            warn!("🚫 阻止连接: {} 解析为 {}", address, reason);
+            return Err(anyhow!("目标被阻止: {} 解析为 {}", address, reason));
+        }
+    }
+    Ok(addrs)
+}
+
+/// 出站的 UDP socket: 目标经出站的解析缓存解析，并按路由规则过滤
+pub struct UdpOutbound {
+    socket: UdpSocket,
+    dns: Arc<DnsCache>,
+    filter: Option<Arc<DestinationFilter>>,
+}
+
+impl UdpOutbound {
+    /// 解析目标地址，取未被阻止的地址；socket 绑定在 IPv4 上，优先取 IPv4 地址
+    pub async fn resolve(&self, address: &Address) -> Result<SocketAddr> {
+        let addrs = resolve_allowed(&self.dns, self.filter.as_deref(), address).await?;
+        addrs
+            .iter()
+            .find(|addr| addr.is_ipv4())
+            .or(addrs.first())
+            .copied()
+            .ok_or_else(|| anyhow!("无法解析 UDP 目标: {}", address))
+    }
+
+    pub async fn send_to(&self, data: &[u8], target: SocketAddr) -> io::Result<usize> {
+        self.socket.send_to(data, target).await
+    }
+
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.socket.recv_from(buf).await
+    }
+}
+
+/// 经由上游 SOCKS5 代理连接目标 (socks)
+pub struct Socks5Outbound {
+    server: String,
+    credentials: Option<(String, String)>,
+    socket: SocketOptions,
+}
+
+impl Socks5Outbound {
+    /// `server` 为代理地址 (host:port)
+    pub fn new(server: impl Into<String>, socket: SocketOptions) -> Self {
+        Self {
+            server: server.into(),
+            credentials: None,
+            socket,
+        }
+    }
+
+    /// 使用用户名/密码认证
+    pub fn with_credentials(mut self, user: impl Into<String>, pass: impl Into<String>) -> Self {
+        self.credentials = Some((user.into(), pass.into()));
+        self
+    }
+
+    async fn handshake(&self, stream: &mut TcpStream, address: &Address) -> Result<()> {
+        // 方法协商
+        let method = if self.credentials.is_some() { 0x02 } else { 0x00 };
+        stream.write_all(&[0x05, 0x01, method]).await?;
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != 0x05 {
+            return Err(anyhow!("SOCKS5 代理版本错误: {}", reply[0]));
+        }
+        if reply[1] != method {
+            return Err(anyhow!("SOCKS5 代理不接受认证方式 {:#04x}", method));
+        }
+
+        // 用户名/密码认证 (RFC 1929)
+        if let Some((user, pass)) = &self.credentials {
+            let mut request = vec![0x01, user.len() as u8];
+            request.extend_from_slice(user.as_bytes());
+            request.push(pass.len() as u8);
+            request.extend_from_slice(pass.as_bytes());
+            stream.write_all(&request).await?;
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0x00 {
+                return Err(anyhow!("SOCKS5 用户名/密码认证失败"));
+            }
+        }
+
+        // CONNECT 请求
+        let mut request = vec![0x05, 0x01, 0x00];
+        match address {
+            Address::Ipv4(ip, port) => {
+                request.push(0x01);
+                request.extend_from_slice(&ip.octets());
+                request.extend_from_slice(&port.to_be_bytes());
+            }
+            Address::Ipv6(ip, port) => {
+                request.push(0x04);
+                request.extend_from_slice(&ip.octets());
+                request.extend_from_slice(&port.to_be_bytes());
+            }
+            Address::Domain(domain, port) => {
+                if domain.len() > 255 {
+                    return Err(anyhow!("域名过长: {}", domain));
+                }
+                request.push(0x03);
+                request.push(domain.len() as u8);
+                request.extend_from_slice(domain.as_bytes());
+                request.extend_from_slice(&port.to_be_bytes());
+            }
+        }
+        stream.write_all(&request).await?;
+
+        let mut head = [0u8; 4];
+        stream.read_exact(&mut head).await?;
+        if head[1] != 0x00 {
+            return Err(anyhow!("SOCKS5 CONNECT 失败 (REP={:#04x})", head[1]));
+        }
+
+        // 跳过 BND.ADDR 和 BND.PORT
+        let addr_len = match head[3] {
+            0x01 => 4,
+            0x04 => 16,
+            0x03 => stream.read_u8().await? as usize,
+            atyp => return Err(anyhow!("SOCKS5 应答地址类型未知: {}", atyp)),
+        };
+        let mut bound = vec![0u8; addr_len + 2];
+        stream.read_exact(&mut bound).await?;
+        Ok(())
+    }
+}
+
+impl Outbound for Socks5Outbound {
+    fn connect<'a>(&'a self, address: &'a Address) -> BoxFuture<'a, Result<Box<dyn AsyncStream>>> {
+        Box::pin(async move {
+            let mut stream = TcpStream::connect(&self.server).await?;
+            self.socket.apply(&stream);
+            self.handshake(&mut stream, address).await?;
+            Ok(Box::new(stream) as Box<dyn AsyncStream>)
+        })
+    }
+}
+
+/// 经 Reality 隧道连接上游 VLESS 服务器 (vless + reality)
+pub struct VlessRealityOutbound {
+    server: String,
+    uuid: Uuid,
+    reality: RealityClient,
+    socket: SocketOptions,
+}
+
+impl VlessRealityOutbound {
+    /// `server` 为上游地址 (host:port)
+    pub fn new(server: impl Into<String>, uuid: Uuid, reality: RealityClient, socket: SocketOptions) -> Self {
+        Self {
+            server: server.into(),
+            uuid,
+            reality,
+            socket,
+        }
+    }
+}
+
+impl Outbound for VlessRealityOutbound {
+    fn connect<'a>(&'a self, address: &'a Address) -> BoxFuture<'a, Result<Box<dyn AsyncStream>>> {
+        Box::pin(async move {
+            let stream = TcpStream::connect(&self.server).await?;
+            self.socket.apply(&stream);
+            let mut tls = self.reality.connect(stream).await?;
+
+            let request = VlessRequest {
+                version: 0,
+                uuid: self.uuid,
+                command: Command::Tcp,
+                address: address.clone(),
+                addon_length: 0,
+                flow: String::new(),
+                mux_session_id: None,
+            };
+            // 立即发出请求头: 上游收到后才会连接目标，目标可能先说话
+            tls.write_all(&request.encode()?).await?;
+            tls.flush().await?;
+            Ok(Box::new(VlessClientStream::new(tls)) as Box<dyn AsyncStream>)
+        })
+    }
+}
+
+/// VLESS 响应头的解析进度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseHeader {
+    Version,
+    AddonsLength,
+    Addons(usize),
+    Done,
+}
+
+/// 读取时去掉 VLESS 响应头 (版本 + 附加数据) 的流
+///
+/// xray-core 把响应头和第一段下行数据一起发出，所以只能在读取时剥离。
+struct VlessClientStream<S> {
+    inner: S,
+    response: ResponseHeader,
+}
+
+impl<S> VlessClientStream<S> {
+    fn new(inner: S) -> Self {
+        Self {
+            inner,
+            response: ResponseHeader::Version,
+        }
+    }
+
+    /// 消费 `data` 开头属于响应头的字节，返回消费的长度
+    fn consume_header(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < data.len() && self.response != ResponseHeader::Done {
+            self.response = match self.response {
+                ResponseHeader::Version => {
+                    if data[n] != 0 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "VLESS 响应版本错误"));
+                    }
+                    n += 1;
+                    ResponseHeader::AddonsLength
+                }
+                ResponseHeader::AddonsLength => {
+                    n += 1;
+                    ResponseHeader::Addons(data[n - 1] as usize)
+                }
+                ResponseHeader::Addons(remaining) => {
+                    let skip = remaining.min(data.len() - n);
+                    n += skip;
+                    ResponseHeader::Addons(remaining - skip)
+                }
+                ResponseHeader::Done => ResponseHeader::Done,
+            };
+            if self.response == ResponseHeader::Addons(0) {
+                self.response = ResponseHeader::Done;
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for VlessClientStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.response == ResponseHeader::Done {
+                return Pin::new(&mut this.inner).poll_read(cx, buf);
+            }
+
+            let start = buf.filled().len();
+            ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+            let end = buf.filled().len();
+            if end == start {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+
+            let consumed = match this.consume_header(&buf.filled()[start..]) {
+                Ok(consumed) => consumed,
+                Err(e) => {
+                    buf.set_filled(start);
+                    return Poll::Ready(Err(e));
+                }
+            };
+            buf.filled_mut().copy_within(start + consumed..end, start);
+            buf.set_filled(end - consumed);
+            if end - consumed > start {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for VlessClientStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// 只支持 CONNECT 的 SOCKS5 桩服务器，返回收到的 CONNECT 请求
+    async fn socks5_stub(listener: TcpListener, credentials: Option<(&str, &str)>) -> Vec<u8> {
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        let mut greeting = [0u8; 3];
+        stream.read_exact(&mut greeting).await.unwrap();
+        let method = if credentials.is_some() { 0x02 } else { 0x00 };
+        assert_eq!(greeting, [0x05, 0x01, method]);
+        stream.write_all(&[0x05, method]).await.unwrap();
+
+        if let Some((user, pass)) = credentials {
+            let mut head = [0u8; 2];
+            stream.read_exact(&mut head).await.unwrap();
+            let mut got_user = vec![0u8; head[1] as usize];
+            stream.read_exact(&mut got_user).await.unwrap();
+            let mut got_pass = vec![0u8; stream.read_u8().await.unwrap() as usize];
+            stream.read_exact(&mut got_pass).await.unwrap();
+            assert_eq!((got_user.as_slice(), got_pass.as_slice()), (user.as_bytes(), pass.as_bytes()));
+            stream.write_all(&[0x01, 0x00]).await.unwrap();
+        }
+
+        let mut request = vec![0u8; 5];
+        stream.read_exact(&mut request).await.unwrap();
+        let rest = match request[3] {
+            0x01 => 3 + 2,
+            0x03 => request[4] as usize + 2,
+            0x04 => 15 + 2,
+            atyp => panic!("unexpected ATYP {}", atyp),
+        };
+        let mut tail = vec![0u8; rest];
+        stream.read_exact(&mut tail).await.unwrap();
+        request.extend_from_slice(&tail);
+
+        stream
+            .write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0x04, 0x38])
+            .await
+            .unwrap();
+
+        // 回显一次数据
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(&buf).await.unwrap();
+        request
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_domain() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let stub = tokio::spawn(socks5_stub(listener, None));
+
+        let outbound = Socks5Outbound::new(server, SocketOptions::default());
+        let address = Address::Domain("example.com".to_string(), 443);
+        let mut stream = outbound.connect(&address).await.unwrap();
+        stream.write_all(b"ping").await.unwrap();
+        let mut echo = [0u8; 4];
+        stream.read_exact(&mut echo).await.unwrap();
+        assert_eq!(&echo, b"ping");
+
+        let mut expected = vec![0x05, 0x01, 0x00, 0x03, 11];
+        expected.extend_from_slice(b"example.com");
+        expected.extend_from_slice(&443u16.to_be_bytes());
+        assert_eq!(stub.await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_ipv4_with_credentials() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let stub = tokio::spawn(socks5_stub(listener, Some(("alice", "secret"))));
+
+        let outbound = Socks5Outbound::new(server, SocketOptions::default()).with_credentials("alice", "secret");
+        let address = Address::Ipv4("10.0.0.1".parse().unwrap(), 8080);
+        let mut stream = outbound.connect(&address).await.unwrap();
+        stream.write_all(b"pong").await.unwrap();
+        let mut echo = [0u8; 4];
+        stream.read_exact(&mut echo).await.unwrap();
+        assert_eq!(&echo, b"pong");
+
+        assert_eq!(stub.await.unwrap(), vec![0x05, 0x01, 0x00, 0x01, 10, 0, 0, 1, 0x1f, 0x90]);
+    }
+
+    #[tokio::test]
+    async fn test_socks5_rejected_method() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            stream.write_all(&[0x05, 0xff]).await.unwrap();
+        });
+
+        let outbound = Socks5Outbound::new(server, SocketOptions::default());
+        let address = Address::Domain("example.com".to_string(), 80);
+        assert!(outbound.connect(&address).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_vless_response_header_is_stripped() {
+        // 响应头 (版本 0, 2 字节附加数据) 被拆散在多次读取中
+        let inner = tokio_test::io::Builder::new()
+            .read(&[0x00])
+            .read(&[0x02, 0xaa])
+            .read(&[0xbb, b'h', b'i'])
+            .read(b"!")
+            .build();
+        let mut stream = VlessClientStream::new(inner);
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data, b"hi!");
+
+        let inner = tokio_test::io::Builder::new().read(&[0x01, 0x00]).build();
+        let mut stream = VlessClientStream::new(inner);
+        assert!(stream.read_to_end(&mut Vec::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_udp_targets_use_dns_cache() {
+        use super::super::dns::{Answer, CacheTtl, Resolve};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct CountingResolver(Arc<AtomicUsize>);
+
+        impl Resolve for CountingResolver {
+            fn resolve<'a>(&'a self, _host: &'a str) -> BoxFuture<'a, Result<Answer>> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async {
+                    Ok(Answer { addrs: vec!["::1".parse()?, "127.0.0.1".parse()?], ttl: Some(Duration::from_secs(60)), ..Default::default() })
+                })
+            }
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let ttl = CacheTtl { min: Duration::from_secs(1), max: Duration::from_secs(300), negative: Duration::from_secs(1) };
+        let outbound = DirectOutbound::new(SocketOptions::default())
+            .with_dns(DnsCache::new(Box::new(CountingResolver(calls.clone())), ttl));
+        let udp = outbound.bind_udp().await.unwrap();
+
+        let address = Address::Domain("dns.example".to_string(), 53);
+        for _ in 0..2 {
+            assert_eq!(udp.resolve(&address).await.unwrap(), "127.0.0.1:53".parse().unwrap());
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_from_config() {
+        let mut config: Config = serde_json::from_str(
+            r#"{
+                "inbounds": [],
+                "outbounds": [{
+                    "protocol": "socks",
+                    "tag": "proxy",
+                    "settings": { "servers": [{ "address": "127.0.0.1", "port": 1080 }] }
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert!(from_config(&config, SocketOptions::default()).is_ok());
+
+        config.outbounds[0].settings = None;
+        assert!(from_config(&config, SocketOptions::default()).is_err());
+
+        config.outbounds[0].protocol = "freedom".to_string();
+        assert!(from_config(&config, SocketOptions::default()).is_ok());
+    }
+}
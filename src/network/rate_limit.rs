@@ -0,0 +1,157 @@
+//! 令牌桶限速
+//!
+//! 每个方向一个令牌桶。读取到数据后先等待令牌再写出，不会丢弃数据。
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// 令牌桶 (单位: 字节)
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// 创建令牌桶，初始为满
+    pub fn new(bytes_per_sec: u64, burst: u64) -> Self {
+        let burst = burst.max(1) as f64;
+        Self {
+            rate: bytes_per_sec.max(1) as f64,
+            burst,
+            state: Mutex::new(BucketState {
+                tokens: burst,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// 等待直到获得 n 个令牌
+    ///
+    /// 超过桶容量的请求会被拆分成多次获取
+    pub async fn acquire(&self, n: usize) {
+        let mut remaining = n as f64;
+        while remaining > 0.0 {
+            let wait = {
+                let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.last).as_secs_f64();
+                state.tokens = (state.tokens + elapsed * self.rate).min(self.burst);
+                state.last = now;
+
+                let take = remaining.min(self.burst);
+                if state.tokens >= take {
+                    state.tokens -= take;
+                    remaining -= take;
+                    None
+                } else {
+                    Some(Duration::from_secs_f64((take - state.tokens) / self.rate))
+                }
+            };
+            if let Some(wait) = wait {
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+}
+
+/// 双向限速器 (克隆后共享同一组令牌桶)
+#[derive(Clone)]
+pub struct RateLimiter {
+    /// 客户端 -> 远程
+    pub upload: Arc<TokenBucket>,
+    /// 远程 -> 客户端
+    pub download: Arc<TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64, burst: u64) -> Self {
+        Self {
+            upload: Arc::new(TokenBucket::new(bytes_per_sec, burst)),
+            download: Arc::new(TokenBucket::new(bytes_per_sec, burst)),
+        }
+    }
+}
+
+enum UserLimit {
+    /// 同一用户的所有连接共享
+    Shared(RateLimiter),
+    /// 每个连接独立计算
+    PerConnection { bytes_per_sec: u64, burst: u64 },
+}
+
+/// 按用户 UUID 查找限速器
+#[derive(Default)]
+pub struct RateLimitRegistry {
+    users: HashMap<Uuid, UserLimit>,
+}
+
+impl RateLimitRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 为用户注册限速
+    pub fn register(&mut self, uuid: Uuid, bytes_per_sec: u64, burst: u64, shared: bool) {
+        let limit = if shared {
+            UserLimit::Shared(RateLimiter::new(bytes_per_sec, burst))
+        } else {
+            UserLimit::PerConnection { bytes_per_sec, burst }
+        };
+        self.users.insert(uuid, limit);
+    }
+
+    /// 获取用户的限速器，未配置时返回 None
+    pub fn limiter_for(&self, uuid: &Uuid) -> Option<RateLimiter> {
+        match self.users.get(uuid)? {
+            UserLimit::Shared(limiter) => Some(limiter.clone()),
+            UserLimit::PerConnection { bytes_per_sec, burst } => {
+                Some(RateLimiter::new(*bytes_per_sec, *burst))
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bucket_waits_for_tokens() {
+        let bucket = TokenBucket::new(10_000, 500);
+        let start = Instant::now();
+        // 前 500 字节来自初始桶，剩余 1500 字节需要 150ms
+        bucket.acquire(2000).await;
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_registry_scope() {
+        let shared = Uuid::new_v4();
+        let per_conn = Uuid::new_v4();
+        let mut registry = RateLimitRegistry::new();
+        registry.register(shared, 1000, 1000, true);
+        registry.register(per_conn, 1000, 1000, false);
+
+        let a = registry.limiter_for(&shared).unwrap();
+        let b = registry.limiter_for(&shared).unwrap();
+        assert!(Arc::ptr_eq(&a.upload, &b.upload));
+
+        let c = registry.limiter_for(&per_conn).unwrap();
+        let d = registry.limiter_for(&per_conn).unwrap();
+        assert!(!Arc::ptr_eq(&c.upload, &d.upload));
+
+        assert!(registry.limiter_for(&Uuid::new_v4()).is_none());
+    }
+}
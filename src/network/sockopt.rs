@@ -0,0 +1,97 @@
+//! 已建立 TCP 连接的 socket 选项
+//!
+//! 入站接受的连接与出站拨出的连接使用同一组设置: 关闭 Nagle 算法降低交互流量的延迟，
+//! TCP KeepAlive 探测已消失的对端，避免半开连接长期占用资源。
+
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tracing::warn;
+
+/// TCP KeepAlive 探测参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// 连接空闲多久后开始探测
+    pub idle: Duration,
+    /// 探测间隔
+    pub interval: Duration,
+    /// 连续多少次探测无响应后断开
+    pub count: u32,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Self {
+            idle: Duration::from_secs(30),
+            interval: Duration::from_secs(10),
+            count: 3,
+        }
+    }
+}
+
+/// TCP 连接的 socket 选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// TCP_NODELAY
+    pub no_delay: bool,
+    /// `None` 时不启用 KeepAlive
+    pub keepalive: Option<Keepalive>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            no_delay: true,
+            keepalive: Some(Keepalive::default()),
+        }
+    }
+}
+
+impl SocketOptions {
+    /// 应用到连接上，失败只记录日志
+    pub fn apply(&self, stream: &TcpStream) {
+        if self.no_delay {
+            if let Err(e) = stream.set_nodelay(true) {
+                warn!("设置 TCP_NODELAY 失败: {}", e);
+            }
+        }
+        if let Some(keepalive) = self.keepalive {
+            let params = socket2::TcpKeepalive::new()
+                .with_time(keepalive.idle)
+                .with_interval(keepalive.interval)
+                .with_retries(keepalive.count);
+            if let Err(e) = socket2::SockRef::from(stream).set_tcp_keepalive(&params) {
+                warn!("设置 TCP KeepAlive 失败: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_apply_sets_nodelay_and_keepalive() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let options = SocketOptions {
+            no_delay: true,
+            keepalive: Some(Keepalive { idle: Duration::from_secs(45), interval: Duration::from_secs(5), count: 4 }),
+        };
+        options.apply(&stream);
+
+        let socket = socket2::SockRef::from(&stream);
+        assert!(stream.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(45));
+        assert_eq!(socket.keepalive_interval().unwrap(), Duration::from_secs(5));
+        assert_eq!(socket.keepalive_retries().unwrap(), 4);
+
+        let (accepted, _) = listener.accept().await.unwrap();
+        SocketOptions { no_delay: false, keepalive: None }.apply(&accepted);
+        assert!(!accepted.nodelay().unwrap());
+        assert!(!socket2::SockRef::from(&accepted).keepalive().unwrap());
+    }
+}
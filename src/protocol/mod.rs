@@ -1,6 +1,8 @@
+pub mod mux;
 pub mod proxy_protocol;
 pub mod sniffer;
 pub mod vless;
 
+pub use mux::{MuxFrame, MuxNetwork, MuxStatus};
 pub use proxy_protocol::{is_proxy_protocol, parse_proxy_protocol, ProxyHeader};
 pub use vless::{VlessCodec, VlessRequest, VlessResponse};
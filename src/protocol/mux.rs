@@ -0,0 +1,251 @@
+//! Mux.Cool 帧编解码
+//!
+//! 帧格式:
+//! [Metadata Length(2)][Metadata][Data Length(2)][Data] (仅当 Option 含 Data 时有数据部分)
+//!
+//! Metadata:
+//! [Session ID(2)][Status(1)][Option(1)][Network(1)][Port(2)][AddrType(1)][Address]
+//! 其中 Network 及地址只在 New 帧 (以及 UDP 的 Keep 帧) 中出现
+
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use super::vless::Address;
+
+/// Option: 帧携带数据
+pub const OPTION_DATA: u8 = 0x01;
+/// Option: 子连接异常结束
+pub const OPTION_ERROR: u8 = 0x02;
+
+/// 单帧最大数据长度 (长度字段为 u16)
+pub const MAX_FRAME_DATA: usize = u16::MAX as usize;
+
+/// 子连接状态
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MuxStatus {
+    /// 新建子连接
+    New = 0x01,
+    /// 保持子连接 (传输数据)
+    Keep = 0x02,
+    /// 关闭子连接
+    End = 0x03,
+    /// 保活帧
+    KeepAlive = 0x04,
+}
+
+impl MuxStatus {
+    pub fn from_u8(value: u8) -> Result<Self> {
+        match value {
+            0x01 => Ok(MuxStatus::New),
+            0x02 => Ok(MuxStatus::Keep),
+            0x03 => Ok(MuxStatus::End),
+            0x04 => Ok(MuxStatus::KeepAlive),
+            _ => Err(anyhow!("未知的 Mux 状态: {}", value)),
+        }
+    }
+}
+
+/// 子连接网络类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MuxNetwork {
+    Tcp = 0x01,
+    Udp = 0x02,
+}
+
+impl MuxNetwork {
+    pub fn from_u8(value: u8) -> Result<Self> {
+        match value {
+            0x01 => Ok(MuxNetwork::Tcp),
+            0x02 => Ok(MuxNetwork::Udp),
+            _ => Err(anyhow!("未知的 Mux 网络类型: {}", value)),
+        }
+    }
+}
+
+/// Mux.Cool 帧
+#[derive(Debug, Clone, PartialEq)]
+pub struct MuxFrame {
+    /// 子连接 ID
+    pub session_id: u16,
+    /// 状态
+    pub status: MuxStatus,
+    /// 选项位
+    pub option: u8,
+    /// 目标 (New 帧必有，UDP 的 Keep 帧可选)
+    pub target: Option<(MuxNetwork, Address)>,
+    /// 数据
+    pub data: Option<Bytes>,
+}
+
+impl MuxFrame {
+    /// 构造携带数据的 Keep 帧
+    pub fn keep(session_id: u16, target: Option<(MuxNetwork, Address)>, data: Bytes) -> Self {
+        Self {
+            session_id,
+            status: MuxStatus::Keep,
+            option: OPTION_DATA,
+            target,
+            data: Some(data),
+        }
+    }
+
+    /// 构造 End 帧
+    pub fn end(session_id: u16, error: bool) -> Self {
+        Self {
+            session_id,
+            status: MuxStatus::End,
+            option: if error { OPTION_ERROR } else { 0 },
+            target: None,
+            data: None,
+        }
+    }
+
+    /// 从缓冲区解码一个完整的帧
+    ///
+    /// 数据不足时返回 `Ok(None)` 且不消耗缓冲区
+    pub fn decode(buf: &mut BytesMut) -> Result<Option<Self>> {
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+        let meta_len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+        if meta_len < 4 {
+            return Err(anyhow!("Mux 元数据过短: {}", meta_len));
+        }
+        if buf.len() < 2 + meta_len {
+            return Ok(None);
+        }
+
+        let option = buf[2 + 3];
+        let mut total = 2 + meta_len;
+        if option & OPTION_DATA != 0 {
+            if buf.len() < total + 2 {
+                return Ok(None);
+            }
+            let data_len = u16::from_be_bytes([buf[total], buf[total + 1]]) as usize;
+            total += 2 + data_len;
+            if buf.len() < total {
+                return Ok(None);
+            }
+        }
+
+        // 完整帧已到达，开始消费
+        buf.advance(2);
+        let mut meta = buf.split_to(meta_len);
+        let session_id = meta.get_u16();
+        let status = MuxStatus::from_u8(meta.get_u8())?;
+        let option = meta.get_u8();
+
+        let target = if meta.has_remaining() && matches!(status, MuxStatus::New | MuxStatus::Keep) {
+            let network = MuxNetwork::from_u8(meta.get_u8())?;
+            let address = Address::decode(&mut meta)?;
+            // 剩余元数据 (例如 XUDP 的 GlobalID) 直接忽略
+            Some((network, address))
+        } else {
+            None
+        };
+
+        if status == MuxStatus::New && target.is_none() {
+            return Err(anyhow!("Mux New 帧缺少目标地址"));
+        }
+
+        let data = if option & OPTION_DATA != 0 {
+            let data_len = buf.get_u16() as usize;
+            Some(buf.split_to(data_len).freeze())
+        } else {
+            None
+        };
+
+        Ok(Some(Self {
+            session_id,
+            status,
+            option,
+            target,
+            data,
+        }))
+    }
+
+    /// 将帧编码到缓冲区
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<()> {
+        let mut meta = BytesMut::with_capacity(32);
+        meta.put_u16(self.session_id);
+        meta.put_u8(self.status as u8);
+        let option = if self.data.is_some() {
+            self.option | OPTION_DATA
+        } else {
+            self.option & !OPTION_DATA
+        };
+        meta.put_u8(option);
+        if let Some((network, address)) = &self.target {
+            meta.put_u8(*network as u8);
+            address.encode(&mut meta);
+        }
+
+        buf.put_u16(meta.len() as u16);
+        buf.put_slice(&meta);
+
+        if let Some(data) = &self.data {
+            if data.len() > MAX_FRAME_DATA {
+                return Err(anyhow!("Mux 帧数据过长: {}", data.len()));
+            }
+            buf.put_u16(data.len() as u16);
+            buf.put_slice(data);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_new_frame_encode_decode() {
+        let frame = MuxFrame {
+            session_id: 1,
+            status: MuxStatus::New,
+            option: OPTION_DATA,
+            target: Some((MuxNetwork::Tcp, Address::Domain("example.com".to_string(), 443))),
+            data: Some(Bytes::from_static(b"hello")),
+        };
+
+        let mut buf = BytesMut::new();
+        frame.encode(&mut buf).unwrap();
+        let decoded = MuxFrame::decode(&mut buf).unwrap().unwrap();
+        assert_eq!(decoded, frame);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_partial_frame() {
+        let frame = MuxFrame::keep(
+            9,
+            Some((MuxNetwork::Udp, Address::Ipv4(Ipv4Addr::new(8, 8, 8, 8), 53))),
+            Bytes::from_static(b"dns"),
+        );
+        let mut full = BytesMut::new();
+        frame.encode(&mut full).unwrap();
+        MuxFrame::end(9, false).encode(&mut full).unwrap();
+
+        // 逐字节喂入，完整之前不应消费任何数据
+        let mut buf = BytesMut::new();
+        let mut frames = Vec::new();
+        for b in full.iter() {
+            buf.put_u8(*b);
+            while let Some(f) = MuxFrame::decode(&mut buf).unwrap() {
+                frames.push(f);
+            }
+        }
+        assert_eq!(frames, vec![frame, MuxFrame::end(9, false)]);
+    }
+
+    #[test]
+    fn test_new_frame_without_target() {
+        let mut buf = BytesMut::new();
+        buf.put_u16(4);
+        buf.put_u16(1);
+        buf.put_u8(MuxStatus::New as u8);
+        buf.put_u8(0);
+        assert!(MuxFrame::decode(&mut buf).is_err());
+    }
+}
@@ -1,6 +1,165 @@
+use crate::transport::reality::hello_parser::parse_client_hello;
+
+/// 嗅探到的应用层协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SniffProtocol {
+    Tls,
+    Http,
+    Quic,
+    BitTorrent,
+    Unknown,
+}
+
+impl SniffProtocol {
+    /// 与配置中 destOverride 对应的名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SniffProtocol::Tls => "tls",
+            SniffProtocol::Http => "http",
+            SniffProtocol::Quic => "quic",
+            SniffProtocol::BitTorrent => "bittorrent",
+            SniffProtocol::Unknown => "unknown",
+        }
+    }
+}
+
+/// ClientHello 中声明的 TLS 版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    Tls10,
+    Tls11,
+    Tls12,
+    Tls13,
+}
+
+impl TlsVersion {
+    pub fn from_u16(value: u16) -> Option<Self> {
+        match value {
+            0x0301 => Some(TlsVersion::Tls10),
+            0x0302 => Some(TlsVersion::Tls11),
+            0x0303 => Some(TlsVersion::Tls12),
+            0x0304 => Some(TlsVersion::Tls13),
+            _ => None,
+        }
+    }
+}
+
+/// 嗅探结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SniffResult {
+    pub protocol: SniffProtocol,
+    /// TLS SNI 或 HTTP Host (不含端口)
+    pub host: Option<String>,
+    /// ALPN 列表 (按客户端顺序)
+    pub alpn: Vec<String>,
+    /// TLS: supported_versions 中的最高版本，缺失时取 legacy_version
+    pub version: Option<TlsVersion>,
+    /// TLS: 带有 encrypted_client_hello 扩展 (ECH)，取自 [`ClientHelloInfo::ech`](crate::transport::reality::hello_parser::ClientHelloInfo::ech)
+    ///
+    /// 此时 `host` 是公开 SNI，真实 SNI 无法还原。首包不含完整 ClientHello 时为 false。
+    pub ech: bool,
+}
+
+impl SniffResult {
+    fn unknown() -> Self {
+        Self {
+            protocol: SniffProtocol::Unknown,
+            host: None,
+            alpn: Vec::new(),
+            version: None,
+            ech: false,
+        }
+    }
+}
+
+/// 对首包进行协议嗅探
+pub fn sniff(data: &[u8]) -> SniffResult {
+    if let Some(result) = sniff_tls(data) {
+        return result;
+    }
+    if is_bittorrent(data) {
+        return SniffResult {
+            protocol: SniffProtocol::BitTorrent,
+            ..SniffResult::unknown()
+        };
+    }
+    if let Some(host) = sniff_http_host(data) {
+        return SniffResult {
+            protocol: SniffProtocol::Http,
+            host,
+            ..SniffResult::unknown()
+        };
+    }
+    if is_quic_initial(data) {
+        return SniffResult {
+            protocol: SniffProtocol::Quic,
+            ..SniffResult::unknown()
+        };
+    }
+    SniffResult::unknown()
+}
+
 /// 尝试从数据包中嗅探 TLS SNI (Server Name Indication)
-/// 这是一个高效的纯 Rust 实现，旨在最小化内存分配
+///
+/// 兼容旧接口，等价于 `sniff(data)` 中 TLS 结果的 host
 pub fn sniff_tls_sni(data: &[u8]) -> Option<String> {
+    sniff_tls(data)?.host
+}
+
+/// BitTorrent 检测只看首包的前 2KB
+const BT_SNIFF_LIMIT: usize = 2048;
+
+/// 识别 BitTorrent 流量: peer 握手、DHT 报文和 tracker announce (HTTP / UDP)
+///
+/// TLS 首字节固定为 0x16，以下特征均不会与之冲突
+pub fn is_bittorrent(data: &[u8]) -> bool {
+    let data = &data[..data.len().min(BT_SNIFF_LIMIT)];
+    if data.first() == Some(&0x16) {
+        return false;
+    }
+
+    // Peer 握手: pstrlen(19) + "BitTorrent protocol"
+    if data.starts_with(b"\x13BitTorrent protocol") {
+        return true;
+    }
+
+    // DHT (KRPC): 查询 d1:ad2:id20: / 响应 d1:rd2:id20:
+    if data.starts_with(b"d1:ad2:id20:") || data.starts_with(b"d1:rd2:id20:") {
+        return true;
+    }
+
+    // UDP tracker connect: protocol_id(0x41727101980) + action(0)
+    if data.len() >= 16
+        && data[..8] == 0x0000_0417_2710_1980u64.to_be_bytes()
+        && data[8..12] == [0, 0, 0, 0]
+    {
+        return true;
+    }
+
+    // HTTP tracker announce / scrape
+    if data.starts_with(b"GET /") {
+        let line_end = data.iter().position(|&b| b == b'\r').unwrap_or(data.len());
+        let line = &data[..line_end];
+        let contains = |pat: &[u8]| line.windows(pat.len()).any(|w| w == pat);
+        if contains(b"info_hash=") && (contains(b"peer_id=") || contains(b"/scrape")) {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// 大端 u16 长度字段，越界时为 None
+fn be16(data: &[u8], pos: usize) -> Option<usize> {
+    let bytes = data.get(pos..pos.checked_add(2)?)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+}
+
+/// 解析 TLS ClientHello，一次遍历提取 SNI / ALPN / supported_versions
+/// 这是一个高效的纯 Rust 实现，旨在最小化内存分配
+///
+/// 所有读取都经过边界检查: 长度字段指向数据之外时停止解析，返回已得到的结果
+fn sniff_tls(data: &[u8]) -> Option<SniffResult> {
     if data.len() < 43 {
         // Min ClientHello size
         return None;
@@ -19,6 +178,14 @@ pub fn sniff_tls_sni(data: &[u8]) -> Option<String> {
         return None;
     }
 
+    let mut result = SniffResult {
+        protocol: SniffProtocol::Tls,
+        host: None,
+        alpn: Vec::new(),
+        version: be16(data, pos + 4).and_then(|v| TlsVersion::from_u16(v as u16)),
+        ech: parse_client_hello(data).ok().flatten().is_some_and(|info| info.ech),
+    };
+
     // Skip HandshakeType(1), Length(3), Version(2), Random(32)
     pos += 38;
 
@@ -27,73 +194,307 @@ pub fn sniff_tls_sni(data: &[u8]) -> Option<String> {
     pos += 1 + sess_id_len;
 
     // Cipher Suites
-    if pos + 2 > data.len() {
-        return None;
-    }
-    let cipher_len = u16::from_be_bytes([data[pos], data[pos + 1]]) as usize;
+    let Some(cipher_len) = be16(data, pos) else {
+        return Some(result);
+    };
     pos += 2 + cipher_len;
 
     // Compression Methods
-    if pos + 1 > data.len() {
-        return None;
-    }
-    let comp_len = *data.get(pos)? as usize;
-    pos += 1 + comp_len;
+    let Some(&comp_len) = data.get(pos) else {
+        return Some(result);
+    };
+    pos += 1 + comp_len as usize;
 
     // Extensions
-    if pos + 2 > data.len() {
-        return None;
-    }
-    let ext_len = u16::from_be_bytes([data[pos], data[pos + 1]]) as usize;
+    let Some(ext_len) = be16(data, pos) else {
+        return Some(result);
+    };
     pos += 2;
 
-    let end_ext = pos + ext_len;
-    if end_ext > data.len() {
-        return None;
-    }
+    // 首包可能被截断，只解析已到达的部分
+    let extensions = &data[pos..(pos + ext_len).min(data.len())];
+    let mut pos = 0;
 
-    while pos + 4 <= end_ext {
-        let ext_type = u16::from_be_bytes([data[pos], data[pos + 1]]);
-        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+    while let (Some(ext_type), Some(len)) = (be16(extensions, pos), be16(extensions, pos + 2)) {
         pos += 4;
+        let Some(body) = extensions.get(pos..pos + len) else {
+            break;
+        };
+
+        match ext_type {
+            // ServerName
+            0x0000 => result.host = parse_server_name(body),
+            // ALPN
+            0x0010 => result.alpn = parse_alpn(body),
+            // supported_versions
+            0x002b => {
+                if let Some(v) = parse_supported_versions(body) {
+                    result.version = Some(v);
+                }
+            }
+            _ => {}
+        }
+        pos += len;
+    }
+
+    Some(result)
+}
 
-        if pos + len > end_ext {
+fn parse_server_name(body: &[u8]) -> Option<String> {
+    let list_len = be16(body, 0)?;
+    // 列表长度必须落在扩展内
+    let list = body.get(2..2 + list_len)?;
+
+    let mut p = 0;
+    while let (Some(&name_type), Some(name_len)) = (list.get(p), be16(list, p + 1)) {
+        p += 3;
+        let name = list.get(p..p + name_len)?;
+
+        if name_type == 0x00 {
+            // HostName
+            return std::str::from_utf8(name).map(|s| s.to_string()).ok();
+        }
+        p += name_len;
+    }
+    None
+}
+
+fn parse_alpn(body: &[u8]) -> Vec<String> {
+    let mut alpn = Vec::new();
+    let Some(list_len) = be16(body, 0) else {
+        return alpn;
+    };
+    let list = &body[2..(2 + list_len).min(body.len())];
+
+    let mut p = 0;
+    while let Some(&len) = list.get(p) {
+        p += 1;
+        let Some(proto) = list.get(p..p + len as usize) else {
             break;
+        };
+        if let Ok(proto) = std::str::from_utf8(proto) {
+            alpn.push(proto.to_string());
         }
+        p += len as usize;
+    }
+    alpn
+}
 
-        if ext_type == 0x0000 {
-            // ServerName Extension
-            if len < 2 {
-                return None;
-            }
-            let list_len = u16::from_be_bytes([data[pos], data[pos + 1]]) as usize;
-            let mut p2 = pos + 2;
-            let end_list = p2 + list_len;
+fn parse_supported_versions(body: &[u8]) -> Option<TlsVersion> {
+    let list_len = *body.first()? as usize;
+    let list = body.get(1..1 + list_len)?;
+    // GREASE 等未知值由 from_u16 过滤
+    list.chunks_exact(2)
+        .filter_map(|v| TlsVersion::from_u16(u16::from_be_bytes([v[0], v[1]])))
+        .max()
+}
 
-            if end_list > pos + len {
-                return None;
-            }
+/// 识别 HTTP/1.x 请求并提取 Host 头 (外层 None 表示不是 HTTP)
+fn sniff_http_host(data: &[u8]) -> Option<Option<String>> {
+    const METHODS: [&[u8]; 9] = [
+        b"GET ", b"POST ", b"HEAD ", b"PUT ", b"DELETE ", b"OPTIONS ", b"PATCH ", b"CONNECT ",
+        b"TRACE ",
+    ];
+    if !METHODS.iter().any(|m| data.starts_with(m)) {
+        return None;
+    }
 
-            while p2 + 3 <= end_list {
-                let name_type = data[p2];
-                let name_len = u16::from_be_bytes([data[p2 + 1], data[p2 + 2]]) as usize;
-                p2 += 3;
+    let text = String::from_utf8_lossy(data);
+    let host = text.split("\r\n").skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if !name.trim().eq_ignore_ascii_case("host") {
+            return None;
+        }
+        let value = value.trim();
+        // 去掉端口，保留 IPv6 方括号内的地址
+        let host = if let Some(rest) = value.strip_prefix('[') {
+            rest.split(']').next().unwrap_or(rest)
+        } else {
+            value.split(':').next().unwrap_or(value)
+        };
+        (!host.is_empty()).then(|| host.to_string())
+    });
+    Some(host)
+}
 
-                if p2 + name_len > end_list {
-                    break;
-                }
+/// QUIC Initial 包: 长包头 + 固定位 + 类型 0，版本为 v1 或 v2
+fn is_quic_initial(data: &[u8]) -> bool {
+    if data.len() < 5 {
+        return false;
+    }
+    let first = data[0];
+    if first & 0xC0 != 0xC0 {
+        return false;
+    }
+    let version = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+    match version {
+        // v1: Initial 类型位为 0b00
+        0x0000_0001 => (first & 0x30) == 0x00,
+        // v2 (RFC 9369): Initial 类型位为 0b01
+        0x6b33_43cf => (first & 0x30) == 0x10,
+        _ => false,
+    }
+}
 
-                if name_type == 0x00 {
-                    // HostName
-                    return std::str::from_utf8(&data[p2..p2 + name_len])
-                        .map(|s| s.to_string())
-                        .ok();
-                }
-                p2 += name_len;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 构造一个最小 ClientHello，包含给定扩展
+    fn client_hello(extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut exts = Vec::new();
+        for (ty, body) in extensions {
+            exts.extend_from_slice(&ty.to_be_bytes());
+            exts.extend_from_slice(&(body.len() as u16).to_be_bytes());
+            exts.extend_from_slice(body);
+        }
+
+        let mut hello = vec![0x03, 0x03];
+        hello.extend_from_slice(&[0u8; 32]); // random
+        hello.push(0); // session id
+        hello.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]); // cipher suites
+        hello.extend_from_slice(&[0x01, 0x00]); // compression
+        hello.extend_from_slice(&(exts.len() as u16).to_be_bytes());
+        hello.extend_from_slice(&exts);
+
+        let mut handshake = vec![0x01, 0x00];
+        handshake.extend_from_slice(&(hello.len() as u16).to_be_bytes());
+        handshake.extend_from_slice(&hello);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    fn sni_ext(host: &str) -> (u16, Vec<u8>) {
+        let mut body = ((host.len() + 3) as u16).to_be_bytes().to_vec();
+        body.push(0);
+        body.extend_from_slice(&(host.len() as u16).to_be_bytes());
+        body.extend_from_slice(host.as_bytes());
+        (0x0000, body)
+    }
+
+    #[test]
+    fn test_tls_sni_alpn_version() {
+        let alpn = vec![0x00, 0x0c, 0x02, b'h', b'2', 0x08, b'h', b't', b't', b'p', b'/', b'1', b'.', b'1'];
+        let versions = vec![0x04, 0x7a, 0x7a, 0x03, 0x04];
+        let data = client_hello(&[sni_ext("www.example.com"), (0x0010, alpn), (0x002b, versions)]);
+
+        let result = sniff(&data);
+        assert_eq!(result.protocol, SniffProtocol::Tls);
+        assert_eq!(result.host.as_deref(), Some("www.example.com"));
+        assert_eq!(result.alpn, vec!["h2".to_string(), "http/1.1".to_string()]);
+        assert_eq!(result.version, Some(TlsVersion::Tls13));
+        assert_eq!(sniff_tls_sni(&data).as_deref(), Some("www.example.com"));
+    }
+
+    #[test]
+    fn test_tls_ech_outer_sni() {
+        // ECHClientHello: outer(0), HPKE 套件, config_id, enc, payload
+        let mut ech = vec![0x00, 0x00, 0x01, 0x00, 0x01, 0x2a, 0x00, 0x20];
+        ech.extend_from_slice(&[0x11; 32]);
+        ech.extend_from_slice(&[0x00, 0x10]);
+        ech.extend_from_slice(&[0x22; 16]);
+        let data = client_hello(&[sni_ext("public.example.com"), (0xfe0d, ech)]);
+
+        let result = sniff(&data);
+        assert!(result.ech);
+        assert_eq!(result.host.as_deref(), Some("public.example.com"));
+
+        // 只有 ECH 扩展、没有 SNI
+        assert_eq!(sniff(&client_hello(&[(0xfe0d, vec![0x00])])).host, None);
+        assert!(sniff(&client_hello(&[(0xfe0d, vec![0x00])])).ech);
+        // 内层 ClientHello 的标记 (类型 1) 不会出现在外层，不计为 ECH
+        assert!(!sniff(&client_hello(&[sni_ext("a.com"), (0xfe0d, vec![0x01])])).ech);
+        assert!(!sniff(&client_hello(&[sni_ext("a.com")])).ech);
+    }
+
+    #[test]
+    fn test_tls_legacy_version() {
+        let data = client_hello(&[sni_ext("a.com")]);
+        let result = sniff(&data);
+        assert_eq!(result.version, Some(TlsVersion::Tls12));
+        assert!(result.alpn.is_empty());
+    }
+
+    #[test]
+    fn test_tls_truncated_and_lying_lengths() {
+        let alpn = vec![0x00, 0x03, 0x02, b'h', b'2'];
+        let data = client_hello(&[sni_ext("www.example.com"), (0x0010, alpn)]);
+
+        // 任意位置截断都不会 panic，截断在扩展之前时仍识别为 TLS
+        for len in 0..data.len() {
+            let result = sniff(&data[..len]);
+            if len >= 52 {
+                assert_eq!(result.protocol, SniffProtocol::Tls, "truncated at {len}");
             }
         }
-        pos += len;
+        assert_eq!(sniff_tls_sni(&data[..data.len() - 1]).as_deref(), Some("www.example.com"));
+        assert_eq!(sniff_tls_sni(&data[..70]), None);
+
+        // 扩展起始偏移: 记录头 5 + 握手头 4 + 版本 2 + random 32 + session_id 1 + 套件 4 + 压缩 2 + 扩展长度 2
+        const EXT: usize = 52;
+        let lie = |offset: usize, value: u16| {
+            let mut data = data.clone();
+            data[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+            sniff(&data)
+        };
+        for value in [0, 1, 0x00ff, 0xfffe, 0xffff] {
+            // 套件长度、扩展总长度、SNI 扩展长度、server_name 列表长度、名称长度、ALPN 列表长度
+            for offset in [44, EXT - 2, EXT + 2, EXT + 4, EXT + 7, EXT + 28] {
+                let result = lie(offset, value);
+                assert_eq!(result.protocol, SniffProtocol::Tls);
+            }
+        }
+        // 列表长度超出扩展体、名称长度超出列表时不取 SNI
+        assert_eq!(lie(EXT + 4, 0x0100).host, None);
+        assert_eq!(lie(EXT + 7, 0x0100).host, None);
+        // ALPN 条目长度超出列表时只丢弃该条目
+        let mut data_alpn = data.clone();
+        data_alpn[EXT + 30] = 0xff;
+        assert!(sniff(&data_alpn).alpn.is_empty());
+        assert_eq!(sniff(&data_alpn).host.as_deref(), Some("www.example.com"));
     }
 
-    None
+    #[test]
+    fn test_http_host() {
+        let data = b"GET / HTTP/1.1\r\nUser-Agent: x\r\nHost: example.org:8080\r\n\r\n";
+        let result = sniff(data);
+        assert_eq!(result.protocol, SniffProtocol::Http);
+        assert_eq!(result.host.as_deref(), Some("example.org"));
+        assert_eq!(sniff_tls_sni(data), None);
+    }
+
+    #[test]
+    fn test_bittorrent_detection() {
+        let mut handshake = b"\x13BitTorrent protocol".to_vec();
+        handshake.extend_from_slice(&[0u8; 48]);
+        assert!(is_bittorrent(&handshake));
+        assert_eq!(sniff(&handshake).protocol, SniffProtocol::BitTorrent);
+
+        let dht = b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe";
+        assert!(is_bittorrent(dht));
+
+        let mut udp_tracker = 0x0000_0417_2710_1980u64.to_be_bytes().to_vec();
+        udp_tracker.extend_from_slice(&[0, 0, 0, 0, 1, 2, 3, 4]);
+        assert!(is_bittorrent(&udp_tracker));
+
+        let announce = b"GET /announce?info_hash=%12%34&peer_id=-qB4250-abc&port=6881 HTTP/1.1\r\nHost: t.example\r\n\r\n";
+        assert!(is_bittorrent(announce));
+
+        // 普通 HTTP 与 TLS 不应被误判
+        assert!(!is_bittorrent(b"GET /index.html HTTP/1.1\r\nHost: a\r\n\r\n"));
+        let tls = client_hello(&[sni_ext("d1:ad2:id20:.example")]);
+        assert!(!is_bittorrent(&tls));
+        assert_eq!(sniff(&tls).protocol, SniffProtocol::Tls);
+    }
+
+    #[test]
+    fn test_quic_and_unknown() {
+        let mut quic = vec![0xc3, 0x00, 0x00, 0x00, 0x01];
+        quic.extend_from_slice(&[0u8; 64]);
+        assert_eq!(sniff(&quic).protocol, SniffProtocol::Quic);
+        assert_eq!(sniff(&[0u8; 64]).protocol, SniffProtocol::Unknown);
+    }
 }
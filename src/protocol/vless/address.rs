@@ -16,7 +16,14 @@ pub enum Address {
 impl Address {
     /// 从字节流解析地址
     /// 注意：VLESS 协议使用 PortThenAddress 格式，即先读 Port 再读地址！
+    ///
+    /// Mux 标记会被跳过，如需获取 Mux Session ID 请使用 `decode_with_mux`
     pub fn decode(buf: &mut BytesMut) -> Result<Self> {
+        Self::decode_with_mux(buf).map(|(address, _)| address)
+    }
+
+    /// 从字节流解析地址，同时返回 Mux 标记中的 Session ID (如果存在)
+    pub fn decode_with_mux(buf: &mut BytesMut) -> Result<(Self, Option<u8>)> {
         // 1. 先读取 Port (2 bytes, big endian) - 这是 VLESS 协议规范！
         if buf.remaining() < 3 {
             return Err(anyhow!("缓冲区太小，无法读取端口和地址类型"));
@@ -34,7 +41,7 @@ impl Address {
                 }
                 let mut octets = [0u8; 4];
                 buf.copy_to_slice(&mut octets);
-                Ok(Address::Ipv4(Ipv4Addr::from(octets), port))
+                Ok((Address::Ipv4(Ipv4Addr::from(octets), port), None))
             }
             // 域名
             0x02 => {
@@ -47,7 +54,7 @@ impl Address {
                 }
                 let domain_bytes = buf.copy_to_bytes(len);
                 let domain = String::from_utf8(domain_bytes.to_vec())?;
-                Ok(Address::Domain(domain, port))
+                Ok((Address::Domain(domain, port), None))
             }
             // IPv6
             0x03 => {
@@ -56,7 +63,7 @@ impl Address {
                 }
                 let mut octets = [0u8; 16];
                 buf.copy_to_slice(&mut octets);
-                Ok(Address::Ipv6(Ipv6Addr::from(octets), port))
+                Ok((Address::Ipv6(Ipv6Addr::from(octets), port), None))
             }
             // Mux 标记 - v2ray/小火箭的多路复用
             0x00 => {
@@ -65,11 +72,14 @@ impl Address {
                     return Err(anyhow!("缓冲区太小，无法读取 Mux Session ID"));
                 }
 
-                let _session_id = buf.get_u8(); // 必须读取并跳过
+                let session_id = buf.get_u8();
 
-                // 递归解析真实地址 (会再读一次 Port + Address)
-                let real_address = Self::decode(buf)?;
-                Ok(real_address)
+                // 解析真实地址 (会再读一次 Port + Address)，不允许嵌套 Mux 标记
+                let (real_address, nested) = Self::decode_with_mux(buf)?;
+                if nested.is_some() {
+                    return Err(anyhow!("不支持嵌套的 Mux 标记"));
+                }
+                Ok((real_address, Some(session_id)))
             }
             _ => Err(anyhow!("未知的地址类型: {}", addr_type)),
         }
@@ -104,13 +114,24 @@ impl Address {
             Address::Ipv4(_, port) | Address::Ipv6(_, port) | Address::Domain(_, port) => *port,
         }
     }
+}
+
+impl From<std::net::SocketAddr> for Address {
+    fn from(addr: std::net::SocketAddr) -> Self {
+        match addr {
+            std::net::SocketAddr::V4(v4) => Address::Ipv4(*v4.ip(), v4.port()),
+            std::net::SocketAddr::V6(v6) => Address::Ipv6(*v6.ip(), v6.port()),
+        }
+    }
+}
 
-    /// 转换为字符串表示
-    pub fn to_string(&self) -> String {
+/// 转换为字符串表示
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Address::Ipv4(ip, port) => format!("{}:{}", ip, port),
-            Address::Ipv6(ip, port) => format!("[{}]:{}", ip, port),
-            Address::Domain(domain, port) => format!("{}:{}", domain, port),
+            Address::Ipv4(ip, port) => write!(f, "{}:{}", ip, port),
+            Address::Ipv6(ip, port) => write!(f, "[{}]:{}", ip, port),
+            Address::Domain(domain, port) => write!(f, "{}:{}", domain, port),
         }
     }
 }
@@ -148,4 +169,17 @@ mod tests {
         let decoded = Address::decode(&mut buf).unwrap();
         assert_eq!(addr, decoded);
     }
+
+    #[test]
+    fn test_mux_marker_decode() {
+        let mut buf = BytesMut::new();
+        buf.put_u16(0); // Mux 标记端口
+        buf.put_u8(0x00); // Mux 标记
+        buf.put_u8(7); // Session ID
+        Address::Domain("example.com".to_string(), 443).encode(&mut buf);
+
+        let (decoded, session_id) = Address::decode_with_mux(&mut buf).unwrap();
+        assert_eq!(decoded, Address::Domain("example.com".to_string(), 443));
+        assert_eq!(session_id, Some(7));
+    }
 }
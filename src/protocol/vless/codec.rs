@@ -1,5 +1,7 @@
 use anyhow::Result;
 use bytes::BytesMut;
+use std::collections::HashMap;
+use std::sync::Arc;
 use uuid::Uuid;
 
 use super::{VlessRequest, VlessResponse};
@@ -9,12 +11,31 @@ use super::{VlessRequest, VlessResponse};
 pub struct VlessCodec {
     /// 允许的客户端 UUID 列表
     allowed_uuids: Vec<Uuid>,
+    /// 客户端标签 (通常为 email)，用于日志
+    labels: Arc<HashMap<Uuid, String>>,
 }
 
 impl VlessCodec {
     /// 创建新的编解码器
     pub fn new(allowed_uuids: Vec<Uuid>) -> Self {
-        Self { allowed_uuids }
+        Self {
+            allowed_uuids,
+            labels: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// 设置客户端标签
+    pub fn with_labels(mut self, labels: HashMap<Uuid, String>) -> Self {
+        self.labels = Arc::new(labels);
+        self
+    }
+
+    /// 获取客户端标签，未设置时返回 UUID
+    pub fn label_for(&self, uuid: &Uuid) -> String {
+        self.labels
+            .get(uuid)
+            .cloned()
+            .unwrap_or_else(|| uuid.to_string())
     }
 
     /// 解码 VLESS 请求
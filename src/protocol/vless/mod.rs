@@ -5,5 +5,5 @@ mod response;
 
 pub use address::Address;
 pub use codec::VlessCodec;
-pub use request::{Command, VlessRequest};
+pub use request::{Command, VlessRequest, FLOW_VISION, MUX_COOL_DOMAIN};
 pub use response::VlessResponse;
@@ -7,6 +7,12 @@ use super::Address;
 /// VLESS 协议版本
 pub const VLESS_VERSION: u8 = 0;
 
+/// XTLS Vision 流控，需要 Vision 填充与直连拷贝，本实现不支持
+pub const FLOW_VISION: &str = "xtls-rprx-vision";
+
+/// Mux.Cool 请求的占位目标地址 (Mux 请求本身不携带目标地址)
+pub const MUX_COOL_DOMAIN: &str = "v1.mux.cool";
+
 /// VLESS 命令类型
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Command {
@@ -42,13 +48,18 @@ pub struct VlessRequest {
     pub address: Address,
     /// 附加数据长度
     pub addon_length: u8,
+    /// 附加数据中的流控 (flow)，未设置时为空
+    pub flow: String,
+    /// 地址中 Mux 标记携带的 Session ID (部分客户端使用 0x00 地址类型标记 Mux)
+    pub mux_session_id: Option<u8>,
 }
 
 impl VlessRequest {
     /// 从字节流解码请求
     pub fn decode(buf: &mut BytesMut, allowed_uuids: &[Uuid]) -> Result<Self> {
-        // 检查最小长度: version(1) + uuid(16) + addon_length(1) + command(1) + port(2) + addr_type(1)
-        if buf.remaining() < 22 {
+        // 检查最小长度: version(1) + uuid(16) + addon_length(1) + command(1)
+        // 地址部分 (Mux 请求没有) 由 Address 自行检查
+        if buf.remaining() < 19 {
             return Err(anyhow!("缓冲区太小，无法解码 VLESS 请求"));
         }
 
@@ -71,11 +82,11 @@ impl VlessRequest {
         // 读取附加数据长度
         let addon_length = buf.get_u8();
 
-        // 跳过附加数据
+        // 读取附加数据 (protobuf 编码的 Addons)
         if buf.remaining() < addon_length as usize {
-            return Err(anyhow!("缓冲区太小，无法跳过附加数据"));
+            return Err(anyhow!("缓冲区太小，无法读取附加数据"));
         }
-        buf.advance(addon_length as usize);
+        let flow = decode_addons(&buf.split_to(addon_length as usize))?;
 
         // 读取命令
         if buf.remaining() < 1 {
@@ -83,8 +94,12 @@ impl VlessRequest {
         }
         let command = Command::from_u8(buf.get_u8())?;
 
-        // 读取目标地址
-        let address = Address::decode(buf)?;
+        // 读取目标地址 (Mux 请求不携带地址，真实目标在 Mux 帧中)
+        let (address, mux_session_id) = if command == Command::Mux {
+            (Address::Domain(MUX_COOL_DOMAIN.to_string(), 0), None)
+        } else {
+            Address::decode_with_mux(buf)?
+        };
 
         Ok(VlessRequest {
             version,
@@ -92,9 +107,16 @@ impl VlessRequest {
             command,
             address,
             addon_length,
+            flow,
+            mux_session_id,
         })
     }
 
+    /// 是否为 Mux 请求 (Mux 命令或带 Mux 标记的地址)
+    pub fn is_mux(&self) -> bool {
+        self.command == Command::Mux || self.mux_session_id.is_some()
+    }
+
     /// 将请求编码为字节流
     pub fn encode(&self) -> Result<BytesMut> {
         let mut buf = BytesMut::new();
@@ -105,19 +127,83 @@ impl VlessRequest {
         // 写入 UUID
         buf.put_slice(self.uuid.as_bytes());
 
-        // 写入附加数据长度 (暂时为 0)
-        buf.put_u8(0);
+        // 写入附加数据 (只携带 flow)
+        let addons = encode_addons(&self.flow)?;
+        buf.put_u8(addons.len() as u8);
+        buf.put_slice(&addons);
 
         // 写入命令
         buf.put_u8(self.command as u8);
 
-        // 写入地址
-        self.address.encode(&mut buf);
+        // 写入地址 (Mux 请求不携带地址)
+        if self.command != Command::Mux {
+            if let Some(session_id) = self.mux_session_id {
+                buf.put_u16(0);
+                buf.put_u8(0x00);
+                buf.put_u8(session_id);
+            }
+            self.address.encode(&mut buf);
+        }
 
         Ok(buf)
     }
 }
 
+/// 解析附加数据，返回其中的 flow
+///
+/// Addons 为 protobuf 消息: `string Flow = 1; bytes Seed = 2;`，未知字段按线型跳过。
+fn decode_addons(mut data: &[u8]) -> Result<String> {
+    fn varint(data: &mut &[u8]) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = data.split_first().ok_or_else(|| anyhow!("附加数据被截断"))?;
+            *data = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(anyhow!("附加数据中的 varint 过长"))
+    }
+
+    let mut flow = String::new();
+    while !data.is_empty() {
+        let key = varint(&mut data)?;
+        let skip = match key & 0x07 {
+            0 => {
+                varint(&mut data)?;
+                0
+            }
+            1 => 8,
+            2 => varint(&mut data)? as usize,
+            5 => 4,
+            wire_type => return Err(anyhow!("附加数据中的未知线型: {}", wire_type)),
+        };
+        if data.len() < skip {
+            return Err(anyhow!("附加数据被截断"));
+        }
+        let (value, rest) = data.split_at(skip);
+        if key == (1 << 3 | 2) {
+            flow = String::from_utf8(value.to_vec()).map_err(|_| anyhow!("flow 不是有效的 UTF-8"))?;
+        }
+        data = rest;
+    }
+    Ok(flow)
+}
+
+fn encode_addons(flow: &str) -> Result<Vec<u8>> {
+    if flow.is_empty() {
+        return Ok(Vec::new());
+    }
+    // 标签 + 长度 + 内容都须放进一个字节的附加数据长度
+    if flow.len() > 127 {
+        return Err(anyhow!("flow 过长: {}", flow.len()));
+    }
+    let mut addons = vec![1 << 3 | 2, flow.len() as u8];
+    addons.extend_from_slice(flow.as_bytes());
+    Ok(addons)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -132,6 +218,8 @@ mod tests {
             command: Command::Tcp,
             address: Address::Ipv4(Ipv4Addr::new(1, 1, 1, 1), 443),
             addon_length: 0,
+            flow: String::new(),
+            mux_session_id: None,
         };
 
         let mut buf = request.encode().unwrap();
@@ -154,6 +242,8 @@ mod tests {
             command: Command::Tcp,
             address: Address::Ipv4(Ipv4Addr::new(1, 1, 1, 1), 443),
             addon_length: 0,
+            flow: String::new(),
+            mux_session_id: None,
         };
 
         let mut buf = request.encode().unwrap();
@@ -162,4 +252,81 @@ mod tests {
         let result = VlessRequest::decode(&mut buf, &[uuid2]);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_command_surfaced() {
+        let uuid = Uuid::parse_str("b831381d-6324-4d53-ad4f-8cda48b30811").unwrap();
+
+        let udp = VlessRequest {
+            version: VLESS_VERSION,
+            uuid,
+            command: Command::Udp,
+            address: Address::Ipv4(Ipv4Addr::new(8, 8, 8, 8), 53),
+            addon_length: 0,
+            flow: String::new(),
+            mux_session_id: None,
+        };
+        let mut buf = udp.encode().unwrap();
+        let decoded = VlessRequest::decode(&mut buf, &[uuid]).unwrap();
+        assert_eq!(decoded.command, Command::Udp);
+        assert!(!decoded.is_mux());
+
+        // Mux 请求不携带地址，解码后剩余数据应原样保留给 Mux 帧解析
+        let mux = VlessRequest {
+            command: Command::Mux,
+            address: Address::Domain(MUX_COOL_DOMAIN.to_string(), 0),
+            ..udp.clone()
+        };
+        let mut buf = mux.encode().unwrap();
+        buf.put_slice(&[0xAA, 0xBB]);
+        let decoded = VlessRequest::decode(&mut buf, &[uuid]).unwrap();
+        assert_eq!(decoded.command, Command::Mux);
+        assert!(decoded.is_mux());
+        assert_eq!(&buf[..], &[0xAA, 0xBB]);
+    }
+
+    #[test]
+    fn test_mux_marker_session_id() {
+        let uuid = Uuid::parse_str("b831381d-6324-4d53-ad4f-8cda48b30811").unwrap();
+        let request = VlessRequest {
+            version: VLESS_VERSION,
+            uuid,
+            command: Command::Tcp,
+            address: Address::Domain("example.com".to_string(), 443),
+            addon_length: 0,
+            flow: String::new(),
+            mux_session_id: Some(3),
+        };
+
+        let mut buf = request.encode().unwrap();
+        let decoded = VlessRequest::decode(&mut buf, &[uuid]).unwrap();
+        assert_eq!(decoded.command, Command::Tcp);
+        assert_eq!(decoded.address, request.address);
+        assert_eq!(decoded.mux_session_id, Some(3));
+    }
+
+    #[test]
+    fn test_flow_addons() {
+        let uuid = Uuid::parse_str("b831381d-6324-4d53-ad4f-8cda48b30811").unwrap();
+        let request = VlessRequest {
+            version: VLESS_VERSION,
+            uuid,
+            command: Command::Tcp,
+            address: Address::Domain("example.com".to_string(), 443),
+            addon_length: 0,
+            flow: FLOW_VISION.to_string(),
+            mux_session_id: None,
+        };
+        let mut buf = request.encode().unwrap();
+        let decoded = VlessRequest::decode(&mut buf, &[uuid]).unwrap();
+        assert_eq!(decoded.flow, FLOW_VISION);
+        assert_eq!(decoded.addon_length as usize, 2 + FLOW_VISION.len());
+        assert_eq!(decoded.address, request.address);
+
+        // 未知字段 (Seed 与 varint 字段) 被跳过
+        let addons = [0x12, 0x02, 0xAB, 0xCD, 0x18, 0x96, 0x01, 0x0A, 0x01, b'x'];
+        assert_eq!(decode_addons(&addons).unwrap(), "x");
+        assert!(decode_addons(&[0x0A, 0x05, b'x']).is_err());
+        assert_eq!(decode_addons(&[]).unwrap(), "");
+    }
 }
@@ -2,46 +2,350 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 use tokio::io::{ReadBuf, AsyncRead, AsyncWrite};
 use bytes::Buf;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
 use tokio::net::{TcpListener, TcpStream};
-use tracing::{error, info, warn, debug};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use std::time::Duration;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 use uuid::Uuid;
 
-use crate::config::{Config, Inbound, Security};
-use crate::network::ConnectionManager;
+use crate::config::{Config, ConnectionLimitStage, Inbound, Network, RateLimitScope, Security, SniffingConfig, SockOpt};
+use crate::network::{outbound, AccessLog, ClientPermit, ConnectionManager, HandshakeLimiter, HandshakePermit, Health, Outbound, RateLimitRegistry, SocketOptions};
 use crate::protocol::vless::VlessCodec;
-use crate::transport::{RealityServer, XhttpServer};
+use crate::transport::reality::Accepted;
+use crate::transport::{RealityServer, TlsTerminator, WsServer, XhttpServer};
 use crate::handler::serve_vless;
 
+/// 宽限期结束后，等待被中止的连接正常关闭的时间
+const CLOSE_MARGIN: Duration = Duration::from_secs(1);
+
+/// 连接 ID 计数器，仅用于日志关联
+static NEXT_CONNECTION_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+fn next_connection_id() -> u64 {
+    NEXT_CONNECTION_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// 从任一监听器接受连接
+///
+/// 从第 `start` 个监听器开始轮询，调用方每次换一个起点，繁忙的监听器不会饿死其他监听器
+async fn accept_any(listeners: &[TcpListener], start: usize) -> std::io::Result<(TcpStream, std::net::SocketAddr)> {
+    std::future::poll_fn(|cx| {
+        for i in 0..listeners.len() {
+            if let Poll::Ready(accepted) = listeners[(start + i) % listeners.len()].poll_accept(cx) {
+                return Poll::Ready(accepted);
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
 /// 定义通用的 AsyncStream trait 以支持 TCP 和 TLS 流
 pub trait AsyncStream: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send {}
 impl<T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send> AsyncStream for T {}
 
+/// VLESS 认证结果的回报端，由传输层随流一起交给处理回调
+///
+/// 认证成功后送出用户标签。不需要结果的传输 (TCP、WebSocket、stream-one) 传入 `AuthReport::none()`。
+#[derive(Clone, Default)]
+pub struct AuthReport(Option<Arc<watch::Sender<Option<String>>>>);
+
+impl AuthReport {
+    pub fn none() -> Self {
+        Self(None)
+    }
+
+    /// 创建回报端与等待结果的接收端
+    pub fn channel() -> (Self, watch::Receiver<Option<String>>) {
+        let (tx, rx) = watch::channel(None);
+        (Self(Some(Arc::new(tx))), rx)
+    }
+
+    /// 报告认证成功的用户
+    pub fn authenticated(&self, user: String) {
+        if let Some(tx) = &self.0 {
+            let _ = tx.send(Some(user));
+        }
+    }
+}
+
 /// 代理服务器
 pub struct Server {
     config: Config,
     connection_manager: ConnectionManager,
+    /// 当前生效的配置，热重载时更新
+    config_tx: Arc<watch::Sender<Arc<Config>>>,
+    health: Health,
+    #[cfg(feature = "xdp")]
+    xdp: Option<crate::xdp::XdpHandle>,
+}
+
+/// 配置热重载句柄
+///
+/// 在线生效: 用户列表 (clients)、限速 (rateLimit)、嗅探 (sniffing)、路由 (routing)、出站 (outbounds)、DNS (dns，解析缓存随之清空)、
+/// 连接最长存活时间与闲置超时 (connection.maxLifetime / idleTimeout)。
+/// 需要重启: 入站数量、listen、port、protocol、streamSettings (Reality / XHTTP / WebSocket / sockopt)、日志 (log)、XDP 网卡 (xdp.interface)。
+/// XDP 的限速阈值与黑名单在线生效。
+/// XHTTP 填充为内置的自适应策略，没有可重载的配置项。
+/// XHTTP 直接终结 TLS 时，证书路径不变，但证书文件会重新读取。
+///
+/// 已建立的隧道不受影响，新配置只作用于之后接受的连接。
+#[derive(Clone)]
+pub struct ReloadHandle {
+    config_tx: Arc<watch::Sender<Arc<Config>>>,
+    connection_manager: ConnectionManager,
+    #[cfg(feature = "xdp")]
+    xdp: Option<crate::xdp::XdpHandle>,
+}
+
+impl ReloadHandle {
+    /// 校验并应用新配置
+    ///
+    /// 返回需要重启才能生效的变更描述，这些字段保持旧值。
+    pub fn apply(&self, mut config: Config) -> Result<Vec<String>> {
+        config.validate()?;
+
+        let current = self.config_tx.borrow().clone();
+        if current.inbounds.len() != config.inbounds.len() {
+            return Err(anyhow!(
+                "入站数量变化 ({} -> {})，需要重启才能生效",
+                current.inbounds.len(),
+                config.inbounds.len()
+            ));
+        }
+
+        let mut pending = Vec::new();
+        for (idx, (old, new)) in current.inbounds.iter().zip(config.inbounds.iter_mut()).enumerate() {
+            if old.listen != new.listen || old.port != new.port {
+                pending.push(format!(
+                    "入站 {} 监听地址 {}:{} -> {}:{}",
+                    idx, old.listen, old.port, new.listen, new.port
+                ));
+            }
+            if format!("{:?}", old.protocol) != format!("{:?}", new.protocol) {
+                pending.push(format!("入站 {} 协议 {:?} -> {:?}", idx, old.protocol, new.protocol));
+            }
+            if serde_json::to_value(&old.stream_settings)? != serde_json::to_value(&new.stream_settings)? {
+                pending.push(format!("入站 {} streamSettings", idx));
+            }
+
+            // 保留正在运行的监听参数，使下一次重载仍以实际状态为基准
+            new.listen = old.listen.clone();
+            new.port = old.port;
+            new.protocol = old.protocol.clone();
+            new.stream_settings = old.stream_settings.clone();
+        }
+        if current.log != config.log {
+            pending.push("日志配置 (log)".to_string());
+            config.log = current.log.clone();
+        }
+        if current.xdp.interface != config.xdp.interface {
+            pending.push("XDP 网卡 (xdp.interface)".to_string());
+            config.xdp.interface = current.xdp.interface.clone();
+        }
+        #[cfg(feature = "xdp")]
+        if let Some(xdp) = &self.xdp {
+            xdp.set_ports(protected_ports(&config));
+            xdp.configure(&config.xdp);
+            for &ip in config.xdp.blocklist.iter().filter(|ip| !current.xdp.blocklist.contains(ip)) {
+                xdp.block_ip(ip);
+            }
+            for &ip in current.xdp.blocklist.iter().filter(|ip| !config.xdp.blocklist.contains(ip)) {
+                xdp.unblock_ip(ip);
+            }
+        }
+
+        self.connection_manager
+            .set_rate_limits(Server::build_rate_limits(&config));
+        self.connection_manager.set_max_lifetime(config.connection.max_lifetime());
+        self.connection_manager.set_idle_timeout(config.connection.idle_timeout());
+        self.connection_manager
+            .set_connection_limits(config.connection.max_connections, config.connection.max_connections_per_ip);
+        self.config_tx.send_replace(Arc::new(config));
+        Ok(pending)
+    }
+
+    /// 当前生效的配置
+    pub fn current(&self) -> Arc<Config> {
+        self.config_tx.borrow().clone()
+    }
+}
+
+/// XDP 防火墙保护的端口: 所有入站的监听端口
+#[cfg(feature = "xdp")]
+fn protected_ports(config: &Config) -> Vec<u16> {
+    config.inbounds.iter().map(|inbound| inbound.port).collect()
+}
+
+/// 单个入站中可热重载的部分
+struct LiveInbound {
+    codec: VlessCodec,
+    sniffing: SniffingConfig,
+    block_bittorrent: bool,
+    outbound: Arc<dyn Outbound>,
+    /// 接受与拨出的连接共用的 socket 选项
+    socket: SocketOptions,
+    limit_stage: ConnectionLimitStage,
+}
+
+impl LiveInbound {
+    fn new(config: &Config, index: usize) -> Result<Self> {
+        let inbound = &config.inbounds[index];
+        let settings = &inbound.settings;
+
+        let uuids: Vec<Uuid> = settings
+            .clients
+            .iter()
+            .filter_map(|c| Uuid::parse_str(&c.id).ok())
+            .collect();
+
+        let labels = settings
+            .clients
+            .iter()
+            .filter(|c| !c.email.is_empty())
+            .filter_map(|c| Some((Uuid::parse_str(&c.id).ok()?, c.email.clone())))
+            .collect();
+
+        let mut socket = config.socket.options();
+        socket.no_delay &= inbound.stream_settings.sockopt.tcp_no_delay;
+
+        Ok(Self {
+            codec: VlessCodec::new(uuids).with_labels(labels),
+            sniffing: settings.sniffing.clone(),
+            block_bittorrent: config.routing.block_bittorrent,
+            outbound: outbound::from_config(config, socket)?,
+            socket,
+            limit_stage: config.connection.limit_stage,
+        })
+    }
 }
 
 impl Server {
     /// 创建新的服务器
     pub fn new(config: Config) -> Result<Self> {
+        let rate_limits = Self::build_rate_limits(&config);
+        let mut connection_manager = ConnectionManager::with_rate_limits(rate_limits);
+        connection_manager.set_max_lifetime(config.connection.max_lifetime());
+        connection_manager.set_idle_timeout(config.connection.idle_timeout());
+        connection_manager.set_connection_limits(config.connection.max_connections, config.connection.max_connections_per_ip);
+        if let Some(target) = config.log.access_target() {
+            connection_manager = connection_manager.with_access_log(AccessLog::open(target, config.log.access_format)?);
+            info!("📝 访问日志: {} ({:?})", target, config.log.access_format);
+        }
+        #[cfg(feature = "xdp")]
+        let xdp = config
+            .xdp
+            .interface()
+            .map(|_| crate::xdp::start_xdp(&config.xdp, protected_ports(&config)));
+        #[cfg(not(feature = "xdp"))]
+        if let Some(interface) = config.xdp.interface() {
+            warn!("⚠️ 未以 xdp feature 构建，忽略 XDP 配置 (网卡 {})", interface);
+        }
+        let (config_tx, _) = watch::channel(Arc::new(config.clone()));
         Ok(Self {
             config,
-            connection_manager: ConnectionManager::new(),
+            connection_manager,
+            config_tx: Arc::new(config_tx),
+            health: Health::default(),
+            #[cfg(feature = "xdp")]
+            xdp,
         })
     }
 
+    /// 获取就绪状态，供健康检查端点使用
+    pub fn health(&self) -> Health {
+        self.health.clone()
+    }
+
+    /// 获取连接管理器，供统计层读取活跃连接数与每个来源 IP 的连接数
+    pub fn connection_manager(&self) -> ConnectionManager {
+        self.connection_manager.clone()
+    }
+
+    /// 获取 XDP 防火墙句柄 (未配置 xdp.interface 时为 `None`)，可读取计数或更新黑名单
+    #[cfg(feature = "xdp")]
+    pub fn xdp(&self) -> Option<crate::xdp::XdpHandle> {
+        self.xdp.clone()
+    }
+
+    /// 获取配置热重载句柄
+    pub fn reload_handle(&self) -> ReloadHandle {
+        ReloadHandle {
+            config_tx: self.config_tx.clone(),
+            connection_manager: self.connection_manager.clone(),
+            #[cfg(feature = "xdp")]
+            xdp: self.xdp.clone(),
+        }
+    }
+
+    /// 根据客户端配置构建用户限速表
+    fn build_rate_limits(config: &Config) -> RateLimitRegistry {
+        let mut registry = RateLimitRegistry::new();
+        for inbound in &config.inbounds {
+            for client in &inbound.settings.clients {
+                let Some(limit) = client.rate_limit.as_ref().or(inbound.settings.rate_limit.as_ref()) else {
+                    continue;
+                };
+                if let Ok(uuid) = Uuid::parse_str(&client.id) {
+                    let shared = limit.scope == RateLimitScope::User;
+                    registry.register(uuid, limit.bytes_per_sec, limit.burst(), shared);
+                }
+            }
+        }
+        if !registry.is_empty() {
+            info!("🚦 已启用用户限速");
+        }
+        registry
+    }
+
     /// 运行服务器
     pub async fn run(self) -> Result<()> {
+        self.run_until(std::future::pending::<()>(), Duration::ZERO).await
+    }
+
+    /// 运行服务器，直到 `shutdown` 完成后优雅停机
+    ///
+    /// 停机顺序: 停止接受新连接 (H2 连接发送 GOAWAY，XHTTP 入站不再建立会话) -> 卸载 XDP 程序 -> 在 `grace_period` 内等待活跃转发结束
+    ///
+    /// 宽限期结束时 H2 连接中止剩余的流，之后再等待最多 `CLOSE_MARGIN` 让连接正常关闭
+    pub async fn run_until<F>(self, shutdown: F, grace_period: Duration) -> Result<()>
+    where
+        F: std::future::Future<Output = ()>,
+    {
         let mut handles = vec![];
+        let stop = CancellationToken::new();
 
         // 为每个入站配置启动监听器
-        for inbound in self.config.inbounds.clone() {
+        if self.config.routing.block_bittorrent {
+            info!("🚫 已启用 BitTorrent 屏蔽");
+        }
+
+        // 启用 XDP 时，挂载成功也是就绪的条件
+        #[cfg(feature = "xdp")]
+        let xdp_pending = usize::from(self.xdp.is_some());
+        #[cfg(not(feature = "xdp"))]
+        let xdp_pending = 0;
+        self.health.starting(self.config.inbounds.len() + xdp_pending);
+        #[cfg(feature = "xdp")]
+        if let Some(xdp) = self.xdp.clone() {
+            let health = self.health.clone();
+            tokio::spawn(async move {
+                if xdp.attached().await {
+                    health.xdp_attached();
+                }
+            });
+        }
+        for (index, inbound) in self.config.inbounds.clone().into_iter().enumerate() {
             let connection_manager = self.connection_manager.clone();
-            
+            let config_rx = self.config_tx.subscribe();
+            let stop = stop.clone();
+            let health = self.health.clone();
+
             let handle = tokio::spawn(async move {
-                if let Err(e) = Self::run_inbound(inbound, connection_manager).await {
+                if let Err(e) = Self::run_inbound(index, inbound, connection_manager, config_rx, stop, grace_period, health).await {
                     error!("入站处理失败: {}", e);
                 }
             });
@@ -49,49 +353,88 @@ impl Server {
             handles.push(handle);
         }
 
-        // 等待所有任务完成
+        // 等待停机信号 (监听器全部退出时也直接返回)
+        let all_inbounds = async {
+            for handle in handles.iter_mut() {
+                let _ = handle.await;
+            }
+        };
+        tokio::select! {
+            _ = shutdown => {}
+            _ = all_inbounds => {
+                self.detach_xdp().await;
+                return Ok(());
+            }
+        }
+
+        info!("🛑 收到停机信号，停止接受新连接");
+        self.health.draining();
+        stop.cancel();
+        // 宽限期从停机信号开始计算，与各 H2 连接的期限一致
+        let deadline = tokio::time::Instant::now() + grace_period + CLOSE_MARGIN;
         for handle in handles {
-            handle.await?;
+            // 已在上面等待完成的任务不能再次 poll
+            if !handle.is_finished() {
+                let _ = handle.await;
+            }
+        }
+
+        // 监听器已全部关闭，端口不再由本进程提供服务
+        self.detach_xdp().await;
+
+        // 等待活跃连接结束
+        let mut remaining = self.connection_manager.active_count();
+        if remaining > 0 {
+            info!("⏳ 等待 {} 个活跃连接结束 (最长 {:?})", remaining, grace_period);
+        }
+        while remaining > 0 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            remaining = self.connection_manager.active_count();
         }
 
+        if remaining > 0 {
+            warn!("⚠️ 宽限期结束，强制关闭 {} 个连接", remaining);
+        } else {
+            info!("✅ 所有连接已结束");
+        }
         Ok(())
     }
 
-    /// 运行单个入站配置
-    async fn run_inbound(inbound: Inbound, connection_manager: ConnectionManager) -> Result<()> {
-        let addr = format!("{}:{}", inbound.listen, inbound.port);
-        let sockopt = &inbound.stream_settings.sockopt;
-        
-        // 使用 socket2 创建监听器以支持 TCP Fast Open 和 TCP KeepAlive
+    /// 从网卡卸载 XDP 程序 (未启用时什么也不做)
+    async fn detach_xdp(&self) {
+        #[cfg(feature = "xdp")]
+        if let Some(xdp) = &self.xdp {
+            xdp.shutdown().await;
+        }
+    }
+
+    /// 按 sockopt 创建并绑定一个监听器
+    fn bind_listener(addr: std::net::SocketAddr, sockopt: &SockOpt, v6_only: Option<bool>) -> Result<TcpListener> {
+        // 使用 socket2 创建监听器以支持 TCP Fast Open
         use socket2::{Socket, Domain, Type, Protocol};
-        use std::net::SocketAddr;
-        
-        let socket_addr: SocketAddr = addr.parse()?;
-        let domain = if socket_addr.is_ipv4() {
+
+        let domain = if addr.is_ipv4() {
             Domain::IPV4
         } else {
             Domain::IPV6
         };
-        
+
         let socket = Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
-        
+
         // 设置 SO_REUSEADDR
         socket.set_reuse_address(true)?;
         #[cfg(unix)]
         socket.set_reuse_port(true).ok();
 
-        // =================================================================================
-        // 核心修复: 启用 TCP KeepAlive 以清理半开连接
-        // =================================================================================
-        let keepalive = socket2::TcpKeepalive::new()
-            .with_time(std::time::Duration::from_secs(60))
-            .with_interval(std::time::Duration::from_secs(10))
-            .with_retries(3);
-        
-        if let Err(e) = socket.set_tcp_keepalive(&keepalive) {
-            warn!("⚠️ 无法设置 TCP KeepAlive: {}", e);
-        } else {
-            debug!("✅ TCP KeepAlive 已启用 (Idle:60s, Intvl:10s, Cnt:3)");
+        if let (true, Some(v6_only)) = (addr.is_ipv6(), v6_only) {
+            socket.set_only_v6(v6_only)?;
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(interface) = &sockopt.interface {
+            socket
+                .bind_device(Some(interface.as_bytes()))
+                .map_err(|e| anyhow!("绑定网卡 {} 失败: {}", interface, e))?;
         }
 
         // 启用 TCP Fast Open (如果配置启用)
@@ -110,38 +453,85 @@ impl Server {
                         std::mem::size_of::<libc::c_int>() as libc::socklen_t,
                     );
                 }
-                info!("🚀 TCP Fast Open 已启用 (队列长度: 256)");
             }
         }
-        
-        socket.bind(&socket_addr.into())?;
+
+        socket.bind(&addr.into()).map_err(|e| anyhow!("监听 {} 失败: {}", addr, e))?;
         socket.listen(1024)?;
         socket.set_nonblocking(true)?;
-        
-        let listener = TcpListener::from_std(std::net::TcpListener::from(socket))?;
 
-        info!("🎯 监听 {} (协议: {:?})", addr, inbound.protocol);
+        Ok(TcpListener::from_std(std::net::TcpListener::from(socket))?)
+    }
 
-        // 创建 VLESS 编解码器
-        let uuids: Vec<Uuid> = inbound
-            .settings
-            .clients
+    /// 运行单个入站配置
+    async fn run_inbound(
+        index: usize,
+        inbound: Inbound,
+        connection_manager: ConnectionManager,
+        mut config_rx: watch::Receiver<Arc<Config>>,
+        stop: CancellationToken,
+        grace_period: Duration,
+        health: Health,
+    ) -> Result<()> {
+        let sockopt = &inbound.stream_settings.sockopt;
+        let addrs = inbound
+            .listen
+            .addrs()
             .iter()
-            .filter_map(|c| Uuid::parse_str(&c.id).ok())
-            .collect();
+            .map(|ip| Ok(std::net::SocketAddr::new(ip.parse()?, inbound.port)))
+            .collect::<Result<Vec<_>>>()?;
+        // 同时监听 IPv4 时，IPv6 监听器默认只接受 IPv6，避免双栈的 [::] 与 0.0.0.0 争抢同一端口
+        let v6_only = sockopt.v6_only.or_else(|| addrs.iter().any(|a| a.is_ipv4()).then_some(true));
+        let listeners = addrs
+            .iter()
+            .map(|&addr| Self::bind_listener(addr, sockopt, v6_only))
+            .collect::<Result<Vec<_>>>()?;
+        if sockopt.tcp_fast_open && cfg!(target_os = "linux") {
+            info!("🚀 TCP Fast Open 已启用 (队列长度: 256)");
+        }
+
+        match &sockopt.interface {
+            Some(interface) => info!("🎯 监听 {} 端口 {} 网卡 {} (协议: {:?})", inbound.listen, inbound.port, interface, inbound.protocol),
+            None => info!("🎯 监听 {} 端口 {} (协议: {:?})", inbound.listen, inbound.port, inbound.protocol),
+        }
+        health.listener_bound();
 
-        let codec = VlessCodec::new(uuids);
+        // VLESS 编解码器等可热重载的设置
+        let mut live = LiveInbound::new(&config_rx.borrow_and_update(), index)?;
 
         // 创建 Reality 服务器 (如果启用)
         let reality_server = if matches!(inbound.stream_settings.security, Security::Reality) {
             if let Some(reality_settings) = &inbound.stream_settings.reality_settings {
+                let dest = &reality_settings.dest;
+                let server_name_dests: std::collections::HashMap<String, String> = dest
+                    .server_name_dests()
+                    .map(|(name, dest)| (name.to_string(), dest.to_string()))
+                    .collect();
+                // dest 映射中的 serverName 同样允许
+                let mut server_names = reality_settings.server_names.clone();
+                for name in server_name_dests.keys() {
+                    if !server_names.contains(name) {
+                        server_names.push(name.clone());
+                    }
+                }
                 let reality_config = crate::transport::reality::RealityConfig {
-                    dest: reality_settings.dest.clone(),
-                    server_names: reality_settings.server_names.clone(),
-                    private_key: reality_settings.private_key.clone(),
+                    server_name_dests,
                     public_key: reality_settings.public_key.clone(),
-                    short_ids: reality_settings.short_ids.clone(),
                     fingerprint: reality_settings.fingerprint.clone(),
+                    session_tickets: reality_settings.session_tickets,
+                    handshake_timeout: Duration::from_secs(reality_settings.handshake_timeout),
+                    certificate_file: reality_settings.certificate_file.clone(),
+                    key_file: reality_settings.key_file.clone(),
+                    server_name_certificates: reality_settings.server_name_certificates.clone(),
+                    self_signed: reality_settings.self_signed.clone(),
+                    dest_certificate_refresh: (reality_settings.dest_certificate_refresh > 0)
+                        .then(|| Duration::from_secs(reality_settings.dest_certificate_refresh)),
+                    ..crate::transport::reality::RealityConfig::new(
+                        dest.default_dest().unwrap_or_default(),
+                        server_names,
+                        reality_settings.private_key.clone(),
+                        reality_settings.short_ids.clone(),
+                    )
                 };
                 Some(RealityServer::new(reality_config)?)
             } else {
@@ -168,66 +558,128 @@ impl Server {
                     crate::config::XhttpMode::StreamOne => {
                         crate::transport::xhttp::XhttpMode::StreamOne
                     }
+                    crate::config::XhttpMode::PacketUp => {
+                        crate::transport::xhttp::XhttpMode::PacketUp
+                    }
                 },
                 path: xhttp_settings.path.clone(),
                 host: xhttp_settings.host.clone(),
+                hosts: xhttp_settings.hosts.clone(),
+                h2: xhttp_settings.h2.tuning(),
+                packet_up: xhttp_settings.packet_up.limits(),
+                sessions: xhttp_settings.sessions.limits(),
+                masquerade: xhttp_settings.masquerade.clone(),
+                decoy: xhttp_settings.decoy.clone(),
+                grpc: xhttp_settings.grpc.clone(),
+                shaping: xhttp_settings.shaping.clone(),
+                pipe: xhttp_settings.pipe.clone(),
             };
-            Some(XhttpServer::new(xhttp_config)?)
+            let xhttp_server = XhttpServer::new(xhttp_config)?.with_shutdown(stop.clone(), grace_period);
+            match &xhttp_settings.tls {
+                Some(tls) => Some(xhttp_server.with_tls(TlsTerminator::new(tls.clone())?)),
+                None => Some(xhttp_server),
+            }
         } else {
             None
         };
 
-        // 连接数限制 (防止 OOM)
-        const MAX_CONNECTIONS: usize = 10000;
-        let connection_semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(MAX_CONNECTIONS));
-        
-        info!("🔒 最大并发连接数: {}", MAX_CONNECTIONS);
+        // 创建 WebSocket 服务器 (network 为 ws 时启用)
+        let ws_server = if matches!(inbound.stream_settings.network, Network::Ws) {
+            let ws_settings = inbound.stream_settings.ws_settings.clone().unwrap_or_default();
+            Some(WsServer::new(crate::transport::ws::WsConfig {
+                path: ws_settings.path,
+                host: ws_settings.host,
+                headers: ws_settings.headers.into_iter().collect(),
+            })?)
+        } else {
+            None
+        };
+
+        // 每个来源 IP 同时进行的 Reality 握手数上限
+        let handshake_limiter = inbound
+            .stream_settings
+            .reality_settings
+            .as_ref()
+            .filter(|_| reality_server.is_some())
+            .map(|settings| HandshakeLimiter::new(settings.max_handshakes_per_ip));
 
         // 接受连接循环
+        let mut accept_round = 0usize;
         loop {
-            // 获取连接许可
-            let permit = match connection_semaphore.clone().acquire_owned().await {
-                Ok(p) => p,
-                Err(_) => {
-                    error!("连接限制信号量已关闭");
-                    return Ok(());
-                }
+            accept_round = accept_round.wrapping_add(1);
+            let accepted = tokio::select! {
+                accepted = accept_any(&listeners, accept_round) => accepted,
+                _ = stop.cancelled() => break,
             };
-            
-            match listener.accept().await {
+
+            match accepted {
                 Ok((stream, addr)) => {
-                    // 获取 sockopt 配置
-                    let sockopt = &inbound.stream_settings.sockopt;
-                    
-                    // 应用 TCP No Delay 配置
-                    if sockopt.tcp_no_delay {
-                        if let Err(e) = stream.set_nodelay(true) {
-                            error!("设置 TCP_NODELAY 失败: {}", e);
+                    // 配置已重载: 之后的连接使用新设置
+                    if config_rx.has_changed().unwrap_or(false) {
+                        match LiveInbound::new(&config_rx.borrow_and_update(), index) {
+                            Ok(new_live) => {
+                                live = new_live;
+                                info!("🔄 入站 {} 已应用新配置", index);
+                            }
+                            Err(e) => error!("入站 {} 新配置无效，保持旧配置: {}", index, e),
+                        }
+                        // 证书路径属于 streamSettings，不随重载改变，但文件内容可能已经更新
+                        if let Some(xhttp) = &_xhttp_server {
+                            if let Err(e) = xhttp.reload_tls() {
+                                error!("入站 {} TLS 证书重新加载失败，继续使用原证书: {}", index, e);
+                            }
                         }
                     }
+
+                    // TCP_NODELAY 与 KeepAlive (半开连接由 KeepAlive 清理)
+                    live.socket.apply(&stream);
                     
-                    info!("📥 新连接来自: {}", addr);
+                    // 连接数已达上限: 立即关闭 (按握手后的真实地址计数时推迟到握手之后)
+                    let client_permit = match live.limit_stage {
+                        ConnectionLimitStage::Accept => match connection_manager.try_admit(addr.ip()) {
+                            Some(permit) => Some(permit),
+                            None => {
+                                debug!("🚧 {} 连接数已达上限，关闭连接", addr.ip());
+                                continue;
+                            }
+                        },
+                        ConnectionLimitStage::Handshake => None,
+                    };
+
+                    // 握手槽位已满: 静默关闭
+                    let handshake_permit = match &handshake_limiter {
+                        Some(limiter) => match limiter.try_acquire(addr.ip()) {
+                            Some(permit) => Some(permit),
+                            None => {
+                                debug!("🚧 {} 并发握手过多，关闭连接", addr.ip());
+                                continue;
+                            }
+                        },
+                        None => None,
+                    };
+
+                    // 连接内的所有日志 (含 spawn 出的子任务) 都带上连接 ID 与来源地址
+                    let span = info_span!("conn", id = %format_args!("{:x}", next_connection_id()), peer = %addr);
+                    span.in_scope(|| info!("📥 新连接来自: {}", addr));
 
-                    let codec = codec.clone();
+                    let codec = live.codec.clone();
                     let reality_server = reality_server.clone();
                     let connection_manager = connection_manager.clone();
                     let _xhttp_server = _xhttp_server.clone();
-                    let sniffing_enabled = inbound.settings.sniffing.enabled;
-                    let tcp_no_delay = inbound.stream_settings.sockopt.tcp_no_delay;
+                    let ws_server = ws_server.clone();
+                    let sniffing = live.sniffing.clone();
+                    let block_bittorrent = live.block_bittorrent;
+                    let outbound = live.outbound.clone();
                     let accept_proxy_protocol = inbound.stream_settings.sockopt.accept_proxy_protocol;
 
                     tokio::spawn(async move {
-                        // 持有 permit 直到连接结束，自动释放
-                        let _permit = permit;
-                        
                         if let Err(e) =
-                            Self::handle_client(stream, codec, reality_server, _xhttp_server, connection_manager, sniffing_enabled, tcp_no_delay, accept_proxy_protocol)
+                            Self::handle_client(stream, client_permit, handshake_permit, codec, reality_server, _xhttp_server, ws_server, connection_manager, sniffing, outbound, accept_proxy_protocol, block_bittorrent)
                                 .await
                         {
                             error!("客户端处理失败: {}", e);
                         }
-                        // permit 在这里自动 drop，释放连接槽
-                    });
+                    }.instrument(span));
                 }
                 Err(e) => {
                     if e.kind() == std::io::ErrorKind::WouldBlock {
@@ -242,21 +694,32 @@ impl Server {
                 }
             }
         }
+
+        info!("🔌 监听器已关闭: {} 端口 {}", inbound.listen, inbound.port);
+        Ok(())
     }
 
     /// 处理客户端连接
+    ///
+    /// `client_permit` 为 `None` 时在握手之后按真实客户端地址计入连接数限制
+    #[allow(clippy::too_many_arguments)]
     async fn handle_client(
         mut stream: TcpStream,
+        client_permit: Option<ClientPermit>,
+        handshake_permit: Option<HandshakePermit>,
         codec: VlessCodec,
         reality_server: Option<RealityServer>,
         xhttp_server: Option<XhttpServer>,
+        ws_server: Option<WsServer>,
         connection_manager: ConnectionManager,
-        sniffing_enabled: bool,
-        tcp_no_delay: bool,
+        sniffing: SniffingConfig,
+        outbound: Arc<dyn Outbound>,
         accept_proxy_protocol: bool,
+        block_bittorrent: bool,
     ) -> Result<()> {
+        let peer_addr = stream.peer_addr().ok();
         // 如果启用 Proxy Protocol，先解析获取真实客户端 IP
-        let (stream, _real_client_addr): (Box<dyn AsyncStream>, Option<std::net::SocketAddr>) = if accept_proxy_protocol {
+        let (stream, real_client_addr): (Box<dyn AsyncStream>, Option<std::net::SocketAddr>) = if accept_proxy_protocol {
             use tokio::io::AsyncReadExt;
             let mut pp_buf = [0u8; 512];
             
@@ -301,31 +764,59 @@ impl Server {
 
         // 如果配置了 Reality，执行握手
         let stream: Box<dyn AsyncStream> = if let Some(reality) = reality_server {
-            // Accept generic S
-            let tls_stream = reality.accept(stream).await?;
-            Box::new(tls_stream)
+            let accepted = reality.accept(stream).await;
+            // 握手结束 (成功、失败或决定回落) 后归还槽位，回落转发不占用握手槽位
+            drop(handshake_permit);
+            match accepted? {
+                Accepted::Reality(tls_stream) => tls_stream,
+                Accepted::Fallback(fallback) => {
+                    let _guard = connection_manager.track();
+                    let stats = fallback
+                        .relay(connection_manager.max_lifetime(), connection_manager.idle_timeout())
+                        .await?;
+                    debug!("回落连接结束 ({}): ↑{} ↓{}", stats.reason, stats.up_bytes, stats.down_bytes);
+                    return if stats.reason.is_error() { stats.into_result() } else { Ok(()) };
+                }
+            }
         } else {
             stream
         };
 
+        let source = real_client_addr.or(peer_addr);
+        let _client_permit = match (client_permit, source) {
+            (Some(permit), _) => Some(permit),
+            (None, Some(source)) => match connection_manager.try_admit(source.ip()) {
+                Some(permit) => Some(permit),
+                None => {
+                    debug!("🚧 {} 连接数已达上限，关闭连接", source.ip());
+                    return Ok(());
+                }
+            },
+            (None, None) => None,
+        };
+
         // 定义 VLESS 处理回调
         let codec_clone = codec.clone();
         let connection_manager_clone = connection_manager.clone(); 
         
-        let vless_handler = move |stream: Box<dyn AsyncStream>| {
+        let vless_handler = move |stream: Box<dyn AsyncStream>, auth: AuthReport| {
             let codec = codec_clone.clone();
             let connection_manager = connection_manager_clone.clone();
+            let sniffing = sniffing.clone();
+            let outbound = outbound.clone();
             async move {
-                serve_vless(stream, codec, connection_manager, sniffing_enabled, tcp_no_delay).await
+                serve_vless(stream, auth, source, codec, connection_manager, sniffing, outbound, block_bittorrent).await
             }
         };
 
         // 如果配置了 XHTTP，使用 XHTTP 处理
         if let Some(xhttp) = xhttp_server {
             xhttp.accept(stream, vless_handler).await?;
+        } else if let Some(ws) = ws_server {
+            ws.accept(stream, vless_handler).await?;
         } else {
             // 标准 TCP 模式，直接处理 VLESS
-            vless_handler(stream).await?;
+            vless_handler(stream, AuthReport::none()).await?;
         }
 
         Ok(())
@@ -382,3 +873,77 @@ impl<S: AsyncWrite + Unpin> AsyncWrite for PrefixedStream<S> {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config(port: u16, uuid: &str) -> Config {
+        let json = format!(
+            r#"{{
+                "inbounds": [{{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": {},
+                    "settings": {{ "clients": [{{ "id": "{}" }}] }},
+                    "streamSettings": {{ "network": "tcp", "security": "none" }}
+                }}],
+                "outbounds": [{{ "protocol": "freedom", "tag": "direct" }}]
+            }}"#,
+            port, uuid
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    const UUID_A: &str = "b831381d-6324-4d53-ad4f-8cda48b30811";
+    const UUID_B: &str = "1d3ae6e0-5a3b-4f52-9c0e-6a8c1f9b2d47";
+
+    #[test]
+    fn test_reload_applies_users() {
+        let server = Server::new(test_config(10443, UUID_A)).unwrap();
+        let handle = server.reload_handle();
+
+        let pending = handle.apply(test_config(10443, UUID_B)).unwrap();
+        assert!(pending.is_empty());
+
+        let live = LiveInbound::new(&handle.current(), 0).unwrap();
+        assert!(live.codec.validate_uuid(&Uuid::parse_str(UUID_B).unwrap()));
+        assert!(!live.codec.validate_uuid(&Uuid::parse_str(UUID_A).unwrap()));
+    }
+
+    #[test]
+    fn test_reload_keeps_listener_settings() {
+        let server = Server::new(test_config(10443, UUID_A)).unwrap();
+        let handle = server.reload_handle();
+
+        let pending = handle.apply(test_config(20443, UUID_B)).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(handle.current().inbounds[0].port, 10443);
+        assert_eq!(handle.current().inbounds[0].settings.clients[0].id, UUID_B);
+    }
+
+    #[test]
+    fn test_reload_keeps_access_log() {
+        let server = Server::new(test_config(10443, UUID_A)).unwrap();
+        let handle = server.reload_handle();
+
+        let mut config = test_config(10443, UUID_A);
+        config.log.access = "stdout".to_string();
+        assert_eq!(handle.apply(config).unwrap(), vec!["日志配置 (log)".to_string()]);
+        assert_eq!(handle.current().log.access_target(), None);
+    }
+
+    #[test]
+    fn test_reload_rejects_invalid_config() {
+        let server = Server::new(test_config(10443, UUID_A)).unwrap();
+        let handle = server.reload_handle();
+
+        assert!(handle.apply(test_config(10443, "not-a-uuid")).is_err());
+
+        let mut two_inbounds = test_config(10443, UUID_A);
+        two_inbounds.inbounds.push(two_inbounds.inbounds[0].clone());
+        assert!(handle.apply(two_inbounds).is_err());
+
+        assert_eq!(handle.current().inbounds[0].settings.clients[0].id, UUID_A);
+    }
+}
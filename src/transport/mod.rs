@@ -1,5 +1,9 @@
 pub mod reality;
+pub mod tls;
+pub mod ws;
 pub mod xhttp;
 
 pub use reality::RealityServer;
+pub use tls::{load_key_pair, TlsSettings, TlsTerminator};
+pub use ws::WsServer;
 pub use xhttp::XhttpServer;
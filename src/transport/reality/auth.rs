@@ -1,124 +1,194 @@
 use anyhow::{anyhow, Result};
-use base64::{engine::general_purpose, Engine as _};
-use ring::hmac;
+use ring::{aead, hkdf, hmac};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use super::hello_parser::ClientHelloInfo;
+
+/// ClientHello 握手消息中 session_id 的偏移: 类型(1) + 长度(3) + 版本(2) + random(32) + session_id 长度(1)
+const SESSION_ID_OFFSET: usize = 39;
+
+/// 认证通过的 Reality 客户端
+#[derive(Debug, Clone)]
+pub struct ClientAuth {
+    /// 本次连接的认证密钥，用于签发 Reality 证书
+    pub auth_key: [u8; 32],
+    /// 客户端版本 (x.y.z)
+    pub version: [u8; 3],
+    /// 客户端发起连接时的 Unix 时间
+    pub timestamp: u32,
+    pub short_id: [u8; 8],
+}
 
-/// Reality 认证密钥派生
+/// Reality 客户端认证 (与 xray-core 一致)
+///
+/// - auth_key = HKDF-SHA256(ECDH(privateKey, 客户端 x25519 key_share), salt = random[..20], info = "REALITY")
+/// - session_id = AES-256-GCM(auth_key, nonce = random[20..], aad = session_id 置零的 ClientHello)
+///   加密的 16 字节明文: 版本 x,y,z | 保留 | Unix 时间 (u32) | short_id (8 字节)
 pub struct RealityAuth {
-    private_key_bytes: Vec<u8>,
+    private_key: StaticSecret,
+    short_ids: Vec<[u8; 8]>,
 }
 
 impl RealityAuth {
     /// 创建新的认证处理器
-    pub fn new(private_key: &str) -> Result<Self> {
-        let private_key_bytes = if let Ok(decoded) = general_purpose::STANDARD.decode(private_key) {
-            decoded
-        } else if let Ok(decoded) = general_purpose::URL_SAFE_NO_PAD.decode(private_key) {
-            decoded
-        } else {
-            return Err(anyhow!("Reality 密钥格式不正确，必须是 Base64"));
-        };
-
-        if private_key_bytes.len() != 32 {
-            return Err(anyhow!(
-                "私钥长度必须是 32 字节，当前: {} 字节",
-                private_key_bytes.len()
-            ));
-        }
+    pub fn new(private_key: [u8; 32], short_ids: &[String]) -> Result<Self> {
+        // shortId 最长 8 字节 (16 个十六进制字符)，不足部分补零
+        let short_ids = short_ids
+            .iter()
+            .map(|id| {
+                let bytes = hex::decode(id).map_err(|e| anyhow!("Invalid shortId {:?}: {}", id, e))?;
+                if bytes.len() > 8 {
+                    return Err(anyhow!("shortId {:?} is longer than 8 bytes", id));
+                }
+                let mut padded = [0u8; 8];
+                padded[..bytes.len()].copy_from_slice(&bytes);
+                Ok(padded)
+            })
+            .collect::<Result<_>>()?;
 
-        Ok(Self { private_key_bytes })
-    }
-
-    /// 生成认证标记 (v0.1.15 以后使用标准 Reality HMAC 算法)
-    ///
-    /// Reality 的做法:
-    /// HMAC-SHA256(key=private_key, message=server_random[0..20] + client_random[0..32])
-    pub fn generate_auth_tag(
-        &self,
-        client_random: &[u8; 32],
-        server_random_20: &[u8; 20],
-    ) -> [u8; 32] {
-        let key = hmac::Key::new(hmac::HMAC_SHA256, &self.private_key_bytes);
-
-        let mut msg = Vec::with_capacity(20 + 32);
-        msg.extend_from_slice(server_random_20);
-        msg.extend_from_slice(client_random);
-
-        let signature = hmac::sign(&key, &msg);
-        let mut auth_tag = [0u8; 32];
-        auth_tag.copy_from_slice(signature.as_ref());
-        auth_tag
+        Ok(Self {
+            private_key: StaticSecret::from(private_key),
+            short_ids,
+        })
     }
 
-    /// 在 ServerHello 的 random 字段中注入认证信息
-    pub fn inject_auth_into_random(
-        &self,
-        original_random: &[u8; 32],
-        client_random: &[u8; 32],
-    ) -> [u8; 32] {
-        // 核心修正: 只取 original_random 的前 20 字节作为 HMAC 输入的一部分
-        let auth_tag =
-            self.generate_auth_tag(client_random, original_random[..20].try_into().unwrap());
-
-        let mut modified_random = [0u8; 32];
-        modified_random[..20].copy_from_slice(&original_random[..20]);
-        modified_random[20..32].copy_from_slice(&auth_tag[..12]);
-
-        modified_random
-    }
+    /// 验证 ClientHello 中的 Reality 认证信息，成功时返回本次连接的 auth_key
+    pub fn authenticate(&self, client_hello: &ClientHelloInfo) -> Option<ClientAuth> {
+        let raw = &client_hello.raw;
+        if client_hello.session_id.len() != 32 || raw.len() < SESSION_ID_OFFSET + 32 {
+            return None;
+        }
+        // 认证绑定在第一个 ClientHello 的 X25519 key_share 上，不发 HelloRetryRequest:
+        // 只带其他组的 ClientHello 回落到 dest，由真实站点决定是否要求重试
+        let client_share: [u8; 32] = client_hello.public_key.as_deref()?.try_into().ok()?;
 
-    /// 验证客户端的 Reality 认证
-    ///
-    /// 客户端会在 ClientHello 的 SessionID 中携带认证信息
-    /// 认证格式：HMAC-SHA256(publicKey, clientRandom)的前 8 字节
-    pub fn verify_client_auth(&self, client_random: &[u8; 32], session_id: &[u8]) -> bool {
-        if session_id.len() < 8 {
-            return false;
+        let shared = self.private_key.diffie_hellman(&PublicKey::from(client_share));
+        if !shared.was_contributory() {
+            return None;
         }
 
-        // 计算期望的认证标记
-        let key = hmac::Key::new(hmac::HMAC_SHA256, &self.private_key_bytes);
-        let signature = hmac::sign(&key, client_random);
+        let mut auth_key = [0u8; 32];
+        hkdf::Salt::new(hkdf::HKDF_SHA256, &client_hello.client_random[..20])
+            .extract(shared.as_bytes())
+            .expand(&[b"REALITY"], hkdf::HKDF_SHA256)
+            .ok()?
+            .fill(&mut auth_key)
+            .ok()?;
+
+        let mut aad = raw.clone();
+        aad[SESSION_ID_OFFSET..SESSION_ID_OFFSET + 32].fill(0);
+
+        let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &auth_key).ok()?);
+        let nonce = aead::Nonce::try_assume_unique_for_key(&client_hello.client_random[20..]).ok()?;
+        let mut session_id = client_hello.session_id.clone();
+        let plaintext = key
+            .open_in_place(nonce, aead::Aad::from(aad.as_slice()), &mut session_id)
+            .ok()?;
+
+        let short_id: [u8; 8] = plaintext[8..16].try_into().ok()?;
+        if !self.short_ids.contains(&short_id) {
+            return None;
+        }
 
-        // 使用常数时间比较前 8 字节
-        use subtle::ConstantTimeEq;
-        signature.as_ref()[..8].ct_eq(&session_id[..8]).into()
+        Some(ClientAuth {
+            auth_key,
+            version: [plaintext[0], plaintext[1], plaintext[2]],
+            timestamp: u32::from_be_bytes([plaintext[4], plaintext[5], plaintext[6], plaintext[7]]),
+            short_id,
+        })
     }
 }
 
-/// 从 ServerHello 数据中提取和修改 random 字段
-pub struct ServerHelloModifier {
-    auth: RealityAuth,
+/// Reality 证书的 "签名": HMAC-SHA512(auth_key, Ed25519 公钥)
+///
+/// 客户端用同一个 auth_key 校验该值，而不是通过 CA 验证证书链。
+pub fn certificate_signature(auth_key: &[u8; 32], public_key: &[u8]) -> [u8; 64] {
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA512, auth_key), public_key);
+    let mut signature = [0u8; 64];
+    signature.copy_from_slice(tag.as_ref());
+    signature
 }
 
-impl ServerHelloModifier {
-    pub fn new(private_key: &str) -> Result<Self> {
-        Ok(Self {
-            auth: RealityAuth::new(private_key)?,
-        })
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::reality::hello_parser::parse_client_hello_message;
+
+    const PRIVATE_KEY: [u8; 32] = [b'A'; 32];
+
+    /// 按 xray-core 客户端的方式生成 ClientHello (session_id 为密文)
+    fn sealed_client_hello(short_id: [u8; 8]) -> Vec<u8> {
+        let server_public = PublicKey::from(&StaticSecret::from([b'A'; 32]));
+        let client_secret = StaticSecret::from([7u8; 32]);
+        let random = [0x11u8; 32];
+
+        let mut ext = vec![0x00, 0x33, 0x00, 0x26, 0x00, 0x24, 0x00, 0x1d, 0x00, 0x20];
+        ext.extend_from_slice(PublicKey::from(&client_secret).as_bytes());
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&random);
+        body.push(32);
+        body.extend_from_slice(&[0u8; 32]);
+        body.extend_from_slice(&[0, 2, 0x13, 0x01, 1, 0]);
+        body.extend_from_slice(&(ext.len() as u16).to_be_bytes());
+        body.extend_from_slice(&ext);
+        let mut hello = vec![1];
+        hello.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        hello.extend_from_slice(&body);
+
+        let shared = client_secret.diffie_hellman(&server_public);
+        let mut auth_key = [0u8; 32];
+        hkdf::Salt::new(hkdf::HKDF_SHA256, &random[..20])
+            .extract(shared.as_bytes())
+            .expand(&[b"REALITY"], hkdf::HKDF_SHA256)
+            .unwrap()
+            .fill(&mut auth_key)
+            .unwrap();
+
+        let mut session_id = vec![1, 8, 0, 0, 0x65, 0, 0, 0];
+        session_id.extend_from_slice(&short_id);
+        let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &auth_key).unwrap());
+        let nonce = aead::Nonce::try_assume_unique_for_key(&random[20..]).unwrap();
+        key.seal_in_place_append_tag(nonce, aead::Aad::from(hello.as_slice()), &mut session_id)
+            .unwrap();
+        hello[SESSION_ID_OFFSET..SESSION_ID_OFFSET + 32].copy_from_slice(&session_id);
+        hello
     }
 
-    pub fn modify_server_hello(
-        &self,
-        server_hello_data: &mut [u8],
-        client_random: &[u8; 32],
-    ) -> Result<()> {
-        if server_hello_data.len() < 38 {
-            return Err(anyhow!("ServerHello 数据太短"));
-        }
+    fn parse(hello: Vec<u8>) -> ClientHelloInfo {
+        parse_client_hello_message(hello).unwrap().unwrap()
+    }
 
-        if server_hello_data[0] != 0x02 {
-            return Err(anyhow!("不是 ServerHello 消息"));
-        }
+    #[test]
+    fn test_authenticate_xray_session_id() {
+        let auth = RealityAuth::new(PRIVATE_KEY, &["0123456789abcdef".to_string(), "".to_string()]).unwrap();
+
+        let hello = parse(sealed_client_hello([0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef]));
+        let client = auth.authenticate(&hello).unwrap();
+        assert_eq!(client.version, [1, 8, 0]);
+        assert_eq!(client.timestamp, 0x6500_0000);
 
-        let random_offset = 6;
-        let mut original_random = [0u8; 32];
-        original_random.copy_from_slice(&server_hello_data[random_offset..random_offset + 32]);
+        // 空 shortId 对应全零
+        let hello = parse(sealed_client_hello([0; 8]));
+        assert!(auth.authenticate(&hello).is_some());
+    }
 
-        let modified_random = self
-            .auth
-            .inject_auth_into_random(&original_random, client_random);
+    #[test]
+    fn test_rejects_unknown_short_id_and_tampering() {
+        let auth = RealityAuth::new(PRIVATE_KEY, &["01".to_string()]).unwrap();
+        let hello = sealed_client_hello([0x02, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(auth.authenticate(&parse(hello)).is_none());
+
+        let mut hello = sealed_client_hello([0x01, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(auth.authenticate(&parse(hello.clone())).is_some());
+        // ClientHello 的任何改动都会使 AAD 校验失败
+        let last = hello.len() - 1;
+        hello[last] ^= 1;
+        assert!(auth.authenticate(&parse(hello)).is_none());
+    }
 
-        server_hello_data[random_offset..random_offset + 32].copy_from_slice(&modified_random);
-        Ok(())
+    #[test]
+    fn test_rejects_bad_short_id_config() {
+        assert!(RealityAuth::new(PRIVATE_KEY, &["0123456789abcdef00".to_string()]).is_err());
+        assert!(RealityAuth::new(PRIVATE_KEY, &["xyz".to_string()]).is_err());
     }
 }
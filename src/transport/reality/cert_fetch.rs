@@ -1,136 +1,253 @@
 use anyhow::{anyhow, Result};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls_pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use bytes::BytesMut;
-
-/// 从目标服务器获取 TLS 证书
-pub async fn fetch_certificate(dest: &str) -> Result<Vec<u8>> {
-    // 解析目标地址
-    let addr = if dest.contains(':') {
-        dest.to_string()
-    } else {
-        format!("{}:443", dest)
+use tracing::{debug, info, warn};
+
+/// 获取证书的超时时间
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 证书链缓存有效期
+const FETCH_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// 按 dest 缓存的证书链及获取时间
+static FETCH_CACHE: Lazy<DashMap<String, (Instant, CertificateChain)>> = Lazy::new(DashMap::new);
+
+/// 从目标服务器获取 TLS 证书链 (DER 编码，叶子证书在前)
+///
+/// 同一 dest 在 `FETCH_CACHE_TTL` 内只握手一次。
+pub async fn fetch_certificate(dest: &str) -> Result<Vec<Vec<u8>>> {
+    if let Some(entry) = FETCH_CACHE.get(dest) {
+        let (fetched_at, chain) = entry.value();
+        if fetched_at.elapsed() < FETCH_CACHE_TTL {
+            return Ok(chain.as_ref().clone());
+        }
+    }
+    let chain = fetch_certificate_uncached(dest).await?;
+    FETCH_CACHE.insert(dest.to_string(), (Instant::now(), Arc::new(chain.clone())));
+    Ok(chain)
+}
+
+/// 与 dest 完成一次真实的 TLS 握手并返回其证书链
+///
+/// TLS 1.3 的 Certificate 消息是加密的，无法从明文记录中提取，
+/// 因此握手时不校验证书，握手完成后读取 `peer_certificates()`。
+async fn fetch_certificate_uncached(dest: &str) -> Result<Vec<Vec<u8>>> {
+    let (host, addr) = split_dest(dest);
+    let server_name = ServerName::try_from(host.to_string())
+        .map_err(|e| anyhow!("Invalid dest server name {}: {}", dest, e))?;
+
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(NoVerifier))
+        .with_no_client_auth();
+
+    let fetch = async {
+        let stream = TcpStream::connect(&addr).await
+            .map_err(|e| anyhow!("Failed to connect to {}: {}", addr, e))?;
+        let tls = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect(server_name, stream)
+            .await
+            .map_err(|e| anyhow!("TLS handshake with {} failed: {}", addr, e))?;
+        let (_, conn) = tls.get_ref();
+        let chain = conn
+            .peer_certificates()
+            .filter(|certs| !certs.is_empty())
+            .ok_or_else(|| anyhow!("No certificate found in response"))?
+            .iter()
+            .map(|cert| cert.as_ref().to_vec())
+            .collect();
+        Ok::<_, anyhow::Error>(chain)
     };
-    
-    // 连接到目标服务器
-    let mut stream = TcpStream::connect(&addr).await
-        .map_err(|e| anyhow!("Failed to connect to {}: {}", addr, e))?;
-    
-    // 发送一个简单的 ClientHello 来触发 ServerHello + Certificate
-    let client_hello = build_simple_client_hello(dest)?;
-    stream.write_all(&client_hello).await?;
-    
-    // 读取响应并提取证书
-    let mut buf = BytesMut::with_capacity(8192);
-    
-    loop {
-        let n = stream.read_buf(&mut buf).await?;
-        if n == 0 || buf.len() > 8192 {
-            break;
+    match tokio::time::timeout(FETCH_TIMEOUT, fetch).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow!("Timed out fetching certificate from {}", addr)),
+    }
+}
+
+/// 拆分 dest 为 (主机名, 连接地址)，未指定端口时使用 443；IPv6 地址可带方括号
+fn split_dest(dest: &str) -> (&str, String) {
+    if let Some(rest) = dest.strip_prefix('[') {
+        if let Some((host, port)) = rest.split_once(']') {
+            let addr = if port.is_empty() { format!("{}:443", dest) } else { dest.to_string() };
+            return (host, addr);
         }
     }
-    
-    // 解析 TLS 记录，查找 Certificate 消息
-    extract_certificate_from_response(&buf)
+    if let Ok(ip) = dest.parse::<std::net::IpAddr>() {
+        return (dest, std::net::SocketAddr::new(ip, 443).to_string());
+    }
+    match dest.rsplit_once(':') {
+        Some((host, _)) => (host, dest.to_string()),
+        None => (dest, format!("{}:443", dest)),
+    }
 }
 
-fn build_simple_client_hello(server_name: &str) -> Result<Vec<u8>> {
-    use bytes::BufMut;
-    
-    let sni = server_name.split(':').next().unwrap_or(server_name);
-    
-    let mut hello = BytesMut::new();
-    
-    // TLS Record Header
-    hello.put_u8(0x16); // ContentType: Handshake
-    hello.put_u16(0x0303); // Version: TLS 1.2
-    
-    // 先占位长度
-    let len_pos = hello.len();
-    hello.put_u16(0);
-    
-    // Handshake Header
-    hello.put_u8(0x01); // HandshakeType: ClientHello
-    let hs_len_pos = hello.len();
-    hello.put_u8(0); hello.put_u8(0); hello.put_u8(0);
-    
-    // ClientHello
-    hello.put_u16(0x0303); // Version: TLS 1.2
-    
-    // Random (32 bytes)
-    use rand::RngCore;
-    let mut random = [0u8; 32];
-    rand::rngs::OsRng.fill_bytes(&mut random);
-    hello.put_slice(&random);
-    
-    // Session ID (empty)
-    hello.put_u8(0);
-    
-    // Cipher Suites
-    hello.put_u16(2); // Length
-    hello.put_u16(0x1301); // TLS_AES_128_GCM_SHA256
-    
-    // Compression Methods
-    hello.put_u8(1); // Length
-    hello.put_u8(0); // null
-    
-    // Extensions
-    let mut extensions = BytesMut::new();
-    
-    // SNI Extension
-    extensions.put_u16(0x0000); // Type: server_name
-    let mut sni_data = BytesMut::new();
-    sni_data.put_u16((sni.len() + 3) as u16); // ServerNameList length
-    sni_data.put_u8(0); // NameType: host_name
-    sni_data.put_u16(sni.len() as u16);
-    sni_data.put_slice(sni.as_bytes());
-    extensions.put_u16(sni_data.len() as u16);
-    extensions.put_slice(&sni_data);
-    
-    // Supported Versions (TLS 1.3)
-    extensions.put_u16(0x002b);
-    extensions.put_u16(3);
-    extensions.put_u8(2);
-    extensions.put_u16(0x0304);
-    
-    hello.put_u16(extensions.len() as u16);
-    hello.put_slice(&extensions);
-    
-    // 回填长度
-    let total_len = hello.len() - len_pos - 2;
-    hello[len_pos..len_pos+2].copy_from_slice(&(total_len as u16).to_be_bytes());
-    
-    let hs_len = hello.len() - hs_len_pos - 3;
-    let hs_len_bytes = (hs_len as u32).to_be_bytes();
-    hello[hs_len_pos..hs_len_pos+3].copy_from_slice(&hs_len_bytes[1..4]);
-    
-    Ok(hello.to_vec())
+/// 接受任意证书的校验器 (只需要拿到证书链，不关心其是否可信)
+#[derive(Debug)]
+struct NoVerifier;
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        rustls::crypto::ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// DER 编码的证书链
+type CertificateChain = Arc<Vec<Vec<u8>>>;
+
+/// dest 证书链缓存
+///
+/// 刷新失败 (dest 暂时不可达) 时保留上一次成功获取的证书链。
+#[derive(Clone)]
+pub struct CertificateCache {
+    dest: String,
+    chain: Arc<RwLock<Option<CertificateChain>>>,
 }
 
-fn extract_certificate_from_response(data: &[u8]) -> Result<Vec<u8>> {
-    let mut pos = 0;
-    
-    while pos + 5 < data.len() {
-        let content_type = data[pos];
-        let record_len = u16::from_be_bytes([data[pos+3], data[pos+4]]) as usize;
-        
-        if pos + 5 + record_len > data.len() {
-            break;
+impl CertificateCache {
+    pub fn new(dest: impl Into<String>) -> Self {
+        Self {
+            dest: dest.into(),
+            chain: Arc::new(RwLock::new(None)),
         }
-        
-        // 查找 Handshake 记录
-        if content_type == 0x16 {
-            let payload = &data[pos+5..pos+5+record_len];
-            
-            // 查找 Certificate 消息 (type 11)
-            if payload.len() > 0 && payload[0] == 11 {
-                // 返回整个 Certificate 握手消息（包括 type + length）
-                return Ok(payload.to_vec());
+    }
+
+    /// 当前缓存的证书链
+    pub fn get(&self) -> Option<CertificateChain> {
+        self.chain.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// 直接设置证书链
+    pub fn set(&self, chain: Vec<Vec<u8>>) {
+        *self.chain.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(chain));
+    }
+
+    /// 从 dest 重新获取证书链 (绕过 TTL 缓存)，失败时缓存保持不变
+    pub async fn refresh(&self) -> Result<()> {
+        let chain = fetch_certificate_uncached(&self.dest).await?;
+        debug!("Fetched {} certificate(s) from {}", chain.len(), self.dest);
+        self.set(chain);
+        Ok(())
+    }
+
+    /// 立即获取一次，之后按 `interval` 定期刷新
+    pub fn spawn_refresh(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match cache.refresh().await {
+                    Ok(()) => info!("已更新 {} 的证书链", cache.dest),
+                    Err(e) if cache.get().is_some() => {
+                        warn!("dest 暂时不可达，继续使用缓存的证书链: {}", e)
+                    }
+                    Err(e) => warn!("无法获取 {} 的证书链: {}", cache.dest, e),
+                }
+                tokio::time::sleep(interval).await;
             }
-        }
-        
-        pos += 5 + record_len;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls_pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    /// 启动只接受 `accepts` 次握手的本地 rustls 服务器，返回端口和证书
+    async fn spawn_tls_server(accepts: usize) -> (u16, Vec<u8>) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_der = cert.cert.der().to_vec();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()));
+
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![CertificateDer::from(cert_der.clone())], key)
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            for _ in 0..accepts {
+                let (stream, _) = listener.accept().await.unwrap();
+                let _ = acceptor.accept(stream).await;
+            }
+        });
+        (port, cert_der)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_certificate_chain() {
+        let (port, cert_der) = spawn_tls_server(1).await;
+        let chain = fetch_certificate_uncached(&format!("localhost:{}", port)).await.unwrap();
+        assert_eq!(chain, vec![cert_der]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_certificate_is_cached() {
+        // 服务器只接受一次握手，第二次必须命中缓存
+        let (port, cert_der) = spawn_tls_server(1).await;
+        let dest = format!("localhost:{}", port);
+        assert_eq!(fetch_certificate(&dest).await.unwrap(), vec![cert_der.clone()]);
+        assert_eq!(fetch_certificate(&dest).await.unwrap(), vec![cert_der]);
+    }
+
+    #[test]
+    fn test_split_dest() {
+        assert_eq!(split_dest("www.apple.com"), ("www.apple.com", "www.apple.com:443".to_string()));
+        assert_eq!(split_dest("www.apple.com:8443"), ("www.apple.com", "www.apple.com:8443".to_string()));
+        assert_eq!(split_dest("127.0.0.1:443"), ("127.0.0.1", "127.0.0.1:443".to_string()));
+        assert_eq!(split_dest("[2001:db8::1]:8443"), ("2001:db8::1", "[2001:db8::1]:8443".to_string()));
+        assert_eq!(split_dest("[2001:db8::1]"), ("2001:db8::1", "[2001:db8::1]:443".to_string()));
+        assert_eq!(split_dest("2001:db8::1"), ("2001:db8::1", "[2001:db8::1]:443".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_failure_keeps_cached_chain() {
+        // 端口 1 上没有服务
+        let cache = CertificateCache::new("127.0.0.1:1");
+        cache.set(vec![vec![0x30, 0x00]]);
+
+        assert!(cache.refresh().await.is_err());
+        assert_eq!(cache.get().unwrap().as_slice(), &[vec![0x30, 0x00]]);
     }
-    
-    Err(anyhow!("No certificate found in response"))
 }
@@ -0,0 +1,769 @@
+//! Reality 客户端，与 xray-core 的 `reality.UClient` 兼容
+//!
+//! 认证信息封装在 ClientHello 的 session_id 中；只有叶子证书的签名位置是
+//! HMAC-SHA512(auth_key, 公钥) 时才认为对端是 Reality 服务端。
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use bytes::{Buf, BytesMut};
+use rand::rngs::OsRng;
+use ring::{aead, hkdf, hmac, signature};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::debug;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use super::crypto::{certificate_verify_input, CipherSuite, TlsKeys, TranscriptHash};
+use super::stream::{TlsStream, MAX_RECORD_PLAINTEXT};
+use super::tls::{
+    HandshakeType, ServerHello, CHANGE_CIPHER_SPEC, GROUP_X25519, HRR_RANDOM, VERSION_TLS12, VERSION_TLS13,
+};
+
+/// ClientHello 握手消息中 session_id 的偏移: 类型(1) + 长度(3) + 版本(2) + random(32) + session_id 长度(1)
+const SESSION_ID_OFFSET: usize = 39;
+
+/// ed25519 (RFC 8446 §4.2.3)
+const SCHEME_ED25519: u16 = 0x0807;
+
+/// Ed25519 SubjectPublicKeyInfo 的 DER 前缀 (RFC 8410)，其后是 32 字节公钥
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// Reality 客户端配置
+#[derive(Debug, Clone)]
+pub struct RealityClientConfig {
+    /// ClientHello 中的 SNI，必须在服务端的 serverNames 中
+    pub server_name: String,
+    /// 服务端 X25519 公钥 (Base64 编码)
+    pub public_key: String,
+    /// Short ID (十六进制，最长 16 个字符)
+    pub short_id: String,
+    /// TLS 指纹类型 (chrome, firefox, ...)
+    pub fingerprint: String,
+}
+
+/// 可模仿的 ClientHello 形态
+///
+/// 基于 Chromium 的指纹 (edge、android 等) 与未知指纹都使用 Chrome 的布局，扩展顺序固定，不做随机排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fingerprint {
+    Chrome,
+    Firefox,
+}
+
+impl Fingerprint {
+    fn from_name(name: &str) -> Self {
+        if name.eq_ignore_ascii_case("firefox") {
+            Self::Firefox
+        } else {
+            Self::Chrome
+        }
+    }
+}
+
+/// Reality 客户端
+#[derive(Clone)]
+pub struct RealityClient {
+    server_name: String,
+    server_public: PublicKey,
+    short_id: [u8; 8],
+    fingerprint: Fingerprint,
+}
+
+impl RealityClient {
+    pub fn new(config: RealityClientConfig) -> Result<Self> {
+        let public_key = general_purpose::URL_SAFE_NO_PAD
+            .decode(&config.public_key)
+            .or_else(|_| general_purpose::STANDARD.decode(&config.public_key))
+            .map_err(|_| anyhow!("Reality 公钥格式不正确，必须是 Base64"))?;
+        let public_key: [u8; 32] = public_key
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow!("公钥长度必须是 32 字节，当前: {} 字节", public_key.len()))?;
+
+        let short_id = hex::decode(&config.short_id)
+            .map_err(|e| anyhow!("Invalid shortId {:?}: {}", config.short_id, e))?;
+        if short_id.len() > 8 {
+            return Err(anyhow!(
+                "shortId {:?} is longer than 8 bytes",
+                config.short_id
+            ));
+        }
+        let mut padded = [0u8; 8];
+        padded[..short_id.len()].copy_from_slice(&short_id);
+
+        if config.server_name.is_empty() || config.server_name.len() > 255 {
+            return Err(anyhow!("Invalid serverName {:?}", config.server_name));
+        }
+
+        Ok(Self {
+            server_name: config.server_name,
+            server_public: PublicKey::from(public_key),
+            short_id: padded,
+            fingerprint: Fingerprint::from_name(&config.fingerprint),
+        })
+    }
+
+    /// 在 `stream` 上完成 Reality 握手，返回应用数据流
+    pub async fn connect<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        mut stream: S,
+    ) -> Result<TlsStream<S>> {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let random: [u8; 32] = rand::random();
+        let mut hello = self.client_hello(&random, PublicKey::from(&secret).as_bytes());
+        let auth_key = self.seal_session_id(&mut hello, &random, &secret)?;
+
+        // 与浏览器一致，第一条记录的版本号写 TLS 1.0，兼容中间设备
+        stream.write_all(&record(22, 0x0301, &hello)).await?;
+        stream.flush().await?;
+
+        let mut buf = BytesMut::with_capacity(MAX_RECORD_PLAINTEXT);
+        let (content_type, payload) = read_record(&mut stream, &mut buf).await?;
+        let server_hello = match content_type {
+            22 => payload.to_vec(),
+            21 => {
+                return Err(anyhow!(
+                    "Server sent alert {} instead of ServerHello",
+                    payload.get(1).copied().unwrap_or(0)
+                ))
+            }
+            other => {
+                return Err(anyhow!(
+                    "Unexpected record type {} before ServerHello",
+                    other
+                ))
+            }
+        };
+
+        let fields = ServerHello::from_raw(server_hello.clone()).parse()?;
+        if fields.random == HRR_RANDOM {
+            return Err(anyhow!("HelloRetryRequest is not supported"));
+        }
+        if fields.selected_version != Some(VERSION_TLS13) {
+            return Err(anyhow!("Server did not negotiate TLS 1.3"));
+        }
+        if fields.session_id != hello[SESSION_ID_OFFSET..SESSION_ID_OFFSET + 32] {
+            return Err(anyhow!("ServerHello does not echo our session_id"));
+        }
+        let suite = CipherSuite::from_u16(fields.cipher_suite).ok_or_else(|| {
+            anyhow!(
+                "Server selected unknown cipher suite {:#06x}",
+                fields.cipher_suite
+            )
+        })?;
+        let server_share: [u8; 32] = match fields.key_share {
+            Some((GROUP_X25519, key)) => key
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow!("Invalid X25519 key share"))?,
+            _ => return Err(anyhow!("ServerHello has no X25519 key share")),
+        };
+
+        let shared = secret.diffie_hellman(&PublicKey::from(server_share));
+        if !shared.was_contributory() {
+            return Err(anyhow!("Server key share is a low-order point"));
+        }
+
+        let mut transcript = TranscriptHash::new(suite);
+        transcript.add(&hello);
+        transcript.add(&server_hello);
+        let (hs_keys, secrets) =
+            TlsKeys::derive_handshake_keys(suite, shared.as_bytes(), &transcript.current_hash())?;
+        hs_keys.log_handshake_secrets(&random);
+        let hs_keys = hs_keys.into_peer_view();
+
+        // 依次为 EncryptedExtensions、Certificate、CertificateVerify、Finished
+        const EXPECTED: [HandshakeType; 4] = [
+            HandshakeType::EncryptedExtensions,
+            HandshakeType::Certificate,
+            HandshakeType::CertificateVerify,
+            HandshakeType::Finished,
+        ];
+        let mut received = 0;
+        let mut messages = Vec::new();
+        let mut read_seq = 0;
+        let mut cert_public_key = None;
+        while received < EXPECTED.len() {
+            let (content_type, mut payload) = read_record(&mut stream, &mut buf).await?;
+            match content_type {
+                20 => continue,
+                23 => {}
+                other => return Err(anyhow!("Unexpected record type {} during handshake", other)),
+            }
+            let header = record_header(23, payload.len());
+            let (inner_type, len) =
+                hs_keys.decrypt_client_record(read_seq, &header, &mut payload)?;
+            read_seq += 1;
+            match inner_type {
+                22 => messages.extend_from_slice(&payload[..len]),
+                21 => {
+                    return Err(anyhow!(
+                        "Server sent alert {}",
+                        payload.get(1).copied().unwrap_or(0)
+                    ))
+                }
+                other => {
+                    return Err(anyhow!(
+                        "Unexpected content type {} during handshake",
+                        other
+                    ))
+                }
+            }
+
+            while let Some(msg) = take_message(&mut messages) {
+                let expected = *EXPECTED
+                    .get(received)
+                    .ok_or_else(|| anyhow!("Unexpected handshake message after Finished"))?;
+                if msg[0] != expected as u8 {
+                    return Err(anyhow!(
+                        "Expected {:?}, got handshake message {}",
+                        expected,
+                        msg[0]
+                    ));
+                }
+
+                match expected {
+                    HandshakeType::Certificate => {
+                        cert_public_key = Some(verify_reality_certificate(&msg, &auth_key)?);
+                    }
+                    HandshakeType::CertificateVerify => {
+                        let public_key = cert_public_key
+                            .ok_or_else(|| anyhow!("CertificateVerify without Certificate"))?;
+                        verify_certificate_verify(&msg, &public_key, &transcript.current_hash())?;
+                    }
+                    HandshakeType::Finished => {
+                        TlsKeys::verify_finished(
+                            suite,
+                            &secrets.server_traffic_secret,
+                            &transcript.current_hash(),
+                            &msg[4..],
+                        )?;
+                    }
+                    _ => {}
+                }
+                transcript.add(&msg);
+                received += 1;
+            }
+        }
+        if !messages.is_empty() {
+            return Err(anyhow!("Trailing data after server Finished"));
+        }
+
+        let handshake_hash = transcript.current_hash();
+        let verify_data =
+            TlsKeys::calculate_verify_data(suite, &secrets.client_traffic_secret, &handshake_hash)?;
+        let mut finished = vec![HandshakeType::Finished as u8, 0, 0, verify_data.len() as u8];
+        finished.extend_from_slice(&verify_data);
+
+        let mut flight = CHANGE_CIPHER_SPEC.to_vec();
+        flight.extend_from_slice(&hs_keys.encrypt_server_record(0, &finished, 22)?);
+        stream.write_all(&flight).await?;
+        stream.flush().await?;
+
+        debug!("Reality 客户端握手完成 ({:?})", suite);
+        let app_keys = TlsKeys::derive_application_keys(&secrets, &handshake_hash)?;
+        app_keys.log_application_secrets(&random);
+        // 证书签名已用 auth_key 校验，到这里服务端必然通过了 Reality 认证
+        Ok(TlsStream::new_client(stream, app_keys, buf).with_session(Some(self.server_name.clone()), true))
+    }
+
+    /// 把 [版本 | Unix 时间 | short id] 封装进 session_id，返回与服务端共享的 auth_key
+    fn seal_session_id(
+        &self,
+        hello: &mut [u8],
+        random: &[u8; 32],
+        secret: &StaticSecret,
+    ) -> Result<[u8; 32]> {
+        let shared = secret.diffie_hellman(&self.server_public);
+        let mut auth_key = [0u8; 32];
+        hkdf::Salt::new(hkdf::HKDF_SHA256, &random[..20])
+            .extract(shared.as_bytes())
+            .expand(&[b"REALITY"], hkdf::HKDF_SHA256)
+            .and_then(|okm| okm.fill(&mut auth_key))
+            .map_err(|_| anyhow!("HKDF failed"))?;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as u32;
+        let mut plaintext = client_version().to_vec();
+        plaintext.push(0);
+        plaintext.extend_from_slice(&now.to_be_bytes());
+        plaintext.extend_from_slice(&self.short_id);
+
+        let key = aead::LessSafeKey::new(
+            aead::UnboundKey::new(&aead::AES_256_GCM, &auth_key)
+                .map_err(|_| anyhow!("Invalid auth key"))?,
+        );
+        let nonce = aead::Nonce::try_assume_unique_for_key(&random[20..])
+            .map_err(|_| anyhow!("Nonce err"))?;
+        key.seal_in_place_append_tag(nonce, aead::Aad::from(&*hello), &mut plaintext)
+            .map_err(|_| anyhow!("Failed to seal session_id"))?;
+        hello[SESSION_ID_OFFSET..SESSION_ID_OFFSET + 32].copy_from_slice(&plaintext);
+        Ok(auth_key)
+    }
+
+    /// 构造 session_id 全零的 ClientHello 握手消息
+    fn client_hello(&self, random: &[u8; 32], key_share: &[u8; 32]) -> Vec<u8> {
+        let mut sni = ((self.server_name.len() + 3) as u16).to_be_bytes().to_vec();
+        sni.push(0);
+        sni.extend_from_slice(&(self.server_name.len() as u16).to_be_bytes());
+        sni.extend_from_slice(self.server_name.as_bytes());
+        let alpn = [
+            0x00, 0x0c, 0x02, b'h', b'2', 0x08, b'h', b't', b't', b'p', b'/', b'1', b'.', b'1',
+        ];
+
+        let (cipher_suites, extensions) = match self.fingerprint {
+            Fingerprint::Chrome => {
+                let grease = grease_values();
+                let mut shares = grease[2].to_be_bytes().to_vec();
+                shares.extend_from_slice(&[0x00, 0x01, 0x00]);
+                shares.extend_from_slice(&GROUP_X25519.to_be_bytes());
+                shares.extend_from_slice(&32u16.to_be_bytes());
+                shares.extend_from_slice(key_share);
+
+                let suites = list16(&[
+                    grease[0], 0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9,
+                    0xcca8, 0xc013, 0xc014, 0x009c, 0x009d, 0x002f, 0x0035,
+                ]);
+                let extensions = [
+                    extension(grease[1], &[]),
+                    extension(0x0000, &sni),
+                    extension(0x0017, &[]),
+                    extension(0xff01, &[0x00]),
+                    extension(0x000a, &list16(&[grease[2], GROUP_X25519, 0x0017, 0x0018])),
+                    extension(0x000b, &[0x01, 0x00]),
+                    extension(0x0023, &[]),
+                    extension(0x0010, &alpn),
+                    extension(0x0005, &[0x01, 0x00, 0x00, 0x00, 0x00]),
+                    // Reality 证书总是 Ed25519，因此必须列出
+                    extension(
+                        0x000d,
+                        &list16(&[
+                            0x0403,
+                            0x0804,
+                            0x0401,
+                            0x0503,
+                            0x0805,
+                            0x0501,
+                            0x0806,
+                            0x0601,
+                            SCHEME_ED25519,
+                        ]),
+                    ),
+                    extension(0x0012, &[]),
+                    extension(0x0033, &with_len16(&shares)),
+                    extension(0x002d, &[0x01, 0x01]),
+                    extension(0x002b, &list8(&[grease[3], VERSION_TLS13, VERSION_TLS12])),
+                    extension(0x001b, &[0x02, 0x00, 0x02]),
+                    extension(0x4469, &[0x00, 0x03, 0x02, b'h', b'2']),
+                    extension(grease[4], &[0x00]),
+                ]
+                .concat();
+                (suites, extensions)
+            }
+            Fingerprint::Firefox => {
+                let mut shares = GROUP_X25519.to_be_bytes().to_vec();
+                shares.extend_from_slice(&32u16.to_be_bytes());
+                shares.extend_from_slice(key_share);
+
+                let suites = list16(&[
+                    0x1301, 0x1303, 0x1302, 0xc02b, 0xc02f, 0xcca9, 0xcca8, 0xc02c, 0xc030, 0xc00a,
+                    0xc009, 0xc013, 0xc014, 0x009c, 0x009d, 0x002f, 0x0035,
+                ]);
+                let extensions = [
+                    extension(0x0000, &sni),
+                    extension(0x0017, &[]),
+                    extension(0xff01, &[0x00]),
+                    extension(
+                        0x000a,
+                        &list16(&[GROUP_X25519, 0x0017, 0x0018, 0x0019, 0x0100, 0x0101]),
+                    ),
+                    extension(0x000b, &[0x01, 0x00]),
+                    extension(0x0023, &[]),
+                    extension(0x0010, &alpn),
+                    extension(0x0005, &[0x01, 0x00, 0x00, 0x00, 0x00]),
+                    extension(0x0022, &list16(&[0x0403, 0x0503, 0x0603, 0x0203])),
+                    extension(0x0033, &with_len16(&shares)),
+                    extension(0x002b, &list8(&[VERSION_TLS13, VERSION_TLS12])),
+                    extension(
+                        0x000d,
+                        &list16(&[
+                            0x0403,
+                            0x0503,
+                            0x0603,
+                            0x0804,
+                            0x0805,
+                            0x0806,
+                            0x0401,
+                            0x0501,
+                            0x0601,
+                            0x0203,
+                            0x0201,
+                            SCHEME_ED25519,
+                        ]),
+                    ),
+                    extension(0x002d, &[0x01, 0x01]),
+                    extension(0x001c, &[0x40, 0x01]),
+                ]
+                .concat();
+                (suites, extensions)
+            }
+        };
+
+        let mut body = VERSION_TLS12.to_be_bytes().to_vec();
+        body.extend_from_slice(random);
+        body.push(32);
+        body.extend_from_slice(&[0u8; 32]);
+        body.extend_from_slice(&cipher_suites);
+        body.extend_from_slice(&[0x01, 0x00]);
+
+        // BoringSSL 把 256 到 511 字节之间的 ClientHello 填充到 512 字节
+        let mut extensions = extensions;
+        let unpadded = 4 + body.len() + 2 + extensions.len();
+        if self.fingerprint == Fingerprint::Chrome && unpadded > 0xff && unpadded < 0x200 {
+            let padding = (0x200 - unpadded).saturating_sub(4).max(1);
+            extensions.extend_from_slice(&extension(0x0015, &vec![0u8; padding]));
+        }
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut hello = vec![HandshakeType::ClientHello as u8];
+        hello.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        hello.extend_from_slice(&body);
+        hello
+    }
+}
+
+/// 报告给服务端的客户端版本: 本 crate 的 x.y.z
+fn client_version() -> [u8; 3] {
+    [
+        env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0),
+        env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0),
+        env!("CARGO_PKG_VERSION_PATCH").parse().unwrap_or(0),
+    ]
+}
+
+/// Chrome 使用 GREASE 的五个位置各取一个不同的值 (RFC 8701)
+fn grease_values() -> [u16; 5] {
+    let mut values = [0u16; 5];
+    let mut next = rand::random::<u8>() >> 4;
+    for value in values.iter_mut() {
+        let b = (next << 4) | 0x0a;
+        *value = u16::from_be_bytes([b, b]);
+        next = (next + 1) & 0x0f;
+    }
+    values
+}
+
+/// 校验叶子证书的 HMAC，返回其中的 Ed25519 公钥
+fn verify_reality_certificate(msg: &[u8], auth_key: &[u8; 32]) -> Result<[u8; 32]> {
+    // 类型(1) 长度(3) context 长度(1) context 列表长度(3) 证书长度(3) 证书
+    let context_len = *msg.get(4).ok_or_else(|| anyhow!("Truncated Certificate"))? as usize;
+    let at = 5 + context_len + 3;
+    let len_bytes = msg
+        .get(at..at + 3)
+        .ok_or_else(|| anyhow!("Empty certificate chain"))?;
+    let cert_len = u32::from_be_bytes([0, len_bytes[0], len_bytes[1], len_bytes[2]]) as usize;
+    let cert = msg
+        .get(at + 3..at + 3 + cert_len)
+        .ok_or_else(|| anyhow!("Truncated Certificate"))?;
+
+    let key_at = cert
+        .windows(ED25519_SPKI_PREFIX.len())
+        .position(|w| w == ED25519_SPKI_PREFIX)
+        .ok_or_else(|| anyhow!("Server certificate is not a Reality certificate"))?
+        + ED25519_SPKI_PREFIX.len();
+    let public_key: [u8; 32] = cert
+        .get(key_at..key_at + 32)
+        .and_then(|k| k.try_into().ok())
+        .ok_or_else(|| anyhow!("Truncated Ed25519 public key"))?;
+    if cert.len() < key_at + 32 + 64 {
+        return Err(anyhow!("Truncated certificate signature"));
+    }
+
+    hmac::verify(
+        &hmac::Key::new(hmac::HMAC_SHA512, auth_key),
+        &public_key,
+        &cert[cert.len() - 64..],
+    )
+    .map_err(|_| anyhow!("Reality certificate signature mismatch (wrong publicKey or shortId?)"))?;
+    Ok(public_key)
+}
+
+/// 以截至 Certificate 的 transcript 校验 Ed25519 CertificateVerify
+fn verify_certificate_verify(
+    msg: &[u8],
+    public_key: &[u8; 32],
+    transcript_hash: &[u8],
+) -> Result<()> {
+    let scheme = msg
+        .get(4..6)
+        .ok_or_else(|| anyhow!("Truncated CertificateVerify"))?;
+    if scheme != SCHEME_ED25519.to_be_bytes() {
+        return Err(anyhow!(
+            "Unexpected CertificateVerify scheme {:02x?}",
+            scheme
+        ));
+    }
+    let signature = msg
+        .get(8..)
+        .ok_or_else(|| anyhow!("Truncated CertificateVerify"))?;
+    signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+        .verify(&certificate_verify_input(transcript_hash), signature)
+        .map_err(|_| anyhow!("CertificateVerify signature mismatch"))
+}
+
+/// 从 `buf` 中取出下一条完整的握手消息
+fn take_message(buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+    if buf.len() < 4 {
+        return None;
+    }
+    let len = 4 + u32::from_be_bytes([0, buf[1], buf[2], buf[3]]) as usize;
+    if buf.len() < len {
+        return None;
+    }
+    let rest = buf.split_off(len);
+    Some(std::mem::replace(buf, rest))
+}
+
+/// 读取一条记录，多读到的数据留在 `buf` 中
+async fn read_record<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+) -> Result<(u8, BytesMut)> {
+    loop {
+        if buf.len() >= 5 {
+            let length = u16::from_be_bytes([buf[3], buf[4]]) as usize;
+            if length > MAX_RECORD_PLAINTEXT + 256 {
+                return Err(anyhow!("Record too large: {} bytes", length));
+            }
+            if buf.len() >= 5 + length {
+                let content_type = buf[0];
+                buf.advance(5);
+                return Ok((content_type, buf.split_to(length)));
+            }
+        }
+        if stream.read_buf(buf).await? == 0 {
+            return Err(anyhow!("Connection closed during Reality handshake"));
+        }
+    }
+}
+
+fn record_header(content_type: u8, length: usize) -> [u8; 5] {
+    let len = (length as u16).to_be_bytes();
+    [content_type, 0x03, 0x03, len[0], len[1]]
+}
+
+fn record(content_type: u8, version: u16, payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![content_type];
+    out.extend_from_slice(&version.to_be_bytes());
+    out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+fn extension(ext_type: u16, data: &[u8]) -> Vec<u8> {
+    let mut out = ext_type.to_be_bytes().to_vec();
+    out.extend_from_slice(&with_len16(data));
+    out
+}
+
+fn with_len16(data: &[u8]) -> Vec<u8> {
+    let mut out = (data.len() as u16).to_be_bytes().to_vec();
+    out.extend_from_slice(data);
+    out
+}
+
+/// 带 2 字节长度前缀的 u16 列表
+fn list16(values: &[u16]) -> Vec<u8> {
+    with_len16(
+        &values
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// 带 1 字节长度前缀的 u16 列表
+fn list8(values: &[u16]) -> Vec<u8> {
+    let mut out = vec![(values.len() * 2) as u8];
+    out.extend(values.iter().flat_map(|v| v.to_be_bytes()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::reality::auth::RealityAuth;
+    use crate::transport::reality::hello_parser::parse_client_hello_message;
+    use crate::transport::reality::{Accepted, RealityConfig, RealityServer};
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream};
+
+    const PRIVATE_KEY: [u8; 32] = [0x42; 32];
+
+    fn client(fingerprint: &str, short_id: &str) -> RealityClient {
+        RealityClient::new(RealityClientConfig {
+            server_name: "www.example.com".to_string(),
+            public_key: general_purpose::URL_SAFE_NO_PAD
+                .encode(PublicKey::from(&StaticSecret::from(PRIVATE_KEY)).as_bytes()),
+            short_id: short_id.to_string(),
+            fingerprint: fingerprint.to_string(),
+        })
+        .unwrap()
+    }
+
+    /// 接受 short id "01" 与 www.example.com 的服务端
+    fn server(session_tickets: bool, handshake_timeout: Duration) -> RealityServer {
+        RealityServer::new(RealityConfig {
+            session_tickets,
+            handshake_timeout,
+            ..RealityConfig::new(
+                "127.0.0.1:9",
+                vec!["www.example.com".to_string()],
+                general_purpose::STANDARD.encode(PRIVATE_KEY),
+                vec!["01".to_string()],
+            )
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_client_hello_authenticates() {
+        let auth = RealityAuth::new(PRIVATE_KEY, &["abcd".to_string()]).unwrap();
+        for fingerprint in ["chrome", "firefox"] {
+            let client = client(fingerprint, "abcd");
+            let secret = StaticSecret::random_from_rng(OsRng);
+            let random: [u8; 32] = rand::random();
+            let mut hello = client.client_hello(&random, PublicKey::from(&secret).as_bytes());
+            let auth_key = client
+                .seal_session_id(&mut hello, &random, &secret)
+                .unwrap();
+
+            let parsed = parse_client_hello_message(hello).unwrap().unwrap();
+            assert_eq!(parsed.server_name.as_deref(), Some("www.example.com"));
+            assert!(parsed.supported_versions.contains(&VERSION_TLS13));
+            let server_view = auth.authenticate(&parsed).unwrap();
+            assert_eq!(server_view.auth_key, auth_key);
+            assert_eq!(server_view.short_id, [0xab, 0xcd, 0, 0, 0, 0, 0, 0]);
+            assert_eq!(server_view.version, client_version());
+        }
+    }
+
+    #[test]
+    fn test_chrome_hello_is_padded() {
+        let client = client("chrome", "");
+        let hello = client.client_hello(&[0; 32], &[9; 32]);
+        assert!(hello.len() >= 0x200 || hello.len() <= 0xff);
+    }
+
+    #[test]
+    fn test_rejects_bad_config() {
+        let mut config = RealityClientConfig {
+            server_name: "www.example.com".to_string(),
+            public_key: "QUFB".to_string(),
+            short_id: String::new(),
+            fingerprint: "chrome".to_string(),
+        };
+        assert!(RealityClient::new(config.clone()).is_err());
+        config.public_key = general_purpose::STANDARD.encode([1u8; 32]);
+        assert!(RealityClient::new(config.clone()).is_ok());
+        config.short_id = "0123456789abcdef01".to_string();
+        assert!(RealityClient::new(config).is_err());
+    }
+
+    /// ServerHello.random 完全来自随机数生成器；连续的零字节会成为被动识别的特征
+    #[tokio::test]
+    async fn test_server_hello_random_is_fresh() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = server(false, Duration::from_secs(10));
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let server = server.clone();
+                tokio::spawn(async move { server.accept(stream).await });
+            }
+        });
+
+        let client = client("chrome", "01");
+        let mut randoms = Vec::new();
+        for _ in 0..2 {
+            let secret = StaticSecret::random_from_rng(OsRng);
+            let random: [u8; 32] = rand::random();
+            let mut hello = client.client_hello(&random, PublicKey::from(&secret).as_bytes());
+            client.seal_session_id(&mut hello, &random, &secret).unwrap();
+
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(&record(22, 0x0301, &hello)).await.unwrap();
+            let (content_type, server_hello) = read_record(&mut stream, &mut BytesMut::new()).await.unwrap();
+            assert_eq!(content_type, 22);
+            let fields = ServerHello::from_raw(server_hello.to_vec()).parse().unwrap();
+
+            let longest_zero_run = fields
+                .random
+                .split(|&b| b != 0)
+                .map(<[u8]>::len)
+                .max()
+                .unwrap_or(0);
+            assert!(longest_zero_run < 4, "{}", hex::encode(fields.random));
+            randoms.push(fields.random);
+        }
+        assert_ne!(randoms[0], randoms[1]);
+    }
+
+    /// 通过认证后不再发送 Finished 的客户端在截止时间被断开，错误中注明停滞的阶段
+    #[tokio::test]
+    async fn test_stalled_client_finished_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = server(false, Duration::from_millis(300));
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            server.accept(stream).await.err().unwrap().to_string()
+        });
+
+        let client = client("chrome", "01");
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let random: [u8; 32] = rand::random();
+        let mut hello = client.client_hello(&random, PublicKey::from(&secret).as_bytes());
+        client.seal_session_id(&mut hello, &random, &secret).unwrap();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(&record(22, 0x0301, &hello)).await.unwrap();
+
+        let error = tokio::time::timeout(Duration::from_secs(2), server).await.unwrap().unwrap();
+        assert_eq!(error, "Handshake timeout");
+    }
+
+    #[tokio::test]
+    async fn test_handshake_with_reality_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = server(true, Duration::from_secs(10));
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let Ok(Accepted::Reality(mut tls)) = server.accept(stream).await else {
+                panic!("client was not authenticated");
+            };
+            assert_eq!(tls.get_ref().1.server_name(), Some("www.example.com"));
+            let mut buf = [0u8; 4];
+            tls.read_exact(&mut buf).await.unwrap();
+            tls.write_all(&buf).await.unwrap();
+            tls.flush().await.unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut tls = client("chrome", "01").connect(stream).await.unwrap();
+        let info = tls.session_info();
+        assert_eq!(info.suite, CipherSuite::Aes128GcmSha256);
+        assert_eq!(info.server_name.as_deref(), Some("www.example.com"));
+        assert!(info.reality_authenticated);
+        tls.write_all(b"ping").await.unwrap();
+        tls.flush().await.unwrap();
+        // 回显之前先收到 NewSessionTicket，读取时跳过
+        let mut echo = [0u8; 4];
+        tls.read_exact(&mut echo).await.unwrap();
+        assert_eq!(&echo, b"ping");
+    }
+}
@@ -1,47 +1,119 @@
 use anyhow::{anyhow, Result};
-use rand::rngs::OsRng;
+use bytes::BytesMut;
 use ring::{aead, digest, hkdf, hmac};
-use x25519_dalek::{PublicKey, StaticSecret};
 
-/// 计算 Transcript Hash (SHA256)
-pub fn hash_transcript(messages: &[&[u8]]) -> Vec<u8> {
-    let mut ctx = digest::Context::new(&digest::SHA256);
-    for msg in messages {
-        ctx.update(msg);
-    }
-    ctx.finish().as_ref().to_vec()
-}
+use super::keylog;
 
-/// Reality 加密助手
-pub struct RealityCrypto {
-    my_secret: StaticSecret,
+/// Reality 客户端支持的 TLS 1.3 密码套件 (RFC 8446 §B.4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherSuite {
+    Aes128GcmSha256,
+    Aes256GcmSha384,
+    Chacha20Poly1305Sha256,
 }
 
-impl RealityCrypto {
-    pub fn new() -> Self {
-        let my_secret = StaticSecret::random_from_rng(OsRng);
-        Self { my_secret }
+impl CipherSuite {
+    pub fn from_u16(value: u16) -> Option<Self> {
+        match value {
+            0x1301 => Some(Self::Aes128GcmSha256),
+            0x1302 => Some(Self::Aes256GcmSha384),
+            0x1303 => Some(Self::Chacha20Poly1305Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn id(self) -> u16 {
+        match self {
+            Self::Aes128GcmSha256 => 0x1301,
+            Self::Aes256GcmSha384 => 0x1302,
+            Self::Chacha20Poly1305Sha256 => 0x1303,
+        }
     }
 
-    pub fn get_public_key(&self) -> Vec<u8> {
-        let pk = PublicKey::from(&self.my_secret);
-        pk.as_bytes().to_vec()
+    /// Picks the first suite in the client's preference order that we support.
+    ///
+    /// Clients put ChaCha20 first when they lack AES hardware, so honouring their
+    /// order is both faster for them and what a real server would answer.
+    pub fn select(offered: &[u16]) -> Option<Self> {
+        offered.iter().find_map(|&id| Self::from_u16(id))
     }
 
-    pub fn derive_shared_secret(&self, peer_public_bytes: &[u8]) -> Result<Vec<u8>> {
-        if peer_public_bytes.len() != 32 {
-            return Err(anyhow!("无效的公钥长度"));
+    fn aead(self) -> &'static aead::Algorithm {
+        match self {
+            Self::Aes128GcmSha256 => &aead::AES_128_GCM,
+            Self::Aes256GcmSha384 => &aead::AES_256_GCM,
+            Self::Chacha20Poly1305Sha256 => &aead::CHACHA20_POLY1305,
         }
-        let mut bytes = [0u8; 32];
-        bytes.copy_from_slice(peer_public_bytes);
+    }
 
-        let peer_pk = PublicKey::from(bytes);
-        let shared = self.my_secret.diffie_hellman(&peer_pk);
-        Ok(shared.as_bytes().to_vec())
+    fn hkdf(self) -> hkdf::Algorithm {
+        match self {
+            Self::Aes256GcmSha384 => hkdf::HKDF_SHA384,
+            _ => hkdf::HKDF_SHA256,
+        }
     }
+
+    fn hmac(self) -> hmac::Algorithm {
+        match self {
+            Self::Aes256GcmSha384 => hmac::HMAC_SHA384,
+            _ => hmac::HMAC_SHA256,
+        }
+    }
+
+    fn digest(self) -> &'static digest::Algorithm {
+        match self {
+            Self::Aes256GcmSha384 => &digest::SHA384,
+            _ => &digest::SHA256,
+        }
+    }
+
+    /// Output length of the suite's hash; also the length of every secret.
+    pub fn hash_len(self) -> usize {
+        self.digest().output_len()
+    }
+
+    /// Hash(""), the context of the "derived" secrets in the key schedule.
+    fn empty_hash(self) -> Vec<u8> {
+        TranscriptHash::new(self).current_hash()
+    }
+}
+
+/// Running transcript hash (RFC 8446 §4.4.1).
+///
+/// Every handshake message is added exactly once, in wire order. `current_hash`
+/// snapshots the digest without consuming it, so Hash(CH..SH), Hash(CH..CV) and
+/// Hash(CH..server Finished) all come from a single pass over the messages.
+#[derive(Clone)]
+pub struct TranscriptHash {
+    ctx: digest::Context,
+}
+
+impl TranscriptHash {
+    /// Starts an empty transcript using the suite's hash (SHA-256 or SHA-384).
+    pub fn new(suite: CipherSuite) -> Self {
+        Self { ctx: digest::Context::new(suite.digest()) }
+    }
+
+    pub fn add(&mut self, message: &[u8]) {
+        self.ctx.update(message);
+    }
+
+    pub fn current_hash(&self) -> Vec<u8> {
+        self.ctx.clone().finish().as_ref().to_vec()
+    }
+}
+
+/// Content signed by the server's CertificateVerify (RFC 8446 §4.4.3).
+pub fn certificate_verify_input(transcript_hash: &[u8]) -> Vec<u8> {
+    let mut input = vec![0x20u8; 64];
+    input.extend_from_slice(b"TLS 1.3, server CertificateVerify");
+    input.push(0);
+    input.extend_from_slice(transcript_hash);
+    input
 }
 
 pub struct TlsKeys {
+    pub suite: CipherSuite,
     pub client_write_key: aead::LessSafeKey,
     pub server_write_key: aead::LessSafeKey,
     pub client_iv: [u8; 12],
@@ -50,31 +122,53 @@ pub struct TlsKeys {
     pub server_traffic_secret: Vec<u8>,
 }
 
+/// Secrets retained from the handshake stage of the key schedule (RFC 8446 §7.1).
+///
+/// The traffic secrets are needed after the handshake keys are installed to compute
+/// and verify Finished (§4.4.4); the handshake secret feeds the application keys.
+pub struct HandshakeSecrets {
+    pub suite: CipherSuite,
+    pub handshake_secret: hkdf::Prk,
+    pub client_traffic_secret: hkdf::Prk,
+    pub server_traffic_secret: hkdf::Prk,
+}
+
 impl TlsKeys {
     pub fn derive_handshake_keys(
+        suite: CipherSuite,
         shared_secret: &[u8],
         hello_hash: &[u8],
-    ) -> Result<(Self, hkdf::Prk)> {
+    ) -> Result<(Self, HandshakeSecrets)> {
+        let hash_len = suite.hash_len();
+        let zeros = vec![0u8; hash_len];
+
         // RFC 8446 Section 7.1: Early Secret = HKDF-Extract(0, 0)
-        // Without PSK, salt is 32 zero bytes for SHA256
-        let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, &[0u8; 32]);
-        let early_secret = salt.extract(&[0u8; 32]);
+        // Without PSK, both salt and IKM are Hash.length zero bytes
+        let early_secret = hkdf::Salt::new(suite.hkdf(), &zeros).extract(&zeros);
 
-        // derived = HKDF-Expand-Label(Early Secret, "derived", "", 32)
-        let derived_secret = expand_label(&early_secret, b"derived", &hash_empty(), 32)?;
+        // derived = HKDF-Expand-Label(Early Secret, "derived", Hash(""), Hash.length)
+        let derived_secret = expand_label(&early_secret, b"derived", &suite.empty_hash(), hash_len)?;
 
         // Handshake Secret = HKDF-Extract(derived_secret, shared_secret)
         let handshake_secret =
-            hkdf::Salt::new(hkdf::HKDF_SHA256, &derived_secret).extract(shared_secret);
+            hkdf::Salt::new(suite.hkdf(), &derived_secret).extract(shared_secret);
+
+        let client_hs_secret = expand_label(&handshake_secret, b"c hs traffic", hello_hash, hash_len)?;
+        let server_hs_secret = expand_label(&handshake_secret, b"s hs traffic", hello_hash, hash_len)?;
 
-        let client_hs_secret = expand_label(&handshake_secret, b"c hs traffic", hello_hash, 32)?;
-        let server_hs_secret = expand_label(&handshake_secret, b"s hs traffic", hello_hash, 32)?;
+        let client_keys = derive_key_iv(suite, &client_hs_secret)?;
+        let server_keys = derive_key_iv(suite, &server_hs_secret)?;
 
-        let client_keys = derive_key_iv(&client_hs_secret)?;
-        let server_keys = derive_key_iv(&server_hs_secret)?;
+        let secrets = HandshakeSecrets {
+            suite,
+            handshake_secret,
+            client_traffic_secret: hkdf::Prk::new_less_safe(suite.hkdf(), &client_hs_secret),
+            server_traffic_secret: hkdf::Prk::new_less_safe(suite.hkdf(), &server_hs_secret),
+        };
 
         Ok((
             TlsKeys {
+                suite,
                 client_write_key: client_keys.0,
                 server_write_key: server_keys.0,
                 client_iv: client_keys.1,
@@ -82,24 +176,28 @@ impl TlsKeys {
                 client_traffic_secret: client_hs_secret,
                 server_traffic_secret: server_hs_secret,
             },
-            handshake_secret,
+            secrets,
         ))
     }
 
     pub fn derive_application_keys(
-        handshake_secret: &hkdf::Prk,
+        secrets: &HandshakeSecrets,
         handshake_hash: &[u8],
     ) -> Result<Self> {
-        let derived_secret = expand_label(handshake_secret, b"derived", &hash_empty(), 32)?;
-        let master_secret = hkdf::Salt::new(hkdf::HKDF_SHA256, &derived_secret).extract(&[0u8; 32]);
+        let suite = secrets.suite;
+        let hash_len = suite.hash_len();
+        let derived_secret =
+            expand_label(&secrets.handshake_secret, b"derived", &suite.empty_hash(), hash_len)?;
+        let master_secret = hkdf::Salt::new(suite.hkdf(), &derived_secret).extract(&vec![0u8; hash_len]);
 
-        let client_app_secret = expand_label(&master_secret, b"c ap traffic", handshake_hash, 32)?;
-        let server_app_secret = expand_label(&master_secret, b"s ap traffic", handshake_hash, 32)?;
+        let client_app_secret = expand_label(&master_secret, b"c ap traffic", handshake_hash, hash_len)?;
+        let server_app_secret = expand_label(&master_secret, b"s ap traffic", handshake_hash, hash_len)?;
 
-        let client_keys = derive_key_iv(&client_app_secret)?;
-        let server_keys = derive_key_iv(&server_app_secret)?;
+        let client_keys = derive_key_iv(suite, &client_app_secret)?;
+        let server_keys = derive_key_iv(suite, &server_app_secret)?;
 
         Ok(TlsKeys {
+            suite,
             client_write_key: client_keys.0,
             server_write_key: server_keys.0,
             client_iv: client_keys.1,
@@ -109,12 +207,62 @@ impl TlsKeys {
         })
     }
 
+    /// Writes the handshake traffic secrets to the key log (no-op unless
+    /// `SSLKEYLOGFILE` is set). Call before `into_peer_view`.
+    pub fn log_handshake_secrets(&self, client_random: &[u8; 32]) {
+        keylog::log(keylog::CLIENT_HANDSHAKE_TRAFFIC_SECRET, client_random, &self.client_traffic_secret);
+        keylog::log(keylog::SERVER_HANDSHAKE_TRAFFIC_SECRET, client_random, &self.server_traffic_secret);
+    }
+
+    /// Writes the first application traffic secrets to the key log.
+    pub fn log_application_secrets(&self, client_random: &[u8; 32]) {
+        keylog::log(keylog::CLIENT_TRAFFIC_SECRET_0, client_random, &self.client_traffic_secret);
+        keylog::log(keylog::SERVER_TRAFFIC_SECRET_0, client_random, &self.server_traffic_secret);
+    }
+
+    /// Swaps the client and server halves, so that a client can drive the
+    /// server-oriented record helpers: `encrypt_server_record` then seals with
+    /// the client write key and `decrypt_client_record` opens server records.
+    pub fn into_peer_view(self) -> Self {
+        TlsKeys {
+            suite: self.suite,
+            client_write_key: self.server_write_key,
+            server_write_key: self.client_write_key,
+            client_iv: self.server_iv,
+            server_iv: self.client_iv,
+            client_traffic_secret: self.server_traffic_secret,
+            server_traffic_secret: self.client_traffic_secret,
+        }
+    }
+
+    /// Encrypts one record and returns it in a fresh buffer.
     pub fn encrypt_server_record(
         &self,
         seq: u64,
         plaintext: &[u8],
         content_type: u8,
     ) -> Result<Vec<u8>> {
+        let mut record = BytesMut::with_capacity(self.sealed_record_len(plaintext.len()));
+        self.seal_server_record(seq, plaintext, content_type, &mut record)?;
+        Ok(record.into())
+    }
+
+    /// Wire length of a record carrying `plaintext_len` bytes.
+    pub fn sealed_record_len(&self, plaintext_len: usize) -> usize {
+        5 + plaintext_len + 1 + self.server_write_key.algorithm().tag_len()
+    }
+
+    /// Appends one encrypted record to `out`, sealing in place.
+    ///
+    /// Header, plaintext and content type are written straight into `out` and
+    /// the tag is appended after, so a reused buffer needs no allocation.
+    pub fn seal_server_record(
+        &self,
+        seq: u64,
+        plaintext: &[u8],
+        content_type: u8,
+        out: &mut BytesMut,
+    ) -> Result<()> {
         let mut nonce_bytes = [0u8; 12];
         let mut padded_seq = [0u8; 12];
         padded_seq[4..].copy_from_slice(&seq.to_be_bytes());
@@ -124,44 +272,79 @@ impl TlsKeys {
         let nonce = aead::Nonce::try_assume_unique_for_key(&nonce_bytes)
             .map_err(|_| anyhow!("Nonce err"))?;
 
-        let mut buffer = plaintext.to_vec();
-        buffer.push(content_type);
-
-        let tag_len = self.server_write_key.algorithm().tag_len();
-        let encrypted_len = buffer.len() + tag_len;
-
-        let mut header = [23u8, 0x03, 0x03, 0, 0];
-        let len_bytes = (encrypted_len as u16).to_be_bytes();
-        header[3] = len_bytes[0];
-        header[4] = len_bytes[1];
-
-        let aad = aead::Aad::from(header);
-
-        self.server_write_key
-            .seal_in_place_append_tag(nonce, aad, &mut buffer)
-            .map_err(|_| anyhow!("Encrypt fail"))?;
-
-        let mut record = Vec::with_capacity(5 + buffer.len());
-        record.extend_from_slice(&header);
-        record.extend_from_slice(&buffer);
-
-        Ok(record)
+        let record_len = self.sealed_record_len(plaintext.len());
+        let len_bytes = ((record_len - 5) as u16).to_be_bytes();
+        let header = [23u8, 0x03, 0x03, len_bytes[0], len_bytes[1]];
+
+        out.reserve(record_len);
+        let start = out.len();
+        out.extend_from_slice(&header);
+        out.extend_from_slice(plaintext);
+        out.extend_from_slice(&[content_type]);
+
+        let tag = match self.server_write_key.seal_in_place_separate_tag(
+            nonce,
+            aead::Aad::from(header),
+            &mut out[start + 5..],
+        ) {
+            Ok(tag) => tag,
+            Err(_) => {
+                out.truncate(start);
+                return Err(anyhow!("Encrypt fail"));
+            }
+        };
+        out.extend_from_slice(tag.as_ref());
+        Ok(())
     }
 
+    /// Finished verify_data = HMAC(finished_key, transcript_hash), where
+    /// finished_key = HKDF-Expand-Label(traffic_secret, "finished", "", Hash.length)
     pub fn calculate_verify_data(
-        traffic_secret_bytes: &[u8],
+        suite: CipherSuite,
+        traffic_secret: &hkdf::Prk,
         handshake_hash: &[u8],
     ) -> Result<Vec<u8>> {
-        let finished_key = expand_label_raw(traffic_secret_bytes, b"finished", &[], 32)?;
-        let key = hmac::Key::new(hmac::HMAC_SHA256, &finished_key);
+        let key = finished_key(suite, traffic_secret)?;
         let tag = hmac::sign(&key, handshake_hash);
         Ok(tag.as_ref().to_vec())
     }
 
+    /// Checks a peer's Finished verify_data in constant time.
+    pub fn verify_finished(
+        suite: CipherSuite,
+        traffic_secret: &hkdf::Prk,
+        handshake_hash: &[u8],
+        verify_data: &[u8],
+    ) -> Result<()> {
+        let key = finished_key(suite, traffic_secret)?;
+        hmac::verify(&key, handshake_hash, verify_data)
+            .map_err(|_| anyhow!("Finished verify_data mismatch"))
+    }
+
+    /// Moves the client application keys to the next generation after the
+    /// client sends a KeyUpdate (RFC 8446 §7.2).
+    pub fn update_client_keys(&mut self) -> Result<()> {
+        let (secret, key, iv) = next_generation(self.suite, &self.client_traffic_secret)?;
+        self.client_traffic_secret = secret;
+        self.client_write_key = key;
+        self.client_iv = iv;
+        Ok(())
+    }
+
+    /// Moves the server application keys to the next generation after we
+    /// send a KeyUpdate.
+    pub fn update_server_keys(&mut self) -> Result<()> {
+        let (secret, key, iv) = next_generation(self.suite, &self.server_traffic_secret)?;
+        self.server_traffic_secret = secret;
+        self.server_write_key = key;
+        self.server_iv = iv;
+        Ok(())
+    }
+
     pub fn decrypt_client_record(
         &self,
         seq: u64,
-        header: &[u8; 5],
+        header: &[u8],
         ciphertext: &mut [u8],
     ) -> Result<(u8, usize)> {
         let mut nonce_bytes = [0u8; 12];
@@ -173,7 +356,7 @@ impl TlsKeys {
         let nonce = aead::Nonce::try_assume_unique_for_key(&nonce_bytes)
             .map_err(|_| anyhow!("Nonce err"))?;
 
-        let aad = aead::Aad::from(&header[..]);
+        let aad = aead::Aad::from(header);
 
         let plaintext_len = self
             .client_write_key
@@ -199,6 +382,11 @@ impl TlsKeys {
 
 // Helpers
 
+fn finished_key(suite: CipherSuite, traffic_secret: &hkdf::Prk) -> Result<hmac::Key> {
+    let finished_key = expand_label(traffic_secret, b"finished", &[], suite.hash_len())?;
+    Ok(hmac::Key::new(suite.hmac(), &finished_key))
+}
+
 struct OutputLen(usize);
 impl hkdf::KeyType for OutputLen {
     fn len(&self) -> usize {
@@ -227,45 +415,330 @@ fn expand_label(prk: &hkdf::Prk, label: &[u8], context: &[u8], len: usize) -> Re
     Ok(out)
 }
 
-fn manual_hkdf_expand(secret: &[u8], info: &[u8], len: usize) -> Result<Vec<u8>> {
-    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
-    if len > 32 {
-        return Err(anyhow!("Manual HKDF-Expand limit: 32 bytes"));
-    }
-    let mut msg = Vec::with_capacity(info.len() + 1);
-    msg.extend_from_slice(info);
-    msg.push(0x01);
-    let tag = hmac::sign(&key, &msg);
-    Ok(tag.as_ref()[..len].to_vec())
-}
-
-fn expand_label_raw(secret: &[u8], label: &[u8], context: &[u8], len: usize) -> Result<Vec<u8>> {
-    let mut info = Vec::new();
-    info.extend_from_slice(&(len as u16).to_be_bytes());
-    let full_label = [b"tls13 ", label].concat();
-    info.push(full_label.len() as u8);
-    info.extend_from_slice(&full_label);
-    info.push(context.len() as u8);
-    info.extend_from_slice(context);
-    manual_hkdf_expand(secret, &info, len)
-}
-
-fn derive_key_iv(secret: &[u8]) -> Result<(aead::LessSafeKey, [u8; 12])> {
-    let key_bytes = expand_label_raw(secret, b"key", &[], 16)?;
-    let unbound_key = aead::UnboundKey::new(&aead::AES_128_GCM, &key_bytes)
+fn derive_key_iv(suite: CipherSuite, secret: &[u8]) -> Result<(aead::LessSafeKey, [u8; 12])> {
+    let prk = hkdf::Prk::new_less_safe(suite.hkdf(), secret);
+    let key_bytes = expand_label(&prk, b"key", &[], suite.aead().key_len())?;
+    let unbound_key = aead::UnboundKey::new(suite.aead(), &key_bytes)
         .map_err(|_| anyhow!("Failed to create unbound key"))?;
     let key = aead::LessSafeKey::new(unbound_key);
 
-    let iv_bytes = expand_label_raw(secret, b"iv", &[], 12)?;
+    let iv_bytes = expand_label(&prk, b"iv", &[], 12)?;
     let mut iv = [0u8; 12];
     iv.copy_from_slice(&iv_bytes);
     Ok((key, iv))
 }
 
-fn hash_empty() -> Vec<u8> {
-    vec![
-        0xe3, 0xb0, 0xc4, 0x42, 0x98, 0xfc, 0x1c, 0x14, 0x9a, 0xfb, 0xf4, 0xc8, 0x99, 0x6f, 0xb9,
-        0x24, 0x27, 0xae, 0x41, 0xe4, 0x64, 0x9b, 0x93, 0x4c, 0xa4, 0x95, 0x99, 0x1b, 0x78, 0x52,
-        0xb8, 0x55,
-    ]
+/// application_traffic_secret_N+1 =
+///     HKDF-Expand-Label(application_traffic_secret_N, "traffic upd", "", Hash.length)
+fn next_generation(suite: CipherSuite, secret: &[u8]) -> Result<(Vec<u8>, aead::LessSafeKey, [u8; 12])> {
+    let prk = hkdf::Prk::new_less_safe(suite.hkdf(), secret);
+    let next = expand_label(&prk, b"traffic upd", &[], suite.hash_len())?;
+    let (key, iv) = derive_key_iv(suite, &next)?;
+    Ok((next, key, iv))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 8448 §3 "Simple 1-RTT Handshake"
+    mod rfc8448 {
+        pub const SHARED_SECRET: &str = "8bd4054fb55b9d63fdfbacf9f04b9f0d35e6d63f537563efd46272900f89492d";
+        pub const HASH_CH_SH: &str = "860c06edc07858ee8e78f0e7428c58edd6b43f2ca3e6e95f02ed063cf0e1cad8";
+
+        pub const CLIENT_HS_TRAFFIC: &str = "b3eddb126e067f35a780b3abf45e2d8f3b1a950738f52e9600746a0e27a55a21";
+        pub const SERVER_HS_TRAFFIC: &str = "b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38";
+        pub const SERVER_HS_KEY: &str = "3fce516009c21727d0f2e4e86ee403bc";
+        pub const SERVER_HS_IV: &str = "5d313eb2671276ee13000b30";
+        pub const CLIENT_HS_KEY: &str = "dbfaa693d1762c5b666af5d950258d01";
+        pub const CLIENT_HS_IV: &str = "5bd3c71b836e0b76bb73265f";
+
+        pub const SERVER_FINISHED_KEY: &str = "008d3b66f816ea559f96b537e885c31fc068bf492c652f01f288a1d8cdc19fc8";
+        pub const CLIENT_FINISHED_KEY: &str = "b80ad01015fb2f0bd65ff7d4da5d6bf83f84821d1f87fdc7d3c75b5a7b42d9c4";
+
+        /// "derived" secret feeding the master secret
+        pub const MASTER_DERIVED: &str = "43de77e0c77713859a944db9db2590b53190a65b3ee2e4f12dd7a0bb7ce254b4";
+
+        pub const EARLY_SECRET: &str = "33ad0a1c607ec03b09e6cd9893680ce210adf300aa1f2660e1b22e10f170f92a";
+        /// "derived" secret feeding the handshake secret
+        pub const EARLY_DERIVED: &str = "6f2615a108c702c5678f54fc9dbab69716c076189c48250cebeac3576c3611ba";
+        pub const HANDSHAKE_SECRET: &str = "1dc826e93606aa6fdc0aadc12f741b01046aa6b99f691ed221a9f0ca043fbeac";
+        pub const MASTER_SECRET: &str = "18df06843d13a08bf2a449844c5f8a478001bc4d4c627984d5a41da8d0402919";
+
+        /// Transcript hash of ClientHello..server Finished
+        pub const HASH_CH_SF: &str = "9608102a0f1ccc6db6250b7b7e417b1a000eaada3daae4777a7686c9ff83df13";
+        pub const CLIENT_AP_TRAFFIC: &str = "9e40646ce79a7f9dc05af8889bce6552875afa0b06df0087f792ebb7c17504a5";
+        pub const SERVER_AP_TRAFFIC: &str = "a11af9f05531f856ad47116b45a950328204b4f44bfb6b3a4b4f1f3fcb631643";
+        pub const SERVER_AP_KEY: &str = "9f02283b6c9c07efc26bb9f2ac92e356";
+        pub const SERVER_AP_IV: &str = "cf782b88dd83549aadf1e984";
+        pub const CLIENT_AP_KEY: &str = "17422dda596ed5d9acd890e3c63f5051";
+        pub const CLIENT_AP_IV: &str = "5b78923dee08579033e523d9";
+    }
+
+    /// ClientHello and ServerHello handshake messages from RFC 8448 §3
+    const RFC8448_CLIENT_HELLO: &str = concat!(
+        "010000c00303cb34ecb1e78163ba1c38c6dacb196a6dffa21a8d9912ec18a2ef6283024dece7000006130113031302",
+        "010000910000000b0009000006736572766572ff01000100000a00140012001d00170018001901000101010201030104",
+        "00230000003300260024001d002099381de560e4bd43d23d8e435a7dbafeb3c06e51c13cae4d5413691e529aaf2c002b",
+        "0003020304000d0020001e040305030603020308040805080604010501060102010402050206020202002d0002010100",
+        "1c00024001",
+    );
+    const RFC8448_SERVER_HELLO: &str = concat!(
+        "020000560303a6af06a4121860dc5e6e60249cd34c95930c8ac5cb1434dac155772ed3e2692800130100002e00330024",
+        "001d0020c9828876112095fe66762bdbf7c672e156d6cc253b833df1dd69b1b04e751f0f002b00020304",
+    );
+
+    /// Hash of `messages` added one by one, as the handshake would
+    fn transcript_of(suite: CipherSuite, messages: &[&[u8]]) -> Vec<u8> {
+        let mut transcript = TranscriptHash::new(suite);
+        for message in messages {
+            transcript.add(message);
+        }
+        transcript.current_hash()
+    }
+
+    fn h(s: &str) -> Vec<u8> {
+        hex::decode(s).unwrap()
+    }
+
+    /// Keys are opaque in ring, so compare them by sealing the same input.
+    fn assert_same_key(key: &aead::LessSafeKey, expected: &str) {
+        let expected = aead::LessSafeKey::new(
+            aead::UnboundKey::new(&aead::AES_128_GCM, &h(expected)).unwrap(),
+        );
+        let seal = |k: &aead::LessSafeKey| {
+            let mut buf = vec![0u8; 32];
+            let nonce = aead::Nonce::assume_unique_for_key([0u8; 12]);
+            k.seal_in_place_append_tag(nonce, aead::Aad::empty(), &mut buf).unwrap();
+            buf
+        };
+        assert_eq!(seal(key), seal(&expected));
+    }
+
+    #[test]
+    fn test_rfc8448_key_schedule() {
+        use rfc8448::*;
+
+        let (hs_keys, secrets) =
+            TlsKeys::derive_handshake_keys(CipherSuite::Aes128GcmSha256, &h(SHARED_SECRET), &h(HASH_CH_SH)).unwrap();
+        assert_eq!(hs_keys.client_traffic_secret, h(CLIENT_HS_TRAFFIC));
+        assert_eq!(hs_keys.server_traffic_secret, h(SERVER_HS_TRAFFIC));
+        assert_same_key(&hs_keys.server_write_key, SERVER_HS_KEY);
+        assert_same_key(&hs_keys.client_write_key, CLIENT_HS_KEY);
+        assert_eq!(hs_keys.server_iv.to_vec(), h(SERVER_HS_IV));
+        assert_eq!(hs_keys.client_iv.to_vec(), h(CLIENT_HS_IV));
+
+        // The retained Prks must be the same secrets as the raw bytes
+        let finished = |prk: &hkdf::Prk| expand_label(prk, b"finished", &[], 32).unwrap();
+        assert_eq!(finished(&secrets.server_traffic_secret), h(SERVER_FINISHED_KEY));
+        assert_eq!(finished(&secrets.client_traffic_secret), h(CLIENT_FINISHED_KEY));
+
+        let derived = expand_label(&secrets.handshake_secret, b"derived", &secrets.suite.empty_hash(), 32).unwrap();
+        assert_eq!(derived, h(MASTER_DERIVED));
+    }
+
+    #[test]
+    fn test_rfc8448_expand_label() {
+        use rfc8448::*;
+
+        let suite = CipherSuite::Aes128GcmSha256;
+        let early = hkdf::Prk::new_less_safe(suite.hkdf(), &h(EARLY_SECRET));
+        assert_eq!(expand_label(&early, b"derived", &suite.empty_hash(), 32).unwrap(), h(EARLY_DERIVED));
+
+        let handshake = hkdf::Prk::new_less_safe(suite.hkdf(), &h(HANDSHAKE_SECRET));
+        assert_eq!(expand_label(&handshake, b"c hs traffic", &h(HASH_CH_SH), 32).unwrap(), h(CLIENT_HS_TRAFFIC));
+        assert_eq!(expand_label(&handshake, b"s hs traffic", &h(HASH_CH_SH), 32).unwrap(), h(SERVER_HS_TRAFFIC));
+        assert_eq!(expand_label(&handshake, b"derived", &suite.empty_hash(), 32).unwrap(), h(MASTER_DERIVED));
+
+        let master = hkdf::Prk::new_less_safe(suite.hkdf(), &h(MASTER_SECRET));
+        assert_eq!(expand_label(&master, b"c ap traffic", &h(HASH_CH_SF), 32).unwrap(), h(CLIENT_AP_TRAFFIC));
+        assert_eq!(expand_label(&master, b"s ap traffic", &h(HASH_CH_SF), 32).unwrap(), h(SERVER_AP_TRAFFIC));
+
+        // Empty context and lengths other than Hash.length (traffic key and IV)
+        let server_hs = hkdf::Prk::new_less_safe(suite.hkdf(), &h(SERVER_HS_TRAFFIC));
+        assert_eq!(expand_label(&server_hs, b"key", &[], 16).unwrap(), h(SERVER_HS_KEY));
+        assert_eq!(expand_label(&server_hs, b"iv", &[], 12).unwrap(), h(SERVER_HS_IV));
+        let client_ap = hkdf::Prk::new_less_safe(suite.hkdf(), &h(CLIENT_AP_TRAFFIC));
+        assert_eq!(expand_label(&client_ap, b"key", &[], 16).unwrap(), h(CLIENT_AP_KEY));
+        assert_eq!(expand_label(&client_ap, b"iv", &[], 12).unwrap(), h(CLIENT_AP_IV));
+    }
+
+    #[test]
+    fn test_rfc8448_application_keys() {
+        use rfc8448::*;
+
+        let (_, secrets) =
+            TlsKeys::derive_handshake_keys(CipherSuite::Aes128GcmSha256, &h(SHARED_SECRET), &h(HASH_CH_SH)).unwrap();
+        let app_keys = TlsKeys::derive_application_keys(&secrets, &h(HASH_CH_SF)).unwrap();
+        assert_eq!(app_keys.client_traffic_secret, h(CLIENT_AP_TRAFFIC));
+        assert_eq!(app_keys.server_traffic_secret, h(SERVER_AP_TRAFFIC));
+        assert_same_key(&app_keys.server_write_key, SERVER_AP_KEY);
+        assert_same_key(&app_keys.client_write_key, CLIENT_AP_KEY);
+        assert_eq!(app_keys.server_iv.to_vec(), h(SERVER_AP_IV));
+        assert_eq!(app_keys.client_iv.to_vec(), h(CLIENT_AP_IV));
+
+        // A different transcript must not produce the same secrets
+        let other = TlsKeys::derive_application_keys(&secrets, &h(HASH_CH_SH)).unwrap();
+        assert_ne!(other.client_traffic_secret, h(CLIENT_AP_TRAFFIC));
+    }
+
+    #[test]
+    fn test_transcript_hash_snapshots() {
+        let sha256 = |data: &[u8]| digest::digest(&digest::SHA256, data).as_ref().to_vec();
+        let mut transcript = TranscriptHash::new(CipherSuite::Aes128GcmSha256);
+        assert_eq!(transcript.current_hash(), sha256(b""));
+
+        transcript.add(&h(RFC8448_CLIENT_HELLO));
+        assert_eq!(transcript.current_hash(), sha256(&h(RFC8448_CLIENT_HELLO)));
+        transcript.add(&h(RFC8448_SERVER_HELLO));
+        assert_eq!(transcript.current_hash(), h(rfc8448::HASH_CH_SH));
+        // Taking a snapshot must not disturb later ones
+        assert_eq!(transcript.current_hash(), h(rfc8448::HASH_CH_SH));
+
+        transcript.add(b"encrypted extensions");
+        assert_eq!(
+            transcript.current_hash(),
+            sha256(&[h(RFC8448_CLIENT_HELLO), h(RFC8448_SERVER_HELLO), b"encrypted extensions".to_vec()].concat())
+        );
+
+        let mut sha384 = TranscriptHash::new(CipherSuite::Aes256GcmSha384);
+        sha384.add(b"client hello");
+        assert_eq!(sha384.current_hash().len(), 48);
+        assert_eq!(sha384.current_hash(), digest::digest(&digest::SHA384, b"client hello").as_ref());
+    }
+
+    #[test]
+    fn test_finished_uses_matching_secret() {
+        let (_, secrets) =
+            TlsKeys::derive_handshake_keys(CipherSuite::Aes128GcmSha256, &h(rfc8448::SHARED_SECRET), &h(rfc8448::HASH_CH_SH)).unwrap();
+        let transcript = transcript_of(CipherSuite::Aes128GcmSha256, &[b"client hello", b"server hello"]);
+
+        let client_vd = TlsKeys::calculate_verify_data(secrets.suite, &secrets.client_traffic_secret, &transcript).unwrap();
+        let expected = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, &h(rfc8448::CLIENT_FINISHED_KEY)),
+            &transcript,
+        );
+        assert_eq!(client_vd, expected.as_ref());
+
+        assert!(TlsKeys::verify_finished(secrets.suite, &secrets.client_traffic_secret, &transcript, &client_vd).is_ok());
+        // A Finished computed with the server secret must not pass as the client's
+        let server_vd = TlsKeys::calculate_verify_data(secrets.suite, &secrets.server_traffic_secret, &transcript).unwrap();
+        assert!(TlsKeys::verify_finished(secrets.suite, &secrets.client_traffic_secret, &transcript, &server_vd).is_err());
+        assert!(TlsKeys::verify_finished(secrets.suite, &secrets.client_traffic_secret, &transcript, &client_vd[..16]).is_err());
+    }
+
+    const ALL_SUITES: [CipherSuite; 3] = [
+        CipherSuite::Aes128GcmSha256,
+        CipherSuite::Aes256GcmSha384,
+        CipherSuite::Chacha20Poly1305Sha256,
+    ];
+
+    /// Derives the same handshake keys twice and swaps one copy's halves, giving
+    /// a peer whose `encrypt_server_record` writes what the client would send.
+    fn server_and_client(suite: CipherSuite) -> (TlsKeys, TlsKeys, HandshakeSecrets) {
+        let hash = transcript_of(suite, &[b"client hello", b"server hello"]);
+        let (server, secrets) = TlsKeys::derive_handshake_keys(suite, &[9u8; 32], &hash).unwrap();
+        let (peer, _) = TlsKeys::derive_handshake_keys(suite, &[9u8; 32], &hash).unwrap();
+        let client = TlsKeys {
+            suite,
+            client_write_key: peer.server_write_key,
+            server_write_key: peer.client_write_key,
+            client_iv: peer.server_iv,
+            server_iv: peer.client_iv,
+            client_traffic_secret: peer.server_traffic_secret,
+            server_traffic_secret: peer.client_traffic_secret,
+        };
+        (server, client, secrets)
+    }
+
+    #[test]
+    fn test_select_follows_client_order() {
+        assert_eq!(
+            CipherSuite::select(&[0x1303, 0x1301, 0x1302]),
+            Some(CipherSuite::Chacha20Poly1305Sha256)
+        );
+        assert_eq!(CipherSuite::select(&[0xc02f, 0x1302]), Some(CipherSuite::Aes256GcmSha384));
+        assert_eq!(CipherSuite::select(&[0xc02f, 0x00ff]), None);
+    }
+
+    #[test]
+    fn test_record_round_trip_per_suite() {
+        for suite in ALL_SUITES {
+            let (server, client, _) = server_and_client(suite);
+            assert_eq!(server.server_traffic_secret.len(), suite.hash_len());
+
+            for seq in 0..3u64 {
+                let payload = format!("{:?} record {}", suite, seq);
+                let mut record = client.encrypt_server_record(seq, payload.as_bytes(), 23).unwrap();
+                assert_eq!(record.len(), 5 + payload.len() + 1 + suite.aead().tag_len());
+
+                let header: [u8; 5] = record[..5].try_into().unwrap();
+                let (ctype, len) = server.decrypt_client_record(seq, &header, &mut record[5..]).unwrap();
+                assert_eq!(ctype, 23);
+                assert_eq!(&record[5..5 + len], payload.as_bytes());
+            }
+
+            // Wrong sequence number must not decrypt
+            let mut record = client.encrypt_server_record(0, b"x", 23).unwrap();
+            let header: [u8; 5] = record[..5].try_into().unwrap();
+            assert!(server.decrypt_client_record(1, &header, &mut record[5..]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_seal_appends_without_reallocating() {
+        let (server, client, _) = server_and_client(CipherSuite::Aes128GcmSha256);
+        let mut out = BytesMut::with_capacity(64);
+        out.extend_from_slice(b"queued");
+        let ptr = out.as_ptr();
+
+        client.seal_server_record(7, b"hello", 23, &mut out).unwrap();
+        assert_eq!(out.as_ptr(), ptr);
+        assert_eq!(&out[..6], b"queued");
+        assert_eq!(&out[6..], &client.encrypt_server_record(7, b"hello", 23).unwrap()[..]);
+
+        let (header, body) = out[6..].split_at_mut(5);
+        let (ctype, len) = server.decrypt_client_record(7, header, body).unwrap();
+        assert_eq!((ctype, &body[..len]), (23, &b"hello"[..]));
+    }
+
+    #[test]
+    fn test_key_update_ratchets_both_sides() {
+        for suite in ALL_SUITES {
+            let (mut server, mut client, _) = server_and_client(suite);
+            let old_secret = server.client_traffic_secret.clone();
+
+            // The client's write half is `server_*` in its swapped view
+            client.update_server_keys().unwrap();
+            server.update_client_keys().unwrap();
+            assert_ne!(server.client_traffic_secret, old_secret);
+            assert_eq!(server.client_traffic_secret, client.server_traffic_secret);
+
+            let mut record = client.encrypt_server_record(0, b"after update", 23).unwrap();
+            let header: [u8; 5] = record[..5].try_into().unwrap();
+            let (_, len) = server.decrypt_client_record(0, &header, &mut record[5..]).unwrap();
+            assert_eq!(&record[5..5 + len], b"after update");
+
+            // The server's write direction is untouched
+            let mut record = server.encrypt_server_record(0, b"x", 23).unwrap();
+            let header: [u8; 5] = record[..5].try_into().unwrap();
+            assert!(client.decrypt_client_record(0, &header, &mut record[5..]).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_finished_and_app_keys_per_suite() {
+        for suite in ALL_SUITES {
+            let (_, _, secrets) = server_and_client(suite);
+            let hash = transcript_of(suite, &[b"through server finished"]);
+
+            let vd = TlsKeys::calculate_verify_data(suite, &secrets.client_traffic_secret, &hash).unwrap();
+            assert_eq!(vd.len(), suite.hash_len());
+            assert!(TlsKeys::verify_finished(suite, &secrets.client_traffic_secret, &hash, &vd).is_ok());
+
+            let app = TlsKeys::derive_application_keys(&secrets, &hash).unwrap();
+            assert_eq!(app.suite, suite);
+            assert_eq!(app.client_traffic_secret.len(), suite.hash_len());
+        }
+    }
 }
@@ -1,28 +1,105 @@
 use anyhow::{anyhow, Result};
 use bytes::Buf;
 
+use super::tls::{offers_tls13, parse_supported_versions, MAX_CLIENT_HELLO_LEN};
+
+/// 逐步从 TLS 记录中拼接 ClientHello 握手消息 (可能跨多个记录)
+///
+/// 每次调用 [`ClientHelloReader::advance`] 只处理新到达的完整记录，慢速发送也不会重复拷贝。
+#[derive(Default)]
+pub struct ClientHelloReader {
+    /// 已拼接到 `message` 的完整记录的字节数
+    consumed: usize,
+    message: Vec<u8>,
+}
+
+pub enum ClientHelloProgress {
+    /// 握手消息 (不含记录头) 与其所占的原始字节数
+    Complete { message: Vec<u8>, consumed: usize },
+    /// 还需要更多数据
+    Incomplete,
+    /// 不是 TLS 握手记录或不是 ClientHello
+    NotClientHello,
+}
+
+impl ClientHelloReader {
+    /// `buf` 为目前读到的全部数据；声明的消息长度超过 `MAX_CLIENT_HELLO_LEN` 时返回错误
+    pub fn advance(&mut self, buf: &[u8]) -> Result<ClientHelloProgress> {
+        while buf.len() >= self.consumed + 5 {
+            let header = &buf[self.consumed..self.consumed + 5];
+            let length = u16::from_be_bytes([header[3], header[4]]) as usize;
+            if header[0] != 0x16 || length > 16384 {
+                return Ok(ClientHelloProgress::NotClientHello);
+            }
+            if buf.len() < self.consumed + 5 + length {
+                break;
+            }
+            self.message.extend_from_slice(&buf[self.consumed + 5..self.consumed + 5 + length]);
+            self.consumed += 5 + length;
+
+            if self.message.is_empty() {
+                continue;
+            }
+            if self.message[0] != 0x01 {
+                return Ok(ClientHelloProgress::NotClientHello);
+            }
+            if self.message.len() >= 4 {
+                let length = u32::from_be_bytes([0, self.message[1], self.message[2], self.message[3]]) as usize;
+                if length > MAX_CLIENT_HELLO_LEN {
+                    return Err(anyhow!("ClientHello too large: {} bytes (max {})", length, MAX_CLIENT_HELLO_LEN));
+                }
+                if self.message.len() >= 4 + length {
+                    let mut message = std::mem::take(&mut self.message);
+                    message.truncate(4 + length);
+                    return Ok(ClientHelloProgress::Complete { message, consumed: self.consumed });
+                }
+            }
+        }
+        if !buf.is_empty() && buf[0] != 0x16 {
+            return Ok(ClientHelloProgress::NotClientHello);
+        }
+        Ok(ClientHelloProgress::Incomplete)
+    }
+}
+
 pub struct ClientHelloInfo {
+    pub legacy_version: u16,
+    /// supported_versions 扩展中的版本 (不含 GREASE)
+    pub supported_versions: Vec<u16>,
     pub session_id: Vec<u8>,
     pub client_random: [u8; 32],
     pub public_key: Option<Vec<u8>>,
     pub server_name: Option<String>,
+    /// 带有 encrypted_client_hello 扩展 (ECH，类型 0xfe0d) 的外层 ClientHello
+    ///
+    /// 此时 `server_name` 是公开 SNI (public_name)，真实 SNI 在加密的内层 ClientHello 中，
+    /// 没有 ECH 私钥无法还原。GREASE ECH 与真实 ECH 在外层无法区分，同样会置位。
+    pub ech: bool,
+    /// 完整的握手消息 (不含记录头)
+    pub raw: Vec<u8>,
 }
 
-/// 解析 ClientHello 消息，提取 SessionID, Random, X25519 Public Key 和 SNI
-/// 注意：这是一个最小化实现，仅用于 Reality 预检
-pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
-    // 检查是否是 TLS Handshake (0x16)
-    if buf.len() < 5 || buf[0] != 0x16 {
-        return Ok(None); // 不是 TLS 握手
+impl ClientHelloInfo {
+    /// 是否是合法的 TLS 1.3 ClientHello (见 [`offers_tls13`])
+    pub fn offers_tls13(&self) -> bool {
+        offers_tls13(self.legacy_version, &self.supported_versions)
     }
+}
 
-    // TLS Record Header: Type(1) + Ver(2) + Len(2)
-    let record_len = u16::from_be_bytes([buf[3], buf[4]]) as usize;
-    if buf.len() < 5 + record_len {
-        return Ok(None); // 数据包不完整
+/// 解析 ClientHello 消息，提取版本、SessionID, Random, X25519 Public Key 和 SNI
+/// 注意：这是一个最小化实现，仅用于 Reality 预检
+///
+/// `buf` 为从连接开头读到的原始数据，ClientHello 可以跨多个记录；数据不完整或不是 ClientHello 时返回 `None`。
+pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
+    match ClientHelloReader::default().advance(buf)? {
+        ClientHelloProgress::Complete { message, .. } => parse_client_hello_message(message),
+        _ => Ok(None),
     }
+}
 
-    let mut cursor = &buf[5..]; // 跳过 Record Header
+/// 解析已拼接好的 ClientHello 握手消息 (不含记录头)
+pub fn parse_client_hello_message(raw: Vec<u8>) -> Result<Option<ClientHelloInfo>> {
+    let mut cursor = &raw[..];
 
     // Handshake Header: Type(1) + Len(3)
     if cursor.remaining() < 4 {
@@ -41,7 +118,7 @@ pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
     if cursor.remaining() < 2 {
         return Err(anyhow!("Short buffer for Version"));
     }
-    cursor.advance(2);
+    let legacy_version = cursor.get_u16();
 
     // Client Random (32 bytes)
     if cursor.remaining() < 32 {
@@ -55,6 +132,9 @@ pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
         return Err(anyhow!("Short buffer for Session ID Len"));
     }
     let session_id_len = cursor.get_u8() as usize;
+    if session_id_len > 32 {
+        return Err(anyhow!("Invalid session_id length {}", session_id_len));
+    }
     if cursor.remaining() < session_id_len {
         return Err(anyhow!("Short buffer for Session ID"));
     }
@@ -86,10 +166,14 @@ pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
     if cursor.remaining() < 2 {
         // No extensions?
         return Ok(Some(ClientHelloInfo {
+            legacy_version,
+            supported_versions: Vec::new(),
             session_id,
             client_random,
             public_key: None,
             server_name: None,
+            ech: false,
+            raw,
         }));
     }
 
@@ -101,6 +185,8 @@ pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
 
     let mut public_key = None;
     let mut server_name = None;
+    let mut supported_versions = Vec::new();
+    let mut ech = false;
 
     while extensions.has_remaining() {
         if extensions.remaining() < 4 {
@@ -184,15 +270,178 @@ pub fn parse_client_hello(buf: &[u8]) -> Result<Option<ClientHelloInfo>> {
             }
         }
 
-        if public_key.is_some() && server_name.is_some() {
-            break;
+        if ext_type == 0x002b {
+            supported_versions = parse_supported_versions(ext_data);
+        }
+
+        // encrypted_client_hello: 外层 ClientHello 的类型字节为 0，内层为 1
+        if ext_type == 0xfe0d {
+            ech = ext_data.first() == Some(&0);
         }
     }
 
     Ok(Some(ClientHelloInfo {
+        legacy_version,
+        supported_versions,
         session_id,
         client_random,
         public_key,
         server_name,
+        ech,
+        raw,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(message: &[u8], fragment: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for chunk in message.chunks(fragment) {
+            out.extend_from_slice(&[0x16, 0x03, 0x01]);
+            out.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            out.extend_from_slice(chunk);
+        }
+        out
+    }
+
+    #[test]
+    fn test_reader_reassembles_across_records() {
+        // 20 KiB 的消息，超过单个记录的上限
+        let mut message = vec![0x01];
+        message.extend_from_slice(&(20480u32).to_be_bytes()[1..]);
+        message.resize(4 + 20480, 0xab);
+        let mut wire = records(&message, 16384);
+        wire.extend_from_slice(&[0x14, 0x03, 0x03, 0x00, 0x01, 0x01]); // 其后的 CCS 不计入
+
+        let mut reader = ClientHelloReader::default();
+        for end in (1..wire.len() - 6).step_by(1000) {
+            assert!(matches!(reader.advance(&wire[..end]).unwrap(), ClientHelloProgress::Incomplete));
+        }
+        match reader.advance(&wire).unwrap() {
+            ClientHelloProgress::Complete { message: got, consumed } => {
+                assert_eq!(got, message);
+                assert_eq!(consumed, wire.len() - 6);
+            }
+            _ => panic!("expected a complete ClientHello"),
+        }
+    }
+
+    #[test]
+    fn test_reader_rejects_oversized_and_non_tls() {
+        let mut message = vec![0x01];
+        message.extend_from_slice(&((MAX_CLIENT_HELLO_LEN + 1) as u32).to_be_bytes()[1..]);
+        message.resize(1024, 0);
+        let err = ClientHelloReader::default().advance(&records(&message, 1024)).err().unwrap();
+        assert!(err.to_string().contains("ClientHello too large"), "{}", err);
+
+        let progress = ClientHelloReader::default().advance(b"GET / HTTP/1.1\r\n").unwrap();
+        assert!(matches!(progress, ClientHelloProgress::NotClientHello));
+    }
+
+    /// 构造 ClientHello 握手消息
+    fn client_hello(cipher_suites: &[u16], extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0u8; 32]);
+        body.push(0);
+        body.extend_from_slice(&((cipher_suites.len() * 2) as u16).to_be_bytes());
+        for suite in cipher_suites {
+            body.extend_from_slice(&suite.to_be_bytes());
+        }
+        body.extend_from_slice(&[1, 0]);
+        let mut ext_block = Vec::new();
+        for (ext_type, data) in extensions {
+            ext_block.extend_from_slice(&ext_type.to_be_bytes());
+            ext_block.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            ext_block.extend_from_slice(data);
+        }
+        body.extend_from_slice(&(ext_block.len() as u16).to_be_bytes());
+        body.extend_from_slice(&ext_block);
+
+        let mut message = vec![0x01];
+        message.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        message.extend_from_slice(&body);
+        message
+    }
+
+    #[test]
+    fn test_supported_versions() {
+        // rustls 客户端发出的真实 ClientHello
+        let config = rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth();
+        let name = rustls_pki_types::ServerName::try_from("example.com").unwrap();
+        let mut conn = rustls::ClientConnection::new(std::sync::Arc::new(config), name).unwrap();
+        let mut wire = Vec::new();
+        conn.write_tls(&mut wire).unwrap();
+        let info = parse_client_hello(&wire).unwrap().unwrap();
+        assert!(info.offers_tls13());
+        assert_eq!(info.server_name.as_deref(), Some("example.com"));
+
+        // GREASE + TLS 1.2 only
+        let hello = client_hello(&[0xc02f], &[(0x002b, vec![4, 0x0a, 0x0a, 0x03, 0x03])]);
+        let info = parse_client_hello_message(hello).unwrap().unwrap();
+        assert_eq!(info.supported_versions, vec![0x0303]);
+        assert!(!info.offers_tls13());
+
+        // 没有 supported_versions 扩展
+        let info = parse_client_hello_message(client_hello(&[0xc02f], &[])).unwrap().unwrap();
+        assert!(info.supported_versions.is_empty());
+        assert!(!info.offers_tls13());
+    }
+
+    #[test]
+    fn test_grease_and_large_key_shares() {
+        // key_share: GREASE, X25519MLKEM768 (1216 字节), 最后才是 x25519
+        let mut key_share = Vec::new();
+        for (group, len) in [(0x2a2au16, 1usize), (0x11ec, 1216), (0x001d, 32)] {
+            key_share.extend_from_slice(&group.to_be_bytes());
+            key_share.extend_from_slice(&(len as u16).to_be_bytes());
+            key_share.extend(vec![group as u8; len]);
+        }
+        let mut key_share_ext = (key_share.len() as u16).to_be_bytes().to_vec();
+        key_share_ext.extend_from_slice(&key_share);
+
+        let hello = client_hello(
+            &[0x0a0a, 0x1301, 0xfafa, 0x1302],
+            &[
+                (0x1a1a, vec![]),
+                (0xfe0d, vec![0x55; 300]),
+                (0x0033, key_share_ext),
+                (0xdada, vec![0]),
+            ],
+        );
+        let info = parse_client_hello_message(hello).unwrap().unwrap();
+        assert_eq!(info.public_key, Some(vec![0x1d; 32]));
+        // 0x55 不是外层 ECH 的类型字节
+        assert!(!info.ech);
+    }
+
+    #[test]
+    fn test_ech_outer_hello() {
+        let hello = client_hello(&[0x1301], &[(0xfe0d, vec![0x00, 0x00, 0x01, 0x00, 0x01, 0x2a])]);
+        assert!(parse_client_hello_message(hello).unwrap().unwrap().ech);
+        let inner = client_hello(&[0x1301], &[(0xfe0d, vec![0x01])]);
+        assert!(!parse_client_hello_message(inner).unwrap().unwrap().ech);
+        assert!(!parse_client_hello_message(client_hello(&[0x1301], &[])).unwrap().unwrap().ech);
+    }
+
+    #[test]
+    fn test_rejects_oversized_session_id() {
+        let mut message = vec![0x01, 0x00, 0x00, 0x00, 0x03, 0x03];
+        message.extend_from_slice(&[0u8; 32]);
+        message.push(33);
+        message.extend_from_slice(&[0u8; 33]);
+        message.extend_from_slice(&[0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+        let len = (message.len() - 4) as u32;
+        message[1..4].copy_from_slice(&len.to_be_bytes()[1..]);
+
+        let err = parse_client_hello_message(message).err().unwrap();
+        assert!(err.to_string().contains("session_id"), "{}", err);
+    }
+}
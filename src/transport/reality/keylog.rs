@@ -0,0 +1,73 @@
+//! NSS key log output (`SSLKEYLOGFILE`) for decrypting captures in Wireshark.
+//!
+//! Strictly opt-in: nothing is written unless the environment variable is set
+//! when the first handshake runs. Anyone holding the file can decrypt every
+//! logged session, so it is meant for debugging only.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use tracing::{error, warn};
+
+pub const CLIENT_HANDSHAKE_TRAFFIC_SECRET: &str = "CLIENT_HANDSHAKE_TRAFFIC_SECRET";
+pub const SERVER_HANDSHAKE_TRAFFIC_SECRET: &str = "SERVER_HANDSHAKE_TRAFFIC_SECRET";
+pub const CLIENT_TRAFFIC_SECRET_0: &str = "CLIENT_TRAFFIC_SECRET_0";
+pub const SERVER_TRAFFIC_SECRET_0: &str = "SERVER_TRAFFIC_SECRET_0";
+
+static KEY_LOG: Lazy<Option<Mutex<File>>> = Lazy::new(|| {
+    let path = std::env::var_os("SSLKEYLOGFILE").filter(|p| !p.is_empty())?;
+    match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(file) => {
+            warn!("⚠️ TLS 密钥日志已启用 ({})，可解密所有连接，仅用于调试", path.to_string_lossy());
+            Some(Mutex::new(file))
+        }
+        Err(e) => {
+            error!("无法打开 SSLKEYLOGFILE {}: {}", path.to_string_lossy(), e);
+            None
+        }
+    }
+});
+
+/// Whether `SSLKEYLOGFILE` is set and writable.
+pub fn enabled() -> bool {
+    KEY_LOG.is_some()
+}
+
+/// Appends one secret, keyed by the ClientHello random.
+pub fn log(label: &str, client_random: &[u8], secret: &[u8]) {
+    let Some(file) = KEY_LOG.as_ref() else {
+        return;
+    };
+    let line = format_line(label, client_random, secret);
+    let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = file.write_all(line.as_bytes()) {
+        error!("写入 SSLKEYLOGFILE 失败: {}", e);
+    }
+}
+
+fn format_line(label: &str, client_random: &[u8], secret: &[u8]) -> String {
+    format!("{} {} {}\n", label, hex::encode(client_random), hex::encode(secret))
+}
+
+/// Forwards secrets from the rustls-based server into the same file.
+#[derive(Debug)]
+pub(super) struct RustlsKeyLog;
+
+impl rustls::KeyLog for RustlsKeyLog {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        log(label, client_random, secret);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nss_line_format() {
+        let line = format_line(CLIENT_TRAFFIC_SECRET_0, &[0xab; 32], &[0x01, 0x02]);
+        assert_eq!(line, format!("CLIENT_TRAFFIC_SECRET_0 {} 0102\n", "ab".repeat(32)));
+    }
+}
@@ -1,25 +1,36 @@
 mod auth;
+mod client;
 mod cert_fetch;
+#[allow(dead_code)]
 mod cert_gen;
 pub mod crypto;
-mod handshake;
+pub mod keylog;
 mod server;
 pub mod stream;
 mod tls;
 
-pub use auth::{RealityAuth, ServerHelloModifier};
-pub use cert_fetch::fetch_certificate;
-pub use handshake::RealityHandshake;
+pub use auth::{certificate_signature, ClientAuth, RealityAuth};
+pub use client::{RealityClient, RealityClientConfig};
+pub use cert_fetch::{fetch_certificate, CertificateCache};
 pub use server::RealityServer;
-pub use tls::{ClientHello, ServerHello, TlsRecord};
+pub use server_rustls::{Accepted, Fallback};
+pub use stream::SessionInfo;
+pub use tls_handler::{CertificateFiles, SelfSignedKeyType, SelfSignedParams};
+pub use tls::{HandshakeType, ServerHello};
+
+use std::collections::HashMap;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
 /// Reality 配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RealityConfig {
-    /// 目标网站地址 (例如: www.apple.com:443)
+    /// 目标网站地址 (例如: www.apple.com:443)，未命中 `server_name_dests` 时使用
     pub dest: String,
+    /// 按 SNI 选择的目标网站 (serverName → 地址)
+    #[serde(default)]
+    pub server_name_dests: HashMap<String, String>,
     /// 服务器名称列表
     pub server_names: Vec<String>,
     /// X25519 私钥 (Base64 编码)
@@ -30,6 +41,85 @@ pub struct RealityConfig {
     pub short_ids: Vec<String>,
     /// TLS 指纹类型 (chrome, firefox, safari, etc.)
     pub fingerprint: String,
+    /// 握手后是否发送 NewSessionTicket
+    #[serde(default = "default_session_tickets")]
+    pub session_tickets: bool,
+    /// 从接受连接到握手完成 (或决定回落) 的最长时间
+    #[serde(default = "default_handshake_timeout")]
+    pub handshake_timeout: Duration,
+    /// 回落连接的 PEM 证书链: 设置后未通过认证的连接由 `RealityTlsHandler` 在本地终结 TLS，
+    /// 再以明文转发到 dest；未设置时原样转发 ClientHello
+    #[serde(default)]
+    pub certificate_file: Option<String>,
+    /// 与 `certificate_file` 配对的 PEM 私钥
+    #[serde(default)]
+    pub key_file: Option<String>,
+    /// 回落连接按 SNI 选择的证书 (serverName → 证书与私钥)，未命中时使用 `certificate_file`
+    #[serde(default)]
+    pub server_name_certificates: HashMap<String, CertificateFiles>,
+    /// Reality 证书与自签名回落证书的参数 (Reality 证书固定使用 Ed25519)
+    #[serde(default)]
+    pub self_signed: SelfSignedParams,
+    /// 借用 dest 证书链的刷新间隔: 设置后 Reality 证书之后附上 dest 的中间证书；
+    /// 回落连接在本地终结 TLS 时 dest 为明文服务，不借用
+    #[serde(default)]
+    pub dest_certificate_refresh: Option<Duration>,
+}
+
+fn default_handshake_timeout() -> Duration {
+    Duration::from_secs(10)
 }
+
+fn default_session_tickets() -> bool {
+    true
+}
+
+impl Default for RealityConfig {
+    fn default() -> Self {
+        Self {
+            dest: String::new(),
+            server_name_dests: HashMap::new(),
+            server_names: Vec::new(),
+            private_key: String::new(),
+            public_key: None,
+            short_ids: Vec::new(),
+            fingerprint: "chrome".to_string(),
+            session_tickets: default_session_tickets(),
+            handshake_timeout: default_handshake_timeout(),
+            certificate_file: None,
+            key_file: None,
+            server_name_certificates: HashMap::new(),
+            self_signed: SelfSignedParams::default(),
+            dest_certificate_refresh: None,
+        }
+    }
+}
+
+impl RealityConfig {
+    /// 使用必填字段创建配置，其余字段取默认值
+    pub fn new(
+        dest: impl Into<String>,
+        server_names: Vec<String>,
+        private_key: impl Into<String>,
+        short_ids: Vec<String>,
+    ) -> Self {
+        Self {
+            dest: dest.into(),
+            server_names,
+            private_key: private_key.into(),
+            short_ids,
+            ..Default::default()
+        }
+    }
+
+    /// 按 ClientHello 的 SNI 选择借用证书与回落的目标，未知 SNI 使用默认 dest
+    pub fn dest_for(&self, sni: Option<&str>) -> &str {
+        sni.and_then(|sni| self.server_name_dests.get(sni))
+            .map(String::as_str)
+            .unwrap_or(&self.dest)
+    }
+}
+
 pub mod server_rustls;
+pub mod tls_handler;
 pub mod hello_parser;
@@ -4,7 +4,8 @@ use tracing::{debug, info};
 use base64::{Engine as _, engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}};
 
 use super::RealityConfig;
-use super::server_rustls::RealityServerRustls;
+use super::server_rustls::{Accepted, RealityServerRustls};
+use super::tls_handler::RealityTlsHandler;
 
 /// Reality 服务器 (Wrapper around RealityServerRustls)
 #[derive(Clone)]
@@ -34,21 +35,35 @@ impl RealityServer {
         }
 
         info!("Reality 服务器初始化成功 (Rustls backend)");
-        debug!("目标: {}", config.dest);
+        debug!("目标: {} (按 SNI: {:?})", config.dest, config.server_name_dests);
         debug!("指纹: {}", config.fingerprint);
 
-        let inner = RealityServerRustls::new(
+        let mut inner = RealityServerRustls::new(
             private_key_bytes, 
             Some(config.dest.clone()), 
             config.short_ids.clone(),
             config.server_names.clone()
-        )?;
+        )?
+        .with_server_name_dests(config.server_name_dests.clone())
+        .with_session_tickets(config.session_tickets)
+        .with_handshake_timeout(config.handshake_timeout)
+        .with_self_signed(config.self_signed.clone());
+        if config.certificate_file.is_some() || !config.server_name_certificates.is_empty() {
+            info!(
+                "回落连接在本地终结 TLS (证书 {:?}，按 SNI 另配 {} 个)",
+                config.certificate_file,
+                config.server_name_certificates.len()
+            );
+            inner = inner.with_fallback_tls(RealityTlsHandler::new(config.clone())?);
+        } else if let Some(refresh) = config.dest_certificate_refresh {
+            inner = inner.with_dest_certificates(refresh);
+        }
 
         Ok(Self { inner })
     }
 
-    /// 处理传入的 TLS 连接
-    pub async fn accept<S>(&self, stream: S) -> Result<tokio_rustls::server::TlsStream<super::server_rustls::PrefixedStream<S>>> 
+    /// 处理传入的 TLS 连接，未通过认证时返回待转发的回落连接
+    pub async fn accept<S>(&self, stream: S) -> Result<Accepted<S>>
     where S: AsyncRead + AsyncWrite + Unpin + Send + 'static {
         // 使用 Sniff-and-Dispatch 逻辑
         self.inner.accept(stream).await
@@ -60,15 +75,13 @@ mod tests {
     use super::*;
 
     fn create_test_config() -> RealityConfig {
-        RealityConfig {
-            dest: "www.apple.com:443".to_string(),
-            server_names: vec!["www.apple.com".to_string()],
+        RealityConfig::new(
+            "www.apple.com:443",
+            vec!["www.apple.com".to_string()],
             // 32 bytes of 'A' in base64
-            private_key: "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=".to_string(),
-            public_key: None,
-            short_ids: vec!["0123456789abcdef".to_string()],
-            fingerprint: "chrome".to_string(),
-        }
+            "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=",
+            vec!["0123456789abcdef".to_string()],
+        )
     }
 
     #[test]
@@ -77,4 +90,21 @@ mod tests {
         let server = RealityServer::new(config);
         assert!(server.is_ok());
     }
+
+    #[test]
+    fn test_config_defaults() {
+        let config: RealityConfig = serde_json::from_str(
+            r#"{
+                "dest": "www.apple.com:443",
+                "server_names": ["www.apple.com"],
+                "private_key": "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=",
+                "short_ids": [""],
+                "fingerprint": "chrome"
+            }"#,
+        )
+        .unwrap();
+        assert!(config.session_tickets);
+        assert_eq!(config.handshake_timeout, std::time::Duration::from_secs(10));
+        assert!(config.certificate_file.is_none());
+    }
 }
@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use tokio::net::TcpStream;
@@ -9,15 +11,18 @@ use rustls::reality::RealityConfig;
 use anyhow::{Result, anyhow, bail};
 use tracing::{info, warn, error, debug};
 use rustls_pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
-use x25519_dalek::{PublicKey as X25519PublicKey, StaticSecret};
-use hkdf::Hkdf;
-use sha2::Sha256;
-use aes_gcm::{Aes256Gcm, KeyInit, AeadInPlace, Nonce};
 use bytes::Buf;
-use ring::hmac;
 
-use super::hello_parser::{self, ClientHelloInfo};
+use super::auth::{certificate_signature, RealityAuth};
+use super::cert_fetch::CertificateCache;
+use super::hello_parser::{self, ClientHelloInfo, ClientHelloProgress, ClientHelloReader};
+use super::tls::MAX_CLIENT_HELLO_LEN;
+use super::tls_handler::{RealityTlsHandler, SelfSignedParams};
+use super::keylog;
+use crate::network::connection::ProxyConnection;
+use crate::network::{RelayStats, HANDSHAKE_STATS};
 use std::sync::Mutex;
+use dashmap::DashMap;
 use lru::LruCache;
 use once_cell::sync::Lazy;
 
@@ -25,29 +30,150 @@ use once_cell::sync::Lazy;
 #[derive(Hash, PartialEq, Eq, Clone)]
 struct CertKey {
     host: String,
+    params: SelfSignedParams,
 }
 
+// 证书模板: (Cert Bytes, PrivateKey Bytes, PublicKey Raw)
+type CertTemplate = (Vec<u8>, Vec<u8>, Vec<u8>);
+
 // 全局证书缓存，容量 100
-// 存储 (Cert Bytes, PrivateKey Bytes, PublicKey Raw)
-static CERT_CACHE: Lazy<Mutex<LruCache<CertKey, (Vec<u8>, Vec<u8>, Vec<u8>)>>> = 
+static CERT_CACHE: Lazy<Mutex<LruCache<CertKey, CertTemplate>>> = 
     Lazy::new(|| Mutex::new(LruCache::new(std::num::NonZeroUsize::new(100).unwrap())));
 
+/// `accept` 的结果: 通过认证的 Reality 连接，或需要转发给 dest 的回落连接
+pub enum Accepted<S> {
+    Reality(Box<tokio_rustls::server::TlsStream<PrefixedStream<S>>>),
+    Fallback(Fallback<S>),
+}
+
+/// 未通过认证的连接，由调用方在释放握手资源后调用 [`Fallback::relay`] 转发
+pub struct Fallback<S> {
+    stream: S,
+    /// 已读取的 ClientHello，转发时先发给 dest (或交给本地 TLS 握手)
+    prefix: Vec<u8>,
+    dest: String,
+    /// 配置了证书时在本地终结 TLS
+    tls: Option<Arc<RealityTlsHandler>>,
+}
+
+impl<S> Fallback<S>
+where S: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    /// 回落目标 (按 SNI 选择)
+    pub fn dest(&self) -> &str {
+        &self.dest
+    }
+
+    /// 连接 dest 并双向转发，与代理连接一样受闲置超时和最长存活时间约束
+    ///
+    /// 配置了证书时先在本地完成 TLS 握手，再把解密后的数据转发给 dest
+    pub async fn relay(self, max_lifetime: Option<Duration>, idle_timeout: Option<Duration>) -> Result<RelayStats> {
+        debug!("Non-Reality client or SNI mismatch, falling back to {}", self.dest);
+        let Some(tls) = self.tls else {
+            let mut dest_stream = connect_dest(&self.dest).await?;
+            dest_stream.write_all(&self.prefix).await?;
+            return Ok(ProxyConnection::new(self.stream, dest_stream)
+                .with_max_lifetime(max_lifetime)
+                .with_idle_timeout(idle_timeout)
+                .relay()
+                .await);
+        };
+        let prefixed = PrefixedStream::new(self.prefix, self.stream);
+        let tls_stream = match tokio::time::timeout(FALLBACK_TIMEOUT, tls.perform_handshake(prefixed)).await {
+            Ok(result) => result?,
+            Err(_) => bail!("Fallback TLS handshake timeout"),
+        };
+        let dest_stream = connect_dest(&self.dest).await?;
+        Ok(ProxyConnection::new(tls_stream, dest_stream)
+            .with_max_lifetime(max_lifetime)
+            .with_idle_timeout(idle_timeout)
+            .relay()
+            .await)
+    }
+}
+
+/// 回落时连接 dest 与本地 TLS 握手各自的时限
+const FALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+async fn connect_dest(dest: &str) -> Result<TcpStream> {
+    match tokio::time::timeout(FALLBACK_TIMEOUT, TcpStream::connect(dest)).await {
+        Ok(result) => Ok(result?),
+        Err(_) => bail!("Fallback connection timeout"),
+    }
+}
+
+/// 按 dest 缓存的证书链，附在 Reality 证书之后发送，使 Certificate 消息的长度与真实站点接近
+///
+/// 每个 dest 有一个后台刷新任务，随最后一个 `RealityServerRustls` 释放而停止。
+struct DestCertificates {
+    refresh: Duration,
+    caches: DashMap<String, (CertificateCache, tokio::task::JoinHandle<()>)>,
+}
+
+impl DestCertificates {
+    /// dest 证书链中叶子之后的证书；首次遇到的 dest 开始后台获取，获取完成前返回空
+    fn intermediates(&self, dest: &str) -> Vec<CertificateDer<'static>> {
+        if tokio::runtime::Handle::try_current().is_err() {
+            return Vec::new();
+        }
+        let entry = self.caches.entry(dest.to_string()).or_insert_with(|| {
+            let cache = CertificateCache::new(dest);
+            let task = cache.spawn_refresh(self.refresh);
+            (cache, task)
+        });
+        entry.0.get()
+            .map(|chain| chain.iter().skip(1).cloned().map(CertificateDer::from).collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for DestCertificates {
+    fn drop(&mut self) {
+        for entry in self.caches.iter() {
+            entry.1.abort();
+        }
+    }
+}
+
 pub struct RealityServerRustls {
     reality_config: Arc<RealityConfig>,
+    auth: Arc<RealityAuth>,
     server_names: Vec<String>,
+    /// 按 SNI 选择的 dest，未命中时使用 reality_config.dest
+    server_name_dests: Arc<HashMap<String, String>>,
+    session_tickets: bool,
+    /// 读取 ClientHello 与 TLS 握手共用的时限 (不含回落转发)
+    handshake_timeout: Duration,
+    /// 回落连接在本地终结 TLS 时使用的证书
+    fallback_tls: Option<Arc<RealityTlsHandler>>,
+    /// Reality 证书的 SAN、CN、组织与有效期
+    self_signed: Arc<SelfSignedParams>,
+    /// 借用的 dest 证书链，未开启时只发送 Reality 证书
+    dest_certificates: Option<Arc<DestCertificates>>,
 }
 
 impl Clone for RealityServerRustls {
     fn clone(&self) -> Self {
         Self {
             reality_config: Arc::clone(&self.reality_config),
+            auth: Arc::clone(&self.auth),
             server_names: self.server_names.clone(),
+            server_name_dests: Arc::clone(&self.server_name_dests),
+            session_tickets: self.session_tickets,
+            handshake_timeout: self.handshake_timeout,
+            fallback_tls: self.fallback_tls.clone(),
+            self_signed: Arc::clone(&self.self_signed),
+            dest_certificates: self.dest_certificates.clone(),
         }
     }
 }
 
 impl RealityServerRustls {
     pub fn new(private_key: Vec<u8>, dest: Option<String>, short_ids: Vec<String>, server_names: Vec<String>) -> Result<Self> {
+        let key: [u8; 32] = private_key
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow!("Reality private key must be 32 bytes, got {}", private_key.len()))?;
+        let auth = RealityAuth::new(key, &short_ids)?;
         let mut short_ids_bytes = Vec::new();
         for id in short_ids {
             let b = hex::decode(&id).map_err(|e| anyhow!("Invalid shortId hex: {}", e))?;
@@ -63,40 +189,109 @@ impl RealityServerRustls {
 
         Ok(Self { 
             reality_config: Arc::new(reality_config),
+            auth: Arc::new(auth),
             server_names,
+            server_name_dests: Arc::new(HashMap::new()),
+            session_tickets: true,
+            handshake_timeout: Duration::from_secs(10),
+            fallback_tls: None,
+            self_signed: Arc::default(),
+            dest_certificates: None,
         })
     }
 
-    pub async fn accept<S>(&self, mut stream: S) -> Result<tokio_rustls::server::TlsStream<PrefixedStream<S>>> 
+    /// 为不同 SNI 指定各自的 dest (证书借用与回落都按 SNI 选择)
+    pub fn with_server_name_dests(mut self, dests: HashMap<String, String>) -> Self {
+        self.server_name_dests = Arc::new(dests);
+        self
+    }
+
+    fn dest_for(&self, sni: Option<&str>) -> &str {
+        sni.and_then(|sni| self.server_name_dests.get(sni))
+            .map(String::as_str)
+            .or(self.reality_config.dest.as_deref())
+            .unwrap_or("www.microsoft.com:443")
+    }
+
+    /// 是否在握手后发送 NewSessionTicket (默认开启)
+    pub fn with_session_tickets(mut self, enabled: bool) -> Self {
+        self.session_tickets = enabled;
+        self
+    }
+
+    /// 握手超时 (默认 10 秒)
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Reality 证书使用的 SAN、CN、组织与有效期 (`domains` 为空时使用 dest 的主机名)
+    pub fn with_self_signed(mut self, params: SelfSignedParams) -> Self {
+        self.self_signed = Arc::new(params);
+        self
+    }
+
+    /// 在 Reality 证书之后附上 dest 的中间证书，每隔 `refresh` 重新获取一次，
+    /// 获取失败时继续使用上一次的证书链
+    ///
+    /// 需在 `with_server_name_dests` 之后调用: 在 tokio 运行时中创建时立即开始获取所有已知 dest 的证书链
+    pub fn with_dest_certificates(mut self, refresh: Duration) -> Self {
+        let dest_certificates = DestCertificates { refresh, caches: DashMap::new() };
+        for dest in std::iter::once(self.dest_for(None)).chain(self.server_name_dests.values().map(String::as_str)) {
+            dest_certificates.intermediates(dest);
+        }
+        self.dest_certificates = Some(Arc::new(dest_certificates));
+        self
+    }
+
+    /// 回落连接先以 `handler` 的证书在本地完成 TLS 握手，再以明文转发到 dest
+    pub fn with_fallback_tls(mut self, handler: RealityTlsHandler) -> Self {
+        self.fallback_tls = Some(Arc::new(handler));
+        self
+    }
+
+    /// 读取 ClientHello 并完成 Reality 握手；未通过认证时返回回落连接，由调用方转发
+    pub async fn accept<S>(&self, mut stream: S) -> Result<Accepted<S>>
     where S: AsyncRead + AsyncWrite + Unpin + Send + 'static {
         let mut buffer = Vec::with_capacity(2048);
-        // 设置握手超时 (防止僵尸连接)
-        let handshake_timeout = std::time::Duration::from_secs(5);
+        // 整个握手共用一个截止时间 (防止慢速发送的僵尸连接)
+        let deadline = tokio::time::Instant::now() + self.handshake_timeout;
 
+        // ClientHello 可能跨多个记录，按需扩展缓冲区直到消息完整，最多 MAX_CLIENT_HELLO_LEN
         let read_task = async {
-            while buffer.len() < 5 {
-                let mut chunk = [0u8; 1024];
-                let n = stream.read(&mut chunk).await?;
-                if n == 0 { bail!("Connection closed early"); }
-                buffer.extend_from_slice(&chunk[..n]);
-            }
-
-            let needed = if buffer[0] == 0x16 { 5 + u16::from_be_bytes([buffer[3], buffer[4]]) as usize } else { buffer.len() };
-            while buffer.len() < needed && buffer.len() < 16384 {
-                 let mut chunk = [0u8; 1024];
-                 let n = stream.read(&mut chunk).await?;
-                 if n == 0 { break; }
-                 buffer.extend_from_slice(&chunk[..n]);
+            let mut reader = ClientHelloReader::default();
+            loop {
+                let n = stream.read_buf(&mut buffer).await?;
+                if n == 0 {
+                    if buffer.len() < 5 { bail!("Connection closed early"); }
+                    return Ok(None);
+                }
+                match reader.advance(&buffer)? {
+                    ClientHelloProgress::Complete { message, .. } => return Ok(Some(message)),
+                    ClientHelloProgress::NotClientHello => return Ok(None),
+                    ClientHelloProgress::Incomplete => {}
+                }
+                if buffer.len() > MAX_CLIENT_HELLO_LEN {
+                    bail!("ClientHello too large: read {} bytes without a complete message", buffer.len());
+                }
+                buffer.reserve(4096);
             }
-            Ok::<(), anyhow::Error>(())
         };
 
-        match tokio::time::timeout(handshake_timeout, read_task).await {
+        let message = match tokio::time::timeout_at(deadline, read_task).await {
             Ok(result) => result?,
-            Err(_) => bail!("Handshake timeout: Client sent incomplete or no data"),
-        }
+            Err(_) => {
+                HANDSHAKE_STATS.record_timeout();
+                warn!("Reality handshake timed out waiting for ClientHello");
+                bail!("Handshake timeout: Client sent incomplete or no data");
+            }
+        };
 
-        if let Ok(Some(info)) = hello_parser::parse_client_hello(&buffer) {
+        let mut dest = self.dest_for(None);
+        if let Some(info) = message
+            .and_then(|m| hello_parser::parse_client_hello_message(m).ok().flatten())
+            .filter(Self::accepts_version)
+        {
             // SNI 验证逻辑
             let sni_valid = if self.server_names.is_empty() {
                 true // 如果没配置 server_names，则允许所有（或者应该默认不允许？为了安全推荐配置）
@@ -106,93 +301,97 @@ impl RealityServerRustls {
                 false // 必须携带 SNI
             };
 
+            if sni_valid {
+                dest = self.dest_for(info.server_name.as_deref());
+            }
+
             if !sni_valid {
                 warn!("Reality SNI mismatch: {:?} (Allowed: {:?})", info.server_name, self.server_names);
                 // Fallthrough to fallback (don't verify reality)
-            } else if let Some((offset, auth_key)) = self.verify_client_reality(&info, &buffer) {
-                let dest_str = self.reality_config.dest.as_deref().unwrap_or("www.microsoft.com");
-                let dest_host = dest_str.split(':').next().unwrap_or("www.microsoft.com");
-
-                info!("Reality: Verified client (Offset {}), generating dynamic signature-certificate", offset);
+            } else if let Some(client) = self.auth.authenticate(&info) {
+                let dest_host = dest_host(self.dest_for(info.server_name.as_deref()));
+
+                info!(
+                    "Reality: Verified client {}.{}.{} (shortId {}), generating dynamic signature-certificate",
+                    client.version[0],
+                    client.version[1],
+                    client.version[2],
+                    hex::encode(client.short_id)
+                );
+                debug!("Reality client timestamp: {}", client.timestamp);
                 
-                let (cert, key) = self.generate_reality_cert(&auth_key, dest_host)?;
+                let (cert, key) = self.generate_reality_cert(&client.auth_key, dest_host)?;
+                // 客户端只校验叶子证书的 HMAC，其后的 dest 中间证书只影响 Certificate 消息的长度
+                let mut chain = vec![cert];
+                if let Some(dest_certificates) = &self.dest_certificates {
+                    chain.extend(dest_certificates.intermediates(dest));
+                }
 
                 let mut conn_reality_config = (*self.reality_config).clone();
-                conn_reality_config.private_key = auth_key.to_vec();
+                conn_reality_config.private_key = client.auth_key.to_vec();
                 conn_reality_config.verify_client = false; 
 
                 // Explicitly use the ring provider to ensure Ed25519 support
                 let mut config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
-                    .with_safe_default_protocol_versions()?
+                    .with_protocol_versions(&[&rustls::version::TLS13])?
                     .with_no_client_auth()
-                    .with_single_cert(vec![cert], key)
+                    .with_single_cert(chain, key)
                     .map_err(|e| anyhow!("Config build fail: {}", e))?;
                 config.reality_config = Some(Arc::new(conn_reality_config));
+                // 按客户端的顺序选择套件: 把 ChaCha20-Poly1305 放在首位的移动端客户端不会被换成 AES
+                config.ignore_client_order = false;
+                // 每个连接使用独立的 ServerConfig，票据无法用于恢复，客户端会回退到完整握手
+                config.send_tls13_tickets = if self.session_tickets { 2 } else { 0 };
+                if keylog::enabled() {
+                    config.key_log = Arc::new(keylog::RustlsKeyLog);
+                }
 
                 let acceptor = TlsAcceptor::from(Arc::new(config));
                 let prefixed = PrefixedStream::new(buffer, stream);
                 
-                // 核心修复：为 TLS 握手添加超时保护 (5s)
-                match tokio::time::timeout(std::time::Duration::from_secs(5), acceptor.accept(prefixed)).await {
+                match tokio::time::timeout_at(deadline, acceptor.accept(prefixed)).await {
                     Ok(Ok(tls)) => {
                         info!("Reality handshake successful");
-                        return Ok(tls);
+                        return Ok(Accepted::Reality(Box::new(tls)));
                     }
                     Ok(Err(e)) => {
                         error!("Reality TLS handshake failed: {}", e);
                         bail!("Handshake failure");
                     }
                     Err(_) => {
-                        error!("Reality TLS handshake timeout");
+                        HANDSHAKE_STATS.record_timeout();
+                        error!("Reality TLS handshake timeout (after ClientHello, before client Finished)");
                         bail!("Handshake timeout");
                     }
                 }
             }
         }
 
-        let dest = self.reality_config.dest.as_deref().unwrap_or("www.microsoft.com:443");
-        debug!("Non-Reality client or SNI mismatch, falling back to {}", dest);
-        self.fallback(stream, &buffer, dest).await?;
-        bail!("Fallback total");
+        Ok(Accepted::Fallback(Fallback {
+            stream,
+            prefix: buffer,
+            dest: dest.to_string(),
+            tls: self.fallback_tls.clone(),
+        }))
     }
 
-    fn verify_client_reality(&self, info: &ClientHelloInfo, full_hello: &[u8]) -> Option<(usize, [u8; 32])> {
-        if info.session_id.len() != 32 || info.public_key.is_none() { return None; }
-        
-        let mut server_priv = [0u8; 32];
-        server_priv.copy_from_slice(&self.reality_config.private_key);
-        let client_pub: [u8; 32] = info.public_key.as_ref()?.as_slice().try_into().ok()?;
-        
-        let shared = StaticSecret::from(server_priv).diffie_hellman(&X25519PublicKey::from(client_pub));
-        
-        // HKDF Salt: Standard Reality uses ClientHello.Random[:20]
-        let hk = Hkdf::<Sha256>::new(Some(&info.client_random[0..20]), shared.as_bytes());
-        let mut auth_key = [0u8; 32];
-        if hk.expand(b"REALITY", &mut auth_key).is_err() { return None; }
-
-        let cipher = Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(&auth_key));
-        let nonce = Nonce::from_slice(&info.client_random[20..32]);
-
-        let handshake_msg = if full_hello[0] == 0x16 { &full_hello[5..] } else { full_hello };
-        let mut aad = handshake_msg.to_vec();
-        if let Some(pos) = hex::encode(&aad).find(&hex::encode(&info.session_id)).map(|p| p/2) {
-            for i in 0..32 { if pos + i < aad.len() { aad[pos + i] = 0; } }
-        }
-
-        let mut buf = info.session_id.clone();
-        if cipher.decrypt_in_place(nonce, &aad, &mut buf).is_err() { return None; }
-        if buf.len() < 16 { return None; }
-
-        for sid in &self.reality_config.short_ids {
-            if sid == &buf[4..12] { return Some((4, auth_key)); }
-            if sid == &buf[8..16] { return Some((8, auth_key)); }
+    /// 只有 TLS 1.3 客户端才进入 Reality 握手；只提供旧版本或 legacy_version 异常的
+    /// ClientHello (降级探测) 交给 dest 处理，由真实站点作出回应
+    fn accepts_version(info: &ClientHelloInfo) -> bool {
+        let accepted = info.offers_tls13();
+        if !accepted {
+            debug!(
+                "Client does not offer TLS 1.3 (legacy_version {:#06x}, supported_versions {:04x?}), falling back",
+                info.legacy_version, info.supported_versions
+            );
         }
-        None
+        accepted
     }
 
     fn generate_reality_cert(&self, auth_key: &[u8; 32], host: &str) -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
         let key = CertKey {
             host: host.to_string(),
+            params: (*self.self_signed).clone(),
         };
 
         // 1. 尝试从缓存获取模板
@@ -214,17 +413,22 @@ impl RealityServerRustls {
 
         // 2. 如果没有命中，生成模板并写入缓存
         if template.is_none() {
-             use rcgen::{CertificateParams, KeyPair, PKCS_ED25519};
- 
-             let key_pair = KeyPair::generate(&PKCS_ED25519).map_err(|e| anyhow!("Key generation fail: {}", e))?;
+             // 客户端用 HMAC 校验 Ed25519 签名位，密钥类型固定为 Ed25519，不受 keyType 影响
+             let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519)
+                 .map_err(|e| anyhow!("Key generation fail: {}", e))?;
              let pub_key_raw = key_pair.public_key_raw().to_vec();
-             let mut params = CertificateParams::new(vec![host.to_string()]);
-             params.alg = &PKCS_ED25519;
-             params.key_pair = Some(key_pair);
-             
-             let cert = rcgen::Certificate::from_params(params).map_err(|e| anyhow!("Cert generation fail: {}", e))?;
-             let cert_der = cert.serialize_der().map_err(|e| anyhow!("Cert serialization fail: {}", e))?;
-             let priv_key_der = cert.serialize_private_key_der();
+             let domains = if self.self_signed.domains.is_empty() {
+                 vec![host.to_string()]
+             } else {
+                 self.self_signed.domains.clone()
+             };
+             let cert = self
+                 .self_signed
+                 .certificate_params(domains)?
+                 .self_signed(&key_pair)
+                 .map_err(|e| anyhow!("Cert generation fail: {}", e))?;
+             let cert_der = cert.der().to_vec();
+             let priv_key_der = key_pair.serialize_der();
 
              // 存入缓存
              {
@@ -251,34 +455,22 @@ impl RealityServerRustls {
             bail!("CERT DER too short");
         }
         let sig_pos = total_len - 64;
-        let ring_key = hmac::Key::new(hmac::HMAC_SHA512, auth_key);
         // 使用模板的 Public Key 进行签名
-        let signature = hmac::sign(&ring_key, &pub_key_raw);
-        let sig_bytes = signature.as_ref(); 
+        let signature = certificate_signature(auth_key, &pub_key_raw);
 
         // Overwrite the signature at the end of DER
-        cert_der[sig_pos..].copy_from_slice(sig_bytes);
+        cert_der[sig_pos..].copy_from_slice(&signature);
         
         let result_cert = CertificateDer::from(cert_der);
         let result_key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(priv_key_der));
         Ok((result_cert, result_key))
     }
+}
 
-    async fn fallback<S>(&self, mut stream: S, prefix: &[u8], dest: &str) -> Result<()> 
-    where S: AsyncRead + AsyncWrite + Unpin + Send + 'static {
-        // 核心修复：为回退连接添加超时保护 (10s)
-        let mut dest_stream = match tokio::time::timeout(
-            std::time::Duration::from_secs(10),
-            TcpStream::connect(dest)
-        ).await {
-            Ok(Ok(s)) => s,
-            Ok(Err(e)) => return Err(e.into()),
-            Err(_) => bail!("Fallback connection timeout"),
-        };
-        dest_stream.write_all(prefix).await?;
-        tokio::io::copy_bidirectional(&mut stream, &mut dest_stream).await?;
-        Ok(())
-    }
+/// 借用证书时使用的主机名 ("host:port" 去掉端口)
+fn dest_host(dest: &str) -> &str {
+    let host = dest.rsplit_once(':').map(|(host, _)| host).unwrap_or(dest);
+    host.trim_start_matches('[').trim_end_matches(']')
 }
 
 pub struct PrefixedStream<S> { prefix: std::io::Cursor<Vec<u8>>, inner: S }
@@ -1,11 +1,32 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use bytes::{Buf, BufMut, BytesMut};
-use std::io;
+use std::io::{self, IoSlice};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
-use super::crypto::TlsKeys;
+use super::crypto::{CipherSuite, TlsKeys};
+use super::tls::{encode_key_update, HandshakeType, ALERT_CLOSE_NOTIFY, ALERT_LEVEL_WARNING};
+
+/// 单条 TLS 1.3 记录的最大明文长度 (RFC 8446 §5.1)
+pub const MAX_RECORD_PLAINTEXT: usize = 16 * 1024;
+
+/// 单条 TLS 1.3 记录的最大密文长度 (2^14 + 256)
+const MAX_RECORD_CIPHERTEXT: usize = MAX_RECORD_PLAINTEXT + 256;
+
+/// 已加密但未发出的数据超过该值时，poll_write 不再接收新数据
+const MAX_PENDING_OUTPUT: usize = 64 * 1024;
+
+/// 握手结果摘要 (用于日志)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// 协商的密码套件
+    pub suite: CipherSuite,
+    /// ClientHello 中的 SNI
+    pub server_name: Option<String>,
+    /// Reality 认证是否通过
+    pub reality_authenticated: bool,
+}
 
 /// 封装了 TLS 1.3 加解密的流
 pub struct TlsStream<S> {
@@ -14,15 +35,31 @@ pub struct TlsStream<S> {
 
     // 输入缓冲区 (存储从 TCP 读到的原始加密数据)
     input_buffer: BytesMut,
-    // 解密后的数据缓冲区 (等待被上层消费)
+    // 已解析头部、正在等待正文的记录: (记录头, 密文长度)
+    record_header: Option<([u8; 5], usize)>,
+    // 当前记录解密后的明文 (与 input_buffer 共享内存，等待被上层消费)
     decrypted_buffer: BytesMut,
 
-    // Write buffer (plaintext accumulation)
+    // 积攒中的明文，满一条记录或 flush 时加密
     write_buffer: BytesMut,
+    // 已加密、等待发送的记录 (按顺序发出，允许部分写入)
+    pending_output: BytesMut,
+    // 单条记录的最大明文长度
+    max_record_size: usize,
 
     // 序列号
     read_seq: u64,
     write_seq: u64,
+
+    // 对端已发送 close_notify
+    peer_closed: bool,
+    // 已排队 close_notify
+    close_notify_sent: bool,
+    // 以客户端身份运行 (密钥已交换读写方向)
+    is_client: bool,
+
+    server_name: Option<String>,
+    reality_authenticated: bool,
 }
 
 impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
@@ -31,10 +68,18 @@ impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
             stream,
             keys,
             input_buffer: BytesMut::with_capacity(24 * 1024),
-            decrypted_buffer: BytesMut::with_capacity(24 * 1024),
-            write_buffer: BytesMut::with_capacity(16 * 1024 + 1024),
+            record_header: None,
+            decrypted_buffer: BytesMut::new(),
+            write_buffer: BytesMut::with_capacity(MAX_RECORD_PLAINTEXT),
+            pending_output: BytesMut::new(),
+            max_record_size: MAX_RECORD_PLAINTEXT,
             read_seq: 0,
             write_seq: 0,
+            peer_closed: false,
+            close_notify_sent: false,
+            is_client: false,
+            server_name: None,
+            reality_authenticated: false,
         }
     }
 
@@ -42,110 +87,229 @@ impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
         Self {
             stream,
             keys,
-            input_buffer: initial_data, // Use provided buffer
-            decrypted_buffer: BytesMut::with_capacity(24 * 1024),
-            write_buffer: BytesMut::with_capacity(16 * 1024 + 1024),
+            input_buffer: initial_data, // 握手阶段多读到的数据
+            record_header: None,
+            decrypted_buffer: BytesMut::new(),
+            write_buffer: BytesMut::with_capacity(MAX_RECORD_PLAINTEXT),
+            pending_output: BytesMut::new(),
+            max_record_size: MAX_RECORD_PLAINTEXT,
             read_seq: 0,
             write_seq: 0,
+            peer_closed: false,
+            close_notify_sent: false,
+            is_client: false,
+            server_name: None,
+            reality_authenticated: false,
         }
     }
 
-    /// 尝试从 input_buffer 解析并解密一条 TLS 记录
-    fn process_record(&mut self) -> Result<bool> {
-        if self.input_buffer.len() < 5 {
-            return Ok(false);
-        }
+    /// 以客户端身份使用握手得到的应用密钥
+    ///
+    /// 读写方向与服务端相反；服务端发来的 NewSessionTicket 会被忽略 (不做会话恢复)。
+    pub fn new_client(stream: S, keys: TlsKeys, initial_data: BytesMut) -> Self {
+        let mut tls = Self::new_with_buffer(stream, keys.into_peer_view(), initial_data);
+        tls.is_client = true;
+        tls
+    }
 
-        let length = u16::from_be_bytes([self.input_buffer[3], self.input_buffer[4]]) as usize;
+    /// 记录握手得到的 SNI 与 Reality 认证结果，供 `session_info` 返回
+    pub fn with_session(mut self, server_name: Option<String>, reality_authenticated: bool) -> Self {
+        self.server_name = server_name;
+        self.reality_authenticated = reality_authenticated;
+        self
+    }
 
-        if self.input_buffer.len() < 5 + length {
-            return Ok(false);
+    /// 协商结果: 密码套件、SNI、Reality 认证是否通过
+    pub fn session_info(&self) -> SessionInfo {
+        SessionInfo {
+            suite: self.keys.suite,
+            server_name: self.server_name.clone(),
+            reality_authenticated: self.reality_authenticated,
         }
+    }
 
-        // 提取整条记录
-        let mut record_data = self.input_buffer.split_to(5 + length);
-        let mut header = [0u8; 5];
-        header.copy_from_slice(&record_data[..5]);
+    /// 设置单条记录的最大明文长度 (1..=16384)
+    ///
+    /// 较小的记录可以让对端更早开始解密，适合交互式流量；默认 16KB 吞吐最高。
+    pub fn set_max_record_size(&mut self, size: usize) {
+        self.max_record_size = size.clamp(1, MAX_RECORD_PLAINTEXT);
+    }
 
-        // Payload 部分 (Ciphertext)
-        let ciphertext = &mut record_data[5..];
+    /// 用当前服务端密钥加密一条握手消息，在下一次 flush 时先于应用数据发出
+    pub fn queue_handshake_message(&mut self, message: &[u8]) -> Result<()> {
+        self.queue_record(22, message)
+    }
 
-        // 解密
-        let (content_type, len) =
-            self.keys
-                .decrypt_client_record(self.read_seq, &header, ciphertext)?;
-        self.read_seq += 1;
+    fn queue_record(&mut self, content_type: u8, payload: &[u8]) -> Result<()> {
+        // 直接加密进 pending_output，发完后其空间会被复用
+        self.keys.seal_server_record(self.write_seq, payload, content_type, &mut self.pending_output)?;
+        self.write_seq += 1;
+        Ok(())
+    }
 
-        // 处理数据
-        if content_type == 23 {
-            // Application Data
-            self.decrypted_buffer.extend_from_slice(&ciphertext[..len]);
-        } else if content_type == 21 { // Alert
-             // Close notify (100) ?
+    /// 从 input_buffer 取出一条完整记录并原地解密，数据不足时返回 false
+    ///
+    /// 只能在 decrypted_buffer 为空时调用: 应用数据记录的明文直接成为新的 decrypted_buffer。
+    fn process_record(&mut self) -> Result<bool> {
+        let (header, length) = match self.record_header {
+            Some(pending) => pending,
+            None => {
+                if self.input_buffer.len() < 5 {
+                    return Ok(false);
+                }
+                let mut header = [0u8; 5];
+                header.copy_from_slice(&self.input_buffer[..5]);
+                if header[0] != 23 {
+                    return Err(anyhow!("Unexpected record type {} after handshake", header[0]));
+                }
+                let length = u16::from_be_bytes([header[3], header[4]]) as usize;
+                if length > MAX_RECORD_CIPHERTEXT {
+                    return Err(anyhow!("Record too large: {} bytes", length));
+                }
+                self.input_buffer.advance(5);
+                self.record_header = Some((header, length));
+                (header, length)
+            }
+        };
+
+        if self.input_buffer.len() < length {
+            return Ok(false);
+        }
+        self.record_header = None;
+
+        // split_to 不复制数据，解密在原内存上进行
+        let mut record = self.input_buffer.split_to(length);
+        let (content_type, len) = self.keys.decrypt_client_record(self.read_seq, &header, &mut record)?;
+        self.read_seq += 1;
+        record.truncate(len);
+
+        match content_type {
+            23 => self.decrypted_buffer = record,
+            21 => {
+                // close_notify 表示对端正常结束写入，其余均为致命错误
+                if record.get(1) == Some(&ALERT_CLOSE_NOTIFY) {
+                    self.peer_closed = true;
+                } else {
+                    return Err(anyhow!("Peer sent alert {}", record.get(1).copied().unwrap_or(0)));
+                }
+            }
+            22 => self.process_post_handshake(&record)?,
+            other => return Err(anyhow!("Unexpected content type {} after handshake", other)),
         }
 
         Ok(true)
     }
 
-    /// 将 write_buffer 中的明文数据打包加密并发送
-    fn flush_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    /// 处理握手完成后对端发来的 Handshake 消息 (RFC 8446 §4.6)
+    ///
+    /// 只接受 KeyUpdate (客户端模式下另外跳过 NewSessionTicket): 切换到下一代对端密钥；
+    /// 若对端请求更新，则排队一条用旧密钥加密的 KeyUpdate，再切换本端密钥。
+    fn process_post_handshake(&mut self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() {
+            if data.len() < 4 {
+                return Err(anyhow!("Truncated post-handshake message"));
+            }
+            let msg_len = u32::from_be_bytes([0, data[1], data[2], data[3]]) as usize;
+            if data.len() < 4 + msg_len {
+                return Err(anyhow!("Truncated post-handshake message"));
+            }
+            let (msg, rest) = data.split_at(4 + msg_len);
+            data = rest;
+
+            if self.is_client && msg[0] == HandshakeType::NewSessionTicket as u8 {
+                continue;
+            }
+            if msg[0] != HandshakeType::KeyUpdate as u8 {
+                return Err(anyhow!("Unexpected post-handshake message type {}", msg[0]));
+            }
+            // 新密钥从下一条记录开始生效，KeyUpdate 必须是记录中的最后一条消息
+            if msg_len != 1 || !data.is_empty() {
+                return Err(anyhow!("Malformed KeyUpdate"));
+            }
+            let update_requested = match msg[4] {
+                0 => false,
+                1 => true,
+                other => return Err(anyhow!("Invalid KeyUpdate request value {}", other)),
+            };
+
+            self.keys.update_client_keys()?;
+            self.read_seq = 0;
+
+            if update_requested {
+                self.queue_handshake_message(&encode_key_update(false))?;
+                self.keys.update_server_keys()?;
+                self.write_seq = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// 将 write_buffer 中的明文加密为一条应用数据记录
+    fn seal_write_buffer(&mut self) -> io::Result<()> {
         if self.write_buffer.is_empty() {
-            return Poll::Ready(Ok(()));
+            return Ok(());
+        }
+        let mut plaintext = std::mem::take(&mut self.write_buffer);
+        let result = self.queue_record(23, &plaintext).map_err(io::Error::other);
+        plaintext.clear();
+        self.write_buffer = plaintext;
+        result
+    }
+
+    /// 把 pending_output 写入底层流，直到写完或底层流返回 Pending
+    fn poll_drain_output(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.pending_output.is_empty() {
+            match Pin::new(&mut self.stream).poll_write(cx, &self.pending_output) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(n)) => self.pending_output.advance(n),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
         }
+        Poll::Ready(Ok(()))
+    }
 
-        // 1. Encrypt accumulated plaintext
-        let encrypted_record =
-            match self
-                .keys
-                .encrypt_server_record(self.write_seq, &self.write_buffer, 23)
-            {
-                Ok(data) => data,
-                Err(e) => return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, e))),
-            };
+    /// 加密剩余明文并全部发出
+    fn poll_flush_records(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.seal_write_buffer()?;
+        self.poll_drain_output(cx)
+    }
 
-        // 2. Write ALL encrypted bytes to underlying stream
-        // Note: For strict correctness, we should handle partial writes properly by keeping `encrypted_record` in a separate buffer.
-        // However, standard tokio AsyncWrite mostly handles the buffering internally or we assume the socket can take it.
-        // But to be safe and simple, we do a loop here - BEWARE: this blocks the task if socket full.
-        // A better approach is another encryption_buffer state. But for now, let's try direct write loop.
-
-        // Actually, we can't loop-wait in Poll.
-        // We really need an `encrypted_output_buffer`.
-
-        // Let's rely on the assumption that for typical TLS record sizes (~16KB), the kernel socket buffer is likely sufficient.
-        // IF it returns Pending, we are in trouble because we lose the encrypted record.
-
-        // REVISION: We MUST just loop write here for simplicity in this minimal implementation, using `poll_write` repeatedly?
-        // No, that panics context.
-
-        // Let's implement a simplified "write all at once" for now.
-        // If this performance optimization is critical, we assume the underlying stream handles buffering (e.g. TCP).
-
-        // Or better: Let's assume poll_write handles the whole buffer or nothing/error.
-        // Most async IO implementations won't do partial writes on small buffers unless socket buffer is nearly full.
-
-        // Correct implementation for production:
-        // shift `write_buffer` -> `encrypted_buffer`.
-        // drain `encrypted_buffer` to `stream`.
-
-        // For this step, I'll do a direct write attempt.
-        match Pin::new(&mut self.stream).poll_write(cx, &encrypted_record) {
-            Poll::Ready(Ok(n)) => {
-                if n < encrypted_record.len() {
-                    // CRITICAL: Partial write of encrypted frame corrupts the stream.
-                    // We must return error.
-                    return Poll::Ready(Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "Partial TLS record write",
-                    )));
+    /// 写路径: 明文按 max_record_size 切分为记录，小块写入合并进同一条记录
+    ///
+    /// 不足一条记录的数据留在 write_buffer 中，直到后续写入填满或 flush。
+    fn poll_write_slices(&mut self, cx: &mut Context<'_>, bufs: &[IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        if self.pending_output.len() >= MAX_PENDING_OUTPUT {
+            match self.poll_drain_output(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending if self.pending_output.len() >= MAX_PENDING_OUTPUT => return Poll::Pending,
+                Poll::Pending => {}
+            }
+        }
+
+        let mut written = 0;
+        'bufs: for buf in bufs {
+            let mut buf: &[u8] = buf;
+            while !buf.is_empty() {
+                if self.pending_output.len() >= MAX_PENDING_OUTPUT {
+                    break 'bufs;
+                }
+                let n = buf.len().min(self.max_record_size - self.write_buffer.len());
+                self.write_buffer.extend_from_slice(&buf[..n]);
+                buf = &buf[n..];
+                written += n;
+                if self.write_buffer.len() == self.max_record_size {
+                    self.seal_write_buffer()?;
                 }
-                self.write_seq += 1;
-                self.write_buffer.clear();
-                Poll::Ready(Ok(()))
             }
-            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-            Poll::Pending => Poll::Pending,
         }
+
+        // 尽量把已加密的记录发出；底层流暂时不可写也不影响本次已接收的数据
+        if let Poll::Ready(Err(e)) = self.poll_drain_output(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(written))
     }
 }
 
@@ -157,36 +321,32 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<S> {
     ) -> Poll<io::Result<()>> {
         let this = self.get_mut();
 
-        // 1. Drain decrypted buffer
-        if !this.decrypted_buffer.is_empty() {
-            let len = std::cmp::min(buf.remaining(), this.decrypted_buffer.len());
-            buf.put_slice(&this.decrypted_buffer[..len]);
-            this.decrypted_buffer.advance(len);
-            return Poll::Ready(Ok(()));
-        }
-
-        // 2. Loop to read and process records
         loop {
-            // Process any pending data
+            if !this.decrypted_buffer.is_empty() {
+                let len = std::cmp::min(buf.remaining(), this.decrypted_buffer.len());
+                buf.put_slice(&this.decrypted_buffer[..len]);
+                this.decrypted_buffer.advance(len);
+                return Poll::Ready(Ok(()));
+            }
+
+            // close_notify 之后视为 EOF
+            if this.peer_closed {
+                return Poll::Ready(Ok(()));
+            }
+
             match this.process_record() {
-                Ok(true) => {
-                    if !this.decrypted_buffer.is_empty() {
-                        let len = std::cmp::min(buf.remaining(), this.decrypted_buffer.len());
-                        buf.put_slice(&this.decrypted_buffer[..len]);
-                        this.decrypted_buffer.advance(len);
-                        return Poll::Ready(Ok(()));
-                    }
-                    continue;
-                }
+                Ok(true) => continue,
                 Ok(false) => { /* Need more data */ }
                 Err(e) => return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, e))),
             }
 
-            // Read from underlying stream
-            if this.input_buffer.capacity() < 1024 {
-                this.input_buffer.reserve(4096);
-            }
+            // 释放已读完的明文，让 input_buffer 可以原地回收前面的空间；
+            // 至少为当前记录的剩余部分预留空间
+            this.decrypted_buffer = BytesMut::new();
+            let needed = this.record_header.map_or(5, |(_, length)| length);
+            this.input_buffer.reserve(needed.saturating_sub(this.input_buffer.len()).max(4096));
 
+            // Read from underlying stream
             let dest = this.input_buffer.chunk_mut();
             // Safety: converting UninitSlice to &mut [MaybeUninit<u8>] manually
             let slice = unsafe {
@@ -202,7 +362,7 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<S> {
                     let n = read_buf.filled().len();
                     if n == 0 {
                         // EOF, but make sure we processed everything
-                        if this.input_buffer.is_empty() {
+                        if this.input_buffer.is_empty() && this.record_header.is_none() {
                             return Poll::Ready(Ok(()));
                         } else {
                             // Unexpected EOF inside record
@@ -229,33 +389,25 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsStream<S> {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        let this = self.get_mut();
-
-        // 缓冲策略: 只有 buffer 超过 14KB 时才 flush，否则只是积攒
-        if this.write_buffer.len() + buf.len() > 14336 {
-            // ~14KB threshold
-            // Try to flush first
-            if let Poll::Ready(Err(e)) = this.flush_write_buffer(cx) {
-                return Poll::Ready(Err(e));
-            }
-            // If flush returned Pending, we can't accept more data yet?
-            // Actually, if flush is Pending, strictly we should return Pending.
-            // But our `flush_write_buffer` is simple and atomic-like.
+        self.get_mut().poll_write_slices(cx, &[IoSlice::new(buf)])
+    }
 
-            if !this.write_buffer.is_empty() {
-                return Poll::Pending;
-            }
-        }
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_write_slices(cx, bufs)
+    }
 
-        // Add to buffer
-        this.write_buffer.extend_from_slice(buf);
-        Poll::Ready(Ok(buf.len()))
+    fn is_write_vectored(&self) -> bool {
+        true
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let this = self.get_mut();
 
-        match this.flush_write_buffer(cx) {
+        match this.poll_flush_records(cx) {
             Poll::Ready(Ok(())) => Pin::new(&mut this.stream).poll_flush(cx),
             Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
             Poll::Pending => Poll::Pending,
@@ -264,11 +416,254 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsStream<S> {
 
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let this = self.get_mut();
-        // Try to flush any remaining data
-        match this.flush_write_buffer(cx) {
+
+        // 数据记录之后排队 close_notify，再一并发出
+        if !this.close_notify_sent {
+            this.seal_write_buffer()?;
+            if let Err(e) = this.queue_record(21, &[ALERT_LEVEL_WARNING, ALERT_CLOSE_NOTIFY]) {
+                return Poll::Ready(Err(io::Error::other(e)));
+            }
+            this.close_notify_sent = true;
+        }
+        match this.poll_drain_output(cx) {
             Poll::Ready(Ok(())) => Pin::new(&mut this.stream).poll_shutdown(cx),
             Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
-            Poll::Pending => Poll::Pending, // Cannot shutdown if can't flush
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::reality::crypto::{CipherSuite, TranscriptHash};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// 服务端密钥和交换了读写方向的客户端密钥
+    fn keys() -> (TlsKeys, TlsKeys) {
+        let suite = CipherSuite::Aes128GcmSha256;
+        let mut transcript = TranscriptHash::new(suite);
+        transcript.add(b"ch");
+        transcript.add(b"sh");
+        let hash = transcript.current_hash();
+        let (server, _) = TlsKeys::derive_handshake_keys(suite, &[3u8; 32], &hash).unwrap();
+        let (peer, _) = TlsKeys::derive_handshake_keys(suite, &[3u8; 32], &hash).unwrap();
+        let client = TlsKeys {
+            suite,
+            client_write_key: peer.server_write_key,
+            server_write_key: peer.client_write_key,
+            client_iv: peer.server_iv,
+            server_iv: peer.client_iv,
+            client_traffic_secret: peer.server_traffic_secret,
+            server_traffic_secret: peer.client_traffic_secret,
+        };
+        (server, client)
+    }
+
+    async fn read_record<R: AsyncRead + Unpin>(stream: &mut R, keys: &TlsKeys, seq: u64) -> (u8, Vec<u8>) {
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).await.unwrap();
+        let mut body = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize];
+        stream.read_exact(&mut body).await.unwrap();
+        let (content_type, len) = keys.decrypt_client_record(seq, &header, &mut body).unwrap();
+        body.truncate(len);
+        (content_type, body)
+    }
+
+    #[tokio::test]
+    async fn test_key_update_mid_transfer() {
+        let (server_keys, mut client) = keys();
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let (mut client_rx, mut client_tx) = tokio::io::split(client_io);
+        let mut server = TlsStream::new(server_io, server_keys);
+
+        // 客户端: 两条旧密钥数据 -> KeyUpdate(update_requested) -> 新密钥数据
+        client_tx.write_all(&client.encrypt_server_record(0, b"before-", 23).unwrap()).await.unwrap();
+        client_tx.write_all(&client.encrypt_server_record(1, b"update-", 23).unwrap()).await.unwrap();
+        client_tx.write_all(&client.encrypt_server_record(2, &encode_key_update(true), 22).unwrap()).await.unwrap();
+        client.update_server_keys().unwrap();
+        client_tx.write_all(&client.encrypt_server_record(0, b"after", 23).unwrap()).await.unwrap();
+
+        let mut received = vec![0u8; 19];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, b"before-update-after");
+
+        // 服务端: 先发出 KeyUpdate，之后的数据使用新密钥且序列号从 0 开始
+        server.write_all(b"response").await.unwrap();
+        server.flush().await.unwrap();
+
+        let (content_type, msg) = read_record(&mut client_rx, &client, 0).await;
+        assert_eq!(content_type, 22);
+        assert_eq!(msg, encode_key_update(false));
+        client.update_client_keys().unwrap();
+
+        let (content_type, data) = read_record(&mut client_rx, &client, 0).await;
+        assert_eq!(content_type, 23);
+        assert_eq!(data, b"response");
+    }
+
+    #[tokio::test]
+    async fn test_close_notify_both_directions() {
+        let (server_keys, client) = keys();
+        let (client_io, server_io) = tokio::io::duplex(4096);
+        let (mut client_rx, mut client_tx) = tokio::io::split(client_io);
+        let mut server = TlsStream::new(server_io, server_keys);
+
+        // 对端的 close_notify 表现为普通 EOF
+        client_tx.write_all(&client.encrypt_server_record(0, b"bye", 23).unwrap()).await.unwrap();
+        client_tx
+            .write_all(&client.encrypt_server_record(1, &[ALERT_LEVEL_WARNING, ALERT_CLOSE_NOTIFY], 21).unwrap())
+            .await
+            .unwrap();
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"bye");
+
+        // shutdown 先发出剩余数据，再发送 close_notify
+        server.write_all(b"done").await.unwrap();
+        server.shutdown().await.unwrap();
+        assert_eq!(read_record(&mut client_rx, &client, 0).await, (23, b"done".to_vec()));
+        assert_eq!(
+            read_record(&mut client_rx, &client, 1).await,
+            (21, vec![ALERT_LEVEL_WARNING, ALERT_CLOSE_NOTIFY])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_large_writes_are_split_into_records() {
+        let (server_keys, client) = keys();
+        // 小缓冲区的 duplex 迫使底层出现部分写入
+        let (mut client_io, server_io) = tokio::io::duplex(1024);
+        let mut server = TlsStream::new(server_io, server_keys);
+
+        let payload: Vec<u8> = (0..40_000u32).map(|i| i as u8).collect();
+        let expected = payload.clone();
+        let writer = tokio::spawn(async move {
+            server.write_all(&payload).await.unwrap();
+            server.flush().await.unwrap();
+        });
+
+        let mut received = Vec::new();
+        for (seq, expected_len) in [MAX_RECORD_PLAINTEXT, MAX_RECORD_PLAINTEXT, 7232].into_iter().enumerate() {
+            let (content_type, data) = read_record(&mut client_io, &client, seq as u64).await;
+            assert_eq!(content_type, 23);
+            assert_eq!(data.len(), expected_len);
+            received.extend(data);
+        }
+        assert_eq!(received, expected);
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_small_writes_are_coalesced() {
+        let (server_keys, client) = keys();
+        let (mut client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let mut server = TlsStream::new(server_io, server_keys);
+
+        for _ in 0..100 {
+            server.write_all(b"0123456789").await.unwrap();
+        }
+        let slices = [IoSlice::new(b"ab"), IoSlice::new(b"cd"), IoSlice::new(b"ef")];
+        assert!(server.is_write_vectored());
+        assert_eq!(server.write_vectored(&slices).await.unwrap(), 6);
+        server.flush().await.unwrap();
+
+        let (content_type, data) = read_record(&mut client_io, &client, 0).await;
+        assert_eq!(content_type, 23);
+        assert_eq!(data.len(), 1006);
+        assert!(data.ends_with(b"abcdef"));
+
+        // 更小的记录上限
+        server.set_max_record_size(400);
+        server.write_all(&[7u8; 1000]).await.unwrap();
+        server.flush().await.unwrap();
+        for (seq, expected_len) in [(1, 400), (2, 400), (3, 200)] {
+            assert_eq!(read_record(&mut client_io, &client, seq).await.1.len(), expected_len);
         }
     }
+
+    /// 客户端的数据、KeyUpdate、新密钥下的数据和 close_notify
+    fn client_flight() -> Vec<u8> {
+        let (_, mut client) = keys();
+        let mut wire = client.encrypt_server_record(0, b"hello ", 23).unwrap();
+        wire.extend(client.encrypt_server_record(1, &encode_key_update(false), 22).unwrap());
+        client.update_server_keys().unwrap();
+        wire.extend(client.encrypt_server_record(0, b"world", 23).unwrap());
+        wire.extend(client.encrypt_server_record(1, &[ALERT_LEVEL_WARNING, ALERT_CLOSE_NOTIFY], 21).unwrap());
+        wire
+    }
+
+    #[tokio::test]
+    async fn test_read_one_byte_at_a_time() {
+        let (server_keys, _) = keys();
+        let mut mock = tokio_test::io::Builder::new();
+        for byte in client_flight() {
+            mock.read(&[byte]);
+        }
+        let mut server = TlsStream::new(mock.build(), server_keys);
+
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn test_read_coalesced_records_and_handshake_leftover() {
+        let wire = client_flight();
+
+        // 所有记录在一次读取中到达
+        let (server_keys, _) = keys();
+        let mut server = TlsStream::new(tokio_test::io::Builder::new().read(&wire).build(), server_keys);
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"hello world");
+
+        // 握手缓冲区中残留了半条记录
+        let (server_keys, _) = keys();
+        let (leftover, rest) = wire.split_at(9);
+        let mock = tokio_test::io::Builder::new().read(&rest[..20]).read(&rest[20..]).build();
+        let mut server = TlsStream::new_with_buffer(mock, server_keys, BytesMut::from(leftover));
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unexpected_record_types() {
+        let (_, client) = keys();
+
+        // 内层类型 change_cipher_spec 不应出现在握手之后
+        let inner = client.encrypt_server_record(0, &[1], 20).unwrap();
+        // 外层类型必须是 application_data
+        let mut outer = client.encrypt_server_record(0, b"data", 23).unwrap();
+        outer[0] = 22;
+        // 记录被截断
+        let truncated = client.encrypt_server_record(0, b"data", 23).unwrap()[..12].to_vec();
+
+        for (wire, kind) in [
+            (inner, io::ErrorKind::InvalidData),
+            (outer, io::ErrorKind::InvalidData),
+            (truncated, io::ErrorKind::UnexpectedEof),
+        ] {
+            let (server_keys, _) = keys();
+            let mut server = TlsStream::new(tokio_test::io::Builder::new().read(&wire).build(), server_keys);
+            let mut buf = [0u8; 16];
+            assert_eq!(server.read(&mut buf).await.unwrap_err().kind(), kind);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejects_other_post_handshake_messages() {
+        let (server_keys, client) = keys();
+        let (mut client_io, server_io) = tokio::io::duplex(1024);
+        let mut server = TlsStream::new(server_io, server_keys);
+
+        // NewSessionTicket 不应由客户端发送
+        let ticket = [HandshakeType::NewSessionTicket as u8, 0, 0, 0];
+        client_io.write_all(&client.encrypt_server_record(0, &ticket, 22).unwrap()).await.unwrap();
+
+        let mut buf = [0u8; 1];
+        let err = server.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
 }
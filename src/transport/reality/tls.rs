@@ -1,28 +1,28 @@
 use anyhow::{anyhow, Result};
-use bytes::{Buf, BytesMut};
-use std::io::Cursor;
 
-/// TLS 内容类型
-#[derive(Debug, Clone, Copy, PartialEq)]
-#[repr(u8)]
-pub enum ContentType {
-    ChangeCipherSpec = 20,
-    Alert = 21,
-    Handshake = 22,
-    ApplicationData = 23,
-}
+/// 警报级别: warning (仅用于 close_notify)
+pub const ALERT_LEVEL_WARNING: u8 = 1;
+/// 警报描述: close_notify
+pub const ALERT_CLOSE_NOTIFY: u8 = 0;
 
-impl ContentType {
-    pub fn from_u8(value: u8) -> Result<Self> {
-        match value {
-            20 => Ok(ContentType::ChangeCipherSpec),
-            21 => Ok(ContentType::Alert),
-            22 => Ok(ContentType::Handshake),
-            23 => Ok(ContentType::ApplicationData),
-            _ => Err(anyhow!("Unknown content type: {}", value)),
-        }
-    }
-}
+/// 兼容中间设备的 change_cipher_spec 记录 (RFC 8446 §D.4)
+pub const CHANGE_CIPHER_SPEC: [u8; 6] = [0x14, 0x03, 0x03, 0x00, 0x01, 0x01];
+
+/// ClientHello 握手消息体的最大长度
+pub const MAX_CLIENT_HELLO_LEN: usize = 64 * 1024;
+
+/// TLS 1.2 / TLS 1.3 版本号
+pub const VERSION_TLS12: u16 = 0x0303;
+pub const VERSION_TLS13: u16 = 0x0304;
+
+/// 命名组: x25519
+pub const GROUP_X25519: u16 = 0x001d;
+
+/// HelloRetryRequest 使用的固定 random (SHA-256("HelloRetryRequest"))
+pub const HRR_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
 
 /// TLS 握手类型
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -31,6 +31,7 @@ pub enum HandshakeType {
     ClientHello = 1,
     ServerHello = 2,
     NewSessionTicket = 4,
+    EncryptedExtensions = 8,
     Certificate = 11,
     ServerKeyExchange = 12,
     CertificateRequest = 13,
@@ -38,6 +39,7 @@ pub enum HandshakeType {
     CertificateVerify = 15,
     ClientKeyExchange = 16,
     Finished = 20,
+    KeyUpdate = 24,
 }
 
 impl HandshakeType {
@@ -46,6 +48,7 @@ impl HandshakeType {
             1 => Ok(HandshakeType::ClientHello),
             2 => Ok(HandshakeType::ServerHello),
             4 => Ok(HandshakeType::NewSessionTicket),
+            8 => Ok(HandshakeType::EncryptedExtensions),
             11 => Ok(HandshakeType::Certificate),
             12 => Ok(HandshakeType::ServerKeyExchange),
             13 => Ok(HandshakeType::CertificateRequest),
@@ -53,257 +56,88 @@ impl HandshakeType {
             15 => Ok(HandshakeType::CertificateVerify),
             16 => Ok(HandshakeType::ClientKeyExchange),
             20 => Ok(HandshakeType::Finished),
+            24 => Ok(HandshakeType::KeyUpdate),
             _ => Err(anyhow!("Unknown handshake type: {}", value)),
         }
     }
 }
 
-/// TLS 记录
-#[derive(Debug, Clone)]
-pub struct TlsRecord {
-    pub content_type: ContentType,
-    pub version: u16,
-    pub payload: Vec<u8>,
+/// 解析 supported_versions 扩展 (ClientHello): u8 长度 + u16 版本列表，忽略 GREASE
+pub fn parse_supported_versions(data: &[u8]) -> Vec<u16> {
+    let Some((&len, versions)) = data.split_first() else {
+        return Vec::new();
+    };
+    versions
+        .get(..len as usize)
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .map(|v| u16::from_be_bytes([v[0], v[1]]))
+        .filter(|&v| !is_grease(v))
+        .collect()
 }
 
-impl TlsRecord {
-    /// 从字节流解析 TLS 记录
-    pub fn parse(buf: &mut BytesMut) -> Result<Option<Self>> {
-        if buf.len() < 5 {
-            return Ok(None); // 需要更多数据
-        }
-
-        let content_type = ContentType::from_u8(buf[0])?;
-        let version = u16::from_be_bytes([buf[1], buf[2]]);
-        let length = u16::from_be_bytes([buf[3], buf[4]]) as usize;
-
-        if buf.len() < 5 + length {
-            return Ok(None); // 需要更多数据
-        }
-
-        buf.advance(5);
-        let payload = buf.split_to(length).to_vec();
-
-        Ok(Some(TlsRecord {
-            content_type,
-            version,
-            payload,
-        }))
-    }
-
-    /// 将 TLS 记录编码为字节流
-    pub fn encode(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        buf.push(self.content_type as u8);
-        buf.extend_from_slice(&self.version.to_be_bytes());
-        buf.extend_from_slice(&(self.payload.len() as u16).to_be_bytes());
-        buf.extend_from_slice(&self.payload);
-        buf
-    }
+/// 客户端是否是合法的 TLS 1.3 客户端
+///
+/// TLS 1.3 客户端的 legacy_version 固定为 0x0303，并在 supported_versions 中提供 0x0304
+/// (RFC 8446 §4.1.2, §4.2.1)。其他情况 (只支持旧版本、或刻意构造的降级探测) 都不走 Reality 握手。
+pub fn offers_tls13(legacy_version: u16, supported_versions: &[u16]) -> bool {
+    legacy_version == VERSION_TLS12 && supported_versions.contains(&VERSION_TLS13)
 }
 
-/// ClientHello 消息
-#[derive(Debug, Clone)]
-pub struct ClientHello {
-    pub version: u16,
-    pub random: [u8; 32],
-    pub session_id: Vec<u8>,
-    pub cipher_suites: Vec<u16>,
-    pub compression_methods: Vec<u8>,
-    pub extensions: Vec<Extension>,
-    pub raw_data: Vec<u8>, // 保存原始数据用于转发
+/// GREASE 值 (RFC 8701): 0x0a0a, 0x1a1a, ..., 0xfafa
+pub fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
 }
 
-impl ClientHello {
-    /// 解析 ClientHello
-    pub fn parse(data: &[u8]) -> Result<Self> {
-        let mut cursor = Cursor::new(data);
-
-        // 检查握手类型
-        let handshake_type = data[0];
-        if handshake_type != HandshakeType::ClientHello as u8 {
-            return Err(anyhow!("Not a ClientHello message"));
-        }
-
-        // 跳过握手类型和长度 (4 字节)
-        cursor.set_position(4);
-
-        // 读取版本
-        let mut version_bytes = [0u8; 2];
-        cursor.copy_to_slice(&mut version_bytes);
-        let version = u16::from_be_bytes(version_bytes);
-
-        // 读取 random (32 字节)
-        let mut random = [0u8; 32];
-        cursor.copy_to_slice(&mut random);
-
-        // 读取 session_id
-        let session_id_len = data[cursor.position() as usize];
-        cursor.set_position(cursor.position() + 1);
-        let mut session_id = vec![0u8; session_id_len as usize];
-        cursor.copy_to_slice(&mut session_id);
-
-        // 读取 cipher_suites
-        let mut cipher_suites_len_bytes = [0u8; 2];
-        cursor.copy_to_slice(&mut cipher_suites_len_bytes);
-        let cipher_suites_len = u16::from_be_bytes(cipher_suites_len_bytes) as usize;
-        let mut cipher_suites = Vec::new();
-        for _ in 0..(cipher_suites_len / 2) {
-            let mut suite_bytes = [0u8; 2];
-            cursor.copy_to_slice(&mut suite_bytes);
-            cipher_suites.push(u16::from_be_bytes(suite_bytes));
-        }
+/// 带边界检查的大端字节读取器
+struct Reader<'a> {
+    data: &'a [u8],
+}
 
-        // 读取 compression_methods
-        let compression_methods_len = data[cursor.position() as usize];
-        cursor.set_position(cursor.position() + 1);
-        let mut compression_methods = vec![0u8; compression_methods_len as usize];
-        cursor.copy_to_slice(&mut compression_methods);
-
-        // 读取 extensions
-        let mut extensions = Vec::new();
-        if cursor.position() < data.len() as u64 {
-            let mut extensions_len_bytes = [0u8; 2];
-            cursor.copy_to_slice(&mut extensions_len_bytes);
-            let extensions_len = u16::from_be_bytes(extensions_len_bytes) as usize;
-            let extensions_start = cursor.position() as usize;
-            let extensions_data = &data[extensions_start..extensions_start + extensions_len];
-            extensions = Extension::parse_all(extensions_data)?;
-        }
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
 
-        Ok(ClientHello {
-            version,
-            random,
-            session_id,
-            cipher_suites,
-            compression_methods,
-            extensions,
-            raw_data: data.to_vec(),
-        })
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
     }
 
-    /// 获取 SNI (Server Name Indication)
-    pub fn get_sni(&self) -> Option<String> {
-        for ext in &self.extensions {
-            if ext.extension_type == 0 {
-                // SNI extension
-                return Extension::parse_sni(&ext.data);
-            }
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(anyhow!("Truncated handshake message: need {} bytes, have {}", len, self.data.len()));
         }
-        None
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
     }
 
-    /// 从 session_id 中提取 Reality short_id
-    pub fn get_reality_short_id(&self) -> Option<Vec<u8>> {
-        // Reality 使用 session_id 的前 8 字节作为 short_id
-        if self.session_id.len() >= 8 {
-            Some(self.session_id[..8].to_vec())
-        } else {
-            None
-        }
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
     }
 
-    /// 获取 ClientHello 的 random 字段
-    pub fn get_random(&self) -> &[u8; 32] {
-        &self.random
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
     }
 
-    /// 获取 Key Share (X25519 public key)
-    pub fn get_key_share(&self) -> Option<Vec<u8>> {
-        for ext in &self.extensions {
-            if ext.extension_type == 0x0033 {
-                // Key Share
-                return Extension::parse_client_key_share(&ext.data);
-            }
-        }
-        None
+    fn u24(&mut self) -> Result<usize> {
+        let b = self.bytes(3)?;
+        Ok(u32::from_be_bytes([0, b[0], b[1], b[2]]) as usize)
     }
 }
 
-/// TLS 扩展
-#[derive(Debug, Clone)]
-pub struct Extension {
-    pub extension_type: u16,
-    pub data: Vec<u8>,
+/// 编码 KeyUpdate 消息
+pub fn encode_key_update(update_requested: bool) -> Vec<u8> {
+    encode_handshake(HandshakeType::KeyUpdate, &[update_requested as u8])
 }
 
-impl Extension {
-    /// 解析所有扩展
-    pub fn parse_all(data: &[u8]) -> Result<Vec<Extension>> {
-        let mut extensions = Vec::new();
-        let mut cursor = Cursor::new(data);
-
-        while cursor.position() < data.len() as u64 {
-            let mut type_bytes = [0u8; 2];
-            cursor.copy_to_slice(&mut type_bytes);
-            let extension_type = u16::from_be_bytes(type_bytes);
-
-            let mut len_bytes = [0u8; 2];
-            cursor.copy_to_slice(&mut len_bytes);
-            let length = u16::from_be_bytes(len_bytes) as usize;
-
-            let mut ext_data = vec![0u8; length];
-            cursor.copy_to_slice(&mut ext_data);
-
-            extensions.push(Extension {
-                extension_type,
-                data: ext_data,
-            });
-        }
-
-        Ok(extensions)
-    }
-
-    /// 解析 SNI 扩展
-    pub fn parse_sni(data: &[u8]) -> Option<String> {
-        if data.len() < 5 {
-            return None;
-        }
-        let name_length = u16::from_be_bytes([data[3], data[4]]) as usize;
-        if data.len() < 5 + name_length {
-            return None;
-        }
-        String::from_utf8(data[5..5 + name_length].to_vec()).ok()
-    }
-
-    /// 解析 Key Share 扩展 (ClientHello)
-    pub fn parse_client_key_share(data: &[u8]) -> Option<Vec<u8>> {
-        if data.len() < 2 {
-            return None;
-        }
-        let mut cursor = Cursor::new(data);
-        cursor.advance(2);
-
-        while cursor.position() < data.len() as u64 {
-            let mut group_bytes = [0u8; 2];
-            if cursor.remaining() < 2 {
-                break;
-            }
-            cursor.copy_to_slice(&mut group_bytes);
-            let group = u16::from_be_bytes(group_bytes);
-
-            let mut len_bytes = [0u8; 2];
-            if cursor.remaining() < 2 {
-                break;
-            }
-            cursor.copy_to_slice(&mut len_bytes);
-            let length = u16::from_be_bytes(len_bytes) as usize;
-
-            if cursor.remaining() < length {
-                break;
-            }
-
-            if group == 0x001d {
-                // X25519
-                let mut key = vec![0u8; length];
-                cursor.copy_to_slice(&mut key);
-                return Some(key);
-            } else {
-                cursor.advance(length);
-            }
-        }
-        None
-    }
+fn encode_handshake(msg_type: HandshakeType, body: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(4 + body.len());
+    msg.push(msg_type as u8);
+    msg.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    msg.extend_from_slice(body);
+    msg
 }
 
 /// ServerHello 消息
@@ -312,142 +146,132 @@ pub struct ServerHello {
     pub raw_data: Vec<u8>,
 }
 
+/// 客户端从 ServerHello 中读取的字段
+#[derive(Debug, Clone)]
+pub struct ServerHelloFields {
+    pub random: [u8; 32],
+    pub session_id: Vec<u8>,
+    pub cipher_suite: u16,
+    /// supported_versions 扩展选中的版本 (TLS 1.2 及以下没有该扩展)
+    pub selected_version: Option<u16>,
+    /// key_share 扩展: (命名组, 公钥)
+    pub key_share: Option<(u16, Vec<u8>)>,
+}
+
 impl ServerHello {
     /// 从原始数据创建
     pub fn from_raw(data: Vec<u8>) -> Self {
         ServerHello { raw_data: data }
     }
 
-    /// 修改 ServerHello 以注入 Reality 认证信息
-    pub fn modify_for_reality(
-        &mut self,
-        private_key: &str,
-        client_random: &[u8; 32],
-    ) -> Result<()> {
-        use super::auth::ServerHelloModifier;
-
-        // 创建修改器
-        let modifier = ServerHelloModifier::new(private_key)?;
-
-        // 修改 raw_data 中的 random 字段
-        modifier.modify_server_hello(&mut self.raw_data, client_random)?;
-
-        Ok(())
-    }
-
-    /// 构造一个新的 ServerHello
-    pub fn new_reality(
-        client_session_id: &[u8],
-        random: [u8; 32],
-        key_share_data: &[u8],
-    ) -> Result<Self> {
-        use bytes::BufMut; // Added for BufMut trait
-
-        let mut payload = BytesMut::new();
-
-        // 1. Handshake Header (Updated later)
-        payload.put_u8(HandshakeType::ServerHello as u8);
-        payload.put_u8(0);
-        payload.put_u8(0);
-        payload.put_u8(0); // Length placeholder
-
-        // 2. Legacy Version (0x0303 for TLS 1.2 compatibility)
-        payload.put_u16(0x0303);
-
-        // 3. Random (Reality Auth injected here)
-        payload.put_slice(&random);
-
-        // 4. Legacy Session ID Echo
-        payload.put_u8(client_session_id.len() as u8);
-        payload.put_slice(client_session_id);
-
-        // 5. Cipher Suite (TLS_AES_128_GCM_SHA256)
-        payload.put_u16(0x1301);
-
-        // 6. Compression Method (0)
-        payload.put_u8(0);
-
-        // 7. Extensions
-        let mut extensions_block = BytesMut::new();
-
-        // 7.1 Supported Versions (TLS 1.3)
-        // Type (0x002b), Len (2), Version (0x0304)
-        extensions_block.put_u16(0x002b);
-        extensions_block.put_u16(2);
-        extensions_block.put_u16(0x0304);
-
-        // 7.2 Key Share
-        // Type (0x0033), Len (4 + key_len), Group(2), KeyLen(2), Key
-        extensions_block.put_u16(0x0033);
-        let ks_len = 4 + key_share_data.len();
-        extensions_block.put_u16(ks_len as u16);
-        extensions_block.put_u16(0x001d); // X25519
-        extensions_block.put_u16(key_share_data.len() as u16);
-        extensions_block.put_slice(key_share_data);
-
-        // Write Extensions Length and Block
-        payload.put_u16(extensions_block.len() as u16);
-        payload.put_slice(&extensions_block);
-
-        // Fix Handshake Length (payload len - 4 bytes header)
-        let total_len = payload.len() - 4;
-        let len_bytes = (total_len as u32).to_be_bytes();
-        payload[1] = len_bytes[1];
-        payload[2] = len_bytes[2];
-        payload[3] = len_bytes[3];
-
-        Ok(ServerHello {
-            raw_data: payload.to_vec(),
-        })
-    }
+    /// 解析 ServerHello 握手消息 (客户端使用)
+    pub fn parse(&self) -> Result<ServerHelloFields> {
+        let mut r = Reader::new(&self.raw_data);
+        if r.u8()? != HandshakeType::ServerHello as u8 {
+            return Err(anyhow!("Not a ServerHello"));
+        }
+        let len = r.u24()?;
+        let mut r = Reader::new(r.bytes(len)?);
 
-    pub fn encode(&self) -> Vec<u8> {
-        use bytes::BufMut; // Added for BufMut trait
+        r.u16()?; // legacy_version
+        let mut random = [0u8; 32];
+        random.copy_from_slice(r.bytes(32)?);
+        let session_id_len = r.u8()? as usize;
+        let session_id = r.bytes(session_id_len)?.to_vec();
+        let cipher_suite = r.u16()?;
+        if r.u8()? != 0 {
+            return Err(anyhow!("ServerHello selected a compression method"));
+        }
 
-        // Wrap in TLS Record Layer
-        let mut record = BytesMut::new();
-        record.put_u8(ContentType::Handshake as u8);
-        record.put_u16(0x0303); // Legacy Record Version
-        record.put_u16(self.raw_data.len() as u16);
-        record.put_slice(&self.raw_data);
-        record.to_vec()
-    }
+        let mut fields = ServerHelloFields {
+            random,
+            session_id,
+            cipher_suite,
+            selected_version: None,
+            key_share: None,
+        };
+        if r.is_empty() {
+            return Ok(fields);
+        }
 
-    pub fn handshake_payload(&self) -> &[u8] {
-        &self.raw_data
+        let ext_len = r.u16()? as usize;
+        let mut exts = Reader::new(r.bytes(ext_len)?);
+        while !exts.is_empty() {
+            let ext_type = exts.u16()?;
+            let len = exts.u16()? as usize;
+            let mut data = Reader::new(exts.bytes(len)?);
+            match ext_type {
+                0x002b => fields.selected_version = Some(data.u16()?),
+                0x0033 => {
+                    // HelloRetryRequest 只携带 selected_group
+                    let group = data.u16()?;
+                    let key = if data.is_empty() {
+                        Vec::new()
+                    } else {
+                        let key_len = data.u16()? as usize;
+                        data.bytes(key_len)?.to_vec()
+                    };
+                    fields.key_share = Some((group, key));
+                }
+                _ => {}
+            }
+        }
+        Ok(fields)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bytes::BufMut;
+
+    /// 构造 ServerHello 握手消息: key_share 为 (命名组, 公钥)，公钥为空时即 HelloRetryRequest 的 selected_group
+    fn server_hello(random: [u8; 32], session_id: &[u8], cipher_suite: u16, key_share: (u16, &[u8])) -> Vec<u8> {
+        let mut key_share_ext = key_share.0.to_be_bytes().to_vec();
+        if !key_share.1.is_empty() {
+            key_share_ext.extend_from_slice(&(key_share.1.len() as u16).to_be_bytes());
+            key_share_ext.extend_from_slice(key_share.1);
+        }
+        let mut extensions = vec![0x00, 0x33];
+        extensions.extend_from_slice(&(key_share_ext.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(&key_share_ext);
+        extensions.extend_from_slice(&[0x00, 0x2b, 0x00, 0x02, 0x03, 0x04]);
+
+        let mut body = VERSION_TLS12.to_be_bytes().to_vec();
+        body.extend_from_slice(&random);
+        body.push(session_id.len() as u8);
+        body.extend_from_slice(session_id);
+        body.extend_from_slice(&cipher_suite.to_be_bytes());
+        body.push(0);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+        encode_handshake(HandshakeType::ServerHello, &body)
+    }
 
     #[test]
-    fn test_tls_record_parse() {
-        let mut buf = BytesMut::new();
-        // TLS 记录: Handshake, TLS 1.2, length 5
-        buf.put_u8(22); // Handshake
-        buf.put_u16(0x0303); // TLS 1.2
-        buf.put_u16(5); // Length
-        buf.put_slice(&[1, 2, 3, 4, 5]); // Payload
-
-        let record = TlsRecord::parse(&mut buf).unwrap().unwrap();
-        assert_eq!(record.content_type, ContentType::Handshake);
-        assert_eq!(record.version, 0x0303);
-        assert_eq!(record.payload.len(), 5);
+    fn test_server_hello_parse() {
+        let raw = server_hello([9u8; 32], &[7u8; 32], 0x1302, (GROUP_X25519, &[5u8; 32]));
+        let fields = ServerHello::from_raw(raw.clone()).parse().unwrap();
+        assert_eq!(fields.random, [9u8; 32]);
+        assert_eq!(fields.session_id, [7u8; 32]);
+        assert_eq!(fields.cipher_suite, 0x1302);
+        assert_eq!(fields.selected_version, Some(VERSION_TLS13));
+        assert_eq!(fields.key_share, Some((GROUP_X25519, vec![5u8; 32])));
+
+        let hrr = server_hello(HRR_RANDOM, &[7u8; 32], 0x1301, (GROUP_X25519, &[]));
+        let fields = ServerHello::from_raw(hrr).parse().unwrap();
+        assert_eq!(fields.random, HRR_RANDOM);
+        assert_eq!(fields.key_share, Some((GROUP_X25519, Vec::new())));
+
+        assert!(ServerHello::from_raw(raw[..raw.len() - 1].to_vec()).parse().is_err());
     }
 
     #[test]
-    fn test_extension_parse_sni() {
-        // SNI 扩展数据: [list_length(2)] [type(1)=0] [name_length(2)] [name]
-        let mut data = Vec::new();
-        data.extend_from_slice(&[0, 14]); // list length
-        data.push(0); // type: hostname
-        data.extend_from_slice(&[0, 11]); // name length
-        data.extend_from_slice(b"example.com");
-
-        let sni = Extension::parse_sni(&data).unwrap();
-        assert_eq!(sni, "example.com");
+    fn test_supported_versions() {
+        assert_eq!(parse_supported_versions(&[4, 0x0a, 0x0a, 0x03, 0x04]), vec![VERSION_TLS13]);
+        assert!(parse_supported_versions(&[9, 0x03, 0x04]).is_empty());
+        assert!(offers_tls13(VERSION_TLS12, &[VERSION_TLS13]));
+        assert!(!offers_tls13(0x0301, &[VERSION_TLS13]));
+        assert!(!offers_tls13(VERSION_TLS12, &[VERSION_TLS12]));
+        assert!(is_grease(0x3a3a) && !is_grease(0x3a4a) && !is_grease(0x1301));
     }
 }
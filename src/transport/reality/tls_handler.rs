@@ -1,13 +1,101 @@
 use anyhow::{anyhow, Result};
+use rustls::crypto::CryptoProvider;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
 use rustls::ServerConfig;
-use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::net::TcpStream;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_rustls::TlsAcceptor;
 use tracing::{debug, info};
 
 use super::RealityConfig;
 
+/// 一组 PEM 证书链与私钥
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateFiles {
+    pub certificate_file: String,
+    pub key_file: String,
+}
+
+/// 自签名证书的密钥类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelfSignedKeyType {
+    /// ECDSA P-256，与大多数网站一致
+    #[default]
+    Ecdsa,
+    Ed25519,
+}
+
+/// 自签名证书参数，未设置的字段取自 Reality 配置
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SelfSignedParams {
+    /// SAN 域名，为空时使用 `server_names`，再为空时使用 `dest` 的主机名
+    pub domains: Vec<String>,
+    /// 为空时使用第一个域名
+    pub common_name: Option<String>,
+    pub organization: Option<String>,
+    pub validity_days: u32,
+    pub key_type: SelfSignedKeyType,
+}
+
+impl SelfSignedParams {
+    /// 证书字段: SAN 为 `domains`，CN 默认取第一个域名，有效期从前一天开始以容忍客户端时钟偏差
+    pub(crate) fn certificate_params(&self, domains: Vec<String>) -> Result<rcgen::CertificateParams> {
+        use rcgen::{DistinguishedName, DnType};
+
+        let common_name = self.common_name.clone().or_else(|| domains.first().cloned()).unwrap_or_default();
+        let mut params = rcgen::CertificateParams::new(domains)
+            .map_err(|e| anyhow!("Invalid certificate domains: {}", e))?;
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::CommonName, common_name);
+        if let Some(organization) = &self.organization {
+            dn.push(DnType::OrganizationName, organization.as_str());
+        }
+        params.distinguished_name = dn;
+
+        let now = rcgen::date_time_ymd(1970, 1, 1) + SystemTime::now().duration_since(UNIX_EPOCH)?;
+        params.not_before = now - Duration::from_secs(86400);
+        params.not_after = now + Duration::from_secs(u64::from(self.validity_days) * 86400);
+        Ok(params)
+    }
+}
+
+impl Default for SelfSignedParams {
+    fn default() -> Self {
+        Self {
+            domains: Vec::new(),
+            common_name: None,
+            organization: None,
+            validity_days: 365,
+            key_type: SelfSignedKeyType::default(),
+        }
+    }
+}
+
+/// 按 ClientHello 的 SNI 选择证书，未知或缺失 SNI 使用默认证书
+#[derive(Debug)]
+pub struct SniCertResolver {
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+    default: Arc<CertifiedKey>,
+}
+
+impl ResolvesServerCert for SniCertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let key = client_hello
+            .server_name()
+            .and_then(|name| self.by_name.get(&name.to_ascii_lowercase()))
+            .unwrap_or(&self.default);
+        Some(key.clone())
+    }
+}
+
 /// Reality TLS 配置构建器
 pub struct RealityTlsConfig {
     config: RealityConfig,
@@ -18,44 +106,78 @@ impl RealityTlsConfig {
         Self { config }
     }
 
-    /// 生成自签名证书和私钥
-    pub fn generate_self_signed_cert() -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
-        use rcgen::{Certificate, CertificateParams, DistinguishedName};
-        
-        let mut params = CertificateParams::new(vec!["localhost".to_string()]);
-        
-        // 设置证书信息
-        let mut dn = DistinguishedName::new();
-        dn.push(rcgen::DnType::CommonName, "Reality Server");
-        dn.push(rcgen::DnType::OrganizationName, "Reality");
-        params.distinguished_name = dn;
-        
-        // 生成证书
-        let cert = Certificate::from_params(params)
+    /// 按 `self_signed` 参数生成自签名证书和私钥
+    pub fn generate_self_signed_cert(&self) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+        let params = &self.config.self_signed;
+        let alg = match params.key_type {
+            SelfSignedKeyType::Ecdsa => &rcgen::PKCS_ECDSA_P256_SHA256,
+            SelfSignedKeyType::Ed25519 => &rcgen::PKCS_ED25519,
+        };
+        let key_pair = rcgen::KeyPair::generate_for(alg)
+            .map_err(|e| anyhow!("Failed to generate key pair: {}", e))?;
+        let cert = params
+            .certificate_params(self.self_signed_domains())?
+            .self_signed(&key_pair)
             .map_err(|e| anyhow!("Failed to generate certificate: {}", e))?;
-        
-        // 获取 DER 编码的证书
-        let cert_der = cert.serialize_der()
-            .map_err(|e| anyhow!("Failed to serialize certificate: {}", e))?;
-        
-        // 获取私钥
-        let key_der = cert.serialize_private_key_der();
-        
-        let certs = vec![CertificateDer::from(cert_der)];
-        let key = PrivateKeyDer::try_from(key_der)
+
+        let key = PrivateKeyDer::try_from(key_pair.serialize_der())
             .map_err(|_| anyhow!("Failed to parse private key"))?;
-        
-        Ok((certs, key))
+        Ok((vec![cert.der().clone()], key))
+    }
+
+    /// 自签名证书的 SAN: 配置的域名，否则为客户端使用的 serverNames，再否则为 dest 的主机名
+    fn self_signed_domains(&self) -> Vec<String> {
+        if !self.config.self_signed.domains.is_empty() {
+            return self.config.self_signed.domains.clone();
+        }
+        if !self.config.server_names.is_empty() {
+            return self.config.server_names.clone();
+        }
+        let dest = &self.config.dest;
+        let host = match dest.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => host,
+            _ => dest.as_str(),
+        };
+        vec![host.trim_start_matches('[').trim_end_matches(']').to_string()]
+    }
+
+    /// 读取配置的证书与私钥，两者都未设置时生成自签名证书
+    pub fn load_cert(&self) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+        match (&self.config.certificate_file, &self.config.key_file) {
+            (Some(certificate_file), Some(key_file)) => {
+                let pair = crate::transport::load_key_pair(certificate_file, key_file)?;
+                info!("Reality TLS 使用证书 {}", certificate_file);
+                Ok(pair)
+            }
+            (None, None) => self.generate_self_signed_cert(),
+            _ => Err(anyhow!("certificate_file 与 key_file 必须同时设置")),
+        }
+    }
+
+    /// 构建 SNI 证书选择器: 逐个读取并校验 `server_name_certificates`
+    pub fn build_resolver(&self, provider: &CryptoProvider) -> Result<SniCertResolver> {
+        let certified = |(certs, key): (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)| -> Result<Arc<CertifiedKey>> {
+            let key = provider.key_provider.load_private_key(key)?;
+            Ok(Arc::new(CertifiedKey::new(certs, key)))
+        };
+        let mut by_name = HashMap::new();
+        for (name, files) in &self.config.server_name_certificates {
+            let pair = crate::transport::load_key_pair(&files.certificate_file, &files.key_file)
+                .map_err(|e| anyhow!("serverName {}: {}", name, e))?;
+            by_name.insert(name.to_ascii_lowercase(), certified(pair)?);
+        }
+        Ok(SniCertResolver { by_name, default: certified(self.load_cert()?)? })
     }
 
     /// 构建 TLS ServerConfig
     pub fn build_tls_config(&self) -> Result<Arc<ServerConfig>> {
-        let (certs, key) = Self::generate_self_signed_cert()?;
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let resolver = self.build_resolver(&provider)?;
         
-        let config = ServerConfig::builder()
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
             .with_no_client_auth()
-            .with_single_cert(certs, key)
-            .map_err(|e| anyhow!("Failed to build TLS config: {}", e))?;
+            .with_cert_resolver(Arc::new(resolver));
         
         Ok(Arc::new(config))
     }
@@ -67,25 +189,21 @@ impl RealityTlsConfig {
     }
 }
 
-/// Reality TLS 握手处理器
+/// 以配置的证书终结 TLS，用于未通过 Reality 认证的回落连接
 pub struct RealityTlsHandler {
-    config: RealityConfig,
     acceptor: TlsAcceptor,
 }
 
 impl RealityTlsHandler {
     pub fn new(config: RealityConfig) -> Result<Self> {
-        let tls_config = RealityTlsConfig::new(config.clone());
-        let acceptor = tls_config.create_acceptor()?;
+        let acceptor = RealityTlsConfig::new(config).create_acceptor()?;
         
-        Ok(Self {
-            config,
-            acceptor,
-        })
+        Ok(Self { acceptor })
     }
 
-    /// 执行 Reality TLS 握手
-    pub async fn perform_handshake(&self, stream: TcpStream) -> Result<tokio_rustls::server::TlsStream<TcpStream>> {
+    /// 执行 TLS 握手
+    pub async fn perform_handshake<S>(&self, stream: S) -> Result<tokio_rustls::server::TlsStream<S>>
+    where S: AsyncRead + AsyncWrite + Unpin {
         debug!("Starting Reality TLS handshake");
         
         // 使用 rustls 执行完整的 TLS 1.3 握手
@@ -97,3 +215,150 @@ impl RealityTlsHandler {
         Ok(tls_stream)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpStream;
+
+    fn config(certificate_file: Option<String>, key_file: Option<String>) -> RealityConfig {
+        RealityConfig {
+            certificate_file,
+            key_file,
+            ..RealityConfig::new(
+                "www.apple.com:443",
+                vec!["www.apple.com".to_string()],
+                "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE=",
+                vec!["0123456789abcdef".to_string()],
+            )
+        }
+    }
+
+    /// 为 `name` 写入自签名证书，返回 (证书文件, 私钥文件)
+    fn write_pair(dir: &std::path::Path, name: &str) -> CertificateFiles {
+        let cert = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+        let files = CertificateFiles {
+            certificate_file: dir.join(format!("{name}.pem")).display().to_string(),
+            key_file: dir.join(format!("{name}.key")).display().to_string(),
+        };
+        std::fs::write(&files.certificate_file, cert.cert.pem()).unwrap();
+        std::fs::write(&files.key_file, cert.key_pair.serialize_pem()).unwrap();
+        files
+    }
+
+    #[test]
+    fn test_load_configured_cert() {
+        let dir = std::env::temp_dir().join(format!("xray-lite-reality-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec!["www.apple.com".to_string()]).unwrap();
+        let other = rcgen::generate_simple_self_signed(vec!["www.apple.com".to_string()]).unwrap();
+        std::fs::write(dir.join("cert.pem"), cert.cert.pem()).unwrap();
+        std::fs::write(dir.join("key.pem"), cert.key_pair.serialize_pem()).unwrap();
+        std::fs::write(dir.join("other.pem"), other.key_pair.serialize_pem()).unwrap();
+        let path = |name: &str| Some(dir.join(name).display().to_string());
+
+        let tls = RealityTlsConfig::new(config(path("cert.pem"), path("key.pem")));
+        assert_eq!(tls.load_cert().unwrap().0.len(), 1);
+        assert!(tls.build_tls_config().is_ok());
+
+        let err = RealityTlsConfig::new(config(path("cert.pem"), path("other.pem"))).load_cert().unwrap_err();
+        assert!(err.to_string().contains("不匹配"), "{}", err);
+        assert!(RealityTlsConfig::new(config(path("cert.pem"), None)).load_cert().is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_fallback_to_self_signed() {
+        let tls = RealityTlsConfig::new(config(None, None));
+        let (certs, _) = tls.load_cert().unwrap();
+        assert_eq!(certs.len(), 1);
+        assert!(tls.build_tls_config().is_ok());
+    }
+
+    /// 以信任 `roots` 的客户端用 `sni` 连接 `handler`，返回服务端出示的叶证书
+    async fn peer_certificate(handler: Arc<RealityTlsHandler>, roots: rustls::RootCertStore, sni: &str) -> CertificateDer<'static> {
+        let client = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let _ = handler.perform_handshake(stream).await;
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let name = rustls_pki_types::ServerName::try_from(sni.to_string()).unwrap();
+        let tls = tokio_rustls::TlsConnector::from(Arc::new(client)).connect(name, stream).await.unwrap();
+        tls.get_ref().1.peer_certificates().unwrap()[0].clone().into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_sni_selects_certificate() {
+        let dir = std::env::temp_dir().join(format!("xray-lite-reality-sni-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = write_pair(&dir, "a.example.com");
+        let b = write_pair(&dir, "b.example.com");
+        let fallback = write_pair(&dir, "c.example.com");
+        let mut config = config(Some(fallback.certificate_file.clone()), Some(fallback.key_file.clone()));
+        config.server_name_certificates = HashMap::from([
+            ("a.example.com".to_string(), a.clone()),
+            ("B.Example.com".to_string(), b.clone()),
+        ]);
+        let handler = Arc::new(RealityTlsHandler::new(config).unwrap());
+
+        let mut roots = rustls::RootCertStore::empty();
+        for files in [&a, &b, &fallback] {
+            let (certs, _) = crate::transport::load_key_pair(&files.certificate_file, &files.key_file).unwrap();
+            roots.add(certs[0].clone()).unwrap();
+        }
+
+        // c.example.com 不在 server_name_certificates 中，使用默认证书
+        for (sni, files) in [("a.example.com", &a), ("b.example.com", &b), ("c.example.com", &fallback)] {
+            let (certs, _) = crate::transport::load_key_pair(&files.certificate_file, &files.key_file).unwrap();
+            assert_eq!(peer_certificate(handler.clone(), roots.clone(), sni).await, certs[0], "{}", sni);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_self_signed_matches_server_name() {
+        use rustls::client::danger::ServerCertVerifier;
+        use rustls_pki_types::{ServerName, UnixTime};
+
+        for key_type in [SelfSignedKeyType::Ecdsa, SelfSignedKeyType::Ed25519] {
+            let mut config = config(None, None);
+            config.self_signed = SelfSignedParams { key_type, validity_days: 90, ..Default::default() };
+            let tls = RealityTlsConfig::new(config.clone());
+            assert_eq!(tls.self_signed_domains(), vec!["www.apple.com".to_string()]);
+            assert!(RealityTlsHandler::new(config).is_ok());
+
+            // 信任证书本身后按 serverName 校验: 通过即说明 SAN 与有效期正确
+            let (certs, _) = tls.generate_self_signed_cert().unwrap();
+            let mut roots = rustls::RootCertStore::empty();
+            roots.add(certs[0].clone()).unwrap();
+            let verifier = rustls::client::WebPkiServerVerifier::builder(Arc::new(roots)).build().unwrap();
+            let verify = |name: &str, now: UnixTime| {
+                verifier.verify_server_cert(&certs[0], &[], &ServerName::try_from(name.to_string()).unwrap(), &[], now)
+            };
+            assert!(verify("www.apple.com", UnixTime::now()).is_ok(), "{:?}", key_type);
+            assert!(verify("localhost", UnixTime::now()).is_err());
+            let expired = UnixTime::since_unix_epoch(Duration::from_secs(UnixTime::now().as_secs() + 91 * 86400));
+            assert!(verify("www.apple.com", expired).is_err());
+        }
+    }
+
+    #[test]
+    fn test_self_signed_domains() {
+        let mut config = config(None, None);
+        config.server_names.clear();
+        config.dest = "[2001:db8::1]:443".to_string();
+        assert_eq!(RealityTlsConfig::new(config.clone()).self_signed_domains(), vec!["2001:db8::1".to_string()]);
+        config.self_signed.domains = vec!["cdn.example.com".to_string()];
+        assert_eq!(RealityTlsConfig::new(config).self_signed_domains(), vec!["cdn.example.com".to_string()]);
+    }
+}
@@ -0,0 +1,250 @@
+//! 普通 TLS 终结 (使用真实证书，不经过 Reality)
+//!
+//! 用于 CDN 回源直连: 入站直接监听 443，以源站证书完成握手后再交给 XHTTP 处理。
+//! 证书文件在热重载时重新读取，已建立的连接继续使用原证书。
+
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::{DigitallySignedStruct, ServerConfig, SignatureScheme};
+use rustls_pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info};
+
+/// TLS 握手期限
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// XHTTP 能处理的 ALPN
+const SUPPORTED_ALPN: [&str; 2] = ["h2", "http/1.1"];
+
+/// 证书与 ALPN 设置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsSettings {
+    /// PEM 证书链文件
+    pub certificate_file: String,
+    /// PEM 私钥文件 (PKCS#8 / PKCS#1 / SEC1)
+    pub key_file: String,
+    /// 按优先级排列
+    #[serde(default = "default_alpn")]
+    pub alpn: Vec<String>,
+}
+
+fn default_alpn() -> Vec<String> {
+    SUPPORTED_ALPN.iter().map(|p| p.to_string()).collect()
+}
+
+impl TlsSettings {
+    /// 错误信息以配置字段名开头
+    pub fn validate(&self) -> Result<()> {
+        if self.certificate_file.is_empty() {
+            return Err(anyhow!("certificateFile: 不能为空"));
+        }
+        if self.key_file.is_empty() {
+            return Err(anyhow!("keyFile: 不能为空"));
+        }
+        if let Some(protocol) = self.alpn.iter().find(|p| !SUPPORTED_ALPN.contains(&p.as_str())) {
+            return Err(anyhow!("alpn: 不支持 {:?}，可选 h2 与 http/1.1", protocol));
+        }
+        Ok(())
+    }
+
+    /// 读取证书文件并构建 ServerConfig
+    fn load(&self) -> Result<Arc<ServerConfig>> {
+        let (certs, key) = load_key_pair(&self.certificate_file, &self.key_file)?;
+        let mut config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+        config.alpn_protocols = self.alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+        Ok(Arc::new(config))
+    }
+}
+
+/// 读取 PEM 证书链与私钥，并确认私钥与证书配对
+pub fn load_key_pair(certificate_file: &str, key_file: &str) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let file = std::fs::File::open(certificate_file).map_err(|e| anyhow!("无法读取证书 {}: {}", certificate_file, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow!("证书 {} 格式错误: {}", certificate_file, e))?;
+    if certs.is_empty() {
+        return Err(anyhow!("证书 {} 中没有证书", certificate_file));
+    }
+    let file = std::fs::File::open(key_file).map_err(|e| anyhow!("无法读取私钥 {}: {}", key_file, e))?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| anyhow!("私钥 {} 格式错误: {}", key_file, e))?
+        .ok_or_else(|| anyhow!("私钥 {} 中没有私钥", key_file))?;
+    check_key_pair(&certs, &key).map_err(|e| anyhow!("私钥 {} 与证书 {} 不匹配: {}", key_file, certificate_file, e))?;
+    Ok((certs, key))
+}
+
+/// 在内存中完成一次握手: 客户端只校验 CertificateVerify 签名，私钥与证书不配对时握手失败
+fn check_key_pair(certs: &[CertificateDer<'static>], key: &PrivateKeyDer<'static>) -> Result<()> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let server_config = ServerConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(certs.to_vec(), key.clone_key())?;
+    let client_config = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(SignatureOnly(provider)))
+        .with_no_client_auth();
+
+    let name = ServerName::try_from("localhost")?;
+    let mut client = rustls::ClientConnection::new(Arc::new(client_config), name)?;
+    let mut server = rustls::ServerConnection::new(Arc::new(server_config))?;
+    let mut buf = Vec::new();
+    while client.is_handshaking() || server.is_handshaking() {
+        let mut progressed = false;
+        while client.wants_write() {
+            client.write_tls(&mut buf)?;
+            progressed = true;
+        }
+        if !buf.is_empty() {
+            server.read_tls(&mut buf.as_slice())?;
+            buf.clear();
+            server.process_new_packets()?;
+        }
+        while server.wants_write() {
+            server.write_tls(&mut buf)?;
+            progressed = true;
+        }
+        if !buf.is_empty() {
+            client.read_tls(&mut buf.as_slice())?;
+            buf.clear();
+            client.process_new_packets()?;
+        }
+        if !progressed {
+            return Err(anyhow!("握手未完成"));
+        }
+    }
+    Ok(())
+}
+
+/// 接受任意证书链，但按证书公钥校验握手签名
+#[derive(Debug)]
+struct SignatureOnly(Arc<rustls::crypto::CryptoProvider>);
+
+impl ServerCertVerifier for SignatureOnly {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// TLS 终结器 (克隆后共享同一份证书)
+#[derive(Clone)]
+pub struct TlsTerminator {
+    settings: TlsSettings,
+    acceptor: Arc<RwLock<TlsAcceptor>>,
+}
+
+impl TlsTerminator {
+    pub fn new(settings: TlsSettings) -> Result<Self> {
+        let acceptor = TlsAcceptor::from(settings.load()?);
+        info!("🔐 TLS 证书已加载: {}", settings.certificate_file);
+        Ok(Self { settings, acceptor: Arc::new(RwLock::new(acceptor)) })
+    }
+
+    /// 重新读取证书文件，失败时保留原证书
+    pub fn reload(&self) -> Result<()> {
+        let acceptor = TlsAcceptor::from(self.settings.load()?);
+        *self.acceptor.write().unwrap_or_else(|e| e.into_inner()) = acceptor;
+        info!("🔐 TLS 证书已重新加载: {}", self.settings.certificate_file);
+        Ok(())
+    }
+
+    /// 完成握手，返回 TLS 流与协商出的 ALPN
+    pub async fn accept<S>(&self, stream: S) -> Result<(TlsStream<S>, Option<Vec<u8>>)>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let acceptor = self.acceptor.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let tls = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+            .await
+            .map_err(|_| anyhow!("TLS 握手超时"))?
+            .map_err(|e| anyhow!("TLS 握手失败: {}", e))?;
+        let alpn = tls.get_ref().1.alpn_protocol().map(<[u8]>::to_vec);
+        debug!("TLS 握手完成 (ALPN: {:?})", alpn.as_deref().map(String::from_utf8_lossy));
+        Ok((tls, alpn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let settings = TlsSettings {
+            certificate_file: "/etc/ssl/origin.pem".into(),
+            key_file: "/etc/ssl/origin.key".into(),
+            alpn: default_alpn(),
+        };
+        assert!(settings.validate().is_ok());
+        assert!(TlsSettings { alpn: vec!["h3".into()], ..settings.clone() }.validate().unwrap_err().to_string().starts_with("alpn"));
+        assert!(TlsSettings { key_file: String::new(), ..settings }.validate().unwrap_err().to_string().starts_with("keyFile"));
+    }
+
+    #[test]
+    fn test_load_checks_key_pair() {
+        let dir = std::env::temp_dir().join(format!("xray-lite-tls-unit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        std::fs::write(dir.join("cert.pem"), cert.cert.pem()).unwrap();
+        std::fs::write(dir.join("empty.pem"), "").unwrap();
+        std::fs::write(dir.join("key.pem"), cert.key_pair.serialize_pem()).unwrap();
+
+        let settings = TlsSettings {
+            certificate_file: dir.join("cert.pem").display().to_string(),
+            key_file: dir.join("empty.pem").display().to_string(),
+            alpn: default_alpn(),
+        };
+        assert!(settings.load().unwrap_err().to_string().contains("没有私钥"));
+        let settings = TlsSettings { key_file: dir.join("key.pem").display().to_string(), ..settings };
+        assert_eq!(settings.load().unwrap().alpn_protocols, vec![b"h2".to_vec(), b"http/1.1".to_vec()]);
+
+        // 另一张证书的私钥
+        let other = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        std::fs::write(dir.join("other.pem"), other.key_pair.serialize_pem()).unwrap();
+        let settings = TlsSettings { key_file: dir.join("other.pem").display().to_string(), ..settings };
+        assert!(settings.load().unwrap_err().to_string().contains("不匹配"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// 单帧负载上限，超出视为异常客户端
+pub const MAX_PAYLOAD: usize = 1 << 20;
+
+/// 帧类型 (RFC 6455 5.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    Continuation = 0x0,
+    Text = 0x1,
+    Binary = 0x2,
+    Close = 0x8,
+    Ping = 0x9,
+    Pong = 0xA,
+}
+
+impl OpCode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x0 => Some(OpCode::Continuation),
+            0x1 => Some(OpCode::Text),
+            0x2 => Some(OpCode::Binary),
+            0x8 => Some(OpCode::Close),
+            0x9 => Some(OpCode::Ping),
+            0xA => Some(OpCode::Pong),
+            _ => None,
+        }
+    }
+
+    /// 控制帧 (Close / Ping / Pong)
+    pub fn is_control(self) -> bool {
+        (self as u8) & 0x8 != 0
+    }
+}
+
+/// WebSocket 帧
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: OpCode,
+    pub payload: Bytes,
+}
+
+impl Frame {
+    pub fn binary(payload: Bytes) -> Self {
+        Self { fin: true, opcode: OpCode::Binary, payload }
+    }
+
+    pub fn pong(payload: Bytes) -> Self {
+        Self { fin: true, opcode: OpCode::Pong, payload }
+    }
+
+    /// 带状态码的关闭帧
+    pub fn close(code: u16) -> Self {
+        Self {
+            fin: true,
+            opcode: OpCode::Close,
+            payload: Bytes::copy_from_slice(&code.to_be_bytes()),
+        }
+    }
+
+    /// 编码帧；服务端发出的帧不加掩码，客户端必须提供掩码
+    pub fn encode(&self, mask: Option<[u8; 4]>, dst: &mut BytesMut) {
+        let len = self.payload.len();
+        dst.reserve(14 + len);
+        dst.put_u8(if self.fin { 0x80 } else { 0 } | self.opcode as u8);
+
+        let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+        if len < 126 {
+            dst.put_u8(mask_bit | len as u8);
+        } else if len <= u16::MAX as usize {
+            dst.put_u8(mask_bit | 126);
+            dst.put_u16(len as u16);
+        } else {
+            dst.put_u8(mask_bit | 127);
+            dst.put_u64(len as u64);
+        }
+
+        match mask {
+            Some(key) => {
+                dst.put_slice(&key);
+                let start = dst.len();
+                dst.put_slice(&self.payload);
+                apply_mask(&mut dst[start..], key);
+            }
+            None => dst.put_slice(&self.payload),
+        }
+    }
+
+    /// 从缓冲区解析一个客户端帧，数据不足时返回 `Ok(None)`
+    ///
+    /// 客户端帧必须带掩码 (RFC 6455 5.1)，解析后负载已去除掩码
+    pub fn parse(src: &mut BytesMut) -> Result<Option<Frame>> {
+        if src.len() < 2 {
+            return Ok(None);
+        }
+        let (b0, b1) = (src[0], src[1]);
+        if b0 & 0x70 != 0 {
+            return Err(anyhow!("WebSocket 帧使用了未协商的 RSV 位"));
+        }
+        let fin = b0 & 0x80 != 0;
+        let opcode = OpCode::from_u8(b0 & 0x0F)
+            .ok_or_else(|| anyhow!("未知的 WebSocket opcode: {:#x}", b0 & 0x0F))?;
+        if b1 & 0x80 == 0 {
+            return Err(anyhow!("客户端帧未加掩码"));
+        }
+
+        let (len, header_len) = match b1 & 0x7F {
+            126 => {
+                if src.len() < 4 {
+                    return Ok(None);
+                }
+                (u16::from_be_bytes([src[2], src[3]]) as usize, 4)
+            }
+            127 => {
+                if src.len() < 10 {
+                    return Ok(None);
+                }
+                let len = u64::from_be_bytes(src[2..10].try_into().unwrap());
+                (usize::try_from(len).unwrap_or(usize::MAX), 10)
+            }
+            n => (n as usize, 2),
+        };
+        if opcode.is_control() && (len > 125 || !fin) {
+            return Err(anyhow!("WebSocket 控制帧不合法"));
+        }
+        if len > MAX_PAYLOAD {
+            return Err(anyhow!("WebSocket 帧过大: {} 字节", len));
+        }
+
+        if src.len() < header_len + 4 + len {
+            return Ok(None);
+        }
+        let key: [u8; 4] = src[header_len..header_len + 4].try_into().unwrap();
+        src.advance(header_len + 4);
+        let mut payload = src.split_to(len);
+        apply_mask(&mut payload, key);
+
+        Ok(Some(Frame { fin, opcode, payload: payload.freeze() }))
+    }
+}
+
+fn apply_mask(data: &mut [u8], key: [u8; 4]) {
+    for (i, b) in data.iter_mut().enumerate() {
+        *b ^= key[i & 3];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masked_roundtrip() {
+        for len in [0usize, 5, 125, 126, 300, 70_000] {
+            let frame = Frame::binary(Bytes::from(vec![0x5A; len]));
+            let mut buf = BytesMut::new();
+            frame.encode(Some([1, 2, 3, 4]), &mut buf);
+
+            // 逐字节喂入，不完整时不应消费数据
+            let mut partial = BytesMut::from(&buf[..buf.len() - 1]);
+            assert!(Frame::parse(&mut partial).unwrap().is_none());
+            assert_eq!(partial.len(), buf.len() - 1);
+
+            assert_eq!(Frame::parse(&mut buf).unwrap(), Some(frame));
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_rfc6455_masked_hello() {
+        // RFC 6455 5.7: 带掩码的 "Hello"
+        let mut buf = BytesMut::from(
+            &[0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58][..],
+        );
+        let frame = Frame::parse(&mut buf).unwrap().unwrap();
+        assert_eq!(frame.opcode, OpCode::Text);
+        assert_eq!(&frame.payload[..], b"Hello");
+
+        let mut out = BytesMut::new();
+        Frame { fin: true, opcode: OpCode::Text, payload: Bytes::from_static(b"Hello") }
+            .encode(None, &mut out);
+        assert_eq!(&out[..], &[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]);
+    }
+
+    #[test]
+    fn test_rejects_invalid_frames() {
+        // 未加掩码
+        let mut buf = BytesMut::new();
+        Frame::binary(Bytes::from_static(b"x")).encode(None, &mut buf);
+        assert!(Frame::parse(&mut buf).is_err());
+
+        // 分片的控制帧
+        let mut buf = BytesMut::from(&[0x09, 0x80, 0, 0, 0, 0][..]);
+        assert!(Frame::parse(&mut buf).is_err());
+
+        // 超过上限
+        let mut buf = BytesMut::from(&[0x82, 0xFF, 0, 0, 0, 0, 0xFF, 0, 0, 0][..]);
+        assert!(Frame::parse(&mut buf).is_err());
+    }
+}
@@ -0,0 +1,16 @@
+mod frame;
+mod server;
+
+pub use frame::{Frame, OpCode};
+pub use server::WsServer;
+
+/// WebSocket 配置
+#[derive(Debug, Clone)]
+pub struct WsConfig {
+    /// 升级路径 (精确匹配，忽略查询串)
+    pub path: String,
+    /// Host 头，为空时不校验
+    pub host: String,
+    /// 客户端必须携带的请求头 (如 User-Agent)，名称不区分大小写
+    pub headers: Vec<(String, String)>,
+}
@@ -0,0 +1,405 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tracing::{debug, info, Instrument};
+
+use super::frame::{Frame, OpCode};
+use super::WsConfig;
+use crate::server::AuthReport;
+
+/// RFC 6455 4.2.2 中用于计算 Sec-WebSocket-Accept 的固定 GUID
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// 升级请求头的最大长度
+const MAX_REQUEST_HEAD: usize = 8192;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// 与 VLESS 处理器之间的内部管道容量
+const PIPE_CAPACITY: usize = 256 * 1024;
+
+/// WebSocket 服务器
+#[derive(Clone)]
+pub struct WsServer {
+    config: WsConfig,
+}
+
+/// 解析后的 HTTP/1.1 升级请求
+struct UpgradeRequest {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl UpgradeRequest {
+    fn parse(head: &[u8]) -> Result<Self> {
+        let head = std::str::from_utf8(head).map_err(|_| anyhow!("HTTP 请求头不是 UTF-8"))?;
+        let mut lines = head.split("\r\n");
+        let request_line = lines.next().unwrap_or_default();
+        let mut parts = request_line.split(' ');
+        let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(m), Some(t), Some(v)) => (m, t, v),
+            _ => return Err(anyhow!("无效的请求行: {:?}", request_line)),
+        };
+        if version != "HTTP/1.1" {
+            return Err(anyhow!("不支持的 HTTP 版本: {}", version));
+        }
+
+        let headers = lines
+            .take_while(|line| !line.is_empty())
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+
+        Ok(Self {
+            method: method.to_string(),
+            path: target.split('?').next().unwrap_or_default().to_string(),
+            headers,
+        })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn header_has_token(&self, name: &str, token: &str) -> bool {
+        self.header(name)
+            .map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+            .unwrap_or(false)
+    }
+}
+
+impl WsServer {
+    /// 创建新的 WebSocket 服务器
+    pub fn new(config: WsConfig) -> Result<Self> {
+        if !config.path.starts_with('/') {
+            return Err(anyhow!("WebSocket path 必须以 / 开头"));
+        }
+
+        info!("WebSocket 服务器初始化成功");
+        debug!("路径: {}", config.path);
+        debug!("Host: {}", config.host);
+
+        Ok(Self { config })
+    }
+
+    /// 获取路径
+    pub fn path(&self) -> &str {
+        &self.config.path
+    }
+
+    /// 处理传入的连接: 完成升级后将二进制帧中的数据交给 VLESS 处理回调
+    pub async fn accept<T, F, Fut>(&self, mut stream: T, handler: F) -> Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        F: Fn(Box<dyn crate::server::AsyncStream>, crate::server::AuthReport) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let mut buf = BytesMut::with_capacity(1024);
+        let head_len = timeout(HANDSHAKE_TIMEOUT, read_request_head(&mut stream, &mut buf))
+            .await
+            .map_err(|_| anyhow!("WebSocket 握手超时"))??;
+        let head = buf.split_to(head_len);
+
+        let request = match UpgradeRequest::parse(&head) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!("WebSocket: {}", e);
+                return reject(&mut stream, "400 Bad Request").await;
+            }
+        };
+
+        if request.method != "GET" || request.path != self.config.path || !self.matches_headers(&request) {
+            debug!("WebSocket: 拒绝请求 {} {}", request.method, request.path);
+            return reject(&mut stream, "404 Not Found").await;
+        }
+
+        let key = match request.header("sec-websocket-key") {
+            Some(key)
+                if request.header_has_token("upgrade", "websocket")
+                    && request.header_has_token("connection", "upgrade")
+                    && request.header("sec-websocket-version") == Some("13") =>
+            {
+                key
+            }
+            _ => return reject(&mut stream, "400 Bad Request").await,
+        };
+
+        // Xray 客户端的 early data 放在 Sec-WebSocket-Protocol 中 (Base64 RawURL)
+        let protocol = request.header("sec-websocket-protocol");
+        let early_data = protocol
+            .and_then(|p| general_purpose::URL_SAFE_NO_PAD.decode(p).ok())
+            .unwrap_or_default();
+
+        let mut response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Server: nginx/1.26.0\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n",
+            accept_key(key)
+        );
+        if let (Some(protocol), false) = (protocol, early_data.is_empty()) {
+            response.push_str(&format!("Sec-WebSocket-Protocol: {}\r\n", protocol));
+        }
+        response.push_str("\r\n");
+        stream.write_all(response.as_bytes()).await?;
+        stream.flush().await?;
+
+        debug!("WebSocket: 升级完成 (early data: {} 字节)", early_data.len());
+
+        let (client_io, server_io) = tokio::io::duplex(PIPE_CAPACITY);
+        tokio::spawn(handler(Box::new(server_io), AuthReport::none()).in_current_span());
+        relay(stream, client_io, buf, early_data).await
+    }
+
+    /// 校验 Host 以及配置中要求的请求头
+    fn matches_headers(&self, request: &UpgradeRequest) -> bool {
+        if !self.config.host.is_empty() {
+            let host = request.header("host").unwrap_or_default();
+            let host = host.rsplit_once(':').map(|(h, _)| h).unwrap_or(host);
+            if !host.eq_ignore_ascii_case(&self.config.host) {
+                return false;
+            }
+        }
+        self.config
+            .headers
+            .iter()
+            .all(|(name, value)| request.header(name) == Some(value.as_str()))
+    }
+}
+
+/// 读取到 `\r\n\r\n` 为止，返回请求头长度 (其后的数据已是 WebSocket 帧)
+async fn read_request_head<T: AsyncRead + Unpin>(stream: &mut T, buf: &mut BytesMut) -> Result<usize> {
+    loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            return Ok(pos + 4);
+        }
+        if buf.len() > MAX_REQUEST_HEAD {
+            return Err(anyhow!("HTTP 请求头过长"));
+        }
+        if stream.read_buf(buf).await? == 0 {
+            return Err(anyhow!("升级完成前连接已关闭"));
+        }
+    }
+}
+
+/// 按 nginx 的样式返回错误页并结束连接
+async fn reject<T: AsyncWrite + Unpin>(stream: &mut T, status: &str) -> Result<()> {
+    let body = format!(
+        "<html>\r\n<head><title>{status}</title></head>\r\n<body>\r\n<center><h1>{status}</h1></center>\r\n<hr><center>nginx/1.26.0</center>\r\n</body>\r\n</html>\r\n"
+    );
+    let response = format!(
+        "HTTP/1.1 {status}\r\nServer: nginx/1.26.0\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn accept_key(key: &str) -> String {
+    let mut ctx = ring::digest::Context::new(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY);
+    ctx.update(key.as_bytes());
+    ctx.update(WS_GUID.as_bytes());
+    general_purpose::STANDARD.encode(ctx.finish())
+}
+
+/// 在 WebSocket 连接与 VLESS 管道之间双向转发
+///
+/// 上行帧去掩码后写入管道，Ping 由下行任务回复 Pong；分片消息中间可以夹带控制帧，
+/// 分片顺序错误 (孤立的 Continuation 或未结束时开始新消息) 以 1002 关闭。
+/// 任一方向发出 Close 后结束。
+async fn relay<T, P>(stream: T, pipe: P, mut pending: BytesMut, early_data: Vec<u8>) -> Result<()>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    P: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut ws_read, mut ws_write) = tokio::io::split(stream);
+    let (mut pipe_read, mut pipe_write) = tokio::io::split(pipe);
+    let (control_tx, mut control_rx) = mpsc::channel::<Frame>(4);
+
+    let upload = async move {
+        if !early_data.is_empty() {
+            pipe_write.write_all(&early_data).await?;
+        }
+        // 正在接收分片消息 (上一个数据帧 FIN=0)
+        let mut fragmented = false;
+        loop {
+            loop {
+                let frame = match Frame::parse(&mut pending) {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => break,
+                    Err(e) => {
+                        let _ = control_tx.send(Frame::close(1002)).await;
+                        return Err(e);
+                    }
+                };
+                match frame.opcode {
+                    OpCode::Continuation | OpCode::Text | OpCode::Binary => {
+                        if fragmented != (frame.opcode == OpCode::Continuation) {
+                            let _ = control_tx.send(Frame::close(1002)).await;
+                            return Err(anyhow!("WebSocket 分片顺序错误: {:?}", frame.opcode));
+                        }
+                        fragmented = !frame.fin;
+                        pipe_write.write_all(&frame.payload).await?;
+                    }
+                    OpCode::Ping => {
+                        let _ = control_tx.send(Frame::pong(frame.payload)).await;
+                    }
+                    OpCode::Pong => {}
+                    OpCode::Close => {
+                        let _ = control_tx.send(Frame::close(1000)).await;
+                        pipe_write.shutdown().await?;
+                        return Ok(());
+                    }
+                }
+            }
+            match timeout(IDLE_TIMEOUT, ws_read.read_buf(&mut pending)).await {
+                Ok(Ok(0)) | Err(_) => {
+                    pipe_write.shutdown().await?;
+                    return Ok(());
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => return Err(e.into()),
+            }
+        }
+    };
+
+    let download = async move {
+        let mut buf = BytesMut::with_capacity(16 * 1024);
+        let mut out = BytesMut::new();
+        loop {
+            let frame = tokio::select! {
+                n = pipe_read.read_buf(&mut buf) => {
+                    if n? == 0 {
+                        Frame::close(1000)
+                    } else {
+                        Frame::binary(buf.split().freeze())
+                    }
+                }
+                Some(frame) = control_rx.recv() => frame,
+            };
+            frame.encode(None, &mut out);
+            ws_write.write_all(&out).await?;
+            ws_write.flush().await?;
+            out.clear();
+            if frame.opcode == OpCode::Close {
+                break;
+            }
+        }
+        ws_write.shutdown().await?;
+        Ok::<_, anyhow::Error>(())
+    };
+
+    let (upload, download) = tokio::join!(upload, download);
+    upload.and(download)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn client_frame(opcode: OpCode, payload: &'static [u8]) -> BytesMut {
+        fragment(true, opcode, payload)
+    }
+
+    fn fragment(fin: bool, opcode: OpCode, payload: &'static [u8]) -> BytesMut {
+        let mut buf = BytesMut::new();
+        Frame { fin, opcode, payload: Bytes::from_static(payload) }.encode(Some([9, 8, 7, 6]), &mut buf);
+        buf
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // RFC 6455 1.3 中的示例
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn test_header_matching() {
+        let server = WsServer::new(WsConfig {
+            path: "/ws".to_string(),
+            host: "cdn.example.com".to_string(),
+            headers: vec![("User-Agent".to_string(), "Mozilla/5.0".to_string())],
+        })
+        .unwrap();
+
+        let request = UpgradeRequest::parse(
+            b"GET /ws?ed=2048 HTTP/1.1\r\nHost: CDN.example.com:443\r\nuser-agent: Mozilla/5.0\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.path, "/ws");
+        assert!(server.matches_headers(&request));
+
+        let request =
+            UpgradeRequest::parse(b"GET /ws HTTP/1.1\r\nHost: cdn.example.com\r\nUser-Agent: curl/8.0\r\n\r\n").unwrap();
+        assert!(!server.matches_headers(&request));
+    }
+
+    #[tokio::test]
+    async fn test_ping_is_answered_with_pong() {
+        let (server_side, mut client_side) = tokio::io::duplex(4096);
+        let (pipe, mut vless_side) = tokio::io::duplex(4096);
+        tokio::spawn(relay(server_side, pipe, BytesMut::new(), Vec::new()));
+
+        client_side.write_all(&client_frame(OpCode::Ping, b"hi")).await.unwrap();
+        let mut pong = [0u8; 4];
+        client_side.read_exact(&mut pong).await.unwrap();
+        assert_eq!(pong, [0x8A, 0x02, b'h', b'i']);
+
+        // 客户端关闭后管道收到 EOF，服务端回复 Close
+        client_side.write_all(&client_frame(OpCode::Close, b"")).await.unwrap();
+        let mut rest = Vec::new();
+        vless_side.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+        let mut close = [0u8; 4];
+        client_side.read_exact(&mut close).await.unwrap();
+        assert_eq!(close, [0x88, 0x02, 0x03, 0xE8]);
+    }
+
+    #[tokio::test]
+    async fn test_fragmented_message_with_interleaved_ping() {
+        let (server_side, mut client_side) = tokio::io::duplex(4096);
+        let (pipe, mut vless_side) = tokio::io::duplex(4096);
+        tokio::spawn(relay(server_side, pipe, BytesMut::new(), Vec::new()));
+
+        client_side.write_all(&fragment(false, OpCode::Binary, b"vle")).await.unwrap();
+        client_side.write_all(&client_frame(OpCode::Ping, b"hi")).await.unwrap();
+        client_side.write_all(&fragment(false, OpCode::Continuation, b"ss")).await.unwrap();
+        client_side.write_all(&fragment(true, OpCode::Continuation, b"!")).await.unwrap();
+
+        let mut pong = [0u8; 4];
+        client_side.read_exact(&mut pong).await.unwrap();
+        assert_eq!(pong, [0x8A, 0x02, b'h', b'i']);
+        let mut payload = [0u8; 6];
+        vless_side.read_exact(&mut payload).await.unwrap();
+        assert_eq!(&payload, b"vless!");
+
+        // 消息已结束，孤立的 Continuation 是协议错误
+        client_side.write_all(&client_frame(OpCode::Continuation, b"x")).await.unwrap();
+        let mut close = [0u8; 4];
+        client_side.read_exact(&mut close).await.unwrap();
+        assert_eq!(close, [0x88, 0x02, 0x03, 0xEA]);
+    }
+
+    #[tokio::test]
+    async fn test_new_message_inside_fragmented_message_is_rejected() {
+        let (server_side, mut client_side) = tokio::io::duplex(4096);
+        let (pipe, _vless_side) = tokio::io::duplex(4096);
+        tokio::spawn(relay(server_side, pipe, BytesMut::new(), Vec::new()));
+
+        client_side.write_all(&fragment(false, OpCode::Binary, b"a")).await.unwrap();
+        client_side.write_all(&client_frame(OpCode::Binary, b"b")).await.unwrap();
+        let mut close = [0u8; 4];
+        client_side.read_exact(&mut close).await.unwrap();
+        assert_eq!(close, [0x88, 0x02, 0x03, 0xEA]);
+    }
+}
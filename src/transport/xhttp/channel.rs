@@ -0,0 +1,85 @@
+//! GET/POST 配对会话的上行通道
+//!
+//! 容量按字节计算: 发送前取得与数据长度相同的许可，接收方把数据写给 VLESS 之后才释放。
+//! 通道满时 `send` 等待，POST 处理随之推迟释放 H2 窗口，由流控限制客户端的上传速度。
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+
+/// 每个会话最多缓存的上行字节数
+pub(super) const UPLOAD_BUFFER_BYTES: usize = 1024 * 1024;
+
+pub(super) fn channel(capacity: usize) -> (UploadSender, UploadReceiver) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let permits = Arc::new(Semaphore::new(capacity));
+    (UploadSender { tx, permits, capacity }, UploadReceiver { rx })
+}
+
+#[derive(Clone)]
+pub(super) struct UploadSender {
+    tx: mpsc::UnboundedSender<Chunk>,
+    permits: Arc<Semaphore>,
+    capacity: usize,
+}
+
+impl UploadSender {
+    /// 等待通道腾出空间后发送，接收端已关闭时原样返回数据
+    ///
+    /// 超过容量的单块数据按整个容量计算，不会永久等待。
+    pub(super) async fn send(&self, data: Bytes) -> Result<(), Bytes> {
+        if self.tx.is_closed() {
+            return Err(data);
+        }
+        let n = data.len().min(self.capacity) as u32;
+        let Ok(permit) = self.permits.clone().acquire_many_owned(n).await else {
+            return Err(data);
+        };
+        self.tx.send(Chunk { data, _permit: permit }).map_err(|e| e.0.data)
+    }
+}
+
+pub(super) struct UploadReceiver {
+    rx: mpsc::UnboundedReceiver<Chunk>,
+}
+
+impl UploadReceiver {
+    pub(super) async fn recv(&mut self) -> Option<Chunk> {
+        self.rx.recv().await
+    }
+}
+
+/// 通道中的一块数据，drop 时归还容量
+pub(super) struct Chunk {
+    pub(super) data: Bytes,
+    _permit: OwnedSemaphorePermit,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_send_waits_until_chunk_consumed() {
+        let (tx, mut rx) = channel(10);
+        tx.send(Bytes::from_static(b"0123456789")).await.unwrap();
+
+        // 通道已满
+        let blocked = tokio::time::timeout(Duration::from_millis(50), tx.send(Bytes::from_static(b"x"))).await;
+        assert!(blocked.is_err());
+
+        // 接收后仍持有数据时不释放容量
+        let chunk = rx.recv().await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(50), tx.send(Bytes::from_static(b"x"))).await.is_err());
+        drop(chunk);
+        tx.send(Bytes::from_static(b"x")).await.unwrap();
+
+        // 超过容量的单块数据也能发送；接收端关闭后返回 Err
+        drop(rx.recv().await);
+        tx.send(Bytes::from(vec![0u8; 100])).await.unwrap();
+        drop(rx);
+        assert!(tx.send(Bytes::from_static(b"x")).await.is_err());
+    }
+}
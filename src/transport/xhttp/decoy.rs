@@ -0,0 +1,333 @@
+//! 伪装站点
+//!
+//! 路径不在 XHTTP 路径下的请求 (探测、浏览器直接访问域名) 交给伪装站点处理:
+//! 本地静态目录，或转发到真实站点 (HTTP/1.1 反向代理) 并把响应原样流式传回。
+
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use hyper::body::{Body, Frame, Incoming};
+use hyper::http::{HeaderMap, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tracing::debug;
+
+/// 伪装站点配置，`root` 与 `proxy` 至多设置一个；都不设置时返回空的 404
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Decoy {
+    /// 静态站点目录
+    pub root: Option<PathBuf>,
+    /// 反向代理目标 (`host:port`)，以 HTTP/1.1 转发
+    pub proxy: Option<String>,
+}
+
+impl Decoy {
+    /// 错误信息以配置字段名开头
+    pub fn validate(&self) -> Result<()> {
+        if self.root.is_some() && self.proxy.is_some() {
+            return Err(anyhow!("root: 不能与 proxy 同时设置"));
+        }
+        if let Some(root) = &self.root {
+            if !root.is_dir() {
+                return Err(anyhow!("root: {} 不是目录", root.display()));
+            }
+        }
+        if let Some(proxy) = &self.proxy {
+            let port = proxy.rsplit_once(':').and_then(|(host, port)| (!host.is_empty()).then_some(port));
+            if port.and_then(|port| port.parse::<u16>().ok()).is_none() {
+                return Err(anyhow!("proxy: 应为 host:port (当前为 {:?})", proxy));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 不能跨连接转发的逐跳头 (RFC 9110 7.6.1)
+const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+    "te",
+    "trailer",
+    "host",
+];
+
+/// 复制除逐跳头以外的头
+pub(super) fn copy_end_to_end(from: &HeaderMap, to: &mut HeaderMap) {
+    for (name, value) in from {
+        if !HOP_BY_HOP.contains(&name.as_str()) {
+            to.append(name, value.clone());
+        }
+    }
+}
+
+/// 转发给站点的请求体
+pub(super) enum ProxyBody {
+    /// 已完整读出的请求体 (HTTP/1.1)
+    Full(Option<Bytes>),
+    /// 边收边转发的 H2 请求体
+    H2(h2::RecvStream),
+}
+
+impl Body for ProxyBody {
+    type Data = Bytes;
+    type Error = h2::Error;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, h2::Error>>> {
+        match self.get_mut() {
+            ProxyBody::Full(data) => Poll::Ready(data.take().map(|data| Ok(Frame::data(data)))),
+            ProxyBody::H2(stream) => match stream.poll_data(cx) {
+                Poll::Ready(Some(Ok(data))) => {
+                    let _ = stream.flow_control().release_capacity(data.len());
+                    Poll::Ready(Some(Ok(Frame::data(data))))
+                }
+                other => other.map(|frame| frame.map(|result| result.map(Frame::data))),
+            },
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            ProxyBody::Full(data) => data.is_none(),
+            ProxyBody::H2(stream) => stream.is_end_stream(),
+        }
+    }
+}
+
+/// 把请求转发给站点，返回其响应 (响应体尚未读取)
+///
+/// `authority` 作为 Host 头；请求 URI 改写为 origin-form。
+pub(super) async fn forward(
+    target: &str,
+    method: &str,
+    path_and_query: &str,
+    authority: &str,
+    headers: &HeaderMap,
+    body: ProxyBody,
+) -> Result<Response<Incoming>> {
+    let stream = TcpStream::connect(target).await?;
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("伪装站点连接结束: {}", e);
+        }
+    });
+
+    let mut request = Request::builder().method(method).uri(path_and_query).body(body)?;
+    copy_end_to_end(headers, request.headers_mut());
+    if !authority.is_empty() {
+        request.headers_mut().insert("host", authority.parse()?);
+    }
+    Ok(sender.send_request(request).await?)
+}
+
+/// 静态站点的响应
+pub(super) struct StaticResponse {
+    pub status: StatusCode,
+    pub headers: Vec<(&'static str, String)>,
+    /// HEAD 与 304 时为空，Content-Length 仍按文件长度
+    pub body: Bytes,
+}
+
+impl StaticResponse {
+    fn empty(status: StatusCode, headers: Vec<(&'static str, String)>) -> Self {
+        let mut headers = headers;
+        headers.push(("Content-Length", "0".to_string()));
+        Self { status, headers, body: Bytes::new() }
+    }
+}
+
+/// 从静态目录返回 `path` 对应的文件
+///
+/// 目录返回其中的 index.html (不以 `/` 结尾时先 301 重定向)；不存在时返回 404，目录下有 404.html 时以其为响应体。
+/// 支持 ETag / If-None-Match 协商缓存。
+pub(super) async fn serve_static(root: &Path, method: &str, path: &str, if_none_match: Option<&str>) -> StaticResponse {
+    if method != "GET" && method != "HEAD" {
+        return StaticResponse::empty(StatusCode::METHOD_NOT_ALLOWED, vec![("Allow", "GET, HEAD".to_string())]);
+    }
+    let Some(relative) = sanitize(path) else {
+        return StaticResponse::empty(StatusCode::BAD_REQUEST, Vec::new());
+    };
+
+    let mut file = root.join(&relative);
+    if tokio::fs::metadata(&file).await.is_ok_and(|m| m.is_dir()) {
+        if !path.ends_with('/') {
+            return StaticResponse::empty(StatusCode::MOVED_PERMANENTLY, vec![("Location", format!("{}/", path))]);
+        }
+        file.push("index.html");
+    }
+
+    match read_file(&file).await {
+        Some((data, modified)) => {
+            let etag = format!("\"{:x}-{:x}\"", modified, data.len());
+            let mut headers = vec![
+                ("Last-Modified", httpdate::fmt_http_date(UNIX_EPOCH + std::time::Duration::from_secs(modified))),
+                ("ETag", etag.clone()),
+            ];
+            if if_none_match.is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*")) {
+                return StaticResponse::empty(StatusCode::NOT_MODIFIED, headers);
+            }
+            headers.insert(0, ("Content-Type", content_type(&file).to_string()));
+            headers.insert(1, ("Content-Length", data.len().to_string()));
+            let body = if method == "HEAD" { Bytes::new() } else { data };
+            StaticResponse { status: StatusCode::OK, headers, body }
+        }
+        None => match read_file(&root.join("404.html")).await {
+            Some((data, _)) => StaticResponse {
+                status: StatusCode::NOT_FOUND,
+                headers: vec![
+                    ("Content-Type", "text/html".to_string()),
+                    ("Content-Length", data.len().to_string()),
+                ],
+                body: if method == "HEAD" { Bytes::new() } else { data },
+            },
+            None => StaticResponse::empty(StatusCode::NOT_FOUND, Vec::new()),
+        },
+    }
+}
+
+/// 读取普通文件及其修改时间 (Unix 秒)
+async fn read_file(path: &Path) -> Option<(Bytes, u64)> {
+    let metadata = tokio::fs::metadata(path).await.ok().filter(|m| m.is_file())?;
+    let modified = metadata
+        .modified()
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let data = tokio::fs::read(path).await.ok()?;
+    Some((Bytes::from(data), modified))
+}
+
+/// 解码 URL 路径并转换为相对路径，拒绝 `..`、反斜杠与 NUL
+fn sanitize(path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(path)?;
+    if decoded.contains(['\\', '\0']) {
+        return None;
+    }
+    let mut relative = PathBuf::new();
+    for component in Path::new(decoded.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(relative)
+}
+
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "application/javascript",
+        "json" => "application/json",
+        "txt" => "text/plain",
+        "xml" => "text/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("xray-lite-decoy-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("index.html"), "<h1>home</h1>").unwrap();
+        std::fs::write(root.join("docs/index.html"), "<h1>docs</h1>").unwrap();
+        std::fs::write(root.join("style.css"), "body {}").unwrap();
+        root
+    }
+
+    fn header<'a>(response: &'a StaticResponse, name: &str) -> Option<&'a str> {
+        response.headers.iter().find(|(n, _)| *n == name).map(|(_, v)| v.as_str())
+    }
+
+    #[tokio::test]
+    async fn test_static_site() {
+        let root = site();
+
+        let home = serve_static(&root, "GET", "/", None).await;
+        assert_eq!(home.status, StatusCode::OK);
+        assert_eq!(&home.body[..], b"<h1>home</h1>");
+        assert_eq!(header(&home, "Content-Type"), Some("text/html"));
+
+        let css = serve_static(&root, "HEAD", "/style.css", None).await;
+        assert_eq!(header(&css, "Content-Type"), Some("text/css"));
+        assert_eq!(header(&css, "Content-Length"), Some("7"));
+        assert!(css.body.is_empty());
+
+        let docs = serve_static(&root, "GET", "/docs", None).await;
+        assert_eq!(docs.status, StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(header(&docs, "Location"), Some("/docs/"));
+        assert_eq!(&serve_static(&root, "GET", "/%64ocs/", None).await.body[..], b"<h1>docs</h1>");
+
+        // ETag 协商
+        let etag = header(&home, "ETag").unwrap().to_string();
+        let cached = serve_static(&root, "GET", "/index.html", Some(&format!("\"x\", {etag}"))).await;
+        assert_eq!(cached.status, StatusCode::NOT_MODIFIED);
+        assert!(cached.body.is_empty());
+        assert_eq!(header(&cached, "ETag"), Some(etag.as_str()));
+
+        assert_eq!(serve_static(&root, "GET", "/missing.png", None).await.status, StatusCode::NOT_FOUND);
+        assert_eq!(serve_static(&root, "POST", "/", None).await.status, StatusCode::METHOD_NOT_ALLOWED);
+        // 不允许离开站点目录
+        for path in ["/../etc/passwd", "/docs/%2e%2e/%2e%2e/etc/passwd", "/a%5c..%5cb", "/%zz"] {
+            assert_eq!(serve_static(&root, "GET", path, None).await.status, StatusCode::BAD_REQUEST, "{path}");
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Decoy::default().validate().is_ok());
+        let proxy = |target: &str| Decoy { proxy: Some(target.to_string()), ..Default::default() };
+        assert!(proxy("127.0.0.1:8080").validate().is_ok());
+        assert!(proxy("www.example.com:80").validate().is_ok());
+        for target in ["127.0.0.1", ":8080", "example.com:http"] {
+            assert!(proxy(target).validate().unwrap_err().to_string().starts_with("proxy:"), "{target}");
+        }
+        let both = Decoy { root: Some(std::env::temp_dir()), proxy: Some("127.0.0.1:80".into()) };
+        assert!(both.validate().unwrap_err().to_string().starts_with("root:"));
+        let missing = Decoy { root: Some(PathBuf::from("/nonexistent/xray-lite-decoy")), proxy: None };
+        assert!(missing.validate().unwrap_err().to_string().starts_with("root:"));
+    }
+}
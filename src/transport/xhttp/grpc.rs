@@ -1,4 +1,85 @@
-use bytes::{BufMut, Bytes, BytesMut};
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+
+/// gRPC 消息头长度: 压缩标志 1 字节 + 消息长度 4 字节
+const HEADER_LEN: usize = 5;
+
+/// gRPC 分帧上行的限制
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GrpcLimits {
+    /// 单条消息的最大字节数
+    pub max_message_bytes: usize,
+}
+
+impl Default for GrpcLimits {
+    fn default() -> Self {
+        Self { max_message_bytes: 4 * 1024 * 1024 }
+    }
+}
+
+impl GrpcLimits {
+    /// 错误信息以配置字段名开头
+    pub fn validate(&self) -> Result<()> {
+        if self.max_message_bytes == 0 || self.max_message_bytes > u32::MAX as usize {
+            return Err(anyhow!("maxMessageBytes: 必须在 1..={} 之间", u32::MAX));
+        }
+        Ok(())
+    }
+}
+
+/// gRPC Length-Prefixed-Message 的流式解码器
+///
+/// 请求体按任意边界分块到达，消息头与消息体都可能跨块，不完整的部分留在缓冲区等待后续数据。
+pub struct GrpcDecoder {
+    buf: BytesMut,
+    max_message_bytes: usize,
+}
+
+impl GrpcDecoder {
+    pub fn new(limits: &GrpcLimits) -> Self {
+        Self {
+            buf: BytesMut::new(),
+            max_message_bytes: limits.max_message_bytes,
+        }
+    }
+
+    /// 追加一块请求体
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// 取出下一条完整消息的内容，数据不足时返回 `None`
+    ///
+    /// 压缩标志为 1 (未协商 grpc-encoding，无法解压) 或无效、消息超过上限时返回错误。
+    pub fn next_message(&mut self) -> Result<Option<Bytes>> {
+        if self.buf.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        match self.buf[0] {
+            0 => {}
+            1 => return Err(anyhow!("不支持压缩的 gRPC 消息")),
+            flag => return Err(anyhow!("无效的 gRPC 压缩标志: {:#04x}", flag)),
+        }
+        let len = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]) as usize;
+        if len > self.max_message_bytes {
+            return Err(anyhow!("gRPC 消息长度 {} 超过上限 {}", len, self.max_message_bytes));
+        }
+        if self.buf.len() < HEADER_LEN + len {
+            // 为消息剩余部分预留空间，避免逐块扩容
+            self.buf.reserve(HEADER_LEN + len - self.buf.len());
+            return Ok(None);
+        }
+        self.buf.advance(HEADER_LEN);
+        Ok(Some(self.buf.split_to(len).freeze()))
+    }
+
+    /// 缓冲区中是否还有不完整的消息
+    pub fn has_partial(&self) -> bool {
+        !self.buf.is_empty()
+    }
+}
 
 /// gRPC 消息格式
 ///
@@ -226,6 +307,56 @@ mod tests {
         assert!(!decoded.compressed);
     }
 
+    /// 把 `data` 按 `chunk` 字节一块送入解码器，返回解出的消息
+    fn decode_in_chunks(data: &[u8], chunk: usize) -> Vec<Bytes> {
+        let mut decoder = GrpcDecoder::new(&GrpcLimits::default());
+        let mut messages = Vec::new();
+        for piece in data.chunks(chunk) {
+            decoder.push(piece);
+            while let Some(message) = decoder.next_message().unwrap() {
+                messages.push(message);
+            }
+        }
+        assert!(!decoder.has_partial());
+        messages
+    }
+
+    #[test]
+    fn test_decoder_byte_by_byte() {
+        let payload: Vec<u8> = (0..100 * 1024).map(|i| (i % 251) as u8).collect();
+        let mut data = GrpcMessage::new(payload.clone()).encode().to_vec();
+        data.extend_from_slice(&GrpcMessage::new(b"tail".to_vec()).encode());
+
+        let messages = decode_in_chunks(&data, 1);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(&messages[0][..], &payload[..]);
+        assert_eq!(&messages[1][..], b"tail");
+
+        // 多条消息在同一块中、消息头跨块
+        assert_eq!(decode_in_chunks(&data, data.len()), messages);
+        assert_eq!(decode_in_chunks(&data, 3), messages);
+    }
+
+    #[test]
+    fn test_decoder_limits() {
+        let mut decoder = GrpcDecoder::new(&GrpcLimits { max_message_bytes: 1024 });
+        decoder.push(&GrpcMessage::new(vec![0; 1024]).encode());
+        assert_eq!(decoder.next_message().unwrap().unwrap().len(), 1024);
+        // 只收到消息头就能判断超限
+        decoder.push(&[0, 0, 0, 4, 1]);
+        assert!(decoder.next_message().is_err());
+
+        let mut decoder = GrpcDecoder::new(&GrpcLimits::default());
+        decoder.push(&[1, 0, 0, 0, 4, 1, 2, 3, 4]);
+        assert!(decoder.next_message().is_err());
+        let mut decoder = GrpcDecoder::new(&GrpcLimits::default());
+        decoder.push(&[0x56, 0, 0, 0, 4]);
+        assert!(decoder.next_message().is_err());
+
+        assert!(GrpcLimits { max_message_bytes: 0 }.validate().is_err());
+        assert!(GrpcLimits::default().validate().is_ok());
+    }
+
     #[test]
     fn test_grpc_empty_message() {
         let empty = GrpcMessage::empty();
@@ -0,0 +1,749 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use bytes::{Buf, Bytes, BytesMut};
+use hyper::body::Body as _;
+use hyper::http::StatusCode;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, info_span, trace, Instrument};
+
+use crate::network::connection::PooledBuffer;
+use crate::server::AuthReport;
+use super::decoy::{self, ProxyBody};
+use super::metrics::XhttpMetrics;
+use super::pipe::pipe;
+use super::h2::{H2Handler, Rejected, Sessions};
+use super::{Masquerade, RequestMode, Shaping, XhttpConfig, XhttpMode, CACHE_CONTROL, MODE_HEADER};
+
+/// 请求头与 chunk 长度行的上限
+const MAX_HEAD_LEN: usize = 16 * 1024;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// 转发给伪装站点的请求体上限 (HTTP/1.1 请求体需先完整读出)
+const MAX_DECOY_BODY: usize = 1024 * 1024;
+
+/// HTTP/1.1 XHTTP 处理器
+///
+/// 部分 CDN 回源只支持 HTTP/1.1，此时 GET/POST 分离会话改用 chunked 传输:
+/// GET 以 chunked 响应承载下行，POST 请求体承载上行。
+#[derive(Clone)]
+pub struct H1Handler {
+    config: XhttpConfig,
+    metrics: Arc<XhttpMetrics>,
+    /// 本入站的分离会话 (与 H2Handler 共用)
+    sessions: Arc<Sessions>,
+}
+
+/// 请求体的解码状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Body {
+    Length(u64),
+    ChunkHeader,
+    ChunkData(u64),
+    ChunkEnd,
+    Done,
+}
+
+struct RequestHead {
+    method: String,
+    path: String,
+    query: Option<String>,
+    headers: Vec<(String, String)>,
+}
+
+impl RequestHead {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn body(&self) -> Result<Body> {
+        if self
+            .header("transfer-encoding")
+            .is_some_and(|v| v.to_ascii_lowercase().contains("chunked"))
+        {
+            return Ok(Body::ChunkHeader);
+        }
+        match self.header("content-length") {
+            Some(len) => Ok(Body::Length(len.parse().map_err(|_| anyhow!("无效的 Content-Length: {}", len))?)),
+            None => Ok(Body::Done),
+        }
+    }
+
+    fn keep_alive(&self) -> bool {
+        !self.header("connection").is_some_and(|v| v.eq_ignore_ascii_case("close"))
+    }
+}
+
+/// 带缓冲的请求读取端
+struct RequestReader<R> {
+    inner: R,
+    buf: BytesMut,
+}
+
+impl<R: AsyncRead + Unpin> RequestReader<R> {
+    async fn fill(&mut self) -> Result<()> {
+        if self.inner.read_buf(&mut self.buf).await? == 0 {
+            return Err(anyhow!("请求未结束连接已关闭"));
+        }
+        Ok(())
+    }
+
+    async fn read_line(&mut self) -> Result<BytesMut> {
+        loop {
+            if let Some(pos) = self.buf.windows(2).position(|w| w == b"\r\n") {
+                let mut line = self.buf.split_to(pos + 2);
+                line.truncate(pos);
+                return Ok(line);
+            }
+            if self.buf.len() > MAX_HEAD_LEN {
+                return Err(anyhow!("HTTP 行过长"));
+            }
+            self.fill().await?;
+        }
+    }
+
+    /// 读取下一个请求头，连接在请求之间正常关闭时返回 `None`
+    async fn read_head(&mut self) -> Result<Option<RequestHead>> {
+        while !self.buf.windows(4).any(|w| w == b"\r\n\r\n") {
+            if self.buf.len() > MAX_HEAD_LEN {
+                return Err(anyhow!("HTTP 请求头过长"));
+            }
+            if self.inner.read_buf(&mut self.buf).await? == 0 {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                return Err(anyhow!("请求头未结束连接已关闭"));
+            }
+        }
+
+        let request_line = self.read_line().await?;
+        let request_line = std::str::from_utf8(&request_line).map_err(|_| anyhow!("请求行不是 UTF-8"))?;
+        let mut parts = request_line.split(' ');
+        let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(m), Some(t), Some(v)) if v.starts_with("HTTP/1.") => (m.to_string(), t),
+            _ => return Err(anyhow!("无效的请求行: {:?}", request_line)),
+        };
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), Some(query.to_string())),
+            None => (target.to_string(), None),
+        };
+
+        let mut headers = Vec::new();
+        loop {
+            let line = self.read_line().await?;
+            if line.is_empty() {
+                break;
+            }
+            let line = String::from_utf8_lossy(&line);
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        Ok(Some(RequestHead { method, path, query, headers }))
+    }
+
+    /// 读取请求体的下一段，读完返回 `None`
+    async fn read_body(&mut self, state: &mut Body) -> Result<Option<Bytes>> {
+        loop {
+            match *state {
+                Body::Done | Body::Length(0) => {
+                    *state = Body::Done;
+                    return Ok(None);
+                }
+                Body::Length(remaining) | Body::ChunkData(remaining) => {
+                    if self.buf.is_empty() {
+                        self.fill().await?;
+                    }
+                    let n = remaining.min(self.buf.len() as u64);
+                    let left = remaining - n;
+                    *state = match state {
+                        Body::Length(_) => Body::Length(left),
+                        _ if left == 0 => Body::ChunkEnd,
+                        _ => Body::ChunkData(left),
+                    };
+                    return Ok(Some(self.buf.split_to(n as usize).freeze()));
+                }
+                Body::ChunkEnd => {
+                    if !self.read_line().await?.is_empty() {
+                        return Err(anyhow!("chunk 数据后缺少 CRLF"));
+                    }
+                    *state = Body::ChunkHeader;
+                }
+                Body::ChunkHeader => {
+                    let line = self.read_line().await?;
+                    let size = std::str::from_utf8(&line)
+                        .ok()
+                        .and_then(|l| u64::from_str_radix(l.split(';').next()?.trim(), 16).ok())
+                        .ok_or_else(|| anyhow!("无效的 chunk 长度行"))?;
+                    if size == 0 {
+                        // 跳过 trailer
+                        while !self.read_line().await?.is_empty() {}
+                        *state = Body::Done;
+                    } else {
+                        *state = Body::ChunkData(size);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 以 chunked 编码写出数据，按随机大小切分以消除长度特征 (与 H2 的分片整形一致)；关闭整形时写成一个 chunk
+async fn write_split_chunks<W: AsyncWrite + Unpin>(
+    src: &mut BytesMut,
+    writer: &mut W,
+    shaping: &Shaping,
+    metrics: &XhttpMetrics,
+) -> Result<()> {
+    let mut out = PooledBuffer::get();
+    while src.has_remaining() {
+        let split_len = shaping.next_chunk_size().map_or(src.len(), |size| size.min(src.len()));
+        metrics.add_down(split_len);
+
+        out.extend_from_slice(format!("{:x}\r\n", split_len).as_bytes());
+        out.extend_from_slice(&src.split_to(split_len));
+        out.extend_from_slice(b"\r\n");
+    }
+    writer.write_all(&out).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// 按拟态模板生成响应头部分 (含结尾空行)
+fn response_head(masquerade: &Masquerade, status: StatusCode, own: Vec<(&'static str, String)>) -> String {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", status.as_u16(), masquerade.reason_phrase(status));
+    for (name, value) in masquerade.response_headers(own) {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    head
+}
+
+fn stream_response_head(masquerade: &Masquerade, padding: String) -> String {
+    response_head(
+        masquerade,
+        StatusCode::OK,
+        vec![
+            ("Content-Type", "application/octet-stream".to_string()),
+            ("Cache-Control", CACHE_CONTROL.to_string()),
+            ("X-Padding", padding),
+            ("Transfer-Encoding", "chunked".to_string()),
+        ],
+    )
+}
+
+/// 返回空响应体的错误状态，随后关闭连接
+async fn send_status<W: AsyncWrite + Unpin>(writer: &mut W, status: StatusCode, masquerade: &Masquerade) -> Result<()> {
+    let own = vec![("Content-Length", "0".to_string()), ("Connection", "close".to_string())];
+    writer.write_all(response_head(masquerade, status, own).as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+impl H1Handler {
+    pub fn new(config: XhttpConfig) -> Self {
+        Self {
+            config,
+            metrics: XhttpMetrics::registered(),
+            sessions: Arc::new(Sessions::new(CancellationToken::new())),
+        }
+    }
+
+    /// 与 H2Handler 共用同一份指标
+    pub fn with_metrics(mut self, metrics: Arc<XhttpMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// 与 H2Handler 共用同一张会话表 (停机信号随会话表一起生效)
+    pub(super) fn with_sessions(mut self, sessions: Arc<Sessions>) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// 处理一条 HTTP/1.1 连接
+    ///
+    /// 上行 POST 可在同一连接上 keep-alive 复用；GET 下行与 stream-one 会独占连接直到结束。
+    pub async fn handle<T, F, Fut>(&self, stream: T, handler: F) -> Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        F: Fn(Box<dyn crate::server::AsyncStream>, crate::server::AuthReport) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        info!("XHTTP: 对端不支持 H2，使用 HTTP/1.1 chunked 传输");
+
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = RequestReader { inner: reader, buf: BytesMut::with_capacity(4096) };
+
+        loop {
+            let head = match tokio::time::timeout(IDLE_TIMEOUT, reader.read_head()).await {
+                Ok(Ok(Some(head))) => head,
+                Ok(Ok(None)) | Err(_) => return Ok(()),
+                Ok(Err(e)) => return Err(e),
+            };
+            debug!("XHTTP H1: {} {}", head.method, head.path);
+
+            if !self.config.accepts_path(&head.path) || !self.config.accepts_host(head.header("host")) {
+                if self.handle_decoy(&head, &mut reader, &mut writer).await? && head.keep_alive() {
+                    continue;
+                }
+                return Ok(());
+            }
+
+            let query = head.query.as_deref();
+            let Some(mode) = self.config.request_mode(&head.method, &head.path, query, head.header(MODE_HEADER)) else {
+                send_status(&mut writer, StatusCode::NOT_FOUND, &self.config.masquerade).await?;
+                return Ok(());
+            };
+            let session_id = self.config.session_key(&head.path, query).map(str::to_string);
+
+            // 下行会话与 stream-one 独占连接，带上请求路径 (分离会话即会话路径)
+            let span = info_span!("xhttp", method = %head.method, path = %head.path);
+            match (head.method.as_str(), mode) {
+                ("GET", RequestMode::Split) => {
+                    let Some(session_id) = session_id else {
+                        send_status(&mut writer, StatusCode::BAD_REQUEST, &self.config.masquerade).await?;
+                        return Ok(());
+                    };
+                    return self.handle_get(session_id, reader, writer, handler).instrument(span).await;
+                }
+                ("GET", RequestMode::StreamOne | RequestMode::PacketUp { .. }) => {
+                    send_status(&mut writer, StatusCode::NOT_FOUND, &self.config.masquerade).await?;
+                    return Ok(());
+                }
+                ("POST", RequestMode::StreamOne) => {
+                    return self.handle_standalone(reader, head.body()?, writer, handler).instrument(span).await;
+                }
+                ("POST", RequestMode::Split) => {
+                    let mut body = head.body()?;
+                    let user_agent = head.header("user-agent").unwrap_or("");
+                    let tx = match &session_id {
+                        Some(session_id) => self.sessions.find(session_id, user_agent).await,
+                        None => None,
+                    };
+                    let Some((tx, mut gate)) = tx else {
+                        // 未声明模式的旧客户端: 没有配对的 GET 时按 stream-one 处理
+                        if self.config.mode == XhttpMode::Auto {
+                            return self.handle_standalone(reader, body, writer, handler).instrument(span).await;
+                        }
+                        send_status(&mut writer, StatusCode::NOT_FOUND, &self.config.masquerade).await?;
+                        return Ok(());
+                    };
+                    let Some(_permit) = gate.enter() else {
+                        send_status(&mut writer, StatusCode::TOO_MANY_REQUESTS, &self.config.masquerade).await?;
+                        return Ok(());
+                    };
+
+                    let mut posted = 0u64;
+                    while let Some(chunk) = reader.read_body(&mut body).await? {
+                        // 认证未通过时不回应，直接关闭连接
+                        if !gate.admit(chunk.len()).await {
+                            return Ok(());
+                        }
+                        posted += chunk.len() as u64;
+                        if gate.exceeds_post_limit(posted) {
+                            debug!("XHTTP H1: 上行 POST 超过上限，中止");
+                            send_status(&mut writer, StatusCode::PAYLOAD_TOO_LARGE, &self.config.masquerade).await?;
+                            return Ok(());
+                        }
+                        if gate.exceeds_upload_limit(chunk.len()) {
+                            debug!("XHTTP H1: 会话上行超过上限，中止 POST");
+                            send_status(&mut writer, StatusCode::PAYLOAD_TOO_LARGE, &self.config.masquerade).await?;
+                            return Ok(());
+                        }
+                        self.metrics.add_up(chunk.len());
+                        let _ = tx.send(chunk).await;
+                    }
+
+                    self.send_upload_ok(&mut writer).await?;
+                    if !head.keep_alive() {
+                        return Ok(());
+                    }
+                }
+                ("POST", RequestMode::PacketUp { seq }) => {
+                    let mut body = head.body()?;
+                    let packets = match &session_id {
+                        Some(session_id) => self.sessions.find_packet_queue(session_id).await,
+                        None => None,
+                    };
+                    let Some((packets, mut gate)) = packets else {
+                        send_status(&mut writer, StatusCode::NOT_FOUND, &self.config.masquerade).await?;
+                        return Ok(());
+                    };
+                    let Some(_permit) = gate.enter() else {
+                        send_status(&mut writer, StatusCode::TOO_MANY_REQUESTS, &self.config.masquerade).await?;
+                        return Ok(());
+                    };
+                    if !gate.admit_packet(seq, &packets).await {
+                        return Ok(());
+                    }
+
+                    let max_bytes = packets.limits().max_each_post_bytes;
+                    let mut data = BytesMut::new();
+                    while let Some(chunk) = reader.read_body(&mut body).await? {
+                        if data.len() + chunk.len() > max_bytes {
+                            debug!("XHTTP H1 packet-up: 数据包 {} 超过 {} 字节", seq, max_bytes);
+                            send_status(&mut writer, StatusCode::PAYLOAD_TOO_LARGE, &self.config.masquerade).await?;
+                            return Ok(());
+                        }
+                        data.extend_from_slice(&chunk);
+                    }
+                    if gate.exceeds_upload_limit(data.len()) {
+                        debug!("XHTTP H1 packet-up: 会话上行超过上限，丢弃数据包 {}", seq);
+                        send_status(&mut writer, StatusCode::PAYLOAD_TOO_LARGE, &self.config.masquerade).await?;
+                        return Ok(());
+                    }
+                    self.metrics.add_up(data.len());
+                    if let Err(e) = packets.push(seq, data.freeze()).await {
+                        debug!("XHTTP H1 packet-up: {}", e);
+                        send_status(&mut writer, StatusCode::BAD_REQUEST, &self.config.masquerade).await?;
+                        return Ok(());
+                    }
+
+                    self.send_upload_ok(&mut writer).await?;
+                    if !head.keep_alive() {
+                        return Ok(());
+                    }
+                }
+                _ => {
+                    send_status(&mut writer, StatusCode::METHOD_NOT_ALLOWED, &self.config.masquerade).await?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// 非 XHTTP 路径: 伪装站点，返回连接能否继续复用
+    async fn handle_decoy<R, W>(&self, head: &RequestHead, reader: &mut RequestReader<R>, writer: &mut W) -> Result<bool>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let decoy = &self.config.decoy;
+        let masquerade = &self.config.masquerade;
+        if decoy.root.is_none() && decoy.proxy.is_none() {
+            send_status(writer, StatusCode::NOT_FOUND, masquerade).await?;
+            return Ok(false);
+        }
+
+        let mut state = head.body()?;
+        let mut body = BytesMut::new();
+        while let Some(chunk) = reader.read_body(&mut state).await? {
+            if body.len() + chunk.len() > MAX_DECOY_BODY {
+                send_status(writer, StatusCode::PAYLOAD_TOO_LARGE, masquerade).await?;
+                return Ok(false);
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        if let Some(root) = &decoy.root {
+            let page = decoy::serve_static(root, &head.method, &head.path, head.header("if-none-match")).await;
+            let mut own = page.headers;
+            if !head.keep_alive() {
+                own.push(("Connection", "close".to_string()));
+            }
+            writer.write_all(response_head(masquerade, page.status, own).as_bytes()).await?;
+            writer.write_all(&page.body).await?;
+            writer.flush().await?;
+            return Ok(true);
+        }
+
+        let Some(target) = &decoy.proxy else {
+            return Ok(false);
+        };
+        let mut headers = hyper::http::HeaderMap::new();
+        for (name, value) in &head.headers {
+            if let (Ok(name), Ok(value)) = (
+                hyper::http::HeaderName::from_bytes(name.as_bytes()),
+                hyper::http::HeaderValue::from_str(value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+        let path_and_query = match &head.query {
+            Some(query) => format!("{}?{}", head.path, query),
+            None => head.path.clone(),
+        };
+        let body = ProxyBody::Full((!body.is_empty()).then(|| body.freeze()));
+        let authority = head.header("host").unwrap_or("");
+        let upstream = match decoy::forward(target, &head.method, &path_and_query, authority, &headers, body).await {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                debug!("XHTTP H1 伪装站点 {} 不可用: {}", target, e);
+                send_status(writer, StatusCode::BAD_GATEWAY, masquerade).await?;
+                return Ok(false);
+            }
+        };
+
+        // 站点响应以关闭连接界定长度，原样流式写回
+        let (parts, mut upstream_body) = upstream.into_parts();
+        let status = parts.status;
+        let mut response = format!("HTTP/1.1 {} {}\r\n", status.as_u16(), status.canonical_reason().unwrap_or(""));
+        let mut end_to_end = hyper::http::HeaderMap::new();
+        decoy::copy_end_to_end(&parts.headers, &mut end_to_end);
+        for (name, value) in &end_to_end {
+            response.push_str(&format!("{}: {}\r\n", name, String::from_utf8_lossy(value.as_bytes())));
+        }
+        response.push_str("Connection: close\r\n\r\n");
+        writer.write_all(response.as_bytes()).await?;
+        if head.method != "HEAD" {
+            while let Some(frame) =
+                std::future::poll_fn(|cx| std::pin::Pin::new(&mut upstream_body).poll_frame(cx)).await
+            {
+                if let Ok(data) = frame?.into_data() {
+                    writer.write_all(&data).await?;
+                }
+            }
+        }
+        writer.flush().await?;
+        Ok(false)
+    }
+
+    /// 上行 POST 的空响应，连接保持以复用
+    async fn send_upload_ok<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> Result<()> {
+        let total = self.metrics.transferred();
+        let own = vec![
+            ("Cache-Control", CACHE_CONTROL.to_string()),
+            ("X-Padding", H2Handler::gen_adaptive_padding(total)),
+            ("Content-Length", "0".to_string()),
+        ];
+        let response = response_head(&self.config.masquerade, StatusCode::OK, own);
+        writer.write_all(response.as_bytes()).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// GET: 注册会话，以 chunked 响应承载下行数据
+    async fn handle_get<R, W, F, Fut>(&self, session_id: String, reader: RequestReader<R>, mut writer: W, handler: F) -> Result<()>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin,
+        F: Fn(Box<dyn crate::server::AsyncStream>, crate::server::AuthReport) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let transferred_bytes = Arc::new(AtomicUsize::new(0));
+        let registered = self.sessions.register(
+            &session_id,
+            self.config.packet_up.clone(),
+            &self.config.sessions,
+            transferred_bytes.clone(),
+        );
+        let (mut to_vless_rx, packets, guard) = match registered {
+            Ok(session) => session,
+            Err(Rejected::Duplicate) => return send_status(&mut writer, StatusCode::CONFLICT, &self.config.masquerade).await,
+            Err(Rejected::Full | Rejected::ShuttingDown) => return send_status(&mut writer, StatusCode::SERVICE_UNAVAILABLE, &self.config.masquerade).await,
+        };
+
+        let (client_io, server_io) = pipe(&self.config.pipe, &self.metrics);
+        tokio::spawn(handler(Box::new(server_io), guard.auth.clone()).in_current_span());
+        let (mut client_read, mut client_write) = tokio::io::split(client_io);
+
+        writer.write_all(stream_response_head(&self.config.masquerade, H2Handler::gen_adaptive_padding(0)).as_bytes()).await?;
+        writer.flush().await?;
+
+        let paired = guard.paired.clone();
+        let upstream = tokio::spawn(async move {
+            while let Ok(Some(chunk)) = tokio::time::timeout(IDLE_TIMEOUT, to_vless_rx.recv()).await {
+                paired.notify_one();
+                client_write.write_all(&chunk.data).await?;
+            }
+            Ok::<(), anyhow::Error>(())
+        }.in_current_span());
+
+        // GET 之后客户端不会再在此连接上发送请求，读端仅用于感知断开
+        let mut reader = reader;
+        let downstream = async {
+            let mut buf = PooledBuffer::get();
+            let deadline = guard.establish_deadline(&self.config.sessions, &self.metrics);
+            tokio::pin!(deadline);
+            loop {
+                buf.ensure_capacity();
+                let read = tokio::select! {
+                    _ = &mut deadline => break,
+                    read = tokio::time::timeout(IDLE_TIMEOUT, client_read.read_buf(&mut *buf)) => read,
+                };
+                let n = match read {
+                    Ok(Ok(n)) => n,
+                    Ok(Err(e)) => return Err(e.into()),
+                    Err(_) => {
+                        debug!("XHTTP H1 DOWN: Idle timeout (300s)");
+                        break;
+                    }
+                };
+                if n == 0 {
+                    break;
+                }
+                guard.responded.notify_one();
+                transferred_bytes.fetch_add(n, Ordering::Relaxed);
+                trace!("XHTTP H1 DOWN: {} 字节", n);
+                write_split_chunks(&mut buf, &mut writer, &self.config.shaping, &self.metrics).await?;
+            }
+            writer.write_all(b"0\r\n\r\n").await?;
+            writer.flush().await?;
+            Ok::<(), anyhow::Error>(())
+        };
+
+        let closed = async {
+            while reader.fill().await.is_ok() {
+                reader.buf.clear();
+            }
+        };
+        tokio::select! {
+            result = downstream => result?,
+            _ = closed => debug!("XHTTP H1: GET 连接被客户端关闭"),
+            _ = packets.closed() => debug!("XHTTP H1 packet-up: 上行出错，关闭会话 {}", session_id),
+        }
+
+        drop(guard);
+        drop(packets);
+        let _ = upstream.await;
+        Ok(())
+    }
+
+    /// stream-one: 请求体上行，chunked 响应下行
+    async fn handle_standalone<R, W, F, Fut>(
+        &self,
+        mut reader: RequestReader<R>,
+        mut body: Body,
+        mut writer: W,
+        handler: F,
+    ) -> Result<()>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin,
+        F: Fn(Box<dyn crate::server::AsyncStream>, crate::server::AuthReport) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let (client_io, server_io) = pipe(&self.config.pipe, &self.metrics);
+        debug!("XHTTP H1 Standard: 启动 VLESS 处理逻辑");
+        tokio::spawn(handler(Box::new(server_io), AuthReport::none()).in_current_span());
+        let (mut client_read, mut client_write) = tokio::io::split(client_io);
+
+        writer.write_all(stream_response_head(&self.config.masquerade, H2Handler::gen_adaptive_padding(0)).as_bytes()).await?;
+        writer.flush().await?;
+
+        let metrics = self.metrics.clone();
+        let sessions = self.config.sessions.clone();
+        // 上行超出总量时取消，下行随之停止并关闭连接
+        let aborted = CancellationToken::new();
+        let aborted_up = aborted.clone();
+        let up_task = tokio::spawn(async move {
+            let mut uploaded = 0u64;
+            while let Some(chunk) = reader.read_body(&mut body).await? {
+                uploaded += chunk.len() as u64;
+                if sessions.exceeds_upload_limit(uploaded) {
+                    debug!("XHTTP H1 UP: 上行超过 {} 字节，关闭连接", sessions.max_upload_bytes);
+                    aborted_up.cancel();
+                    return Ok(());
+                }
+                metrics.add_up(chunk.len());
+                client_write.write_all(&chunk).await?;
+            }
+            client_write.shutdown().await?;
+            debug!("XHTTP H1 UP: 请求体读取结束");
+            Ok::<(), anyhow::Error>(())
+        }.in_current_span());
+
+        let mut buf = PooledBuffer::get();
+        loop {
+            buf.ensure_capacity();
+            let n = tokio::select! {
+                n = client_read.read_buf(&mut *buf) => n?,
+                // 不发送结束块，客户端由此知道响应不完整
+                _ = aborted.cancelled() => return Ok(()),
+            };
+            if n == 0 {
+                break;
+            }
+            write_split_chunks(&mut buf, &mut writer, &self.config.shaping, &self.metrics).await?;
+        }
+        writer.write_all(b"0\r\n\r\n").await?;
+        writer.flush().await?;
+
+        up_task.abort();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_chunked_body_across_reads() {
+        let body = b"POST /x HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: 1\r\n\r\nGET";
+        let mock = tokio_test::io::Builder::new()
+            .read(&body[..30])
+            .read(&body[30..60])
+            .read(&body[60..])
+            .build();
+        let mut reader = RequestReader { inner: mock, buf: BytesMut::new() };
+
+        let head = reader.read_head().await.unwrap().unwrap();
+        assert_eq!((head.method.as_str(), head.path.as_str()), ("POST", "/x"));
+        let mut state = head.body().unwrap();
+        let mut data = Vec::new();
+        while let Some(chunk) = reader.read_body(&mut state).await.unwrap() {
+            data.extend_from_slice(&chunk);
+        }
+        assert_eq!(data, b"hello world");
+        // 下一个请求的数据保留在缓冲区中
+        assert_eq!(&reader.buf[..], b"GET");
+    }
+
+    #[tokio::test]
+    async fn test_content_length_body() {
+        let mock = tokio_test::io::Builder::new()
+            .read(b"POST /x?a=1 HTTP/1.1\r\ncontent-length: 4\r\nConnection: close\r\n\r\nab")
+            .read(b"cd")
+            .build();
+        let mut reader = RequestReader { inner: mock, buf: BytesMut::new() };
+
+        let head = reader.read_head().await.unwrap().unwrap();
+        assert_eq!(head.path, "/x");
+        assert!(!head.keep_alive());
+        let mut state = head.body().unwrap();
+        let mut data = Vec::new();
+        while let Some(chunk) = reader.read_body(&mut state).await.unwrap() {
+            data.extend_from_slice(&chunk);
+        }
+        assert_eq!(data, b"abcd");
+    }
+
+    /// 解析 chunked 输出，返回各块长度与拼接后的数据
+    fn parse_chunks(mut out: &[u8]) -> (Vec<usize>, Vec<u8>) {
+        let (mut sizes, mut data) = (Vec::new(), Vec::new());
+        while !out.is_empty() {
+            let line_end = out.windows(2).position(|w| w == b"\r\n").unwrap();
+            let size = usize::from_str_radix(std::str::from_utf8(&out[..line_end]).unwrap(), 16).unwrap();
+            data.extend_from_slice(&out[line_end + 2..line_end + 2 + size]);
+            sizes.push(size);
+            out = &out[line_end + 2 + size + 2..];
+        }
+        (sizes, data)
+    }
+
+    #[tokio::test]
+    async fn test_split_chunks_follow_shaping() {
+        let payload: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        let metrics = XhttpMetrics::default();
+
+        let shaping = Shaping { enabled: true, min_chunk_size: 1000, max_chunk_size: 3000 };
+        let mut out = Vec::new();
+        write_split_chunks(&mut BytesMut::from(&payload[..]), &mut out, &shaping, &metrics).await.unwrap();
+        let (sizes, data) = parse_chunks(&out);
+        assert_eq!(data, payload);
+        assert!(sizes[..sizes.len() - 1].iter().all(|&size| (1000..=3000).contains(&size)), "{:?}", sizes);
+
+        let shaping = Shaping { enabled: false, ..Shaping::default() };
+        let mut out = Vec::new();
+        write_split_chunks(&mut BytesMut::from(&payload[..]), &mut out, &shaping, &metrics).await.unwrap();
+        assert_eq!(parse_chunks(&out), (vec![payload.len()], payload.clone()));
+        assert_eq!(metrics.bytes_down.load(Ordering::Relaxed), 2 * payload.len() as u64);
+    }
+}
@@ -2,64 +2,472 @@ use anyhow::Result;
 use bytes::{Buf, Bytes, BytesMut};
 use h2::server::{self, SendResponse};
 use h2::SendStream;
-use hyper::http::{Request, Response, StatusCode};
+use hyper::body::Body;
+use hyper::http::{HeaderName, HeaderValue, Request, Response, StatusCode};
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio::sync::{mpsc, Notify};
-use tracing::{debug, info, warn, error, trace};
+use tokio::sync::{watch, Notify, OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, info_span, warn, trace, Instrument};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicUsize, AtomicU64, Ordering};
-use std::time::Duration;
-use once_cell::sync::Lazy;
-use rand::{distributions::Alphanumeric, Rng};
+use std::time::{Duration, Instant};
+use rand::Rng;
 
-use super::XhttpConfig;
+use crate::server::AuthReport;
+use super::channel::{self, UploadSender};
+use super::decoy::{self, Decoy, ProxyBody};
+use super::grpc::GrpcDecoder;
+use super::metrics::{GaugeGuard, XhttpMetrics};
+use super::packet::PacketQueue;
+use super::pipe::pipe;
+use crate::network::connection::PooledBuffer;
+use super::{H2Tuning, Masquerade, PacketUpLimits, RequestMode, SessionLimits, Shaping, XhttpConfig, XhttpMode, CACHE_CONTROL, MODE_HEADER};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 
-/// 全局会话管理器
-struct Session {
-    to_vless_tx: mpsc::UnboundedSender<Bytes>,
-    notify: Arc<Notify>,
-    transferred_bytes: Arc<AtomicUsize>,
+/// 会话表中的一个分离会话
+#[allow(dead_code)]
+pub(super) struct Session {
+    pub(super) to_vless_tx: UploadSender,
+    /// packet-up 模式的上行重组
+    pub(super) packets: Arc<PacketQueue>,
+    pub(super) notify: Arc<Notify>,
+    pub(super) transferred_bytes: Arc<AtomicUsize>,
+    /// 上行的认证闸门，所有 POST 共享
+    pub(super) gate: UploadGate,
 }
 
-static SESSIONS: Lazy<Arc<DashMap<String, Session>>> = Lazy::new(|| {
-    Arc::new(DashMap::new())
-});
+/// 一个 XHTTP 入站的分离会话表，以客户端生成的会话 ID 为键
+///
+/// 同一入站的 H2 与 HTTP/1.1 处理器共用一张表。停机信号取消后不再建立新会话，
+/// 已有会话不再接受新的 POST，表被清空；其他入站的会话不受影响。
+pub(super) struct Sessions {
+    map: DashMap<String, Session>,
+    shutdown: CancellationToken,
+}
+
+/// VLESS 认证之前上行最多转发的字节数，足以容纳 VLESS 请求头
+const PRE_AUTH_BYTES: usize = 2048;
+
+/// 分离会话上行的闸门
+///
+/// 会话的 VLESS 认证成功之前只放行请求头所需的数据，其余上行等待认证结果，
+/// 握手期限内仍未认证时 POST 端静默关闭，垃圾会话不会持续向出站处理任务灌入数据。
+/// 同时限制会话的并发 POST 数、stream-up 单个 POST 的大小与会话的上行总量。
+#[derive(Clone)]
+pub(super) struct UploadGate {
+    auth: watch::Receiver<Option<String>>,
+    pre_auth: Arc<AtomicUsize>,
+    timeout: Duration,
+    posts: Arc<Semaphore>,
+    max_stream_post_bytes: u64,
+    /// 会话所有 POST 累计的上行字节
+    uploaded: Arc<AtomicU64>,
+    max_upload_bytes: u64,
+}
+
+impl UploadGate {
+    /// 占用一个并发 POST 名额，POST 结束时释放；已达上限时返回 `None`
+    pub(super) fn enter(&self) -> Option<OwnedSemaphorePermit> {
+        let permit = self.posts.clone().try_acquire_owned().ok();
+        if permit.is_none() {
+            debug!("XHTTP: 会话的并发 POST 已达上限，拒绝上行");
+        }
+        permit
+    }
+
+    /// stream-up: 单个 POST 已转发 `posted` 字节时是否超出上限
+    pub(super) fn exceeds_post_limit(&self, posted: u64) -> bool {
+        self.max_stream_post_bytes > 0 && posted > self.max_stream_post_bytes
+    }
+
+    /// 会话累计上行再加 `len` 字节后是否超出 `maxUploadBytes`
+    pub(super) fn exceeds_upload_limit(&self, len: usize) -> bool {
+        let uploaded = self.uploaded.fetch_add(len as u64, Ordering::Relaxed) + len as u64;
+        self.max_upload_bytes > 0 && uploaded > self.max_upload_bytes
+    }
+
+    /// 转发一段 `len` 字节的上行之前调用，返回是否放行
+    pub(super) async fn admit(&mut self, len: usize) -> bool {
+        if self.auth.borrow().is_some() || self.pre_auth.fetch_add(len, Ordering::Relaxed) < PRE_AUTH_BYTES {
+            return true;
+        }
+        self.authenticated().await
+    }
+
+    /// packet-up: 序号 0 的数据包携带请求头，总是放行
+    ///
+    /// 先于它到达的数据包只进入有上限的乱序缓存，同样放行 (同一条 HTTP/1.1 连接上它们排在序号 0 之前)；
+    /// 序号 0 交付之后到达的数据包等待认证结果。
+    pub(super) async fn admit_packet(&mut self, seq: u64, packets: &PacketQueue) -> bool {
+        if seq == 0 || self.auth.borrow().is_some() || !packets.started().await {
+            return true;
+        }
+        self.authenticated().await
+    }
+
+    async fn authenticated(&mut self) -> bool {
+        let result = tokio::time::timeout(self.timeout, self.auth.wait_for(Option::is_some)).await;
+        if !matches!(result, Ok(Ok(_))) {
+            debug!("XHTTP: 会话在 {:?} 内没有通过 VLESS 认证，关闭上行", self.timeout);
+            return false;
+        }
+        true
+    }
+}
+
+/// 分离会话数 (供统计层读取)
+#[derive(Debug, Default)]
+pub struct SessionStats {
+    /// 已注册、尚未完成 VLESS 握手的会话
+    pub pending: AtomicUsize,
+    /// 已建立的会话
+    pub active: AtomicUsize,
+}
+
+/// 全局会话统计
+pub static SESSION_STATS: SessionStats = SessionStats {
+    pending: AtomicUsize::new(0),
+    active: AtomicUsize::new(0),
+};
+
+impl SessionStats {
+    /// (pending, active)
+    pub fn snapshot(&self) -> (usize, usize) {
+        (self.pending.load(Ordering::Relaxed), self.active.load(Ordering::Relaxed))
+    }
+}
 
 /// 会话守卫 (RAII Guard)
 /// 确保 Session 在离开作用域时必然被移除，防止内存泄漏
-struct SessionGuard {
-    path: String,
-    notify: Arc<Notify>,
+pub(super) struct SessionGuard {
+    sessions: Arc<Sessions>,
+    pub(super) session_id: String,
+    pub(super) notify: Arc<Notify>,
+    /// 收到第一段上行数据时通知
+    pub(super) paired: Arc<Notify>,
+    /// handler 写出第一个下行字节 (VLESS 响应头) 时通知
+    pub(super) responded: Notify,
+    /// 交给 handler 的认证回报端
+    pub(super) auth: AuthReport,
+    user: watch::Receiver<Option<String>>,
+    established: AtomicBool,
+    registered: Instant,
+}
+
+impl SessionGuard {
+    /// 会话建立的期限，超时时返回，由调用方结束下行并关闭会话
+    ///
+    /// 两个阶段都按期完成后会话计为已建立，此后不再返回。
+    pub(super) async fn establish_deadline(&self, limits: &SessionLimits, metrics: &XhttpMetrics) {
+        if tokio::time::timeout(limits.pairing_timeout, self.paired.notified()).await.is_err() {
+            debug!("XHTTP: 会话 {} 在 {:?} 内没有上行数据，关闭", self.session_id, limits.pairing_timeout);
+            return;
+        }
+        metrics.record_pairing_wait(self.registered.elapsed());
+        if tokio::time::timeout(limits.handshake_timeout, self.responded.notified()).await.is_err() {
+            debug!("XHTTP: 会话 {} 的 VLESS 握手未在 {:?} 内完成，关闭", self.session_id, limits.handshake_timeout);
+            return;
+        }
+        if !self.established.swap(true, Ordering::SeqCst) {
+            SESSION_STATS.pending.fetch_sub(1, Ordering::Relaxed);
+            SESSION_STATS.active.fetch_add(1, Ordering::Relaxed);
+            let user = self.user.borrow().clone().unwrap_or_default();
+            info!("XHTTP: 会话 {} 已建立 (用户: {})", self.session_id, user);
+        }
+        std::future::pending::<()>().await
+    }
 }
 
 impl Drop for SessionGuard {
     fn drop(&mut self) {
-        if SESSIONS.remove(&self.path).is_some() {
-            debug!("Session clean up: {}", self.path);
+        if self.sessions.map.remove(&self.session_id).is_some() {
+            debug!("Session clean up: {}", self.session_id);
         }
+        let gauge = if self.established.load(Ordering::SeqCst) { &SESSION_STATS.active } else { &SESSION_STATS.pending };
+        gauge.fetch_sub(1, Ordering::Relaxed);
         self.notify.notify_waiters();
     }
 }
 
+/// 会话注册被拒绝的原因
+pub(super) enum Rejected {
+    /// 同一会话 ID 已有下行 GET
+    Duplicate,
+    /// 待建立的会话数已达上限
+    Full,
+    /// 入站正在停机
+    ShuttingDown,
+}
+
+impl Sessions {
+    /// 停机信号取消后拒绝新会话与新的 POST
+    pub(super) fn new(shutdown: CancellationToken) -> Self {
+        Self { map: DashMap::new(), shutdown }
+    }
+
+    /// 入站已停机时清空会话表并返回 true
+    fn closed(&self) -> bool {
+        if !self.shutdown.is_cancelled() {
+            return false;
+        }
+        if !self.map.is_empty() {
+            let pending = self.map.len();
+            self.map.clear();
+            info!("XHTTP: 入站停机，清理会话: {}", pending);
+        }
+        true
+    }
+
+    /// 等待 GET 建立会话，最多 2 秒
+    async fn wait_for(&self, session_id: &str) {
+        for _ in 0..40 {
+            if self.map.contains_key(session_id) || self.shutdown.is_cancelled() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// 查找 POST 对应的下行会话
+    ///
+    /// 浏览器类客户端的 POST 可能先于 GET 到达，最多等待 2 秒配对；
+    /// Go 客户端 (PC 端) 总是先建立 GET，不必等待。
+    pub(super) async fn find(&self, session_id: &str, user_agent: &str) -> Option<(UploadSender, UploadGate)> {
+        if !user_agent.contains("Go-http-client") {
+            self.wait_for(session_id).await;
+        }
+        if self.closed() {
+            return None;
+        }
+        self.map.get(session_id).map(|s| (s.to_vless_tx.clone(), s.gate.clone()))
+    }
+
+    /// 查找 packet-up POST 所属的会话
+    ///
+    /// 客户端并发发出 GET 与第一批 POST，无论 User-Agent 都等待配对。
+    pub(super) async fn find_packet_queue(&self, session_id: &str) -> Option<(Arc<PacketQueue>, UploadGate)> {
+        self.wait_for(session_id).await;
+        if self.closed() {
+            return None;
+        }
+        self.map.get(session_id).map(|s| (s.packets.clone(), s.gate.clone()))
+    }
+
+    /// 创建会话并注册到表中，返回上行接收端、packet-up 队列与守卫
+    ///
+    /// 会话 ID 已被另一个 GET 占用时不替换已有会话；待建立的会话过多或入站正在停机时拒绝
+    pub(super) fn register(
+        self: &Arc<Self>,
+        session_id: &str,
+        packet_up: PacketUpLimits,
+        limits: &SessionLimits,
+        transferred_bytes: Arc<AtomicUsize>,
+    ) -> Result<(channel::UploadReceiver, Arc<PacketQueue>, SessionGuard), Rejected> {
+        if self.closed() {
+            return Err(Rejected::ShuttingDown);
+        }
+        let reserved = SESSION_STATS.pending.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+            (pending < limits.max_pending).then_some(pending + 1)
+        });
+        if reserved.is_err() {
+            warn!("XHTTP: 待建立的会话已达上限 ({})，拒绝会话 {}", limits.max_pending, session_id);
+            return Err(Rejected::Full);
+        }
+        let Entry::Vacant(entry) = self.map.entry(session_id.to_string()) else {
+            SESSION_STATS.pending.fetch_sub(1, Ordering::SeqCst);
+            warn!("XHTTP: 会话 {} 已有下行 GET，拒绝重复请求", session_id);
+            return Err(Rejected::Duplicate);
+        };
+        let (to_vless_tx, to_vless_rx) = channel::channel(channel::UPLOAD_BUFFER_BYTES);
+        let packets = Arc::new(PacketQueue::new(to_vless_tx.clone(), packet_up));
+        let notify = Arc::new(Notify::new());
+        let (auth, user) = AuthReport::channel();
+        let gate = UploadGate {
+            auth: user.clone(),
+            pre_auth: Arc::new(AtomicUsize::new(0)),
+            timeout: limits.handshake_timeout,
+            posts: Arc::new(Semaphore::new(limits.max_concurrent_posts)),
+            max_stream_post_bytes: limits.max_stream_post_bytes,
+            uploaded: Arc::new(AtomicU64::new(0)),
+            max_upload_bytes: limits.max_upload_bytes,
+        };
+        entry.insert(Session {
+            to_vless_tx,
+            packets: packets.clone(),
+            notify: notify.clone(),
+            transferred_bytes,
+            gate,
+        });
+        let guard = SessionGuard {
+            sessions: self.clone(),
+            session_id: session_id.to_string(),
+            notify,
+            paired: Arc::new(Notify::new()),
+            responded: Notify::new(),
+            auth,
+            user,
+            established: AtomicBool::new(false),
+            registered: Instant::now(),
+        };
+        Ok((to_vless_rx, packets, guard))
+    }
+}
+
+/// H2 PING 计数 (供统计层读取)
+#[derive(Debug, Default)]
+pub struct PingStats {
+    pub pings_sent: AtomicU64,
+    pub pings_acked: AtomicU64,
+    /// 严格保活下因 PING 未被 ACK 而关闭的连接数
+    pub keepalive_timeouts: AtomicU64,
+}
+
+/// 全局 PING 统计
+pub static PING_STATS: PingStats = PingStats {
+    pings_sent: AtomicU64::new(0),
+    pings_acked: AtomicU64::new(0),
+    keepalive_timeouts: AtomicU64::new(0),
+};
+
+impl PingStats {
+    /// (pings_sent, pings_acked, keepalive_timeouts)
+    pub fn snapshot(&self) -> (u64, u64, u64) {
+        (
+            self.pings_sent.load(Ordering::Relaxed),
+            self.pings_acked.load(Ordering::Relaxed),
+            self.keepalive_timeouts.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// H2 Ping-Pong 随机心跳混淆 (V89)
+///
+/// 在 `ping_interval_min..=ping_interval_max` 内随机间隔发送 PING，迫使客户端回复 ACK，
+/// 制造双向的背景流量噪声，干扰时序分析。每个 PING 收到 ACK 后才开始下一次间隔。
+/// 连接已断开或 (严格保活下) ACK 超时时取消 `dead`，由连接主循环关闭连接；
+/// `cancel` 被取消时结束。
+fn spawn_ping_noise(
+    mut ping_pong: h2::PingPong,
+    tuning: &H2Tuning,
+    cancel: CancellationToken,
+    dead: CancellationToken,
+) -> tokio::task::JoinHandle<()> {
+    let interval = tuning.ping_interval_min.as_millis() as u64..=tuning.ping_interval_max.as_millis() as u64;
+    let keepalive_timeout = (!tuning.keepalive_timeout.is_zero()).then_some(tuning.keepalive_timeout);
+    tokio::spawn(async move {
+        loop {
+            let sleep_ms = rand::thread_rng().gen_range(interval.clone());
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = tokio::time::sleep(Duration::from_millis(sleep_ms)) => {}
+            }
+
+            // h2 crate 限制 payload 为 opaque，主要依赖时序混淆
+            match ping_pong.send_ping(h2::Ping::opaque()) {
+                Ok(()) => {
+                    PING_STATS.pings_sent.fetch_add(1, Ordering::Relaxed);
+                    trace!("🌪️ H2 Noise: Sent random PING");
+                }
+                Err(e) if e.is_io() => {
+                    debug!("H2 PING: 连接已断开: {}", e);
+                    dead.cancel();
+                    break;
+                }
+                // 上一个 PING 仍未被 ACK: 继续等待它的 ACK 以确认连接存活
+                Err(e) => debug!("H2 PING: {}", e),
+            }
+
+            let pong = std::future::poll_fn(|cx| ping_pong.poll_pong(cx));
+            let acked = async {
+                match keepalive_timeout {
+                    Some(limit) => tokio::time::timeout(limit, pong).await.ok(),
+                    None => Some(pong.await),
+                }
+            };
+            let acked = tokio::select! {
+                _ = cancel.cancelled() => break,
+                acked = acked => acked,
+            };
+            match acked {
+                Some(Ok(_)) => {
+                    PING_STATS.pings_acked.fetch_add(1, Ordering::Relaxed);
+                }
+                Some(Err(e)) => {
+                    debug!("H2 PING: 连接已断开: {}", e);
+                    dead.cancel();
+                    break;
+                }
+                None => {
+                    PING_STATS.keepalive_timeouts.fetch_add(1, Ordering::Relaxed);
+                    debug!("H2 PING: {:?} 内未收到 ACK，关闭连接", keepalive_timeout.unwrap_or_default());
+                    dead.cancel();
+                    break;
+                }
+            }
+        }
+        trace!("H2 Noise: ping task exited");
+    }.in_current_span())
+}
+
 /// 终极 H2/XHTTP 处理器 (v0.4.1: 编译修复与告警清理版)
 #[derive(Clone)]
 pub struct H2Handler {
     config: XhttpConfig,
-    /// 流量计数器 (用于自适应优化)
-    traffic_counter: Arc<std::sync::atomic::AtomicU64>,
+    /// 连接、流与流量指标 (流量同时用于自适应 Padding)
+    metrics: Arc<XhttpMetrics>,
+    /// 停机信号: 取消后连接发送 GOAWAY 并开始排空
+    shutdown: CancellationToken,
+    /// 排空期间留给进行中的流的时间，到期后中止剩余的流
+    grace_period: Duration,
+    /// 本入站的分离会话 (与 H1Handler 共用)
+    sessions: Arc<Sessions>,
 }
 
+/// 被 drop 时中止对应的任务
+struct AbortOnDrop(tokio::task::AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// 宽限期到期、剩余的流被中止后，等待连接正常关闭的时间
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
 impl H2Handler {
     pub fn new(config: XhttpConfig) -> Self {
+        let shutdown = CancellationToken::new();
         Self { 
             config,
-            traffic_counter: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            metrics: XhttpMetrics::registered(),
+            sessions: Arc::new(Sessions::new(shutdown.clone())),
+            shutdown,
+            grace_period: Duration::from_secs(30),
         }
     }
 
+    /// 使用外部的停机信号与宽限期 (会话表随之更换，需在处理连接之前调用)
+    pub fn with_shutdown(mut self, shutdown: CancellationToken, grace_period: Duration) -> Self {
+        self.sessions = Arc::new(Sessions::new(shutdown.clone()));
+        self.shutdown = shutdown;
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// 本入站的分离会话表
+    pub(super) fn sessions(&self) -> &Arc<Sessions> {
+        &self.sessions
+    }
+
+    /// 该处理器的指标 (已登记到 `metrics::collect`)
+    pub fn metrics(&self) -> &Arc<XhttpMetrics> {
+        &self.metrics
+    }
+
     /// 自适应随机 Padding (V90: 流量敏感型)
-    fn gen_adaptive_padding(traffic: u64) -> String {
+    pub fn gen_adaptive_padding(traffic: u64) -> String {
         let mut rng = rand::thread_rng();
         
         let (min, max) = if traffic < 1048576 {
@@ -81,16 +489,22 @@ impl H2Handler {
     }
 
     /// 智能分片发送（流量整形/Shredder）
-    /// 将大数据块切分成随机大小的小块发送，消除长度特征
-    fn send_split_data(src: &mut BytesMut, send_stream: &mut SendStream<Bytes>, counter: &Arc<std::sync::atomic::AtomicU64>) -> Result<()> {
-        let mut rng = rand::thread_rng();
-        
+    /// 将大数据块切分成随机大小的小块发送，消除长度特征；关闭整形时窗口足够即整块发送
+    ///
+    /// 每块只在对端窗口放行后发送，块大小不超过获得的窗口；
+    /// 客户端停止读取时在此等待，读缓冲区不再被清空，背压传回 VLESS 侧。
+    pub async fn send_split_data(
+        src: &mut BytesMut,
+        send_stream: &mut SendStream<Bytes>,
+        shaping: &Shaping,
+        metrics: &XhttpMetrics,
+    ) -> Result<()> {
         while src.has_remaining() {
-            let chunk_size = rng.gen_range(8192..16384);
-            let split_len = std::cmp::min(src.len(), chunk_size);
+            let want = shaping.next_chunk_size().map_or(src.len(), |size| size.min(src.len()));
+            let split_len = Self::wait_capacity(send_stream, want).await?;
             
-            // 累加流量计数
-            counter.fetch_add(split_len as u64, Ordering::Relaxed);
+            // 累加下行流量
+            metrics.add_down(split_len);
 
             let chunk = src.split_to(split_len).freeze();
             send_stream.send_data(chunk, false)?;
@@ -98,63 +512,85 @@ impl H2Handler {
         Ok(())
     }
 
+    /// 按窗口发送一段不参与整形的数据 (gRPC 消息头)
+    async fn send_bounded(mut data: Bytes, send_stream: &mut SendStream<Bytes>) -> Result<()> {
+        while data.has_remaining() {
+            let len = Self::wait_capacity(send_stream, data.len()).await?;
+            send_stream.send_data(data.split_to(len), false)?;
+        }
+        Ok(())
+    }
+
+    /// 申请 `want` 字节的发送窗口，等到至少放行 1 字节，返回可发送的字节数 (不超过 `want`)
+    async fn wait_capacity(send_stream: &mut SendStream<Bytes>, want: usize) -> Result<usize> {
+        send_stream.reserve_capacity(want);
+        loop {
+            let granted = send_stream.capacity();
+            if granted > 0 {
+                return Ok(granted.min(want));
+            }
+            match std::future::poll_fn(|cx| send_stream.poll_capacity(cx)).await {
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err(anyhow::anyhow!("H2 流已关闭，无法继续发送")),
+            }
+        }
+    }
+
     pub async fn handle<T, F, Fut>(&self, stream: T, handler: F) -> Result<()>
     where
         T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
-        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + Sync + 'static,
+        F: Fn(Box<dyn crate::server::AsyncStream>, crate::server::AuthReport) -> Fut + Clone + Send + Sync + 'static,
         Fut: std::future::Future<Output = Result<()>> + Send + 'static,
     {
         info!("XHTTP: 启动 V41 拟态防御引擎 (Balanced Performance + Adaptive Memory)");
 
+        let tuning = &self.config.h2;
         let mut builder = server::Builder::new();
         builder
-            .initial_window_size(4194304)    // 4MB 窗口
-            .initial_connection_window_size(8388608) // 8MB 连接窗口
-            .max_concurrent_streams(500)
-            .max_frame_size(16384);
+            .initial_window_size(tuning.initial_window_size)
+            .initial_connection_window_size(tuning.initial_connection_window_size)
+            .max_concurrent_streams(tuning.max_concurrent_streams)
+            .max_frame_size(tuning.max_frame_size);
 
         // 使用 tokio::time::timeout 替代不存在的 handshake_timeout 方法
         let mut connection = tokio::time::timeout(
-            Duration::from_secs(20),
+            tuning.handshake_timeout,
             builder.handshake(stream)
         ).await??;
+        let _connection = GaugeGuard::enter(&self.metrics.connections);
 
         // --- 🌟 H2 Ping-Pong 随机心跳混淆 (V89) ---
-        // 获取 PingPong 句柄，启动后台任务随机发送 PING
-        // 这会迫使客户端回复 ACK，制造双向的背景流量噪声，干扰时序分析。
-        if let Some(mut ping_pong) = connection.ping_pong() {
-            tokio::spawn(async move {
-                loop {
-                    // 随机休眠 15 - 45 秒 (模拟真实心跳间隔，不要太频繁以免浪费流量)
-                    let sleep_ms = {
-                         let mut rng = rand::thread_rng();
-                         rng.gen_range(15000..45000)
-                    };
-                    tokio::time::sleep(tokio::time::Duration::from_millis(sleep_ms)).await;
-
-                    // 生成随机 8 字节载荷 (h2 crate 限制 payload 为 opaque，主要依赖时序混淆)
-                    let _payload: [u8; 8] = {
-                        let mut rng = rand::thread_rng();
-                        rng.gen()
-                    };
-                    
-                    // 发送 PING
-                    if let Err(e) = ping_pong.send_ping(h2::Ping::opaque()) {
-                        debug!("🌪️ H2 Noise: Ping failed (system busy or network jitter): {}", e);
-                        // 稳定性优化：Ping 这种辅助任务失败不应该立即拖死主循环，尝试等待后重启逻辑
-                        // tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                        break;
-                    }
-                    debug!("🌪️ H2 Noise: Sent random PING");
-                }
-            });
+        // 心跳任务随连接一起结束: 主循环退出 (含出错返回) 时 drop guard 取消令牌
+        let ping_cancel = CancellationToken::new();
+        let _ping_guard = ping_cancel.clone().drop_guard();
+        let ping_dead = CancellationToken::new();
+        if tuning.ping_enabled() {
+            if let Some(ping_pong) = connection.ping_pong() {
+                spawn_ping_noise(ping_pong, tuning, ping_cancel.clone(), ping_dead.clone());
+            }
         }
         // -------------------------------------------
         
         let active_streams = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        // 排空开始后的期限: 先是宽限期，到期中止剩余的流后再等待 CLOSE_TIMEOUT
+        let mut drain_deadline: Option<tokio::time::Instant> = None;
+        // 宽限期到期时取消，进行中的流随之中止 (h2 向客户端发送 RST_STREAM)
+        let cut_streams = CancellationToken::new();
+        // 连接结束后流已无法收发，仍在等待 VLESS 侧的流任务随之结束
+        let _cut_guard = cut_streams.clone().drop_guard();
+        let idle_timeout = self.config.h2.idle_timeout;
 
         loop {
             let is_idle = active_streams.load(Ordering::Relaxed) == 0;
+
+            if drain_deadline.is_none() && self.shutdown.is_cancelled() {
+                // 停机: 发送 GOAWAY (带最后处理的流 ID)，不再接受新流，等待现有流结束
+                connection.graceful_shutdown();
+                drain_deadline = Some(tokio::time::Instant::now() + self.grace_period);
+            }
+            let draining = drain_deadline.is_some();
+            let deadline = drain_deadline.unwrap_or_else(tokio::time::Instant::now);
             
             tokio::select! {
                 result = connection.accept() => {
@@ -162,16 +598,27 @@ impl H2Handler {
                         Some(Ok((request, respond))) => {
                             let config = self.config.clone();
                             let handler = handler.clone();
-                            let counter = self.traffic_counter.clone();
+                            let metrics = self.metrics.clone();
+                            let sessions = self.sessions.clone();
                             let active_streams_inner = active_streams.clone();
+                            let cut = cut_streams.clone();
                             
                             active_streams_inner.fetch_add(1, Ordering::Relaxed);
+                            metrics.streams.fetch_add(1, Ordering::Relaxed);
+                            // 每个流带上请求路径 (分离会话即会话路径)
+                            let span = info_span!("xhttp", method = %request.method(), path = %request.uri().path());
                             tokio::spawn(async move {
-                                if let Err(e) = Self::handle_request(config, request, respond, handler, counter).await {
-                                    debug!("连接处理闭合: {}", e);
+                                tokio::select! {
+                                    result = Self::handle_request(config, sessions, request, respond, handler, metrics.clone()) => {
+                                        if let Err(e) = result {
+                                            debug!("连接处理闭合: {}", e);
+                                        }
+                                    }
+                                    _ = cut.cancelled() => debug!("连接已关闭或停机宽限期结束，中止流"),
                                 }
                                 active_streams_inner.fetch_sub(1, Ordering::Relaxed);
-                            });
+                                metrics.streams.fetch_sub(1, Ordering::Relaxed);
+                            }.instrument(span));
                         }
                         Some(Err(e)) => {
                             debug!("H2 连接中断: {}", e);
@@ -182,11 +629,23 @@ impl H2Handler {
                 }
                 // --- 🌟 H2 Zombie Watchdog (V92) ---
                 // 如果当前没有任何活跃流 (Active Streams == 0)
-                // 且持续 300 秒没有新请求进入，则认为此连接为僵尸连接，强制关闭。
-                _ = tokio::time::sleep(Duration::from_secs(300)), if is_idle => {
-                    debug!("H2 Connection: Zombie watchdog triggered (300s idle)");
+                // 且持续 idle_timeout (默认 300 秒) 没有新请求进入，则认为此连接为僵尸连接，强制关闭。
+                // 排空期间由停机期限接管: 空闲连接发送 GOAWAY 后即关闭，无需等待
+                _ = tokio::time::sleep(idle_timeout), if is_idle && !draining => {
+                    debug!("H2 Connection: Zombie watchdog triggered ({:?} idle)", idle_timeout);
                     break;
                 }
+                _ = self.shutdown.cancelled(), if !draining => {}
+                _ = tokio::time::sleep_until(deadline), if draining => {
+                    if cut_streams.is_cancelled() {
+                        debug!("H2 连接未能在停机期限内关闭，强制断开");
+                        break;
+                    }
+                    debug!("停机宽限期 {:?} 结束，中止 {} 个流", self.grace_period, active_streams.load(Ordering::Relaxed));
+                    cut_streams.cancel();
+                    drain_deadline = Some(tokio::time::Instant::now() + CLOSE_TIMEOUT);
+                }
+                _ = ping_dead.cancelled() => break,
             }
         }
         Ok(())
@@ -194,50 +653,95 @@ impl H2Handler {
 
     async fn handle_request<F, Fut>(
         config: XhttpConfig,
+        sessions: Arc<Sessions>,
         request: Request<h2::RecvStream>,
         mut respond: SendResponse<Bytes>,
         handler: F,
-        traffic_counter: Arc<std::sync::atomic::AtomicU64>,
+        metrics: Arc<XhttpMetrics>,
     ) -> Result<()>
     where
-        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + 'static,
+        F: Fn(Box<dyn crate::server::AsyncStream>, crate::server::AuthReport) -> Fut + Clone + Send + 'static,
         Fut: std::future::Future<Output = Result<()>> + Send + 'static,
     {
         let path = request.uri().path().to_string();
-        let method = request.method();
-        
-        if !path.starts_with(&config.path) {
-            Self::send_error_response(&mut respond, StatusCode::NOT_FOUND).await?;
-            return Ok(());
+        let method = request.method().clone();
+        let masquerade = &config.masquerade;
+        // H2 请求带 :authority，经 HTTP/1.1 转换的请求可能只有 Host 头
+        let authority = match request.uri().authority() {
+            Some(authority) => Some(authority.as_str()),
+            None => request.headers().get("host").and_then(|v| v.to_str().ok()),
+        };
+
+        if !config.accepts_path(&path) || !config.accepts_host(authority) {
+            return Self::handle_decoy(request, respond, &config.decoy, masquerade).await;
         }
 
-        if method == "GET" {
-            Self::handle_xhttp_get(path, respond, handler, traffic_counter).await?;
-        } else if method == "POST" {
-            let user_agent = request.headers().get("user-agent").and_then(|v| v.to_str().ok()).unwrap_or("");
-            let is_pc = user_agent.contains("Go-http-client");
+        let query = request.uri().query();
+        let mode_header = request.headers().get(MODE_HEADER).and_then(|v| v.to_str().ok());
+        let Some(mode) = config.request_mode(method.as_str(), &path, query, mode_header) else {
+            Self::send_error_response(&mut respond, StatusCode::NOT_FOUND, masquerade).await?;
+            return Ok(());
+        };
+        let session_id = config.session_key(&path, query).map(str::to_string);
+        let content_type = request.headers().get("content-type").and_then(|v| v.to_str().ok()).unwrap_or("");
+        // 是否按 gRPC 分帧只由 Content-Type 决定，不根据内容猜测
+        let grpc = content_type.contains("grpc").then(|| GrpcDecoder::new(&config.grpc));
 
-            // 等候配对逻辑
-            if !is_pc {
-                for _ in 0..40 {
-                    let found = SESSIONS.contains_key(&path);
-                    if found { break; }
-                    tokio::time::sleep(Duration::from_millis(50)).await;
+        match (method.as_str(), mode) {
+            ("GET", RequestMode::Split) => match session_id {
+                Some(session_id) => {
+                    Self::handle_xhttp_get(session_id, &config, &sessions, respond, handler, metrics).await?;
                 }
+                None => Self::send_error_response(&mut respond, StatusCode::BAD_REQUEST, masquerade).await?,
+            },
+            ("POST", RequestMode::StreamOne) => {
+                Self::handle_standalone(request, respond, handler, grpc, &config, metrics).await?;
             }
+            ("POST", RequestMode::Split) => {
+                let user_agent = request.headers().get("user-agent").and_then(|v| v.to_str().ok()).unwrap_or("");
 
-            let session_tx = SESSIONS.get(&path).map(|s| s.to_vless_tx.clone());
-
-            if let Some(tx) = session_tx {
-                Self::handle_xhttp_post(request, respond, tx, traffic_counter).await?;
-            } else {
-                let content_type = request.headers().get("content-type").and_then(|v| v.to_str().ok()).unwrap_or("");
-                let is_grpc = content_type.contains("grpc");
-                Self::handle_standalone(request, respond, handler, is_grpc, traffic_counter).await?;
+                // 等候配对逻辑
+                let tx = match &session_id {
+                    Some(session_id) => sessions.find(session_id, user_agent).await,
+                    None => None,
+                };
+                match tx {
+                    Some((tx, gate)) => {
+                        let Some(_permit) = gate.enter() else {
+                            Self::send_error_response(&mut respond, StatusCode::TOO_MANY_REQUESTS, masquerade).await?;
+                            return Ok(());
+                        };
+                        Self::handle_xhttp_post(request, respond, tx, gate, masquerade, metrics).await?
+                    }
+                    // 未声明模式的旧客户端: 没有配对的 GET 时按 stream-one 处理
+                    None if config.mode == XhttpMode::Auto => {
+                        Self::handle_standalone(request, respond, handler, grpc, &config, metrics).await?;
+                    }
+                    None => Self::send_error_response(&mut respond, StatusCode::NOT_FOUND, masquerade).await?,
+                }
+            }
+            ("POST", RequestMode::PacketUp { seq }) => {
+                let packets = match &session_id {
+                    Some(session_id) => sessions.find_packet_queue(session_id).await,
+                    None => None,
+                };
+                match packets {
+                    Some((packets, gate)) => {
+                        let Some(_permit) = gate.enter() else {
+                            Self::send_error_response(&mut respond, StatusCode::TOO_MANY_REQUESTS, masquerade).await?;
+                            return Ok(());
+                        };
+                        Self::handle_packet_post(request, respond, seq, packets, gate, masquerade, metrics).await?
+                    }
+                    None => Self::send_error_response(&mut respond, StatusCode::NOT_FOUND, masquerade).await?,
+                }
+            }
+            ("GET", RequestMode::StreamOne | RequestMode::PacketUp { .. }) => {
+                Self::send_error_response(&mut respond, StatusCode::NOT_FOUND, masquerade).await?;
+            }
+            _ => {
+                Self::send_error_response(&mut respond, StatusCode::METHOD_NOT_ALLOWED, masquerade).await?;
             }
-        }
- else {
-            Self::send_error_response(&mut respond, StatusCode::METHOD_NOT_ALLOWED).await?;
         }
         Ok(())
     }
@@ -246,127 +750,109 @@ impl H2Handler {
         request: Request<h2::RecvStream>,
         mut respond: SendResponse<Bytes>,
         handler: F,
-        is_grpc: bool,
-        traffic_counter: Arc<std::sync::atomic::AtomicU64>,
+        mut grpc: Option<GrpcDecoder>,
+        config: &XhttpConfig,
+        metrics: Arc<XhttpMetrics>,
     ) -> Result<()>
     where
-        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + 'static,
+        F: Fn(Box<dyn crate::server::AsyncStream>, crate::server::AuthReport) -> Fut + Clone + Send + 'static,
         Fut: std::future::Future<Output = Result<()>> + Send + 'static,
     {
-        let response = Response::builder()
-            .status(StatusCode::OK)
-            .header("content-type", "application/octet-stream")
-            .header("server", "nginx/1.26.0")
-            .header("cache-control", "no-store, no-cache, must-revalidate, proxy-revalidate, max-age=0")
-            .header("x-padding", Self::gen_adaptive_padding(0)) // Standalone 通常为首包，使用全量填充
-            .body(())
-            .unwrap();
+        // Standalone 通常为首包，使用全量填充
+        let response = Self::stream_response(&config.masquerade, Self::gen_adaptive_padding(0));
 
         let mut send_stream = respond.send_response(response, false)?;
-        // 扩容核心：将内部管道从 64KB 扩大到 512KB (Zero-copy buffer)
-        // 彻底消除高带宽下载时的反向压力 (Backpressure)
-        let (client_io, server_io) = tokio::io::duplex(524288); // 512KB Buffer
+        let (client_io, server_io) = pipe(&config.pipe, &metrics);
+        let shaping = config.shaping.clone();
         
-        let use_grpc_framing = Arc::new(AtomicBool::new(is_grpc));
-        let use_grpc_framing_up = use_grpc_framing.clone();
-        let use_grpc_framing_down = use_grpc_framing.clone();
-
+        let is_grpc = grpc.is_some();
         debug!("XHTTP Standard: 启动 VLESS 处理逻辑 (is_grpc: {})", is_grpc);
-        tokio::spawn(handler(Box::new(server_io)));
+        tokio::spawn(handler(Box::new(server_io), AuthReport::none()).in_current_span());
         let (mut client_read, mut client_write) = tokio::io::split(client_io);
 
-        let traffic_counter_up = traffic_counter.clone();
-        let traffic_counter_down = traffic_counter.clone();
+        let metrics_up = metrics.clone();
+        let metrics_down = metrics;
+        // 上行不是合法的 gRPC 分帧或超出上行总量时取消，整个流随之中止
+        let aborted = CancellationToken::new();
+        let aborted_up = aborted.clone();
+        let sessions = config.sessions.clone();
 
         // UP
         let up_task = async move {
             let mut body = request.into_body();
-            let mut leftover = BytesMut::new();
             use tokio::io::AsyncWriteExt;
             debug!("XHTTP UP: 开始从请求体读取数据");
-            
-            let mut first_chunk = true;
 
             // 移除 30s 强行超时，改用更稳健的流式读取
             // 这样即使 30s 没有上行数据，连接也不会被误杀
+            let mut uploaded = 0u64;
             while let Some(chunk) = body.data().await {
                 let chunk = chunk?;
                 let len = chunk.len();
-                traffic_counter_up.fetch_add(len as u64, Ordering::Relaxed);
+                uploaded += len as u64;
+                if sessions.exceeds_upload_limit(uploaded) {
+                    aborted_up.cancel();
+                    return Err(anyhow::anyhow!("上行超过 {} 字节", sessions.max_upload_bytes));
+                }
+                metrics_up.add_up(len);
                 let _ = body.flow_control().release_capacity(len);
                 trace!("XHTTP UP: 收到 {} 字节原始数据", len);
-                
-                if first_chunk && use_grpc_framing_up.load(Ordering::Relaxed) {
-                    first_chunk = false;
-                    if len > 0 && chunk[0] != 0x00 && chunk[0] != 0x01 {
-                        warn!("XHTTP UP: 检测到首字节 ({:02x}) 非 gRPC 格式，自动回退到普通流模式", chunk[0]);
-                        use_grpc_framing_up.store(false, Ordering::Relaxed);
-                    }
-                }
 
-                if use_grpc_framing_up.load(Ordering::Relaxed) {
-                    leftover.extend_from_slice(&chunk);
-                    while leftover.len() >= 5 {
-                        let msg_len = u32::from_be_bytes([leftover[1], leftover[2], leftover[3], leftover[4]]) as usize;
-                        if msg_len > 65535 {
-                            warn!("XHTTP UP: 检测到异常消息长度 ({}), 判定为 VLESS 原始流，转回普通模式", msg_len);
-                            use_grpc_framing_up.store(false, Ordering::Relaxed);
-                            client_write.write_all(&leftover).await?;
-                            leftover.clear();
-                            break;
-                        }
-
-                        if leftover.len() >= 5 + msg_len {
-                            let _ = leftover.split_to(5);
-                            let data = leftover.split_to(msg_len);
-                            trace!("XHTTP UP: 解析到 {} 字节 gRPC 消息", data.len());
-                            client_write.write_all(&data).await?;
-                        } else { 
-                            break; 
+                match grpc.as_mut() {
+                    Some(decoder) => {
+                        decoder.push(&chunk);
+                        loop {
+                            let message = match decoder.next_message() {
+                                Ok(Some(message)) => message,
+                                Ok(None) => break,
+                                Err(e) => {
+                                    aborted_up.cancel();
+                                    return Err(e);
+                                }
+                            };
+                            trace!("XHTTP UP: 解析到 {} 字节 gRPC 消息", message.len());
+                            client_write.write_all(&message).await?;
                         }
                     }
-                } else {
-                    client_write.write_all(&chunk).await?;
+                    None => client_write.write_all(&chunk).await?,
                 }
             }
+            if grpc.as_ref().is_some_and(GrpcDecoder::has_partial) {
+                aborted_up.cancel();
+                return Err(anyhow::anyhow!("请求体在 gRPC 消息中间结束"));
+            }
+            // 请求体结束即上行半关闭，VLESS 侧读到 EOF 后下行仍可继续直到目标关闭
+            client_write.shutdown().await?;
             debug!("XHTTP UP: 请求体读取结束");
             Ok::<(), anyhow::Error>(())
         };
 
         // DOWN (使用 Traffic Shaping)
         let down_task = async move {
-            let mut buf = BytesMut::with_capacity(65536);
+            let mut buf = PooledBuffer::get();
             use tokio::io::AsyncReadExt;
             debug!("XHTTP DOWN: 开始从 VLESS 读取数据并发送给客户端");
             loop {
-                if buf.capacity() < 2048 {
-                    buf.reserve(65536);
-                }
-                let n = client_read.read_buf(&mut buf).await?;
+                buf.ensure_capacity();
+                let n = client_read.read_buf(&mut *buf).await?;
                 if n == 0 { 
                     debug!("XHTTP DOWN: VLESS 已关闭输出");
                     break; 
                 }
                 trace!("XHTTP DOWN: 从 VLESS 收到 {} 字节数据", n);
                 
-                if use_grpc_framing_down.load(Ordering::Relaxed) {
-                    let mut frame = BytesMut::with_capacity(5 + n);
-                    frame.extend_from_slice(&[0u8]);
-                    frame.extend_from_slice(&(n as u32).to_be_bytes());
-                    // copy needed here as we are framing
-                    frame.extend_from_slice(&buf[..n]);
-                    buf.advance(n);
-
-                    // 整形发送 gRPC 帧
-                    Self::send_split_data(&mut frame, &mut send_stream, &traffic_counter_down)?;
-                } else {
-                    // 整形发送普通数据流
-                    Self::send_split_data(&mut buf, &mut send_stream, &traffic_counter_down)?;
+                if is_grpc {
+                    // gRPC 消息头单独发送，消息体直接取自读缓冲区，不再拷贝
+                    let mut header = [0u8; 5];
+                    header[1..].copy_from_slice(&(n as u32).to_be_bytes());
+                    Self::send_bounded(Bytes::copy_from_slice(&header), &mut send_stream).await?;
                 }
+                // 整形发送
+                Self::send_split_data(&mut buf, &mut send_stream, &shaping, &metrics_down).await?;
             }
             
             debug!("XHTTP DOWN: 发送结束标记 (Trailers/EndStream)");
-            if use_grpc_framing_down.load(Ordering::Relaxed) {
+            if is_grpc {
                 let mut trailers = hyper::http::HeaderMap::new();
                 trailers.insert("grpc-status", "0".parse().unwrap());
                 send_stream.send_trailers(trailers)?;
@@ -380,61 +866,75 @@ impl H2Handler {
         // 必须联动：一端彻底结束或出错，另一端也该停止，释放 H2 流
         debug!("XHTTP Standalone: 启动联动传输任务");
         
-        let up_handle = tokio::spawn(up_task);
-        let _ = down_task.await;
+        let up_handle = tokio::spawn(up_task.in_current_span());
+        // 流被中止 (停机宽限期结束) 时上行任务一并结束，释放请求体，h2 才会重置这个流
+        let _abort_up = AbortOnDrop(up_handle.abort_handle());
+        tokio::select! {
+            _ = down_task => {}
+            _ = aborted.cancelled() => {
+                // 放弃下行: SendStream 未结束即被释放，h2 向客户端重置这个流
+                if let Ok(Err(e)) = up_handle.await {
+                    debug!("XHTTP UP: {}，中止流", e);
+                }
+                return Ok(());
+            }
+        }
         let _ = up_handle.await;
         
         Ok(())
     }
 
     async fn handle_xhttp_get<F, Fut>(
-        path: String,
+        session_id: String,
+        config: &XhttpConfig,
+        sessions: &Arc<Sessions>,
         mut respond: SendResponse<Bytes>,
         handler: F,
-        traffic_counter: Arc<std::sync::atomic::AtomicU64>,
+        metrics: Arc<XhttpMetrics>,
     ) -> Result<()>
     where
-        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + 'static,
+        F: Fn(Box<dyn crate::server::AsyncStream>, crate::server::AuthReport) -> Fut + Clone + Send + 'static,
         Fut: std::future::Future<Output = Result<()>> + Send + 'static,
     {
-        let (to_vless_tx, mut to_vless_rx) = mpsc::unbounded_channel::<Bytes>();
-        let notify = Arc::new(Notify::new());
         let transferred_bytes = Arc::new(AtomicUsize::new(0));
-        
-        SESSIONS.insert(path.clone(), Session { 
-            to_vless_tx, 
-            notify: notify.clone(),
-            transferred_bytes: transferred_bytes.clone(),
-        });
-        
-        // 创建守卫，确保函数退出(无论成功/失败/Panic)都会清理 Session
-        let _guard = SessionGuard { path: path.clone(), notify: notify.clone() };
+        let session_bytes = transferred_bytes.clone();
+        // 守卫确保函数退出(无论成功/失败/Panic)都会清理 Session
+        let (mut to_vless_rx, packets, guard) =
+            match sessions.register(&session_id, config.packet_up.clone(), &config.sessions, transferred_bytes.clone()) {
+                Ok(session) => session,
+                Err(rejected) => {
+                    let status = match rejected {
+                        Rejected::Duplicate => StatusCode::CONFLICT,
+                        Rejected::Full | Rejected::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+                    };
+                    Self::send_error_response(&mut respond, status, &config.masquerade).await?;
+                    return Ok(());
+                }
+            };
 
-        // 扩容核心：将内部管道从 64KB 扩大到 512KB (Zero-copy buffer)
-        let (client_io, server_io) = tokio::io::duplex(524288);
-        tokio::spawn(handler(Box::new(server_io)));
+        let (client_io, server_io) = pipe(&config.pipe, &metrics);
+        tokio::spawn(handler(Box::new(server_io), guard.auth.clone()).in_current_span());
         let (mut client_read, mut client_write) = tokio::io::split(client_io);
 
-        let response = Response::builder()
-            .status(StatusCode::OK)
-            .header("content-type", "application/octet-stream")
-            .header("server", "nginx/1.26.0")
-            .header("cache-control", "no-store, no-cache, must-revalidate, proxy-revalidate, max-age=0")
-            .header("x-padding", Self::gen_adaptive_padding(0)) // 初始响应使用 0 流量权重
-            .body(())
-            .unwrap();
+        // 初始响应使用 0 流量权重
+        let response = Self::stream_response(&config.masquerade, Self::gen_adaptive_padding(0));
         let mut send_stream = respond.send_response(response, false)?;
 
+        let session = &guard;
         let downstream = async move {
-            let mut buf = BytesMut::with_capacity(65536);
+            let mut buf = PooledBuffer::get();
             use tokio::io::AsyncReadExt;
+            let deadline = session.establish_deadline(&config.sessions, &metrics);
+            tokio::pin!(deadline);
             loop {
-                if buf.capacity() < 2048 {
-                    buf.reserve(65536);
-                }
+                buf.ensure_capacity();
                 // 加入 300秒 闲置超时 (Idle Timeout)
                 // 如果 5分钟 没有任何数据交换，主动断开回收资源
-                let n = match tokio::time::timeout(std::time::Duration::from_secs(300), client_read.read_buf(&mut buf)).await {
+                let read = tokio::select! {
+                    _ = &mut deadline => break,
+                    read = tokio::time::timeout(std::time::Duration::from_secs(300), client_read.read_buf(&mut *buf)) => read,
+                };
+                let n = match read {
                     Ok(Ok(n)) => n,
                     Ok(Err(e)) => return Err(e.into()),
                     Err(_) => {
@@ -444,24 +944,28 @@ impl H2Handler {
                 };
                 
                 if n == 0 { break; }
+                session.responded.notify_one();
                 
                 // 更新流量统计
                 transferred_bytes.fetch_add(n, Ordering::Relaxed);
                 
                 // 整形发送
-                Self::send_split_data(&mut buf, &mut send_stream, &traffic_counter)?;
+                Self::send_split_data(&mut buf, &mut send_stream, &config.shaping, &metrics).await?;
             }
             send_stream.send_data(Bytes::new(), true)?;
             Ok::<(), anyhow::Error>(())
         };
 
+        let paired = guard.paired.clone();
         let upstream = async move {
             use tokio::io::AsyncWriteExt;
             // 上行同样加入闲置超时，防止 POST 端长时间挂死
             loop {
                 match tokio::time::timeout(std::time::Duration::from_secs(300), to_vless_rx.recv()).await {
-                    Ok(Some(data)) => {
-                        client_write.write_all(&data).await?;
+                    Ok(Some(chunk)) => {
+                        paired.notify_one();
+                        // 写完后 chunk 才释放，POST 端据此获得背压
+                        client_write.write_all(&chunk.data).await?;
                     }
                     Ok(None) => break,
                     Err(_) => {
@@ -474,15 +978,19 @@ impl H2Handler {
         };
 
         // 运行任务
-        let up_handle = tokio::spawn(upstream);
-        let _ = downstream.await;
+        let up_handle = tokio::spawn(upstream.in_current_span());
+        tokio::select! {
+            _ = downstream => {}
+            _ = packets.closed() => debug!("XHTTP packet-up: 上行出错，关闭会话 {}", session_id),
+        }
         
         // 无论如何，确保从管理器移除 Session
-        SESSIONS.remove(&path);
-        notify.notify_waiters();
+        drop(guard);
+        drop(packets);
         
         // 等待上行任务结束
         let _ = up_handle.await;
+        debug!("XHTTP: 会话 {} 结束 (下行 {} 字节)", session_id, session_bytes.load(Ordering::Relaxed));
 
         Ok(())
     }
@@ -490,41 +998,264 @@ impl H2Handler {
     async fn handle_xhttp_post(
         request: Request<h2::RecvStream>,
         mut respond: SendResponse<Bytes>,
-        tx: mpsc::UnboundedSender<Bytes>,
-        traffic_counter: Arc<AtomicU64>,
+        tx: UploadSender,
+        mut gate: UploadGate,
+        masquerade: &Masquerade,
+        metrics: Arc<XhttpMetrics>,
     ) -> Result<()> {
         let mut body = request.into_body();
+        let mut posted = 0u64;
         while let Some(chunk_res) = body.data().await {
             let chunk = chunk_res?;
             let len = chunk.len();
-            traffic_counter.fetch_add(len as u64, Ordering::Relaxed);
+            if !gate.admit(len).await {
+                // 不返回状态码，直接重置流
+                respond.send_reset(h2::Reason::CANCEL);
+                return Ok(());
+            }
+            posted += len as u64;
+            if gate.exceeds_post_limit(posted) {
+                // 只结束这个 POST，会话与下行不受影响
+                debug!("XHTTP: 上行 POST 超过 {} 字节，中止", gate.max_stream_post_bytes);
+                return Self::send_error_response(&mut respond, StatusCode::PAYLOAD_TOO_LARGE, masquerade).await;
+            }
+            if gate.exceeds_upload_limit(len) {
+                // 会话的上行额度已用完，之后的 POST 同样被拒绝
+                debug!("XHTTP: 会话上行超过 {} 字节，中止 POST", gate.max_upload_bytes);
+                return Self::send_error_response(&mut respond, StatusCode::PAYLOAD_TOO_LARGE, masquerade).await;
+            }
+            metrics.add_up(len);
+            // 会话缓冲满时等待，窗口随之推迟释放，H2 流控让客户端减速
+            let _ = tx.send(chunk).await;
             let _ = body.flow_control().release_capacity(len);
-            let _ = tx.send(chunk);
         }
         
-        let total = traffic_counter.load(Ordering::Relaxed);
-
-        let response = Response::builder()
-            .status(StatusCode::OK)
-            .header("server", "nginx/1.26.0")
-            .header("cache-control", "no-store, no-cache, must-revalidate, proxy-revalidate, max-age=0")
-            .header("x-padding", Self::gen_adaptive_padding(total)) // 注入动态填充 (自适应长度)
-            .body(())
-            .unwrap();
+        let total = metrics.transferred();
+
+        // 注入动态填充 (自适应长度)
+        let response = Self::upload_response(masquerade, Self::gen_adaptive_padding(total));
         respond.send_response(response, true)?;
         Ok(())
     }
 
+    /// packet-up: 读完整个请求体后按序号交给会话
+    async fn handle_packet_post(
+        request: Request<h2::RecvStream>,
+        mut respond: SendResponse<Bytes>,
+        seq: u64,
+        packets: Arc<PacketQueue>,
+        mut gate: UploadGate,
+        masquerade: &Masquerade,
+        metrics: Arc<XhttpMetrics>,
+    ) -> Result<()> {
+        if !gate.admit_packet(seq, &packets).await {
+            respond.send_reset(h2::Reason::CANCEL);
+            return Ok(());
+        }
+        let max_bytes = packets.limits().max_each_post_bytes;
+        let mut body = request.into_body();
+        let mut data = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+            let _ = body.flow_control().release_capacity(chunk.len());
+            if data.len() + chunk.len() > max_bytes {
+                debug!("XHTTP packet-up: 数据包 {} 超过 {} 字节", seq, max_bytes);
+                return Self::send_error_response(&mut respond, StatusCode::PAYLOAD_TOO_LARGE, masquerade).await;
+            }
+            data.extend_from_slice(&chunk);
+        }
+        if gate.exceeds_upload_limit(data.len()) {
+            debug!("XHTTP packet-up: 会话上行超过 {} 字节，丢弃数据包 {}", gate.max_upload_bytes, seq);
+            return Self::send_error_response(&mut respond, StatusCode::PAYLOAD_TOO_LARGE, masquerade).await;
+        }
+        metrics.add_up(data.len());
+
+        if let Err(e) = packets.push(seq, data.freeze()).await {
+            debug!("XHTTP packet-up: {}", e);
+            return Self::send_error_response(&mut respond, StatusCode::BAD_REQUEST, masquerade).await;
+        }
+
+        let response = Self::upload_response(masquerade, Self::gen_adaptive_padding(metrics.transferred()));
+        respond.send_response(response, true)?;
+        Ok(())
+    }
+
+    /// 非 XHTTP 路径: 伪装站点
+    async fn handle_decoy(
+        request: Request<h2::RecvStream>,
+        mut respond: SendResponse<Bytes>,
+        decoy: &Decoy,
+        masquerade: &Masquerade,
+    ) -> Result<()> {
+        if let Some(root) = &decoy.root {
+            let if_none_match = request.headers().get("if-none-match").and_then(|v| v.to_str().ok());
+            let page = decoy::serve_static(root, request.method().as_str(), request.uri().path(), if_none_match).await;
+            let end_of_stream = page.body.is_empty();
+            let mut send_stream = respond.send_response(Self::response(masquerade, page.status, page.headers), end_of_stream)?;
+            if !end_of_stream {
+                Self::send_bounded(page.body, &mut send_stream).await?;
+                send_stream.send_data(Bytes::new(), true)?;
+            }
+            return Ok(());
+        }
+        let Some(target) = &decoy.proxy else {
+            return Self::send_error_response(&mut respond, StatusCode::NOT_FOUND, masquerade).await;
+        };
+
+        let (parts, body) = request.into_parts();
+        let path_and_query = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+        let authority = parts.uri.authority().map_or("", |a| a.as_str());
+        let upstream = decoy::forward(target, parts.method.as_str(), path_and_query, authority, &parts.headers, ProxyBody::H2(body)).await;
+        let upstream = match upstream {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                debug!("XHTTP 伪装站点 {} 不可用: {}", target, e);
+                return Self::send_error_response(&mut respond, StatusCode::BAD_GATEWAY, masquerade).await;
+            }
+        };
+
+        let (parts, mut body) = upstream.into_parts();
+        let mut response = Response::new(());
+        *response.status_mut() = parts.status;
+        decoy::copy_end_to_end(&parts.headers, response.headers_mut());
+        if body.is_end_stream() {
+            respond.send_response(response, true)?;
+            return Ok(());
+        }
+        let mut send_stream = respond.send_response(response, false)?;
+        // 站点的响应体原样流式转发，按对端窗口发送
+        while let Some(frame) = std::future::poll_fn(|cx| std::pin::Pin::new(&mut body).poll_frame(cx)).await {
+            let frame = frame?;
+            if frame.is_data() {
+                if let Ok(data) = frame.into_data() {
+                    Self::send_bounded(data, &mut send_stream).await?;
+                }
+            } else if let Ok(trailers) = frame.into_trailers() {
+                send_stream.send_trailers(trailers)?;
+                return Ok(());
+            }
+        }
+        send_stream.send_data(Bytes::new(), true)?;
+        Ok(())
+    }
+
     async fn send_error_response(
         respond: &mut SendResponse<Bytes>,
         status: StatusCode,
+        masquerade: &Masquerade,
     ) -> Result<()> {
-        let response = Response::builder()
-            .status(status)
-            .header("server", "nginx/1.26.0")
-            .body(())
-            .unwrap();
-        respond.send_response(response, true)?;
+        respond.send_response(Self::response(masquerade, status, Vec::new()), true)?;
         Ok(())
     }
+
+    /// 按拟态模板构造响应头
+    fn response(masquerade: &Masquerade, status: StatusCode, own: Vec<(&'static str, String)>) -> Response<()> {
+        let mut response = Response::new(());
+        *response.status_mut() = status;
+        for (name, value) in masquerade.response_headers(own) {
+            // 自定义头已在配置校验时检查，这里跳过无法编码的头而不是中断响应
+            if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value)) {
+                response.headers_mut().append(name, value);
+            }
+        }
+        response
+    }
+
+    /// 下行 (流式响应体) 的响应头
+    fn stream_response(masquerade: &Masquerade, padding: String) -> Response<()> {
+        Self::response(
+            masquerade,
+            StatusCode::OK,
+            vec![
+                ("Content-Type", "application/octet-stream".to_string()),
+                ("Cache-Control", CACHE_CONTROL.to_string()),
+                ("X-Padding", padding),
+            ],
+        )
+    }
+
+    /// 上行 POST 的空响应
+    fn upload_response(masquerade: &Masquerade, padding: String) -> Response<()> {
+        Self::response(
+            masquerade,
+            StatusCode::OK,
+            vec![("Cache-Control", CACHE_CONTROL.to_string()), ("X-Padding", padding)],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ping_task_ends_with_connection() {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let client = tokio::spawn(h2::client::handshake(client_io));
+        let mut connection = server::handshake(server_io).await.unwrap();
+        let (_client, _client_conn) = client.await.unwrap().unwrap();
+
+        let cancel = CancellationToken::new();
+        let dead = CancellationToken::new();
+        let task = spawn_ping_noise(connection.ping_pong().unwrap(), &H2Tuning::default(), cancel.clone(), dead.clone());
+        // 与 handle 相同: 连接处理结束时 guard 被 drop
+        drop(cancel.drop_guard());
+        tokio::time::timeout(Duration::from_secs(1), task)
+            .await
+            .expect("ping task outlived the connection")
+            .unwrap();
+        assert!(!dead.is_cancelled());
+    }
+
+    fn fast_pings(keepalive_ms: u64) -> H2Tuning {
+        H2Tuning {
+            ping_interval_min: Duration::from_millis(10),
+            ping_interval_max: Duration::from_millis(20),
+            keepalive_timeout: Duration::from_millis(keepalive_ms),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pings_are_acked_repeatedly() {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let client = tokio::spawn(h2::client::handshake(client_io));
+        let mut connection = server::handshake(server_io).await.unwrap();
+        let (_client, client_conn) = client.await.unwrap().unwrap();
+        // 客户端连接被驱动时自动回复 ACK，服务端连接也需要被轮询才会收发帧
+        tokio::spawn(client_conn);
+        let ping_pong = connection.ping_pong().unwrap();
+        tokio::spawn(async move { while connection.accept().await.is_some() {} });
+
+        let (_, acked_before, _) = PING_STATS.snapshot();
+        let cancel = CancellationToken::new();
+        let dead = CancellationToken::new();
+        let task = spawn_ping_noise(ping_pong, &fast_pings(500), cancel.clone(), dead.clone());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        cancel.cancel();
+        task.await.unwrap();
+
+        // 旧实现只会成功发送第一个 PING
+        assert!(PING_STATS.snapshot().1 >= acked_before + 3);
+        assert!(!dead.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_strict_keepalive_closes_silent_peer() {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let client = tokio::spawn(h2::client::handshake(client_io));
+        let mut connection = server::handshake(server_io).await.unwrap();
+        // 客户端连接不再被轮询，PING 永远得不到 ACK
+        let (_client, _client_conn) = client.await.unwrap().unwrap();
+        let ping_pong = connection.ping_pong().unwrap();
+        tokio::spawn(async move { while connection.accept().await.is_some() {} });
+
+        let cancel = CancellationToken::new();
+        let dead = CancellationToken::new();
+        let task = spawn_ping_noise(ping_pong, &fast_pings(100), cancel, dead.clone());
+        tokio::time::timeout(Duration::from_secs(2), dead.cancelled())
+            .await
+            .expect("silent peer was not detected");
+        task.await.unwrap();
+    }
 }
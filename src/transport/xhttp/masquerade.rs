@@ -0,0 +1,205 @@
+//! 响应头拟态
+//!
+//! 所有 XHTTP 响应 (包括 404/405 等错误响应) 的 Server、Date、附加头及其顺序由同一个模板决定，
+//! 使响应看起来与前置域名实际使用的服务器一致。
+
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use hyper::http::StatusCode;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// 响应头模板
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MasqueradeProfile {
+    /// `Server: nginx/1.26.0`，Server 与 Date 在最前
+    #[default]
+    Nginx,
+    /// Caddy (Go net/http): 响应头按名称排序，附带 Alt-Svc
+    Caddy,
+    /// Cloudflare 边缘: Date 在最前，末尾为 CF-Cache-Status、Server 与每个响应不同的 CF-RAY
+    Cloudflare,
+    /// 使用 `headers` 中给出的响应头
+    Custom,
+}
+
+/// 处理器自身设置的头，模板不能覆盖
+const RESERVED: [&str; 7] = [
+    "content-type",
+    "content-length",
+    "transfer-encoding",
+    "connection",
+    "cache-control",
+    "date",
+    "x-padding",
+];
+
+/// Cloudflare 机房代码，进程启动时选定一个，同一服务端的 CF-RAY 后缀保持不变
+static CF_COLO: Lazy<&'static str> = Lazy::new(|| {
+    const COLOS: [&str; 8] = ["LAX", "SJC", "SEA", "FRA", "AMS", "NRT", "HKG", "SIN"];
+    COLOS[rand::thread_rng().gen_range(0..COLOS.len())]
+});
+
+/// XHTTP 响应头拟态配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Masquerade {
+    pub profile: MasqueradeProfile,
+    /// custom 模板按顺序发送的静态头，每项为 `"名称: 值"`
+    pub headers: Vec<String>,
+    /// custom 模板是否发送 Date 头
+    pub date: bool,
+}
+
+impl Default for Masquerade {
+    fn default() -> Self {
+        Self {
+            profile: MasqueradeProfile::default(),
+            headers: Vec::new(),
+            date: true,
+        }
+    }
+}
+
+impl Masquerade {
+    /// 错误信息以配置字段名开头
+    pub fn validate(&self) -> Result<()> {
+        if self.profile != MasqueradeProfile::Custom && !self.headers.is_empty() {
+            return Err(anyhow!("headers: 仅 custom 模板可以设置"));
+        }
+        for (i, line) in self.headers.iter().enumerate() {
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(anyhow!("headers[{}]: 应为 \"名称: 值\" (当前为 {:?})", i, line));
+            };
+            let name = hyper::http::HeaderName::from_bytes(name.trim().as_bytes())
+                .map_err(|_| anyhow!("headers[{}]: 非法的头名称 {:?}", i, name))?;
+            if RESERVED.contains(&name.as_str()) {
+                return Err(anyhow!("headers[{}]: {} 由服务端设置，不能自定义", i, name));
+            }
+            hyper::http::HeaderValue::from_str(value.trim())
+                .map_err(|_| anyhow!("headers[{}]: 非法的头取值 {:?}", i, value))?;
+        }
+        Ok(())
+    }
+
+    /// HTTP/1.1 状态行中的原因短语，nginx 对部分状态码使用自己的写法
+    pub fn reason_phrase(&self, status: StatusCode) -> &'static str {
+        let nginx = match status {
+            StatusCode::METHOD_NOT_ALLOWED => Some("Not Allowed"),
+            StatusCode::PAYLOAD_TOO_LARGE => Some("Request Entity Too Large"),
+            StatusCode::SERVICE_UNAVAILABLE => Some("Service Temporarily Unavailable"),
+            _ => None,
+        };
+        match nginx {
+            Some(reason) if self.profile == MasqueradeProfile::Nginx => reason,
+            _ => status.canonical_reason().unwrap_or(""),
+        }
+    }
+
+    /// 按模板合并出完整的响应头 (名称为 HTTP/1.1 的大小写形式)
+    ///
+    /// `own` 为处理器自身的头 (Content-Type、Cache-Control、X-Padding 等)，模板决定它们与拟态头的相对顺序。
+    pub fn response_headers(&self, own: Vec<(&'static str, String)>) -> Vec<(String, String)> {
+        let own = own.into_iter().map(|(name, value)| (name.to_string(), value));
+        let mut headers: Vec<(String, String)> = Vec::new();
+        match self.profile {
+            MasqueradeProfile::Nginx => {
+                headers.push(("Server".into(), "nginx/1.26.0".into()));
+                headers.push(("Date".into(), http_date()));
+                headers.extend(own);
+            }
+            MasqueradeProfile::Caddy => {
+                headers.push(("Alt-Svc".into(), "h3=\":443\"; ma=2592000".into()));
+                headers.push(("Date".into(), http_date()));
+                headers.push(("Server".into(), "Caddy".into()));
+                headers.extend(own);
+                headers.sort_by_key(|(name, _)| name.to_ascii_lowercase());
+            }
+            MasqueradeProfile::Cloudflare => {
+                headers.push(("Date".into(), http_date()));
+                headers.extend(own);
+                headers.push(("CF-Cache-Status".into(), "DYNAMIC".into()));
+                headers.push(("Server".into(), "cloudflare".into()));
+                headers.push(("CF-RAY".into(), cf_ray()));
+            }
+            MasqueradeProfile::Custom => {
+                if self.date {
+                    headers.push(("Date".into(), http_date()));
+                }
+                headers.extend(self.headers.iter().filter_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    Some((name.trim().to_string(), value.trim().to_string()))
+                }));
+                headers.extend(own);
+            }
+        }
+        headers
+    }
+}
+
+fn http_date() -> String {
+    httpdate::fmt_http_date(SystemTime::now())
+}
+
+/// 16 位十六进制请求 ID + 机房代码
+fn cf_ray() -> String {
+    format!("{:016x}-{}", rand::thread_rng().gen::<u64>(), *CF_COLO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(masquerade: &Masquerade) -> Vec<String> {
+        let own = vec![("Content-Type", "application/octet-stream".to_string()), ("X-Padding", "abc".to_string())];
+        masquerade.response_headers(own).into_iter().map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn test_profile_header_order() {
+        let profile = |profile| Masquerade { profile, ..Default::default() };
+        assert_eq!(names(&profile(MasqueradeProfile::Nginx)), ["Server", "Date", "Content-Type", "X-Padding"]);
+        assert_eq!(
+            names(&profile(MasqueradeProfile::Caddy)),
+            ["Alt-Svc", "Content-Type", "Date", "Server", "X-Padding"]
+        );
+        assert_eq!(
+            names(&profile(MasqueradeProfile::Cloudflare)),
+            ["Date", "Content-Type", "X-Padding", "CF-Cache-Status", "Server", "CF-RAY"]
+        );
+
+        let custom = Masquerade {
+            profile: MasqueradeProfile::Custom,
+            headers: vec!["Server: Apache".into(), "X-Powered-By: PHP/8.2".into()],
+            date: false,
+        };
+        assert_eq!(names(&custom), ["Server", "X-Powered-By", "Content-Type", "X-Padding"]);
+        let headers = custom.response_headers(Vec::new());
+        assert_eq!(headers[0], ("Server".to_string(), "Apache".to_string()));
+
+        let nginx = profile(MasqueradeProfile::Nginx);
+        assert_eq!(nginx.reason_phrase(StatusCode::METHOD_NOT_ALLOWED), "Not Allowed");
+        assert_eq!(nginx.reason_phrase(StatusCode::NOT_FOUND), "Not Found");
+        assert_eq!(profile(MasqueradeProfile::Caddy).reason_phrase(StatusCode::METHOD_NOT_ALLOWED), "Method Not Allowed");
+
+        let ray = cf_ray();
+        assert_eq!(ray.len(), 20);
+        assert_ne!(ray, cf_ray());
+    }
+
+    #[test]
+    fn test_validate_custom_headers() {
+        assert!(Masquerade::default().validate().is_ok());
+
+        let with = |profile, line: &str| Masquerade { profile, headers: vec![line.to_string()], date: true };
+        assert!(with(MasqueradeProfile::Custom, "Server: Apache").validate().is_ok());
+        assert!(with(MasqueradeProfile::Nginx, "Server: Apache").validate().unwrap_err().to_string().starts_with("headers:"));
+        for line in ["Server Apache", "Bad Name: x", "Content-Length: 0", "X-Padding: 1", "Server: a\u{7f}"] {
+            let err = with(MasqueradeProfile::Custom, line).validate().unwrap_err().to_string();
+            assert!(err.starts_with("headers[0]"), "{}: {}", line, err);
+        }
+    }
+}
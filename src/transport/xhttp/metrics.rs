@@ -0,0 +1,169 @@
+//! XHTTP 传输层指标
+//!
+//! 每个入站 (XhttpServer) 持有一份 [`XhttpMetrics`]，H2 与 HTTP/1.1 处理器共享，
+//! 计数器都是在数据本来经过的位置累加的原子变量。创建时登记到全局表，
+//! [`collect`] 汇总所有仍存活的入站。
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use super::h2::SESSION_STATS;
+
+/// 配对等待时间分布的桶上限 (毫秒)，最后一个桶收纳超出的部分
+pub const PAIRING_WAIT_BUCKETS_MS: [u64; 8] = [10, 50, 100, 250, 500, 1000, 2000, 5000];
+
+/// 一个入站的 XHTTP 指标
+#[derive(Debug, Default)]
+pub struct XhttpMetrics {
+    /// 活跃的 H2 连接
+    pub connections: AtomicUsize,
+    /// 活跃的 H2 流
+    pub streams: AtomicUsize,
+    /// 客户端上行字节 (POST 请求体)
+    pub bytes_up: AtomicU64,
+    /// 发往客户端的下行字节
+    pub bytes_down: AtomicU64,
+    /// 与 VLESS 处理之间的管道容量之和 (字节)
+    pub pipe_bytes: AtomicUsize,
+    /// 分离会话从 GET 注册到收到第一段上行的等待时间分布
+    pairing_wait: [AtomicU64; PAIRING_WAIT_BUCKETS_MS.len() + 1],
+}
+
+/// 指标快照
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XhttpMetricsSnapshot {
+    pub connections: usize,
+    pub streams: usize,
+    /// 待建立的会话 (全局计数，见 `SESSION_STATS`)
+    pub pending_sessions: usize,
+    /// 已建立的会话 (全局计数)
+    pub paired_sessions: usize,
+    pub bytes_up: u64,
+    pub bytes_down: u64,
+    pub pipe_bytes: usize,
+    /// 与 [`PAIRING_WAIT_BUCKETS_MS`] 对应的各桶计数，多出的最后一项为超出最大上限的次数
+    pub pairing_wait: Vec<u64>,
+}
+
+static REGISTRY: Lazy<Mutex<Vec<Weak<XhttpMetrics>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+impl XhttpMetrics {
+    /// 创建并登记到全局表
+    pub fn registered() -> Arc<Self> {
+        let metrics = Arc::new(Self::default());
+        let mut registry = REGISTRY.lock().unwrap();
+        registry.retain(|m| m.strong_count() > 0);
+        registry.push(Arc::downgrade(&metrics));
+        metrics
+    }
+
+    pub(super) fn add_up(&self, len: usize) {
+        self.bytes_up.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn add_down(&self, len: usize) {
+        self.bytes_down.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    /// 上下行合计 (自适应 Padding 的流量权重)
+    pub(super) fn transferred(&self) -> u64 {
+        self.bytes_up.load(Ordering::Relaxed) + self.bytes_down.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn record_pairing_wait(&self, wait: Duration) {
+        let ms = wait.as_millis() as u64;
+        let bucket = PAIRING_WAIT_BUCKETS_MS.iter().position(|&le| ms <= le).unwrap_or(PAIRING_WAIT_BUCKETS_MS.len());
+        self.pairing_wait[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> XhttpMetricsSnapshot {
+        let (pending_sessions, paired_sessions) = SESSION_STATS.snapshot();
+        XhttpMetricsSnapshot {
+            connections: self.connections.load(Ordering::Relaxed),
+            streams: self.streams.load(Ordering::Relaxed),
+            pending_sessions,
+            paired_sessions,
+            bytes_up: self.bytes_up.load(Ordering::Relaxed),
+            bytes_down: self.bytes_down.load(Ordering::Relaxed),
+            pipe_bytes: self.pipe_bytes.load(Ordering::Relaxed),
+            pairing_wait: self.pairing_wait.iter().map(|c| c.load(Ordering::Relaxed)).collect(),
+        }
+    }
+}
+
+/// 汇总所有入站的指标
+pub fn collect() -> XhttpMetricsSnapshot {
+    let (pending_sessions, paired_sessions) = SESSION_STATS.snapshot();
+    let mut total = XhttpMetricsSnapshot {
+        pending_sessions,
+        paired_sessions,
+        pairing_wait: vec![0; PAIRING_WAIT_BUCKETS_MS.len() + 1],
+        ..Default::default()
+    };
+    let registry = REGISTRY.lock().unwrap();
+    for metrics in registry.iter().filter_map(Weak::upgrade) {
+        let snapshot = metrics.snapshot();
+        total.connections += snapshot.connections;
+        total.streams += snapshot.streams;
+        total.bytes_up += snapshot.bytes_up;
+        total.bytes_down += snapshot.bytes_down;
+        total.pipe_bytes += snapshot.pipe_bytes;
+        for (sum, count) in total.pairing_wait.iter_mut().zip(snapshot.pairing_wait) {
+            *sum += count;
+        }
+    }
+    total
+}
+
+/// 计数加一，drop 时减一
+pub(super) struct GaugeGuard<'a>(&'a AtomicUsize);
+
+impl<'a> GaugeGuard<'a> {
+    pub(super) fn enter(gauge: &'a AtomicUsize) -> Self {
+        gauge.fetch_add(1, Ordering::Relaxed);
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pairing_wait_buckets() {
+        let metrics = XhttpMetrics::default();
+        metrics.record_pairing_wait(Duration::from_millis(3));
+        metrics.record_pairing_wait(Duration::from_millis(10));
+        metrics.record_pairing_wait(Duration::from_millis(300));
+        metrics.record_pairing_wait(Duration::from_secs(9));
+        assert_eq!(metrics.snapshot().pairing_wait, vec![2, 0, 0, 0, 1, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_collect_sums_registered() {
+        let a = XhttpMetrics::registered();
+        let b = XhttpMetrics::registered();
+        a.add_up(100);
+        b.add_down(40);
+        let stream = GaugeGuard::enter(&b.streams);
+        a.record_pairing_wait(Duration::from_millis(60));
+
+        // 其他测试可能同时登记了指标，只检查下限
+        let total = collect();
+        assert!(total.bytes_up >= 100 && total.bytes_down >= 40 && total.streams >= 1);
+        assert!(total.pairing_wait[2] >= 1);
+
+        drop(stream);
+        assert_eq!(b.snapshot().streams, 0);
+        assert_eq!(a.transferred(), 100);
+    }
+}
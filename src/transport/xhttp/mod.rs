@@ -1,12 +1,29 @@
+mod channel;
+mod decoy;
 mod grpc;
+mod h1;
 mod h2;
+mod masquerade;
+pub mod metrics;
+mod packet;
+mod pipe;
 mod server;
 
-pub use grpc::{GrpcHeaders, GrpcMessage, GrpcStatus, GrpcTrailer};
-pub use h2::H2Handler;
+pub use decoy::Decoy;
+pub use grpc::{GrpcDecoder, GrpcHeaders, GrpcLimits, GrpcMessage, GrpcStatus, GrpcTrailer};
+pub use h1::H1Handler;
+pub use h2::{H2Handler, PingStats, SessionStats, PING_STATS, SESSION_STATS};
+pub use masquerade::{Masquerade, MasqueradeProfile};
+pub use metrics::{XhttpMetrics, XhttpMetricsSnapshot};
+pub use pipe::PipeSizing;
 pub use server::XhttpServer;
 
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use tracing::debug;
 
 /// XHTTP 模式
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -18,8 +35,10 @@ pub enum XhttpMode {
     StreamUp,
     /// 流式下载
     StreamDown,
-    /// 单向流
+    /// 单个 POST 同时承载上下行
     StreamOne,
+    /// 每个上行数据包是一个带序号的 POST
+    PacketUp,
 }
 
 impl XhttpMode {
@@ -29,6 +48,7 @@ impl XhttpMode {
             XhttpMode::StreamUp => "stream-up",
             XhttpMode::StreamDown => "stream-down",
             XhttpMode::StreamOne => "stream-one",
+            XhttpMode::PacketUp => "packet-up",
         }
     }
 }
@@ -48,4 +68,602 @@ pub struct XhttpConfig {
     pub path: String,
     /// Host 头
     pub host: String,
+    /// 允许的 Host / `:authority`，为空时不检查；不匹配的请求交给伪装站点
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// H2 服务端参数
+    #[serde(default)]
+    pub h2: H2Tuning,
+    /// packet-up 上行重组的限制
+    #[serde(default)]
+    pub packet_up: PacketUpLimits,
+    /// 分离会话的建立期限
+    #[serde(default)]
+    pub sessions: SessionLimits,
+    /// 响应头拟态
+    #[serde(default)]
+    pub masquerade: Masquerade,
+    /// 非 XHTTP 路径请求的伪装站点
+    #[serde(default)]
+    pub decoy: Decoy,
+    /// stream-one gRPC 分帧上行的限制
+    #[serde(default)]
+    pub grpc: GrpcLimits,
+    /// 下行随机分块整形
+    #[serde(default)]
+    pub shaping: Shaping,
+    /// 与 VLESS 处理之间的管道容量
+    #[serde(default)]
+    pub pipe: PipeSizing,
+}
+
+impl Default for XhttpConfig {
+    /// 与配置文件省略 `xhttpSettings` 各字段时一致: auto 模式，路径 `/`，不校验 Host
+    fn default() -> Self {
+        Self {
+            mode: XhttpMode::Auto,
+            path: "/".to_string(),
+            host: String::new(),
+            hosts: Vec::new(),
+            h2: H2Tuning::default(),
+            packet_up: PacketUpLimits::default(),
+            sessions: SessionLimits::default(),
+            masquerade: Masquerade::default(),
+            decoy: Decoy::default(),
+            grpc: GrpcLimits::default(),
+            shaping: Shaping::default(),
+            pipe: PipeSizing::default(),
+        }
+    }
+}
+
+/// 流量控制窗口上限 (RFC 9113 6.9.1)
+pub const MAX_WINDOW_SIZE: u32 = (1 << 31) - 1;
+/// 连接窗口初始值，只能调大 (RFC 9113 6.9.2)
+pub const DEFAULT_CONNECTION_WINDOW_SIZE: u32 = 65_535;
+/// SETTINGS_MAX_FRAME_SIZE 的合法范围 (RFC 9113 6.5.2)
+pub const MIN_FRAME_SIZE: u32 = 16_384;
+pub const MAX_FRAME_SIZE: u32 = (1 << 24) - 1;
+
+/// H2 服务端参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct H2Tuning {
+    /// 流窗口
+    pub initial_window_size: u32,
+    /// 连接窗口
+    pub initial_connection_window_size: u32,
+    /// 单连接最大并发流数
+    pub max_concurrent_streams: u32,
+    /// 最大帧长度
+    pub max_frame_size: u32,
+    /// H2 握手超时
+    pub handshake_timeout: Duration,
+    /// 无活跃流时的空闲超时 (僵尸连接回收)
+    pub idle_timeout: Duration,
+    /// 随机 PING 间隔下限
+    pub ping_interval_min: Duration,
+    /// 随机 PING 间隔上限，为 0 时不发送 PING
+    pub ping_interval_max: Duration,
+    /// 严格保活: PING 在此时间内未收到 ACK 即关闭连接，为 0 时不启用
+    pub keepalive_timeout: Duration,
+}
+
+impl Default for H2Tuning {
+    fn default() -> Self {
+        Self {
+            initial_window_size: 4 * 1024 * 1024,
+            initial_connection_window_size: 8 * 1024 * 1024,
+            max_concurrent_streams: 500,
+            max_frame_size: MIN_FRAME_SIZE,
+            handshake_timeout: Duration::from_secs(20),
+            idle_timeout: Duration::from_secs(300),
+            ping_interval_min: Duration::from_secs(15),
+            ping_interval_max: Duration::from_secs(45),
+            keepalive_timeout: Duration::ZERO,
+        }
+    }
+}
+
+impl H2Tuning {
+    /// 检查参数是否在 H2 允许的范围内，错误信息以配置字段名开头
+    pub fn validate(&self) -> Result<()> {
+        if self.initial_window_size == 0 || self.initial_window_size > MAX_WINDOW_SIZE {
+            return Err(anyhow!(
+                "initialWindowSize: 必须在 1..={} 之间 (当前为 {})",
+                MAX_WINDOW_SIZE,
+                self.initial_window_size
+            ));
+        }
+        if !(DEFAULT_CONNECTION_WINDOW_SIZE..=MAX_WINDOW_SIZE).contains(&self.initial_connection_window_size) {
+            return Err(anyhow!(
+                "initialConnectionWindowSize: 必须在 {}..={} 之间 (当前为 {})",
+                DEFAULT_CONNECTION_WINDOW_SIZE,
+                MAX_WINDOW_SIZE,
+                self.initial_connection_window_size
+            ));
+        }
+        if self.max_concurrent_streams == 0 {
+            return Err(anyhow!("maxConcurrentStreams: 必须大于 0"));
+        }
+        if !(MIN_FRAME_SIZE..=MAX_FRAME_SIZE).contains(&self.max_frame_size) {
+            return Err(anyhow!(
+                "maxFrameSize: 必须在 {}..={} 之间 (当前为 {})",
+                MIN_FRAME_SIZE,
+                MAX_FRAME_SIZE,
+                self.max_frame_size
+            ));
+        }
+        if self.handshake_timeout.is_zero() {
+            return Err(anyhow!("handshakeTimeout: 必须大于 0"));
+        }
+        if self.idle_timeout.is_zero() {
+            return Err(anyhow!("idleTimeout: 必须大于 0"));
+        }
+        if self.ping_enabled() {
+            if self.ping_interval_min.is_zero() || self.ping_interval_min > self.ping_interval_max {
+                return Err(anyhow!(
+                    "pingIntervalMin: 必须在 1..={} 之间 (当前为 {})",
+                    self.ping_interval_max.as_secs(),
+                    self.ping_interval_min.as_secs()
+                ));
+            }
+        } else if !self.keepalive_timeout.is_zero() {
+            return Err(anyhow!("keepaliveTimeout: 需要启用 PING (pingIntervalMax 大于 0)"));
+        }
+        Ok(())
+    }
+
+    /// 是否发送随机 PING
+    pub fn ping_enabled(&self) -> bool {
+        !self.ping_interval_max.is_zero()
+    }
+}
+
+/// packet-up 上行重组的限制 (每个会话)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacketUpLimits {
+    /// 重组窗口: 最多领先已交付位置多少个序号
+    pub max_buffered_posts: usize,
+    /// 单个 POST 的最大字节数
+    pub max_each_post_bytes: usize,
+    /// 乱序缓存的最大字节数
+    pub max_buffered_bytes: usize,
+    /// 缺失的数据包在此时间内未到达时关闭会话
+    pub gap_timeout: Duration,
+}
+
+impl Default for PacketUpLimits {
+    fn default() -> Self {
+        // 前两项与 xray-core 的 scMaxBufferedPosts / scMaxEachPostBytes 默认值一致
+        Self {
+            max_buffered_posts: 30,
+            max_each_post_bytes: 1_000_000,
+            max_buffered_bytes: 8 * 1024 * 1024,
+            gap_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl PacketUpLimits {
+    /// 错误信息以配置字段名开头
+    pub fn validate(&self) -> Result<()> {
+        if self.max_buffered_posts == 0 {
+            return Err(anyhow!("maxBufferedPosts: 必须大于 0"));
+        }
+        if self.max_each_post_bytes == 0 {
+            return Err(anyhow!("maxEachPostBytes: 必须大于 0"));
+        }
+        if self.max_buffered_bytes < self.max_each_post_bytes {
+            return Err(anyhow!(
+                "maxBufferedBytes: 不能小于 maxEachPostBytes ({} < {})",
+                self.max_buffered_bytes,
+                self.max_each_post_bytes
+            ));
+        }
+        if self.gap_timeout.is_zero() {
+            return Err(anyhow!("gapTimeout: 必须大于 0"));
+        }
+        Ok(())
+    }
+}
+
+/// 分离会话的建立限制
+///
+/// VLESS 认证要等 GET 配对之后才进行，这之前的会话谁都可以建立。待建立的会话数设上限，
+/// 并要求 GET 之后 `pairing_timeout` 内收到上行数据、其后 `handshake_timeout` 内 VLESS 握手完成，
+/// 否则关闭会话。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionLimits {
+    /// 待建立 (尚未完成 VLESS 握手) 的会话数上限，所有 XHTTP 入站共享
+    pub max_pending: usize,
+    /// GET 之后等待上行数据的时间
+    pub pairing_timeout: Duration,
+    /// 收到上行数据后等待 VLESS 握手完成的时间
+    pub handshake_timeout: Duration,
+    /// 每个会话同时进行的上行 POST 数上限 (对应 xray-core 的 scMaxConcurrentPosts)
+    pub max_concurrent_posts: usize,
+    /// stream-up 单个上行 POST 的最大字节数，0 为不限制 (packet-up 见 `PacketUpLimits::max_each_post_bytes`)
+    pub max_stream_post_bytes: u64,
+    /// 上行总字节数上限: stream-one 按单个流计，分离模式按整个会话的所有 POST 累计，0 为不限制
+    pub max_upload_bytes: u64,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        Self {
+            max_pending: 1024,
+            pairing_timeout: Duration::from_secs(10),
+            handshake_timeout: Duration::from_secs(10),
+            max_concurrent_posts: 100,
+            max_stream_post_bytes: 0,
+            max_upload_bytes: 0,
+        }
+    }
+}
+
+impl SessionLimits {
+    /// 错误信息以配置字段名开头
+    pub fn validate(&self) -> Result<()> {
+        if self.max_pending == 0 {
+            return Err(anyhow!("maxPending: 必须大于 0"));
+        }
+        if self.pairing_timeout.is_zero() {
+            return Err(anyhow!("pairingTimeout: 必须大于 0"));
+        }
+        if self.handshake_timeout.is_zero() {
+            return Err(anyhow!("handshakeTimeout: 必须大于 0"));
+        }
+        if self.max_concurrent_posts == 0 {
+            return Err(anyhow!("maxConcurrentPosts: 必须大于 0"));
+        }
+        Ok(())
+    }
+
+    /// 累计上行 `uploaded` 字节时是否超出 `max_upload_bytes`
+    pub(super) fn exceeds_upload_limit(&self, uploaded: u64) -> bool {
+        self.max_upload_bytes > 0 && uploaded > self.max_upload_bytes
+    }
+}
+
+/// 下行整形: 把每次读到的数据切成随机大小的块发送，消除长度特征
+///
+/// 关闭后按发送窗口整块发送，减少 CPU 与系统调用开销，适合不需要整形的场景。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Shaping {
+    pub enabled: bool,
+    /// 分块大小的范围 (字节，含两端)
+    pub min_chunk_size: usize,
+    pub max_chunk_size: usize,
+}
+
+impl Default for Shaping {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_chunk_size: 8192,
+            max_chunk_size: 16384,
+        }
+    }
+}
+
+impl Shaping {
+    /// 错误信息以配置字段名开头
+    pub fn validate(&self) -> Result<()> {
+        if self.min_chunk_size == 0 {
+            return Err(anyhow!("minChunkSize: 必须大于 0"));
+        }
+        if self.max_chunk_size < self.min_chunk_size {
+            return Err(anyhow!(
+                "maxChunkSize: 不能小于 minChunkSize ({} < {})",
+                self.max_chunk_size,
+                self.min_chunk_size
+            ));
+        }
+        Ok(())
+    }
+
+    /// 下一块的大小，关闭整形时为 `None` (不分块)
+    pub(crate) fn next_chunk_size(&self) -> Option<usize> {
+        self.enabled.then(|| rand::thread_rng().gen_range(self.min_chunk_size..=self.max_chunk_size))
+    }
+}
+
+/// 所有 XHTTP 成功响应使用的 Cache-Control，防止 CDN 缓存
+pub(crate) const CACHE_CONTROL: &str = "no-store, no-cache, must-revalidate, proxy-revalidate, max-age=0";
+
+/// 客户端显式选择模式的请求头 (`stream-one` / `stream-up`)
+pub const MODE_HEADER: &str = "x-xhttp-mode";
+
+/// 单个请求采用的传输方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestMode {
+    /// 单个 POST 在同一条流上双向传输
+    StreamOne,
+    /// GET 下行 + POST 上行，按路径中的会话 ID 配对
+    Split,
+    /// 发往 `{path}/{会话 ID}/{序号}` 的单个上行数据包，会话由 GET 建立
+    PacketUp { seq: u64 },
+}
+
+impl XhttpConfig {
+    /// 确定请求的传输方式，服务端模式不允许时返回 `None`
+    ///
+    /// `X-Xhttp-Mode` 请求头优先；否则不带会话 ID (路径恰为配置路径且没有 `x-session-id` 查询参数) 时为 stream-one，
+    /// 会话 ID 之后带序号时为 packet-up。服务端为 stream-one 模式时所有请求都按 stream-one 处理。
+    /// 与 xray-core 一致，stream-up 与 packet-up 服务端只接受各自的上行 POST，下行 GET 两者通用。
+    pub fn request_mode(
+        &self,
+        method: &str,
+        path: &str,
+        query: Option<&str>,
+        mode_header: Option<&str>,
+    ) -> Option<RequestMode> {
+        let requested = match mode_header.map(str::trim) {
+            Some(mode) if mode.eq_ignore_ascii_case("stream-one") => RequestMode::StreamOne,
+            Some(mode) if mode.eq_ignore_ascii_case("stream-up") => RequestMode::Split,
+            _ if self.session_path(path).is_empty() && query_session(query).is_none() => RequestMode::StreamOne,
+            _ => match self.packet_seq(path) {
+                Some(seq) => RequestMode::PacketUp { seq },
+                None => RequestMode::Split,
+            },
+        };
+        match (&self.mode, requested) {
+            (XhttpMode::Auto, mode) => Some(mode),
+            (XhttpMode::StreamOne, _) => Some(RequestMode::StreamOne),
+            (_, RequestMode::StreamOne) => None,
+            (XhttpMode::PacketUp, RequestMode::Split) if method == "POST" => None,
+            (XhttpMode::PacketUp, mode) => Some(mode),
+            (_, RequestMode::PacketUp { .. }) => None,
+            (_, RequestMode::Split) => Some(RequestMode::Split),
+        }
+    }
+
+    /// 配对 GET 与上行 POST 的会话 ID
+    ///
+    /// 取配置路径之后的第一段，路径中没有时取 `x-session-id` 查询参数。
+    /// 配置路径来自服务端，所有客户端共用，因此只能以会话 ID 区分会话；不是合法 ID 时返回 `None`。
+    pub(super) fn session_key<'a>(&self, path: &'a str, query: Option<&'a str>) -> Option<&'a str> {
+        let id = match self.session_path(path).split('/').next() {
+            Some(segment) if !segment.is_empty() => segment,
+            _ => query_session(query)?,
+        };
+        is_session_id(id).then_some(id)
+    }
+
+    /// 配置路径之后的部分: `{会话 ID}` 或 `{会话 ID}/{序号}`
+    fn session_path<'a>(&self, path: &'a str) -> &'a str {
+        self.path_remainder(path).unwrap_or_default().trim_matches('/')
+    }
+
+    /// 请求路径是否为配置路径本身或其下的子路径
+    pub fn accepts_path(&self, path: &str) -> bool {
+        self.path_remainder(path).is_some()
+    }
+
+    /// 配置路径之后的部分，只在路径段边界处匹配 (`/xhttp` 不匹配 `/xhttpfoo`)
+    fn path_remainder<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let rest = path.strip_prefix(self.path.as_str())?;
+        (rest.is_empty() || rest.starts_with('/') || self.path.ends_with('/')).then_some(rest)
+    }
+
+    /// `{会话 ID}/{序号}` 中的序号
+    fn packet_seq(&self, path: &str) -> Option<u64> {
+        let (session, seq) = self.session_path(path).split_once('/')?;
+        if session.is_empty() {
+            return None;
+        }
+        seq.parse().ok()
+    }
+}
+
+impl XhttpConfig {
+    /// 请求的 Host / `:authority` 是否在 `hosts` 中
+    ///
+    /// 忽略端口与大小写，`*.example.com` 匹配其任意子域名 (不含 example.com 本身)。
+    /// 直接暴露的监听器会收到按 IP 扫描的请求，这些请求不进入隧道。
+    pub fn accepts_host(&self, authority: Option<&str>) -> bool {
+        if self.hosts.is_empty() {
+            return true;
+        }
+        let host = authority.map(strip_port).unwrap_or_default();
+        let accepted = !host.is_empty() && self.hosts.iter().any(|pattern| host_matches(pattern, host));
+        if !accepted {
+            debug!("XHTTP: Host {:?} 不在允许列表中，交给伪装站点", authority.unwrap_or_default());
+        }
+        accepted
+    }
+}
+
+/// 去掉 `host:port` 中的端口，IPv6 字面量去掉方括号
+fn strip_port(authority: &str) -> &str {
+    if let Some(rest) = authority.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or_default();
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) if port.bytes().all(|b| b.is_ascii_digit()) => host,
+        _ => authority,
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host
+            .len()
+            .checked_sub(suffix.len() + 1)
+            .filter(|&dot| dot > 0 && host.as_bytes()[dot] == b'.')
+            .is_some_and(|dot| host[dot + 1..].eq_ignore_ascii_case(suffix)),
+        None => host.eq_ignore_ascii_case(pattern),
+    }
+}
+
+/// 检查 `hosts` 中的一项: 域名或 IP，可带前导 `*.`，不带端口
+pub fn validate_host_pattern(pattern: &str) -> Result<()> {
+    let name = pattern.strip_prefix("*.").unwrap_or(pattern);
+    if name.is_empty() || name.contains('*') {
+        return Err(anyhow!("通配符只能作为前导 `*.` 使用 (当前为 {:?})", pattern));
+    }
+    let is_name = name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.');
+    if !is_name && name.parse::<std::net::IpAddr>().is_err() {
+        return Err(anyhow!("不是合法的域名或 IP，且不能带端口 (当前为 {:?})", pattern));
+    }
+    Ok(())
+}
+
+/// 部分客户端通过查询参数而不是路径传递会话 ID
+const SESSION_QUERY: &str = "x-session-id";
+
+fn query_session(query: Option<&str>) -> Option<&str> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| name.eq_ignore_ascii_case(SESSION_QUERY))
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+/// 会话 ID 为客户端生成的 UUID 一类的随机标记: 1-64 位字母、数字、`-` 或 `_`
+fn is_session_id(id: &str) -> bool {
+    (1..=64).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: XhttpMode) -> XhttpConfig {
+        XhttpConfig { mode, path: "/xhttp".to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_h2_tuning_ranges() {
+        assert!(H2Tuning::default().validate().is_ok());
+
+        let mut tuning = H2Tuning { initial_window_size: MAX_WINDOW_SIZE, ..Default::default() };
+        assert!(tuning.validate().is_ok());
+        tuning.initial_window_size = MAX_WINDOW_SIZE + 1;
+        assert!(tuning.validate().unwrap_err().to_string().starts_with("initialWindowSize"));
+
+        let tuning = H2Tuning { initial_connection_window_size: 1024, ..Default::default() };
+        assert!(tuning.validate().unwrap_err().to_string().starts_with("initialConnectionWindowSize"));
+
+        let tuning = H2Tuning { max_frame_size: MAX_FRAME_SIZE + 1, ..Default::default() };
+        assert!(tuning.validate().unwrap_err().to_string().starts_with("maxFrameSize"));
+
+        // pingIntervalMax 为 0 时关闭 PING，下限不再检查，但严格保活依赖 PING
+        let mut tuning = H2Tuning { ping_interval_max: Duration::ZERO, ..Default::default() };
+        assert!(tuning.validate().is_ok());
+        tuning.keepalive_timeout = Duration::from_secs(10);
+        assert!(tuning.validate().unwrap_err().to_string().starts_with("keepaliveTimeout"));
+
+        let tuning = H2Tuning { ping_interval_min: Duration::from_secs(60), ..Default::default() };
+        assert!(tuning.validate().unwrap_err().to_string().starts_with("pingIntervalMin"));
+    }
+
+    #[test]
+    fn test_request_mode() {
+        let auto = config(XhttpMode::Auto);
+        assert_eq!(auto.request_mode("POST", "/xhttp", None, None), Some(RequestMode::StreamOne));
+        assert_eq!(auto.request_mode("POST", "/xhttp/", None, None), Some(RequestMode::StreamOne));
+        assert_eq!(auto.request_mode("POST", "/xhttp/4b1d", None, None), Some(RequestMode::Split));
+        assert_eq!(auto.request_mode("POST", "/xhttp/4b1d", None, Some("stream-one")), Some(RequestMode::StreamOne));
+        assert_eq!(auto.request_mode("POST", "/xhttp/4b1d", None, Some("bogus")), Some(RequestMode::Split));
+
+        let stream_one = config(XhttpMode::StreamOne);
+        assert_eq!(stream_one.request_mode("POST", "/xhttp/4b1d", None, None), Some(RequestMode::StreamOne));
+
+        let stream_up = config(XhttpMode::StreamUp);
+        assert_eq!(stream_up.request_mode("POST", "/xhttp/4b1d", None, None), Some(RequestMode::Split));
+        assert_eq!(stream_up.request_mode("POST", "/xhttp", None, None), None);
+        assert_eq!(stream_up.request_mode("POST", "/xhttp/4b1d", None, Some("stream-one")), None);
+        assert_eq!(stream_up.request_mode("POST", "/xhttp/4b1d/0", None, None), None);
+    }
+
+    #[test]
+    fn test_packet_up_paths() {
+        let auto = config(XhttpMode::Auto);
+        assert_eq!(auto.request_mode("POST", "/xhttp/4b1d/7", None, None), Some(RequestMode::PacketUp { seq: 7 }));
+        assert_eq!(auto.request_mode("POST", "/xhttp/4b1d/7/", None, None), Some(RequestMode::PacketUp { seq: 7 }));
+        // 序号不是数字时仍按会话 ID 处理
+        assert_eq!(auto.request_mode("POST", "/xhttp/4b1d/x", None, None), Some(RequestMode::Split));
+        assert_eq!(auto.session_key("/xhttp/4b1d/7", None), Some("4b1d"));
+        assert_eq!(auto.session_key("/xhttp/4b1d/7/", None), Some("4b1d"));
+
+        let packet_up = config(XhttpMode::PacketUp);
+        assert_eq!(packet_up.request_mode("GET", "/xhttp/4b1d", None, None), Some(RequestMode::Split));
+        assert_eq!(packet_up.request_mode("POST", "/xhttp/4b1d", None, None), None);
+        assert_eq!(packet_up.request_mode("POST", "/xhttp/4b1d/0", None, None), Some(RequestMode::PacketUp { seq: 0 }));
+        assert_eq!(packet_up.request_mode("POST", "/xhttp", None, None), None);
+
+        assert!(PacketUpLimits::default().validate().is_ok());
+        let limits = PacketUpLimits { max_buffered_bytes: 1024, ..Default::default() };
+        assert!(limits.validate().unwrap_err().to_string().starts_with("maxBufferedBytes"));
+    }
+
+    #[test]
+    fn test_accepts_host() {
+        let mut auto = config(XhttpMode::Auto);
+        assert!(auto.accepts_host(None));
+
+        auto.hosts = vec!["cdn.example.com".into(), "*.example.net".into(), "::1".into()];
+        assert!(auto.accepts_host(Some("cdn.example.com")));
+        assert!(auto.accepts_host(Some("CDN.Example.COM:443")));
+        assert!(auto.accepts_host(Some("a.example.net")));
+        assert!(auto.accepts_host(Some("a.b.Example.Net:8443")));
+        assert!(auto.accepts_host(Some("[::1]:443")));
+        assert!(!auto.accepts_host(Some("example.net")));
+        assert!(!auto.accepts_host(Some("aexample.net")));
+        assert!(!auto.accepts_host(Some("203.0.113.7:443")));
+        assert!(!auto.accepts_host(Some("www.example.com")));
+        assert!(!auto.accepts_host(None));
+
+        assert!(validate_host_pattern("*.example.com").is_ok());
+        assert!(validate_host_pattern("203.0.113.7").is_ok());
+        assert!(validate_host_pattern("2001:db8::1").is_ok());
+        assert!(validate_host_pattern("*").is_err());
+        assert!(validate_host_pattern("example.com:443").is_err());
+        assert!(validate_host_pattern("a.*.example.com").is_err());
+    }
+
+    #[test]
+    fn test_shaping() {
+        let shaping = Shaping { enabled: true, min_chunk_size: 100, max_chunk_size: 100 };
+        assert_eq!(shaping.next_chunk_size(), Some(100));
+        assert_eq!(Shaping { enabled: false, ..shaping.clone() }.next_chunk_size(), None);
+        assert!(Shaping::default().validate().is_ok());
+        assert!(Shaping { min_chunk_size: 0, ..shaping.clone() }.validate().is_err());
+        let err = Shaping { max_chunk_size: 99, ..shaping }.validate().unwrap_err().to_string();
+        assert!(err.starts_with("maxChunkSize"), "{}", err);
+    }
+
+    #[test]
+    fn test_session_key() {
+        let auto = config(XhttpMode::Auto);
+        let uuid = "6f1c2d9e-0b4a-4f7e-9a51-3c2e8d7b1a60";
+        assert_eq!(auto.session_key(&format!("/xhttp/{uuid}"), None), Some(uuid));
+        assert_eq!(auto.session_key(&format!("/xhttp/{uuid}/"), Some("x_padding=XX")), Some(uuid));
+        // 路径中没有会话 ID 时取查询参数
+        assert_eq!(auto.session_key("/xhttp", Some(&format!("x_padding=XX&x-session-id={uuid}"))), Some(uuid));
+        assert_eq!(auto.request_mode("GET", "/xhttp", Some(&format!("x-session-id={uuid}")), None), Some(RequestMode::Split));
+        assert_eq!(auto.session_key("/xhttp", None), None);
+        assert_eq!(auto.session_key("/xhttp/", Some("x-session-id=")), None);
+        assert_eq!(auto.session_key("/xhttp/a%2Fb", None), None);
+        assert_eq!(auto.session_key(&format!("/xhttp/{}", "a".repeat(65)), None), None);
+    }
+
+    #[test]
+    fn test_path_segment_boundary() {
+        let auto = config(XhttpMode::Auto);
+        assert!(auto.accepts_path("/xhttp"));
+        assert!(auto.accepts_path("/xhttp/4b1d"));
+        assert!(!auto.accepts_path("/xhttpfoo/x"));
+        assert!(!auto.accepts_path("/xhttpfoo"));
+        assert_eq!(auto.session_key("/xhttpfoo/x", None), None);
+        assert_eq!(auto.session_key("/xhttpfoo", None), None);
+
+        let mut root = config(XhttpMode::Auto);
+        root.path = "/".to_string();
+        assert!(root.accepts_path("/4b1d"));
+        assert_eq!(root.session_key("/4b1d", None), Some("4b1d"));
+    }
 }
@@ -0,0 +1,180 @@
+//! packet-up 模式的上行重组
+//!
+//! 客户端把每个上行数据包作为独立的 POST 发往 `{path}/{会话 ID}/{序号}`，这些 POST 可能并发、乱序到达。
+//! 乱序的数据包按序号缓存，连续的部分按顺序写入会话的上行通道；缺口长时间不补齐或超出限制时关闭会话。
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::{warn, Instrument};
+
+use super::channel::UploadSender;
+use super::PacketUpLimits;
+
+/// 按序号重组的状态
+#[derive(Default)]
+struct Reorder {
+    /// 下一个应交付的序号
+    next_seq: u64,
+    pending: BTreeMap<u64, Bytes>,
+    pending_bytes: usize,
+    /// 正在计时的缺口 (缺失的序号)
+    watched_gap: Option<u64>,
+}
+
+impl Reorder {
+    /// 放入一个数据包，返回可以按顺序交付的数据
+    fn insert(&mut self, seq: u64, data: Bytes, limits: &PacketUpLimits) -> Result<Vec<Bytes>> {
+        if seq < self.next_seq || self.pending.contains_key(&seq) {
+            return Err(anyhow!("数据包 {} 重复", seq));
+        }
+        if seq - self.next_seq >= limits.max_buffered_posts as u64 {
+            return Err(anyhow!("数据包 {} 超出重组窗口 (等待 {})", seq, self.next_seq));
+        }
+        if seq != self.next_seq {
+            if self.pending_bytes + data.len() > limits.max_buffered_bytes {
+                return Err(anyhow!("乱序缓存超过 {} 字节", limits.max_buffered_bytes));
+            }
+            self.pending_bytes += data.len();
+            self.pending.insert(seq, data);
+            return Ok(Vec::new());
+        }
+
+        let mut ready = vec![data];
+        self.next_seq += 1;
+        while let Some(data) = self.pending.remove(&self.next_seq) {
+            self.pending_bytes -= data.len();
+            ready.push(data);
+            self.next_seq += 1;
+        }
+        Ok(ready)
+    }
+
+    /// 有乱序数据在等待时返回缺失的序号
+    fn gap(&self) -> Option<u64> {
+        (!self.pending.is_empty()).then_some(self.next_seq)
+    }
+}
+
+/// 一个会话的 packet-up 上行
+pub(super) struct PacketQueue {
+    tx: UploadSender,
+    limits: PacketUpLimits,
+    state: Mutex<Reorder>,
+    closed: CancellationToken,
+}
+
+impl PacketQueue {
+    pub(super) fn new(tx: UploadSender, limits: PacketUpLimits) -> Self {
+        Self { tx, limits, state: Mutex::new(Reorder::default()), closed: CancellationToken::new() }
+    }
+
+    pub(super) fn limits(&self) -> &PacketUpLimits {
+        &self.limits
+    }
+
+    /// 放入序号为 `seq` 的数据包，连续的数据写入上行通道 (通道满时等待)
+    ///
+    /// 重复、超出窗口或缓存上限时关闭会话并返回错误。
+    pub(super) async fn push(self: &Arc<Self>, seq: u64, data: Bytes) -> Result<()> {
+        // 持锁写入通道，保证并发的 POST 按序号顺序交付
+        let mut state = self.state.lock().await;
+        if self.closed.is_cancelled() {
+            return Err(anyhow!("会话已关闭"));
+        }
+        let ready = state.insert(seq, data, &self.limits).inspect_err(|_| self.closed.cancel())?;
+        for data in ready {
+            if self.tx.send(data).await.is_err() {
+                self.closed.cancel();
+                return Err(anyhow!("会话已关闭"));
+            }
+        }
+
+        if let Some(missing) = state.gap() {
+            if state.watched_gap != Some(missing) {
+                state.watched_gap = Some(missing);
+                self.watch_gap(missing);
+            }
+        }
+        Ok(())
+    }
+
+    /// 缺失的数据包超时未到达时关闭会话
+    fn watch_gap(self: &Arc<Self>, missing: u64) {
+        let queue = Arc::downgrade(self);
+        let closed = self.closed.clone();
+        let timeout = self.limits.gap_timeout;
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = closed.cancelled() => return,
+                _ = tokio::time::sleep(timeout) => {}
+            }
+            let Some(queue) = queue.upgrade() else { return };
+            if queue.state.lock().await.gap() == Some(missing) {
+                warn!("XHTTP packet-up: 数据包 {} 在 {:?} 内未到达，关闭会话", missing, timeout);
+                queue.closed.cancel();
+            }
+        }.in_current_span());
+    }
+
+    /// 序号 0 (携带 VLESS 请求头) 是否已交付
+    pub(super) async fn started(&self) -> bool {
+        self.state.lock().await.next_seq > 0
+    }
+
+    /// 会话因上行出错或缺口超时而关闭
+    pub(super) async fn closed(&self) {
+        self.closed.cancelled().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::xhttp::channel;
+    use std::time::Duration;
+
+    fn limits() -> PacketUpLimits {
+        PacketUpLimits {
+            max_buffered_posts: 4,
+            max_each_post_bytes: 8,
+            max_buffered_bytes: 16,
+            gap_timeout: Duration::from_secs(30),
+        }
+    }
+
+    #[test]
+    fn test_reorder_delivers_in_sequence() {
+        let mut reorder = Reorder::default();
+        let limits = limits();
+        assert!(reorder.insert(2, Bytes::from_static(b"c"), &limits).unwrap().is_empty());
+        assert!(reorder.insert(1, Bytes::from_static(b"b"), &limits).unwrap().is_empty());
+        assert_eq!(reorder.gap(), Some(0));
+        let ready = reorder.insert(0, Bytes::from_static(b"a"), &limits).unwrap();
+        assert_eq!(ready, vec![&b"a"[..], &b"b"[..], &b"c"[..]]);
+        assert_eq!((reorder.gap(), reorder.pending_bytes), (None, 0));
+
+        // 重复、超出窗口、超出缓存上限
+        assert!(reorder.insert(1, Bytes::from_static(b"b"), &limits).is_err());
+        assert!(reorder.insert(3 + 4, Bytes::from_static(b"x"), &limits).is_err());
+        reorder.insert(4, Bytes::from(vec![0u8; 8]), &limits).unwrap();
+        reorder.insert(5, Bytes::from(vec![0u8; 8]), &limits).unwrap();
+        assert!(reorder.insert(6, Bytes::from_static(b"x"), &limits).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unfilled_gap_closes_session() {
+        let (tx, mut rx) = channel::channel(1024);
+        let queue = Arc::new(PacketQueue::new(tx, limits()));
+        queue.push(0, Bytes::from_static(b"a")).await.unwrap();
+        queue.push(2, Bytes::from_static(b"c")).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().data, &b"a"[..]);
+
+        tokio::time::timeout(Duration::from_secs(31), queue.closed()).await.expect("gap never timed out");
+        assert!(queue.push(1, Bytes::from_static(b"b")).await.is_err());
+    }
+}
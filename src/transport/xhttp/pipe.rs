@@ -0,0 +1,263 @@
+//! XHTTP 与 VLESS 处理之间的内存管道
+//!
+//! 与 `tokio::io::duplex` 相同，两端各有一个方向的缓冲区，写满时写端等待读端取走数据。
+//! 不同之处在于容量可以增长: 自适应时从较小的容量开始，写端发现缓冲区已满，
+//! 且自上次扩容以来流过的数据已达到容量的数倍 (持续的大流量，而不是一次突发) 时容量翻倍，
+//! 直到上限。大量空闲会话因此只占用初始容量。各管道的容量之和计入 `XhttpMetrics::pipe_bytes`。
+
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BytesMut};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::metrics::XhttpMetrics;
+
+/// 扩容前自上次扩容以来须流过的数据量 (当前容量的倍数)
+const GROW_AFTER_PASSES: usize = 4;
+
+/// 管道容量
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PipeSizing {
+    /// 关闭时固定使用 `maxSize`
+    pub adaptive: bool,
+    /// 自适应时的初始容量 (字节)
+    pub initial_size: usize,
+    /// 容量上限 (字节)
+    pub max_size: usize,
+}
+
+impl Default for PipeSizing {
+    fn default() -> Self {
+        Self {
+            adaptive: true,
+            initial_size: 64 * 1024,
+            max_size: 512 * 1024,
+        }
+    }
+}
+
+impl PipeSizing {
+    /// 错误信息以配置字段名开头
+    pub fn validate(&self) -> Result<()> {
+        if self.initial_size == 0 {
+            return Err(anyhow!("initialSize: 必须大于 0"));
+        }
+        if self.max_size < self.initial_size {
+            return Err(anyhow!("maxSize: 不能小于 initialSize ({} < {})", self.max_size, self.initial_size));
+        }
+        Ok(())
+    }
+
+    fn initial(&self) -> usize {
+        if self.adaptive {
+            self.initial_size
+        } else {
+            self.max_size
+        }
+    }
+}
+
+/// 创建一对相连的管道端
+pub(super) fn pipe(sizing: &PipeSizing, metrics: &Arc<XhttpMetrics>) -> (PipeStream, PipeStream) {
+    let a_to_b = Arc::new(Mutex::new(Pipe::new(sizing, metrics.clone())));
+    let b_to_a = Arc::new(Mutex::new(Pipe::new(sizing, metrics.clone())));
+    (
+        PipeStream { read: b_to_a.clone(), write: a_to_b.clone() },
+        PipeStream { read: a_to_b, write: b_to_a },
+    )
+}
+
+/// 单向缓冲区
+struct Pipe {
+    buf: BytesMut,
+    limit: usize,
+    max: usize,
+    /// 自上次扩容以来读走的字节
+    passed: usize,
+    /// 写端已关闭或读端已释放
+    closed: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+    metrics: Arc<XhttpMetrics>,
+}
+
+impl Pipe {
+    fn new(sizing: &PipeSizing, metrics: Arc<XhttpMetrics>) -> Self {
+        let limit = sizing.initial();
+        metrics.pipe_bytes.fetch_add(limit, Ordering::Relaxed);
+        Self {
+            buf: BytesMut::new(),
+            limit,
+            max: sizing.max_size,
+            passed: 0,
+            closed: false,
+            read_waker: None,
+            write_waker: None,
+            metrics,
+        }
+    }
+
+    /// 写满时判断是否扩容
+    fn try_grow(&mut self) -> bool {
+        if self.limit >= self.max || self.passed < self.limit * GROW_AFTER_PASSES {
+            return false;
+        }
+        let grown = (self.limit * 2).min(self.max);
+        self.metrics.pipe_bytes.fetch_add(grown - self.limit, Ordering::Relaxed);
+        self.limit = grown;
+        self.passed = 0;
+        true
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        self.metrics.pipe_bytes.fetch_sub(self.limit, Ordering::Relaxed);
+    }
+}
+
+/// 管道的一端，drop 时两个方向都关闭
+pub(super) struct PipeStream {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+impl AsyncRead for PipeStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, dst: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let mut pipe = self.read.lock().unwrap();
+        if pipe.buf.has_remaining() {
+            let n = pipe.buf.len().min(dst.remaining());
+            dst.put_slice(&pipe.buf[..n]);
+            pipe.buf.advance(n);
+            pipe.passed += n;
+            if let Some(waker) = pipe.write_waker.take() {
+                waker.wake();
+            }
+            Poll::Ready(Ok(()))
+        } else if pipe.closed {
+            Poll::Ready(Ok(()))
+        } else {
+            pipe.read_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl AsyncWrite for PipeStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, src: &[u8]) -> Poll<io::Result<usize>> {
+        let mut pipe = self.write.lock().unwrap();
+        if pipe.closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        if pipe.buf.len() >= pipe.limit && !pipe.try_grow() {
+            pipe.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = (pipe.limit - pipe.buf.len()).min(src.len());
+        pipe.buf.extend_from_slice(&src[..n]);
+        if let Some(waker) = pipe.read_waker.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.write.lock().unwrap().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for PipeStream {
+    fn drop(&mut self) {
+        self.read.lock().unwrap().close();
+        self.write.lock().unwrap().close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn sizing(adaptive: bool) -> PipeSizing {
+        PipeSizing { adaptive, initial_size: 1024, max_size: 8192 }
+    }
+
+    #[tokio::test]
+    async fn test_bulk_transfer_grows_to_max() {
+        let metrics = Arc::new(XhttpMetrics::default());
+        let (mut a, mut b) = pipe(&sizing(true), &metrics);
+        assert_eq!(metrics.pipe_bytes.load(Ordering::Relaxed), 2 * 1024);
+
+        let writer = tokio::spawn(async move {
+            let data: Vec<u8> = (0..256 * 1024u32).map(|i| i as u8).collect();
+            a.write_all(&data).await.unwrap();
+            a.shutdown().await.unwrap();
+            (a, data)
+        });
+        let mut received = Vec::new();
+        b.read_to_end(&mut received).await.unwrap();
+        let (a, data) = writer.await.unwrap();
+        assert_eq!(received, data);
+        // 只有 a -> b 方向扩容
+        assert_eq!(metrics.pipe_bytes.load(Ordering::Relaxed), 8192 + 1024);
+
+        drop((a, b));
+        assert_eq!(metrics.pipe_bytes.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_small_writes_keep_initial_size() {
+        let metrics = Arc::new(XhttpMetrics::default());
+        let (mut a, mut b) = pipe(&sizing(true), &metrics);
+        let mut buf = [0u8; 512];
+        for _ in 0..64 {
+            a.write_all(&[7u8; 512]).await.unwrap();
+            b.read_exact(&mut buf).await.unwrap();
+        }
+        // 从未写满，不扩容
+        assert_eq!(metrics.pipe_bytes.load(Ordering::Relaxed), 2 * 1024);
+
+        let (_a, _b) = pipe(&sizing(false), &metrics);
+        assert_eq!(metrics.pipe_bytes.load(Ordering::Relaxed), 2 * 1024 + 2 * 8192);
+    }
+
+    #[tokio::test]
+    async fn test_drop_closes_both_directions() {
+        let metrics = Arc::new(XhttpMetrics::default());
+        let (mut a, b) = pipe(&sizing(false), &metrics);
+        drop(b);
+        let mut buf = [0u8; 8];
+        assert_eq!(a.read(&mut buf).await.unwrap(), 0);
+        assert_eq!(a.write(b"x").await.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(PipeSizing::default().validate().is_ok());
+        assert!(PipeSizing { initial_size: 0, ..PipeSizing::default() }.validate().is_err());
+        let err = PipeSizing { max_size: 1024, ..PipeSizing::default() }.validate().unwrap_err().to_string();
+        assert!(err.starts_with("maxSize"), "{}", err);
+    }
+}
@@ -1,13 +1,27 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::{anyhow, Result};
 use tracing::{debug, info};
 
-use super::{XhttpConfig, H2Handler, XhttpMode};
+use tokio::io::AsyncReadExt;
+use tokio_util::sync::CancellationToken;
+
+use super::{XhttpConfig, XhttpMetrics, H1Handler, H2Handler, XhttpMode};
+use crate::server::PrefixedStream;
+use crate::transport::tls::TlsTerminator;
+
+/// HTTP/2 连接前言 (RFC 9113 3.4)
+const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
 
 /// XHTTP 服务器
 #[derive(Clone)]
 pub struct XhttpServer {
     config: XhttpConfig,
     h2_handler: H2Handler,
+    h1_handler: H1Handler,
+    /// 直接终结 TLS 时的证书 (前面有反向代理时为 `None`)
+    tls: Option<TlsTerminator>,
 }
 
 impl XhttpServer {
@@ -17,6 +31,8 @@ impl XhttpServer {
         if config.path.is_empty() {
             return Err(anyhow!("XHTTP path 不能为空"));
         }
+        config.h2.validate().map_err(|e| anyhow!("XHTTP h2 参数无效: {}", e))?;
+        config.packet_up.validate().map_err(|e| anyhow!("XHTTP packetUp 参数无效: {}", e))?;
 
         // if config.host.is_empty() {
         //     return Err(anyhow!("XHTTP host 不能为空"));
@@ -28,25 +44,96 @@ impl XhttpServer {
         debug!("Host: {}", config.host);
 
         let h2_handler = H2Handler::new(config.clone());
+        let h1_handler = H1Handler::new(config.clone())
+            .with_metrics(h2_handler.metrics().clone())
+            .with_sessions(h2_handler.sessions().clone());
+
+        Ok(Self { config, h2_handler, h1_handler, tls: None })
+    }
+
+    /// 使用外部的停机信号: 取消后 H2 连接发送 GOAWAY，`grace_period` 后中止仍未结束的流，
+    /// 本入站不再建立新会话，已有会话不再接受新的 POST
+    pub fn with_shutdown(mut self, shutdown: CancellationToken, grace_period: Duration) -> Self {
+        self.h2_handler = self.h2_handler.with_shutdown(shutdown, grace_period);
+        self.h1_handler = self.h1_handler.with_sessions(self.h2_handler.sessions().clone());
+        self
+    }
+
+    /// 先完成 TLS 握手再处理 HTTP
+    pub fn with_tls(mut self, tls: TlsTerminator) -> Self {
+        self.tls = Some(tls);
+        self
+    }
 
-        Ok(Self { config, h2_handler })
+    /// 重新读取 TLS 证书 (未启用 TLS 时什么也不做)
+    pub fn reload_tls(&self) -> Result<()> {
+        match &self.tls {
+            Some(tls) => tls.reload(),
+            None => Ok(()),
+        }
     }
 
     /// 处理传入的连接
+    ///
+    /// 启用 TLS 时按协商出的 ALPN 选择 H2 或 HTTP/1.1，没有 ALPN 时与明文连接一样按连接前言分派
     pub async fn accept<T, F, Fut>(&self, stream: T, handler: F) -> Result<()>
     where
         T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
-        F: Fn(Box<dyn crate::server::AsyncStream>) -> Fut + Clone + Send + Sync + 'static,
+        F: Fn(Box<dyn crate::server::AsyncStream>, crate::server::AuthReport) -> Fut + Clone + Send + Sync + 'static,
         Fut: std::future::Future<Output = Result<()>> + Send + 'static,
     {
         debug!("接收到新的 XHTTP 连接");
 
-        // 使用 H2Handler 处理 HTTP/2 连接
-        self.h2_handler.handle(stream, handler).await?;
+        let Some(tls) = &self.tls else {
+            return self.dispatch(stream, handler).await;
+        };
+        let (stream, alpn) = tls.accept(stream).await?;
+        match alpn.as_deref() {
+            Some(b"h2") => self.h2_handler.handle(stream, handler).await,
+            Some(b"http/1.1") => self.h1_handler.handle(stream, handler).await,
+            _ => self.dispatch(stream, handler).await,
+        }
+    }
+
+    /// 按连接前言分派: H2 前言走 H2Handler，其余 (CDN 降级的 HTTP/1.1) 走 H1Handler
+    async fn dispatch<T, F, Fut>(&self, mut stream: T, handler: F) -> Result<()>
+    where
+        T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+        F: Fn(Box<dyn crate::server::AsyncStream>, crate::server::AuthReport) -> Fut + Clone + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let mut preface = Vec::with_capacity(H2_PREFACE.len());
+        tokio::time::timeout(Duration::from_secs(20), async {
+            while preface.len() < H2_PREFACE.len() && H2_PREFACE.starts_with(&preface) {
+                let mut byte_buf = [0u8; 24];
+                let want = H2_PREFACE.len() - preface.len();
+                let n = stream.read(&mut byte_buf[..want]).await?;
+                if n == 0 {
+                    return Err(anyhow!("连接在发送请求前关闭"));
+                }
+                preface.extend_from_slice(&byte_buf[..n]);
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|_| anyhow!("等待 HTTP 请求超时"))??;
+
+        let is_h2 = preface == H2_PREFACE;
+        let stream = PrefixedStream::new(preface, stream);
+        if is_h2 {
+            self.h2_handler.handle(stream, handler).await?;
+        } else {
+            self.h1_handler.handle(stream, handler).await?;
+        }
 
         Ok(())
     }
 
+    /// 该入站的 XHTTP 指标 (H2 与 HTTP/1.1 共用)
+    pub fn metrics(&self) -> &Arc<XhttpMetrics> {
+        self.h2_handler.metrics()
+    }
+
     /// 获取工作模式
     pub fn mode(&self) -> &XhttpMode {
         &self.config.mode
@@ -73,6 +160,7 @@ mod tests {
             mode: XhttpMode::StreamUp,
             path: "/".to_string(),
             host: "www.example.com".to_string(),
+            ..Default::default()
         };
 
         let server = XhttpServer::new(config);
@@ -81,6 +169,9 @@ mod tests {
         let server = server.unwrap();
         assert_eq!(server.path(), "/");
         assert_eq!(server.host(), "www.example.com");
+
+        let server = XhttpServer::new(XhttpConfig::default()).unwrap();
+        assert_eq!(server.path(), "/");
     }
 
     #[test]
@@ -89,6 +180,7 @@ mod tests {
             mode: XhttpMode::StreamUp,
             path: "".to_string(),
             host: "www.example.com".to_string(),
+            ..Default::default()
         };
         let server = XhttpServer::new(config);
         assert!(server.is_err());
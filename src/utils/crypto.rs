@@ -1,14 +1,122 @@
+use anyhow::{anyhow, Result};
 use base64::{engine::general_purpose, Engine as _};
 use rand::rngs::OsRng;
-use x25519_dalek::{EphemeralSecret, PublicKey};
+use x25519_dalek::{PublicKey, StaticSecret};
 
-/// X25519 密钥对
+/// 密钥输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyFormat {
+    /// 十六进制原始字节
+    Raw,
+    /// 标准 Base64 (带填充)
+    Base64,
+    /// URL-safe Base64 无填充 (Xray 格式)
+    Base64Url,
+}
+
+impl KeyFormat {
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            KeyFormat::Raw => hex::encode(bytes),
+            KeyFormat::Base64 => general_purpose::STANDARD.encode(bytes),
+            KeyFormat::Base64Url => general_purpose::URL_SAFE_NO_PAD.encode(bytes),
+        }
+    }
+}
+
+/// X25519 密钥对 (Reality privateKey / publicKey)
 pub struct X25519KeyPair {
-    pub private_key: EphemeralSecret,
+    pub private_key: StaticSecret,
     pub public_key: PublicKey,
 }
 
+impl X25519KeyPair {
+    /// 生成随机密钥对
+    pub fn generate() -> Self {
+        Self::from_private_key(StaticSecret::random_from_rng(OsRng).to_bytes())
+    }
+
+    /// 由私钥推导公钥
+    pub fn from_private_key(private_key: [u8; 32]) -> Self {
+        let private_key = StaticSecret::from(private_key);
+        let public_key = PublicKey::from(&private_key);
+        Self { private_key, public_key }
+    }
+
+    /// 从 Base64 私钥 (URL-safe 无填充或标准格式) 恢复密钥对
+    pub fn from_private_base64(private_key: &str) -> Result<Self> {
+        let bytes = general_purpose::URL_SAFE_NO_PAD
+            .decode(private_key)
+            .or_else(|_| general_purpose::STANDARD.decode(private_key))
+            .map_err(|_| anyhow!("私钥不是有效的 Base64"))?;
+        let bytes: [u8; 32] = bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow!("私钥长度必须是 32 字节，当前: {} 字节", bytes.len()))?;
+        Ok(Self::from_private_key(bytes))
+    }
+
+    /// 私钥 (Xray 格式: URL-safe Base64 无填充)
+    pub fn private_key_base64(&self) -> String {
+        self.private_key_string(KeyFormat::Base64Url)
+    }
+
+    /// 公钥 (Xray 格式: URL-safe Base64 无填充)
+    pub fn public_key_base64(&self) -> String {
+        self.public_key_string(KeyFormat::Base64Url)
+    }
+
+    pub fn private_key_string(&self, format: KeyFormat) -> String {
+        format.encode(self.private_key.as_bytes())
+    }
+
+    pub fn public_key_string(&self, format: KeyFormat) -> String {
+        format.encode(self.public_key.as_bytes())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transport::reality::{RealityConfig, RealityServer};
+
+    #[test]
+    fn test_key_formats() {
+        let pair = X25519KeyPair::from_private_key([0x41; 32]);
+        assert_eq!(pair.private_key_string(KeyFormat::Raw), "41".repeat(32));
+        assert_eq!(
+            pair.private_key_string(KeyFormat::Base64),
+            "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE="
+        );
+        assert_eq!(
+            pair.private_key_string(KeyFormat::Base64Url),
+            "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE"
+        );
+        assert_eq!(pair.public_key, PublicKey::from(&StaticSecret::from([0x41; 32])));
+    }
+
+    #[test]
+    fn test_base64_round_trip() {
+        let pair = X25519KeyPair::generate();
+        let restored = X25519KeyPair::from_private_base64(&pair.private_key_base64()).unwrap();
+        assert_eq!(restored.private_key.to_bytes(), pair.private_key.to_bytes());
+        assert_eq!(restored.public_key_base64(), pair.public_key_base64());
+
+        // 标准 Base64 同样接受
+        let restored = X25519KeyPair::from_private_base64(&pair.private_key_string(KeyFormat::Base64)).unwrap();
+        assert_eq!(restored.public_key, pair.public_key);
+
+        assert!(X25519KeyPair::from_private_base64("not base64!").is_err());
+        assert!(X25519KeyPair::from_private_base64("QUFBQQ").is_err());
+    }
+
+    #[test]
+    fn test_generated_keys_are_accepted_by_reality() {
+        let pair = X25519KeyPair::generate();
+        for format in [KeyFormat::Base64, KeyFormat::Base64Url] {
+            let config = RealityConfig::new("www.example.com:443", vec![], pair.private_key_string(format), vec![]);
+            assert!(RealityServer::new(config).is_ok());
+        }
+        assert_ne!(pair.private_key.to_bytes(), X25519KeyPair::generate().private_key.to_bytes());
+    }
 }
@@ -1,4 +1,4 @@
 pub mod crypto;
 pub mod error;
 
-pub use crypto::X25519KeyPair;
+pub use crypto::{KeyFormat, X25519KeyPair};
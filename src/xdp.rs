@@ -1,185 +1,545 @@
-#[cfg(feature = "xdp")]
-pub mod loader {
-    use aya::programs::XdpFlags;
-    use aya::{include_bytes_aligned, programs::Xdp, Bpf};
-    use aya::maps::HashMap;
-    use aya_log::EbpfLogger;
-    use tracing::{error, info, warn};
-    use tokio;
-
-    pub fn start_xdp(iface: &str, ports: Vec<u16>) {
-        let iface = iface.to_string();
-        let ports = ports.clone();
-
-        // Must use tokio::spawn to provide Reactor context for aya::log
-        tokio::spawn(async move {
-            info!("正在初始化 XDP 防火墙，接口: {}", iface);
-
-            // 加载逻辑
-            // 这里的路径是相对于 User Space crate root 的 (xray-lite/)
-            #[cfg(debug_assertions)]
-             let program_bytes = include_bytes_aligned!(
-                "../xray-lite-ebpf/target/bpfel-unknown-none/release/xray-lite-ebpf"
-            );
-            #[cfg(not(debug_assertions))]
-            let program_bytes = include_bytes_aligned!(
-                "../xray-lite-ebpf/target/bpfel-unknown-none/release/xray-lite-ebpf"
-            );
-
-            // 加载 BPF
-            let mut bpf = match Bpf::load(program_bytes) {
-                Ok(b) => b,
-                Err(e) => {
-                    error!("XDP 加载失败: {}", e);
-                    return;
-                }
-            };
+//! XDP 防火墙加载器
+//!
+//! eBPF 程序位于 `xray-lite-ebpf`，需先以 nightly 构建 (见 release_build.sh)，产物在编译时嵌入。
+
+use aya::maps::{Array, HashMap, MapData, MapError, PerCpuArray, PerCpuHashMap};
+use aya::programs::xdp::XdpLinkId;
+use aya::programs::{Xdp, XdpFlags};
+use aya::{include_bytes_aligned, Ebpf};
+use std::collections::HashSet;
+use std::net::Ipv4Addr;
+use tokio::sync::{mpsc, oneshot, watch};
+use tracing::{error, info, warn};
+use xray_lite_common::{
+    RateLimitEntry, CONFIG_SYN_RATE_LIMIT, CONFIG_UDP_RATE_LIMIT, STAT_BLOCKED, STAT_ILLEGAL_FLAGS_DROPPED, STAT_PASSED,
+    STAT_SYN_DROPPED, STAT_UDP_DROPPED,
+};
+
+use crate::config::XdpConfig;
+
+/// 编译好的 eBPF 程序 (路径相对于本文件)
+static PROGRAM: &[u8] = include_bytes_aligned!("../xray-lite-ebpf/target/bpfel-unknown-none/release/xray-lite-ebpf");
+
+/// ALLOWED_PORTS 的键/值类型，必须与 eBPF 定义 (HashMap<u16, u8>) 一致
+type PortKey = u16;
+type PortValue = u8;
+
+/// 计数器日志输出间隔
+const STATS_INTERVAL_SECS: u64 = 60;
+/// 限速表清理间隔
+const GC_INTERVAL_SECS: u64 = 180;
+/// 超过该时长 (纳秒) 未更新的限速记录视为过期
+const STALE_ENTRY_NS: u64 = 180 * 1_000_000_000;
+/// 按源 IP 限速的表: SYN 与 UDP (QUIC) 各一个，值均为 RateLimitEntry
+const RATE_LIMIT_MAPS: [&str; 2] = ["RATE_LIMIT_MAP", "UDP_RATE_LIMIT_MAP"];
+
+/// XDP 丢弃/放行计数 (所有 CPU 求和)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct XdpStats {
+    pub syn_dropped: u64,
+    pub illegal_flags_dropped: u64,
+    pub udp_dropped: u64,
+    /// 发往受保护端口并放行的包
+    pub passed: u64,
+    /// 来自黑名单的包 (所有端口)
+    pub blocked: u64,
+}
+
+/// 读取 XDP_STATS 计数器
+fn read_stats(bpf: &Ebpf) -> Option<XdpStats> {
+    let map = bpf.map("XDP_STATS")?;
+    let stats: PerCpuArray<_, u64> = match PerCpuArray::try_from(map) {
+        Ok(m) => m,
+        Err(e) => {
+            warn!("无法读取 XDP_STATS: {}", e);
+            return None;
+        }
+    };
+    let sum = |index: u32| -> u64 {
+        stats
+            .get(&index, 0)
+            .map(|values| values.iter().sum())
+            .unwrap_or(0)
+    };
+    Some(XdpStats {
+        syn_dropped: sum(STAT_SYN_DROPPED),
+        illegal_flags_dropped: sum(STAT_ILLEGAL_FLAGS_DROPPED),
+        udp_dropped: sum(STAT_UDP_DROPPED),
+        passed: sum(STAT_PASSED),
+        blocked: sum(STAT_BLOCKED),
+    })
+}
+
+/// 发送给 XDP 任务的运行时更新
+enum XdpCommand {
+    SetPorts(Vec<u16>),
+    Configure(XdpConfig),
+    Block(Ipv4Addr),
+    Unblock(Ipv4Addr),
+    Stats(oneshot::Sender<Option<XdpStats>>),
+    /// 卸载 XDP 程序并结束任务，完成后回复
+    Shutdown(oneshot::Sender<()>),
+}
+
+/// 运行时更新 XDP 状态的句柄
+///
+/// 调用 `shutdown` 或丢弃所有句柄后，XDP 程序会从网卡上卸载。
+///
+/// 配置重新加载或新的 inbound 绑定后，调用 `set_ports` 传入完整的端口集合，
+/// 新增端口写入 ALLOWED_PORTS，移除的端口从中删除，无需重新加载 eBPF 程序。
+#[derive(Clone)]
+pub struct XdpHandle {
+    command_tx: mpsc::UnboundedSender<XdpCommand>,
+    attached: watch::Receiver<bool>,
+}
+
+impl XdpHandle {
+    /// 等待程序挂载并写入端口，挂载失败时返回 false
+    pub async fn attached(&self) -> bool {
+        self.attached.clone().wait_for(|&attached| attached).await.is_ok()
+    }
+
+    /// 替换受保护的端口集合
+    pub fn set_ports(&self, ports: Vec<u16>) {
+        self.send(XdpCommand::SetPorts(ports));
+    }
+
+    /// 应用新的限速阈值 (网卡变更需要重启)
+    pub fn configure(&self, config: &XdpConfig) {
+        self.send(XdpCommand::Configure(config.clone()));
+    }
 
-            // 初始化日志：必须在 Tokio Runtime 上下文中调用
-            if let Err(e) = EbpfLogger::init(&mut bpf) {
-                warn!("XDP EbpfLogger 初始化失败 (非致命): {}", e);
+    /// 在内核中直接丢弃来自 `ip` 的所有数据包
+    pub fn block_ip(&self, ip: Ipv4Addr) {
+        self.send(XdpCommand::Block(ip));
+    }
+
+    /// 从 BLOCKLIST 中移除 `ip`
+    pub fn unblock_ip(&self, ip: Ipv4Addr) {
+        self.send(XdpCommand::Unblock(ip));
+    }
+
+    /// 读取当前计数，程序未挂载时为 `None`
+    pub async fn stats(&self) -> Option<XdpStats> {
+        let (stats_tx, stats_rx) = oneshot::channel();
+        self.send(XdpCommand::Stats(stats_tx));
+        stats_rx.await.ok().flatten()
+    }
+
+    /// 从网卡卸载 XDP 程序，等待卸载完成
+    pub async fn shutdown(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        self.send(XdpCommand::Shutdown(done_tx));
+        let _ = done_rx.await;
+    }
+
+    fn send(&self, command: XdpCommand) {
+        if self.command_tx.send(command).is_err() {
+            warn!("XDP 任务已退出，无法更新");
+        }
+    }
+}
+
+/// BLOCKLIST 的键: 与 eBPF 程序直接读取的 IPv4 头中 saddr 字节序一致
+fn blocklist_key(ip: Ipv4Addr) -> u32 {
+    u32::from_ne_bytes(ip.octets())
+}
+
+/// 更新 BLOCKLIST (HashMap<u32, u32>)
+fn update_blocklist(bpf: &mut Ebpf, ip: Ipv4Addr, blocked: bool) {
+    let Some(map) = bpf.map_mut("BLOCKLIST") else {
+        error!("XDP Map 'BLOCKLIST' not found in eBPF program!");
+        return;
+    };
+    let mut blocklist: HashMap<_, u32, u32> = match HashMap::try_from(map) {
+        Ok(m) => m,
+        Err(e) => {
+            error!("Failed to access BLOCKLIST map as HashMap: {}", e);
+            return;
+        }
+    };
+    let result = if blocked {
+        blocklist.insert(blocklist_key(ip), 1, 0)
+    } else {
+        blocklist.remove(&blocklist_key(ip))
+    };
+    match result {
+        Ok(()) if blocked => info!("⛔ {} 已加入 XDP 黑名单", ip),
+        Ok(()) => info!("✅ {} 已从 XDP 黑名单移除", ip),
+        Err(e) => error!("Failed to update BLOCKLIST for {}: {}", ip, e),
+    }
+}
+
+/// 将限速阈值写入 CONFIG (Array<u32>)
+fn write_config(bpf: &mut Ebpf, config: &XdpConfig) {
+    let Some(map) = bpf.map_mut("CONFIG") else {
+        error!("XDP Map 'CONFIG' not found in eBPF program!");
+        return;
+    };
+    let mut values: Array<_, u32> = match Array::try_from(map) {
+        Ok(m) => m,
+        Err(e) => {
+            error!("Failed to access CONFIG map as Array: {}", e);
+            return;
+        }
+    };
+    let result = values
+        .set(CONFIG_SYN_RATE_LIMIT, config.syn_rate_limit, 0)
+        .and_then(|()| values.set(CONFIG_UDP_RATE_LIMIT, config.udp_rate_limit, 0));
+    match result {
+        Ok(()) => info!(
+            "🚦 XDP 限速: 每个源 IP SYN {} 个/秒, UDP {} 个/秒",
+            config.syn_rate_limit, config.udp_rate_limit
+        ),
+        Err(e) => error!("Failed to update XDP CONFIG: {}", e),
+    }
+}
+
+/// 将 ALLOWED_PORTS 同步为 `ports`，返回实际生效的端口集合
+fn sync_ports(
+    ports_map: &mut HashMap<&mut MapData, PortKey, PortValue>,
+    current: &HashSet<u16>,
+    ports: &HashSet<u16>,
+) -> HashSet<u16> {
+    let mut active = current.clone();
+    for port in current.difference(ports) {
+        match ports_map.remove(port) {
+            Ok(()) => {
+                active.remove(port);
+                info!("🔓 Port {} removed from XDP Kernel Firewall", port);
+            }
+            Err(e) => error!("Failed to remove port {} from XDP Map: {}", port, e),
+        }
+    }
+    for port in ports.difference(current) {
+        match ports_map.insert(*port, 1, 0) {
+            Ok(()) => {
+                active.insert(*port);
+                info!("🛡️  Port {} is now protected by XDP Kernel Firewall", port);
             }
+            Err(e) => error!("Failed to add port {} to XDP Map: {}", port, e),
+        }
+    }
+    active
+}
+
+/// 启动时检查 ALLOWED_PORTS 的键/值大小与 eBPF 定义 (HashMap<u16, u8>) 一致
+///
+/// 不一致时 map 无法使用，所有端口都不会受保护，因此明确报错而不是静默失败。
+fn check_allowed_ports_layout(bpf: &Ebpf) -> bool {
+    let Some(map) = bpf.map("ALLOWED_PORTS") else {
+        error!("XDP Map 'ALLOWED_PORTS' not found in eBPF program!");
+        return false;
+    };
+    match HashMap::<_, PortKey, PortValue>::try_from(map) {
+        Ok(_) => true,
+        Err(MapError::InvalidKeySize { size, expected }) => {
+            error!("ALLOWED_PORTS 键大小不匹配: 内核 {} 字节, 用户态 {} 字节", expected, size);
+            false
+        }
+        Err(MapError::InvalidValueSize { size, expected }) => {
+            error!("ALLOWED_PORTS 值大小不匹配: 内核 {} 字节, 用户态 {} 字节", expected, size);
+            false
+        }
+        Err(e) => {
+            error!("Failed to access ALLOWED_PORTS map as HashMap: {}", e);
+            false
+        }
+    }
+}
 
-            // 挂载 XDP 程序
-            let program: &mut Xdp = match bpf.program_mut("xdp_firewall").unwrap().try_into() {
-                Ok(p) => p,
+/// 将 `ports` 同步到 ALLOWED_PORTS map
+fn update_allowed_ports(bpf: &mut Ebpf, current: &HashSet<u16>, ports: &HashSet<u16>) -> HashSet<u16> {
+    match bpf.map_mut("ALLOWED_PORTS") {
+        Some(map) => {
+            let ports_map_result: Result<HashMap<_, PortKey, PortValue>, _> = HashMap::try_from(map);
+            match ports_map_result {
+                Ok(mut ports_map) => sync_ports(&mut ports_map, current, ports),
                 Err(e) => {
-                    error!("无法获取 xdp_firewall 程序: {}", e);
-                    return;
+                    error!("Failed to access ALLOWED_PORTS map as HashMap: {}", e);
+                    current.clone()
                 }
-            };
+            }
+        },
+        None => {
+            error!("XDP Map 'ALLOWED_PORTS' not found in eBPF program!");
+            current.clone()
+        }
+    }
+}
+
+/// 当前 CLOCK_MONOTONIC 时间 (纳秒)，与 eBPF 侧 bpf_ktime_get_ns() 同一时钟
+fn monotonic_ns() -> Option<u64> {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // Safety: ts 是有效的可写 timespec
+    if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) } != 0 {
+        return None;
+    }
+    Some(ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64)
+}
+
+/// 某个源 IP 在各 CPU 上的记录 (`last_times`) 是否都已超过 STALE_ENTRY_NS 未更新
+///
+/// `now_ns` 来自 monotonic_ns()；开机不足 STALE_ENTRY_NS 时没有记录会过期。
+fn is_stale(last_times: impl IntoIterator<Item = u64>, now_ns: u64) -> bool {
+    let threshold_ns = now_ns.saturating_sub(STALE_ENTRY_NS);
+    last_times.into_iter().max().unwrap_or(0) < threshold_ns
+}
+
+/// 从网卡卸载 XDP 程序
+fn detach(bpf: &mut Ebpf, link_id: XdpLinkId, iface: &str) {
+    let program: Result<&mut Xdp, _> = match bpf.program_mut("xdp_firewall") {
+        Some(program) => program.try_into(),
+        None => {
+            error!("无法卸载 XDP: 找不到 xdp_firewall 程序");
+            return;
+        }
+    };
+    match program.map(|program| program.detach(link_id)) {
+        Ok(Ok(())) => info!("🔌 XDP 防火墙已从 {} 卸载", iface),
+        Ok(Err(e)) => error!("XDP 卸载失败: {}", e),
+        Err(e) => error!("无法获取 xdp_firewall 程序: {}", e),
+    }
+}
+
+/// 加载 eBPF 程序并挂载到 `config.interface`，在后台任务中维护各个 map
+///
+/// 挂载失败时只记录错误，服务照常运行 (没有内核防护)
+pub fn start_xdp(config: &XdpConfig, ports: Vec<u16>) -> XdpHandle {
+    let config = config.clone();
+    let iface = config.interface().unwrap_or_default().to_string();
+    let (command_tx, mut command_rx) = mpsc::unbounded_channel::<XdpCommand>();
+    let (attached_tx, attached) = watch::channel(false);
 
-            if let Err(e) = program.load() {
-                error!("XDP 程序加载到内核失败: {}", e);
+    tokio::spawn(async move {
+        info!("正在初始化 XDP 防火墙，接口: {}", iface);
+
+        // 加载 BPF
+        let mut bpf = match Ebpf::load(PROGRAM) {
+            Ok(b) => b,
+            Err(e) => {
+                error!("XDP 加载失败: {}", e);
+                return;
+            }
+        };
+
+        // 挂载 XDP 程序
+        let program: &mut Xdp = match bpf.program_mut("xdp_firewall").map(TryInto::try_into) {
+            Some(Ok(p)) => p,
+            Some(Err(e)) => {
+                error!("无法获取 xdp_firewall 程序: {}", e);
+                return;
+            }
+            None => {
+                error!("eBPF 程序中没有 xdp_firewall");
                 return;
             }
+        };
 
-            // Try attach in default (Driver) mode first
-            if let Err(e) = program.attach(&iface, XdpFlags::default()) {
+        if let Err(e) = program.load() {
+            error!("XDP 程序加载到内核失败: {}", e);
+            return;
+        }
+
+        // Try attach in default (Driver) mode first
+        let link_id = match program.attach(&iface, XdpFlags::default()) {
+            Ok(link_id) => link_id,
+            Err(e) => {
                 warn!("XDP Native (Driver) attach failed: {}. Falling back to SKB (Generic) mode...", e);
                 // Fallback to SKB (Generic) mode
                 // Note: SKB mode is slower but works on almost all drivers/kernels
-                if let Err(e_skb) = program.attach(&iface, XdpFlags::SKB_MODE) {
-                    error!("XDP SKB (Generic) attach also failed: {}", e_skb);
-                    return;
+                match program.attach(&iface, XdpFlags::SKB_MODE) {
+                    Ok(link_id) => {
+                        info!("⚠️ Falling back to XDP SKB (Generic) mode. Performance might be reduced but still better than iptables.");
+                        link_id
+                    }
+                    Err(e_skb) => {
+                        error!("XDP SKB (Generic) attach also failed: {}", e_skb);
+                        return;
+                    }
                 }
-                info!("⚠️ Falling back to XDP SKB (Generic) mode. Performance might be reduced but still better than iptables.");
             }
+        };
 
-            info!(
-                "🚀 XDP 防火墙已成功挂载到 {}！高性能内核级过滤生效中。",
-                iface
-            );
-
-            // --- Configure Dynamic Ports ---
-            match bpf.map_mut("ALLOWED_PORTS") {
-                Some(map) => {
-                    // Enforce type <_, u16, u32> to match eBPF definition
-                    let ports_map_result: Result<HashMap<_, u16, u32>, _> = HashMap::try_from(map);
-                    match ports_map_result {
-                        Ok(mut ports_map) => {
-                            for port in &ports {
-                                if let Err(e) = ports_map.insert(*port, 1, 0) {
-                                    error!("Failed to add port {} to XDP Map: {}", port, e);
-                                } else {
-                                    info!("🛡️  Port {} is now protected by XDP Kernel Firewall (DROP non-TLS)", port);
-                                }
-                            }
-                        },
-                        Err(e) => error!("Failed to access ALLOWED_PORTS map as HashMap: {}", e),
+        info!(
+            "🚀 XDP 防火墙已成功挂载到 {}！高性能内核级过滤生效中。",
+            iface
+        );
+
+        // --- Configure Dynamic Ports ---
+        if !check_allowed_ports_layout(&bpf) {
+            error!("⚠️ XDP 端口保护未生效，请确认 eBPF 程序与用户态版本一致");
+        }
+        let mut protected_ports = update_allowed_ports(
+            &mut bpf,
+            &HashSet::new(),
+            &ports.into_iter().collect(),
+        );
+        write_config(&mut bpf, &config);
+
+        for &ip in &config.blocklist {
+            update_blocklist(&mut bpf, ip, true);
+        }
+
+        attached_tx.send_replace(true);
+
+        // --- Stats & Garbage Collection Loop ---
+        let mut last_stats = XdpStats::default();
+        let mut elapsed_secs = 0;
+        let mut stats_interval = tokio::time::interval(std::time::Duration::from_secs(STATS_INTERVAL_SECS));
+        stats_interval.tick().await;
+        loop {
+            tokio::select! {
+                _ = stats_interval.tick() => {}
+                command = command_rx.recv() => {
+                    match command {
+                        // 所有句柄都已丢弃时同样卸载
+                        None => {
+                            detach(&mut bpf, link_id, &iface);
+                            return;
+                        }
+                        Some(XdpCommand::Shutdown(done)) => {
+                            detach(&mut bpf, link_id, &iface);
+                            let _ = done.send(());
+                            return;
+                        }
+                        Some(XdpCommand::SetPorts(ports)) => {
+                            protected_ports = update_allowed_ports(
+                                &mut bpf,
+                                &protected_ports,
+                                &ports.into_iter().collect(),
+                            );
+                        }
+                        Some(XdpCommand::Configure(config)) => write_config(&mut bpf, &config),
+                        Some(XdpCommand::Block(ip)) => update_blocklist(&mut bpf, ip, true),
+                        Some(XdpCommand::Unblock(ip)) => update_blocklist(&mut bpf, ip, false),
+                        Some(XdpCommand::Stats(reply)) => {
+                            let _ = reply.send(read_stats(&bpf));
+                        }
                     }
-                },
-                None => error!("XDP Map 'ALLOWED_PORTS' not found in eBPF program!"),
+                    continue;
+                }
             }
+            elapsed_secs += STATS_INTERVAL_SECS;
 
-            // --- Garbage Collection Loop ---
-            loop {
-                // Sleep for 3 minutes
-                tokio::time::sleep(std::time::Duration::from_secs(180)).await;
-
-                // Perform GC
-                if let Some(map) = bpf.map_mut("RATE_LIMIT_MAP") {
-                    // Try to borrow as HashMap. 
-                    // Note: In aya, maps are not async, so this might block slightly, but it's user space.
-                    // u32 key (src_ip), RateLimitEntry value (need to define struct layout or read raw bytes)
-                    // Simplified: We assume we can access it using the PerCpuHashMap or HashMap wrapper.
-                    // However, we need the exact struct definition from ebpf crate to decode "RateLimitEntry".
-                    // Since we can't easily import "RateLimitEntry" from the ebpf crate here without a dependency cycle 
-                    // or code duplication, and given this is a quick fix, let's use the raw primitive approach if possible, 
-                    // OR (better) just clean by time if we can decode the struct similarly to how eBPF does.
-
-                    // To avoid dependency complexity, we define a local POD struct matching the eBPF one.
-                    #[repr(C)]
-                    #[derive(Clone, Copy)]
-                    struct RateLimitEntry {
-                        pub last_time_ns: u64,
-                        pub count: u32,
-                    }
-                    // Safety: Must match eBPF definition exactly.
-                    unsafe impl aya::Pod for RateLimitEntry {}
+            // 输出本周期的增量
+            if let Some(stats) = read_stats(&bpf) {
+                if stats != last_stats {
+                    info!(
+                        "📊 XDP: SYN 丢弃 {} (+{}), 非法标志丢弃 {} (+{}), UDP 丢弃 {} (+{}), 黑名单丢弃 {} (+{}), 放行 {} (+{})",
+                        stats.syn_dropped,
+                        stats.syn_dropped.saturating_sub(last_stats.syn_dropped),
+                        stats.illegal_flags_dropped,
+                        stats.illegal_flags_dropped.saturating_sub(last_stats.illegal_flags_dropped),
+                        stats.udp_dropped,
+                        stats.udp_dropped.saturating_sub(last_stats.udp_dropped),
+                        stats.blocked,
+                        stats.blocked.saturating_sub(last_stats.blocked),
+                        stats.passed,
+                        stats.passed.saturating_sub(last_stats.passed),
+                    );
+                    last_stats = stats;
+                }
+            }
+
+            if elapsed_secs < GC_INTERVAL_SECS {
+                continue;
+            }
+            elapsed_secs = 0;
 
-                    // Wrap the map
-                    let limit_map_result: Result<HashMap<_, u32, RateLimitEntry>, _> = HashMap::try_from(map);
+            // Perform GC (SYN 与 UDP 限速表结构相同)
+            for map_name in RATE_LIMIT_MAPS {
+                if let Some(map) = bpf.map_mut(map_name) {
+                    // 限速表是 PerCpuHashMap，每个键对应所有 CPU 上的记录 (阈值语义见 eBPF 程序)
+                    let limit_map_result: Result<PerCpuHashMap<_, u32, RateLimitEntry>, _> =
+                        PerCpuHashMap::try_from(map);
 
                     match limit_map_result {
                         Ok(mut limit_map) => {
                             let mut keys_to_remove = Vec::new();
-                            // Kernel uses CLOCK_MONOTONIC (bpf_ktime_get_ns).
-                            // We need a comparable timestamp. Rust's Instant::now() often maps to CLOCK_MONOTONIC.
-                            // But to be precise, we should diff against the "last_time_ns" recorded.
-                            // Actually, bpf_ktime_get_ns() is usually boot time. 
-                            // std::time::Instant uses an opaque value, but we can check elapsed time.
-                            //
-                            // WAIT: The eBPF store `last_time_ns` from `bpf_ktime_get_ns()`.
-                            // User space cannot easily get the EXACT same clock reference without `libc::clock_gettime(CLOCK_MONOTONIC, ...)`.
-                            //
-                            // Let's use a heuristic: Any entry not updated deeply in the past is stale.
-                            // BUT: user space doesn't know "now" in eBPF terms effortlessly.
-                            // 
-                            // ALTERNATIVE: Use uptime.
-                            // `bpf_ktime_get_ns()` returns nanoseconds since boot.
-                            // In Rust, we can get uptime from `/proc/uptime` or using `libc`.
-                            //
-                            // Let's us `nix` or `libc` if available, or just read /proc/uptime for simplicity?
-                            // Or better: std::time::Instant::now() is monotonic.
-                            // But we need the ABSOLUTE value to compare.
-                            //
-                            // Let's try reading /proc/uptime.
-                            if let Ok(uptime_seconds) = std::fs::read_to_string("/proc/uptime") {
-                                if let Some(sec_str) = uptime_seconds.split_whitespace().next() {
-                                    if let Ok(sec_f64) = sec_str.parse::<f64>() {
-                                        let now_ns = (sec_f64 * 1_000_000_000.0) as u64;
-                                        let threshold_ns = now_ns.saturating_sub(180 * 1_000_000_000); // 3 mins ago
-
-                                        // Retrieve keys. HashMap iterator in Aya gives Result<(Key, Value)>.
-                                        // We have to be careful about iteration invalidation.
-                                        // We collect keys first.
-                                        
-                                        // Iterate map. Note: This can be slow if map is HUGE, but 10k entries is fine.
-                                        for item in limit_map.iter() {
-                                            if let Ok((k, v)) = item {
-                                                if v.last_time_ns < threshold_ns {
-                                                    keys_to_remove.push(k);
-                                                }
-                                            }
-                                        }
-                                    }
+                            let Some(now_ns) = monotonic_ns() else {
+                                warn!("GC: clock_gettime(CLOCK_MONOTONIC) failed, skipping {}", map_name);
+                                continue;
+                            };
+
+                            for (k, values) in limit_map.iter().flatten() {
+                                if is_stale(values.iter().map(|v| v.last_time_ns), now_ns) {
+                                    keys_to_remove.push(k);
                                 }
                             }
 
                             if !keys_to_remove.is_empty() {
-                                info!("🧹 GC: Cleaned up {} stale IPs from Rate Limit Map", keys_to_remove.len());
+                                info!("🧹 GC: Cleaned up {} stale IPs from {}", keys_to_remove.len(), map_name);
                                 for k in keys_to_remove {
                                     let _ = limit_map.remove(&k);
                                 }
                             }
                         },
-                        Err(e) => warn!("GC: Failed to access RATE_LIMIT_MAP: {}", e),
+                        Err(e) => warn!("GC: Failed to access {}: {}", map_name, e),
                     }
                 } else {
-                    warn!("GC: RATE_LIMIT_MAP not found");
+                    warn!("GC: {} not found", map_name);
                 }
             }
-        });
+        }
+    });
+
+    XdpHandle { command_tx, attached }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_program_parses() {
+        let object = aya_obj::Object::parse(PROGRAM).unwrap();
+        assert!(object.programs.contains_key("xdp_firewall"));
+        for map in ["ALLOWED_PORTS", "BLOCKLIST", "RATE_LIMIT_MAP", "UDP_RATE_LIMIT_MAP", "CONFIG", "XDP_STATS"] {
+            assert!(object.maps.contains_key(map), "missing map {map}");
+        }
+    }
+
+    #[test]
+    fn test_allowed_ports_types_match_kernel() {
+        let object = aya_obj::Object::parse(PROGRAM).unwrap();
+        let map = &object.maps["ALLOWED_PORTS"];
+        assert_eq!(map.key_size() as usize, std::mem::size_of::<PortKey>());
+        assert_eq!(map.value_size() as usize, std::mem::size_of::<PortValue>());
+        assert_eq!((map.key_size(), map.value_size()), (2, 1));
+    }
+
+    #[test]
+    fn test_gc_cutoff() {
+        let now_ns = monotonic_ns().unwrap();
+        // 刚写入的记录保留
+        assert!(!is_stale([now_ns], now_ns));
+        if now_ns > STALE_ENTRY_NS {
+            let cutoff = now_ns - STALE_ENTRY_NS;
+            assert!(is_stale([cutoff - 1], now_ns));
+            assert!(!is_stale([cutoff], now_ns));
+            // 任一 CPU 上仍有新记录就不删除
+            assert!(!is_stale([0, cutoff - 1, now_ns], now_ns));
+            assert!(is_stale([0, cutoff - 1], now_ns));
+        }
+
+        // 开机不足 STALE_ENTRY_NS: 截止时间饱和为 0，没有记录过期
+        let early = STALE_ENTRY_NS / 2;
+        assert!(!is_stale([0], early));
+        assert!(!is_stale([], early));
+        assert!(is_stale([0], STALE_ENTRY_NS + 1));
+    }
+
+    #[test]
+    fn test_rate_limit_maps_are_per_cpu() {
+        use aya_obj::generated::bpf_map_type::BPF_MAP_TYPE_PERCPU_HASH;
+
+        let object = aya_obj::Object::parse(PROGRAM).unwrap();
+        for name in RATE_LIMIT_MAPS {
+            let map = &object.maps[name];
+            assert_eq!(map.map_type(), BPF_MAP_TYPE_PERCPU_HASH as u32, "{name}");
+            assert_eq!(map.key_size() as usize, std::mem::size_of::<u32>(), "{name}");
+            assert_eq!(map.value_size() as usize, std::mem::size_of::<RateLimitEntry>(), "{name}");
+        }
     }
 }
@@ -0,0 +1,76 @@
+//! 访问日志: 连接结束时写出一行摘要
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+
+mod common;
+use common::spawn_echo;
+
+const UUID: &str = "1d3ae6e0-5a3b-4f52-9c0e-6a8c1f9b2d47";
+
+async fn start_server(access_log: &Path) -> u16 {
+    common::start_server_with(
+        json!({
+            "protocol": "vless",
+            "settings": { "clients": [{ "id": UUID, "email": "alice@example.com" }] },
+            "streamSettings": { "network": "tcp", "security": "none" }
+        }),
+        json!({ "log": { "access": access_log, "accessFormat": "json" } }),
+    )
+    .await
+    .port
+}
+
+#[tokio::test]
+async fn test_connection_summary_line() {
+    let path = std::env::temp_dir().join(format!("xray-lite-access-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let port = start_server(&path).await;
+    let (target, _) = spawn_echo().await;
+
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let source = client.local_addr().unwrap();
+    let request = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::from(target),
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    client.write_all(&request.encode().unwrap()).await.unwrap();
+    client.write_all(b"ping").await.unwrap();
+
+    // VLESS 响应头 (2 字节) + 回显
+    let mut received = [0u8; 6];
+    client.read_exact(&mut received).await.unwrap();
+    assert_eq!(&received, b"\x00\x00ping");
+    // 目标已关闭，客户端读到 EOF 后断开
+    assert_eq!(client.read(&mut [0u8; 1]).await.unwrap(), 0);
+    drop(client);
+
+    let mut line = String::new();
+    for _ in 0..50 {
+        line = std::fs::read_to_string(&path).unwrap_or_default();
+        if !line.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(line.lines().count(), 1, "unexpected access log: {line:?}");
+    let record: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+    assert_eq!(record["source"], source.to_string());
+    assert_eq!(record["user"], "alice@example.com");
+    assert_eq!(record["destination"], format!("tcp:{target}"));
+    // 上行为请求头之后的载荷，下行为回显 (不含 VLESS 响应头)
+    assert_eq!((record["up"].as_u64(), record["down"].as_u64()), (Some(4), Some(4)));
+    assert_eq!(record["reason"], "closed");
+}
@@ -0,0 +1,151 @@
+//! 集成测试共用的服务端启动与测试对端
+//!
+//! 每个测试文件按需 `mod common;`，未用到的工具不算死代码
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::Bytes;
+use h2::client::SendRequest;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use xray_lite::network::{ConnectionManager, Readiness};
+use xray_lite::server::ReloadHandle;
+use xray_lite::{Config, Server};
+
+/// 端口在选定与绑定之间被其他测试占用时的重试次数
+const BIND_ATTEMPTS: usize = 5;
+
+/// 已启动的服务端
+pub struct TestServer {
+    pub port: u16,
+    /// 实际生效的配置 (已填入端口)，可修改后用于热重载
+    pub config: Config,
+    pub connection_manager: ConnectionManager,
+    pub reload_handle: ReloadHandle,
+    #[cfg(feature = "xdp")]
+    pub xdp: Option<xray_lite::xdp::XdpHandle>,
+}
+
+/// 以单个入站启动服务端，返回监听端口
+///
+/// `inbound` 不含 `port`，未指定 `listen` 时监听 127.0.0.1。
+/// 出站为 freedom，测试对端都在本机，因此默认关闭 `blockPrivate`
+pub async fn start_server(inbound: Value) -> u16 {
+    start_server_with(inbound, json!({})).await.port
+}
+
+/// 同 [`start_server`]，`extra` 的顶层字段 (`routing`、`outbounds`、`log`、`connection` 等) 替换默认值
+pub async fn start_server_with(inbound: Value, extra: Value) -> TestServer {
+    for _ in 0..BIND_ATTEMPTS {
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        let mut inbound = inbound.clone();
+        inbound["port"] = json!(port);
+        if inbound.get("listen").is_none() {
+            inbound["listen"] = json!("127.0.0.1");
+        }
+        let mut config = json!({
+            "inbounds": [inbound],
+            "outbounds": [{ "protocol": "freedom", "tag": "direct" }],
+            "routing": { "blockPrivate": false }
+        });
+        for (key, value) in extra.as_object().expect("extra must be a JSON object") {
+            config[key] = value.clone();
+        }
+        let config: Config = serde_json::from_value(config).unwrap();
+        config.validate().unwrap();
+
+        let server = Server::new(config.clone()).unwrap();
+        let health = server.health();
+        let started = TestServer {
+            port,
+            config,
+            connection_manager: server.connection_manager(),
+            reload_handle: server.reload_handle(),
+            #[cfg(feature = "xdp")]
+            xdp: server.xdp(),
+        };
+        let run = tokio::spawn(server.run());
+
+        // 绑定失败时入站任务退出，run 随之返回，换一个端口重试
+        for _ in 0..250 {
+            if health.readiness() == Readiness::Ready {
+                return started;
+            }
+            if run.is_finished() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(run.is_finished(), "server is not listening");
+    }
+    panic!("server failed to bind after {BIND_ATTEMPTS} attempts");
+}
+
+/// 回显一次读到的数据后关闭，任务句柄可用于判断目标是否被连接过
+pub async fn spawn_echo() -> (SocketAddr, tokio::task::JoinHandle<()>) {
+    let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = echo.local_addr().unwrap();
+    let task = tokio::spawn(async move {
+        let (mut stream, _) = echo.accept().await.unwrap();
+        let mut buf = [0u8; 4096];
+        let n = stream.read(&mut buf).await.unwrap();
+        stream.write_all(&buf[..n]).await.unwrap();
+    });
+    (addr, task)
+}
+
+/// 持续回显，对端关闭写方向后同样关闭
+pub async fn spawn_stream_echo() -> SocketAddr {
+    let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = echo.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = echo.accept().await.unwrap();
+        let (mut read, mut write) = stream.split();
+        let _ = tokio::io::copy(&mut read, &mut write).await;
+        let _ = write.shutdown().await;
+    });
+    addr
+}
+
+/// 读满 `want` 字节后交给测试
+pub async fn spawn_sink(want: usize) -> (SocketAddr, tokio::sync::oneshot::Receiver<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut data = vec![0; want];
+        stream.read_exact(&mut data).await.unwrap();
+        let _ = tx.send(data);
+    });
+    (addr, rx)
+}
+
+/// 明文 H2 客户端
+pub async fn h2_client(port: u16) -> SendRequest<Bytes> {
+    let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let (client, connection) = h2::client::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    client
+}
+
+/// 读完响应体并归还流控窗口
+pub async fn read_to_end(body: &mut h2::RecvStream) -> Vec<u8> {
+    let mut received = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.unwrap();
+        body.flow_control().release_capacity(chunk.len()).unwrap();
+        received.extend_from_slice(&chunk);
+    }
+    received
+}
+
+/// 从指定的本地地址连接 (127.0.0.0/8 均可用作来源)
+pub async fn connect_from(local: &str, port: u16) -> TcpStream {
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.bind(format!("{local}:0").parse().unwrap()).unwrap();
+    socket.connect(([127, 0, 0, 1], port).into()).await.unwrap()
+}
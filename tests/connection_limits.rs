@@ -0,0 +1,97 @@
+//! 连接数限制: 每个来源 IP 与全局的客户端连接上限，超出的连接立即关闭
+use std::net::IpAddr;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use xray_lite::network::ConnectionManager;
+
+mod common;
+use common::connect_from;
+
+async fn start_server(connection: Value, accept_proxy_protocol: bool) -> (u16, ConnectionManager) {
+    let server = common::start_server_with(
+        json!({
+            "protocol": "vless",
+            "settings": { "clients": [{ "id": "0d6f4b27-93a1-4c58-b2e7-5f8c1a9d3e60" }] },
+            "streamSettings": {
+                "network": "tcp",
+                "security": "none",
+                "sockopt": { "acceptProxyProtocol": accept_proxy_protocol }
+            }
+        }),
+        json!({ "connection": connection }),
+    )
+    .await;
+    (server.port, server.connection_manager)
+}
+
+/// 等到每个来源 IP 的连接数等于 `expected`
+async fn wait_for(manager: &ConnectionManager, expected: &[(&str, usize)]) {
+    let expected: Vec<(IpAddr, usize)> = expected.iter().map(|(ip, n)| (ip.parse().unwrap(), *n)).collect();
+    for _ in 0..100 {
+        if manager.clients_per_ip() == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("connections per IP: {:?}, expected {:?}", manager.clients_per_ip(), expected);
+}
+
+/// 服务端是否已关闭连接
+async fn is_closed(stream: &mut TcpStream) -> bool {
+    let mut buf = [0u8; 1];
+    match tokio::time::timeout(Duration::from_millis(500), stream.read(&mut buf)).await {
+        Ok(Ok(n)) => n == 0,
+        Ok(Err(_)) => true,
+        Err(_) => false,
+    }
+}
+
+#[tokio::test]
+async fn test_limits_at_accept() {
+    let (port, manager) = start_server(json!({ "maxConnections": 3, "maxConnectionsPerIp": 2 }), false).await;
+
+    let mut first = connect_from("127.0.0.1", port).await;
+    let mut second = connect_from("127.0.0.1", port).await;
+    wait_for(&manager, &[("127.0.0.1", 2)]).await;
+    let mut third = connect_from("127.0.0.1", port).await;
+    assert!(is_closed(&mut third).await);
+
+    // 其他 IP 不受单 IP 上限影响，但受总数限制
+    let mut other = connect_from("127.0.0.2", port).await;
+    wait_for(&manager, &[("127.0.0.1", 2), ("127.0.0.2", 1)]).await;
+    let mut over = connect_from("127.0.0.3", port).await;
+    assert!(is_closed(&mut over).await);
+    assert!(!is_closed(&mut first).await);
+    assert!(!is_closed(&mut other).await);
+
+    // 连接关闭后名额归还
+    drop(first);
+    drop(other);
+    wait_for(&manager, &[("127.0.0.1", 1)]).await;
+    let mut again = connect_from("127.0.0.1", port).await;
+    assert!(!is_closed(&mut again).await);
+    assert!(!is_closed(&mut second).await);
+}
+
+#[tokio::test]
+async fn test_limits_after_proxy_protocol() {
+    let (port, manager) = start_server(json!({ "maxConnectionsPerIp": 1, "limitStage": "handshake" }), true).await;
+
+    // 同一个负载均衡器转发的不同客户端分别计数
+    let mut streams = Vec::new();
+    for client in ["203.0.113.7", "198.51.100.4", "203.0.113.7"] {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream
+            .write_all(format!("PROXY TCP4 {client} 127.0.0.1 40000 {port}\r\n").as_bytes())
+            .await
+            .unwrap();
+        streams.push(stream);
+    }
+    assert!(is_closed(&mut streams[2]).await);
+    assert!(!is_closed(&mut streams[0]).await);
+    assert!(!is_closed(&mut streams[1]).await);
+    wait_for(&manager, &[("198.51.100.4", 1), ("203.0.113.7", 1)]).await;
+}
@@ -0,0 +1,149 @@
+//! 目标地址过滤: 默认阻止私有地址，规则可放行或阻止指定目标
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+
+mod common;
+use common::spawn_echo;
+
+const UUID: &str = "5f0c9a1e-7d42-4b8e-a3c6-2e91b7d4f058";
+
+async fn start_server(routing: Value) -> u16 {
+    start_server_with_sniffing(json!({ "enabled": false }), routing).await
+}
+
+async fn start_server_with_sniffing(sniffing: Value, routing: Value) -> u16 {
+    common::start_server_with(
+        json!({
+            "protocol": "vless",
+            "settings": { "clients": [{ "id": UUID }], "sniffing": sniffing },
+            "streamSettings": { "network": "tcp", "security": "none" }
+        }),
+        json!({
+            "outbounds": [
+                { "protocol": "freedom", "tag": "direct" },
+                { "protocol": "blackhole", "tag": "block" }
+            ],
+            "routing": routing
+        }),
+    )
+    .await
+    .port
+}
+
+/// 经代理向目标发送 "ping"，返回代理关闭连接前收到的全部数据
+async fn relay_ping(port: u16, address: Address) -> Vec<u8> {
+    relay(port, address, b"ping").await
+}
+
+/// 经代理向目标发送 `payload`，返回代理关闭连接前收到的全部数据
+async fn relay(port: u16, address: Address, payload: &[u8]) -> Vec<u8> {
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let request = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address,
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    client.write_all(&request.encode().unwrap()).await.unwrap();
+    client.write_all(payload).await.unwrap();
+
+    let mut received = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut received))
+        .await
+        .expect("proxy did not close the connection")
+        .unwrap_or_default();
+    received
+}
+
+#[tokio::test]
+async fn test_loopback_blocked_by_default() {
+    let port = start_server(json!({})).await;
+    let (target, echo) = spawn_echo().await;
+
+    let received = relay_ping(port, Address::from(target)).await;
+    assert!(!received.ends_with(b"ping"), "blocked target was reached: {received:?}");
+    // 目标从未收到连接
+    assert!(!echo.is_finished());
+    echo.abort();
+
+    // 解析到回环地址的域名同样被阻止
+    let (target, echo) = spawn_echo().await;
+    let received = relay_ping(port, Address::Domain("localhost".into(), target.port())).await;
+    assert!(!received.ends_with(b"ping"), "blocked target was reached: {received:?}");
+    assert!(!echo.is_finished());
+    echo.abort();
+}
+
+#[tokio::test]
+async fn test_allow_rule_overrides_block_private() {
+    let port = start_server(json!({
+        "rules": [
+            { "type": "field", "ip": ["127.0.0.1/32"], "outboundTag": "direct" },
+            { "type": "field", "domain": ["full:localhost"], "outboundTag": "block" }
+        ]
+    }))
+    .await;
+
+    let (target, echo) = spawn_echo().await;
+    assert_eq!(relay_ping(port, Address::from(target)).await, b"\x00\x00ping");
+    echo.await.unwrap();
+
+    // 域名规则在解析前生效
+    let (target, echo) = spawn_echo().await;
+    let received = relay_ping(port, Address::Domain("localhost".into(), target.port())).await;
+    assert!(!received.ends_with(b"ping"), "blocked target was reached: {received:?}");
+    assert!(!echo.is_finished());
+    echo.abort();
+}
+
+/// 以 `server_name` 为 SNI 的 TLS ClientHello
+fn client_hello(server_name: &str) -> Vec<u8> {
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth();
+    let name = rustls_pki_types::ServerName::try_from(server_name.to_string()).unwrap();
+    let mut connection = rustls::ClientConnection::new(Arc::new(config), name).unwrap();
+    let mut hello = Vec::new();
+    connection.write_tls(&mut hello).unwrap();
+    hello
+}
+
+#[tokio::test]
+async fn test_route_only_domain_rule_blocks_ip_target() {
+    let port = start_server_with_sniffing(
+        json!({ "enabled": true, "destOverride": ["tls"], "routeOnly": true }),
+        json!({
+            "rules": [
+                { "type": "field", "domain": ["domain:blocked.example.com"], "outboundTag": "block" },
+                { "type": "field", "ip": ["127.0.0.1/32"], "outboundTag": "direct" }
+            ]
+        }),
+    )
+    .await;
+
+    // 连接目标是 IP，只有嗅探出的 SNI 命中阻止规则
+    let hello = client_hello("www.blocked.example.com");
+    let (target, echo) = spawn_echo().await;
+    let received = relay(port, Address::from(target), &hello).await;
+    assert!(!received.ends_with(&hello), "blocked target was reached");
+    assert!(!echo.is_finished());
+    echo.abort();
+
+    // 其他 SNI 仍连接原始 IP
+    let hello = client_hello("allowed.example.com");
+    let (target, echo) = spawn_echo().await;
+    let received = relay(port, Address::from(target), &hello).await;
+    assert_eq!(&received[2..], &hello[..]);
+    echo.await.unwrap();
+}
@@ -0,0 +1,137 @@
+//! 握手防护: 慢速 ClientHello 不能阻塞正常客户端，回落连接不占用握手槽位
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine as _};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::network::HANDSHAKE_STATS;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+use xray_lite::transport::reality::{RealityClient, RealityClientConfig};
+
+mod common;
+use common::{connect_from, spawn_echo};
+
+const UUID: &str = "b831381d-6324-4d53-ad4f-8cda48b30811";
+const PRIVATE_KEY: [u8; 32] = [b'A'; 32];
+
+async fn start_server() -> u16 {
+    start_server_with("127.0.0.1:9", 16).await
+}
+
+async fn start_server_with(dest: &str, max_handshakes_per_ip: usize) -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "listen": "0.0.0.0",
+        "settings": { "clients": [{ "id": UUID }] },
+        "streamSettings": {
+            "network": "tcp",
+            "security": "reality",
+            "realitySettings": {
+                "dest": dest,
+                "serverNames": ["www.example.com"],
+                "privateKey": general_purpose::URL_SAFE_NO_PAD.encode(PRIVATE_KEY),
+                "shortIds": ["0123456789abcdef"],
+                "handshakeTimeout": 2,
+                "maxHandshakesPerIp": max_handshakes_per_ip
+            }
+        }
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn test_stalled_handshakes_do_not_block_others() {
+    let port = start_server().await;
+
+    let (echo_addr, _) = spawn_echo().await;
+
+    // 100 个只发送了记录头前几个字节就停住的连接
+    let mut stalled = Vec::new();
+    for _ in 0..100 {
+        let mut stream = connect_from("127.0.0.1", port).await;
+        stream.write_all(&[0x16, 0x03, 0x01]).await.unwrap();
+        stalled.push(stream);
+    }
+
+    // 另一来源的正常客户端照常完成握手
+    let client = RealityClient::new(RealityClientConfig {
+        server_name: "www.example.com".to_string(),
+        public_key: general_purpose::URL_SAFE_NO_PAD
+            .encode(PublicKey::from(&StaticSecret::from(PRIVATE_KEY)).as_bytes()),
+        short_id: "0123456789abcdef".to_string(),
+        fingerprint: "chrome".to_string(),
+    })
+    .unwrap();
+    let stream = connect_from("127.0.0.2", port).await;
+    let mut tls = tokio::time::timeout(Duration::from_secs(1), client.connect(stream))
+        .await
+        .expect("handshake was blocked by stalled connections")
+        .unwrap();
+
+    let request = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::from(echo_addr),
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    let mut hello = request.encode().unwrap().to_vec();
+    hello.extend_from_slice(b"ping");
+    tls.write_all(&hello).await.unwrap();
+    tls.flush().await.unwrap();
+    let mut reply = [0u8; 6];
+    tokio::time::timeout(Duration::from_secs(5), tls.read_exact(&mut reply))
+        .await
+        .expect("no reply through the tunnel")
+        .unwrap();
+    assert_eq!(&reply, b"\x00\x00ping");
+
+    // 超出每 IP 上限的连接被直接关闭，其余在超时后关闭
+    let (_, rejects) = HANDSHAKE_STATS.snapshot();
+    assert!(rejects >= 84, "rejects: {}", rejects);
+
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let (timeouts, _) = HANDSHAKE_STATS.snapshot();
+    assert!(timeouts >= 16, "timeouts: {}", timeouts);
+    for mut stream in stalled {
+        let mut buf = [0u8; 1];
+        assert_eq!(stream.read(&mut buf).await.unwrap_or(0), 0);
+    }
+}
+
+#[tokio::test]
+async fn test_fallback_does_not_hold_handshake_slots() {
+    // 回落目标: 每个连接持续回显，直到客户端关闭
+    let dest = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dest_addr = dest.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = dest.accept().await.unwrap();
+            tokio::spawn(async move {
+                let (mut read, mut write) = stream.split();
+                let _ = tokio::io::copy(&mut read, &mut write).await;
+            });
+        }
+    });
+    let port = start_server_with(&dest_addr, 4).await;
+
+    // 同一来源超过握手上限的回落连接同时保持打开，每个都被转发到 dest
+    let mut relayed = Vec::new();
+    for i in 0..5 {
+        let mut stream = connect_from("127.0.0.3", port).await;
+        let probe = format!("GET /{i} HTTP/1.1\r\n\r\n");
+        stream.write_all(probe.as_bytes()).await.unwrap();
+        let mut reply = vec![0u8; probe.len()];
+        tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut reply))
+            .await
+            .unwrap_or_else(|_| panic!("fallback connection {i} was not relayed"))
+            .unwrap_or_else(|e| panic!("fallback connection {i} was closed: {e}"));
+        assert_eq!(reply, probe.as_bytes());
+        relayed.push(stream);
+    }
+}
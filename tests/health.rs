@@ -0,0 +1,72 @@
+//! 健康检查端点: 启动中 503，监听器就绪后 200，停机排空期间 503
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use xray_lite::network::health;
+use xray_lite::{Config, Server};
+
+async fn probe(port: u16, method: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all(format!("{method} /health HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    response
+}
+
+async fn wait_for(port: u16, status: &str) -> String {
+    for _ in 0..100 {
+        let response = probe(port, "GET").await;
+        if response.starts_with(status) {
+            return response;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("health endpoint never returned {status}");
+}
+
+#[tokio::test]
+async fn test_health_follows_server_lifecycle() {
+    let inbound = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+    let config: Config = serde_json::from_str(&format!(
+        r#"{{
+            "inbounds": [{{
+                "protocol": "vless",
+                "listen": "127.0.0.1",
+                "port": {inbound},
+                "settings": {{ "clients": [{{ "id": "5e0c7a92-3b1f-4d68-8a2e-c4f9b1d70e35" }}] }},
+                "streamSettings": {{ "network": "tcp", "security": "none" }}
+            }}],
+            "outbounds": [{{ "protocol": "freedom", "tag": "direct" }}]
+        }}"#
+    ))
+    .unwrap();
+    let server = Server::new(config).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(health::serve(listener, server.health()));
+
+    let response = probe(port, "GET").await;
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nstarting"), "{}", response);
+
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let run = tokio::spawn(server.run_until(async { let _ = stop_rx.await; }, Duration::from_secs(1)));
+    let response = wait_for(port, "HTTP/1.1 200 OK").await;
+    assert!(response.ends_with("\r\n\r\nready"), "{}", response);
+    assert!(probe(port, "HEAD").await.ends_with("Content-Length: 5\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n"));
+    assert!(probe(port, "POST").await.starts_with("HTTP/1.1 405"));
+
+    // 停机开始后保持 503，直到进程退出
+    stop_tx.send(()).unwrap();
+    let response = wait_for(port, "HTTP/1.1 503").await;
+    assert!(response.ends_with("\r\n\r\ndraining"), "{}", response);
+    run.await.unwrap().unwrap();
+}
@@ -0,0 +1,78 @@
+//! SSLKEYLOGFILE: 握手两端都按 NSS 格式写出密钥
+//!
+//! 环境变量在首次握手时读取，因此本文件只有一个测试。
+use base64::{engine::general_purpose, Engine as _};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::transport::reality::{keylog, Accepted, RealityClient, RealityClientConfig, RealityConfig, RealityServer};
+
+const PRIVATE_KEY: [u8; 32] = [b'A'; 32];
+
+#[tokio::test]
+async fn test_key_log_written_for_both_sides() {
+    let path = std::env::temp_dir().join(format!("xray-lite-keylog-{}.txt", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    std::env::set_var("SSLKEYLOGFILE", &path);
+    assert!(keylog::enabled());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let reality = RealityServer::new(RealityConfig {
+        session_tickets: false,
+        ..RealityConfig::new(
+            "127.0.0.1:9",
+            vec!["www.example.com".to_string()],
+            general_purpose::STANDARD.encode(PRIVATE_KEY),
+            vec!["01".to_string()],
+        )
+    })
+    .unwrap();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let Ok(Accepted::Reality(mut tls)) = reality.accept(stream).await else {
+            panic!("client was not authenticated");
+        };
+        let mut buf = [0u8; 4];
+        tls.read_exact(&mut buf).await.unwrap();
+    });
+
+    let client = RealityClient::new(RealityClientConfig {
+        server_name: "www.example.com".to_string(),
+        public_key: general_purpose::STANDARD.encode(PublicKey::from(&StaticSecret::from(PRIVATE_KEY)).as_bytes()),
+        short_id: "01".to_string(),
+        fingerprint: "chrome".to_string(),
+    })
+    .unwrap();
+    let mut tls = client.connect(TcpStream::connect(addr).await.unwrap()).await.unwrap();
+    tls.write_all(b"ping").await.unwrap();
+    tls.flush().await.unwrap();
+    server.await.unwrap();
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    // rustls 服务端另写一行 EXPORTER_SECRET，客户端不导出该密钥
+    let lines: Vec<Vec<&str>> = log
+        .lines()
+        .map(|l| l.split(' ').collect::<Vec<_>>())
+        .filter(|l| l[0] != "EXPORTER_SECRET")
+        .collect();
+    // 客户端与服务端各写 4 行，同一个 client random
+    assert_eq!(lines.len(), 8, "{}", log);
+    for line in &lines {
+        assert_eq!(line.len(), 3);
+        assert_eq!(line[1], lines[0][1]);
+        assert_eq!(line[1].len(), 64);
+        assert_eq!(line[2].len(), 64);
+    }
+    for label in [
+        keylog::CLIENT_HANDSHAKE_TRAFFIC_SECRET,
+        keylog::SERVER_HANDSHAKE_TRAFFIC_SECRET,
+        keylog::CLIENT_TRAFFIC_SECRET_0,
+        keylog::SERVER_TRAFFIC_SECRET_0,
+    ] {
+        let secrets: Vec<&str> = lines.iter().filter(|l| l[0] == label).map(|l| l[2]).collect();
+        assert_eq!(secrets.len(), 2, "{}", label);
+        assert_eq!(secrets[0], secrets[1], "{}", label);
+    }
+}
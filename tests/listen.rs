@@ -0,0 +1,54 @@
+//! 入站监听多个地址与 IPv6 双栈设置
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+mod common;
+
+async fn start_server(listen: Value, sockopt: Value) -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "listen": listen,
+        "settings": { "clients": [{ "id": "95c1e4a8-3b7d-4f26-8e0a-d2f9b6c7a134" }] },
+        "streamSettings": {
+            "network": "http",
+            "security": "none",
+            "xhttpSettings": { "path": "/xhttp" },
+            "sockopt": sockopt
+        }
+    }))
+    .await
+}
+
+/// 经 `addr` 发送一个 HTTP/1.1 探测请求，返回状态行
+async fn probe(addr: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /index.html HTTP/1.1\r\nHost: cdn.example.com\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+    response.lines().next().unwrap_or_default().to_string()
+}
+
+#[tokio::test]
+async fn test_listen_on_multiple_addresses() {
+    let port = start_server(json!(["127.0.0.1", "::1"]), json!({})).await;
+    for ip in ["127.0.0.1", "::1"] {
+        let addr = SocketAddr::new(ip.parse().unwrap(), port);
+        assert_eq!(probe(addr).await, "HTTP/1.1 404 Not Found", "{addr}");
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_v6_only_controls_dual_stack() {
+    let ipv4 = |port| SocketAddr::new("127.0.0.1".parse().unwrap(), port);
+
+    let port = start_server(json!("::"), json!({ "v6Only": false })).await;
+    assert_eq!(probe(ipv4(port)).await, "HTTP/1.1 404 Not Found");
+
+    let port = start_server(json!("::"), json!({ "v6Only": true })).await;
+    assert!(TcpStream::connect(ipv4(port)).await.is_err());
+}
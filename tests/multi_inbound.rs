@@ -0,0 +1,92 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream};
+use xray_lite::{Config, Server};
+
+/// 获取一个当前空闲的本地端口
+async fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+/// 两个入站: 443 风格的 VLESS+Reality 与独立端口上的明文 XHTTP
+fn two_inbounds(reality_port: u16, xhttp_port: u16) -> Config {
+    let json = format!(
+        r#"{{
+            "inbounds": [
+                {{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": {reality_port},
+                    "settings": {{
+                        "clients": [{{ "id": "b831381d-6324-4d53-ad4f-8cda48b30811", "email": "reality@example.com" }}]
+                    }},
+                    "streamSettings": {{
+                        "network": "tcp",
+                        "security": "reality",
+                        "realitySettings": {{
+                            "dest": "www.microsoft.com:443",
+                            "serverNames": ["www.microsoft.com"],
+                            "privateKey": "QUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUE",
+                            "shortIds": ["0123456789abcdef"]
+                        }}
+                    }}
+                }},
+                {{
+                    "protocol": "vless",
+                    "listen": "127.0.0.1",
+                    "port": {xhttp_port},
+                    "settings": {{
+                        "clients": [{{ "id": "1d3ae6e0-5a3b-4f52-9c0e-6a8c1f9b2d47", "email": "xhttp@example.com" }}]
+                    }},
+                    "streamSettings": {{
+                        "network": "http",
+                        "security": "none",
+                        "xhttpSettings": {{ "mode": "auto", "path": "/xhttp" }}
+                    }}
+                }}
+            ],
+            "outbounds": [{{ "protocol": "freedom", "tag": "direct" }}],
+            "routing": {{ "blockPrivate": false }}
+        }}"#
+    );
+    let config: Config = serde_json::from_str(&json).unwrap();
+    config.validate().unwrap();
+    config
+}
+
+async fn wait_for_listener(addr: SocketAddr) -> bool {
+    for _ in 0..50 {
+        if TcpStream::connect(addr).await.is_ok() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_two_inbounds_bind() {
+    let reality_port = free_port().await;
+    let xhttp_port = free_port().await;
+    let server = Server::new(two_inbounds(reality_port, xhttp_port)).unwrap();
+
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(server.run_until(
+        async {
+            let _ = stop_rx.await;
+        },
+        Duration::ZERO,
+    ));
+
+    assert!(wait_for_listener(([127, 0, 0, 1], reality_port).into()).await);
+    assert!(wait_for_listener(([127, 0, 0, 1], xhttp_port).into()).await);
+
+    stop_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), server_task)
+        .await
+        .expect("server did not stop")
+        .unwrap()
+        .unwrap();
+}
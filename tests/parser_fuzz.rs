@@ -0,0 +1,139 @@
+//! 解析器的随机输入测试: 外部可达的 ClientHello 解析与嗅探不能 panic，且总能返回
+//!
+//! 默认每个用例跑几万个输入；设置 `PARSER_FUZZ_ITERATIONS` 可以跑得更久，
+//! `PARSER_FUZZ_SEED` 可以复现某个失败的种子。
+use std::sync::mpsc;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use xray_lite::protocol::sniffer::{sniff, sniff_tls_sni};
+use xray_lite::transport::reality::hello_parser::{parse_client_hello, parse_client_hello_message, ClientHelloReader};
+
+/// RFC 8448 §3 的 ClientHello，外加一个 SNI 扩展中的长度字段用于变异
+const CLIENT_HELLO: &str = concat!(
+    "010000c00303cb34ecb1e78163ba1c38c6dacb196a6dffa21a8d9912ec18a2ef6283024dece7000006130113031302",
+    "010000910000000b0009000006736572766572ff01000100000a00140012001d00170018001901000101010201030104",
+    "00230000003300260024001d002099381de560e4bd43d23d8e435a7dbafeb3c06e51c13cae4d5413691e529aaf2c002b",
+    "0003020304000d0020001e040305030603020308040805080604010501060102010402050206020202002d0002010100",
+    "1c00024001",
+);
+
+fn iterations() -> usize {
+    std::env::var("PARSER_FUZZ_ITERATIONS").ok().and_then(|v| v.parse().ok()).unwrap_or(20_000)
+}
+
+fn seed() -> u64 {
+    std::env::var("PARSER_FUZZ_SEED").ok().and_then(|v| v.parse().ok()).unwrap_or(0x5eed_c1e7)
+}
+
+/// 带记录头的合法 ClientHello
+fn client_hello_record() -> Vec<u8> {
+    let message = hex::decode(CLIENT_HELLO).unwrap();
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    record.extend_from_slice(&message);
+    record
+}
+
+/// 对合法输入做随机变异: 改字节、写入边界长度、截断、插入与删除
+fn mutate(rng: &mut StdRng, seed: &[u8]) -> Vec<u8> {
+    let mut data = seed.to_vec();
+    for _ in 0..rng.gen_range(1..8) {
+        if data.is_empty() {
+            data.push(rng.gen());
+            continue;
+        }
+        let pos = rng.gen_range(0..data.len());
+        match rng.gen_range(0..6) {
+            0 => data[pos] = rng.gen(),
+            1 => data[pos] ^= 1 << rng.gen_range(0..8),
+            // 长度字段常见的越界取值
+            2 => {
+                let value: u16 = *[0, 1, 0x7fff, 0xffff, data.len() as u16].get(rng.gen_range(0..5)).unwrap();
+                let end = (pos + 2).min(data.len());
+                data[pos..end].copy_from_slice(&value.to_be_bytes()[..end - pos]);
+            }
+            3 => data.truncate(pos),
+            4 => {
+                let extra: Vec<u8> = (0..rng.gen_range(1..64)).map(|_| rng.gen()).collect();
+                data.splice(pos..pos, extra);
+            }
+            _ => {
+                let end = (pos + rng.gen_range(1..16)).min(data.len());
+                data.drain(pos..end);
+            }
+        }
+    }
+    data
+}
+
+/// 把一个输入喂给所有解析入口，只关心它们不 panic
+fn exercise(data: &[u8]) {
+    let _ = sniff(data);
+    let _ = sniff_tls_sni(data);
+
+    let _ = parse_client_hello_message(data.to_vec());
+    let _ = parse_client_hello_message(data.get(5..).unwrap_or_default().to_vec());
+
+    let _ = parse_client_hello(data);
+    // 逐段到达的数据
+    let mut reader = ClientHelloReader::default();
+    let step = data.len() / 3 + 1;
+    for end in (step..data.len()).step_by(step).chain([data.len()]) {
+        if reader.advance(&data[..end]).is_err() {
+            break;
+        }
+    }
+}
+
+/// 在独立线程中运行，超时视为死循环
+fn run_bounded(name: &'static str, fuzz: impl FnOnce() + Send + 'static) {
+    let (tx, rx) = mpsc::channel();
+    let worker = std::thread::spawn(move || {
+        fuzz();
+        let _ = tx.send(());
+    });
+    match rx.recv_timeout(Duration::from_secs(120)) {
+        Ok(()) => worker.join().unwrap(),
+        // 线程 panic 时发送端被 drop，join 得到 panic 信息
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            if let Err(panic) = worker.join() {
+                std::panic::resume_unwind(panic);
+            }
+        }
+        Err(mpsc::RecvTimeoutError::Timeout) => panic!("{name}: parser did not terminate"),
+    }
+}
+
+#[test]
+fn test_random_bytes_never_panic() {
+    run_bounded("random bytes", || {
+        let mut rng = StdRng::seed_from_u64(seed());
+        for _ in 0..iterations() {
+            let len = rng.gen_range(0..600);
+            let mut data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            // 一半输入带上 TLS 握手记录头与 ClientHello 类型，走得更深
+            if rng.gen() && data.len() >= 6 {
+                data[..6].copy_from_slice(&[0x16, 0x03, 0x01, (len >> 8) as u8, len as u8, 0x01]);
+            }
+            exercise(&data);
+        }
+    });
+}
+
+#[test]
+fn test_mutated_client_hello_never_panics() {
+    run_bounded("mutated ClientHello", || {
+        let record = client_hello_record();
+        // 变异前的输入必须能被完整解析，否则变异测试覆盖不到深层代码
+        let hello = parse_client_hello(&record).unwrap().unwrap();
+        assert!(hello.public_key.is_some());
+        assert_eq!(sniff_tls_sni(&record).as_deref(), Some("server"));
+
+        let mut rng = StdRng::seed_from_u64(seed());
+        for _ in 0..iterations() {
+            exercise(&mutate(&mut rng, &record));
+        }
+    });
+}
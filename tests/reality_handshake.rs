@@ -0,0 +1,586 @@
+//! 以逐条构造的 TLS 1.3 客户端驱动 Reality 服务端
+//!
+//! rustls 客户端无法构造错误的 Finished、0-RTT 记录或超大 ClientHello，这里手工完成握手，
+//! 密钥计划使用 `crypto::TlsKeys` (已对照 RFC 8448 校验)。
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use ring::{aead, hkdf};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::network::HANDSHAKE_STATS;
+use xray_lite::transport::reality::crypto::{CipherSuite, HandshakeSecrets, TlsKeys, TranscriptHash};
+use xray_lite::transport::reality::{Accepted, RealityConfig, RealityServer};
+
+const PRIVATE_KEY: [u8; 32] = [0x42; 32];
+const SHORT_ID: &str = "0123456789abcdef";
+const SERVER_NAME: &str = "www.example.com";
+/// TLS_AES_128_GCM_SHA256、TLS_AES_256_GCM_SHA384、TLS_CHACHA20_POLY1305_SHA256
+const SUITES: [u16; 3] = [0x1301, 0x1302, 0x1303];
+/// warning(1) close_notify(0)
+const CLOSE_NOTIFY: [u8; 2] = [1, 0];
+
+fn reality_config() -> RealityConfig {
+    RealityConfig::new(
+        // 认证失败的客户端回落到这里，连接被拒绝
+        "127.0.0.1:9",
+        vec![SERVER_NAME.to_string()],
+        general_purpose::URL_SAFE_NO_PAD.encode(PRIVATE_KEY),
+        vec![SHORT_ID.to_string()],
+    )
+}
+
+/// 接受一个连接；通过认证时把收到的数据原样写回，直到对端关闭
+async fn spawn_echo_server(config: RealityConfig) -> Result<(std::net::SocketAddr, JoinHandle<Result<()>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let reality = RealityServer::new(config)?;
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        let Accepted::Reality(mut tls) = reality.accept(stream).await? else {
+            return Err(anyhow!("client was not authenticated"));
+        };
+        let mut buf = vec![0u8; 4096];
+        loop {
+            let n = tls.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            tls.write_all(&buf[..n]).await?;
+            tls.flush().await?;
+        }
+    });
+    Ok((addr, server))
+}
+
+fn extension(ext_type: u16, data: &[u8]) -> Vec<u8> {
+    let mut out = ext_type.to_be_bytes().to_vec();
+    out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+/// session_id 仍为全零的 ClientHello 握手消息，`extra` 追加在扩展列表末尾
+fn client_hello(random: &[u8; 32], key_share: &[u8; 32], suites: &[u16], extra: &[Vec<u8>]) -> Vec<u8> {
+    let mut sni = vec![0, (SERVER_NAME.len() + 3) as u8, 0, 0, SERVER_NAME.len() as u8];
+    sni.extend_from_slice(SERVER_NAME.as_bytes());
+    let mut shares = vec![0x00, 0x24, 0x00, 0x1d, 0x00, 0x20];
+    shares.extend_from_slice(key_share);
+
+    let mut extensions = [
+        extension(0x0000, &sni),
+        extension(0x000a, &[0x00, 0x02, 0x00, 0x1d]),
+        extension(0x000d, &[0x00, 0x04, 0x08, 0x07, 0x04, 0x03]),
+        extension(0x002b, &[0x02, 0x03, 0x04]),
+        extension(0x002d, &[0x01, 0x01]),
+        extension(0x0033, &shares),
+    ]
+    .concat();
+    extensions.extend(extra.concat());
+
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(random);
+    body.push(32);
+    body.extend_from_slice(&[0u8; 32]);
+    body.extend_from_slice(&((suites.len() * 2) as u16).to_be_bytes());
+    for suite in suites {
+        body.extend_from_slice(&suite.to_be_bytes());
+    }
+    body.extend_from_slice(&[0x01, 0x00]);
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extensions);
+
+    let mut hello = vec![0x01];
+    hello.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    hello.extend_from_slice(&body);
+    hello
+}
+
+/// 已封装 session_id 的 ClientHello 与客户端的 X25519 私钥
+struct SealedHello {
+    message: Vec<u8>,
+    secret: StaticSecret,
+}
+
+/// 按 xray-core 客户端的方式把 [版本 | 时间 | short id] 封装进 session_id
+fn sealed_hello(suites: &[u16], extra: &[Vec<u8>]) -> SealedHello {
+    let server_public = PublicKey::from(&StaticSecret::from(PRIVATE_KEY));
+    let secret = StaticSecret::random_from_rng(rand::rngs::OsRng);
+    let random: [u8; 32] = rand::random();
+    let mut message = client_hello(&random, PublicKey::from(&secret).as_bytes(), suites, extra);
+
+    let mut auth_key = [0u8; 32];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, &random[..20])
+        .extract(secret.diffie_hellman(&server_public).as_bytes())
+        .expand(&[b"REALITY"], hkdf::HKDF_SHA256)
+        .unwrap()
+        .fill(&mut auth_key)
+        .unwrap();
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as u32;
+    let mut plaintext = vec![1, 8, 1, 0];
+    plaintext.extend_from_slice(&now.to_be_bytes());
+    plaintext.extend_from_slice(&hex::decode(SHORT_ID).unwrap());
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &auth_key).unwrap());
+    let nonce = aead::Nonce::try_assume_unique_for_key(&random[20..]).unwrap();
+    key.seal_in_place_append_tag(nonce, aead::Aad::from(&message), &mut plaintext).unwrap();
+    message[39..71].copy_from_slice(&plaintext);
+    SealedHello { message, secret }
+}
+
+fn record(content_type: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![content_type, 0x03, 0x03];
+    out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+async fn read_record(stream: &mut TcpStream) -> Result<([u8; 5], Vec<u8>)> {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header).await?;
+    let mut body = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize];
+    stream.read_exact(&mut body).await?;
+    Ok((header, body))
+}
+
+/// 服务端第一轮发送的内容，以及客户端据此推出的握手密钥
+struct ServerFlight {
+    server_hello: Vec<u8>,
+    suite: CipherSuite,
+    /// 客户端视角: `encrypt_server_record` 用客户端密钥加密，`decrypt_client_record` 解密服务端记录
+    keys: TlsKeys,
+    secrets: HandshakeSecrets,
+    /// 截至 CertificateVerify，用于校验服务端 Finished
+    certificate_verify_hash: Vec<u8>,
+    /// 截至服务端 Finished，用于客户端 Finished 与应用密钥
+    finished_hash: Vec<u8>,
+    /// 服务端 Finished 的 verify_data
+    server_verify_data: Vec<u8>,
+}
+
+/// 读取 ServerHello 到服务端 Finished，不做任何校验
+async fn read_server_flight(stream: &mut TcpStream, hello: &SealedHello) -> Result<ServerFlight> {
+    let (header, server_hello) = read_record(stream).await?;
+    if header[0] != 0x16 || server_hello.first() != Some(&2) {
+        return Err(anyhow!("expected ServerHello, got record {:02x?}", &header));
+    }
+    let suite = CipherSuite::from_u16(u16::from_be_bytes([server_hello[71], server_hello[72]]))
+        .ok_or_else(|| anyhow!("unknown cipher suite"))?;
+    let mut exts = &server_hello[76..];
+    let mut server_share = None;
+    while exts.len() >= 4 {
+        let ext_type = u16::from_be_bytes([exts[0], exts[1]]);
+        let len = u16::from_be_bytes([exts[2], exts[3]]) as usize;
+        if ext_type == 0x0033 {
+            server_share = Some(<[u8; 32]>::try_from(&exts[8..4 + len])?);
+        }
+        exts = &exts[4 + len..];
+    }
+    let server_share = PublicKey::from(server_share.ok_or_else(|| anyhow!("no key share"))?);
+
+    let (ccs, _) = read_record(stream).await?;
+    assert_eq!(ccs[0], 0x14, "middlebox compatibility ChangeCipherSpec");
+
+    let mut transcript = TranscriptHash::new(suite);
+    transcript.add(&hello.message);
+    transcript.add(&server_hello);
+    let shared = hello.secret.diffie_hellman(&server_share);
+    let (keys, secrets) = TlsKeys::derive_handshake_keys(suite, shared.as_bytes(), &transcript.current_hash())?;
+    let keys = keys.into_peer_view();
+
+    let mut seq = 0;
+    loop {
+        let (header, mut body) = read_record(stream).await?;
+        let (content_type, len) = keys.decrypt_client_record(seq, &header, &mut body)?;
+        seq += 1;
+        assert_eq!(content_type, 22);
+        let mut data = &body[..len];
+        while data.len() >= 4 {
+            let msg_len = 4 + u32::from_be_bytes([0, data[1], data[2], data[3]]) as usize;
+            let (message, rest) = data.split_at(msg_len);
+            data = rest;
+            let before = transcript.current_hash();
+            transcript.add(message);
+            if message[0] == 20 {
+                return Ok(ServerFlight {
+                    server_hello: server_hello.clone(),
+                    suite,
+                    keys,
+                    secrets,
+                    certificate_verify_hash: before,
+                    finished_hash: transcript.current_hash(),
+                    server_verify_data: message[4..].to_vec(),
+                });
+            }
+        }
+    }
+}
+
+impl ServerFlight {
+    /// 以客户端握手流量密钥计算的 Finished 消息
+    fn client_finished(&self) -> Result<Vec<u8>> {
+        let verify_data =
+            TlsKeys::calculate_verify_data(self.suite, &self.secrets.client_traffic_secret, &self.finished_hash)?;
+        let mut message = vec![20, 0, 0, verify_data.len() as u8];
+        message.extend_from_slice(&verify_data);
+        Ok(message)
+    }
+
+    /// ServerHello 的扩展类型
+    fn server_hello_extensions(&self) -> Vec<u16> {
+        let mut exts = &self.server_hello[76..];
+        let mut types = Vec::new();
+        while exts.len() >= 4 {
+            types.push(u16::from_be_bytes([exts[0], exts[1]]));
+            exts = &exts[4 + u16::from_be_bytes([exts[2], exts[3]]) as usize..];
+        }
+        types
+    }
+
+    /// 客户端视角的应用流量密钥
+    fn application_keys(&self) -> Result<TlsKeys> {
+        Ok(TlsKeys::derive_application_keys(&self.secrets, &self.finished_hash)?.into_peer_view())
+    }
+}
+
+/// 连接、发送 ClientHello 并读取服务端第一轮
+async fn connect(addr: std::net::SocketAddr, hello: &SealedHello) -> Result<(TcpStream, ServerFlight)> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(&record(0x16, &hello.message)).await?;
+    let flight = tokio::time::timeout(Duration::from_secs(5), read_server_flight(&mut stream, hello)).await??;
+    Ok((stream, flight))
+}
+
+/// 读取下一条应用数据，跳过 NewSessionTicket，返回 (内容类型, 明文)
+async fn read_application(stream: &mut TcpStream, keys: &TlsKeys, seq: &mut u64) -> Result<(u8, Vec<u8>)> {
+    loop {
+        let (header, mut body) = read_record(stream).await?;
+        let (content_type, len) = keys.decrypt_client_record(*seq, &header, &mut body)?;
+        *seq += 1;
+        body.truncate(len);
+        if content_type == 22 && body.first() == Some(&4) {
+            continue;
+        }
+        return Ok((content_type, body));
+    }
+}
+
+#[tokio::test]
+async fn test_finished_uses_each_sides_handshake_secret() -> Result<()> {
+    let (addr, server) = spawn_echo_server(reality_config()).await?;
+    let hello = sealed_hello(&SUITES, &[]);
+    let (mut stream, flight) = connect(addr, &hello).await?;
+
+    // 服务端 Finished 只能用服务端握手流量密钥验证
+    TlsKeys::verify_finished(
+        flight.suite,
+        &flight.secrets.server_traffic_secret,
+        &flight.certificate_verify_hash,
+        &flight.server_verify_data,
+    )?;
+    assert!(TlsKeys::verify_finished(
+        flight.suite,
+        &flight.secrets.client_traffic_secret,
+        &flight.certificate_verify_hash,
+        &flight.server_verify_data,
+    )
+    .is_err());
+
+    // 以客户端握手流量密钥计算的 Finished 被接受，随后可以交换应用数据
+    stream.write_all(&flight.keys.encrypt_server_record(0, &flight.client_finished()?, 22)?).await?;
+    let app_keys = flight.application_keys()?;
+    stream.write_all(&app_keys.encrypt_server_record(0, b"ping", 23)?).await?;
+    let reply = tokio::time::timeout(Duration::from_secs(5), read_application(&mut stream, &app_keys, &mut 0)).await??;
+    assert_eq!(reply, (23, b"ping".to_vec()));
+
+    stream.write_all(&app_keys.encrypt_server_record(1, &CLOSE_NOTIFY, 21)?).await?;
+    server.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_forged_client_finished_is_rejected_with_decrypt_error() -> Result<()> {
+    let (addr, server) = spawn_echo_server(reality_config()).await?;
+    let hello = sealed_hello(&SUITES, &[]);
+    let (mut stream, flight) = connect(addr, &hello).await?;
+
+    let mut finished = flight.client_finished()?;
+    *finished.last_mut().unwrap() ^= 1;
+    stream.write_all(&flight.keys.encrypt_server_record(0, &finished, 22)?).await?;
+
+    // 服务端已切换到应用流量密钥，致命告警 decrypt_error(51) 以此加密
+    let (header, mut body) = tokio::time::timeout(Duration::from_secs(5), read_record(&mut stream)).await??;
+    let (content_type, len) = flight.application_keys()?.decrypt_client_record(0, &header, &mut body)?;
+    assert_eq!((content_type, &body[..len]), (21, &[2u8, 51][..]));
+    assert!(server.await?.is_err(), "a forged Finished must not complete the handshake");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_finished_fragmented_across_records() -> Result<()> {
+    let (addr, server) = spawn_echo_server(reality_config()).await?;
+    let hello = sealed_hello(&SUITES, &[]);
+    let (mut stream, flight) = connect(addr, &hello).await?;
+
+    let finished = flight.client_finished()?;
+    let (head, tail) = finished.split_at(7);
+    stream.write_all(&flight.keys.encrypt_server_record(0, head, 22)?).await?;
+    stream.flush().await?;
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    stream.write_all(&flight.keys.encrypt_server_record(1, tail, 22)?).await?;
+
+    let app_keys = flight.application_keys()?;
+    stream.write_all(&app_keys.encrypt_server_record(0, b"ping", 23)?).await?;
+    let reply = tokio::time::timeout(Duration::from_secs(5), read_application(&mut stream, &app_keys, &mut 0)).await??;
+    assert_eq!(reply, (23, b"ping".to_vec()));
+
+    stream.write_all(&app_keys.encrypt_server_record(1, &CLOSE_NOTIFY, 21)?).await?;
+    server.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cipher_suite_follows_client_preference() -> Result<()> {
+    // 移动端客户端常把 ChaCha20-Poly1305 放在首位
+    for offered in [[0x1303, 0x1301, 0x1302], [0x1302, 0x1303, 0x1301], [0x1301, 0x1303, 0x1302]] {
+        let (addr, server) = spawn_echo_server(reality_config()).await?;
+        let hello = sealed_hello(&offered, &[]);
+        let (mut stream, flight) = connect(addr, &hello).await?;
+        assert_eq!(flight.suite.id(), offered[0]);
+
+        stream.write_all(&flight.keys.encrypt_server_record(0, &flight.client_finished()?, 22)?).await?;
+        let app_keys = flight.application_keys()?;
+        stream.write_all(&app_keys.encrypt_server_record(0, b"ping", 23)?).await?;
+        let reply = tokio::time::timeout(Duration::from_secs(5), read_application(&mut stream, &app_keys, &mut 0)).await??;
+        assert_eq!(reply, (23, b"ping".to_vec()), "{:04x?}", offered);
+        stream.write_all(&app_keys.encrypt_server_record(1, &CLOSE_NOTIFY, 21)?).await?;
+        server.await??;
+    }
+    Ok(())
+}
+
+/// 引用一张服务端从未签发的票据的 pre_shared_key 扩展 (必须是最后一个扩展)
+fn stale_psk_extension() -> Vec<u8> {
+    let ticket: [u8; 32] = rand::random();
+    let mut identities = (ticket.len() as u16).to_be_bytes().to_vec();
+    identities.extend_from_slice(&ticket);
+    identities.extend_from_slice(&rand::random::<u32>().to_be_bytes());
+    let mut data = (identities.len() as u16).to_be_bytes().to_vec();
+    data.extend_from_slice(&identities);
+    data.extend_from_slice(&[0x00, 0x21, 0x20]);
+    data.extend_from_slice(&rand::random::<[u8; 32]>());
+    extension(0x0029, &data)
+}
+
+/// 完成握手并交换一次应用数据，返回回应之前收到的 NewSessionTicket 数
+async fn finish_and_count_tickets(stream: &mut TcpStream, flight: &ServerFlight) -> Result<usize> {
+    stream.write_all(&flight.keys.encrypt_server_record(0, &flight.client_finished()?, 22)?).await?;
+    let app_keys = flight.application_keys()?;
+    stream.write_all(&app_keys.encrypt_server_record(0, b"ping", 23)?).await?;
+    let mut tickets = 0;
+    let mut seq = 0;
+    loop {
+        let (header, mut body) = tokio::time::timeout(Duration::from_secs(5), read_record(stream)).await??;
+        let (content_type, len) = app_keys.decrypt_client_record(seq, &header, &mut body)?;
+        seq += 1;
+        match (content_type, body[0]) {
+            (22, 4) => tickets += 1,
+            (23, _) => {
+                assert_eq!(&body[..len], b"ping");
+                break;
+            }
+            other => return Err(anyhow!("unexpected record {:?}", other)),
+        }
+    }
+    stream.write_all(&app_keys.encrypt_server_record(1, &CLOSE_NOTIFY, 21)?).await?;
+    Ok(tickets)
+}
+
+#[tokio::test]
+async fn test_session_tickets_and_stale_psk_fall_back_to_full_handshake() -> Result<()> {
+    for session_tickets in [true, false] {
+        let config = RealityConfig { session_tickets, ..reality_config() };
+        let (addr, server) = spawn_echo_server(config).await?;
+        // 用旧票据请求 PSK 恢复的客户端照常完成完整握手
+        let hello = sealed_hello(&SUITES, &[stale_psk_extension()]);
+        let (mut stream, flight) = connect(addr, &hello).await?;
+        assert!(!flight.server_hello_extensions().contains(&0x0029), "PSK must not be accepted");
+
+        let tickets = finish_and_count_tickets(&mut stream, &flight).await?;
+        assert_eq!(tickets, if session_tickets { 2 } else { 0 });
+        server.await??;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unexpected_message_before_finished_gets_encrypted_alert() -> Result<()> {
+    let (addr, server) = spawn_echo_server(reality_config()).await?;
+    let hello = sealed_hello(&SUITES, &[]);
+    let (mut stream, flight) = connect(addr, &hello).await?;
+
+    // 握手密钥下的应用数据: 服务端期待的是 Finished
+    stream.write_all(&flight.keys.encrypt_server_record(0, b"ping", 23)?).await?;
+    let (header, mut body) = tokio::time::timeout(Duration::from_secs(5), read_record(&mut stream)).await??;
+    let (content_type, len) = flight.application_keys()?.decrypt_client_record(0, &header, &mut body)?;
+    // fatal(2) unexpected_message(10)
+    assert_eq!((content_type, &body[..len]), (21, &[2u8, 10][..]));
+    assert!(server.await?.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shutdown_sends_close_notify() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let reality = RealityServer::new(reality_config())?;
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        let Accepted::Reality(mut tls) = reality.accept(stream).await? else {
+            return Err(anyhow!("client was not authenticated"));
+        };
+        let mut ping = [0u8; 4];
+        tls.read_exact(&mut ping).await?;
+        tls.shutdown().await?;
+        anyhow::Ok(())
+    });
+
+    let hello = sealed_hello(&SUITES, &[]);
+    let (mut stream, flight) = connect(addr, &hello).await?;
+    stream.write_all(&flight.keys.encrypt_server_record(0, &flight.client_finished()?, 22)?).await?;
+    let app_keys = flight.application_keys()?;
+    stream.write_all(&app_keys.encrypt_server_record(0, b"ping", 23)?).await?;
+
+    let alert = tokio::time::timeout(Duration::from_secs(5), read_application(&mut stream, &app_keys, &mut 0)).await??;
+    assert_eq!(alert, (21, CLOSE_NOTIFY.to_vec()));
+    server.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_hello_random_is_fresh() -> Result<()> {
+    let mut randoms = Vec::new();
+    for _ in 0..2 {
+        let (addr, _server) = spawn_echo_server(reality_config()).await?;
+        let hello = sealed_hello(&SUITES, &[]);
+        let (_, flight) = connect(addr, &hello).await?;
+        let random: [u8; 32] = flight.server_hello[6..38].try_into()?;
+        let longest_zero_run = random.split(|&b| b != 0).map(<[u8]>::len).max().unwrap_or(0);
+        assert!(longest_zero_run <= 4, "ServerHello.random {:02x?}", random);
+        randoms.push(random);
+    }
+    assert_ne!(randoms[0], randoms[1]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stalled_handshake_times_out_in_each_phase() -> Result<()> {
+    let config = RealityConfig { handshake_timeout: Duration::from_millis(500), ..reality_config() };
+    let hello = sealed_hello(&SUITES, &[]);
+    let (before_timeouts, _) = HANDSHAKE_STATS.snapshot();
+
+    // 只发送半条 ClientHello 的慢速客户端
+    let (addr, server) = spawn_echo_server(config.clone()).await?;
+    let mut stream = TcpStream::connect(addr).await?;
+    let partial = record(0x16, &hello.message);
+    stream.write_all(&partial[..partial.len() / 2]).await?;
+    let error = tokio::time::timeout(Duration::from_secs(3), server).await??.unwrap_err();
+    assert!(error.to_string().starts_with("Handshake timeout: Client sent incomplete"), "{}", error);
+
+    // 通过认证、读完服务端第一轮后不再发送 Finished
+    let (addr, server) = spawn_echo_server(config).await?;
+    let (_stream, _flight) = connect(addr, &hello).await?;
+    let error = tokio::time::timeout(Duration::from_secs(3), server).await??.unwrap_err();
+    assert_eq!(error.to_string(), "Handshake timeout");
+
+    let (after_timeouts, _) = HANDSHAKE_STATS.snapshot();
+    assert!(after_timeouts >= before_timeouts + 2);
+    drop(stream);
+    Ok(())
+}
+
+/// 紧跟 ClientHello 发送 `records` 条各 `len` 字节的 0-RTT 记录 (服务端没有其密钥)，再读取服务端第一轮
+async fn connect_with_early_data(
+    addr: std::net::SocketAddr,
+    hello: &SealedHello,
+    records: usize,
+    len: usize,
+) -> Result<(TcpStream, ServerFlight)> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let mut flight = record(0x16, &hello.message);
+    for _ in 0..records {
+        flight.extend(record(0x17, &(0..len).map(|_| rand::random::<u8>()).collect::<Vec<_>>()));
+    }
+    stream.write_all(&flight).await?;
+    let flight = tokio::time::timeout(Duration::from_secs(5), read_server_flight(&mut stream, hello)).await??;
+    Ok((stream, flight))
+}
+
+#[tokio::test]
+async fn test_rejected_early_data_is_skipped() -> Result<()> {
+    // early_data 扩展为空，pre_shared_key 必须在最后
+    let extra = [extension(0x002a, &[]), stale_psk_extension()];
+
+    let (addr, server) = spawn_echo_server(reality_config()).await?;
+    let hello = sealed_hello(&SUITES, &extra);
+    let (mut stream, flight) = connect_with_early_data(addr, &hello, 4, 4096).await?;
+    assert!(!flight.server_hello_extensions().contains(&0x0029), "PSK must not be accepted");
+    // 0-RTT 记录解密失败被丢弃，不占用握手密钥的序号
+    let tickets = finish_and_count_tickets(&mut stream, &flight).await?;
+    assert_eq!(tickets, 2);
+    server.await??;
+
+    // 超过 16384 字节的 0-RTT 数据不再被跳过
+    let (addr, server) = spawn_echo_server(reality_config()).await?;
+    let hello = sealed_hello(&SUITES, &extra);
+    let (mut stream, flight) = connect_with_early_data(addr, &hello, 5, 4096).await?;
+    let _ = stream.write_all(&flight.keys.encrypt_server_record(0, &flight.client_finished()?, 22)?).await;
+    let result = tokio::time::timeout(Duration::from_secs(5), server).await??;
+    assert!(result.is_err(), "early data beyond the cap must fail the handshake");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_hello_larger_than_one_record() -> Result<()> {
+    let (addr, server) = spawn_echo_server(reality_config()).await?;
+    // 带 ECH 与大量扩展的浏览器 ClientHello 可能超过单条记录的 16KB
+    let hello = sealed_hello(&SUITES, &[extension(0x0015, &[0u8; 20000])]);
+    assert!(hello.message.len() > 16384);
+
+    let mut stream = TcpStream::connect(addr).await?;
+    for fragment in hello.message.chunks(16384) {
+        stream.write_all(&record(0x16, fragment)).await?;
+        stream.flush().await?;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let flight = tokio::time::timeout(Duration::from_secs(5), read_server_flight(&mut stream, &hello)).await??;
+
+    let tickets = finish_and_count_tickets(&mut stream, &flight).await?;
+    assert_eq!(tickets, 2);
+    server.await??;
+
+    // 声明长度超过 64KB 上限的 ClientHello 明确报错，而不是一直等待
+    let (addr, server) = spawn_echo_server(reality_config()).await?;
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(&record(0x16, &[0x01, 0x01, 0x00, 0x01])).await?;
+    let error = tokio::time::timeout(Duration::from_secs(5), server).await??.unwrap_err();
+    assert!(error.to_string().contains("ClientHello too large"), "{}", error);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_no_common_cipher_suite_gets_plaintext_alert() -> Result<()> {
+    let (addr, server) = spawn_echo_server(reality_config()).await?;
+    // 只提供 TLS_AES_128_CCM_SHA256: 通过认证，但还没有任何密钥
+    let hello = sealed_hello(&[0x1304], &[]);
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(&record(0x16, &hello.message)).await?;
+
+    let (header, body) = tokio::time::timeout(Duration::from_secs(5), read_record(&mut stream)).await??;
+    // 明文 fatal(2) handshake_failure(40)，与真实 TLS 1.3 服务端一致
+    assert_eq!([&header[..], &body[..]].concat(), [0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x28]);
+    assert!(server.await?.is_err());
+    Ok(())
+}
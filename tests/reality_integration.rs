@@ -2,6 +2,7 @@ use anyhow::Result;
 use tokio::net::{TcpStream, TcpListener};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use xray_lite::transport::reality::server_rustls::RealityServerRustls;
+use xray_lite::transport::reality::Accepted;
 use std::time::Duration;
 
 #[tokio::test]
@@ -14,7 +15,7 @@ async fn test_reality_fallback() -> Result<()> {
         let (mut stream, _) = dest_listener.accept().await.unwrap();
         // Fallback server just echoes "I am fallback"
         let mut buf = [0u8; 1024];
-        let n = stream.read(&mut buf).await.unwrap();
+        let _ = stream.read(&mut buf).await.unwrap();
         stream.write_all(b"I am fallback").await.unwrap();
     });
 
@@ -30,7 +31,6 @@ async fn test_reality_fallback() -> Result<()> {
     // Pick a random port
     let server_listener = TcpListener::bind("127.0.0.1:0").await?;
     let server_addr = server_listener.local_addr()?;
-    let server_port = server_addr.port();
     
     // Run server in background
     let server = std::sync::Arc::new(server);
@@ -42,10 +42,11 @@ async fn test_reality_fallback() -> Result<()> {
                 let s = server.clone();
                 tokio::spawn(async move {
                     // accept() handles Sniff-and-Dispatch.
-                    // If fallback, it returns Err("Reality fallback handled").
-                    // If success, it returns Ok(tls_stream). 
-                    // We just let it run.
-                    let _ = s.accept(stream).await;
+                    // If fallback, it returns Accepted::Fallback for the caller to relay.
+                    // If success, it returns Accepted::Reality(tls_stream).
+                    if let Ok(Accepted::Fallback(fallback)) = s.accept(stream).await {
+                        let _ = fallback.relay(None, None).await;
+                    }
                 });
             }
         }
@@ -65,3 +66,396 @@ async fn test_reality_fallback() -> Result<()> {
 
     Ok(())
 }
+
+/// 未认证的 TLS 1.3 ClientHello，SNI 为 `server_name`
+fn client_hello(server_name: &str) -> Vec<u8> {
+    let config = rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])
+    .unwrap()
+    .with_root_certificates(rustls::RootCertStore::empty())
+    .with_no_client_auth();
+    let name = rustls_pki_types::ServerName::try_from(server_name.to_string()).unwrap();
+    let mut conn = rustls::ClientConnection::new(std::sync::Arc::new(config), name).unwrap();
+    let mut hello = Vec::new();
+    conn.write_tls(&mut hello).unwrap();
+    hello
+}
+
+/// 回落目标: 读到 ClientHello 后回复自己的名字
+async fn tagged_dest(tag: &'static [u8]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream.write_all(tag).await;
+            });
+        }
+    });
+    addr.to_string()
+}
+
+#[tokio::test]
+async fn test_fallback_per_server_name() -> Result<()> {
+    let apple = tagged_dest(b"apple").await;
+    let microsoft = tagged_dest(b"microsoft").await;
+    let default = tagged_dest(b"default").await;
+
+    let server = RealityServerRustls::new(
+        vec![0x42; 32],
+        Some(default),
+        vec!["0123456789abcdef".to_string()],
+        vec!["www.apple.com".to_string(), "www.microsoft.com".to_string()],
+    )?
+    .with_server_name_dests(
+        [("www.apple.com", apple), ("www.microsoft.com", microsoft)]
+            .into_iter()
+            .map(|(name, dest)| (name.to_string(), dest))
+            .collect(),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let server = server.clone();
+            tokio::spawn(async move {
+                if let Ok(Accepted::Fallback(fallback)) = server.accept(stream).await {
+                    let _ = fallback.relay(None, None).await;
+                }
+            });
+        }
+    });
+
+    for (sni, expected) in [
+        ("www.apple.com", &b"apple"[..]),
+        ("www.microsoft.com", &b"microsoft"[..]),
+        ("unknown.example.com", &b"default"[..]),
+    ] {
+        let mut client = TcpStream::connect(addr).await?;
+        client.write_all(&client_hello(sni)).await?;
+        let mut reply = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut reply)).await??;
+        assert_eq!(reply, expected, "SNI {}", sni);
+    }
+
+    Ok(())
+}
+
+/// 在 ClientHello 末尾加入 padding 扩展，使握手消息达到 `target` 字节，并按 16 KiB 拆成多个记录
+fn split_client_hello(record: &[u8], target: usize) -> Vec<u8> {
+    let mut message = record[5..].to_vec();
+    let pad = target - message.len() - 4;
+    message.extend_from_slice(&0x0015u16.to_be_bytes());
+    message.extend_from_slice(&(pad as u16).to_be_bytes());
+    message.resize(target, 0);
+
+    let len = (message.len() - 4) as u32;
+    message[1..4].copy_from_slice(&len.to_be_bytes()[1..]);
+    // legacy_version + random + session_id + cipher_suites + compression_methods 之后是扩展总长度
+    let mut i = 4 + 2 + 32;
+    i += 1 + message[i] as usize;
+    i += 2 + u16::from_be_bytes([message[i], message[i + 1]]) as usize;
+    i += 1 + message[i] as usize;
+    let extensions_len = (message.len() - i - 2) as u16;
+    message[i..i + 2].copy_from_slice(&extensions_len.to_be_bytes());
+
+    let mut wire = Vec::new();
+    for chunk in message.chunks(16384) {
+        wire.extend_from_slice(&[0x16, 0x03, 0x01]);
+        wire.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+        wire.extend_from_slice(chunk);
+    }
+    wire
+}
+
+#[tokio::test]
+async fn test_client_hello_spanning_records() -> Result<()> {
+    let hello = split_client_hello(&client_hello("www.apple.com"), 24 * 1024);
+    assert!(hello.len() > 16384 + 5);
+
+    // 回落目标读完整个 ClientHello 后原样返回，确认重放的字节没有被截断
+    let dest_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let dest = dest_listener.local_addr()?.to_string();
+    let expected_len = hello.len();
+    tokio::spawn(async move {
+        let (mut stream, _) = dest_listener.accept().await.unwrap();
+        let mut buf = vec![0u8; expected_len];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(&buf).await.unwrap();
+    });
+
+    let server = RealityServerRustls::new(
+        vec![0x42; 32],
+        Some("127.0.0.1:9".to_string()),
+        vec!["0123456789abcdef".to_string()],
+        vec!["www.apple.com".to_string()],
+    )?
+    .with_server_name_dests([("www.apple.com".to_string(), dest)].into_iter().collect());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        if let Ok(Accepted::Fallback(fallback)) = server.accept(stream).await {
+            let _ = fallback.relay(None, None).await;
+        }
+    });
+
+    // 慢速分段发送，服务端需要多次读取才能拿到完整消息
+    let mut client = TcpStream::connect(addr).await?;
+    for chunk in hello.chunks(3000) {
+        client.write_all(chunk).await?;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let mut reply = vec![0u8; hello.len()];
+    tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut reply)).await??;
+    assert_eq!(reply, hello);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fallback_idle_timeout() -> Result<()> {
+    // 回落目标回复后保持连接，既不发数据也不关闭
+    let dest_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let dest = dest_listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        let (mut stream, _) = dest_listener.accept().await.unwrap();
+        let mut buf = [0u8; 4096];
+        let _ = stream.read(&mut buf).await;
+        stream.write_all(b"dest").await.unwrap();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+    });
+
+    let server = RealityServerRustls::new(
+        vec![0x42; 32],
+        Some(dest),
+        vec!["0123456789abcdef".to_string()],
+        vec!["www.apple.com".to_string()],
+    )?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let relay = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        match server.accept(stream).await.unwrap() {
+            Accepted::Fallback(fallback) => fallback.relay(None, Some(Duration::from_millis(200))).await.unwrap(),
+            Accepted::Reality(_) => panic!("unauthenticated client must fall back"),
+        }
+    });
+
+    let mut client = TcpStream::connect(addr).await?;
+    client.write_all(&client_hello("www.apple.com")).await?;
+    let mut reply = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut reply)).await??;
+    assert_eq!(reply, b"dest");
+
+    let stats = relay.await?;
+    assert_eq!(stats.reason, xray_lite::network::CloseReason::IdleTimeout);
+    assert_eq!(stats.down_bytes, 4);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fallback_terminates_tls_with_configured_certificate() -> Result<()> {
+    use xray_lite::transport::reality::tls_handler::RealityTlsHandler;
+    use xray_lite::transport::reality::RealityConfig;
+
+    let dir = std::env::temp_dir().join(format!("xray-lite-fallback-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let cert = rcgen::generate_simple_self_signed(vec!["www.apple.com".to_string()])?;
+    let (certificate_file, key_file) = (dir.join("cert.pem"), dir.join("key.pem"));
+    std::fs::write(&certificate_file, cert.cert.pem())?;
+    std::fs::write(&key_file, cert.key_pair.serialize_pem())?;
+
+    // dest 收到的是解密后的明文
+    let dest_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let dest = dest_listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        let (mut stream, _) = dest_listener.accept().await.unwrap();
+        let mut buf = [0u8; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+        stream.write_all(b"plain").await.unwrap();
+    });
+
+    let config = RealityConfig {
+        certificate_file: Some(certificate_file.display().to_string()),
+        key_file: Some(key_file.display().to_string()),
+        ..RealityConfig::new(dest.clone(), vec!["www.apple.com".to_string()], "", vec![])
+    };
+    let server = RealityServerRustls::new(
+        vec![0x42; 32],
+        Some(dest),
+        vec!["0123456789abcdef".to_string()],
+        vec!["www.apple.com".to_string()],
+    )?
+    .with_fallback_tls(RealityTlsHandler::new(config)?);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        if let Ok(Accepted::Fallback(fallback)) = server.accept(stream).await {
+            let _ = fallback.relay(None, None).await;
+        }
+    });
+
+    // 信任配置的证书的普通 TLS 客户端
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert.cert.der().clone())?;
+    let client = rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = rustls_pki_types::ServerName::try_from("www.apple.com")?;
+    let stream = TcpStream::connect(addr).await?;
+    let mut tls = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client)).connect(name, stream).await?;
+    tls.write_all(b"hello").await?;
+    let mut reply = [0u8; 5];
+    tokio::time::timeout(Duration::from_secs(5), tls.read_exact(&mut reply)).await??;
+    assert_eq!(&reply, b"plain");
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+/// 为 `name` 写入自签名证书文件，返回文件路径与证书
+fn write_certificate(
+    dir: &std::path::Path,
+    name: &str,
+) -> (xray_lite::transport::reality::CertificateFiles, rustls_pki_types::CertificateDer<'static>) {
+    let cert = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+    let files = xray_lite::transport::reality::CertificateFiles {
+        certificate_file: dir.join(format!("{name}.pem")).display().to_string(),
+        key_file: dir.join(format!("{name}.key")).display().to_string(),
+    };
+    std::fs::write(&files.certificate_file, cert.cert.pem()).unwrap();
+    std::fs::write(&files.key_file, cert.key_pair.serialize_pem()).unwrap();
+    (files, cert.cert.der().clone())
+}
+
+#[tokio::test]
+async fn test_fallback_tls_selects_certificate_by_sni() -> Result<()> {
+    use xray_lite::transport::reality::tls_handler::RealityTlsHandler;
+    use xray_lite::transport::reality::RealityConfig;
+
+    let dir = std::env::temp_dir().join(format!("xray-lite-fallback-sni-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let (a, a_der) = write_certificate(&dir, "a.example.com");
+    let (b, b_der) = write_certificate(&dir, "b.example.com");
+    let names = vec!["a.example.com".to_string(), "b.example.com".to_string()];
+    let config = RealityConfig {
+        server_name_certificates: [("a.example.com".to_string(), a), ("b.example.com".to_string(), b)].into(),
+        ..RealityConfig::new("127.0.0.1:9", names.clone(), "", vec![])
+    };
+    let server = std::sync::Arc::new(
+        RealityServerRustls::new(vec![0x42; 32], Some("127.0.0.1:9".to_string()), vec![], names)?
+            .with_fallback_tls(RealityTlsHandler::new(config)?),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let server = server.clone();
+            tokio::spawn(async move {
+                if let Ok(Accepted::Fallback(fallback)) = server.accept(stream).await {
+                    let _ = fallback.relay(None, None).await;
+                }
+            });
+        }
+    });
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(a_der.clone())?;
+    roots.add(b_der.clone())?;
+    let client = std::sync::Arc::new(
+        rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    );
+    for (sni, expected) in [("a.example.com", &a_der), ("b.example.com", &b_der)] {
+        let stream = TcpStream::connect(addr).await?;
+        let name = rustls_pki_types::ServerName::try_from(sni)?;
+        // 握手完成后 dest 不可达，连接随即关闭；证书在握手时已经确定
+        let tls = tokio_rustls::TlsConnector::from(client.clone()).connect(name, stream).await?;
+        assert_eq!(&tls.get_ref().1.peer_certificates().unwrap()[0], expected, "{}", sni);
+    }
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+/// 只带 P-256 key_share 的 ClientHello 无法完成 Reality 认证 (认证依赖 X25519 共享密钥)，
+/// 整个连接交给 dest: 只支持 X25519 的 dest 自己发送 HelloRetryRequest，第二个 ClientHello 同样经回落转发
+#[tokio::test]
+async fn test_p256_only_key_share_is_retried_by_dest() -> Result<()> {
+    use rustls::crypto::ring::{default_provider, kx_group};
+    use std::sync::Arc;
+
+    let cert = rcgen::generate_simple_self_signed(vec!["www.apple.com".to_string()])?;
+    let dest_provider = rustls::crypto::CryptoProvider { kx_groups: vec![kx_group::X25519], ..default_provider() };
+    let dest_config = rustls::ServerConfig::builder_with_provider(Arc::new(dest_provider))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![cert.cert.der().clone()],
+            rustls_pki_types::PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()).into(),
+        )?;
+    let dest_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let dest = dest_listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        let (stream, _) = dest_listener.accept().await.unwrap();
+        let mut tls = tokio_rustls::TlsAcceptor::from(Arc::new(dest_config)).accept(stream).await.unwrap();
+        let mut buf = [0u8; 4];
+        tls.read_exact(&mut buf).await.unwrap();
+        tls.write_all(b"dest").await.unwrap();
+        tls.flush().await.unwrap();
+    });
+
+    let server = RealityServerRustls::new(
+        vec![0x42; 32],
+        Some(dest),
+        vec!["0123456789abcdef".to_string()],
+        vec!["www.apple.com".to_string()],
+    )?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        if let Ok(Accepted::Fallback(fallback)) = server.accept(stream).await {
+            let _ = fallback.relay(None, None).await;
+        }
+    });
+
+    // 客户端优先 P-256，第一个 ClientHello 只带 P-256 的 key_share
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert.cert.der().clone())?;
+    let client_provider = rustls::crypto::CryptoProvider {
+        kx_groups: vec![kx_group::SECP256R1, kx_group::X25519],
+        ..default_provider()
+    };
+    let client = rustls::ClientConfig::builder_with_provider(Arc::new(client_provider))
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = rustls_pki_types::ServerName::try_from("www.apple.com")?;
+    let stream = TcpStream::connect(addr).await?;
+    let mut tls = tokio::time::timeout(
+        Duration::from_secs(5),
+        tokio_rustls::TlsConnector::from(Arc::new(client)).connect(name, stream),
+    )
+    .await??;
+    tls.write_all(b"ping").await?;
+    let mut reply = [0u8; 4];
+    tokio::time::timeout(Duration::from_secs(5), tls.read_exact(&mut reply)).await??;
+    assert_eq!(&reply, b"dest");
+    Ok(())
+}
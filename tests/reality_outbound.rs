@@ -0,0 +1,155 @@
+//! 两台 xray-lite 串联: 边缘节点通过 vless + Reality 出站连接上游节点
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine as _};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+use xray_lite::{Config, Server};
+
+const UPSTREAM_UUID: &str = "b831381d-6324-4d53-ad4f-8cda48b30811";
+const EDGE_UUID: &str = "1d3ae6e0-5a3b-4f52-9c0e-6a8c1f9b2d47";
+const PRIVATE_KEY: [u8; 32] = [b'A'; 32];
+
+async fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+async fn wait_for_listener(addr: SocketAddr) {
+    for _ in 0..50 {
+        if TcpStream::connect(addr).await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("{} is not listening", addr);
+}
+
+/// 上游: VLESS + Reality 入站，直连出站
+fn upstream_config(port: u16) -> Config {
+    let json = format!(
+        r#"{{
+            "inbounds": [{{
+                "protocol": "vless",
+                "listen": "127.0.0.1",
+                "port": {port},
+                "settings": {{ "clients": [{{ "id": "{UPSTREAM_UUID}" }}] }},
+                "streamSettings": {{
+                    "network": "tcp",
+                    "security": "reality",
+                    "realitySettings": {{
+                        "dest": "127.0.0.1:9",
+                        "serverNames": ["www.example.com"],
+                        "privateKey": "{}",
+                        "shortIds": ["0123456789abcdef", "ab"]
+                    }}
+                }}
+            }}],
+            "outbounds": [{{ "protocol": "freedom", "tag": "direct" }}],
+            "routing": {{ "blockPrivate": false }}
+        }}"#,
+        general_purpose::URL_SAFE_NO_PAD.encode(PRIVATE_KEY)
+    );
+    serde_json::from_str(&json).unwrap()
+}
+
+/// 边缘: 明文 VLESS 入站，vless + Reality 出站指向上游
+fn edge_config(port: u16, upstream_port: u16, fingerprint: &str, short_id: &str) -> Config {
+    let public_key = PublicKey::from(&StaticSecret::from(PRIVATE_KEY));
+    let json = format!(
+        r#"{{
+            "inbounds": [{{
+                "protocol": "vless",
+                "listen": "127.0.0.1",
+                "port": {port},
+                "settings": {{ "clients": [{{ "id": "{EDGE_UUID}" }}] }},
+                "streamSettings": {{ "network": "tcp", "security": "none" }}
+            }}],
+            "outbounds": [{{
+                "protocol": "vless",
+                "tag": "upstream",
+                "settings": {{
+                    "vnext": [{{ "address": "127.0.0.1", "port": {upstream_port}, "users": [{{ "id": "{UPSTREAM_UUID}" }}] }}]
+                }},
+                "streamSettings": {{
+                    "security": "reality",
+                    "realitySettings": {{
+                        "serverName": "www.example.com",
+                        "publicKey": "{}",
+                        "shortId": "{short_id}",
+                        "fingerprint": "{fingerprint}"
+                    }}
+                }}
+            }}],
+            "routing": {{ "blockPrivate": false }}
+        }}"#,
+        general_purpose::URL_SAFE_NO_PAD.encode(public_key.as_bytes())
+    );
+    let config: Config = serde_json::from_str(&json).unwrap();
+    config.validate().unwrap();
+    config
+}
+
+async fn chained_echo(fingerprint: &str, short_id: &str) {
+    let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let echo_addr = echo.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = echo.accept().await.unwrap();
+        let mut buf = [0u8; 64];
+        let n = stream.read(&mut buf).await.unwrap();
+        stream.write_all(&buf[..n]).await.unwrap();
+    });
+
+    let upstream_port = free_port().await;
+    let edge_port = free_port().await;
+    tokio::spawn(Server::new(upstream_config(upstream_port)).unwrap().run());
+    tokio::spawn(
+        Server::new(edge_config(edge_port, upstream_port, fingerprint, short_id))
+            .unwrap()
+            .run(),
+    );
+    wait_for_listener(([127, 0, 0, 1], upstream_port).into()).await;
+    wait_for_listener(([127, 0, 0, 1], edge_port).into()).await;
+
+    let request = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(EDGE_UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::from(echo_addr),
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    let mut client = TcpStream::connect(("127.0.0.1", edge_port)).await.unwrap();
+    let mut hello = request.encode().unwrap().to_vec();
+    hello.extend_from_slice(b"ping");
+    client.write_all(&hello).await.unwrap();
+
+    let mut reply = [0u8; 6];
+    tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut reply))
+        .await
+        .expect("no reply through the chain")
+        .unwrap();
+    // 边缘节点的 VLESS 响应头，随后是经上游转发回来的数据
+    assert_eq!(&reply, b"\x00\x00ping");
+}
+
+#[tokio::test]
+async fn test_chain_through_reality_outbound() {
+    chained_echo("chrome", "0123456789abcdef").await;
+}
+
+#[tokio::test]
+async fn test_chain_with_firefox_fingerprint() {
+    chained_echo("firefox", "0123456789abcdef").await;
+}
+
+/// 不足 8 字节的 shortId 两端都补零后比较
+#[tokio::test]
+async fn test_chain_with_short_short_id() {
+    chained_echo("chrome", "ab").await;
+}
@@ -0,0 +1,372 @@
+//! Drives the Reality server with an unmodified rustls client.
+//!
+//! rustls has no notion of Reality, so the test supplies the two pieces a
+//! Reality client adds on top of plain TLS 1.3: a sealed ClientHello
+//! session_id and a certificate check against HMAC-SHA512(auth_key, key).
+//! The session_id is sealed over the ClientHello it sits in, so the hello is
+//! produced twice from the same scripted randomness and key share: once with
+//! a zero session_id to obtain the AAD, then for real with the sealed one.
+//! Everything after the ClientHello (key schedule, transcript hash, Finished,
+//! record protection) is rustls' own, checked against ours.
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use base64::{engine::general_purpose, Engine as _};
+use ring::{aead, hkdf, hmac};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ActiveKeyExchange, CryptoProvider, GetRandomFailed, SecureRandom, SharedSecret, SupportedKxGroup};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, NamedGroup, SignatureScheme, SupportedCipherSuite};
+use rustls_pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::transport::reality::{Accepted, RealityConfig, RealityServer};
+
+const PRIVATE_KEY: [u8; 32] = [0x42; 32];
+const CLIENT_SECRET: [u8; 32] = [0x17; 32];
+const SHORT_ID: [u8; 8] = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+const SERVER_NAME: &str = "www.example.com";
+
+/// DER prefix of an Ed25519 SubjectPublicKeyInfo (RFC 8410), followed by the 32-byte key.
+const ED25519_SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+/// Hands out queued values first (session_id, then random), OS randomness after that.
+#[derive(Debug)]
+struct ScriptedRandom(Mutex<VecDeque<[u8; 32]>>);
+
+impl SecureRandom for ScriptedRandom {
+    fn fill(&self, buf: &mut [u8]) -> Result<(), GetRandomFailed> {
+        match self.0.lock().unwrap().pop_front() {
+            Some(value) if value.len() == buf.len() => buf.copy_from_slice(&value),
+            Some(_) => panic!("unexpected random request of {} bytes", buf.len()),
+            None => ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), buf).map_err(|_| GetRandomFailed)?,
+        }
+        Ok(())
+    }
+}
+
+/// X25519 with a fixed client secret, so the test can derive the Reality auth key.
+#[derive(Debug)]
+struct FixedX25519;
+
+struct FixedKeyExchange {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl SupportedKxGroup for FixedX25519 {
+    fn start(&self) -> Result<Box<dyn ActiveKeyExchange>, rustls::Error> {
+        let secret = StaticSecret::from(CLIENT_SECRET);
+        let public = PublicKey::from(&secret);
+        Ok(Box::new(FixedKeyExchange { secret, public }))
+    }
+
+    fn name(&self) -> NamedGroup {
+        NamedGroup::X25519
+    }
+}
+
+impl ActiveKeyExchange for FixedKeyExchange {
+    fn complete(self: Box<Self>, peer_pub_key: &[u8]) -> Result<SharedSecret, rustls::Error> {
+        let peer: [u8; 32] = peer_pub_key
+            .try_into()
+            .map_err(|_| rustls::Error::General("bad X25519 key share".into()))?;
+        Ok(SharedSecret::from(self.secret.diffie_hellman(&PublicKey::from(peer)).as_bytes().as_slice()))
+    }
+
+    fn pub_key(&self) -> &[u8] {
+        self.public.as_bytes()
+    }
+
+    fn group(&self) -> NamedGroup {
+        NamedGroup::X25519
+    }
+}
+
+/// Accepts the server only if the leaf certificate carries the Reality HMAC;
+/// CertificateVerify is still checked by rustls' own Ed25519 verification.
+#[derive(Debug)]
+struct RealityVerifier {
+    auth_key: [u8; 32],
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for RealityVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let cert = end_entity.as_ref();
+        let key_at = cert
+            .windows(ED25519_SPKI_PREFIX.len())
+            .position(|w| w == ED25519_SPKI_PREFIX)
+            .ok_or_else(|| rustls::Error::General("not an Ed25519 certificate".into()))?
+            + ED25519_SPKI_PREFIX.len();
+        let public_key = &cert[key_at..key_at + 32];
+        hmac::verify(&hmac::Key::new(hmac::HMAC_SHA512, &self.auth_key), public_key, &cert[cert.len() - 64..])
+            .map_err(|_| rustls::Error::General("Reality certificate signature mismatch".into()))?;
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Err(rustls::Error::General("TLS 1.2 is not used by Reality".into()))
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        vec![SignatureScheme::ED25519]
+    }
+}
+
+fn reality_config() -> RealityConfig {
+    RealityConfig {
+        session_tickets: false,
+        ..RealityConfig::new(
+            // Nothing listens here: a client that fails authentication gets a refused fallback.
+            "127.0.0.1:9",
+            vec![SERVER_NAME.to_string()],
+            general_purpose::URL_SAFE_NO_PAD.encode(PRIVATE_KEY),
+            vec![hex::encode(SHORT_ID)],
+        )
+    }
+}
+
+/// A rustls client config offering one suite, with scripted session_id and random.
+fn client_config(suite: SupportedCipherSuite, session_id: [u8; 32], random: [u8; 32], auth_key: [u8; 32]) -> ClientConfig {
+    let base = rustls::crypto::ring::default_provider();
+    let provider = Arc::new(CryptoProvider {
+        cipher_suites: vec![suite],
+        kx_groups: vec![&FixedX25519],
+        secure_random: Box::leak(Box::new(ScriptedRandom(Mutex::new(VecDeque::from([session_id, random]))))),
+        ..base
+    });
+    let mut config = ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(RealityVerifier { auth_key, provider }))
+        .with_no_client_auth();
+    config.resumption = rustls::client::Resumption::disabled();
+    config
+}
+
+/// auth_key and sealed session_id for the ClientHello rustls produces with this suite and random.
+fn seal_session_id(suite: SupportedCipherSuite, random: [u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let server_public = PublicKey::from(&StaticSecret::from(PRIVATE_KEY));
+    let shared = StaticSecret::from(CLIENT_SECRET).diffie_hellman(&server_public);
+    let mut auth_key = [0u8; 32];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, &random[..20])
+        .extract(shared.as_bytes())
+        .expand(&[b"REALITY"], hkdf::HKDF_SHA256)
+        .unwrap()
+        .fill(&mut auth_key)
+        .unwrap();
+
+    // The ClientHello with a zero session_id is exactly the AAD the server rebuilds.
+    let config = client_config(suite, [0; 32], random, auth_key);
+    let mut conn = ClientConnection::new(Arc::new(config), SERVER_NAME.try_into().unwrap()).unwrap();
+    let mut record = Vec::new();
+    conn.write_tls(&mut record).unwrap();
+    let hello = &record[5..];
+    assert_eq!(&hello[39..71], &[0u8; 32], "session_id is not where Reality expects it");
+
+    // version 1.8.0 | reserved | Unix time | short_id
+    let mut session_id = vec![1, 8, 0, 0];
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as u32;
+    session_id.extend_from_slice(&now.to_be_bytes());
+    session_id.extend_from_slice(&SHORT_ID);
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &auth_key).unwrap());
+    let nonce = aead::Nonce::try_assume_unique_for_key(&random[20..]).unwrap();
+    key.seal_in_place_append_tag(nonce, aead::Aad::from(hello), &mut session_id).unwrap();
+    (auth_key, session_id.try_into().unwrap())
+}
+
+/// Handshakes once with `suite` and echoes a payload spanning several records.
+async fn handshake_and_echo(suite: SupportedCipherSuite) -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let reality = RealityServer::new(reality_config())?;
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        let Accepted::Reality(mut tls) = reality.accept(stream).await? else {
+            anyhow::bail!("client was not authenticated");
+        };
+        let mut buf = vec![0u8; 8192];
+        loop {
+            let n = tls.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            tls.write_all(&buf[..n]).await?;
+            tls.flush().await?;
+        }
+        anyhow::Ok(())
+    });
+
+    let random: [u8; 32] = std::array::from_fn(|i| i as u8 ^ 0x5a);
+    let (auth_key, session_id) = seal_session_id(suite, random);
+    let connector = TlsConnector::from(Arc::new(client_config(suite, session_id, random, auth_key)));
+    let tcp = TcpStream::connect(addr).await?;
+    let mut tls = tokio::time::timeout(Duration::from_secs(5), connector.connect(SERVER_NAME.try_into()?, tcp))
+        .await
+        .expect("handshake timed out")?;
+    assert_eq!(tls.get_ref().1.negotiated_cipher_suite(), Some(suite));
+
+    // Larger than one TLS record (16 KiB) in both directions.
+    let payload: Vec<u8> = (0..40_000u32).map(|i| (i % 251) as u8).collect();
+    let (mut read, mut write) = tokio::io::split(tls);
+    let writer = async {
+        write.write_all(&payload).await?;
+        write.flush().await?;
+        anyhow::Ok(write)
+    };
+    let reader = async {
+        let mut echoed = vec![0u8; payload.len()];
+        read.read_exact(&mut echoed).await?;
+        anyhow::Ok(echoed)
+    };
+    let (write, echoed) = tokio::time::timeout(Duration::from_secs(5), async { tokio::try_join!(writer, reader) })
+        .await
+        .expect("echo timed out")?;
+    assert!(echoed == payload, "echoed data differs");
+
+    tls = read.unsplit(write);
+    tls.shutdown().await?;
+    drop(tls);
+    server.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rustls_client_aes_128_gcm() -> Result<()> {
+    handshake_and_echo(rustls::crypto::ring::cipher_suite::TLS13_AES_128_GCM_SHA256).await
+}
+
+#[tokio::test]
+async fn test_rustls_client_aes_256_gcm() -> Result<()> {
+    handshake_and_echo(rustls::crypto::ring::cipher_suite::TLS13_AES_256_GCM_SHA384).await
+}
+
+#[tokio::test]
+async fn test_rustls_client_chacha20_poly1305() -> Result<()> {
+    handshake_and_echo(rustls::crypto::ring::cipher_suite::TLS13_CHACHA20_POLY1305_SHA256).await
+}
+
+#[tokio::test]
+async fn test_reality_certificate_uses_self_signed_params() -> Result<()> {
+    let suite = rustls::crypto::ring::cipher_suite::TLS13_AES_128_GCM_SHA256;
+    let mut config = reality_config();
+    config.self_signed = serde_json::from_value(serde_json::json!({
+        "domains": ["cdn.example.net"],
+        "commonName": "Example CDN",
+        "organization": "Example Org",
+        "validityDays": 30,
+        // Ignored for the Reality certificate, whose signature slot carries the HMAC.
+        "keyType": "ecdsa",
+    }))?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let reality = RealityServer::new(config)?;
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        let Accepted::Reality(mut tls) = reality.accept(stream).await? else {
+            anyhow::bail!("client was not authenticated");
+        };
+        let _ = tls.read(&mut [0u8; 1]).await;
+        anyhow::Ok(())
+    });
+
+    let random: [u8; 32] = std::array::from_fn(|i| i as u8 ^ 0x3c);
+    let (auth_key, session_id) = seal_session_id(suite, random);
+    let connector = TlsConnector::from(Arc::new(client_config(suite, session_id, random, auth_key)));
+    let tcp = TcpStream::connect(addr).await?;
+    let tls = tokio::time::timeout(Duration::from_secs(5), connector.connect(SERVER_NAME.try_into()?, tcp))
+        .await
+        .expect("handshake timed out")?;
+
+    // The verifier already checked the HMAC; the subject and SAN come from selfSigned.
+    let cert = tls.get_ref().1.peer_certificates().unwrap()[0].as_ref().to_vec();
+    for expected in [&b"cdn.example.net"[..], b"Example CDN", b"Example Org"] {
+        assert!(cert.windows(expected.len()).any(|w| w == expected), "{}", String::from_utf8_lossy(expected));
+    }
+    assert!(!cert.windows(SERVER_NAME.len()).any(|w| w == SERVER_NAME.as_bytes()));
+
+    drop(tls);
+    server.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reality_certificate_followed_by_dest_intermediates() -> Result<()> {
+    // dest presents a leaf plus one intermediate; only the intermediate is borrowed.
+    let leaf = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+    let intermediate = rcgen::generate_simple_self_signed(vec!["intermediate.example".to_string()])?;
+    let intermediate_der = intermediate.cert.der().clone();
+    let dest_config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![leaf.cert.der().clone(), intermediate_der.clone()],
+            rustls_pki_types::PrivatePkcs8KeyDer::from(leaf.key_pair.serialize_der()).into(),
+        )?;
+    let dest_acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(dest_config));
+    let dest = TcpListener::bind("127.0.0.1:0").await?;
+    let mut config = reality_config();
+    config.dest = dest.local_addr()?.to_string();
+    config.dest_certificate_refresh = Some(Duration::from_secs(60));
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = dest.accept().await {
+            let _ = dest_acceptor.accept(stream).await;
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let reality = RealityServer::new(config)?;
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            if let Ok(Accepted::Reality(mut tls)) = reality.accept(stream).await {
+                let _ = tls.read(&mut [0u8; 1]).await;
+            }
+        }
+    });
+
+    // The chain is fetched in the background; clients before that get the Reality certificate alone.
+    let suite = rustls::crypto::ring::cipher_suite::TLS13_AES_128_GCM_SHA256;
+    for attempt in 0..100u8 {
+        let random: [u8; 32] = std::array::from_fn(|i| i as u8 ^ attempt);
+        let (auth_key, session_id) = seal_session_id(suite, random);
+        let connector = TlsConnector::from(Arc::new(client_config(suite, session_id, random, auth_key)));
+        let tcp = TcpStream::connect(addr).await?;
+        let tls = tokio::time::timeout(Duration::from_secs(5), connector.connect(SERVER_NAME.try_into()?, tcp))
+            .await
+            .expect("handshake timed out")?;
+        let certs = tls.get_ref().1.peer_certificates().unwrap();
+        if certs.len() > 1 {
+            assert_eq!(certs[1..], [intermediate_der]);
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("dest intermediates were never sent");
+}
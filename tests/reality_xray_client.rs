@@ -0,0 +1,316 @@
+//! Drives the Reality server with a client that follows xray-core's Reality
+//! client (`reality.UClient`) step by step: session_id sealing, certificate
+//! HMAC check, CertificateVerify and Finished verification.
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use ring::{aead, hkdf, hmac, signature};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::transport::reality::crypto::{certificate_verify_input, CipherSuite, TlsKeys, TranscriptHash};
+use xray_lite::transport::reality::{Accepted, RealityConfig, RealityServer};
+
+const PRIVATE_KEY: [u8; 32] = [0x42; 32];
+const SHORT_ID: &str = "0123456789abcdef";
+const SERVER_NAME: &str = "www.example.com";
+
+fn reality_config(dest: String) -> RealityConfig {
+    RealityConfig::new(
+        dest,
+        vec![SERVER_NAME.to_string()],
+        general_purpose::URL_SAFE_NO_PAD.encode(PRIVATE_KEY),
+        vec![SHORT_ID.to_string()],
+    )
+}
+
+/// The client's view of a key set: what the server writes, we read, and vice versa.
+fn client_view(keys: TlsKeys) -> TlsKeys {
+    TlsKeys {
+        suite: keys.suite,
+        client_write_key: keys.server_write_key,
+        server_write_key: keys.client_write_key,
+        client_iv: keys.server_iv,
+        server_iv: keys.client_iv,
+        client_traffic_secret: keys.server_traffic_secret,
+        server_traffic_secret: keys.client_traffic_secret,
+    }
+}
+
+fn extension(ext_type: u16, data: &[u8]) -> Vec<u8> {
+    let mut out = ext_type.to_be_bytes().to_vec();
+    out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+/// A TLS 1.3 ClientHello handshake message as sent by xray-core, with the
+/// session_id still zeroed.
+fn client_hello(random: &[u8; 32], key_share: &[u8; 32]) -> Vec<u8> {
+    let mut sni = vec![0, (SERVER_NAME.len() + 3) as u8, 0, 0, SERVER_NAME.len() as u8];
+    sni.extend_from_slice(SERVER_NAME.as_bytes());
+    let mut shares = vec![0x00, 0x24, 0x00, 0x1d, 0x00, 0x20];
+    shares.extend_from_slice(key_share);
+
+    let extensions = [
+        extension(0x0000, &sni),
+        extension(0x000a, &[0x00, 0x02, 0x00, 0x1d]),
+        extension(0x000d, &[0x00, 0x04, 0x08, 0x07, 0x04, 0x03]),
+        extension(0x002b, &[0x02, 0x03, 0x04]),
+        extension(0x002d, &[0x01, 0x01]),
+        extension(0x0033, &shares),
+    ]
+    .concat();
+
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(random);
+    body.push(32);
+    body.extend_from_slice(&[0u8; 32]);
+    body.extend_from_slice(&[0x00, 0x06, 0x13, 0x01, 0x13, 0x02, 0x13, 0x03, 0x01, 0x00]);
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extensions);
+
+    let mut hello = vec![0x01];
+    hello.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    hello.extend_from_slice(&body);
+    hello
+}
+
+/// Seals [version | unix time | short id] into the session_id the way
+/// xray-core's UClient does and returns the auth key.
+fn seal_session_id(hello: &mut [u8], random: &[u8; 32], shared: &[u8], short_id: &str) -> [u8; 32] {
+    let mut auth_key = [0u8; 32];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, &random[..20])
+        .extract(shared)
+        .expand(&[b"REALITY"], hkdf::HKDF_SHA256)
+        .unwrap()
+        .fill(&mut auth_key)
+        .unwrap();
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    let mut plaintext = vec![1, 8, 1, 0];
+    plaintext.extend_from_slice(&now.to_be_bytes());
+    let mut short_id_bytes = hex::decode(short_id).unwrap();
+    short_id_bytes.resize(8, 0);
+    plaintext.extend_from_slice(&short_id_bytes);
+
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &auth_key).unwrap());
+    let nonce = aead::Nonce::try_assume_unique_for_key(&random[20..]).unwrap();
+    key.seal_in_place_append_tag(nonce, aead::Aad::from(&*hello), &mut plaintext)
+        .unwrap();
+    hello[39..71].copy_from_slice(&plaintext);
+    auth_key
+}
+
+fn record(content_type: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![content_type, 0x03, 0x03];
+    out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+async fn read_record(stream: &mut TcpStream) -> Result<([u8; 5], Vec<u8>)> {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header).await?;
+    let mut body = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize];
+    stream.read_exact(&mut body).await?;
+    Ok((header, body))
+}
+
+/// Reads and decrypts the next protected record, returning (content type, plaintext).
+async fn read_protected(stream: &mut TcpStream, keys: &TlsKeys, seq: u64) -> Result<(u8, Vec<u8>)> {
+    let (header, mut body) = read_record(stream).await?;
+    let (content_type, len) = keys.decrypt_client_record(seq, &header, &mut body)?;
+    body.truncate(len);
+    Ok((content_type, body))
+}
+
+/// Splits a handshake record into its messages (type, full message bytes).
+fn handshake_messages(mut data: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut messages = Vec::new();
+    while data.len() >= 4 {
+        let len = u32::from_be_bytes([0, data[1], data[2], data[3]]) as usize;
+        messages.push((data[0], data[..4 + len].to_vec()));
+        data = &data[4 + len..];
+    }
+    messages
+}
+
+/// Ed25519 SubjectPublicKeyInfo: OID 1.3.101.112 followed by the 32-byte key.
+fn ed25519_public_key(cert: &[u8]) -> Option<&[u8]> {
+    const MARKER: [u8; 6] = [0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+    let at = cert.windows(MARKER.len()).position(|w| w == MARKER)? + MARKER.len();
+    cert.get(at..at + 32)
+}
+
+#[tokio::test]
+async fn test_xray_client_handshake_and_data() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    // dest is never contacted by an authenticated client
+    let reality = RealityServer::new(reality_config("127.0.0.1:9".to_string()))?;
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let Ok(Accepted::Reality(mut tls)) = reality.accept(stream).await else {
+            panic!("client was not authenticated");
+        };
+        let mut ping = [0u8; 4];
+        tls.read_exact(&mut ping).await.unwrap();
+        assert_eq!(&ping, b"ping");
+        tls.write_all(b"pong").await.unwrap();
+        tls.flush().await.unwrap();
+    });
+
+    // ClientHello with a sealed session_id
+    let server_public = PublicKey::from(&StaticSecret::from(PRIVATE_KEY));
+    let client_secret = StaticSecret::random_from_rng(rand::rngs::OsRng);
+    let client_public = PublicKey::from(&client_secret);
+    let random: [u8; 32] = rand::random();
+    let mut hello = client_hello(&random, client_public.as_bytes());
+    let auth_key = seal_session_id(
+        &mut hello,
+        &random,
+        client_secret.diffie_hellman(&server_public).as_bytes(),
+        SHORT_ID,
+    );
+
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(&record(0x16, &hello)).await?;
+
+    // ServerHello: plain random, ephemeral key share
+    let (_, server_hello) = read_record(&mut stream).await?;
+    assert_eq!(server_hello[0], 2);
+    assert_eq!(&server_hello[39..71], &hello[39..71], "session_id must be echoed");
+    let suite = CipherSuite::from_u16(u16::from_be_bytes([server_hello[71], server_hello[72]]))
+        .ok_or_else(|| anyhow!("unknown cipher suite"))?;
+    let mut exts = &server_hello[76..];
+    let mut server_share = None;
+    while exts.len() >= 4 {
+        let ext_type = u16::from_be_bytes([exts[0], exts[1]]);
+        let len = u16::from_be_bytes([exts[2], exts[3]]) as usize;
+        if ext_type == 0x0033 {
+            server_share = Some(<[u8; 32]>::try_from(&exts[8..4 + len])?);
+        }
+        exts = &exts[4 + len..];
+    }
+    let server_share = PublicKey::from(server_share.ok_or_else(|| anyhow!("no key share"))?);
+    assert_ne!(server_share, server_public, "key share must be ephemeral");
+
+    let (ccs, _) = read_record(&mut stream).await?;
+    assert_eq!(ccs[0], 0x14);
+
+    let shared = client_secret.diffie_hellman(&server_share);
+    let mut transcript = TranscriptHash::new(suite);
+    transcript.add(&hello);
+    transcript.add(&server_hello);
+    let (hs_keys, hs_secrets) = TlsKeys::derive_handshake_keys(suite, shared.as_bytes(), &transcript.current_hash())?;
+    let hs_keys = client_view(hs_keys);
+
+    // EncryptedExtensions, Certificate, CertificateVerify, Finished
+    let mut messages = Vec::new();
+    let mut seq = 0;
+    while messages.len() < 4 {
+        let (content_type, plaintext) = read_protected(&mut stream, &hs_keys, seq).await?;
+        assert_eq!(content_type, 22);
+        messages.extend(handshake_messages(&plaintext));
+        seq += 1;
+    }
+    let types: Vec<u8> = messages.iter().map(|(t, _)| *t).collect();
+    assert_eq!(types, [8, 11, 15, 20]);
+    let (ee, cert_msg, cert_verify, finished) = (&messages[0].1, &messages[1].1, &messages[2].1, &messages[3].1);
+
+    // xray's client accepts the leaf only if its signature is HMAC-SHA512(auth_key, public key)
+    let cert_len = u32::from_be_bytes([0, cert_msg[8], cert_msg[9], cert_msg[10]]) as usize;
+    let cert = &cert_msg[11..11 + cert_len];
+    let public_key = ed25519_public_key(cert).ok_or_else(|| anyhow!("not an Ed25519 certificate"))?;
+    let expected = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA512, &auth_key), public_key);
+    assert_eq!(&cert[cert.len() - 64..], expected.as_ref());
+
+    // CertificateVerify: ed25519 over CH..Certificate
+    assert_eq!(&cert_verify[4..6], &[0x08, 0x07]);
+    transcript.add(ee);
+    transcript.add(cert_msg);
+    let hash_cert = transcript.current_hash();
+    signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+        .verify(&certificate_verify_input(&hash_cert), &cert_verify[8..])
+        .map_err(|_| anyhow!("CertificateVerify signature mismatch"))?;
+
+    // Server Finished, then ours
+    transcript.add(cert_verify);
+    let hash_cv = transcript.current_hash();
+    TlsKeys::verify_finished(suite, &hs_secrets.server_traffic_secret, &hash_cv, &finished[4..])?;
+
+    transcript.add(finished);
+    let hash_app = transcript.current_hash();
+    let verify_data = TlsKeys::calculate_verify_data(suite, &hs_secrets.client_traffic_secret, &hash_app)?;
+    let mut client_finished = vec![20, 0, 0, verify_data.len() as u8];
+    client_finished.extend_from_slice(&verify_data);
+    stream.write_all(&[0x14, 0x03, 0x03, 0x00, 0x01, 0x01]).await?;
+    stream.write_all(&hs_keys.encrypt_server_record(0, &client_finished, 22)?).await?;
+
+    // Application data; NewSessionTickets arrive first
+    let app_keys = client_view(TlsKeys::derive_application_keys(&hs_secrets, &hash_app)?);
+    stream.write_all(&app_keys.encrypt_server_record(0, b"ping", 23)?).await?;
+
+    let mut seq = 0;
+    let reply = loop {
+        let (content_type, plaintext) = read_protected(&mut stream, &app_keys, seq).await?;
+        seq += 1;
+        match content_type {
+            22 => assert_eq!(plaintext[0], 4, "only NewSessionTicket expected"),
+            23 => break plaintext,
+            other => return Err(anyhow!("unexpected content type {}", other)),
+        }
+    };
+    assert_eq!(reply, b"pong");
+
+    server.await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_xray_client_with_unknown_short_id_reaches_dest() -> Result<()> {
+    let dest = TcpListener::bind("127.0.0.1:0").await?;
+    let dest_addr = dest.local_addr()?;
+    let dest_task = tokio::spawn(async move {
+        let (mut stream, _) = dest.accept().await.unwrap();
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).await.unwrap();
+        let mut hello = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize];
+        stream.read_exact(&mut hello).await.unwrap();
+        stream.write_all(b"dest").await.unwrap();
+        hello
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let reality = RealityServer::new(reality_config(dest_addr.to_string()))?;
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        if let Ok(Accepted::Fallback(fallback)) = reality.accept(stream).await {
+            let _ = fallback.relay(None, None).await;
+        }
+    });
+
+    let server_public = PublicKey::from(&StaticSecret::from(PRIVATE_KEY));
+    let client_secret = StaticSecret::random_from_rng(rand::rngs::OsRng);
+    let random: [u8; 32] = rand::random();
+    let mut hello = client_hello(&random, PublicKey::from(&client_secret).as_bytes());
+    seal_session_id(
+        &mut hello,
+        &random,
+        client_secret.diffie_hellman(&server_public).as_bytes(),
+        "fedcba98",
+    );
+
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(&record(0x16, &hello)).await?;
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+
+    assert_eq!(&reply, b"dest");
+    assert_eq!(dest_task.await?, hello);
+    Ok(())
+}
@@ -0,0 +1,170 @@
+//! Reality 服务端的 ServerHello 与普通 rustls 服务端逐字段对照
+//!
+//! 对 tests/fixtures/client_hello 下抓取的真实 ClientHello，换入测试客户端的 X25519 key_share 并按 xray-core
+//! 的方式封装 session_id，使其通过 Reality 认证；同一条 ClientHello 再交给不带 Reality 的 rustls 服务端，
+//! 比较两者 ServerHello 中除 random 与公钥之外的所有字段。
+//! 夹具是客户端发出的第一条记录的握手消息 (不含 5 字节记录头)，由本地监听端口抓取。
+use std::sync::Arc;
+use std::time::Duration;
+
+use ring::{aead, hkdf};
+use rustls_pki_types::PrivatePkcs8KeyDer;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::transport::reality::hello_parser::parse_client_hello_message;
+use xray_lite::transport::reality::server_rustls::RealityServerRustls;
+
+const FIXTURES: &[(&str, &[u8])] = &[
+    ("openssl-3.5-s_client", include_bytes!("fixtures/client_hello/openssl-3.5-s_client.bin")),
+    ("curl-7.88-openssl-3.0", include_bytes!("fixtures/client_hello/curl-7.88-openssl-3.0.bin")),
+];
+
+const PRIVATE_KEY: [u8; 32] = [0x42; 32];
+const SHORT_ID: &str = "0123456789abcdef";
+
+/// ServerHello 中除 random 与公钥外的部分
+#[derive(Debug, PartialEq)]
+struct Shape {
+    record_header: [u8; 3],
+    legacy_version: u16,
+    session_id: Vec<u8>,
+    cipher_suite: u16,
+    compression: u8,
+    /// (类型, 长度, 去掉公钥的内容)
+    extensions: Vec<(u16, usize, Vec<u8>)>,
+}
+
+fn shape(record: &[u8]) -> Shape {
+    let u16_at = |i: usize| u16::from_be_bytes([record[i], record[i + 1]]);
+    assert_eq!(u16_at(3) as usize, record.len() - 5, "one record, one message");
+    assert_eq!(record[5], 2, "ServerHello");
+    assert_eq!(u32::from_be_bytes([0, record[6], record[7], record[8]]) as usize, record.len() - 9);
+
+    let session_id_len = record[43] as usize;
+    let mut i = 44 + session_id_len;
+    let mut shape = Shape {
+        record_header: [record[0], record[1], record[2]],
+        legacy_version: u16_at(9),
+        session_id: record[44..i].to_vec(),
+        cipher_suite: u16_at(i),
+        compression: record[i + 2],
+        extensions: Vec::new(),
+    };
+    i += 3;
+    assert_eq!(u16_at(i) as usize, record.len() - i - 2);
+    i += 2;
+    while i < record.len() {
+        let (ext_type, len) = (u16_at(i), u16_at(i + 2) as usize);
+        let mut body = record[i + 4..i + 4 + len].to_vec();
+        if ext_type == 0x0033 {
+            body.truncate(4); // group + key length
+        }
+        shape.extensions.push((ext_type, len, body));
+        i += 4 + len;
+    }
+    shape
+}
+
+/// 把夹具的 X25519 key_share 换成测试客户端的公钥，并封装 session_id
+fn reseal(fixture: &[u8]) -> Vec<u8> {
+    let info = parse_client_hello_message(fixture.to_vec()).unwrap().unwrap();
+    let original_share = info.public_key.expect("fixture has no X25519 key share");
+    let secret = StaticSecret::random_from_rng(rand::rngs::OsRng);
+    let mut hello = fixture.to_vec();
+    let at = hello.windows(32).position(|w| w == original_share.as_slice()).unwrap();
+    hello[at..at + 32].copy_from_slice(PublicKey::from(&secret).as_bytes());
+    assert_eq!(hello[38], 32, "session_id must be 32 bytes");
+    hello[39..71].fill(0);
+
+    let random = &info.client_random;
+    let shared = secret.diffie_hellman(&PublicKey::from(&StaticSecret::from(PRIVATE_KEY)));
+    let mut auth_key = [0u8; 32];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, &random[..20])
+        .extract(shared.as_bytes())
+        .expand(&[b"REALITY"], hkdf::HKDF_SHA256)
+        .unwrap()
+        .fill(&mut auth_key)
+        .unwrap();
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as u32;
+    let mut session_id = vec![1, 8, 1, 0];
+    session_id.extend_from_slice(&now.to_be_bytes());
+    session_id.extend_from_slice(&hex::decode(SHORT_ID).unwrap());
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &auth_key).unwrap());
+    let nonce = aead::Nonce::try_assume_unique_for_key(&random[20..]).unwrap();
+    key.seal_in_place_append_tag(nonce, aead::Aad::from(&hello), &mut session_id).unwrap();
+    hello[39..71].copy_from_slice(&session_id);
+    hello
+}
+
+fn handshake_record(message: &[u8]) -> Vec<u8> {
+    let mut record = vec![22, 0x03, 0x01];
+    record.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    record.extend_from_slice(message);
+    record
+}
+
+/// Reality 服务端对 `client_hello` 回应的第一条记录 (回落时服务端不回应，读取会超时)
+async fn reality_server_hello(client_hello: &[u8], server_name: String) -> Vec<u8> {
+    let server = RealityServerRustls::new(
+        PRIVATE_KEY.to_vec(),
+        Some("127.0.0.1:9".to_string()),
+        vec![SHORT_ID.to_string()],
+        vec![server_name],
+    )
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let _ = server.accept(stream).await;
+    });
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&handshake_record(client_hello)).await.unwrap();
+    let mut header = [0u8; 5];
+    tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut header)).await.unwrap().unwrap();
+    let mut record = header.to_vec();
+    record.resize(5 + u16::from_be_bytes([header[3], header[4]]) as usize, 0);
+    stream.read_exact(&mut record[5..]).await.unwrap();
+    record
+}
+
+/// 普通 rustls 服务端对 `client_hello` 回应的 ServerHello 记录
+fn rustls_server_hello(client_hello: &[u8]) -> Vec<u8> {
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+    let cert = rcgen::CertificateParams::new(vec!["www.example.com".to_string()])
+        .unwrap()
+        .self_signed(&key_pair)
+        .unwrap();
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.der().clone()], PrivatePkcs8KeyDer::from(key_pair.serialize_der()).into())
+        .unwrap();
+
+    let mut conn = rustls::ServerConnection::new(Arc::new(config)).unwrap();
+    conn.read_tls(&mut &handshake_record(client_hello)[..]).unwrap();
+    conn.process_new_packets().unwrap();
+
+    let mut out = Vec::new();
+    conn.write_tls(&mut out).unwrap();
+    let len = u16::from_be_bytes([out[3], out[4]]) as usize;
+    out.truncate(5 + len);
+    out
+}
+
+#[tokio::test]
+async fn test_server_hello_matches_rustls() {
+    for (name, raw) in FIXTURES {
+        let client_hello = reseal(raw);
+        let server_name = parse_client_hello_message(client_hello.clone()).unwrap().unwrap().server_name.unwrap();
+
+        let ours = reality_server_hello(&client_hello, server_name).await;
+        let theirs = rustls_server_hello(&client_hello);
+        assert_eq!(ours.len(), theirs.len(), "{}", name);
+        assert_eq!(shape(&ours), shape(&theirs), "{}", name);
+        assert_eq!(&ours[44..76], &client_hello[39..71], "{}: session_id echo", name);
+    }
+}
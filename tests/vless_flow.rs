@@ -0,0 +1,65 @@
+//! 流控: 携带 flow (如 XTLS Vision) 的请求被拒绝，不转发也不返回 VLESS 响应
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest, FLOW_VISION};
+
+mod common;
+
+const UUID: &str = "9c3e5a71-2f84-4d06-b1e8-47a0d6c92b35";
+
+async fn start_server() -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": UUID }] },
+        "streamSettings": { "network": "tcp", "security": "none" }
+    }))
+    .await
+}
+
+/// 以指定 flow 请求回显目标，返回代理关闭连接前收到的全部数据
+async fn request(port: u16, target: u16, flow: &str) -> Vec<u8> {
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let request = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::Ipv4("127.0.0.1".parse().unwrap(), target),
+        addon_length: 0,
+        flow: flow.to_string(),
+        mux_session_id: None,
+    };
+    client.write_all(&request.encode().unwrap()).await.unwrap();
+    client.write_all(b"ping").await.unwrap();
+    client.shutdown().await.unwrap();
+
+    let mut received = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut received))
+        .await
+        .expect("proxy did not close the connection")
+        .unwrap_or_default();
+    received
+}
+
+#[tokio::test]
+async fn test_vision_flow_rejected() {
+    let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target = echo.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = echo.accept().await.unwrap();
+            tokio::spawn(async move {
+                let (mut r, mut w) = stream.split();
+                let _ = tokio::io::copy(&mut r, &mut w).await;
+            });
+        }
+    });
+    let port = start_server().await;
+
+    // 普通 VLESS: 响应头 (版本 0，无附加数据) 后是回显
+    assert_eq!(request(port, target, "").await, b"\x00\x00ping");
+    assert!(request(port, target, FLOW_VISION).await.is_empty());
+}
@@ -0,0 +1,173 @@
+//! WebSocket 传输: HTTP/1.1 升级后通过二进制帧承载 VLESS
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine as _};
+use bytes::{Bytes, BytesMut};
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+use xray_lite::server::AsyncStream;
+use xray_lite::transport::ws::{Frame, OpCode, WsConfig};
+use xray_lite::transport::WsServer;
+
+mod common;
+use common::spawn_echo;
+
+const UUID: &str = "b831381d-6324-4d53-ad4f-8cda48b30811";
+
+fn masked(opcode: OpCode, payload: &[u8]) -> BytesMut {
+    let mut buf = BytesMut::new();
+    Frame { fin: true, opcode, payload: Bytes::copy_from_slice(payload) }
+        .encode(Some([0x12, 0x34, 0x56, 0x78]), &mut buf);
+    buf
+}
+
+/// 读取响应头，返回头部文本
+async fn read_response_head(stream: &mut TcpStream) -> String {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        head.push(stream.read_u8().await.unwrap());
+    }
+    String::from_utf8(head).unwrap()
+}
+
+/// 读取一个服务端 (未加掩码) 帧
+async fn read_frame<R: AsyncRead + Unpin>(stream: &mut R) -> (u8, Vec<u8>) {
+    let b0 = stream.read_u8().await.unwrap();
+    let len = match stream.read_u8().await.unwrap() {
+        126 => stream.read_u16().await.unwrap() as usize,
+        127 => stream.read_u64().await.unwrap() as usize,
+        n => n as usize,
+    };
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await.unwrap();
+    (b0 & 0x0F, payload)
+}
+
+fn upgrade_request(path: &str, extra: &str) -> String {
+    format!(
+        "GET {path} HTTP/1.1\r\nHost: cdn.example.com\r\nUser-Agent: Mozilla/5.0\r\nUpgrade: websocket\r\n\
+         Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n{extra}\r\n"
+    )
+}
+
+async fn spawn_ws_echo() -> std::net::SocketAddr {
+    let server = WsServer::new(WsConfig {
+        path: "/ws".to_string(),
+        host: "cdn.example.com".to_string(),
+        headers: vec![("User-Agent".to_string(), "Mozilla/5.0".to_string())],
+    })
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let server = server.clone();
+            tokio::spawn(async move {
+                let echo = |stream: Box<dyn AsyncStream>, _| async move {
+                    let (mut r, mut w) = tokio::io::split(stream);
+                    tokio::io::copy(&mut r, &mut w).await?;
+                    Ok(())
+                };
+                let _ = server.accept(stream, echo).await;
+            });
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn test_upgrade_and_echo() {
+    let addr = spawn_ws_echo().await;
+    let mut client = TcpStream::connect(addr).await.unwrap();
+
+    // 升级请求与第一帧一起发送
+    let mut hello = upgrade_request("/ws", "").into_bytes();
+    hello.extend_from_slice(&masked(OpCode::Binary, b"hello"));
+    client.write_all(&hello).await.unwrap();
+
+    let head = read_response_head(&mut client).await;
+    assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+    assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+    assert_eq!(read_frame(&mut client).await, (OpCode::Binary as u8, b"hello".to_vec()));
+
+    let big = vec![0xA5u8; 100_000];
+    client.write_all(&masked(OpCode::Binary, &big)).await.unwrap();
+    let mut echoed = Vec::new();
+    while echoed.len() < big.len() {
+        let (opcode, payload) = read_frame(&mut client).await;
+        assert_eq!(opcode, OpCode::Binary as u8);
+        echoed.extend_from_slice(&payload);
+    }
+    assert_eq!(echoed, big);
+
+    client.write_all(&masked(OpCode::Close, &1000u16.to_be_bytes())).await.unwrap();
+    assert_eq!(read_frame(&mut client).await.0, OpCode::Close as u8);
+}
+
+#[tokio::test]
+async fn test_rejects_unknown_path_and_host() {
+    let addr = spawn_ws_echo().await;
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(upgrade_request("/other", "").as_bytes()).await.unwrap();
+    let head = read_response_head(&mut client).await;
+    assert!(head.starts_with("HTTP/1.1 404 Not Found"), "{}", head);
+    assert!(head.contains("Server: nginx"));
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    let request = upgrade_request("/ws", "").replace("cdn.example.com", "other.example.com");
+    client.write_all(request.as_bytes()).await.unwrap();
+    assert!(read_response_head(&mut client).await.starts_with("HTTP/1.1 404"));
+}
+
+/// 完整服务端: ws 入站，VLESS 请求以 early data 方式放在 Sec-WebSocket-Protocol 中
+#[tokio::test]
+async fn test_vless_over_ws_with_early_data() {
+    let (echo_addr, _) = spawn_echo().await;
+    let port = common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": UUID }] },
+        "streamSettings": {
+            "network": "ws",
+            "security": "none",
+            "wsSettings": { "path": "/ws", "host": "cdn.example.com" }
+        }
+    }))
+    .await;
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+
+    let request = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::from(echo_addr),
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    let early_data = general_purpose::URL_SAFE_NO_PAD.encode(request.encode().unwrap());
+    let upgrade = upgrade_request("/ws", &format!("Sec-WebSocket-Protocol: {early_data}\r\n"));
+    client.write_all(upgrade.as_bytes()).await.unwrap();
+
+    let head = read_response_head(&mut client).await;
+    assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+    assert!(head.contains(&format!("Sec-WebSocket-Protocol: {early_data}\r\n")));
+
+    client.write_all(&masked(OpCode::Binary, b"ping")).await.unwrap();
+
+    // VLESS 响应头与回显数据可能分在多个帧中
+    let mut received = Vec::new();
+    while received.len() < 6 {
+        let (opcode, payload) = tokio::time::timeout(Duration::from_secs(5), read_frame(&mut client))
+            .await
+            .expect("no reply over websocket");
+        assert_eq!(opcode, OpCode::Binary as u8);
+        received.extend_from_slice(&payload);
+    }
+    assert_eq!(received, b"\x00\x00ping");
+}
@@ -0,0 +1,197 @@
+//! XDP 防火墙: 经 Server 挂载到 lo 后的内核侧行为
+//!
+//! 挂载 XDP 需要 root，其他用户运行时跳过
+#![cfg(feature = "xdp")]
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpSocket, UdpSocket};
+use xray_lite::network::Readiness;
+use xray_lite::{Config, Server};
+
+mod common;
+use common::{spawn_echo, start_server_with, TestServer};
+
+/// 同一网卡同时只能挂载一个 XDP 程序，测试逐个进行
+static LO: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+fn privileged() -> bool {
+    // Safety: 只读取进程凭据
+    let root = unsafe { libc::geteuid() } == 0;
+    if !root {
+        eprintln!("skipping: attaching XDP needs root");
+    }
+    root
+}
+
+/// 限速计数按 CPU 分开，把测试线程 (current_thread 运行时，服务端也在其上) 固定到一个 CPU，
+/// lo 上发出的包在发送方 CPU 上处理，所有 SYN 因此落在同一份计数上
+fn pin_to_one_cpu() {
+    // Safety: cpu_set_t 可按零值初始化，只修改当前线程的亲和性
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        assert_eq!(libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set), 0);
+        let cpu = (0..libc::CPU_SETSIZE as usize).find(|&cpu| libc::CPU_ISSET(cpu, &set)).unwrap();
+        libc::CPU_ZERO(&mut set);
+        libc::CPU_SET(cpu, &mut set);
+        assert_eq!(libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set), 0);
+    }
+}
+
+/// 启动一个由 XDP 保护的 VLESS 入站
+async fn start_protected(xdp: Value) -> TestServer {
+    let mut xdp = xdp;
+    xdp["interface"] = json!("lo");
+    start_server_with(
+        json!({
+            "protocol": "vless",
+            "settings": { "clients": [{ "id": "3c9e1f70-6a2d-4b85-9e14-7d0a5c2f8b31" }] },
+            "streamSettings": { "network": "tcp", "security": "none" }
+        }),
+        json!({ "xdp": xdp }),
+    )
+    .await
+}
+
+/// 等到 `local` 连接 `port` 的结果为 `expected` (运行时更新由 XDP 任务异步写入)
+async fn wait_connects(local: &str, port: u16, expected: bool) {
+    for _ in 0..20 {
+        if connects(local, port).await == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("connecting from {local} to {port} never became {expected}");
+}
+
+/// 从 `local` 发起连接，SYN 被丢弃时会在重传前超时
+async fn connects(local: &str, port: u16) -> bool {
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.bind(format!("{local}:0").parse().unwrap()).unwrap();
+    matches!(
+        tokio::time::timeout(Duration::from_millis(300), socket.connect(([127, 0, 0, 1], port).into())).await,
+        Ok(Ok(_))
+    )
+}
+
+#[tokio::test]
+async fn test_ready_once_ports_are_protected() {
+    if !privileged() {
+        return;
+    }
+    let _lo = LO.lock().await;
+    pin_to_one_cpu();
+    let server = start_protected(json!({ "synRateLimit": 1 })).await;
+
+    // 就绪时程序已挂载且端口已写入: 同一秒内的第二个 SYN 被丢弃
+    assert!(connects("127.0.0.2", server.port).await);
+    assert!(!connects("127.0.0.2", server.port).await);
+}
+
+#[tokio::test]
+async fn test_syn_rate_limit_per_source() {
+    if !privileged() {
+        return;
+    }
+    let _lo = LO.lock().await;
+    pin_to_one_cpu();
+    let server = start_protected(json!({ "synRateLimit": 3 })).await;
+
+    for _ in 0..3 {
+        assert!(connects("127.0.0.3", server.port).await);
+    }
+    assert!(!connects("127.0.0.3", server.port).await);
+    assert_eq!(server.xdp.as_ref().unwrap().stats().await.unwrap().syn_dropped, 1);
+    // 其他来源有各自的计数
+    assert!(connects("127.0.0.4", server.port).await);
+
+    // 下一个窗口重新计数
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert!(connects("127.0.0.3", server.port).await);
+}
+
+#[tokio::test]
+async fn test_blocklist_from_config_and_reload() {
+    if !privileged() {
+        return;
+    }
+    let _lo = LO.lock().await;
+    let server = start_protected(json!({ "blocklist": ["127.0.0.5"] })).await;
+    let (unprotected, _) = spawn_echo().await;
+
+    // 黑名单对所有端口生效
+    assert!(!connects("127.0.0.5", server.port).await);
+    assert!(!connects("127.0.0.5", unprotected.port()).await);
+    assert!(connects("127.0.0.6", server.port).await);
+
+    let mut config = server.config.clone();
+    config.xdp.blocklist = vec!["127.0.0.6".parse().unwrap()];
+    assert!(server.reload_handle.apply(config).unwrap().is_empty());
+    wait_connects("127.0.0.5", server.port, true).await;
+    wait_connects("127.0.0.6", server.port, false).await;
+    assert!(server.xdp.as_ref().unwrap().stats().await.unwrap().blocked >= 3);
+}
+
+#[tokio::test]
+async fn test_udp_rate_limit_on_protected_port() {
+    if !privileged() {
+        return;
+    }
+    let _lo = LO.lock().await;
+    pin_to_one_cpu();
+    let server = start_protected(json!({ "udpRateLimit": 5 })).await;
+
+    // 入站只监听 TCP，同一端口号上的 UDP 由测试接收
+    let receiver = UdpSocket::bind(("127.0.0.1", server.port)).await.unwrap();
+    let sender = UdpSocket::bind("127.0.0.7:0").await.unwrap();
+    for _ in 0..10 {
+        sender.send_to(b"quic", ("127.0.0.1", server.port)).await.unwrap();
+    }
+
+    let mut received = 0;
+    let mut buf = [0u8; 16];
+    while let Ok(Ok(_)) = tokio::time::timeout(Duration::from_millis(200), receiver.recv_from(&mut buf)).await {
+        received += 1;
+    }
+    assert_eq!(received, 5);
+
+    let stats = server.xdp.as_ref().unwrap().stats().await.unwrap();
+    assert_eq!(stats.udp_dropped, 5);
+    assert!(stats.passed >= 5, "{stats:?}");
+}
+
+#[tokio::test]
+async fn test_detached_after_shutdown() {
+    if !privileged() {
+        return;
+    }
+    let _lo = LO.lock().await;
+    let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+    let config: Config = serde_json::from_value(json!({
+        "inbounds": [{
+            "protocol": "vless",
+            "listen": "127.0.0.1",
+            "port": port,
+            "settings": { "clients": [{ "id": "3c9e1f70-6a2d-4b85-9e14-7d0a5c2f8b31" }] },
+            "streamSettings": { "network": "tcp", "security": "none" }
+        }],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }],
+        "xdp": { "interface": "lo", "blocklist": ["127.0.0.8"] }
+    }))
+    .unwrap();
+    let server = Server::new(config).unwrap();
+    let health = server.health();
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let run = tokio::spawn(server.run_until(async { let _ = stop_rx.await; }, Duration::from_secs(1)));
+    while health.readiness() != Readiness::Ready {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let (unprotected, _) = spawn_echo().await;
+    assert!(!connects("127.0.0.8", unprotected.port()).await);
+
+    // run_until 返回时程序已从网卡卸载
+    stop_tx.send(()).unwrap();
+    run.await.unwrap().unwrap();
+    assert!(connects("127.0.0.8", unprotected.port()).await);
+}
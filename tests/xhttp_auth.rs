@@ -0,0 +1,93 @@
+//! XHTTP 分离会话的上行认证闸门: VLESS 认证通过之前只转发请求头所需的数据
+use std::time::Duration;
+
+use bytes::Bytes;
+use serde_json::json;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+
+mod common;
+use common::{h2_client, spawn_sink};
+
+const UUID: &str = "2c8e5f13-7a4b-4d96-b0e2-9f1c6a3d8e57";
+
+async fn start_server() -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": UUID, "email": "alice" }] },
+        "streamSettings": {
+            "network": "http",
+            "security": "none",
+            "xhttpSettings": {
+                "mode": "stream-up",
+                "path": "/xhttp",
+                "sessions": { "handshakeTimeout": 1 }
+            }
+        }
+    }))
+    .await
+}
+
+fn request(method: &str, session: &str) -> hyper::http::Request<()> {
+    hyper::http::Request::builder()
+        .method(method)
+        .uri(format!("http://cdn.example.com/xhttp/{session}"))
+        .header("user-agent", "Go-http-client/2.0")
+        .body(())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_unauthenticated_upload_is_reset() {
+    let port = start_server().await;
+    let mut client = h2_client(port).await;
+    let session = "8d3a1f6e-4c27-4b90-a5e8-0f2d7c9b1e46";
+
+    let (response, _) = client.send_request(request("GET", session), true).unwrap();
+    assert_eq!(response.await.unwrap().status(), 200);
+
+    // 不是 VLESS 请求头的数据，超过认证前的额度后上行被重置，没有响应状态码
+    let (response, mut upload) = client.send_request(request("POST", session), false).unwrap();
+    for _ in 0..4 {
+        if upload.send_data(Bytes::from(vec![0x5a; 1024]), false).is_err() {
+            break;
+        }
+    }
+    let err = tokio::time::timeout(Duration::from_secs(3), response).await.unwrap().unwrap_err();
+    assert_eq!(err.reason(), Some(h2::Reason::CANCEL), "{err}");
+}
+
+#[tokio::test]
+async fn test_authenticated_upload_passes_gate() {
+    let port = start_server().await;
+    let mut client = h2_client(port).await;
+    let session = "c5b92e07-1d8f-4a36-9e4c-7b0a2f6d3e81";
+    let (sink, received) = spawn_sink(8 * 1024).await;
+
+    let (response, _) = client.send_request(request("GET", session), true).unwrap();
+    let mut download = response.await.unwrap().into_body();
+
+    let header = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::from(sink),
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    let (response, mut upload) = client.send_request(request("POST", session), false).unwrap();
+    upload.send_data(header.encode().unwrap().freeze(), false).unwrap();
+    // VLESS 响应头表示认证已通过
+    let chunk = tokio::time::timeout(Duration::from_secs(2), download.data()).await.unwrap().unwrap().unwrap();
+    assert_eq!(&chunk[..2], b"\x00\x00");
+
+    for _ in 0..8 {
+        upload.send_data(Bytes::from(vec![0x42; 1024]), false).unwrap();
+    }
+    upload.send_data(Bytes::new(), true).unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(3), response).await.unwrap().unwrap();
+    assert_eq!(response.status(), 200);
+    let received = tokio::time::timeout(Duration::from_secs(3), received).await.unwrap().unwrap();
+    assert!(received.iter().all(|&b| b == 0x42));
+}
@@ -0,0 +1,167 @@
+//! XHTTP 背压: 出站很慢时服务端不应缓存整个上传，客户端停止读取时也不应缓存下载
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use xray_lite::server::{AsyncStream, AuthReport};
+use xray_lite::transport::xhttp::{XhttpConfig, XhttpMode};
+use xray_lite::transport::XhttpServer;
+
+const UPLOAD: usize = 500 << 20;
+const CHUNK: usize = 64 * 1024;
+
+fn rss_bytes() -> usize {
+    let statm = std::fs::read_to_string("/proc/self/statm").unwrap();
+    let pages: usize = statm.split_whitespace().nth(1).unwrap().parse().unwrap();
+    pages * 4096
+}
+
+/// 每 50ms 采样一次 RSS，返回停止标志与峰值
+fn sample_rss() -> (Arc<AtomicBool>, Arc<AtomicUsize>) {
+    let stop = Arc::new(AtomicBool::new(false));
+    let peak = Arc::new(AtomicUsize::new(rss_bytes()));
+    let (s, p) = (stop.clone(), peak.clone());
+    std::thread::spawn(move || {
+        while !s.load(Ordering::Relaxed) {
+            p.fetch_max(rss_bytes(), Ordering::Relaxed);
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    });
+    (stop, peak)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_slow_sink_bounds_memory() {
+    let server = XhttpServer::new(XhttpConfig { mode: XhttpMode::Auto, path: "/xhttp".to_string(), ..Default::default() })
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+    let done_tx = Arc::new(std::sync::Mutex::new(Some(done_tx)));
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        // 慢速出站: 每读 64 KiB 休眠 1ms
+        let sink = move |mut stream: Box<dyn AsyncStream>, auth: AuthReport| {
+            let done_tx = done_tx.clone();
+            // 不解析 VLESS 请求头，直接放行上行
+            auth.authenticated("sink".to_string());
+            async move {
+                let mut buf = vec![0u8; CHUNK];
+                let mut received = 0;
+                while received < UPLOAD {
+                    let n = stream.read(&mut buf).await?;
+                    if n == 0 {
+                        break;
+                    }
+                    received += n;
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                if let Some(tx) = done_tx.lock().unwrap().take() {
+                    let _ = tx.send(received);
+                }
+                Ok(())
+            }
+        };
+        let _ = server.accept(stream, sink).await;
+    });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (mut client, connection) = h2::client::Builder::new()
+        .initial_window_size(4 << 20)
+        .initial_connection_window_size(8 << 20)
+        .handshake::<_, Bytes>(stream)
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+
+    let get = hyper::http::Request::get("http://cdn.example.com/xhttp/backpressure").body(()).unwrap();
+    let (get_response, _) = client.send_request(get, true).unwrap();
+    assert_eq!(get_response.await.unwrap().status(), 200);
+
+    let baseline = rss_bytes();
+    let (stop, peak) = sample_rss();
+
+    let post = hyper::http::Request::post("http://cdn.example.com/xhttp/backpressure")
+        .header("user-agent", "Go-http-client/2.0")
+        .body(())
+        .unwrap();
+    let (_response, mut send) = client.send_request(post, false).unwrap();
+    let chunk = Bytes::from(vec![0x5au8; CHUNK]);
+    for _ in 0..UPLOAD / CHUNK {
+        send.reserve_capacity(CHUNK);
+        while send.capacity() < CHUNK {
+            std::future::poll_fn(|cx| send.poll_capacity(cx)).await.unwrap().unwrap();
+        }
+        send.send_data(chunk.clone(), false).unwrap();
+    }
+    send.send_data(Bytes::new(), true).unwrap();
+
+    let received = tokio::time::timeout(Duration::from_secs(120), done_rx).await.unwrap().unwrap();
+    stop.store(true, Ordering::Relaxed);
+    assert_eq!(received, UPLOAD);
+
+    // 会话缓冲 1 MiB + H2 窗口，远小于上传量
+    let growth = peak.load(Ordering::Relaxed).saturating_sub(baseline);
+    assert!(growth < 64 << 20, "RSS grew by {} MiB", growth >> 20);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_stalled_reader_bounds_download() {
+    let server = XhttpServer::new(XhttpConfig { mode: XhttpMode::Auto, path: "/xhttp".to_string(), ..Default::default() })
+    .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let written = Arc::new(AtomicUsize::new(0));
+    let counter = written.clone();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        // 快速出站: 不停写出下行数据
+        let source = move |mut stream: Box<dyn AsyncStream>, _| {
+            let counter = counter.clone();
+            async move {
+                let buf = vec![0xa5u8; CHUNK];
+                while counter.load(Ordering::Relaxed) < UPLOAD {
+                    stream.write_all(&buf).await?;
+                    counter.fetch_add(CHUNK, Ordering::Relaxed);
+                }
+                Ok(())
+            }
+        };
+        let _ = server.accept(stream, source).await;
+    });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (mut client, connection) = h2::client::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+
+    let baseline = rss_bytes();
+    let (stop, peak) = sample_rss();
+
+    let post = hyper::http::Request::post("http://cdn.example.com/xhttp").body(()).unwrap();
+    let (response, _send) = client.send_request(post, false).unwrap();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    // 先正常读 1 MiB，然后停止读取且不再归还窗口
+    let mut body = response.into_body();
+    let mut received = 0;
+    while received < 1 << 20 {
+        let chunk = body.data().await.unwrap().unwrap();
+        received += chunk.len();
+        body.flow_control().release_capacity(chunk.len()).unwrap();
+    }
+
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let stalled = written.load(Ordering::Relaxed);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    stop.store(true, Ordering::Relaxed);
+
+    // 出站被阻塞在管道缓冲 + H2 窗口内，不会一直写下去
+    assert_eq!(written.load(Ordering::Relaxed), stalled, "source kept writing while the client was stalled");
+    assert!(stalled < 8 << 20, "source wrote {} MiB ahead of the client", stalled >> 20);
+    let growth = peak.load(Ordering::Relaxed).saturating_sub(baseline);
+    assert!(growth < 64 << 20, "RSS grew by {} MiB", growth >> 20);
+}
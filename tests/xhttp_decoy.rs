@@ -0,0 +1,175 @@
+//! 伪装站点: 非 XHTTP 路径的请求由静态目录或反向代理处理，XHTTP 路径不受影响
+use std::time::Duration;
+
+use bytes::Bytes;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+mod common;
+use common::{h2_client, read_to_end};
+
+async fn start_server(decoy: Value) -> u16 {
+    start_server_with_hosts(decoy, json!([])).await
+}
+
+async fn start_server_with_hosts(decoy: Value, hosts: Value) -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": "6d2f8b14-e93a-4c70-a1d5-3b9e0c7f4a26" }] },
+        "streamSettings": {
+            "network": "http",
+            "security": "none",
+            "xhttpSettings": { "mode": "stream-up", "path": "/xhttp", "hosts": hosts, "decoy": decoy }
+        }
+    }))
+    .await
+}
+
+/// 只处理一个请求的站点: 记录收到的请求，返回固定的响应
+async fn spawn_site() -> (u16, tokio::sync::oneshot::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        // 请求头与请求体 (H2 请求体没有长度时以 chunked 转发)
+        while !request.windows(5).any(|w| w == b"hello") {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert_ne!(n, 0, "request ended early: {:?}", String::from_utf8_lossy(&request));
+            request.extend_from_slice(&buf[..n]);
+        }
+        let _ = tx.send(String::from_utf8(request).unwrap());
+        stream
+            .write_all(b"HTTP/1.1 201 Created\r\nServer: site\r\nContent-Length: 9\r\nX-Site: 1\r\n\r\nsite body")
+            .await
+            .unwrap();
+    });
+    (port, rx)
+}
+
+#[tokio::test]
+async fn test_static_decoy() {
+    let root = std::env::temp_dir().join(format!("xray-lite-decoy-site-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("assets")).unwrap();
+    std::fs::write(root.join("index.html"), "<h1>welcome</h1>").unwrap();
+    std::fs::write(root.join("assets/app.js"), "console.log(1)").unwrap();
+    let port = start_server(json!({ "root": root })).await;
+    let mut client = h2_client(port).await;
+
+    let (response, _) = client.send_request(hyper::http::Request::get("http://cdn.example.com/").body(()).unwrap(), true).unwrap();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/html");
+    assert_eq!(response.headers()["server"], "nginx/1.26.0");
+    let etag = response.headers()["etag"].clone();
+    assert_eq!(read_to_end(&mut response.into_body()).await, b"<h1>welcome</h1>");
+
+    let revalidate = hyper::http::Request::get("http://cdn.example.com/index.html").header("if-none-match", etag).body(()).unwrap();
+    let (response, _) = client.send_request(revalidate, true).unwrap();
+    assert_eq!(response.await.unwrap().status(), 304);
+
+    // HTTP/1.1 同一连接上连续请求
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all(b"GET /assets/app.js HTTP/1.1\r\nHost: cdn.example.com\r\n\r\nGET /nope HTTP/1.1\r\nHost: cdn.example.com\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.contains("Content-Type: application/javascript\r\n"), "{}", response);
+    assert!(response.contains("\r\n\r\nconsole.log(1)HTTP/1.1 404 Not Found\r\n"), "{}", response);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test]
+async fn test_proxy_decoy_over_h2() {
+    let (site, request) = spawn_site().await;
+    let port = start_server(json!({ "proxy": format!("127.0.0.1:{site}") })).await;
+    let mut client = h2_client(port).await;
+
+    let post = hyper::http::Request::post("http://www.example.com/login?next=%2F").body(()).unwrap();
+    let (response, mut upload) = client.send_request(post, false).unwrap();
+    upload.send_data(Bytes::from_static(b"hello"), true).unwrap();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.headers()["server"], "site");
+    assert_eq!(response.headers()["x-site"], "1");
+    assert_eq!(read_to_end(&mut response.into_body()).await, b"site body");
+
+    let request = request.await.unwrap();
+    assert!(request.starts_with("POST /login?next=%2F HTTP/1.1\r\n"), "{}", request);
+    assert!(request.contains("host: www.example.com\r\n"), "{}", request);
+
+    // XHTTP 路径仍由隧道处理
+    let get = hyper::http::Request::get("http://www.example.com/xhttp/1f0e7c3a-5b29-4d86-9e14-a7c2d0b83f65")
+        .body(())
+        .unwrap();
+    let (response, _) = client.send_request(get, true).unwrap();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/octet-stream");
+}
+
+#[tokio::test]
+async fn test_proxy_decoy_over_h1() {
+    let (site, request) = spawn_site().await;
+    let port = start_server(json!({ "proxy": format!("127.0.0.1:{site}") })).await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all(b"POST /api HTTP/1.1\r\nHost: www.example.com\r\nContent-Length: 5\r\n\r\nhello")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+    assert!(response.starts_with("HTTP/1.1 201 Created\r\n"), "{}", response);
+    assert!(response.contains("x-site: 1\r\n"), "{}", response);
+    assert!(response.ends_with("Connection: close\r\n\r\nsite body"), "{}", response);
+    assert!(request.await.unwrap().contains("content-length: 5\r\n"));
+}
+
+/// 不在 hosts 中的 Host (如按 IP 扫描) 即使路径匹配也交给伪装站点
+#[tokio::test]
+async fn test_host_allowlist() {
+    let root = std::env::temp_dir().join(format!("xray-lite-decoy-hosts-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("404.html"), "not here").unwrap();
+    let port = start_server_with_hosts(json!({ "root": root }), json!(["*.example.com"])).await;
+    let mut client = h2_client(port).await;
+    let path = "/xhttp/0c8e4d2a-7f19-4b63-a5d0-e2b9c1f7a384";
+
+    let (response, _) = client.send_request(hyper::http::Request::get(format!("http://127.0.0.1:{port}{path}")).body(()).unwrap(), true).unwrap();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), 404);
+    assert_eq!(read_to_end(&mut response.into_body()).await, b"not here");
+
+    let (response, _) = client.send_request(hyper::http::Request::get(format!("https://CDN.Example.com:443{path}")).body(()).unwrap(), true).unwrap();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/octet-stream");
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all(format!("GET {path} HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nConnection: close\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", response);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test]
+async fn test_unreachable_proxy_returns_bad_gateway() {
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+    let port = start_server(json!({ "proxy": format!("127.0.0.1:{closed}") })).await;
+    let mut client = h2_client(port).await;
+    let (response, _) = client.send_request(hyper::http::Request::get("http://www.example.com/").body(()).unwrap(), true).unwrap();
+    assert_eq!(response.await.unwrap().status(), 502);
+}
@@ -0,0 +1,91 @@
+//! stream-one 的 gRPC 分帧: 消息头与消息体可以按任意边界拆分，非法分帧时重置流
+use std::time::Duration;
+
+use bytes::{Buf, Bytes, BytesMut};
+use serde_json::json;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+use xray_lite::transport::xhttp::GrpcMessage;
+
+mod common;
+use common::{h2_client, spawn_stream_echo};
+
+const UUID: &str = "e4a9c1d2-6b3f-4e80-9a75-2d1c8f0b3e69";
+
+async fn start_server() -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": UUID }] },
+        "streamSettings": {
+            "network": "http",
+            "security": "none",
+            "xhttpSettings": { "mode": "stream-one", "path": "/xhttp" }
+        }
+    }))
+    .await
+}
+
+fn grpc_request() -> hyper::http::Request<()> {
+    hyper::http::Request::post("http://cdn.example.com/xhttp")
+        .header("content-type", "application/grpc")
+        .body(())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_messages_split_into_single_bytes() {
+    let port = start_server().await;
+    let target = spawn_stream_echo().await;
+    let mut client = h2_client(port).await;
+
+    let header = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::from(target),
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    // 超过旧实现 64 KiB 上限的消息
+    let payload: Vec<u8> = (0..100 * 1024).map(|i| (i % 253) as u8).collect();
+    let mut upload = GrpcMessage::new(header.encode().unwrap().to_vec()).encode().to_vec();
+    upload.extend_from_slice(&GrpcMessage::new(payload.clone()).encode());
+
+    let (response, mut send) = client.send_request(grpc_request(), false).unwrap();
+    for &byte in &upload {
+        send.send_data(Bytes::copy_from_slice(&[byte]), false).unwrap();
+    }
+
+    let response = tokio::time::timeout(Duration::from_secs(5), response).await.unwrap().unwrap();
+    assert_eq!(response.status(), 200);
+    let mut body = response.into_body();
+    let mut framed = BytesMut::new();
+    let mut received = Vec::new();
+    while received.len() < 2 + payload.len() {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), body.data()).await.unwrap().unwrap().unwrap();
+        body.flow_control().release_capacity(chunk.len()).unwrap();
+        framed.extend_from_slice(&chunk);
+        while let Some(message) = GrpcMessage::decode(&framed) {
+            framed.advance(5 + message.data.len());
+            received.extend_from_slice(&message.data);
+        }
+    }
+    assert_eq!(&received[..2], b"\x00\x00");
+    assert!(received[2..] == payload[..]);
+}
+
+#[tokio::test]
+async fn test_malformed_framing_resets_stream() {
+    let port = start_server().await;
+    let mut client = h2_client(port).await;
+
+    // 普通流数据 (VLESS 版本号 0 之后是 UUID) 不会被当作 gRPC 消息猜测回退
+    let (response, mut send) = client.send_request(grpc_request(), false).unwrap();
+    send.send_data(Bytes::from_static(&[0x00, 0xe4, 0xa9, 0xc1, 0xd2, 0x6b, 0x3f]), false).unwrap();
+
+    let response = tokio::time::timeout(Duration::from_secs(5), response).await.unwrap().unwrap();
+    let mut body = response.into_body();
+    let err = tokio::time::timeout(Duration::from_secs(5), body.data()).await.unwrap().unwrap().unwrap_err();
+    assert_eq!(err.reason(), Some(h2::Reason::CANCEL), "{err}");
+}
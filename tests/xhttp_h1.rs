@@ -0,0 +1,218 @@
+//! XHTTP 在 HTTP/1.1 (CDN 降级) 与 H2 下的分派
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::Bytes;
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+
+mod common;
+use common::{h2_client, spawn_echo};
+
+const UUID: &str = "1d3ae6e0-5a3b-4f52-9c0e-6a8c1f9b2d47";
+
+async fn start_server() -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": UUID }] },
+        "streamSettings": {
+            "network": "http",
+            "security": "none",
+            "xhttpSettings": { "mode": "auto", "path": "/xhttp" }
+        }
+    }))
+    .await
+}
+
+/// VLESS 请求头 + "ping"
+fn vless_ping(target: SocketAddr) -> Vec<u8> {
+    let request = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::from(target),
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    let mut data = request.encode().unwrap().to_vec();
+    data.extend_from_slice(b"ping");
+    data
+}
+
+/// 读取 chunked 响应直到收到 `len` 字节
+async fn read_chunked<R: AsyncBufReadExt + Unpin>(reader: &mut R, len: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    while data.len() < len {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        let size = usize::from_str_radix(line.trim_end(), 16).unwrap();
+        assert!(size > 0, "stream ended early");
+        let mut chunk = vec![0u8; size + 2];
+        reader.read_exact(&mut chunk).await.unwrap();
+        data.extend_from_slice(&chunk[..size]);
+    }
+    data
+}
+
+async fn read_head<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> String {
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        head.push_str(&line);
+        if line == "\r\n" {
+            return head;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_h1_split_session() {
+    let port = start_server().await;
+    let (target, _) = spawn_echo().await;
+
+    // 下行: chunked GET
+    let get = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let (get_read, mut get_write) = get.into_split();
+    let mut get_read = BufReader::new(get_read);
+    get_write
+        .write_all(b"GET /xhttp/4b1d HTTP/1.1\r\nHost: cdn.example.com\r\nUser-Agent: Go-http-client/1.1\r\n\r\n")
+        .await
+        .unwrap();
+    let head = read_head(&mut get_read).await;
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+    assert!(head.contains("Transfer-Encoding: chunked"));
+
+    // 上行: 同一路径上的 POST (keep-alive 复用)
+    let body = vless_ping(target);
+    let mut post = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let request = format!(
+        "POST /xhttp/4b1d HTTP/1.1\r\nHost: cdn.example.com\r\nUser-Agent: Go-http-client/1.1\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    );
+    post.write_all(request.as_bytes()).await.unwrap();
+    post.write_all(&body).await.unwrap();
+    let mut post_read = BufReader::new(&mut post);
+    let head = read_head(&mut post_read).await;
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+    assert!(head.contains("X-Padding: "));
+
+    let reply = tokio::time::timeout(Duration::from_secs(5), read_chunked(&mut get_read, 6))
+        .await
+        .expect("no downstream data");
+    assert_eq!(reply, b"\x00\x00ping");
+}
+
+/// packet-up: 乱序的上行数据包在同一条 keep-alive 连接上依次发送，分块与定长请求体混用
+#[tokio::test]
+async fn test_h1_packet_up_keep_alive() {
+    let port = start_server().await;
+    let (target, _) = spawn_echo().await;
+
+    let get = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let (get_read, mut get_write) = get.into_split();
+    let mut get_read = BufReader::new(get_read);
+    get_write
+        .write_all(b"GET /xhttp/7a2c HTTP/1.1\r\nHost: cdn.example.com\r\nUser-Agent: Go-http-client/1.1\r\n\r\n")
+        .await
+        .unwrap();
+    let head = read_head(&mut get_read).await;
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+
+    let mut header = vless_ping(target);
+    header.truncate(header.len() - 4);
+    let mut post = BufReader::new(TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+
+    // 序号 1 先到，使用 chunked 请求体
+    post.write_all(b"POST /xhttp/7a2c/1 HTTP/1.1\r\nHost: cdn.example.com\r\nTransfer-Encoding: chunked\r\n\r\n2\r\npi\r\n2\r\nng\r\n0\r\n\r\n")
+        .await
+        .unwrap();
+    let head = read_head(&mut post).await;
+    assert!(head.starts_with("HTTP/1.1 200 OK") && head.contains("Content-Length: 0"), "{}", head);
+
+    // 序号 0 在同一连接上补齐缺口
+    let request = format!(
+        "POST /xhttp/7a2c/0 HTTP/1.1\r\nHost: cdn.example.com\r\nContent-Length: {}\r\n\r\n",
+        header.len()
+    );
+    post.write_all(request.as_bytes()).await.unwrap();
+    post.write_all(&header).await.unwrap();
+    let head = read_head(&mut post).await;
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+
+    let reply = tokio::time::timeout(Duration::from_secs(5), read_chunked(&mut get_read, 6))
+        .await
+        .expect("no downstream data");
+    assert_eq!(reply, b"\x00\x00ping");
+}
+
+#[tokio::test]
+async fn test_h1_stream_one() {
+    let port = start_server().await;
+    let (target, _) = spawn_echo().await;
+
+    let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let (read, mut write) = stream.into_split();
+    let mut read = BufReader::new(read);
+
+    // 没有配对 GET 的 chunked POST: 同一请求内上下行
+    let body = vless_ping(target);
+    let mut request =
+        b"POST /xhttp/solo HTTP/1.1\r\nHost: cdn.example.com\r\nUser-Agent: Go-http-client/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"
+            .to_vec();
+    request.extend_from_slice(format!("{:x}\r\n", body.len()).as_bytes());
+    request.extend_from_slice(&body);
+    request.extend_from_slice(b"\r\n");
+    write.write_all(&request).await.unwrap();
+
+    let head = read_head(&mut read).await;
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+    let reply = tokio::time::timeout(Duration::from_secs(5), read_chunked(&mut read, 6))
+        .await
+        .expect("no downstream data");
+    assert_eq!(reply, b"\x00\x00ping");
+}
+
+#[tokio::test]
+async fn test_h1_unknown_path_is_404() {
+    let port = start_server().await;
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream.write_all(b"GET /index.html HTTP/1.1\r\nHost: cdn.example.com\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 404 Not Found"), "{}", response);
+}
+
+/// H2 前言仍然走 H2 处理器
+#[tokio::test]
+async fn test_h2_still_dispatched() {
+    let port = start_server().await;
+    let (target, _) = spawn_echo().await;
+
+    let mut client = h2_client(port).await;
+
+    let request = hyper::http::Request::post("http://cdn.example.com/xhttp/h2")
+        .header("user-agent", "Go-http-client/2.0")
+        .body(())
+        .unwrap();
+    let (response, mut send) = client.send_request(request, false).unwrap();
+    send.send_data(Bytes::from(vless_ping(target)), false).unwrap();
+
+    let response = tokio::time::timeout(Duration::from_secs(5), response).await.unwrap().unwrap();
+    assert_eq!(response.status(), 200);
+    let mut body = response.into_body();
+    let mut received = Vec::new();
+    while received.len() < 6 {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), body.data())
+            .await
+            .expect("no downstream data")
+            .unwrap()
+            .unwrap();
+        received.extend_from_slice(&chunk);
+    }
+    assert_eq!(received, b"\x00\x00ping");
+}
@@ -0,0 +1,80 @@
+//! XHTTP 响应头拟态: 成功与错误响应 (H2 / HTTP/1.1) 使用同一个模板
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+mod common;
+use common::h2_client;
+
+async fn start_server(masquerade: Value) -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": "0b7e9d42-6c1a-4f38-a5d9-e2c4f7b8a013" }] },
+        "streamSettings": {
+            "network": "http",
+            "security": "none",
+            "xhttpSettings": { "path": "/xhttp", "masquerade": masquerade }
+        }
+    }))
+    .await
+}
+
+/// 发送一个 HTTP/1.1 请求，返回响应头部分
+async fn h1_head(port: u16, request: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    response.split("\r\n\r\n").next().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_cloudflare_profile_on_h2_probe() {
+    let port = start_server(json!({ "profile": "cloudflare" })).await;
+    let mut client = h2_client(port).await;
+
+    let probe = hyper::http::Request::get("http://cdn.example.com/index.html").body(()).unwrap();
+    let (response, _) = client.send_request(probe, true).unwrap();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    let names: Vec<&str> = response.headers().keys().map(|name| name.as_str()).collect();
+    assert_eq!(names, ["date", "cf-cache-status", "server", "cf-ray"]);
+    assert_eq!(response.headers()["server"], "cloudflare");
+    assert_eq!(response.headers()["cf-ray"].to_str().unwrap().len(), 20);
+}
+
+#[tokio::test]
+async fn test_custom_profile_on_h1_responses() {
+    let port = start_server(json!({
+        "profile": "custom",
+        "date": false,
+        "headers": ["Server: Apache/2.4.62", "X-Frame-Options: SAMEORIGIN"]
+    }))
+    .await;
+
+    let head = h1_head(port, "GET /index.html HTTP/1.1\r\nHost: cdn.example.com\r\n\r\n").await;
+    assert_eq!(
+        head,
+        "HTTP/1.1 404 Not Found\r\nServer: Apache/2.4.62\r\nX-Frame-Options: SAMEORIGIN\r\nContent-Length: 0\r\nConnection: close"
+    );
+
+    let head = h1_head(port, "DELETE /xhttp/4b1d HTTP/1.1\r\nHost: cdn.example.com\r\n\r\n").await;
+    assert!(head.starts_with("HTTP/1.1 405 Method Not Allowed\r\nServer: Apache/2.4.62\r\n"), "{}", head);
+    assert!(!head.contains("nginx"), "{}", head);
+}
+
+#[tokio::test]
+async fn test_default_profile_is_nginx() {
+    let port = start_server(json!({})).await;
+    let head = h1_head(port, "DELETE /xhttp/4b1d HTTP/1.1\r\nHost: cdn.example.com\r\n\r\n").await;
+    let lines: Vec<&str> = head.split("\r\n").collect();
+    assert_eq!(lines[0], "HTTP/1.1 405 Not Allowed");
+    assert_eq!(lines[1], "Server: nginx/1.26.0");
+    assert!(lines[2].starts_with("Date: ") && lines[2].ends_with(" GMT"), "{}", head);
+}
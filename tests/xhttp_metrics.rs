@@ -0,0 +1,100 @@
+//! XHTTP 指标: 连接、流、上下行字节与配对等待时间在数据经过时累加
+use std::time::Duration;
+
+use bytes::Bytes;
+use serde_json::json;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+use xray_lite::transport::xhttp::metrics;
+
+mod common;
+use common::{h2_client, spawn_stream_echo};
+
+const UUID: &str = "9b4e2d71-0c6a-4f83-b5d9-3e8a1c7f2b60";
+
+async fn start_server() -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": UUID }] },
+        "streamSettings": {
+            "network": "http",
+            "security": "none",
+            "xhttpSettings": { "mode": "stream-up", "path": "/xhttp" }
+        }
+    }))
+    .await
+}
+
+fn request(method: &str, session: &str) -> hyper::http::Request<()> {
+    hyper::http::Request::builder()
+        .method(method)
+        .uri(format!("http://cdn.example.com/xhttp/{session}"))
+        .header("user-agent", "Go-http-client/2.0")
+        .body(())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_split_session_updates_metrics() {
+    let port = start_server().await;
+    let echo = spawn_stream_echo().await;
+    let before = metrics::collect();
+
+    let mut client = h2_client(port).await;
+    let session = "3f7a0c2e-8d15-4b69-a0e4-6c9b2d1f5e83";
+
+    let (response, _) = client.send_request(request("GET", session), true).unwrap();
+    let mut download = response.await.unwrap().into_body();
+
+    let header = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::from(echo),
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    let header = header.encode().unwrap().freeze();
+    let header_len = header.len() as u64;
+    let (upload_response, mut upload) = client.send_request(request("POST", session), false).unwrap();
+    upload.send_data(header, false).unwrap();
+    upload.send_data(Bytes::from(vec![0x42; 16 * 1024]), false).unwrap();
+
+    // VLESS 响应头 (2 字节) 加回显的数据
+    let mut received = 0;
+    while received < 2 + 16 * 1024 {
+        let chunk = tokio::time::timeout(Duration::from_secs(3), download.data()).await.unwrap().unwrap().unwrap();
+        download.flow_control().release_capacity(chunk.len()).unwrap();
+        received += chunk.len();
+    }
+
+    // 会话在下行写出 VLESS 响应头之后才计为已建立
+    let mut during = metrics::collect();
+    for _ in 0..50 {
+        if during.paired_sessions > before.paired_sessions {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        during = metrics::collect();
+    }
+    assert_eq!(during.connections, before.connections + 1);
+    assert_eq!(during.streams, before.streams + 2);
+    assert_eq!(during.paired_sessions, before.paired_sessions + 1);
+    assert_eq!(during.bytes_up, before.bytes_up + header_len + 16 * 1024);
+    assert_eq!(during.bytes_down, before.bytes_down + received as u64);
+    let paired: u64 = during.pairing_wait.iter().sum::<u64>() - before.pairing_wait.iter().sum::<u64>();
+    assert_eq!(paired, 1);
+
+    drop((upload_response, upload));
+    drop(download);
+    drop(client);
+    for _ in 0..100 {
+        let after = metrics::collect();
+        if after.connections == before.connections && after.streams == before.streams {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("metrics after close: {:?}", metrics::collect());
+}
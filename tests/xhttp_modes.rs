@@ -0,0 +1,297 @@
+//! XHTTP 显式模式: stream-one 与 GET/POST 分离会话互不干扰
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::Bytes;
+use h2::client::SendRequest;
+use serde_json::json;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+
+mod common;
+use common::{h2_client, spawn_echo, spawn_stream_echo};
+
+const UUID: &str = "1d3ae6e0-5a3b-4f52-9c0e-6a8c1f9b2d47";
+
+async fn start_server(mode: &str) -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": UUID }] },
+        "streamSettings": {
+            "network": "http",
+            "security": "none",
+            "xhttpSettings": { "mode": mode, "path": "/xhttp" }
+        }
+    }))
+    .await
+}
+
+/// VLESS 请求头 + "ping"
+fn vless_ping(target: SocketAddr) -> Vec<u8> {
+    vless_payload(target, b"ping")
+}
+
+fn vless_payload(target: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let request = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::from(target),
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    let mut data = request.encode().unwrap().to_vec();
+    data.extend_from_slice(payload);
+    data
+}
+
+/// 发送 stream-one POST，返回响应状态与收到的下行数据
+async fn stream_one(client: &mut SendRequest<Bytes>, path: &str, mode: Option<&str>, body: Vec<u8>) -> (u16, Vec<u8>) {
+    let mut request = hyper::http::Request::post(format!("http://cdn.example.com{path}"))
+        // 浏览器 UA: 走配对逻辑时会等待 GET，stream-one 不应等待
+        .header("user-agent", "Mozilla/5.0");
+    if let Some(mode) = mode {
+        request = request.header("x-xhttp-mode", mode);
+    }
+    let (response, mut send) = client.send_request(request.body(()).unwrap(), false).unwrap();
+    send.send_data(Bytes::from(body), false).unwrap();
+
+    let response = tokio::time::timeout(Duration::from_secs(1), response)
+        .await
+        .expect("no response head")
+        .unwrap();
+    let status = response.status().as_u16();
+    let mut body = response.into_body();
+    let mut received = Vec::new();
+    while status == 200 && received.len() < 6 {
+        let chunk = tokio::time::timeout(Duration::from_secs(1), body.data())
+            .await
+            .expect("no downstream data")
+            .unwrap()
+            .unwrap();
+        received.extend_from_slice(&chunk);
+    }
+    (status, received)
+}
+
+#[tokio::test]
+async fn test_stream_one_on_base_path() {
+    let port = start_server("auto").await;
+    let (target, _) = spawn_echo().await;
+    let mut client = h2_client(port).await;
+
+    let (status, received) = stream_one(&mut client, "/xhttp", None, vless_ping(target)).await;
+    assert_eq!(status, 200);
+    assert_eq!(received, b"\x00\x00ping");
+}
+
+/// 请求头选择 stream-one 时不与同路径的 GET 会话配对
+#[tokio::test]
+async fn test_mode_header_bypasses_pairing() {
+    let port = start_server("auto").await;
+    let (target, _) = spawn_echo().await;
+    let mut client = h2_client(port).await;
+
+    let get = hyper::http::Request::get("http://cdn.example.com/xhttp/4b1d")
+        .header("user-agent", "Mozilla/5.0")
+        .body(())
+        .unwrap();
+    let (get_response, _) = client.send_request(get, true).unwrap();
+    let get_response = tokio::time::timeout(Duration::from_secs(1), get_response).await.unwrap().unwrap();
+    assert_eq!(get_response.status(), 200);
+
+    let (status, received) = stream_one(&mut client, "/xhttp/4b1d", Some("stream-one"), vless_ping(target)).await;
+    assert_eq!(status, 200);
+    assert_eq!(received, b"\x00\x00ping");
+}
+
+#[tokio::test]
+async fn test_server_mode_restricts_requests() {
+    let (target, _) = spawn_echo().await;
+
+    // stream-up 服务端不接受 stream-one
+    let mut client = h2_client(start_server("stream-up").await).await;
+    let (status, _) = stream_one(&mut client, "/xhttp", None, vless_ping(target)).await;
+    assert_eq!(status, 404);
+
+    // stream-one 服务端把带会话 ID 的 POST 也当作 stream-one，GET 一律 404
+    let mut client = h2_client(start_server("stream-one").await).await;
+    let (status, received) = stream_one(&mut client, "/xhttp/4b1d", None, vless_ping(target)).await;
+    assert_eq!(status, 200);
+    assert_eq!(received, b"\x00\x00ping");
+
+    let get = hyper::http::Request::get("http://cdn.example.com/xhttp/4b1d").body(()).unwrap();
+    let (response, _) = client.send_request(get, true).unwrap();
+    assert_eq!(response.await.unwrap().status(), 404);
+}
+
+/// 按 xray-core packet-up 客户端的方式发送一个上行数据包: 独立的 POST，路径末尾为序号，填充放在查询参数中
+async fn packet_post(client: &mut SendRequest<Bytes>, session: &str, seq: u64, data: &[u8]) -> u16 {
+    let request = hyper::http::Request::post(format!(
+        "http://cdn.example.com/xhttp/{session}/{seq}?x_padding={}",
+        "X".repeat(100)
+    ))
+    .header("user-agent", "Go-http-client/2.0")
+    .body(())
+    .unwrap();
+    let (response, mut send) = client.send_request(request, false).unwrap();
+    send.send_data(Bytes::copy_from_slice(data), true).unwrap();
+    tokio::time::timeout(Duration::from_secs(3), response)
+        .await
+        .expect("no response to packet")
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn test_packet_up_reorders_posts() {
+    let port = start_server("packet-up").await;
+    let (target, _) = spawn_echo().await;
+    let mut client = h2_client(port).await;
+    let session = "6f1c2d9e-0b4a-4f7e-9a51-3c2e8d7b1a60";
+
+    let get = hyper::http::Request::get(format!("http://cdn.example.com/xhttp/{session}?x_padding=XXXX"))
+        .header("user-agent", "Go-http-client/2.0")
+        .body(())
+        .unwrap();
+    let (get_response, _) = client.send_request(get, true).unwrap();
+    let get_response = tokio::time::timeout(Duration::from_secs(1), get_response).await.unwrap().unwrap();
+    assert_eq!(get_response.status(), 200);
+
+    // VLESS 请求头拆成两个数据包，"ping" 单独一个；先发后面的序号
+    let request = vless_ping(target);
+    let (header, ping) = request.split_at(request.len() - 4);
+    let (first, second) = header.split_at(header.len() / 2);
+    assert_eq!(packet_post(&mut client, session, 2, ping).await, 200);
+    assert_eq!(packet_post(&mut client, session, 1, second).await, 200);
+    assert_eq!(packet_post(&mut client, session, 0, first).await, 200);
+
+    let mut body = get_response.into_body();
+    let mut received = Vec::new();
+    while received.len() < 6 {
+        let chunk = tokio::time::timeout(Duration::from_secs(1), body.data())
+            .await
+            .expect("no downstream data")
+            .unwrap()
+            .unwrap();
+        received.extend_from_slice(&chunk);
+    }
+    assert_eq!(received, b"\x00\x00ping");
+
+    // packet-up 服务端不接受 stream-up 上行
+    let (status, _) = stream_one(&mut client, &format!("/xhttp/{session}"), Some("stream-up"), Vec::new()).await;
+    assert_eq!(status, 404);
+}
+
+/// 下一段非空下行数据，响应结束时返回 `None`
+async fn next_data(body: &mut h2::RecvStream) -> Option<Bytes> {
+    loop {
+        let chunk = tokio::time::timeout(Duration::from_secs(2), body.data()).await.expect("downstream stalled")?;
+        let chunk = chunk.unwrap();
+        body.flow_control().release_capacity(chunk.len()).unwrap();
+        if !chunk.is_empty() {
+            return Some(chunk);
+        }
+    }
+}
+
+/// stream-one 全双工: 请求体未结束时下行已在流动，请求体结束后下行随目标关闭而结束
+#[tokio::test]
+async fn test_stream_one_full_duplex_echo() {
+    let port = start_server("stream-one").await;
+    let target = spawn_stream_echo().await;
+    let mut client = h2_client(port).await;
+
+    let request = hyper::http::Request::post("http://cdn.example.com/xhttp/4b1d").body(()).unwrap();
+    let (response, mut send) = client.send_request(request, false).unwrap();
+    send.send_data(Bytes::from(vless_ping(target)), false).unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(1), response).await.unwrap().unwrap();
+    assert_eq!(response.status(), 200);
+    let mut body = response.into_body();
+
+    let mut received = Vec::new();
+    while received.len() < 6 {
+        received.extend_from_slice(&next_data(&mut body).await.expect("response ended early"));
+    }
+    assert_eq!(received, b"\x00\x00ping");
+
+    // 多轮往返，请求体始终保持打开
+    for round in 0..3 {
+        let message = format!("round {round}");
+        send.send_data(Bytes::from(message.clone()), false).unwrap();
+        let mut echoed = Vec::new();
+        while echoed.len() < message.len() {
+            echoed.extend_from_slice(&next_data(&mut body).await.expect("response ended early"));
+        }
+        assert_eq!(echoed, message.as_bytes());
+    }
+
+    // 上行先结束: 目标收到 EOF 后关闭，响应随之结束
+    send.send_data(Bytes::new(), true).unwrap();
+    assert_eq!(next_data(&mut body).await, None);
+}
+
+/// 发起下行 GET，返回响应状态与响应体
+async fn split_get(client: &mut SendRequest<Bytes>, url: &str) -> (u16, h2::RecvStream) {
+    let get = hyper::http::Request::get(format!("http://cdn.example.com{url}"))
+        .header("user-agent", "Go-http-client/2.0")
+        .body(())
+        .unwrap();
+    let (response, _) = client.send_request(get, true).unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(1), response).await.unwrap().unwrap();
+    (response.status().as_u16(), response.into_body())
+}
+
+/// 两个客户端使用同一配置路径，各自的 GET/POST 只按会话 ID 配对
+#[tokio::test]
+async fn test_concurrent_sessions_on_same_base_path() {
+    let port = start_server("stream-up").await;
+    let clients = [
+        ("3b6a0f52-91d4-4c1e-8e7f-5a2d9c0b4e18", &b"alice"[..]),
+        ("c8e21d7a-6f3b-4a09-b5d2-1e9f7a3c6b40", &b"bob"[..]),
+    ];
+
+    let mut sessions = Vec::new();
+    for (session, payload) in clients {
+        let target = spawn_stream_echo().await;
+        let mut client = h2_client(port).await;
+        let (status, body) = split_get(&mut client, &format!("/xhttp/{session}")).await;
+        assert_eq!(status, 200);
+        sessions.push((client, body, session, payload, target));
+    }
+
+    // 两个 GET 都建立后再发上行，路径末尾的斜杠与填充参数不影响配对
+    for (client, _, session, payload, target) in &mut sessions {
+        let post = hyper::http::Request::post(format!("http://cdn.example.com/xhttp/{session}/?x_padding=XXXX"))
+            .header("user-agent", "Go-http-client/2.0")
+            .body(())
+            .unwrap();
+        let (_, mut send) = client.send_request(post, false).unwrap();
+        send.send_data(Bytes::from(vless_payload(*target, payload)), false).unwrap();
+    }
+
+    for (_, body, session, payload, _) in &mut sessions {
+        let mut received = Vec::new();
+        while received.len() < 2 + payload.len() {
+            received.extend_from_slice(&next_data(body).await.expect("response ended early"));
+        }
+        assert_eq!(&received[2..], *payload, "session {session} got another client's data");
+    }
+
+    // 会话仍在时，同一会话 ID 的第二个 GET 被拒绝，而不是替换原会话
+    let (client, _, session, _, _) = &mut sessions[0];
+    let (status, _) = split_get(client, &format!("/xhttp/{session}")).await;
+    assert_eq!(status, 409);
+    // 配置路径之后没有会话 ID 的 GET
+    assert_eq!(split_get(client, "/xhttp").await.0, 404);
+    assert_eq!(split_get(client, "/xhttp/bad!session").await.0, 400);
+
+    // 会话 ID 也可以放在查询参数中
+    let session = "0e4f7b2c-8d1a-4f36-a5c9-72b1e6d3f804";
+    let (status, _) = split_get(client, &format!("/xhttp?x-session-id={session}")).await;
+    assert_eq!(status, 200);
+    let (status, _) = split_get(client, &format!("/xhttp/{session}")).await;
+    assert_eq!(status, 409);
+}
@@ -0,0 +1,84 @@
+//! 自适应管道容量: 大流量下载扩容到上限，吞吐不低于固定容量
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use xray_lite::server::AsyncStream;
+use xray_lite::transport::xhttp::{PipeSizing, XhttpConfig, XhttpMode};
+use xray_lite::transport::XhttpServer;
+
+const DOWNLOAD: usize = 64 << 20;
+const CHUNK: usize = 64 * 1024;
+
+/// 以 stream-one 下载 DOWNLOAD 字节，返回耗时与传输中途的管道容量之和
+async fn download(pipe: PipeSizing) -> (Duration, usize) {
+    let server = XhttpServer::new(XhttpConfig { mode: XhttpMode::Auto, path: "/xhttp".to_string(), pipe, ..Default::default() })
+    .unwrap();
+    let metrics = server.metrics().clone();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let source = |mut stream: Box<dyn AsyncStream>, _| async move {
+            let buf = vec![0xa5u8; CHUNK];
+            for _ in 0..DOWNLOAD / CHUNK {
+                stream.write_all(&buf).await?;
+            }
+            Ok(())
+        };
+        let _ = server.accept(stream, source).await;
+    });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (mut client, connection) = h2::client::Builder::new()
+        .initial_window_size(4 << 20)
+        .initial_connection_window_size(8 << 20)
+        .handshake::<_, Bytes>(stream)
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+
+    let started = Instant::now();
+    let post = hyper::http::Request::post("http://cdn.example.com/xhttp").body(()).unwrap();
+    let (response, mut upload) = client.send_request(post, false).unwrap();
+    let mut body = response.await.unwrap().into_body();
+    let (mut received, mut pipe_bytes) = (0, 0);
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.unwrap();
+        received += chunk.len();
+        body.flow_control().release_capacity(chunk.len()).unwrap();
+        if received >= DOWNLOAD / 2 && pipe_bytes == 0 {
+            pipe_bytes = metrics.snapshot().pipe_bytes;
+        }
+    }
+    let elapsed = started.elapsed();
+    assert_eq!(received, DOWNLOAD);
+
+    // 结束上行后整个流关闭，两个方向的管道都被释放
+    upload.send_data(Bytes::new(), true).unwrap();
+    for _ in 0..50 {
+        if metrics.snapshot().pipe_bytes == 0 {
+            return (elapsed, pipe_bytes);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("pipes are still accounted after the stream closed");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_adaptive_pipe_keeps_throughput() {
+    let fixed = PipeSizing { adaptive: false, ..PipeSizing::default() };
+    let (fixed_time, fixed_bytes) = download(fixed).await;
+    assert_eq!(fixed_bytes, 2 * 512 * 1024);
+
+    let (adaptive_time, adaptive_bytes) = download(PipeSizing::default()).await;
+    // 下行方向扩容到上限，上行方向保持初始容量
+    assert_eq!(adaptive_bytes, 512 * 1024 + 64 * 1024);
+    assert!(
+        adaptive_time < fixed_time * 2,
+        "adaptive pipe took {:?}, fixed pipe {:?}",
+        adaptive_time,
+        fixed_time
+    );
+}
@@ -0,0 +1,157 @@
+//! XHTTP 上行限制: 单个 POST 的大小、每个会话的并发 POST 数与上行总量
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::Bytes;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+
+mod common;
+use common::{h2_client, spawn_sink};
+
+const UUID: &str = "7e3c9a51-2f84-4b0d-96e1-c5a8d2f0b734";
+
+async fn start_server(mode: &str, sessions: Value) -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": UUID }] },
+        "streamSettings": {
+            "network": "http",
+            "security": "none",
+            "xhttpSettings": { "mode": mode, "path": "/xhttp", "sessions": sessions }
+        }
+    }))
+    .await
+}
+
+fn request(method: &str, session: &str) -> hyper::http::Request<()> {
+    hyper::http::Request::builder()
+        .method(method)
+        .uri(format!("http://cdn.example.com/xhttp/{session}").trim_end_matches('/'))
+        .header("user-agent", "Go-http-client/2.0")
+        .body(())
+        .unwrap()
+}
+
+fn vless_header(target: SocketAddr) -> Bytes {
+    let header = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::from(target),
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    header.encode().unwrap().freeze()
+}
+
+#[tokio::test]
+async fn test_oversized_post_is_cut_off_and_session_survives() {
+    let port = start_server("stream-up", json!({ "maxStreamPostBytes": 4096 })).await;
+    let mut client = h2_client(port).await;
+    let session = "3a9f0c62-8d17-4e5b-b2c4-61e7a0d5f938";
+    // 第一个 POST 在上限之前转发的 3 KiB 加上第二个 POST 的 1 KiB
+    let (sink, received) = spawn_sink(4096).await;
+
+    let (response, _) = client.send_request(request("GET", session), true).unwrap();
+    let mut download = response.await.unwrap().into_body();
+
+    let (response, mut upload) = client.send_request(request("POST", session), false).unwrap();
+    upload.send_data(vless_header(sink), false).unwrap();
+    let chunk = tokio::time::timeout(Duration::from_secs(2), download.data()).await.unwrap().unwrap().unwrap();
+    assert_eq!(&chunk[..2], b"\x00\x00");
+    for _ in 0..4 {
+        upload.send_data(Bytes::from(vec![0x42; 1024]), false).unwrap();
+    }
+    let response = tokio::time::timeout(Duration::from_secs(3), response).await.unwrap().unwrap();
+    assert_eq!(response.status(), 413);
+    assert!(tokio::time::timeout(Duration::from_millis(200), download.data()).await.is_err(), "download ended");
+
+    // 下行仍然打开，新的 POST 继续向同一会话上传
+    let (response, mut upload) = client.send_request(request("POST", session), false).unwrap();
+    upload.send_data(Bytes::from(vec![0x42; 1024]), true).unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(3), response).await.unwrap().unwrap();
+    assert_eq!(response.status(), 200);
+    let received = tokio::time::timeout(Duration::from_secs(3), received).await.unwrap().unwrap();
+    assert!(received.iter().all(|&b| b == 0x42));
+}
+
+#[tokio::test]
+async fn test_concurrent_posts_are_limited() {
+    let port = start_server("stream-up", json!({ "maxConcurrentPosts": 1 })).await;
+    let mut client = h2_client(port).await;
+    let session = "e04b7d19-5c3a-4f82-a96d-2b8e1f7c0a45";
+    let (sink, _received) = spawn_sink(1).await;
+
+    let (response, _) = client.send_request(request("GET", session), true).unwrap();
+    let mut download = response.await.unwrap().into_body();
+    let (first, mut upload) = client.send_request(request("POST", session), false).unwrap();
+    upload.send_data(vless_header(sink), false).unwrap();
+    tokio::time::timeout(Duration::from_secs(2), download.data()).await.unwrap().unwrap().unwrap();
+
+    // 第一个 POST 仍在进行，第二个被拒绝
+    let (response, _) = client.send_request(request("POST", session), true).unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(3), response).await.unwrap().unwrap();
+    assert_eq!(response.status(), 429);
+
+    // 第一个 POST 结束后名额释放
+    upload.send_data(Bytes::new(), true).unwrap();
+    tokio::time::timeout(Duration::from_secs(3), first).await.unwrap().unwrap();
+    let (response, _) = client.send_request(request("POST", session), true).unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(3), response).await.unwrap().unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_session_upload_total_is_limited() {
+    let port = start_server("stream-up", json!({ "maxUploadBytes": 4096 })).await;
+    let mut client = h2_client(port).await;
+    let session = "5d2b8f04-9e61-4c3a-8f7d-a0c4e6b91257";
+    // 目标读不满，连接保持打开，会话不会因目标关闭而结束
+    let (sink, _received) = spawn_sink(8192).await;
+
+    let (response, _) = client.send_request(request("GET", session), true).unwrap();
+    let mut download = response.await.unwrap().into_body();
+    let (response, mut upload) = client.send_request(request("POST", session), false).unwrap();
+    upload.send_data(vless_header(sink), false).unwrap();
+    tokio::time::timeout(Duration::from_secs(2), download.data()).await.unwrap().unwrap().unwrap();
+    upload.send_data(Bytes::from(vec![0x42; 3072]), true).unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(3), response).await.unwrap().unwrap();
+    assert_eq!(response.status(), 200);
+
+    // 会话累计越过上限，此后的 POST 都被拒绝
+    for len in [2048, 1] {
+        let (response, mut upload) = client.send_request(request("POST", session), false).unwrap();
+        upload.send_data(Bytes::from(vec![0x42; len]), true).unwrap();
+        let response = tokio::time::timeout(Duration::from_secs(3), response).await.unwrap().unwrap();
+        assert_eq!(response.status(), 413);
+    }
+}
+
+#[tokio::test]
+async fn test_stream_one_upload_is_limited() {
+    let port = start_server("auto", json!({ "maxUploadBytes": 4096 })).await;
+    let mut client = h2_client(port).await;
+    let (sink, _received) = spawn_sink(8192).await;
+
+    let (response, mut upload) = client.send_request(request("POST", ""), false).unwrap();
+    let mut download = response.await.unwrap().into_body();
+    upload.send_data(vless_header(sink), false).unwrap();
+    for _ in 0..8 {
+        upload.send_data(Bytes::from(vec![0x42; 1024]), false).unwrap();
+    }
+
+    // 下行没有正常结束，整个流被重置
+    let reset = tokio::time::timeout(Duration::from_secs(3), async {
+        loop {
+            match download.data().await {
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return e.is_reset(),
+                None => return false,
+            }
+        }
+    });
+    assert!(reset.await.unwrap());
+}
@@ -0,0 +1,112 @@
+//! XHTTP 分离会话的建立限制: 待建立会话数上限、配对期限与会话统计
+//!
+//! 会话统计是进程内全局的，因此本文件只有一个测试。
+use std::time::Duration;
+
+use bytes::Bytes;
+use h2::client::SendRequest;
+use serde_json::json;
+use uuid::Uuid;
+use xray_lite::protocol::vless::{Address, Command, VlessRequest};
+use xray_lite::transport::xhttp::SESSION_STATS;
+
+mod common;
+use common::{h2_client, read_to_end, spawn_stream_echo};
+
+const UUID: &str = "9a4e2c71-3f8b-4d05-b6e1-0c7d5a2f9e38";
+
+async fn start_server() -> u16 {
+    common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": UUID }] },
+        "streamSettings": {
+            "network": "http",
+            "security": "none",
+            "xhttpSettings": {
+                "mode": "stream-up",
+                "path": "/xhttp",
+                "sessions": { "maxPending": 2, "pairingTimeout": 1, "handshakeTimeout": 1 }
+            }
+        }
+    }))
+    .await
+}
+
+async fn get(client: &mut SendRequest<Bytes>, session: &str) -> (u16, h2::RecvStream) {
+    let request = hyper::http::Request::get(format!("http://cdn.example.com/xhttp/{session}"))
+        .header("user-agent", "Go-http-client/2.0")
+        .body(())
+        .unwrap();
+    let (response, _) = client.send_request(request, true).unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(1), response).await.unwrap().unwrap();
+    (response.status().as_u16(), response.into_body())
+}
+
+async fn wait_for_stats(expected: (usize, usize)) {
+    for _ in 0..100 {
+        if SESSION_STATS.snapshot() == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("session stats {:?}, expected {:?}", SESSION_STATS.snapshot(), expected);
+}
+
+#[tokio::test]
+async fn test_pending_sessions_capped_and_expired() {
+    let port = start_server().await;
+    let mut client = h2_client(port).await;
+
+    // 两个没有上行的 GET 占满待建立名额，第三个被拒绝
+    let (status, mut first) = get(&mut client, "5c1e8a02-7b4d-4f93-a6e0-d2b9c3f71a85").await;
+    assert_eq!(status, 200);
+    let (status, mut second) = get(&mut client, "e07b3d9f-2a6c-4e18-9f45-81c0b7d2e6a3").await;
+    assert_eq!(status, 200);
+    wait_for_stats((2, 0)).await;
+    let (status, _) = get(&mut client, "4f92c6d1-8e3a-4b07-b5d2-6a1e0f9c3b74").await;
+    assert_eq!(status, 503);
+
+    // 配对期限过后下行正常结束，名额释放
+    let expired = tokio::time::timeout(Duration::from_secs(3), async {
+        (read_to_end(&mut first).await, read_to_end(&mut second).await)
+    })
+    .await
+    .expect("unpaired sessions were not closed");
+    assert_eq!(expired, (Vec::new(), Vec::new()));
+    wait_for_stats((0, 0)).await;
+
+    // 按期配对并完成 VLESS 握手的会话计为已建立，超过期限后也不会被关闭
+    let session = "b3d57e10-9c2f-4a86-8e4b-f61a02c9d7e5";
+    let (status, mut body) = get(&mut client, session).await;
+    assert_eq!(status, 200);
+    let request = VlessRequest {
+        version: 0,
+        uuid: Uuid::parse_str(UUID).unwrap(),
+        command: Command::Tcp,
+        address: Address::from(spawn_stream_echo().await),
+        addon_length: 0,
+        flow: String::new(),
+        mux_session_id: None,
+    };
+    let post = hyper::http::Request::post(format!("http://cdn.example.com/xhttp/{session}"))
+        .header("user-agent", "Go-http-client/2.0")
+        .body(())
+        .unwrap();
+    let (_, mut upload) = client.send_request(post, false).unwrap();
+    upload.send_data(request.encode().unwrap().freeze(), false).unwrap();
+    upload.send_data(Bytes::from_static(b"ping"), false).unwrap();
+
+    let mut received = Vec::new();
+    while received.len() < 6 {
+        let chunk = tokio::time::timeout(Duration::from_secs(1), body.data()).await.unwrap().unwrap().unwrap();
+        received.extend_from_slice(&chunk);
+    }
+    assert_eq!(received, b"\x00\x00ping");
+    wait_for_stats((0, 1)).await;
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    upload.send_data(Bytes::from_static(b"pong"), false).unwrap();
+    let chunk = tokio::time::timeout(Duration::from_secs(1), body.data()).await.unwrap().unwrap().unwrap();
+    assert_eq!(&chunk[..], b"pong");
+    assert_eq!(SESSION_STATS.snapshot(), (0, 1));
+}
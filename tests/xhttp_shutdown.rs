@@ -0,0 +1,150 @@
+//! XHTTP 优雅停机: 停机信号后 H2 连接发送 GOAWAY，宽限期内的流正常结束，超出的流被中止
+use std::time::Duration;
+
+use bytes::Bytes;
+use h2::client::SendRequest;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use xray_lite::server::{AsyncStream, AuthReport};
+use xray_lite::transport::xhttp::{XhttpConfig, XhttpMode};
+use xray_lite::transport::XhttpServer;
+
+mod common;
+use common::h2_client;
+
+/// 启动只接受一个连接的 XHTTP 服务器，stream-one 流原样回显
+async fn start_server(shutdown: CancellationToken, grace_period: Duration) -> (SendRequest<Bytes>, tokio::task::JoinHandle<()>) {
+    let server = XhttpServer::new(XhttpConfig { mode: XhttpMode::Auto, path: "/xhttp".to_string(), ..Default::default() })
+    .unwrap()
+    .with_shutdown(shutdown, grace_period);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let served = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let echo = |mut stream: Box<dyn AsyncStream>, _: AuthReport| async move {
+            let mut buf = vec![0u8; 4096];
+            loop {
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                stream.write_all(&buf[..n]).await?;
+            }
+            stream.shutdown().await?;
+            Ok(())
+        };
+        let _ = server.accept(stream, echo).await;
+    });
+
+    (h2_client(addr.port()).await, served)
+}
+
+fn stream_one() -> hyper::http::Request<()> {
+    hyper::http::Request::post("http://cdn.example.com/xhttp").body(()).unwrap()
+}
+
+#[tokio::test]
+async fn test_stream_finishing_within_grace_period_completes() {
+    let shutdown = CancellationToken::new();
+    let (mut client, served) = start_server(shutdown.clone(), Duration::from_secs(5)).await;
+
+    let (response, mut upload) = client.send_request(stream_one(), false).unwrap();
+    upload.send_data(Bytes::from_static(b"before "), false).unwrap();
+    let mut body = response.await.unwrap().into_body();
+    assert_eq!(body.data().await.unwrap().unwrap(), "before ");
+
+    shutdown.cancel();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    // GOAWAY 之后不再接受新的流
+    let refused = match client.send_request(stream_one(), true) {
+        Ok((response, _)) => response.await.is_err(),
+        Err(_) => true,
+    };
+    assert!(refused, "new stream accepted after GOAWAY");
+
+    // 进行中的流在宽限期内继续收发并正常结束
+    upload.send_data(Bytes::from_static(b"after"), true).unwrap();
+    let mut received = Vec::new();
+    while let Some(chunk) = body.data().await {
+        received.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(received, b"after");
+    assert!(body.trailers().await.is_ok());
+
+    // 最后一个流结束后连接关闭
+    tokio::time::timeout(Duration::from_secs(2), served).await.expect("connection outlived its last stream").unwrap();
+}
+
+#[tokio::test]
+async fn test_stream_is_cut_at_grace_deadline() {
+    let shutdown = CancellationToken::new();
+    let (mut client, served) = start_server(shutdown.clone(), Duration::from_millis(300)).await;
+
+    let (response, mut upload) = client.send_request(stream_one(), false).unwrap();
+    upload.send_data(Bytes::from_static(b"ping"), false).unwrap();
+    let mut body = response.await.unwrap().into_body();
+    assert_eq!(body.data().await.unwrap().unwrap(), "ping");
+
+    let started = tokio::time::Instant::now();
+    shutdown.cancel();
+    // 上行始终不结束: 宽限期到期后流被重置
+    let err = tokio::time::timeout(Duration::from_secs(3), body.data())
+        .await
+        .unwrap()
+        .expect("stream ended without a reset")
+        .unwrap_err();
+    assert_eq!(err.reason(), Some(h2::Reason::CANCEL), "{err}");
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    tokio::time::timeout(Duration::from_secs(3), served).await.expect("connection was not closed").unwrap();
+}
+
+fn xhttp_inbound() -> serde_json::Value {
+    serde_json::json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": "1d3ae6e0-5a3b-4f52-9c0e-6a8c1f9b2d47" }] },
+        "streamSettings": { "network": "http", "security": "none", "xhttpSettings": { "path": "/xhttp" } }
+    })
+}
+
+/// 同一进程中一个 Server 停机，不影响另一个 Server 的 XHTTP 入站建立分离会话
+#[tokio::test]
+async fn test_server_shutdown_does_not_touch_other_servers() {
+    let port = common::start_server(xhttp_inbound()).await;
+
+    let stopped_port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+    let mut inbound = xhttp_inbound();
+    inbound["listen"] = "127.0.0.1".into();
+    inbound["port"] = stopped_port.into();
+    let config: xray_lite::Config = serde_json::from_value(serde_json::json!({
+        "inbounds": [inbound],
+        "outbounds": [{ "protocol": "freedom", "tag": "direct" }]
+    }))
+    .unwrap();
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let stopped_server = xray_lite::Server::new(config).unwrap();
+    let run = tokio::spawn(stopped_server.run_until(async { let _ = stopped.await; }, Duration::from_millis(100)));
+    for _ in 0..100 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", stopped_port)).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(3), run).await.unwrap().unwrap().unwrap();
+
+    // 仍在运行的入站: GET 建立会话 (不是 503)，连接也没有收到 GOAWAY
+    let mut client = h2_client(port).await;
+    let get = hyper::http::Request::get("http://cdn.example.com/xhttp/scoped-session").body(()).unwrap();
+    let (response, _) = client.send_request(get, true).unwrap();
+    let post = hyper::http::Request::post("http://cdn.example.com/xhttp/scoped-session")
+        .header("user-agent", "Go-http-client/2.0")
+        .body(())
+        .unwrap();
+    let (post_response, mut upload) = client.send_request(post, false).unwrap();
+    upload.send_data(Bytes::from_static(b"not vless"), true).unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(2), response).await.unwrap().unwrap();
+    assert_eq!(response.status(), 200);
+    let post_response = tokio::time::timeout(Duration::from_secs(2), post_response).await.unwrap().unwrap();
+    assert_eq!(post_response.status(), 200, "the session was not found");
+}
@@ -0,0 +1,109 @@
+//! XHTTP 直接终结 TLS: 按 ALPN 选择 H2 / HTTP/1.1，SIGHUP 重载后使用新证书
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::{ClientConfig, RootCertStore};
+use rustls_pki_types::{CertificateDer, ServerName};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use xray_lite::server::ReloadHandle;
+use xray_lite::Config;
+
+mod common;
+
+/// 生成 localhost 的自签名证书写入 `dir`，返回证书 DER
+fn write_certificate(dir: &Path) -> Vec<u8> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    std::fs::write(dir.join("cert.pem"), cert.cert.pem()).unwrap();
+    std::fs::write(dir.join("key.pem"), cert.key_pair.serialize_pem()).unwrap();
+    cert.cert.der().to_vec()
+}
+
+async fn start_server(dir: &Path) -> (u16, Config, ReloadHandle) {
+    let server = common::start_server_with(
+        json!({
+            "protocol": "vless",
+            "settings": { "clients": [{ "id": "4b7e2d90-1c3f-4a58-8e6b-d02f9c7a3e15" }] },
+            "streamSettings": {
+                "network": "http",
+                "security": "tls",
+                "xhttpSettings": {
+                    "path": "/xhttp",
+                    "tls": { "certificateFile": dir.join("cert.pem"), "keyFile": dir.join("key.pem") }
+                }
+            }
+        }),
+        json!({}),
+    )
+    .await;
+    (server.port, server.config, server.reload_handle)
+}
+
+async fn connect(port: u16, trusted: &[u8], alpn: &[u8]) -> std::io::Result<TlsStream<TcpStream>> {
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from(trusted.to_vec())).unwrap();
+    let mut config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![alpn.to_vec()];
+    let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    TlsConnector::from(Arc::new(config)).connect(ServerName::try_from("localhost").unwrap(), stream).await
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("xray-lite-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn test_alpn_selects_protocol() {
+    let dir = temp_dir("tls-alpn");
+    let cert = write_certificate(&dir);
+    let (port, _, _) = start_server(&dir).await;
+
+    let tls = connect(port, &cert, b"h2").await.unwrap();
+    assert_eq!(tls.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+    let (mut client, connection) = h2::client::handshake(tls).await.unwrap();
+    tokio::spawn(connection);
+    let get = hyper::http::Request::get("https://cdn.example.com/xhttp/6a0f3c8e-2d71-4b95-a4e3-9c1b7d5f0e28").body(()).unwrap();
+    let (response, _) = client.send_request(get, true).unwrap();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/octet-stream");
+
+    let mut tls = connect(port, &cert, b"http/1.1").await.unwrap();
+    tls.write_all(b"GET /index.html HTTP/1.1\r\nHost: cdn.example.com\r\nConnection: close\r\n\r\n").await.unwrap();
+    let mut response = Vec::new();
+    let _ = tokio::time::timeout(Duration::from_secs(5), tls.read_to_end(&mut response)).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", response);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_certificate_reload() {
+    let dir = temp_dir("tls-reload");
+    let old = write_certificate(&dir);
+    let (port, config, handle) = start_server(&dir).await;
+    connect(port, &old, b"h2").await.unwrap();
+
+    // 续期: 同一路径写入新证书，配置本身不变
+    let new = write_certificate(&dir);
+    assert!(handle.apply(config).unwrap().is_empty());
+    let tls = connect(port, &new, b"h2").await.unwrap();
+    let (mut client, connection) = h2::client::handshake(tls).await.unwrap();
+    tokio::spawn(connection);
+    let (response, _) = client.send_request(hyper::http::Request::get("https://cdn.example.com/").body(()).unwrap(), true).unwrap();
+    assert_eq!(response.await.unwrap().status(), 404);
+
+    assert!(connect(port, &old, b"h2").await.is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,336 @@
+//! 与真实 xray-core 互通: 以 `xray` 可执行文件作为对端
+//!
+//! PATH 中没有 `xray` 时各测试直接跳过。xray 作为客户端时，其 dokodemo-door 入站把本地连接转发到固定目标，
+//! 测试向其写入数据并等待回显，数据经过 xray 与 xray-lite 之间的真实连接。
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine as _};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::process::{Child, Command};
+use uuid::Uuid;
+use x25519_dalek::{PublicKey, StaticSecret};
+use xray_lite::protocol::vless::{Address, Command as VlessCommand, VlessRequest};
+
+mod common;
+
+const UUID: &str = "b831381d-6324-4d53-ad4f-8cda48b30811";
+const PRIVATE_KEY: [u8; 32] = [0x42; 32];
+const SHORT_ID: &str = "0123456789abcdef";
+const SERVER_NAME: &str = "www.example.com";
+
+/// PATH 中的 `xray`，找不到时返回 None 并说明跳过原因
+fn xray_binary() -> Option<PathBuf> {
+    let found = std::env::var_os("PATH")
+        .and_then(|paths| std::env::split_paths(&paths).map(|dir| dir.join("xray")).find(|path| path.is_file()));
+    if found.is_none() {
+        eprintln!("xray not found on PATH, skipping");
+    }
+    found
+}
+
+/// 运行中的 xray 进程，丢弃时结束进程并删除配置文件
+struct Xray {
+    _child: Child,
+    config: PathBuf,
+}
+
+impl Xray {
+    fn spawn(binary: &Path, config: Value) -> Self {
+        let path = std::env::temp_dir().join(format!("xray-lite-interop-{}-{}.json", std::process::id(), rand::random::<u32>()));
+        std::fs::write(&path, serde_json::to_vec(&config).unwrap()).unwrap();
+        let child = Command::new(binary)
+            .arg("run")
+            .arg("-c")
+            .arg(&path)
+            .stdout(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        Self { _child: child, config: path }
+    }
+}
+
+impl Drop for Xray {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.config);
+    }
+}
+
+async fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port()
+}
+
+async fn wait_for_listener(port: u16) {
+    for _ in 0..250 {
+        if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("127.0.0.1:{} is not listening", port);
+}
+
+/// 持续接受连接并回显 (探测端口可用性的连接也会被转发到这里)
+async fn spawn_echo() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut read, mut write) = stream.split();
+                let _ = tokio::io::copy(&mut read, &mut write).await;
+            });
+        }
+    });
+    addr
+}
+
+/// 转发到 `target` 的 dokodemo-door 入站
+fn dokodemo_inbound(port: u16, target: SocketAddr) -> Value {
+    json!({
+        "protocol": "dokodemo-door",
+        "listen": "127.0.0.1",
+        "port": port,
+        "settings": { "address": target.ip().to_string(), "port": target.port(), "network": "tcp" }
+    })
+}
+
+/// 经 `port` 写入 `payload` 并读回等长的回显
+async fn echo_through(port: u16, payload: &[u8]) -> Vec<u8> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream.write_all(payload).await.unwrap();
+    let mut reply = vec![0u8; payload.len()];
+    tokio::time::timeout(Duration::from_secs(10), stream.read_exact(&mut reply))
+        .await
+        .expect("no echo through xray")
+        .unwrap();
+    reply
+}
+
+fn public_key() -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(PublicKey::from(&StaticSecret::from(PRIVATE_KEY)).as_bytes())
+}
+
+/// xray-core 客户端 (各指纹) 通过 VLESS + Reality 连接 xray-lite 服务端
+#[tokio::test]
+async fn test_xray_client_to_reality_inbound() {
+    let Some(xray) = xray_binary() else { return };
+    let echo = spawn_echo().await;
+    let server_port = common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": UUID }] },
+        "streamSettings": {
+            "network": "tcp",
+            "security": "reality",
+            "realitySettings": {
+                "dest": "127.0.0.1:9",
+                "serverNames": [SERVER_NAME],
+                "privateKey": general_purpose::URL_SAFE_NO_PAD.encode(PRIVATE_KEY),
+                "shortIds": [SHORT_ID]
+            }
+        }
+    }))
+    .await;
+
+    for fingerprint in ["chrome", "firefox", "safari"] {
+        let client_port = free_port().await;
+        let _client = Xray::spawn(
+            &xray,
+            json!({
+                "log": { "loglevel": "warning" },
+                "inbounds": [dokodemo_inbound(client_port, echo)],
+                "outbounds": [{
+                    "protocol": "vless",
+                    "settings": {
+                        "vnext": [{ "address": "127.0.0.1", "port": server_port, "users": [{ "id": UUID, "encryption": "none" }] }]
+                    },
+                    "streamSettings": {
+                        "network": "tcp",
+                        "security": "reality",
+                        "realitySettings": {
+                            "serverName": SERVER_NAME,
+                            "fingerprint": fingerprint,
+                            "publicKey": public_key(),
+                            "shortId": SHORT_ID
+                        }
+                    }
+                }]
+            }),
+        );
+        wait_for_listener(client_port).await;
+        assert_eq!(echo_through(client_port, b"ping").await, b"ping", "{}", fingerprint);
+    }
+}
+
+/// 只支持 TLS 1.3 的本地站点，作为 xray-core Reality 服务端的 dest
+async fn spawn_tls_dest() -> SocketAddr {
+    let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()]).unwrap();
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![cert.cert.der().clone()],
+            rustls_pki_types::PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()).into(),
+        )
+        .unwrap();
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                // xray 对通过认证的客户端只借用 dest 的握手，这里的错误无关紧要
+                if let Ok(mut tls) = acceptor.accept(stream).await {
+                    let _ = tokio::io::copy(&mut tls, &mut tokio::io::sink()).await;
+                }
+            });
+        }
+    });
+    addr
+}
+
+/// xray-lite 的 vless + Reality 出站 (各指纹) 连接 xray-core 的 Reality 服务端
+#[tokio::test]
+async fn test_reality_outbound_to_xray_server() {
+    let Some(xray) = xray_binary() else { return };
+    let echo = spawn_echo().await;
+    let dest = spawn_tls_dest().await;
+    let xray_port = free_port().await;
+    let _server = Xray::spawn(
+        &xray,
+        json!({
+            "log": { "loglevel": "warning" },
+            "inbounds": [{
+                "protocol": "vless",
+                "listen": "127.0.0.1",
+                "port": xray_port,
+                "settings": { "clients": [{ "id": UUID }], "decryption": "none" },
+                "streamSettings": {
+                    "network": "tcp",
+                    "security": "reality",
+                    "realitySettings": {
+                        "dest": dest.to_string(),
+                        "serverNames": [SERVER_NAME],
+                        "privateKey": general_purpose::URL_SAFE_NO_PAD.encode(PRIVATE_KEY),
+                        "shortIds": [SHORT_ID]
+                    }
+                }
+            }],
+            "outbounds": [{ "protocol": "freedom" }]
+        }),
+    );
+    wait_for_listener(xray_port).await;
+
+    for fingerprint in ["chrome", "firefox", "safari"] {
+        let edge = common::start_server_with(
+            json!({
+                "protocol": "vless",
+                "settings": { "clients": [{ "id": UUID }] },
+                "streamSettings": { "network": "tcp", "security": "none" }
+            }),
+            json!({
+                "outbounds": [{
+                    "protocol": "vless",
+                    "tag": "upstream",
+                    "settings": {
+                        "vnext": [{ "address": "127.0.0.1", "port": xray_port, "users": [{ "id": UUID }] }]
+                    },
+                    "streamSettings": {
+                        "security": "reality",
+                        "realitySettings": {
+                            "serverName": SERVER_NAME,
+                            "publicKey": public_key(),
+                            "shortId": SHORT_ID,
+                            "fingerprint": fingerprint
+                        }
+                    }
+                }]
+            }),
+        )
+        .await;
+
+        let request = VlessRequest {
+            version: 0,
+            uuid: Uuid::parse_str(UUID).unwrap(),
+            command: VlessCommand::Tcp,
+            address: Address::from(echo),
+            addon_length: 0,
+            flow: String::new(),
+            mux_session_id: None,
+        };
+        let mut payload = request.encode().unwrap().to_vec();
+        payload.extend_from_slice(b"ping");
+        // 边缘节点的 VLESS 响应头，随后是经 xray-core 转发回来的数据
+        let mut stream = TcpStream::connect(("127.0.0.1", edge.port)).await.unwrap();
+        stream.write_all(&payload).await.unwrap();
+        let mut reply = [0u8; 6];
+        tokio::time::timeout(Duration::from_secs(10), stream.read_exact(&mut reply))
+            .await
+            .expect("no reply through xray-core")
+            .unwrap();
+        assert_eq!(&reply, b"\x00\x00ping", "{}", fingerprint);
+    }
+}
+
+/// xray-core 客户端以 XHTTP packet-up 模式连接 xray-lite: 上行拆成带序号的 POST，由服务端按序重组
+#[tokio::test]
+async fn test_xray_packet_up_client_to_xhttp_inbound() {
+    let Some(xray) = xray_binary() else { return };
+    let echo = spawn_echo().await;
+    let server_port = common::start_server(json!({
+        "protocol": "vless",
+        "settings": { "clients": [{ "id": UUID }] },
+        "streamSettings": {
+            "network": "http",
+            "security": "none",
+            "xhttpSettings": { "mode": "packet-up", "path": "/xhttp" }
+        }
+    }))
+    .await;
+
+    let client_port = free_port().await;
+    let _client = Xray::spawn(
+        &xray,
+        json!({
+            "log": { "loglevel": "warning" },
+            "inbounds": [dokodemo_inbound(client_port, echo)],
+            "outbounds": [{
+                "protocol": "vless",
+                "settings": {
+                    "vnext": [{ "address": "127.0.0.1", "port": server_port, "users": [{ "id": UUID, "encryption": "none" }] }]
+                },
+                "streamSettings": {
+                    "network": "xhttp",
+                    "security": "none",
+                    "xhttpSettings": { "mode": "packet-up", "path": "/xhttp" }
+                }
+            }]
+        }),
+    );
+    wait_for_listener(client_port).await;
+    assert_eq!(echo_through(client_port, b"ping").await, b"ping");
+
+    // 分多次写入，间隔超过 xray 的最小 POST 间隔，上行被拆成多个序号
+    let payload: Vec<u8> = (0..256 * 1024).map(|_| rand::random()).collect();
+    let mut stream = TcpStream::connect(("127.0.0.1", client_port)).await.unwrap();
+    let (mut read, mut write) = stream.split();
+    let send = async {
+        for chunk in payload.chunks(16 * 1024) {
+            write.write_all(chunk).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    let mut reply = vec![0u8; payload.len()];
+    let receive = tokio::time::timeout(Duration::from_secs(20), read.read_exact(&mut reply));
+    let (_, received) = tokio::join!(send, receive);
+    received.expect("no echo through xray packet-up").unwrap();
+    assert!(reply == payload, "packet-up upload was reassembled out of order");
+}
@@ -0,0 +1,66 @@
+//! eBPF 程序与用户态加载器共享的 map 值类型
+//!
+//! 内核侧按 `#![no_std]` 使用；用户态启用 `user` feature 以获得 `aya::Pod` 实现。
+#![no_std]
+
+/// 按源 IP 限速的记录 (RATE_LIMIT_MAP / UDP_RATE_LIMIT_MAP 的值)
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitEntry {
+    /// 最后一次更新的时间 (bpf_ktime_get_ns)
+    pub last_time_ns: u64,
+    /// 当前窗口内的包数
+    pub count: u32,
+    /// 显式填充，始终为 0: 与 map 之间按字节复制时不含未初始化的字节
+    pub _pad: u32,
+}
+
+// 没有隐式填充，Pod 复制的每个字节都已初始化
+const _: () = assert!(core::mem::size_of::<RateLimitEntry>() == 16);
+
+impl RateLimitEntry {
+    /// 新窗口的第一条记录
+    pub const fn new(last_time_ns: u64, count: u32) -> Self {
+        Self { last_time_ns, count, _pad: 0 }
+    }
+}
+
+// Safety: #[repr(C)]，只包含整数字段且没有隐式填充
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for RateLimitEntry {}
+
+/// 限速窗口长度 (纳秒)，阈值按每个窗口内的包数计
+pub const RATE_LIMIT_WINDOW_NS: u64 = 1_000_000_000;
+
+/// CONFIG (Array<u32>) 下标: 每个源 IP 每秒允许的 SYN 数，0 为不限速
+pub const CONFIG_SYN_RATE_LIMIT: u32 = 0;
+/// CONFIG 下标: 每个源 IP 每秒允许的 UDP 包数，0 为不限速
+pub const CONFIG_UDP_RATE_LIMIT: u32 = 1;
+/// CONFIG 的条目数
+pub const CONFIG_LEN: u32 = 2;
+
+/// XDP_STATS (PerCpuArray<u64>) 下标: 超过 SYN 限速而丢弃的包
+pub const STAT_SYN_DROPPED: u32 = 0;
+/// 非法 TCP 标志组合
+pub const STAT_ILLEGAL_FLAGS_DROPPED: u32 = 1;
+/// 超过 UDP 限速而丢弃的包
+pub const STAT_UDP_DROPPED: u32 = 2;
+/// 发往受保护端口并放行的包
+pub const STAT_PASSED: u32 = 3;
+/// 来自黑名单的包
+pub const STAT_BLOCKED: u32 = 4;
+/// XDP_STATS 的条目数
+pub const STAT_LEN: u32 = 5;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_matches_kernel() {
+        // 内核侧按 { u64, u32, u32 } 读取，填充字段显式写出
+        assert_eq!(core::mem::size_of::<RateLimitEntry>(), 16);
+        assert_eq!(core::mem::align_of::<RateLimitEntry>(), 8);
+        assert_eq!(RateLimitEntry::new(1, 2)._pad, 0);
+    }
+}
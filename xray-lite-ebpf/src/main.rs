@@ -0,0 +1,207 @@
+//! XDP 防火墙: 只处理发往受保护端口 (ALLOWED_PORTS) 的 IPv4 包
+//!
+//! 黑名单 (BLOCKLIST) 中的源 IP 例外，发往任何端口的包都会丢弃。
+//!
+//! - 非法 TCP 标志组合 (NULL、SYN+FIN、XMAS 等) 直接丢弃
+//! - 每个源 IP 的 SYN 与 UDP 包 (QUIC) 分别按秒限速，阈值来自 CONFIG
+//!
+//! 其他端口与非 IPv4 流量一律放行。各类丢弃与受保护端口的放行计入 XDP_STATS。
+#![no_std]
+#![no_main]
+
+use core::mem;
+
+use aya_ebpf::bindings::xdp_action;
+use aya_ebpf::helpers::bpf_ktime_get_ns;
+use aya_ebpf::macros::{map, xdp};
+use aya_ebpf::maps::{Array, HashMap, PerCpuArray, PerCpuHashMap};
+use aya_ebpf::programs::XdpContext;
+use xray_lite_common::{
+    RateLimitEntry, CONFIG_LEN, CONFIG_SYN_RATE_LIMIT, CONFIG_UDP_RATE_LIMIT, RATE_LIMIT_WINDOW_NS, STAT_BLOCKED,
+    STAT_ILLEGAL_FLAGS_DROPPED, STAT_LEN, STAT_PASSED, STAT_SYN_DROPPED, STAT_UDP_DROPPED,
+};
+
+const ETH_HDR_LEN: usize = 14;
+const ETH_P_IP: u16 = 0x0800;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+const TCP_URG: u8 = 0x20;
+
+/// 受保护的端口 (主机字节序)，值无意义
+#[map]
+static ALLOWED_PORTS: HashMap<u16, u8> = HashMap::with_max_entries(64, 0);
+
+/// 黑名单，键与 RATE_LIMIT_MAP 相同，值无意义
+#[map]
+static BLOCKLIST: HashMap<u32, u32> = HashMap::with_max_entries(16384, 0);
+
+/// 按源 IP 的 SYN 计数，键为 IPv4 头中的 saddr 原样读取
+///
+/// 每个 CPU 各自计数: 多队列网卡下不争用同一 cache line，count 也不需要原子操作。
+/// 阈值因此按 CPU 生效，同一 IP 的包分散到 N 个队列时，实际上限最多为阈值的 N 倍。
+#[map]
+static RATE_LIMIT_MAP: PerCpuHashMap<u32, RateLimitEntry> = PerCpuHashMap::with_max_entries(65536, 0);
+
+/// 按源 IP 的 UDP 包计数，与 RATE_LIMIT_MAP 相同
+#[map]
+static UDP_RATE_LIMIT_MAP: PerCpuHashMap<u32, RateLimitEntry> = PerCpuHashMap::with_max_entries(65536, 0);
+
+/// 用户态写入的运行参数，下标见 xray_lite_common::CONFIG_*
+#[map]
+static CONFIG: Array<u32> = Array::with_max_entries(CONFIG_LEN, 0);
+
+/// 丢弃/放行计数，下标见 xray_lite_common::STAT_*，用户态按 CPU 求和
+#[map]
+static XDP_STATS: PerCpuArray<u64> = PerCpuArray::with_max_entries(STAT_LEN, 0);
+
+#[xdp]
+pub fn xdp_firewall(ctx: XdpContext) -> u32 {
+    try_xdp_firewall(&ctx).unwrap_or(xdp_action::XDP_PASS)
+}
+
+/// 返回 `ctx` 中 `offset` 处 `T` 的指针，越界时返回 Err (校验器要求每次访问前检查)
+#[inline(always)]
+fn ptr_at<T>(ctx: &XdpContext, offset: usize) -> Result<*const T, ()> {
+    let start = ctx.data();
+    let end = ctx.data_end();
+    if start + offset + mem::size_of::<T>() > end {
+        return Err(());
+    }
+    Ok((start + offset) as *const T)
+}
+
+#[inline(always)]
+fn read<T: Copy>(ctx: &XdpContext, offset: usize) -> Result<T, ()> {
+    // Safety: ptr_at 已确认 [offset, offset + size_of::<T>()) 在包内
+    Ok(unsafe { ptr_at::<T>(ctx, offset)?.read_unaligned() })
+}
+
+fn try_xdp_firewall(ctx: &XdpContext) -> Result<u32, ()> {
+    if u16::from_be(read::<u16>(ctx, 12)?) != ETH_P_IP {
+        return Ok(xdp_action::XDP_PASS);
+    }
+
+    let version_ihl = read::<u8>(ctx, ETH_HDR_LEN)?;
+    let ip_hdr_len = ((version_ihl & 0x0f) as usize) * 4;
+    if ip_hdr_len < 20 {
+        return Ok(xdp_action::XDP_PASS);
+    }
+    let protocol = read::<u8>(ctx, ETH_HDR_LEN + 9)?;
+    let saddr = read::<u32>(ctx, ETH_HDR_LEN + 12)?;
+    // Safety: 只读取值是否存在
+    if unsafe { BLOCKLIST.get(&saddr) }.is_some() {
+        return Ok(drop_counted(STAT_BLOCKED));
+    }
+    let l4 = ETH_HDR_LEN + ip_hdr_len;
+
+    match protocol {
+        IPPROTO_TCP => {
+            let dport = u16::from_be(read::<u16>(ctx, l4 + 2)?);
+            if !is_protected(dport) {
+                return Ok(xdp_action::XDP_PASS);
+            }
+            let flags = read::<u8>(ctx, l4 + 13)?;
+            if is_illegal(flags) {
+                return Ok(drop_counted(STAT_ILLEGAL_FLAGS_DROPPED));
+            }
+            if flags & (TCP_SYN | TCP_ACK) == TCP_SYN && over_limit(&RATE_LIMIT_MAP, saddr, limit(CONFIG_SYN_RATE_LIMIT)) {
+                return Ok(drop_counted(STAT_SYN_DROPPED));
+            }
+            Ok(pass_counted())
+        }
+        IPPROTO_UDP => {
+            let dport = u16::from_be(read::<u16>(ctx, l4 + 2)?);
+            if !is_protected(dport) {
+                return Ok(xdp_action::XDP_PASS);
+            }
+            if over_limit(&UDP_RATE_LIMIT_MAP, saddr, limit(CONFIG_UDP_RATE_LIMIT)) {
+                return Ok(drop_counted(STAT_UDP_DROPPED));
+            }
+            Ok(pass_counted())
+        }
+        _ => Ok(xdp_action::XDP_PASS),
+    }
+}
+
+#[inline(always)]
+fn count(index: u32) {
+    if let Some(value) = XDP_STATS.get_ptr_mut(index) {
+        // Safety: 当前 CPU 的副本，不会被并发修改
+        unsafe { *value += 1 };
+    }
+}
+
+#[inline(always)]
+fn drop_counted(index: u32) -> u32 {
+    count(index);
+    xdp_action::XDP_DROP
+}
+
+#[inline(always)]
+fn pass_counted() -> u32 {
+    count(STAT_PASSED);
+    xdp_action::XDP_PASS
+}
+
+#[inline(always)]
+fn is_protected(port: u16) -> bool {
+    // Safety: 只读取值是否存在
+    unsafe { ALLOWED_PORTS.get(&port) }.is_some()
+}
+
+/// 正常 TCP 栈不会发出的标志组合
+#[inline(always)]
+fn is_illegal(flags: u8) -> bool {
+    let has = |mask: u8| flags & mask == mask;
+    flags & 0x3f == 0
+        || has(TCP_SYN | TCP_FIN)
+        || has(TCP_SYN | TCP_RST)
+        || has(TCP_FIN | TCP_RST)
+        || has(TCP_FIN | TCP_PSH | TCP_URG)
+}
+
+#[inline(always)]
+fn limit(index: u32) -> u32 {
+    CONFIG.get(index).copied().unwrap_or(0)
+}
+
+/// 在当前 CPU 的记录中计入一个包，窗口内超过 `limit` 时返回 true (`limit` 为 0 时不限速)
+#[inline(always)]
+fn over_limit(map: &PerCpuHashMap<u32, RateLimitEntry>, saddr: u32, limit: u32) -> bool {
+    if limit == 0 {
+        return false;
+    }
+    // Safety: 无副作用的 helper
+    let now = unsafe { bpf_ktime_get_ns() };
+    match map.get_ptr_mut(&saddr) {
+        Some(entry) => {
+            // Safety: 指针来自 map 查找，指向当前 CPU 的副本，在本次程序执行期间有效
+            let entry = unsafe { &mut *entry };
+            if now.saturating_sub(entry.last_time_ns) >= RATE_LIMIT_WINDOW_NS {
+                entry.last_time_ns = now;
+                entry.count = 1;
+                false
+            } else {
+                entry.count = entry.count.saturating_add(1);
+                entry.count > limit
+            }
+        }
+        None => {
+            let entry = RateLimitEntry::new(now, 1);
+            let _ = map.insert(&saddr, &entry, 0);
+            false
+        }
+    }
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}